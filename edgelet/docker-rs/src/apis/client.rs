@@ -8,6 +8,7 @@ use hyper;
 pub struct APIClient<C: hyper::client::connect::Connect> {
     configuration: Arc<Configuration<C>>,
     container_api: Box<dyn crate::apis::ContainerApi>,
+    exec_api: Box<dyn crate::apis::ExecApi>,
     image_api: Box<dyn crate::apis::ImageApi>,
     network_api: Box<dyn crate::apis::NetworkApi>,
     system_api: Box<dyn crate::apis::SystemApi>,
@@ -21,6 +22,7 @@ impl<C: hyper::client::connect::Connect + 'static> APIClient<C> {
         APIClient {
             configuration: configuration.clone(),
             container_api: Box::new(crate::apis::ContainerApiClient::new(configuration.clone())),
+            exec_api: Box::new(crate::apis::ExecApiClient::new(configuration.clone())),
             image_api: Box::new(crate::apis::ImageApiClient::new(configuration.clone())),
             network_api: Box::new(crate::apis::NetworkApiClient::new(configuration.clone())),
             system_api: Box::new(crate::apis::SystemApiClient::new(configuration.clone())),
@@ -32,6 +34,10 @@ impl<C: hyper::client::connect::Connect + 'static> APIClient<C> {
         self.container_api.as_ref()
     }
 
+    pub fn exec_api(&self) -> &dyn crate::apis::ExecApi {
+        self.exec_api.as_ref()
+    }
+
     pub fn image_api(&self) -> &dyn crate::apis::ImageApi {
         self.image_api.as_ref()
     }
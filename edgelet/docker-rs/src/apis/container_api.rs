@@ -68,6 +68,17 @@ pub trait ContainerApi: Send + Sync {
     ) -> Box<
         dyn Future<Item = Vec<crate::models::InlineResponse2002>, Error = Error<serde_json::Value>>,
     >;
+    fn container_checkpoint_create(
+        &self,
+        id: &str,
+        config: crate::models::CheckpointCreateConfig,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
+    fn container_checkpoint_list(
+        &self,
+        id: &str,
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::Checkpoint>, Error = Error<serde_json::Value>> + Send,
+    >;
     fn container_create(
         &self,
         body: crate::models::ContainerCreateBody,
@@ -99,7 +110,7 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         signal: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn container_list(
         &self,
         all: bool,
@@ -117,13 +128,14 @@ pub trait ContainerApi: Send + Sync {
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: i32,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn container_pause(
         &self,
         id: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn container_prune(
         &self,
         filters: &str,
@@ -132,7 +144,7 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         name: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn container_resize(
         &self,
         id: &str,
@@ -148,12 +160,13 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         detach_keys: &str,
+        checkpoint: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn container_stats(
         &self,
         id: &str,
         stream: bool,
-    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send>;
     fn container_stop(
         &self,
         id: &str,
@@ -170,24 +183,30 @@ pub trait ContainerApi: Send + Sync {
     fn container_unpause(
         &self,
         id: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn container_update(
         &self,
         id: &str,
         update: crate::models::ContainerUpdateUpdate,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse2003, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse2003, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn container_wait(
         &self,
         id: &str,
         condition: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse2004, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse2004, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn put_container_archive(
         &self,
         id: &str,
         path: &str,
-        input_stream: &str,
+        input_stream: Vec<u8>,
         no_overwrite_dir_non_dir: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
 }
 
 impl<C> ContainerApi for ContainerApiClient<C>
@@ -473,6 +492,114 @@ where
         )
     }
 
+    fn container_checkpoint_create(
+        &self,
+        id: &str,
+        config: crate::models::CheckpointCreateConfig,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::POST;
+
+        let uri_str = format!("/containers/{id}/checkpoints", id = id);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let serialized = serde_json::to_string(&config).unwrap();
+        let serialized_len = serialized.len();
+
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let mut req = req
+            .body(hyper::Body::from(serialized))
+            .expect("could not build hyper::Request");
+        req.headers_mut()
+            .typed_insert(&typed_headers::ContentType(mime::APPLICATION_JSON));
+        req.headers_mut()
+            .typed_insert(&typed_headers::ContentLength(serialized_len as u64));
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(|e| Error::from(e))
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(|e| Error::from(e))
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|_| futures::future::ok(())),
+        )
+    }
+
+    fn container_checkpoint_list(
+        &self,
+        id: &str,
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::Checkpoint>, Error = Error<serde_json::Value>> + Send,
+    > {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::GET;
+
+        let uri_str = format!("/containers/{id}/checkpoints", id = id);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(|e| Error::from(e))
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(|e| Error::from(e))
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|body| {
+                    let parsed: Result<Vec<crate::models::Checkpoint>, _> =
+                        serde_json::from_slice(&body);
+                    parsed.map_err(|e| Error::from(e))
+                }),
+        )
+    }
+
     fn container_create(
         &self,
         body: crate::models::ContainerCreateBody,
@@ -702,7 +829,7 @@ where
         &self,
         id: &str,
         signal: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -819,6 +946,7 @@ where
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: i32,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
@@ -831,6 +959,7 @@ where
             .append_pair("stdout", &stdout.to_string())
             .append_pair("stderr", &stderr.to_string())
             .append_pair("since", &since.to_string())
+            .append_pair("until", &until.to_string())
             .append_pair("timestamps", &timestamps.to_string())
             .append_pair("tail", &tail.to_string())
             .finish();
@@ -871,7 +1000,7 @@ where
     fn container_pause(
         &self,
         id: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -974,7 +1103,7 @@ where
         &self,
         id: &str,
         name: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -1129,6 +1258,7 @@ where
         &self,
         id: &str,
         detach_keys: &str,
+        checkpoint: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -1136,6 +1266,7 @@ where
 
         let query = ::url::form_urlencoded::Serializer::new(String::new())
             .append_pair("detachKeys", &detach_keys.to_string())
+            .append_pair("checkpoint", &checkpoint.to_string())
             .finish();
         let uri_str = format!("/containers/{id}/start?{}", query, id = id);
 
@@ -1180,7 +1311,7 @@ where
         &self,
         id: &str,
         stream: bool,
-    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -1342,7 +1473,7 @@ where
     fn container_unpause(
         &self,
         id: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -1390,8 +1521,10 @@ where
         &self,
         id: &str,
         update: crate::models::ContainerUpdateUpdate,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse2003, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse2003, Error = Error<serde_json::Value>>
+            + Send,
+    > {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -1450,8 +1583,10 @@ where
         &self,
         id: &str,
         condition: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse2004, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse2004, Error = Error<serde_json::Value>>
+            + Send,
+    > {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -1506,9 +1641,9 @@ where
         &self,
         id: &str,
         path: &str,
-        input_stream: &str,
+        input_stream: Vec<u8>,
         no_overwrite_dir_non_dir: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::PUT;
@@ -1527,8 +1662,8 @@ where
         // if let Err(e) = uri {
         //     return Box::new(futures::future::err(e));
         // }
-        let serialized = serde_json::to_string(&input_stream).unwrap();
-        let serialized_len = serialized.len();
+        // The body is the tar archive itself, not JSON.
+        let input_stream_len = input_stream.len();
 
         let mut req = hyper::Request::builder();
         req.method(method).uri(uri.unwrap());
@@ -1536,12 +1671,11 @@ where
             req.header(http::header::USER_AGENT, &**user_agent);
         }
         let mut req = req
-            .body(hyper::Body::from(serialized))
+            .header(http::header::CONTENT_TYPE, "application/x-tar")
+            .body(hyper::Body::from(input_stream))
             .expect("could not build hyper::Request");
         req.headers_mut()
-            .typed_insert(&typed_headers::ContentType(mime::APPLICATION_JSON));
-        req.headers_mut()
-            .typed_insert(&typed_headers::ContentLength(serialized_len as u64));
+            .typed_insert(&typed_headers::ContentLength(input_stream_len as u64));
 
         // send request
         Box::new(
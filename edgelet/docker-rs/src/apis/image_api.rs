@@ -86,12 +86,18 @@ pub trait ImageApi: Send + Sync {
         x_registry_auth: &str,
         platform: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
+    fn image_create_progress(
+        &self,
+        from_image: &str,
+        x_registry_auth: &str,
+        platform: &str,
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn image_delete(
         &self,
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>;
     fn image_get(
         &self,
         name: &str,
@@ -115,7 +121,10 @@ pub trait ImageApi: Send + Sync {
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn image_load(
         &self,
         images_tarball: Vec<u8>,
@@ -466,12 +475,63 @@ where
         )
     }
 
+    fn image_create_progress(
+        &self,
+        from_image: &str,
+        x_registry_auth: &str,
+        platform: &str,
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::POST;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("fromImage", &from_image.to_string())
+            .append_pair("platform", &platform.to_string())
+            .finish();
+        let uri_str = format!("/images/create?{}", query);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let req = req
+            .header("X-Registry-Auth", x_registry_auth)
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        // The response body is a sequence of JSON objects that is only
+        // complete once the pull is done, so it is returned unread.
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(|e| Error::from(e))
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        let b: &[u8] = &[];
+                        Err(Error::from((status, b)))
+                    }
+                }),
+        )
+    }
+
     fn image_delete(
         &self,
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>
     {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -735,8 +795,10 @@ where
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    > {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
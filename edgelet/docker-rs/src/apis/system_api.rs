@@ -43,11 +43,13 @@ pub trait SystemApi: Send + Sync {
         since: &str,
         until: &str,
         filters: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse20012, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn system_info(
         &self,
     ) -> Box<dyn Future<Item = crate::models::SystemInfo, Error = Error<serde_json::Value>> + Send>;
-    fn system_ping(&self) -> Box<dyn Future<Item = String, Error = Error<serde_json::Value>>>;
+    fn system_ping(
+        &self,
+    ) -> Box<dyn Future<Item = String, Error = Error<serde_json::Value>> + Send>;
     fn system_version(
         &self,
     ) -> Box<dyn Future<Item = crate::models::InlineResponse20011, Error = Error<serde_json::Value>>>;
@@ -174,8 +176,7 @@ where
         since: &str,
         until: &str,
         filters: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse20012, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -209,21 +210,12 @@ where
                 .map_err(|e| Error::from(e))
                 .and_then(|resp| {
                     let (http::response::Parts { status, .. }, body) = resp.into_parts();
-                    body.concat2()
-                        .and_then(move |body| Ok((status, body)))
-                        .map_err(|e| Error::from(e))
-                })
-                .and_then(|(status, body)| {
                     if status.is_success() {
                         Ok(body)
                     } else {
-                        Err(Error::from((status, &*body)))
+                        let b: &[u8] = &[];
+                        Err(Error::from((status, b)))
                     }
-                })
-                .and_then(|body| {
-                    let parsed: Result<crate::models::InlineResponse20012, _> =
-                        serde_json::from_slice(&body);
-                    parsed.map_err(|e| Error::from(e))
                 }),
         )
     }
@@ -279,7 +271,9 @@ where
         )
     }
 
-    fn system_ping(&self) -> Box<dyn Future<Item = String, Error = Error<serde_json::Value>>> {
+    fn system_ping(
+        &self,
+    ) -> Box<dyn Future<Item = String, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -319,10 +313,7 @@ where
                         Err(Error::from((status, &*body)))
                     }
                 })
-                .and_then(|body| {
-                    let parsed: Result<String, _> = serde_json::from_slice(&body);
-                    parsed.map_err(|e| Error::from(e))
-                }),
+                .map(|body| String::from_utf8_lossy(&body).into_owned()),
         )
     }
 
@@ -19,7 +19,7 @@ pub struct ContainerUpdateUpdate {
     cpu_shares: Option<i32>,
     /// Memory limit in bytes.
     #[serde(rename = "Memory", skip_serializing_if = "Option::is_none")]
-    memory: Option<i32>,
+    memory: Option<i64>,
     /// Path to `cgroups` under which the container's `cgroup` is created. If the path is not absolute, the path is considered to be relative to the `cgroups` path of the init process. Cgroups are created if they do not already exist.
     #[serde(rename = "CgroupParent", skip_serializing_if = "Option::is_none")]
     cgroup_parent: Option<String>,
@@ -171,16 +171,16 @@ impl ContainerUpdateUpdate {
         self.cpu_shares = None;
     }
 
-    pub fn set_memory(&mut self, memory: i32) {
+    pub fn set_memory(&mut self, memory: i64) {
         self.memory = Some(memory);
     }
 
-    pub fn with_memory(mut self, memory: i32) -> Self {
+    pub fn with_memory(mut self, memory: i64) -> Self {
         self.memory = Some(memory);
         self
     }
 
-    pub fn memory(&self) -> Option<i32> {
+    pub fn memory(&self) -> Option<i64> {
         self.memory
     }
 
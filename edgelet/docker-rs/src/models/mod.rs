@@ -12,6 +12,10 @@ mod body_3;
 pub use self::body_3::Body3;
 mod build_info;
 pub use self::build_info::BuildInfo;
+mod checkpoint;
+pub use self::checkpoint::Checkpoint;
+mod checkpoint_create_config;
+pub use self::checkpoint_create_config::CheckpointCreateConfig;
 mod cluster_info;
 pub use self::cluster_info::ClusterInfo;
 mod commit;
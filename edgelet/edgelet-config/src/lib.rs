@@ -9,8 +9,14 @@
     clippy::use_self,
 )]
 
-use std::fs::OpenOptions;
-use std::io::Read;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 
 use config::{Config, Environment, File, FileFormat};
@@ -24,7 +30,11 @@ use sha2::{Digest, Sha256};
 use url::Url;
 
 use edgelet_core::crypto::MemoryKey;
-use edgelet_core::ModuleSpec;
+use edgelet_core::watchdog::WatchdogConfig;
+use edgelet_core::{
+    AllowedRegistries, DaemonConfigSource, GcPolicy, ModuleNameRules, ModuleSpec, ProxySettings,
+    TenantConfig,
+};
 use edgelet_utils::log_failure;
 
 /// This is the name of the network created by the iotedged
@@ -39,6 +49,9 @@ const DEFAULTS: &str = include_str!("../config/unix/default.yaml");
 #[cfg(windows)]
 const DEFAULTS: &str = include_str!("../config/windows/default.yaml");
 
+/// The prefix of the environment variables that override the settings.
+const ENV_PREFIX: &str = "iotedge_";
+
 const DEVICEID_KEY: &str = "DeviceId";
 const HOSTNAME_KEY: &str = "HostName";
 const SHAREDACCESSKEY_KEY: &str = "SharedAccessKey";
@@ -178,12 +191,26 @@ impl Connect {
     }
 }
 
+/// The URIs that iotedged serves its APIs on.
+///
+/// The `IOTEDGE_MANAGEMENT_URI` and `IOTEDGE_WORKLOAD_URI` environment
+/// variables override `management_uri` and `workload_uri` when iotedged
+/// starts, without changing these settings. The path of a `unix://` URI can
+/// be at most 104 bytes long.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Listen {
     #[serde(with = "url_serde")]
     workload_uri: Url,
     #[serde(with = "url_serde")]
     management_uri: Url,
+    // Left out when false so that the settings of existing devices still hash
+    // the same, and aren't mistaken for a configuration change.
+    #[serde(default, skip_serializing_if = "is_false")]
+    use_http2: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    management_hmac_auth: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    management_tls: Option<ListenTls>,
 }
 
 impl Listen {
@@ -194,6 +221,54 @@ impl Listen {
     pub fn management_uri(&self) -> &Url {
         &self.management_uri
     }
+
+    /// Whether the APIs are served over cleartext HTTP/2 (h2c) instead of
+    /// HTTP/1.1. Clients then have to speak HTTP/2 from the start, since the
+    /// server doesn't support upgrading a HTTP/1.1 connection.
+    pub fn use_http2(&self) -> bool {
+        self.use_http2
+    }
+
+    /// Whether requests to the management API over TCP need an HMAC token,
+    /// since the callers of a TCP listener can't be authorized by their pid.
+    pub fn management_hmac_auth(&self) -> bool {
+        self.management_hmac_auth
+    }
+
+    /// The certificates that the management API is served over TLS with on
+    /// TCP, if any. Unix sockets are served without TLS either way.
+    pub fn management_tls(&self) -> Option<&ListenTls> {
+        self.management_tls.as_ref()
+    }
+}
+
+/// The PEM files of a TLS listener. Clients have to present a certificate
+/// signed by `client_ca_cert`, whose common name the API then sees as the
+/// caller.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListenTls {
+    server_cert: PathBuf,
+    server_key: PathBuf,
+    client_ca_cert: PathBuf,
+}
+
+impl ListenTls {
+    pub fn server_cert(&self) -> &Path {
+        &self.server_cert
+    }
+
+    pub fn server_key(&self) -> &Path {
+        &self.server_key
+    }
+
+    pub fn client_ca_cert(&self) -> &Path {
+        &self.client_ca_cert
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -248,6 +323,23 @@ pub struct Settings<T> {
     homedir: PathBuf,
     moby_runtime: MobyRuntime,
     certificates: Option<Certificates>,
+    // Left out when unset for the same reason as `Listen::use_http2`.
+    #[serde(default, skip_serializing_if = "WatchdogConfig::is_default")]
+    watchdog: WatchdogConfig,
+    #[serde(default, skip_serializing_if = "TenantConfig::is_default")]
+    tenants: TenantConfig,
+    #[serde(default, skip_serializing_if = "GcPolicy::is_default")]
+    image_gc: GcPolicy,
+    #[serde(default, skip_serializing_if = "AllowedRegistries::is_default")]
+    allowed_registries: AllowedRegistries,
+    #[serde(default, skip_serializing_if = "ModuleNameRules::is_default")]
+    module_names: ModuleNameRules,
+    #[serde(default, skip_serializing_if = "ProxySettings::is_default")]
+    proxy: ProxySettings,
+    // The file the settings were loaded from, so that they can be loaded
+    // again from it. It isn't a setting itself.
+    #[serde(skip)]
+    config_file: Option<PathBuf>,
 }
 
 impl<T> Settings<T>
@@ -271,7 +363,8 @@ where
 
         config.merge(Environment::with_prefix("iotedge"))?;
 
-        let settings: Self = config.try_into()?;
+        let mut settings: Self = config.try_into()?;
+        settings.config_file = filename.map(PathBuf::from);
 
         Ok(settings)
     }
@@ -312,6 +405,36 @@ where
         self.certificates.as_ref()
     }
 
+    pub fn watchdog(&self) -> &WatchdogConfig {
+        &self.watchdog
+    }
+
+    pub fn tenants(&self) -> &TenantConfig {
+        &self.tenants
+    }
+
+    pub fn image_gc(&self) -> &GcPolicy {
+        &self.image_gc
+    }
+
+    pub fn allowed_registries(&self) -> &AllowedRegistries {
+        &self.allowed_registries
+    }
+
+    pub fn module_names(&self) -> &ModuleNameRules {
+        &self.module_names
+    }
+
+    pub fn proxy(&self) -> &ProxySettings {
+        &self.proxy
+    }
+
+    /// The file the settings were loaded from, if any, from which they can be
+    /// loaded again to pick up changes.
+    pub fn config_file(&self) -> Option<&Path> {
+        self.config_file.as_ref().map(PathBuf::as_path)
+    }
+
     pub fn diff_with_cached(&self, path: &Path) -> bool {
         fn diff_with_cached_inner<T>(
             cached_settings: &Settings<T>,
@@ -346,6 +469,249 @@ where
     }
 }
 
+/// Replaces the `proxy` section of the config file at `path` with `proxy`,
+/// for the settings loaded from the file from then on. The rest of the file
+/// is kept as it is, comments included.
+pub fn save_proxy_settings(path: &Path, proxy: &ProxySettings) -> Result<(), LoadSettingsError> {
+    let section = if proxy.is_default() {
+        None
+    } else {
+        Some(serde_json::to_value(proxy)?)
+    };
+    save_settings(path, &[("proxy", section)])
+}
+
+/// Replaces the top-level sections of the config file at `path` named in
+/// `sections` with their values, and removes those without one, like
+/// `save_proxy_settings`.
+///
+/// The file keeps its mode and owner, since it holds the credentials of the
+/// device and is only readable by iotedged.
+pub fn save_settings(
+    path: &Path,
+    sections: &[(&str, Option<serde_json::Value>)],
+) -> Result<(), LoadSettingsError> {
+    let mut file = OpenOptions::new().read(true).open(path)?;
+    let mut buffer = String::new();
+    file.read_to_string(&mut buffer)?;
+    let mut config: serde_yaml::Value = serde_yaml::from_str(&buffer)?;
+    match config {
+        serde_yaml::Value::Mapping(ref mut config) => {
+            for (name, value) in sections {
+                let key = serde_yaml::Value::String((*name).to_string());
+                match value {
+                    Some(value) => {
+                        config.insert(key, serde_yaml::to_value(value)?);
+                    }
+                    None => {
+                        config.remove(&key);
+                    }
+                }
+            }
+        }
+        _ => {
+            return Err(LoadSettingsError(Context::new(Box::new(format!(
+                "{} does not hold a map of settings",
+                path.display()
+            )))));
+        }
+    }
+
+    // Only the lines of the sections that changed are replaced, so that the
+    // comments and formatting of the rest are kept. Files too unusual for
+    // that, like those of flow style, are written out whole instead.
+    let edited = replace_sections(&buffer, sections)?;
+    let contents = match serde_yaml::from_str(&edited) {
+        Ok(ref parsed) if same_settings(parsed, &config) => edited,
+        _ => serde_yaml::to_string(&config)?,
+    };
+    replace_file(path, contents.as_bytes())?;
+    Ok(())
+}
+
+/// `yaml` with the lines of the top-level sections named in `sections`
+/// replaced by their values, or removed for those without one. Sections
+/// that aren't in `yaml` yet are added at its end.
+///
+/// A section starts at the line of its name and ends before the comments
+/// and blank lines that precede the next one, which describe that one.
+fn replace_sections(
+    yaml: &str,
+    sections: &[(&str, Option<serde_json::Value>)],
+) -> Result<String, LoadSettingsError> {
+    let mut lines: Vec<String> = yaml.lines().map(ToString::to_string).collect();
+    for (name, value) in sections {
+        let section = match value {
+            Some(value) => {
+                let mut section = serde_yaml::Mapping::new();
+                section.insert(
+                    serde_yaml::Value::String((*name).to_string()),
+                    serde_yaml::to_value(value)?,
+                );
+                serde_yaml::to_string(&section)?
+                    .lines()
+                    .skip_while(|line| *line == "---")
+                    .map(ToString::to_string)
+                    .collect()
+            }
+            None => vec![],
+        };
+
+        let start = lines
+            .iter()
+            .position(|line| top_level_key(line) == Some(*name));
+        match start {
+            Some(start) => {
+                let next = lines[start + 1..]
+                    .iter()
+                    .position(|line| top_level_key(line).is_some())
+                    .map_or(lines.len(), |offset| start + 1 + offset);
+                let mut end = next;
+                while end > start + 1 && is_blank_or_comment(&lines[end - 1]) {
+                    end -= 1;
+                }
+                lines.splice(start..end, section);
+            }
+            None if !section.is_empty() => {
+                if lines.last().map_or(false, |line| !line.trim().is_empty()) {
+                    lines.push(String::new());
+                }
+                lines.extend(section);
+            }
+            None => (),
+        }
+    }
+
+    let mut yaml = lines.join("\n");
+    yaml.push('\n');
+    Ok(yaml)
+}
+
+/// The name of the top-level section that starts at `line`, if it does.
+fn top_level_key(line: &str) -> Option<&str> {
+    if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '-') {
+        return None;
+    }
+    let (key, _) = line.split_at(line.find(':')?);
+    if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        None
+    } else {
+        Some(key)
+    }
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// Whether the settings of the two config files are the same, whatever the
+/// order of their sections.
+fn same_settings(file: &serde_yaml::Value, other: &serde_yaml::Value) -> bool {
+    match (file, other) {
+        (serde_yaml::Value::Mapping(file), serde_yaml::Value::Mapping(other)) => {
+            file.len() == other.len()
+                && file
+                    .iter()
+                    .all(|(key, value)| other.get(key) == Some(value))
+        }
+        _ => false,
+    }
+}
+
+/// Replaces the file at `path` with `contents`. The contents are written to
+/// a temporary file next to it first, which keeps the file whole if the
+/// write fails halfway. The temporary file gets the mode and owner of the
+/// file before anything is written to it.
+fn replace_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+    let temp = path.with_extension("yaml.tmp");
+
+    // A temporary file left behind by an earlier failure is removed, so that
+    // it is created anew rather than opened through whatever it now is.
+    if let Err(err) = fs::remove_file(&temp) {
+        if err.kind() != io::ErrorKind::NotFound {
+            return Err(err);
+        }
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&temp)?;
+    let result = copy_owner(&file, &metadata)
+        .and_then(|()| file.set_permissions(metadata.permissions()))
+        .and_then(|()| file.write_all(contents))
+        .and_then(|()| file.sync_all())
+        .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn copy_owner(file: &fs::File, metadata: &fs::Metadata) -> io::Result<()> {
+    let current = file.metadata()?;
+    if current.uid() == metadata.uid() && current.gid() == metadata.gid() {
+        return Ok(());
+    }
+    // iotedged runs as root, which is allowed to give the file to the user
+    // and group that own the config.
+    if unsafe { libc::fchown(file.as_raw_fd(), metadata.uid(), metadata.gid()) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// On Windows the temporary file inherits the ACL of the directory, like the
+// config did.
+#[cfg(windows)]
+fn copy_owner(_file: &fs::File, _metadata: &fs::Metadata) -> io::Result<()> {
+    Ok(())
+}
+/// Where each top-level section of the settings that `Settings::new` loads
+/// from the config file at `path` came from. Sections that came from the
+/// defaults are left out.
+pub fn settings_sources(
+    path: Option<&Path>,
+) -> Result<BTreeMap<String, DaemonConfigSource>, LoadSettingsError> {
+    let file = match path {
+        Some(path) => {
+            let mut file = OpenOptions::new().read(true).open(path)?;
+            let mut buffer = String::new();
+            file.read_to_string(&mut buffer)?;
+            serde_yaml::from_str(&buffer)?
+        }
+        None => serde_yaml::Value::Null,
+    };
+    let vars = env::vars_os().filter_map(|(key, _)| key.into_string().ok());
+    Ok(sources_of(&file, vars))
+}
+
+/// Like the `Environment` source of the settings, the variables match the
+/// prefix and the names of the sections regardless of case.
+fn sources_of<I>(file: &serde_yaml::Value, vars: I) -> BTreeMap<String, DaemonConfigSource>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut sources = BTreeMap::new();
+    if let serde_yaml::Value::Mapping(file) = file {
+        for key in file.iter().filter_map(|(key, _)| key.as_str()) {
+            sources.insert(key.to_lowercase(), DaemonConfigSource::File);
+        }
+    }
+    for key in vars {
+        let key = key.to_lowercase();
+        if key.starts_with(ENV_PREFIX) {
+            sources.insert(key[ENV_PREFIX.len()..].to_string(), DaemonConfigSource::Env);
+        }
+    }
+    sources
+}
+
 #[derive(Debug, Fail)]
 #[fail(display = "Could not load settings")]
 pub struct LoadSettingsError(#[cause] Context<Box<dyn std::fmt::Display + Send + Sync>>);
@@ -368,6 +734,12 @@ impl From<serde_json::Error> for LoadSettingsError {
     }
 }
 
+impl From<serde_yaml::Error> for LoadSettingsError {
+    fn from(err: serde_yaml::Error) -> Self {
+        LoadSettingsError(Context::new(Box::new(err)))
+    }
+}
+
 #[derive(Clone, Copy, Debug, Fail)]
 pub enum ParseManualDeviceConnectionStringError {
     #[fail(
@@ -390,8 +762,11 @@ mod tests {
     use super::*;
     use config::{Config, File, FileFormat};
     use edgelet_docker::DockerConfig;
+    use serde_json::json;
     use std::fs::File as FsFile;
     use std::io::Write;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
     use tempdir::TempDir;
 
     #[cfg(unix)]
@@ -443,6 +818,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn http2_is_off_by_default() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert!(!settings.listen().use_http2());
+
+        let listen = serde_json::to_value(settings.listen()).unwrap();
+        assert!(listen.get("use_http2").is_none());
+
+        let listen: Listen = serde_json::from_str(
+            r#"{"workload_uri":"unix:///w.sock","management_uri":"unix:///m.sock","use_http2":true}"#,
+        )
+        .unwrap();
+        assert!(listen.use_http2());
+    }
+
+    #[test]
+    fn management_tls_is_off_by_default() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert!(settings.listen().management_tls().is_none());
+
+        let listen = serde_json::to_value(settings.listen()).unwrap();
+        assert!(listen.get("management_tls").is_none());
+
+        let listen: Listen = serde_json::from_str(
+            r#"{"workload_uri":"unix:///w.sock","management_uri":"unix:///m.sock","management_tls":{"server_cert":"/s.pem","server_key":"/k.pem","client_ca_cert":"/ca.pem"}}"#,
+        )
+        .unwrap();
+        let tls = listen.management_tls().unwrap();
+        assert_eq!(Path::new("/s.pem"), tls.server_cert());
+        assert_eq!(Path::new("/k.pem"), tls.server_key());
+        assert_eq!(Path::new("/ca.pem"), tls.client_ca_cert());
+    }
+
     #[test]
     fn no_file_gets_error() {
         let settings = Settings::<DockerConfig>::new(Some(Path::new("garbage")));
@@ -559,6 +967,170 @@ mod tests {
         assert_eq!(settings.diff_with_cached(Path::new("i dont exist")), true);
     }
 
+    #[test]
+    fn watchdog_defaults_to_unlimited_restarts() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert_eq!(None, settings.watchdog().max_restart_attempts());
+        assert!(!serde_json::to_string(&settings)
+            .unwrap()
+            .contains("watchdog"));
+    }
+
+    #[test]
+    fn tenants_default_to_none() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert!(settings.tenants().tenants().is_empty());
+        assert!(!serde_json::to_string(&settings)
+            .unwrap()
+            .contains("tenants"));
+    }
+
+    #[test]
+    fn image_gc_defaults_to_off() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert_eq!(0, settings.image_gc().min_free_bytes());
+        assert!(!serde_json::to_string(&settings)
+            .unwrap()
+            .contains("image_gc"));
+    }
+
+    #[test]
+    fn allowed_registries_default_to_all() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert!(settings.allowed_registries().registries().is_empty());
+        assert!(!serde_json::to_string(&settings)
+            .unwrap()
+            .contains("allowed_registries"));
+    }
+
+    #[test]
+    fn module_names_default_to_reserving_edge_runtime_modules() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert!(settings.module_names().pattern().is_none());
+        assert!(settings.module_names().is_reserved("edgeHub"));
+        assert!(!serde_json::to_string(&settings)
+            .unwrap()
+            .contains("module_names"));
+    }
+
+    #[test]
+    fn config_file_is_remembered() {
+        let settings = Settings::<DockerConfig>::new(Some(Path::new(GOOD_SETTINGS))).unwrap();
+        assert_eq!(Some(Path::new(GOOD_SETTINGS)), settings.config_file());
+        assert!(!serde_json::to_string(&settings)
+            .unwrap()
+            .contains("config_file"));
+    }
+
+    #[test]
+    fn proxy_settings_are_saved() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let path = tmp_dir.path().join("config.yaml");
+        fs::copy(GOOD_SETTINGS, &path).unwrap();
+        let proxy = ProxySettings::new(
+            Some("http://proxy.contoso.com:3128/".to_string()),
+            None,
+            Some("localhost".to_string()),
+        );
+
+        save_proxy_settings(&path, &proxy).unwrap();
+
+        let settings = Settings::<DockerConfig>::new(Some(path.as_path())).unwrap();
+        assert_eq!(&proxy, settings.proxy());
+        assert_eq!("localhost", settings.hostname());
+
+        save_proxy_settings(&path, &ProxySettings::default()).unwrap();
+
+        let settings = Settings::<DockerConfig>::new(Some(path.as_path())).unwrap();
+        assert!(settings.proxy().is_default());
+    }
+
+    #[test]
+    fn settings_sections_are_saved() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let path = tmp_dir.path().join("config.yaml");
+        fs::copy(GOOD_SETTINGS, &path).unwrap();
+
+        save_settings(
+            &path,
+            &[
+                (
+                    "image_gc",
+                    Some(json!({ "min_free_bytes": 1024, "max_image_age_secs": 3600 })),
+                ),
+                ("allowed_registries", None),
+            ],
+        )
+        .unwrap();
+
+        let settings = Settings::<DockerConfig>::new(Some(path.as_path())).unwrap();
+        assert_eq!(&GcPolicy::new(1024, 3600), settings.image_gc());
+        assert!(settings.allowed_registries().registries().is_empty());
+        assert_eq!("localhost", settings.hostname());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saved_settings_keep_the_mode_and_comments_of_the_file() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let path = tmp_dir.path().join("config.yaml");
+        fs::copy(GOOD_SETTINGS, &path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o400)).unwrap();
+        let proxy = ProxySettings::new(
+            Some("http://proxy.contoso.com:3128/".to_string()),
+            None,
+            None,
+        );
+
+        save_proxy_settings(&path, &proxy).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(0o400, metadata.permissions().mode() & 0o777);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("# Configures the provisioning mode\nprovisioning:\n"));
+        assert!(contents.contains("# Sets the uris to listen on\n"));
+        assert!(!tmp_dir.path().join("config.yaml.tmp").exists());
+        let settings = Settings::<DockerConfig>::new(Some(path.as_path())).unwrap();
+        assert_eq!(&proxy, settings.proxy());
+    }
+
+    #[test]
+    fn sections_are_replaced_in_place() {
+        let yaml = "# The name of the device\nhostname: \"localhost\"\n\n# The proxies\nproxy:\n  https_proxy: \"http://old:3128\"\n\n# The home\nhomedir: \"/tmp\"\n";
+
+        let edited = replace_sections(
+            yaml,
+            &[
+                ("proxy", Some(json!({ "https_proxy": "http://new:3128" }))),
+                ("homedir", None),
+                ("image_gc", Some(json!({ "min_free_bytes": 1024 }))),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            "# The name of the device\nhostname: \"localhost\"\n\n# The proxies\nproxy:\n  https_proxy: \"http://new:3128\"\n\n# The home\n\nimage_gc:\n  min_free_bytes: 1024\n",
+            edited
+        );
+    }
+
+    #[test]
+    fn settings_sources_prefer_env_over_file() {
+        let file = serde_yaml::from_str("hostname: device1\nhomedir: /var/lib/iotedge\n").unwrap();
+        let vars = vec![
+            "IOTEDGE_HOMEDIR".to_string(),
+            "iotedge_allowed_registries".to_string(),
+            "HTTPS_PROXY".to_string(),
+        ];
+
+        let sources = sources_of(&file, vars);
+
+        assert_eq!(3, sources.len());
+        assert_eq!(DaemonConfigSource::File, sources["hostname"]);
+        assert_eq!(DaemonConfigSource::Env, sources["homedir"]);
+        assert_eq!(DaemonConfigSource::Env, sources["allowed_registries"]);
+    }
+
     #[test]
     fn network_default() {
         let moby1 = MobyRuntime {
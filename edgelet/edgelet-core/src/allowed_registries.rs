@@ -0,0 +1,78 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The registries that modules may be created from, by the registry host of
+/// their image and optionally a path under it. An entry allows the images
+/// that start with it up to a `:` or `/`, so `contoso.azurecr.io` allows
+/// `contoso.azurecr.io/module` and `contoso.azurecr.io:443/module`, but not
+/// `contoso.azurecr.io.evil.io/module`. All registries are allowed when the
+/// list is empty, which it is by default.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AllowedRegistries(Vec<String>);
+
+impl AllowedRegistries {
+    pub fn new(registries: Vec<String>) -> Self {
+        AllowedRegistries(registries)
+    }
+
+    pub fn registries(&self) -> &[String] {
+        &self.0
+    }
+
+    /// Whether `image`, a reference that starts with the host of its
+    /// registry, is from an allowed registry.
+    pub fn allows(&self, image: &str) -> bool {
+        self.0.is_empty()
+            || self.0.iter().any(|allowed| {
+                image.starts_with(allowed.as_str())
+                    && match image[allowed.len()..].chars().next() {
+                        None | Some(':') | Some('/') => true,
+                        Some(_) => allowed.ends_with('/'),
+                    }
+            })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_list_allows_every_registry() {
+        let allowed = AllowedRegistries::default();
+
+        assert!(allowed.allows("docker.io"));
+        assert!(allowed.allows("contoso.azurecr.io"));
+    }
+
+    #[test]
+    fn entries_match_up_to_a_separator() {
+        let allowed = AllowedRegistries::new(vec![
+            "mcr.microsoft.com".to_string(),
+            "contoso.azurecr.io/edge".to_string(),
+        ]);
+
+        assert!(allowed.allows("mcr.microsoft.com"));
+        assert!(allowed.allows("mcr.microsoft.com/azureiotedge-agent:1.0"));
+        assert!(allowed.allows("mcr.microsoft.com:443/azureiotedge-agent"));
+        assert!(allowed.allows("contoso.azurecr.io/edge/module:1.0"));
+        assert!(!allowed.allows("contoso.azurecr.io/edgex/module"));
+        assert!(!allowed.allows("contoso.azurecr.io/other/module"));
+        assert!(!allowed.allows("docker.io/library/alpine"));
+        assert!(!allowed.allows("fabrikam.azurecr.io/module"));
+    }
+
+    #[test]
+    fn look_alike_hosts_are_not_allowed() {
+        let allowed = AllowedRegistries::new(vec!["mcr.microsoft.com".to_string()]);
+
+        assert!(!allowed.allows("mcr.microsoft.com.evil.io/x"));
+        assert!(!allowed.allows("mcr.microsoft.comx.io/x"));
+        assert!(!allowed.allows("mcr.microsoft.co/x"));
+    }
+}
@@ -2,18 +2,27 @@
 
 use failure::Fail;
 use futures::future::Either;
-use futures::{future, Future};
+use futures::{future, Future, Stream};
 use log::info;
 
 use crate::error::{Error, ErrorKind};
-use crate::module::{ModuleRuntime, ModuleRuntimeErrorReason};
+use crate::module::{Module, ModuleRuntime, ModuleRuntimeErrorReason};
 use crate::pid::Pid;
+use crate::tenant::unscoped_name;
 
 #[derive(Debug)]
 pub enum Policy {
     Anonymous,
     Caller,
     Module(&'static str),
+    /// Only the named module may call, whichever module the request names.
+    /// The caller is identified the same way as for `Caller`, by checking
+    /// that its process belongs to the module.
+    ModuleId(String),
+    /// Only modules of the tenant named by the request may call. The tenant
+    /// is passed to `authorize` as the name, and the caller's process must
+    /// belong to one of the tenant's modules.
+    Tenant,
 }
 
 pub struct Authorization<M> {
@@ -39,10 +48,32 @@ where
         match self.policy {
             Policy::Anonymous => Either::A(Either::A(self.auth_anonymous())),
             Policy::Caller => Either::A(Either::B(self.auth_caller(name, pid))),
-            Policy::Module(ref expected_name) => Either::B(self.auth_module(expected_name, pid)),
+            Policy::ModuleId(ref expected_name) => Either::A(Either::B(
+                self.auth_caller(Some(expected_name.clone()), pid),
+            )),
+            Policy::Module(ref expected_name) => {
+                Either::B(Either::A(self.auth_module(expected_name, pid)))
+            }
+            Policy::Tenant => Either::B(Either::B(self.auth_tenant(name, pid))),
+        }
+    }
+
+    /// The module that a request this policy authorized was made by, if the
+    /// policy identifies it. `name` is the module named by the request.
+    pub fn caller(&self, name: Option<&str>) -> Option<String> {
+        match self.policy {
+            Policy::Anonymous => None,
+            Policy::Caller => name.map(|n| n.trim_start_matches('$').to_string()),
+            Policy::Module(expected_name) => Some(expected_name.to_string()),
+            Policy::ModuleId(ref expected_name) => Some(expected_name.clone()),
+            Policy::Tenant => None,
         }
     }
 
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
     fn auth_anonymous(&self) -> impl Future<Item = bool, Error = Error> {
         future::ok(true)
     }
@@ -55,7 +86,8 @@ where
         name.map_or_else(
             || Either::A(future::ok(false)),
             |name| {
-                Either::B(self.runtime.top(&name).then(move |result| match result {
+                let top = self.runtime.top(&name, None);
+                Either::B(top.then(move |result| match result {
                     Ok(mt) => {
                         let authorize = mt.process_ids().contains(&pid);
                         if !authorize {
@@ -102,21 +134,59 @@ where
                 },
             })
     }
+
+    fn auth_tenant(
+        &self,
+        tenant: Option<String>,
+        pid: Pid,
+    ) -> impl Future<Item = bool, Error = Error> {
+        tenant.map_or_else(
+            || Either::A(future::ok(false)),
+            |tenant| {
+                Either::B(
+                    self.runtime
+                        .list_with_details()
+                        .filter(move |(m, rs)| {
+                            unscoped_name(&tenant, m.name()).is_some() && rs.pid() == pid
+                        })
+                        .collect()
+                        .then(move |modules| match modules {
+                            Ok(ref modules) if modules.is_empty() => {
+                                info!(
+                                    "Request not authorized - caller pid {} not found in tenant modules",
+                                    pid
+                                );
+                                Ok(false)
+                            }
+                            Ok(_) => Ok(true),
+                            Err(err) => Err(Error::from(err.context(ErrorKind::ModuleRuntime))),
+                        }),
+                )
+            },
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use bytes::Bytes;
     use futures::future::FutureResult;
     use futures::stream::Empty;
     use futures::{future, stream, IntoFuture, Stream};
+    use std::collections::HashMap;
     use std::error::Error;
     use std::time::Duration;
 
     use super::*;
+    use crate::capabilities::Capabilities;
+    use crate::connectivity::ConnectivityStatus;
     use crate::module::{
-        LogOptions, Module, ModuleRegistry, ModuleRuntimeState, ModuleSpec, ModuleTop,
-        SystemInfo as CoreSystemInfo,
+        CircuitState, LogOptions, Module, ModuleDetails, ModuleEvent, ModuleRegistry,
+        ModuleRuntimeState, ModuleSpec, ModuleStats, ModuleTop, PortBinding, PruneImagesResult,
+        PullProgress, ResourceLimits, SystemInfo as CoreSystemInfo, SystemResources, VolumeMount,
     };
+    use crate::snapshot::SystemSnapshot;
+    use crate::start_all::StartResult;
 
     #[test]
     fn should_authorize_anonymous() {
@@ -209,6 +279,91 @@ mod tests {
         assert_eq!(true, auth.authorize(None, Pid::Value(123)).wait().unwrap());
     }
 
+    #[test]
+    fn caller_is_named_by_policy() {
+        let runtime = TestModuleList::new(vec![]);
+        let anonymous = Authorization::new(runtime.clone(), Policy::Anonymous);
+        let caller = Authorization::new(runtime.clone(), Policy::Caller);
+        let module = Authorization::new(runtime.clone(), Policy::Module("abc"));
+        let module_id = Authorization::new(runtime.clone(), Policy::ModuleId("abc".to_string()));
+        let tenant = Authorization::new(runtime, Policy::Tenant);
+
+        assert_eq!(None, anonymous.caller(Some("xyz")));
+        assert_eq!(Some("xyz".to_string()), caller.caller(Some("$xyz")));
+        assert_eq!(None, caller.caller(None));
+        assert_eq!(Some("abc".to_string()), module.caller(Some("xyz")));
+        assert_eq!(Some("abc".to_string()), module_id.caller(Some("xyz")));
+        assert_eq!(None, tenant.caller(Some("t1")));
+    }
+
+    #[test]
+    fn should_authorize_tenant_module() {
+        let runtime = TestModuleList::new(vec![
+            TestModule::new("t2.abc", 987),
+            TestModule::new("t1.abc", 123),
+        ]);
+        let auth = Authorization::new(runtime, Policy::Tenant);
+        assert_eq!(
+            true,
+            auth.authorize(Some("t1".to_string()), Pid::Value(123))
+                .wait()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn should_reject_module_of_other_tenant() {
+        let runtime = TestModuleList::new(vec![
+            TestModule::new("t2.abc", 987),
+            TestModule::new("t1.abc", 123),
+            TestModule::new("t1", 456),
+        ]);
+        let auth = Authorization::new(runtime, Policy::Tenant);
+        assert_eq!(
+            false,
+            auth.authorize(Some("t1".to_string()), Pid::Value(987))
+                .wait()
+                .unwrap()
+        );
+        assert_eq!(
+            false,
+            auth.authorize(Some("t1".to_string()), Pid::Value(456))
+                .wait()
+                .unwrap()
+        );
+        assert_eq!(false, auth.authorize(None, Pid::Value(123)).wait().unwrap());
+    }
+
+    #[test]
+    fn should_authorize_module_id_whatever_module_is_named() {
+        let runtime = TestModuleList::new(vec![
+            TestModule::new("xyz", 987),
+            TestModule::new("abc", 123),
+        ]);
+        let auth = Authorization::new(runtime, Policy::ModuleId("abc".to_string()));
+        assert_eq!(
+            true,
+            auth.authorize(Some("xyz".to_string()), Pid::Value(123))
+                .wait()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn should_reject_module_id_with_different_pid() {
+        let runtime = TestModuleList::new(vec![
+            TestModule::new("xyz", 987),
+            TestModule::new("abc", 123),
+        ]);
+        let auth = Authorization::new(runtime, Policy::ModuleId("abc".to_string()));
+        assert_eq!(
+            false,
+            auth.authorize(Some("xyz".to_string()), Pid::Value(987))
+                .wait()
+                .unwrap()
+        );
+    }
+
     #[test]
     fn should_reject_module_whose_name_does_not_match_policy() {
         let runtime = TestModuleList::new(vec![TestModule::new("xyz", 123)]);
@@ -323,6 +478,9 @@ mod tests {
         fn config(&self) -> &Self::Config {
             &TestConfig {}
         }
+        fn labels(&self) -> HashMap<String, String> {
+            HashMap::new()
+        }
         fn runtime_state(&self) -> Self::RuntimeStateFuture {
             notimpl_error!()
         }
@@ -378,6 +536,7 @@ mod tests {
         type ModuleRegistry = Self;
         type Chunk = String;
         type Logs = Empty<Self::Chunk, Self::Error>;
+        type RegistryAuth = ();
 
         type CreateFuture = FutureResult<(), Self::Error>;
         type GetFuture = FutureResult<(Self::Module, ModuleRuntimeState), Self::Error>;
@@ -391,8 +550,37 @@ mod tests {
         type StartFuture = FutureResult<(), Self::Error>;
         type StopFuture = FutureResult<(), Self::Error>;
         type SystemInfoFuture = FutureResult<CoreSystemInfo, Self::Error>;
+        type SystemResourcesFuture = FutureResult<SystemResources, Self::Error>;
         type RemoveAllFuture = FutureResult<(), Self::Error>;
         type TopFuture = FutureResult<ModuleTop, Self::Error>;
+        type StatsFuture = FutureResult<ModuleStats, Self::Error>;
+        type InspectFuture = FutureResult<ModuleDetails, Self::Error>;
+        type EnvFuture = FutureResult<HashMap<String, String>, Self::Error>;
+        type UpdateEnvFuture = FutureResult<(), Self::Error>;
+        type UpdateResourcesFuture = FutureResult<(), Self::Error>;
+        type UpsertFuture = FutureResult<(Self::Module, bool), Self::Error>;
+        type PingFuture = FutureResult<(), Self::Error>;
+        type EventStream = Box<dyn Stream<Item = ModuleEvent, Error = Self::Error> + Send>;
+        type GetConfigFuture = FutureResult<Option<ModuleSpec<Self::Config>>, Self::Error>;
+        type ExecFuture = FutureResult<Self::Logs, Self::Error>;
+        type PullImageStream = Empty<PullProgress, Self::Error>;
+        type PullImageFuture = FutureResult<Self::PullImageStream, Self::Error>;
+        type PruneImagesFuture = FutureResult<PruneImagesResult, Self::Error>;
+        type SignalFuture = FutureResult<(), Self::Error>;
+        type PortsFuture = FutureResult<Vec<PortBinding>, Self::Error>;
+        type WaitUntilStoppedFuture = FutureResult<(), Self::Error>;
+        type ConnectivityStatusFuture = FutureResult<ConnectivityStatus, Self::Error>;
+        type CheckpointFuture = FutureResult<(), Self::Error>;
+        type RestoreFuture = FutureResult<(), Self::Error>;
+        type ListCheckpointsFuture = FutureResult<Vec<String>, Self::Error>;
+        type VolumesFuture = FutureResult<Vec<VolumeMount>, Self::Error>;
+        type SnapshotFuture = FutureResult<SystemSnapshot, Self::Error>;
+        type StartAllFuture = FutureResult<Vec<StartResult>, crate::Error>;
+        type SetBandwidthFuture = FutureResult<(), Self::Error>;
+        type PauseFuture = FutureResult<(), Self::Error>;
+        type UnpauseFuture = FutureResult<(), Self::Error>;
+        type CopyToContainerFuture = FutureResult<(), Self::Error>;
+        type RenameFuture = FutureResult<(), Self::Error>;
 
         fn init(&self) -> Self::InitFuture {
             notimpl_error!()
@@ -436,6 +624,10 @@ mod tests {
             notimpl_error!()
         }
 
+        fn system_resources(&self) -> Self::SystemResourcesFuture {
+            notimpl_error!()
+        }
+
         fn restart(&self, _id: &str) -> Self::RestartFuture {
             notimpl_error!()
         }
@@ -449,7 +641,20 @@ mod tests {
         }
 
         fn list_with_details(&self) -> Self::ListWithDetailsStream {
-            Box::new(notimpl_error_stream!())
+            match self.behavior {
+                TestModuleListBehavior::FailCall => Box::new(notimpl_error_stream!()),
+                _ => Box::new(stream::iter_ok(
+                    self.modules
+                        .iter()
+                        .map(|m| {
+                            (
+                                m.clone(),
+                                ModuleRuntimeState::default().with_pid(Pid::Value(m.pid)),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+            }
         }
 
         fn logs(&self, _id: &str, _options: &LogOptions) -> Self::LogsFuture {
@@ -464,7 +669,7 @@ mod tests {
             notimpl_error!()
         }
 
-        fn top(&self, id: &str) -> Self::TopFuture {
+        fn top(&self, id: &str, _ps_args: Option<&str>) -> Self::TopFuture {
             let module = self
                 .modules
                 .iter()
@@ -478,5 +683,143 @@ mod tests {
                 TestModuleListBehavior::FailCall => notimpl_error!(),
             }
         }
+
+        fn stats(&self, _id: &str) -> Self::StatsFuture {
+            notimpl_error!()
+        }
+
+        fn inspect(&self, _id: &str) -> Self::InspectFuture {
+            notimpl_error!()
+        }
+
+        fn env(&self, _id: &str) -> Self::EnvFuture {
+            notimpl_error!()
+        }
+
+        fn update_env(&self, _id: &str, _env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+            notimpl_error!()
+        }
+
+        fn update_resources(
+            &self,
+            _id: &str,
+            _limits: ResourceLimits,
+        ) -> Self::UpdateResourcesFuture {
+            notimpl_error!()
+        }
+
+        fn upsert(&self, _module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
+            notimpl_error!()
+        }
+
+        fn ping(&self) -> Self::PingFuture {
+            notimpl_error!()
+        }
+
+        fn events(&self) -> Self::EventStream {
+            Box::new(notimpl_error_stream!())
+        }
+
+        fn get_config(&self, _id: &str) -> Self::GetConfigFuture {
+            notimpl_error!()
+        }
+
+        fn exec(&self, _id: &str, _cmd: &[String], _tty: bool) -> Self::ExecFuture {
+            notimpl_error!()
+        }
+
+        fn pull_image(
+            &self,
+            _image: &str,
+            _auth: Option<Self::RegistryAuth>,
+        ) -> Self::PullImageFuture {
+            notimpl_error!()
+        }
+
+        fn prune_images(&self, _dangling_only: bool) -> Self::PruneImagesFuture {
+            notimpl_error!()
+        }
+
+        fn signal(&self, _id: &str, _signal: u32) -> Self::SignalFuture {
+            notimpl_error!()
+        }
+
+        fn get_ports(&self, _id: &str) -> Self::PortsFuture {
+            notimpl_error!()
+        }
+
+        fn wait_until_stopped(
+            &self,
+            _id: &str,
+            _timeout: Duration,
+        ) -> Self::WaitUntilStoppedFuture {
+            notimpl_error!()
+        }
+
+        fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+            notimpl_error!()
+        }
+
+        fn checkpoint(&self, _id: &str, _checkpoint_id: &str) -> Self::CheckpointFuture {
+            notimpl_error!()
+        }
+
+        fn restore(&self, _id: &str, _checkpoint_id: &str) -> Self::RestoreFuture {
+            notimpl_error!()
+        }
+
+        fn list_checkpoints(&self, _id: &str) -> Self::ListCheckpointsFuture {
+            notimpl_error!()
+        }
+
+        fn get_volumes(&self, _id: &str) -> Self::VolumesFuture {
+            notimpl_error!()
+        }
+
+        fn snapshot(&self) -> Self::SnapshotFuture {
+            notimpl_error!()
+        }
+
+        fn start_all(&self, _specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+            unimplemented!()
+        }
+
+        fn set_bandwidth(
+            &self,
+            _id: &str,
+            _ingress_kbps: u32,
+            _egress_kbps: u32,
+        ) -> Self::SetBandwidthFuture {
+            unimplemented!()
+        }
+
+        fn pause(&self, _id: &str) -> Self::PauseFuture {
+            unimplemented!()
+        }
+
+        fn unpause(&self, _id: &str) -> Self::UnpauseFuture {
+            unimplemented!()
+        }
+
+        fn copy_to_container(
+            &self,
+            _id: &str,
+            _dest_path: &str,
+            _content: Bytes,
+        ) -> Self::CopyToContainerFuture {
+            unimplemented!()
+        }
+
+        fn rename(&self, _old_name: &str, _new_name: &str) -> Self::RenameFuture {
+            unimplemented!()
+        }
+
+        fn circuit_state(&self) -> CircuitState {
+            CircuitState::Closed
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
     }
 }
@@ -0,0 +1,125 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use serde_derive::Serialize;
+
+/// The optional features of a `ModuleRuntime`, as returned by
+/// `ModuleRuntime::capabilities`. A runtime that lacks a feature may fail the
+/// calls that need it or do nothing for them, so clients should check first.
+/// Every feature is missing by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Capabilities {
+    /// Running commands in a module with `exec`.
+    exec: bool,
+    /// Saving and restoring the state of modules with `checkpoint`,
+    /// `restore` and `list_checkpoints`.
+    checkpoint: bool,
+    /// Sending POSIX signals to modules with `signal`.
+    signal: bool,
+    /// Listing the processes of a module with `top`.
+    top: bool,
+    /// Reporting the resource usage of a module with `stats`.
+    stats: bool,
+    /// Changing the resource limits of a running module with
+    /// `update_resources`.
+    update_resources: bool,
+    /// Pulling images ahead of deployments with `pull_image`.
+    pull_image: bool,
+    /// Removing unused images with `prune_images`.
+    prune_images: bool,
+    /// Streaming the lifecycle events of modules with `events`.
+    events: bool,
+    /// Limiting the network traffic of modules with `set_bandwidth`.
+    bandwidth: bool,
+}
+
+impl Capabilities {
+    pub fn exec(self) -> bool {
+        self.exec
+    }
+
+    pub fn with_exec(mut self, exec: bool) -> Self {
+        self.exec = exec;
+        self
+    }
+
+    pub fn checkpoint(self) -> bool {
+        self.checkpoint
+    }
+
+    pub fn with_checkpoint(mut self, checkpoint: bool) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    pub fn signal(self) -> bool {
+        self.signal
+    }
+
+    pub fn with_signal(mut self, signal: bool) -> Self {
+        self.signal = signal;
+        self
+    }
+
+    pub fn top(self) -> bool {
+        self.top
+    }
+
+    pub fn with_top(mut self, top: bool) -> Self {
+        self.top = top;
+        self
+    }
+
+    pub fn stats(self) -> bool {
+        self.stats
+    }
+
+    pub fn with_stats(mut self, stats: bool) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    pub fn update_resources(self) -> bool {
+        self.update_resources
+    }
+
+    pub fn with_update_resources(mut self, update_resources: bool) -> Self {
+        self.update_resources = update_resources;
+        self
+    }
+
+    pub fn pull_image(self) -> bool {
+        self.pull_image
+    }
+
+    pub fn with_pull_image(mut self, pull_image: bool) -> Self {
+        self.pull_image = pull_image;
+        self
+    }
+
+    pub fn prune_images(self) -> bool {
+        self.prune_images
+    }
+
+    pub fn with_prune_images(mut self, prune_images: bool) -> Self {
+        self.prune_images = prune_images;
+        self
+    }
+
+    pub fn events(self) -> bool {
+        self.events
+    }
+
+    pub fn with_events(mut self, events: bool) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn bandwidth(self) -> bool {
+        self.bandwidth
+    }
+
+    pub fn with_bandwidth(mut self, bandwidth: bool) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+}
@@ -0,0 +1,517 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::{DateTime, TimeZone, Utc};
+use failure::{Fail, ResultExt};
+use futures::future::{self, FutureResult};
+use futures::Future;
+use log::warn;
+use openssl::asn1::Asn1TimeRef;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use serde_derive::Serialize;
+
+use crate::certificate_properties::CertificateProperties;
+use crate::crypto::{
+    Certificate, CreateCertificate, Decrypt, Encrypt, GetTrustBundle, MasterEncryptionKey,
+};
+use crate::error::{Error, ErrorKind, Result};
+
+/// The alias the certificates of the trust bundle are listed with.
+pub const TRUST_BUNDLE_ALIAS: &str = "trust-bundle";
+
+/// A certificate as listed by a `CertificateManager`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CertInfo {
+    alias: String,
+    not_before: DateTime<Utc>,
+    not_after: DateTime<Utc>,
+    /// Hex of the SHA-1 of the certificate, as IoT Hub shows thumbprints.
+    thumbprint: String,
+    subject_cn: String,
+}
+
+impl CertInfo {
+    pub fn new(
+        alias: String,
+        not_before: DateTime<Utc>,
+        not_after: DateTime<Utc>,
+        thumbprint: String,
+        subject_cn: String,
+    ) -> Self {
+        CertInfo {
+            alias,
+            not_before,
+            not_after,
+            thumbprint,
+            subject_cn,
+        }
+    }
+
+    /// Reads every certificate of `pem`, which can hold a chain or bundle.
+    pub fn from_pem(alias: &str, pem: &[u8]) -> Result<Vec<Self>> {
+        X509::stack_from_pem(pem)
+            .context(ErrorKind::ReadCertificate)?
+            .iter()
+            .map(|cert| {
+                let thumbprint = cert
+                    .digest(MessageDigest::sha1())
+                    .context(ErrorKind::ReadCertificate)?
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect();
+                let subject_cn = cert
+                    .subject_name()
+                    .entries_by_nid(Nid::COMMONNAME)
+                    .next()
+                    .and_then(|cn| cn.data().as_utf8().ok())
+                    .map_or_else(String::new, |cn| cn.to_string());
+                Ok(CertInfo {
+                    alias: alias.to_string(),
+                    not_before: to_datetime(cert.not_before())?,
+                    not_after: to_datetime(cert.not_after())?,
+                    thumbprint,
+                    subject_cn,
+                })
+            })
+            .collect()
+    }
+
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    pub fn not_before(&self) -> &DateTime<Utc> {
+        &self.not_before
+    }
+
+    pub fn not_after(&self) -> &DateTime<Utc> {
+        &self.not_after
+    }
+
+    pub fn thumbprint(&self) -> &str {
+        &self.thumbprint
+    }
+
+    pub fn subject_cn(&self) -> &str {
+        &self.subject_cn
+    }
+}
+
+/// OpenSSL prints times like `Jan 30 12:00:00 2019 GMT`.
+pub(crate) fn to_datetime(time: &Asn1TimeRef) -> Result<DateTime<Utc>> {
+    let time = Utc
+        .datetime_from_str(&time.to_string(), "%b %d %H:%M:%S %Y GMT")
+        .context(ErrorKind::ReadCertificate)?;
+    Ok(time)
+}
+
+/// Lists the certificates the daemon manages, so that monitoring can warn
+/// about certificates that are about to expire, and renews them on demand.
+pub trait CertificateManager {
+    type Error: Fail;
+    type ListFuture: Future<Item = Vec<CertInfo>, Error = Self::Error> + Send;
+    type RenewFuture: Future<Item = CertInfo, Error = Self::Error> + Send;
+
+    fn list(&self) -> Self::ListFuture;
+    /// Replaces the certificate `alias` with a new one created from the same
+    /// properties.
+    fn renew(&self, alias: &str) -> Self::RenewFuture;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CertificateManagerErrorReason {
+    NotFound,
+    RenewalInProgress,
+    Other,
+}
+
+impl<'a> From<&'a Error> for CertificateManagerErrorReason {
+    fn from(err: &'a Error) -> Self {
+        match err.kind() {
+            ErrorKind::CertificateNotFound(_) => CertificateManagerErrorReason::NotFound,
+            ErrorKind::CertificateRenewalInProgress(_) => {
+                CertificateManagerErrorReason::RenewalInProgress
+            }
+            _ => CertificateManagerErrorReason::Other,
+        }
+    }
+}
+
+/// A certificate created through a `CertificateInventory`, with the
+/// properties it was created from so that it can be renewed.
+#[derive(Clone)]
+struct Recorded {
+    properties: CertificateProperties,
+    certificates: Vec<CertInfo>,
+}
+
+/// Keeps track of the certificates created through it, since the HSM can't
+/// list the certificates it holds. Everything else is passed on to the
+/// wrapped crypto. Clones share the same certificates.
+///
+/// The trust bundle is listed with the alias `trust-bundle`. Only the
+/// certificates created through the inventory can be renewed.
+#[derive(Clone)]
+pub struct CertificateInventory<C> {
+    crypto: C,
+    certificates: Arc<RwLock<BTreeMap<String, Recorded>>>,
+    renewing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl<C> CertificateInventory<C> {
+    pub fn new(crypto: C) -> Self {
+        CertificateInventory {
+            crypto,
+            certificates: Arc::new(RwLock::new(BTreeMap::new())),
+            renewing: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+}
+
+impl<C> CertificateInventory<C>
+where
+    C: CreateCertificate,
+{
+    /// The certificate is destroyed before it is created again, since the
+    /// HSM would otherwise hand back the certificate it already has.
+    fn renew_certificate(&self, alias: &str) -> Result<CertInfo> {
+        let properties = self
+            .certificates
+            .read()
+            .unwrap()
+            .get(alias)
+            .map(|recorded| recorded.properties.clone())
+            .ok_or_else(|| ErrorKind::CertificateNotFound(alias.to_string()))?;
+        if !self.renewing.lock().unwrap().insert(alias.to_string()) {
+            return Err(Error::from(ErrorKind::CertificateRenewalInProgress(
+                alias.to_string(),
+            )));
+        }
+
+        let cert = self
+            .destroy_certificate(alias.to_string())
+            .and_then(|()| self.create_certificate(&properties))
+            .and_then(|cert| cert.pem())
+            .and_then(|pem| CertInfo::from_pem(alias, pem.as_ref()));
+        self.renewing.lock().unwrap().remove(alias);
+
+        let info = cert
+            .with_context(|_| ErrorKind::RenewCertificate(alias.to_string()))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ErrorKind::RenewCertificate(alias.to_string()))?;
+        Ok(info)
+    }
+}
+
+impl<C> CreateCertificate for CertificateInventory<C>
+where
+    C: CreateCertificate,
+{
+    type Certificate = C::Certificate;
+
+    fn create_certificate(&self, properties: &CertificateProperties) -> Result<Self::Certificate> {
+        let cert = self.crypto.create_certificate(properties)?;
+
+        let alias = properties.alias();
+        match cert
+            .pem()
+            .and_then(|pem| CertInfo::from_pem(alias, pem.as_ref()))
+        {
+            Ok(certificates) => {
+                self.certificates.write().unwrap().insert(
+                    alias.to_string(),
+                    Recorded {
+                        properties: properties.clone(),
+                        certificates,
+                    },
+                );
+            }
+            Err(err) => warn!(
+                "Could not read certificate {} for the certificate inventory: {}",
+                alias, err
+            ),
+        }
+
+        Ok(cert)
+    }
+
+    fn destroy_certificate(&self, alias: String) -> Result<()> {
+        self.certificates.write().unwrap().remove(&alias);
+        self.crypto.destroy_certificate(alias)
+    }
+}
+
+impl<C> GetTrustBundle for CertificateInventory<C>
+where
+    C: GetTrustBundle,
+{
+    type Certificate = C::Certificate;
+
+    fn get_trust_bundle(&self) -> Result<Self::Certificate> {
+        self.crypto.get_trust_bundle()
+    }
+}
+
+impl<C> MasterEncryptionKey for CertificateInventory<C>
+where
+    C: MasterEncryptionKey,
+{
+    fn create_key(&self) -> Result<()> {
+        self.crypto.create_key()
+    }
+
+    fn destroy_key(&self) -> Result<()> {
+        self.crypto.destroy_key()
+    }
+}
+
+impl<C> Encrypt for CertificateInventory<C>
+where
+    C: Encrypt,
+{
+    type Buffer = C::Buffer;
+
+    fn encrypt(
+        &self,
+        client_id: &[u8],
+        plaintext: &[u8],
+        initialization_vector: &[u8],
+    ) -> Result<Self::Buffer> {
+        self.crypto
+            .encrypt(client_id, plaintext, initialization_vector)
+    }
+}
+
+impl<C> Decrypt for CertificateInventory<C>
+where
+    C: Decrypt,
+{
+    type Buffer = C::Buffer;
+
+    fn decrypt(
+        &self,
+        client_id: &[u8],
+        ciphertext: &[u8],
+        initialization_vector: &[u8],
+    ) -> Result<Self::Buffer> {
+        self.crypto
+            .decrypt(client_id, ciphertext, initialization_vector)
+    }
+}
+
+impl<C> CertificateManager for CertificateInventory<C>
+where
+    C: CreateCertificate + GetTrustBundle,
+{
+    type Error = Error;
+    type ListFuture = FutureResult<Vec<CertInfo>, Self::Error>;
+    type RenewFuture = FutureResult<CertInfo, Self::Error>;
+
+    fn list(&self) -> Self::ListFuture {
+        let mut certificates: Vec<_> = self
+            .certificates
+            .read()
+            .unwrap()
+            .values()
+            .flat_map(|recorded| recorded.certificates.iter().cloned())
+            .collect();
+
+        let trust_bundle = self
+            .crypto
+            .get_trust_bundle()
+            .and_then(|bundle| bundle.pem())
+            .and_then(|pem| CertInfo::from_pem(TRUST_BUNDLE_ALIAS, pem.as_ref()))
+            .context(ErrorKind::ListCertificates);
+        match trust_bundle {
+            Ok(trust_bundle) => {
+                certificates.extend(trust_bundle);
+                future::ok(certificates)
+            }
+            Err(err) => future::err(Error::from(err)),
+        }
+    }
+
+    fn renew(&self, alias: &str) -> Self::RenewFuture {
+        future::result(self.renew_certificate(alias))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::asn1::Asn1Time;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::X509NameBuilder;
+
+    use super::*;
+    use crate::certificate_properties::CertificateType;
+    use crate::crypto::PrivateKey;
+
+    fn pem(cn: &str, days: u32) -> Vec<u8> {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(days).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        cert.build().to_pem().unwrap()
+    }
+
+    struct TestCert(Vec<u8>);
+
+    impl Certificate for TestCert {
+        type Buffer = Vec<u8>;
+        type KeyBuffer = Vec<u8>;
+
+        fn pem(&self) -> Result<Self::Buffer> {
+            Ok(self.0.clone())
+        }
+
+        fn get_private_key(&self) -> Result<Option<PrivateKey<Self::KeyBuffer>>> {
+            Ok(None)
+        }
+
+        fn get_valid_to(&self) -> Result<DateTime<Utc>> {
+            unimplemented!()
+        }
+    }
+
+    struct TestCrypto;
+
+    impl CreateCertificate for TestCrypto {
+        type Certificate = TestCert;
+
+        fn create_certificate(
+            &self,
+            properties: &CertificateProperties,
+        ) -> Result<Self::Certificate> {
+            Ok(TestCert(pem(properties.common_name(), 90)))
+        }
+
+        fn destroy_certificate(&self, _alias: String) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl GetTrustBundle for TestCrypto {
+        type Certificate = TestCert;
+
+        fn get_trust_bundle(&self) -> Result<Self::Certificate> {
+            let mut bundle = pem("root ca", 365);
+            bundle.extend(pem("other root ca", 730));
+            Ok(TestCert(bundle))
+        }
+    }
+
+    fn properties(cn: &str, alias: &str) -> CertificateProperties {
+        CertificateProperties::new(
+            3600,
+            cn.to_string(),
+            CertificateType::Server,
+            alias.to_string(),
+        )
+    }
+
+    #[test]
+    fn from_pem_reads_certificate() {
+        let pem = pem("mod1", 30);
+
+        let info = CertInfo::from_pem("mod1server", &pem).unwrap();
+
+        assert_eq!(1, info.len());
+        assert_eq!("mod1server", info[0].alias());
+        assert_eq!("mod1", info[0].subject_cn());
+        assert_eq!(40, info[0].thumbprint().len());
+        let validity = info[0]
+            .not_after()
+            .signed_duration_since(*info[0].not_before());
+        assert_eq!(30, validity.num_days());
+    }
+
+    #[test]
+    fn inventory_lists_created_certificates_and_trust_bundle() {
+        let inventory = CertificateInventory::new(TestCrypto);
+        inventory
+            .create_certificate(&properties("mod1", "mod1server"))
+            .unwrap();
+        inventory
+            .create_certificate(&properties("mod2", "mod2server"))
+            .unwrap();
+        inventory
+            .destroy_certificate("mod2server".to_string())
+            .unwrap();
+
+        let certificates = inventory.list().wait().unwrap();
+
+        let aliases: Vec<_> = certificates.iter().map(CertInfo::alias).collect();
+        assert_eq!(
+            vec!["mod1server", TRUST_BUNDLE_ALIAS, TRUST_BUNDLE_ALIAS],
+            aliases
+        );
+        assert_eq!("other root ca", certificates[2].subject_cn());
+    }
+
+    #[test]
+    fn renew_replaces_certificate() {
+        let inventory = CertificateInventory::new(TestCrypto);
+        inventory
+            .create_certificate(&properties("mod1", "mod1server"))
+            .unwrap();
+        let before = inventory.list().wait().unwrap();
+
+        let renewed = inventory.renew("mod1server").wait().unwrap();
+
+        assert_eq!("mod1server", renewed.alias());
+        assert_eq!("mod1", renewed.subject_cn());
+        assert_ne!(before[0].thumbprint(), renewed.thumbprint());
+        let after = inventory.list().wait().unwrap();
+        assert_eq!(renewed, after[0]);
+        assert_eq!(before.len(), after.len());
+    }
+
+    #[test]
+    fn renew_unknown_alias_is_not_found() {
+        let inventory = CertificateInventory::new(TestCrypto);
+
+        let err = inventory.renew(TRUST_BUNDLE_ALIAS).wait().unwrap_err();
+
+        assert_eq!(
+            CertificateManagerErrorReason::NotFound,
+            CertificateManagerErrorReason::from(&err)
+        );
+    }
+
+    #[test]
+    fn renew_in_progress_is_refused() {
+        let inventory = CertificateInventory::new(TestCrypto);
+        inventory
+            .create_certificate(&properties("mod1", "mod1server"))
+            .unwrap();
+        inventory
+            .renewing
+            .lock()
+            .unwrap()
+            .insert("mod1server".to_string());
+
+        let err = inventory.renew("mod1server").wait().unwrap_err();
+
+        assert_eq!(
+            CertificateManagerErrorReason::RenewalInProgress,
+            CertificateManagerErrorReason::from(&err)
+        );
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use failure::ResultExt;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use crate::error::{ErrorKind, Result};
+use crate::module::ModuleSpec;
+
+/// Keeps the spec every module was last created from, so the spec can be
+/// looked up without asking the module runtime, which for Docker means
+/// inspecting the container and parsing its labels.
+pub trait ModuleConfigStore {
+    type Config;
+
+    fn get(&self, name: &str) -> Option<ModuleSpec<Self::Config>>;
+    fn set(&self, name: &str, spec: ModuleSpec<Self::Config>) -> Result<()>;
+    fn delete(&self, name: &str) -> Result<()>;
+    /// Moves the spec of module `old_name` to `new_name` in a single change,
+    /// so that no lookup sees both or neither.
+    fn rename(&self, old_name: &str, new_name: &str) -> Result<()>;
+    /// Forgets the specs of all modules.
+    fn clear(&self) -> Result<()>;
+}
+
+type Specs<T> = HashMap<String, ModuleSpec<T>>;
+
+/// A store that lives as long as the process. Clones share the same specs.
+pub struct InMemoryConfigStore<T> {
+    specs: Arc<RwLock<Specs<T>>>,
+}
+
+impl<T> InMemoryConfigStore<T> {
+    pub fn new() -> Self {
+        InMemoryConfigStore {
+            specs: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+impl<T> Default for InMemoryConfigStore<T> {
+    fn default() -> Self {
+        InMemoryConfigStore::new()
+    }
+}
+
+impl<T> Clone for InMemoryConfigStore<T> {
+    fn clone(&self) -> Self {
+        InMemoryConfigStore {
+            specs: self.specs.clone(),
+        }
+    }
+}
+
+impl<T> ModuleConfigStore for InMemoryConfigStore<T>
+where
+    T: Clone,
+{
+    type Config = T;
+
+    fn get(&self, name: &str) -> Option<ModuleSpec<T>> {
+        self.specs.read().unwrap().get(name).cloned()
+    }
+
+    fn set(&self, name: &str, spec: ModuleSpec<T>) -> Result<()> {
+        self.specs.write().unwrap().insert(name.to_string(), spec);
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        self.specs.write().unwrap().remove(name);
+        Ok(())
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> Result<()> {
+        rename(&mut self.specs.write().unwrap(), old_name, new_name);
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.specs.write().unwrap().clear();
+        Ok(())
+    }
+}
+
+/// A store that is also kept in a JSON file, so it survives restarts of the
+/// daemon. The file is read when the store is created and written after
+/// every change. Clones share the same specs.
+pub struct FileBackedConfigStore<T> {
+    path: PathBuf,
+    specs: Arc<RwLock<Specs<T>>>,
+}
+
+impl<T> FileBackedConfigStore<T>
+where
+    T: DeserializeOwned,
+{
+    /// A missing or unreadable file starts an empty store.
+    pub fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let specs = match read(&path) {
+            Ok(specs) => specs,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                warn!(
+                    "Could not read module configurations from {}, starting with none: {}",
+                    path.display(),
+                    err
+                );
+                HashMap::new()
+            }
+        };
+        FileBackedConfigStore {
+            path,
+            specs: Arc::new(RwLock::new(specs)),
+        }
+    }
+}
+
+impl<T> FileBackedConfigStore<T>
+where
+    T: Serialize,
+{
+    /// Changes the specs and writes them out while holding the lock, so that
+    /// concurrent changes are written in the order they are made.
+    fn update<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Specs<T>),
+    {
+        let mut specs = self.specs.write().unwrap();
+        f(&mut specs);
+        write(&self.path, &specs)
+            .with_context(|_| ErrorKind::ModuleConfigStore(self.path.display().to_string()))?;
+        Ok(())
+    }
+}
+
+impl<T> Clone for FileBackedConfigStore<T> {
+    fn clone(&self) -> Self {
+        FileBackedConfigStore {
+            path: self.path.clone(),
+            specs: self.specs.clone(),
+        }
+    }
+}
+
+impl<T> ModuleConfigStore for FileBackedConfigStore<T>
+where
+    T: Clone + Serialize,
+{
+    type Config = T;
+
+    fn get(&self, name: &str) -> Option<ModuleSpec<T>> {
+        self.specs.read().unwrap().get(name).cloned()
+    }
+
+    fn set(&self, name: &str, spec: ModuleSpec<T>) -> Result<()> {
+        self.update(|specs| {
+            specs.insert(name.to_string(), spec);
+        })
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        self.update(|specs| {
+            specs.remove(name);
+        })
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> Result<()> {
+        self.update(|specs| rename(specs, old_name, new_name))
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.update(HashMap::clear)
+    }
+}
+
+/// A spec that isn't in the store isn't moved, but any spec under
+/// `new_name` is still dropped, since it isn't that of the renamed module.
+fn rename<T>(specs: &mut Specs<T>, old_name: &str, new_name: &str) {
+    specs.remove(new_name);
+    if let Some(spec) = specs.remove(old_name) {
+        specs.insert(new_name.to_string(), spec.with_name(new_name.to_string()));
+    }
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> io::Result<Specs<T>> {
+    let b = fs::read(path)?;
+    let specs = serde_json::from_slice(&b)?;
+    Ok(specs)
+}
+
+/// Writes to a temporary file first and renames it over the store, which
+/// replaces the file atomically on Linux, so that a crash can't leave a
+/// partly written store behind.
+fn write<T: Serialize>(path: &Path, specs: &Specs<T>) -> io::Result<()> {
+    let b = serde_json::to_vec(specs)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, b)?;
+    fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(image: &str) -> ModuleSpec<String> {
+        ModuleSpec::new(
+            "mod1".to_string(),
+            "docker".to_string(),
+            image.to_string(),
+            HashMap::new(),
+        )
+        .unwrap()
+    }
+
+    fn exercise<S: ModuleConfigStore<Config = String>>(store: &S) {
+        assert!(store.get("mod1").is_none());
+
+        store.set("mod1", spec("img:1")).unwrap();
+        store.set("mod1", spec("img:2")).unwrap();
+        store.set("mod2", spec("img:3")).unwrap();
+        assert_eq!("img:2", store.get("mod1").unwrap().config().as_str());
+
+        store.delete("mod1").unwrap();
+        assert!(store.get("mod1").is_none());
+        assert!(store.get("mod2").is_some());
+
+        store.rename("mod2", "mod3").unwrap();
+        assert!(store.get("mod2").is_none());
+        let renamed = store.get("mod3").unwrap();
+        assert_eq!("mod3", renamed.name());
+        assert_eq!("img:3", renamed.config().as_str());
+        store.rename("mod3", "mod2").unwrap();
+
+        store.clear().unwrap();
+        assert!(store.get("mod2").is_none());
+    }
+
+    #[test]
+    fn in_memory_store() {
+        let store = InMemoryConfigStore::new();
+        exercise(&store);
+
+        let clone = store.clone();
+        clone.set("mod1", spec("img:1")).unwrap();
+        assert!(store.get("mod1").is_some());
+    }
+
+    #[test]
+    fn file_backed_store() {
+        let dir = tempfile::tempdir().unwrap();
+        exercise(&FileBackedConfigStore::load(
+            dir.path().join("configs.json"),
+        ));
+    }
+
+    #[test]
+    fn file_backed_store_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache").join("configs.json");
+
+        let store = FileBackedConfigStore::load(&path);
+        store.set("mod1", spec("img:1")).unwrap();
+        store.set("mod2", spec("img:2")).unwrap();
+        store.delete("mod2").unwrap();
+
+        let reloaded = FileBackedConfigStore::<String>::load(&path);
+        assert_eq!("img:1", reloaded.get("mod1").unwrap().config().as_str());
+        assert!(reloaded.get("mod2").is_none());
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn unreadable_file_starts_empty_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("configs.json");
+        fs::write(&path, "not json").unwrap();
+
+        let store = FileBackedConfigStore::<String>::load(&path);
+
+        assert!(store.get("mod1").is_none());
+    }
+}
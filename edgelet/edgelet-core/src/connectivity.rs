@@ -0,0 +1,163 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectivityState {
+    Connected,
+    Disconnected,
+    /// No request to IoT Hub has completed yet.
+    Unknown,
+}
+
+/// Whether the device could reach IoT Hub the last time it tried.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConnectivityStatus {
+    status: ConnectivityState,
+    /// When the device got into the current state. Not set while the state is
+    /// unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    since: Option<DateTime<Utc>>,
+    /// The error of the last attempt that failed to reach IoT Hub, which is
+    /// kept after the device reconnects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+impl ConnectivityStatus {
+    pub fn new(status: ConnectivityState) -> Self {
+        ConnectivityStatus {
+            status,
+            since: None,
+            last_error: None,
+        }
+    }
+
+    pub fn with_since(mut self, since: Option<DateTime<Utc>>) -> Self {
+        self.since = since;
+        self
+    }
+
+    pub fn with_last_error(mut self, last_error: Option<String>) -> Self {
+        self.last_error = last_error;
+        self
+    }
+
+    pub fn status(&self) -> ConnectivityState {
+        self.status
+    }
+
+    pub fn since(&self) -> Option<&DateTime<Utc>> {
+        self.since.as_ref()
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_ref().map(AsRef::as_ref)
+    }
+}
+
+impl Default for ConnectivityStatus {
+    fn default() -> Self {
+        ConnectivityStatus::new(ConnectivityState::Unknown)
+    }
+}
+
+/// Tracks the connectivity to IoT Hub from the outcome of the requests made
+/// to it. Clones share the same status, so the component that talks to IoT
+/// Hub can record it while others report it.
+#[derive(Clone, Debug, Default)]
+pub struct Connectivity {
+    status: Arc<Mutex<ConnectivityStatus>>,
+}
+
+impl Connectivity {
+    pub fn new() -> Self {
+        Connectivity::default()
+    }
+
+    /// Records that IoT Hub answered a request, whatever the answer was.
+    pub fn connected(&self) {
+        self.record(ConnectivityState::Connected, None, Utc::now());
+    }
+
+    /// Records that a request couldn't reach IoT Hub because of `error`.
+    pub fn disconnected(&self, error: String) {
+        self.record(ConnectivityState::Disconnected, Some(error), Utc::now());
+    }
+
+    pub fn status(&self) -> ConnectivityStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn record(&self, state: ConnectivityState, error: Option<String>, now: DateTime<Utc>) {
+        let mut status = self.status.lock().unwrap();
+        if status.status != state {
+            status.status = state;
+            status.since = Some(now);
+        }
+        if error.is_some() {
+            status.last_error = error;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn status_is_unknown_until_recorded() {
+        let connectivity = Connectivity::new();
+        assert_eq!(ConnectivityStatus::default(), connectivity.status());
+        assert_eq!(None, connectivity.status().since());
+    }
+
+    #[test]
+    fn since_only_changes_with_state() {
+        let connectivity = Connectivity::new();
+        let t1 = Utc.ymd(2019, 1, 30).and_hms(10, 0, 0);
+        let t2 = Utc.ymd(2019, 1, 30).and_hms(10, 0, 5);
+        let t3 = Utc.ymd(2019, 1, 30).and_hms(10, 0, 10);
+
+        connectivity.record(ConnectivityState::Connected, None, t1);
+        connectivity.record(ConnectivityState::Connected, None, t2);
+        assert_eq!(
+            ConnectivityStatus::new(ConnectivityState::Connected).with_since(Some(t1)),
+            connectivity.status()
+        );
+
+        connectivity.record(
+            ConnectivityState::Disconnected,
+            Some("connection refused".to_string()),
+            t3,
+        );
+        let status = connectivity.status();
+        assert_eq!(ConnectivityState::Disconnected, status.status());
+        assert_eq!(Some(&t3), status.since());
+        assert_eq!(Some("connection refused"), status.last_error());
+    }
+
+    #[test]
+    fn last_error_is_kept_after_reconnecting() {
+        let connectivity = Connectivity::new();
+        connectivity.disconnected("connection refused".to_string());
+        connectivity.connected();
+
+        let status = connectivity.status();
+        assert_eq!(ConnectivityState::Connected, status.status());
+        assert_eq!(Some("connection refused"), status.last_error());
+    }
+
+    #[test]
+    fn clones_share_status() {
+        let connectivity = Connectivity::new();
+        connectivity.clone().connected();
+        assert_eq!(ConnectivityState::Connected, connectivity.status().status());
+    }
+}
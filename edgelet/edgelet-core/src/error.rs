@@ -19,6 +19,15 @@ pub enum ErrorKind {
     #[fail(display = "Identity error")]
     Certificate,
 
+    #[fail(display = "Certificate {:?} not found", _0)]
+    CertificateNotFound(String),
+
+    #[fail(display = "Certificate {:?} is already being renewed", _0)]
+    CertificateRenewalInProgress(String),
+
+    #[fail(display = "Could not get or set the daemon settings")]
+    DaemonConfig,
+
     #[fail(
         display = "Edge runtime module has not been created in IoT Hub. Please make sure this device is an IoT Edge capable device."
     )]
@@ -33,6 +42,9 @@ pub enum ErrorKind {
     #[fail(display = "A error occurred in the key store.")]
     KeyStore,
 
+    #[fail(display = "Invalid daemon settings: {}", _0)]
+    InvalidDaemonConfig(String),
+
     #[fail(display = "Invalid log tail {:?}", _0)]
     InvalidLogTail(String),
 
@@ -42,20 +54,71 @@ pub enum ErrorKind {
     #[fail(display = "Invalid module type {:?}", _0)]
     InvalidModuleType(String),
 
+    #[fail(display = "Invalid resource limits: {}", _0)]
+    InvalidResourceLimits(String),
+
+    #[fail(display = "Invalid CA certificate: {}", _0)]
+    InvalidTrustedCertificate(String),
+
     #[fail(display = "Invalid URL {:?}", _0)]
     InvalidUrl(String),
 
+    #[fail(
+        display = "Invalid X.509 thumbprint {:?}: expected 40 (SHA-1) or 64 (SHA-256) hex characters",
+        _0
+    )]
+    InvalidX509Thumbprint(String),
+
     #[fail(display = "Item not found.")]
     KeyStoreItemNotFound,
 
+    #[fail(display = "Could not list certificates")]
+    ListCertificates,
+
+    #[fail(display = "Could not write module configurations to {}", _0)]
+    ModuleConfigStore(String),
+
+    #[fail(
+        display = "Modules {:?} can't be started because their dependencies form a cycle",
+        _0
+    )]
+    ModuleDependencyCycle(Vec<String>),
+
+    #[fail(
+        display = "Module name {:?} does not match the pattern {:?} of allowed module names",
+        _0, _1
+    )]
+    ModuleNameNotAllowed(String, String),
+
     #[fail(display = "A module runtime error occurred.")]
     ModuleRuntime,
 
+    #[fail(display = "Could not update the trust store of module {}", _0)]
+    ModuleTrustStore(String),
+
+    #[fail(display = "Could not save the proxy settings")]
+    ProxySettings,
+
+    #[fail(display = "Could not read certificate")]
+    ReadCertificate,
+
+    #[fail(display = "Could not renew certificate {:?}", _0)]
+    RenewCertificate(String),
+
+    #[fail(display = "The timer that delays module restarts encountered an error.")]
+    ModuleWatchdogTimer,
+
     #[fail(display = "Signing error occurred.")]
     Sign,
 
     #[fail(display = "Signing error occurred. Invalid key length: {}", _0)]
     SignInvalidKeyLength(usize),
+
+    #[fail(
+        display = "CA certificate {:?} is not in the trust store of module {:?}",
+        _0, _1
+    )]
+    TrustedCertificateNotFound(String, String),
 }
 
 impl Fail for Error {
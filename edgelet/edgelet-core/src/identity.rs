@@ -1,10 +1,30 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::cmp;
 use std::fmt;
+use std::time::{Duration, Instant};
 
 use failure::Fail;
+use futures::future::{self, Either, Loop};
 use futures::Future;
+use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::timer::Delay;
+
+use crate::error::{Error, ErrorKind};
+
+/// The delay before the first retry of a request that failed transiently.
+const INITIAL_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between retries of a request.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(32);
+
+/// How much the delay between retries varies, in per mille.
+const RETRY_JITTER_PERMILLE: u64 = 200;
+
+/// How many times a request is made before its error is returned.
+const MAX_ATTEMPTS: u32 = 5;
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub enum AuthType {
@@ -31,10 +51,38 @@ pub trait Identity {
     fn auth_type(&self) -> AuthType;
 }
 
+/// The SHA-1 or SHA-256 thumbprint of the certificate an identity
+/// authenticates with, as 40 or 64 hex characters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct X509Thumbprint(String);
+
+impl X509Thumbprint {
+    pub fn new(thumbprint: String) -> Result<Self, Error> {
+        let valid_len = thumbprint.len() == 40 || thumbprint.len() == 64;
+        if valid_len && thumbprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(X509Thumbprint(thumbprint))
+        } else {
+            Err(Error::from(ErrorKind::InvalidX509Thumbprint(thumbprint)))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The identity to create, update, get or delete.
+///
+/// Identities authenticate with SAS keys derived by the identity manager,
+/// unless they have an X.509 thumbprint. The thumbprint is only set when
+/// the identity is created or updated, so rotating the certificate of an
+/// identity takes an update with the new thumbprint.
+#[derive(Clone)]
 pub struct IdentitySpec {
     module_id: String,
     generation_id: Option<String>,
     managed_by: Option<String>,
+    x509_thumbprint: Option<X509Thumbprint>,
 }
 
 impl IdentitySpec {
@@ -43,6 +91,7 @@ impl IdentitySpec {
             module_id,
             generation_id: None,
             managed_by: None,
+            x509_thumbprint: None,
         }
     }
 
@@ -67,6 +116,15 @@ impl IdentitySpec {
         self.managed_by = Some(managed_by);
         self
     }
+
+    pub fn x509_thumbprint(&self) -> Option<&X509Thumbprint> {
+        self.x509_thumbprint.as_ref()
+    }
+
+    pub fn with_x509_thumbprint(mut self, x509_thumbprint: X509Thumbprint) -> Self {
+        self.x509_thumbprint = Some(x509_thumbprint);
+        self
+    }
 }
 
 pub trait IdentityManager {
@@ -77,12 +135,163 @@ pub trait IdentityManager {
     type ListFuture: Future<Item = Vec<Self::Identity>, Error = Self::Error> + Send;
     type GetFuture: Future<Item = Option<Self::Identity>, Error = Self::Error> + Send;
     type DeleteFuture: Future<Item = (), Error = Self::Error> + Send;
+    type GetTwinFuture: Future<Item = Option<Value>, Error = Self::Error> + Send;
+    type GetTwinDesiredFuture: Future<Item = Value, Error = Self::Error> + Send;
 
     fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture;
     fn update(&mut self, id: IdentitySpec) -> Self::UpdateFuture;
     fn list(&self) -> Self::ListFuture;
     fn get(&self, id: IdentitySpec) -> Self::GetFuture;
     fn delete(&mut self, id: IdentitySpec) -> Self::DeleteFuture;
+    /// The desired and reported properties of the module's twin, as
+    /// `{"desired": ..., "reported": ...}`, or `None` if there is no such
+    /// module.
+    fn get_twin(&self, id: IdentitySpec) -> Self::GetTwinFuture;
+    /// The desired properties of the device's own twin.
+    fn get_twin_desired(&self) -> Self::GetTwinDesiredFuture;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IdentityManagerErrorReason {
+    /// The identity service answered with this HTTP status code.
+    ServiceError(u16),
+    Other,
+}
+
+impl IdentityManagerErrorReason {
+    /// Whether the request may succeed if it is made again, because the
+    /// identity service was throttling it or unavailable.
+    pub fn is_transient(self) -> bool {
+        match self {
+            IdentityManagerErrorReason::ServiceError(429)
+            | IdentityManagerErrorReason::ServiceError(503) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Retries the requests to an identity manager that fail with `429 Too Many
+/// Requests` or `503 Service Unavailable`, up to 5 times in all. The delay
+/// between attempts doubles from 1 second up to 32 seconds, give or take 20%.
+/// Other errors are returned right away.
+///
+/// Each request is made on a clone of the inner identity manager, so clones
+/// must share their state.
+#[derive(Clone)]
+pub struct RetryingIdentityManager<I> {
+    inner: I,
+    initial_backoff: Duration,
+}
+
+impl<I> RetryingIdentityManager<I> {
+    pub fn new(inner: I) -> Self {
+        RetryingIdentityManager {
+            inner,
+            initial_backoff: INITIAL_RETRY_BACKOFF,
+        }
+    }
+}
+
+impl<I> RetryingIdentityManager<I>
+where
+    I: 'static + IdentityManager + Clone + Send,
+    for<'r> &'r I::Error: Into<IdentityManagerErrorReason>,
+{
+    fn retry<F, R>(
+        &self,
+        mut request: R,
+    ) -> Box<dyn Future<Item = F::Item, Error = I::Error> + Send>
+    where
+        R: 'static + FnMut(&mut I) -> F + Send,
+        F: 'static + Future<Error = I::Error> + Send,
+        F::Item: Send,
+    {
+        let inner = self.inner.clone();
+        let initial_backoff = self.initial_backoff;
+        Box::new(future::loop_fn((inner, 0), move |(mut inner, attempt)| {
+            request(&mut inner).then(move |result| match result {
+                Err(ref err) if attempt + 1 < MAX_ATTEMPTS && is_transient(err) => {
+                    let jitter = rand::thread_rng().gen_range(
+                        1000 - RETRY_JITTER_PERMILLE,
+                        1000 + RETRY_JITTER_PERMILLE + 1,
+                    );
+                    let delay = backoff(initial_backoff, attempt, jitter);
+                    // A timer error only means the retry is made early.
+                    Either::A(
+                        Delay::new(Instant::now() + delay)
+                            .then(move |_| Ok(Loop::Continue((inner, attempt + 1)))),
+                    )
+                }
+                result => Either::B(future::result(result.map(Loop::Break))),
+            })
+        }))
+    }
+}
+
+impl<I> IdentityManager for RetryingIdentityManager<I>
+where
+    I: 'static + IdentityManager + Clone + Send,
+    I::Identity: Send,
+    for<'r> &'r I::Error: Into<IdentityManagerErrorReason>,
+{
+    type Identity = I::Identity;
+    type Error = I::Error;
+    type CreateFuture = Box<dyn Future<Item = Self::Identity, Error = Self::Error> + Send>;
+    type UpdateFuture = Box<dyn Future<Item = Self::Identity, Error = Self::Error> + Send>;
+    type ListFuture = Box<dyn Future<Item = Vec<Self::Identity>, Error = Self::Error> + Send>;
+    type GetFuture = Box<dyn Future<Item = Option<Self::Identity>, Error = Self::Error> + Send>;
+    type DeleteFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type GetTwinFuture = Box<dyn Future<Item = Option<Value>, Error = Self::Error> + Send>;
+    type GetTwinDesiredFuture = Box<dyn Future<Item = Value, Error = Self::Error> + Send>;
+
+    fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture {
+        self.retry(move |inner| inner.create(id.clone()))
+    }
+
+    fn update(&mut self, id: IdentitySpec) -> Self::UpdateFuture {
+        self.retry(move |inner| inner.update(id.clone()))
+    }
+
+    fn list(&self) -> Self::ListFuture {
+        self.retry(|inner| inner.list())
+    }
+
+    fn get(&self, id: IdentitySpec) -> Self::GetFuture {
+        self.retry(move |inner| inner.get(id.clone()))
+    }
+
+    fn delete(&mut self, id: IdentitySpec) -> Self::DeleteFuture {
+        self.retry(move |inner| inner.delete(id.clone()))
+    }
+
+    fn get_twin(&self, id: IdentitySpec) -> Self::GetTwinFuture {
+        self.retry(move |inner| inner.get_twin(id.clone()))
+    }
+
+    fn get_twin_desired(&self) -> Self::GetTwinDesiredFuture {
+        self.retry(|inner| inner.get_twin_desired())
+    }
+}
+
+fn is_transient<E>(err: &E) -> bool
+where
+    for<'r> &'r E: Into<IdentityManagerErrorReason>,
+{
+    err.into().is_transient()
+}
+
+/// The delay before retry number `attempt`, counting from 0, which doubles
+/// from `initial` up to `MAX_RETRY_BACKOFF`. The delay is then scaled by
+/// `jitter` per mille.
+fn backoff(initial: Duration, attempt: u32, jitter: u64) -> Duration {
+    let delay = 2_u32
+        .checked_pow(attempt)
+        .and_then(|factor| initial.checked_mul(factor))
+        .map_or(MAX_RETRY_BACKOFF, |delay| {
+            cmp::min(delay, MAX_RETRY_BACKOFF)
+        });
+    let millis = delay.as_secs() * 1000 + u64::from(delay.subsec_millis());
+    Duration::from_millis(millis * jitter / 1000)
 }
 
 // Useful for error contexts
@@ -91,6 +300,8 @@ pub enum IdentityOperation {
     CreateIdentity(String),
     DeleteIdentity(String),
     GetIdentity(String),
+    GetTwin(String),
+    GetTwinDesired,
     ListIdentities,
     UpdateIdentity(String),
 }
@@ -105,6 +316,10 @@ impl fmt::Display for IdentityOperation {
                 write!(f, "Could not delete identity {}", name)
             }
             IdentityOperation::GetIdentity(name) => write!(f, "Could not get identity {}", name),
+            IdentityOperation::GetTwin(name) => write!(f, "Could not get twin of module {}", name),
+            IdentityOperation::GetTwinDesired => {
+                write!(f, "Could not get the desired properties of the device twin")
+            }
             IdentityOperation::ListIdentities => write!(f, "Could not list identities"),
             IdentityOperation::UpdateIdentity(name) => {
                 write!(f, "Could not update identity {}", name)
@@ -112,3 +327,214 @@ impl fmt::Display for IdentityOperation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use futures::future::FutureResult;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+
+    #[derive(Debug, Fail, PartialEq)]
+    #[fail(display = "Service error {}", _0)]
+    struct TestError(u16);
+
+    impl<'a> From<&'a TestError> for IdentityManagerErrorReason {
+        fn from(err: &'a TestError) -> Self {
+            IdentityManagerErrorReason::ServiceError(err.0)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct TestIdentity;
+
+    impl Identity for TestIdentity {
+        fn module_id(&self) -> &str {
+            "m1"
+        }
+
+        fn managed_by(&self) -> &str {
+            "iotedge"
+        }
+
+        fn generation_id(&self) -> &str {
+            "1"
+        }
+
+        fn auth_type(&self) -> AuthType {
+            AuthType::Sas
+        }
+    }
+
+    /// Fails with the given status codes, in order, and then succeeds.
+    #[derive(Clone)]
+    struct FlakyIdentityManager {
+        errors: Arc<Mutex<Vec<u16>>>,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    impl FlakyIdentityManager {
+        fn new(mut errors: Vec<u16>) -> Self {
+            errors.reverse();
+            FlakyIdentityManager {
+                errors: Arc::new(Mutex::new(errors)),
+                attempts: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn attempts(&self) -> usize {
+            self.attempts.load(Ordering::SeqCst)
+        }
+
+        fn respond<T>(&self, item: T) -> FutureResult<T, TestError> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            match self.errors.lock().unwrap().pop() {
+                Some(code) => future::err(TestError(code)),
+                None => future::ok(item),
+            }
+        }
+    }
+
+    impl IdentityManager for FlakyIdentityManager {
+        type Identity = TestIdentity;
+        type Error = TestError;
+        type CreateFuture = FutureResult<Self::Identity, Self::Error>;
+        type UpdateFuture = FutureResult<Self::Identity, Self::Error>;
+        type ListFuture = FutureResult<Vec<Self::Identity>, Self::Error>;
+        type GetFuture = FutureResult<Option<Self::Identity>, Self::Error>;
+        type DeleteFuture = FutureResult<(), Self::Error>;
+        type GetTwinFuture = FutureResult<Option<Value>, Self::Error>;
+        type GetTwinDesiredFuture = FutureResult<Value, Self::Error>;
+
+        fn create(&mut self, _id: IdentitySpec) -> Self::CreateFuture {
+            self.respond(TestIdentity)
+        }
+
+        fn update(&mut self, _id: IdentitySpec) -> Self::UpdateFuture {
+            self.respond(TestIdentity)
+        }
+
+        fn list(&self) -> Self::ListFuture {
+            self.respond(vec![TestIdentity])
+        }
+
+        fn get(&self, _id: IdentitySpec) -> Self::GetFuture {
+            self.respond(Some(TestIdentity))
+        }
+
+        fn delete(&mut self, _id: IdentitySpec) -> Self::DeleteFuture {
+            self.respond(())
+        }
+
+        fn get_twin(&self, _id: IdentitySpec) -> Self::GetTwinFuture {
+            self.respond(None)
+        }
+
+        fn get_twin_desired(&self) -> Self::GetTwinDesiredFuture {
+            self.respond(Value::Null)
+        }
+    }
+
+    fn retrying(inner: &FlakyIdentityManager) -> RetryingIdentityManager<FlakyIdentityManager> {
+        let mut retrying = RetryingIdentityManager::new(inner.clone());
+        retrying.initial_backoff = Duration::from_millis(1);
+        retrying
+    }
+
+    #[test]
+    fn throttled_requests_are_retried() {
+        let inner = FlakyIdentityManager::new(vec![429, 429, 429]);
+        let mut manager = retrying(&inner);
+
+        let identity = Runtime::new()
+            .unwrap()
+            .block_on(manager.create(IdentitySpec::new("m1".to_string())))
+            .unwrap();
+
+        assert_eq!(TestIdentity, identity);
+        assert_eq!(4, inner.attempts());
+    }
+
+    #[test]
+    fn unavailable_service_is_retried_at_most_five_times() {
+        let inner = FlakyIdentityManager::new(vec![503; 6]);
+        let manager = retrying(&inner);
+
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(manager.list())
+            .unwrap_err();
+
+        assert_eq!(TestError(503), err);
+        assert_eq!(5, inner.attempts());
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        for &code in &[400, 401, 404, 500] {
+            let inner = FlakyIdentityManager::new(vec![code]);
+            let manager = retrying(&inner);
+
+            let err = Runtime::new()
+                .unwrap()
+                .block_on(manager.get(IdentitySpec::new("m1".to_string())))
+                .unwrap_err();
+
+            assert_eq!(TestError(code), err);
+            assert_eq!(1, inner.attempts());
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(
+            Duration::from_secs(1),
+            backoff(INITIAL_RETRY_BACKOFF, 0, 1000)
+        );
+        assert_eq!(
+            Duration::from_secs(8),
+            backoff(INITIAL_RETRY_BACKOFF, 3, 1000)
+        );
+        assert_eq!(MAX_RETRY_BACKOFF, backoff(INITIAL_RETRY_BACKOFF, 5, 1000));
+        assert_eq!(MAX_RETRY_BACKOFF, backoff(INITIAL_RETRY_BACKOFF, 64, 1000));
+        assert_eq!(
+            Duration::from_millis(3200),
+            backoff(INITIAL_RETRY_BACKOFF, 2, 800)
+        );
+    }
+
+    #[test]
+    fn thumbprint_accepts_sha1_and_sha256() {
+        let sha1 = "0123456789abcdefABCDEF0123456789abcdef01";
+        let sha256 = "0123456789abcdef0123456789abcdef0123456789ABCDEF0123456789abcdef";
+
+        assert_eq!(
+            sha1,
+            X509Thumbprint::new(sha1.to_string()).unwrap().as_str()
+        );
+        assert_eq!(
+            sha256,
+            X509Thumbprint::new(sha256.to_string()).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn thumbprint_rejects_other_lengths_and_non_hex() {
+        for thumbprint in &[
+            "",
+            "0123456789abcdef0123456789abcdef012345",
+            "0123456789abcdef0123456789abcdef01234567890",
+            "0123456789abcdef0123456789abcdef0123456g",
+            "01:23:45:67:89:ab:cd:ef:01:23:45:67:89:ab",
+        ] {
+            let err = X509Thumbprint::new(thumbprint.to_string()).unwrap_err();
+            match err.kind() {
+                ErrorKind::InvalidX509Thumbprint(t) => assert_eq!(thumbprint, t),
+                _ => panic!("unexpected error {}", err),
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// When unused module images are removed automatically. Once the disk
+/// holding the images has less than `min_free_bytes` free, unused images
+/// older than `max_image_age_secs` are removed.
+///
+/// The default `min_free_bytes` of 0 never triggers, so images are only
+/// removed automatically when a policy is configured.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct GcPolicy {
+    #[serde(default)]
+    min_free_bytes: u64,
+    #[serde(default)]
+    max_image_age_secs: u64,
+}
+
+impl GcPolicy {
+    pub fn new(min_free_bytes: u64, max_image_age_secs: u64) -> Self {
+        GcPolicy {
+            min_free_bytes,
+            max_image_age_secs,
+        }
+    }
+
+    pub fn min_free_bytes(&self) -> u64 {
+        self.min_free_bytes
+    }
+
+    pub fn max_image_age_secs(&self) -> u64 {
+        self.max_image_age_secs
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == GcPolicy::default()
+    }
+}
+
+/// The image garbage collection policy in effect. Clones share the same
+/// policy, so it can be changed through the management API while the
+/// garbage collector runs.
+#[derive(Clone, Debug, Default)]
+pub struct SharedGcPolicy {
+    policy: Arc<Mutex<GcPolicy>>,
+}
+
+impl SharedGcPolicy {
+    pub fn new(policy: GcPolicy) -> Self {
+        SharedGcPolicy {
+            policy: Arc::new(Mutex::new(policy)),
+        }
+    }
+
+    pub fn get(&self) -> GcPolicy {
+        self.policy.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, policy: GcPolicy) {
+        *self.policy.lock().unwrap() = policy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_policy() {
+        let policy = SharedGcPolicy::default();
+        let clone = policy.clone();
+
+        clone.set(GcPolicy::new(1024, 3600));
+
+        assert_eq!(GcPolicy::new(1024, 3600), policy.get());
+    }
+}
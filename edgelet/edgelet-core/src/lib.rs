@@ -2,7 +2,11 @@
 
 #![deny(rust_2018_idioms, warnings)]
 #![deny(clippy::all, clippy::pedantic)]
-#![allow(clippy::module_name_repetitions, clippy::use_self)]
+#![allow(
+    clippy::doc_markdown, // clippy want the "IoT" of "IoT Hub" in a code fence
+    clippy::module_name_repetitions,
+    clippy::use_self,
+)]
 
 use std::path::{Path, PathBuf};
 
@@ -10,29 +14,70 @@ use failure::ResultExt;
 use lazy_static::lazy_static;
 use url::Url;
 
+mod allowed_registries;
 mod authorization;
+mod capabilities;
+mod certificate_manager;
 mod certificate_properties;
+mod config_store;
+mod connectivity;
 pub mod crypto;
+mod daemon_config;
 mod error;
 mod identity;
+mod image_gc;
 mod module;
+mod module_names;
 pub mod pid;
+mod proxy;
+mod snapshot;
+mod start_all;
+mod state_reset;
+pub mod tenant;
+mod trust_store;
 pub mod watchdog;
 pub mod workload;
 
+pub use allowed_registries::AllowedRegistries;
 pub use authorization::{Authorization, Policy};
+pub use capabilities::Capabilities;
+pub use certificate_manager::{
+    CertInfo, CertificateInventory, CertificateManager, CertificateManagerErrorReason,
+    TRUST_BUNDLE_ALIAS,
+};
 pub use certificate_properties::{CertificateIssuer, CertificateProperties, CertificateType};
+pub use config_store::{FileBackedConfigStore, InMemoryConfigStore, ModuleConfigStore};
+pub use connectivity::{Connectivity, ConnectivityState, ConnectivityStatus};
 pub use crypto::{
     Certificate, CreateCertificate, Decrypt, Encrypt, GetTrustBundle, KeyBytes, KeyIdentity,
     KeyStore, MasterEncryptionKey, PrivateKey, Signature, IOTEDGED_CA_ALIAS,
 };
+pub use daemon_config::{
+    redact_daemon_config, DaemonConfigSource, DaemonConfigStore, InMemoryDaemonConfigStore,
+    RELOADABLE_SETTINGS,
+};
 pub use error::{Error, ErrorKind};
-pub use identity::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
+pub use identity::{
+    AuthType, Identity, IdentityManager, IdentityManagerErrorReason, IdentityOperation,
+    IdentitySpec, RetryingIdentityManager, X509Thumbprint,
+};
+pub use image_gc::{GcPolicy, SharedGcPolicy};
 pub use module::{
-    LogOptions, LogTail, Module, ModuleOperation, ModuleRegistry, ModuleRuntime,
-    ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec, ModuleStatus, ModuleTop,
-    RegistryOperation, RuntimeOperation, SystemInfo,
+    validate_module_name, CgroupVersion, CircuitState, LogOptions, LogTail, Module, ModuleDetails,
+    ModuleEvent, ModuleEventType, ModuleOperation, ModuleRegistry, ModuleRuntime,
+    ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec, ModuleStats, ModuleStatus, ModuleTop,
+    PortBinding, PruneImagesResult, PullProgress, RegistryOperation, ResourceLimits, RestartPolicy,
+    RuntimeOperation, SystemInfo, SystemResources, VolumeMount,
+};
+pub use module_names::ModuleNameRules;
+pub use proxy::{InMemoryProxySettingsStore, ProxySettings, ProxySettingsStore};
+pub use snapshot::{
+    DiskUsage, ModuleSnapshot, NetworkInterface, SystemSnapshot, SNAPSHOT_EVENT_COUNT,
 };
+pub use start_all::{start_in_dependency_order, StartResult};
+pub use state_reset::StateReset;
+pub use tenant::TenantConfig;
+pub use trust_store::{ModuleTrustStore, TrustedCa, TRUST_BUNDLE_FILE_NAME};
 pub use workload::WorkloadConfig;
 
 lazy_static! {
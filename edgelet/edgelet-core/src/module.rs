@@ -3,21 +3,29 @@
 use std::collections::HashMap;
 use std::default::Default;
 use std::fmt;
+use std::net::IpAddr;
 use std::result::Result as StdResult;
 use std::str::FromStr;
 use std::string::ToString;
 use std::time::Duration;
 
+use bytes::Bytes;
 use chrono::prelude::*;
 use failure::{Fail, ResultExt};
 use futures::{Future, Stream};
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
 
 use edgelet_utils::{ensure_not_empty_with_context, serialize_ordered};
 
+use crate::capabilities::Capabilities;
+use crate::connectivity::ConnectivityStatus;
 use crate::error::{Error, ErrorKind, Result};
 use crate::pid::Pid;
+use crate::snapshot::SystemSnapshot;
+use crate::start_all::StartResult;
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "lowercase")]
@@ -26,6 +34,9 @@ pub enum ModuleStatus {
     Running,
     Stopped,
     Failed,
+    /// The processes of the module are frozen by `POST /modules/{name}/pause`
+    /// until it is unpaused.
+    Paused,
 }
 
 impl FromStr for ModuleStatus {
@@ -57,6 +68,8 @@ pub struct ModuleRuntimeState {
     finished_at: Option<DateTime<Utc>>,
     image_id: Option<String>,
     pid: Pid,
+    #[serde(default)]
+    restart_count: u32,
 }
 
 impl Default for ModuleRuntimeState {
@@ -69,6 +82,7 @@ impl Default for ModuleRuntimeState {
             finished_at: None,
             image_id: None,
             pid: Pid::None,
+            restart_count: 0,
         }
     }
 }
@@ -136,6 +150,16 @@ impl ModuleRuntimeState {
         self.pid = pid;
         self
     }
+
+    /// How often the runtime has restarted the module since it was created.
+    pub fn restart_count(&self) -> u32 {
+        self.restart_count
+    }
+
+    pub fn with_restart_count(mut self, restart_count: u32) -> Self {
+        self.restart_count = restart_count;
+        self
+    }
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -147,6 +171,25 @@ pub struct ModuleSpec<T> {
     #[serde(default = "HashMap::new")]
     #[serde(serialize_with = "serialize_ordered")]
     env: HashMap<String, String>,
+    // Skipped when empty so that the serialized form of existing specs, which
+    // iotedged hashes to detect settings changes, stays the same.
+    #[serde(default = "HashMap::new")]
+    #[serde(serialize_with = "serialize_ordered")]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    labels: HashMap<String, String>,
+    // Skipped when unset for the same reason as the labels.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "ResourceLimits::is_empty")]
+    resource_limits: ResourceLimits,
+    // Skipped when `Never` for the same reason as the labels.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "RestartPolicy::is_never")]
+    restart_policy: RestartPolicy,
+    /// The names of the modules that have to be started before this one.
+    /// Skipped when empty for the same reason as the labels.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
 }
 
 impl<T> Clone for ModuleSpec<T>
@@ -159,6 +202,10 @@ where
             type_: self.type_.clone(),
             config: self.config.clone(),
             env: self.env.clone(),
+            labels: self.labels.clone(),
+            resource_limits: self.resource_limits.clone(),
+            restart_policy: self.restart_policy,
+            depends_on: self.depends_on.clone(),
         }
     }
 }
@@ -178,6 +225,10 @@ impl<T> ModuleSpec<T> {
             type_,
             config,
             env,
+            labels: HashMap::new(),
+            resource_limits: ResourceLimits::default(),
+            restart_policy: RestartPolicy::default(),
+            depends_on: Vec::new(),
         })
     }
 
@@ -224,6 +275,157 @@ impl<T> ModuleSpec<T> {
         self.env = env;
         self
     }
+
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn resource_limits(&self) -> &ResourceLimits {
+        &self.resource_limits
+    }
+
+    pub fn with_resource_limits(mut self, resource_limits: ResourceLimits) -> Self {
+        self.resource_limits = resource_limits;
+        self
+    }
+
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    pub fn depends_on(&self) -> &[String] {
+        &self.depends_on
+    }
+
+    pub fn with_depends_on(mut self, depends_on: Vec<String>) -> Self {
+        self.depends_on = depends_on;
+        self
+    }
+}
+
+lazy_static! {
+    /// Docker's rule for container names, which module names become.
+    static ref DOCKER_NAME_REGEX: Regex = Regex::new("^[a-zA-Z0-9][a-zA-Z0-9_.-]+$")
+        .expect("This hard-coded regex is expected to be valid.");
+}
+
+/// Checks that `name` can be the name of a module's container, and that it
+/// matches `pattern` too when there is one, so that a bad name fails before
+/// the module runtime is called instead of with its error.
+pub fn validate_module_name(name: &str, pattern: Option<&Regex>) -> Result<()> {
+    if !DOCKER_NAME_REGEX.is_match(name) {
+        return Err(Error::from(ErrorKind::InvalidModuleName(name.to_string())));
+    }
+    match pattern {
+        Some(pattern) if !pattern.is_match(name) => Err(Error::from(
+            ErrorKind::ModuleNameNotAllowed(name.to_string(), pattern.as_str().to_string()),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the module watchdog restarts a module after it exits. This is
+/// separate from any restart policy the module runtime applies itself.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    Never,
+    /// Restart the module when it exits with a non-zero exit code.
+    OnFailure,
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Never
+    }
+}
+
+impl RestartPolicy {
+    /// Whether a module that exited with `exit_code` should be restarted. A
+    /// module without an exit code is assumed to have failed.
+    pub fn should_restart(self, exit_code: Option<i64>) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => exit_code != Some(0),
+            RestartPolicy::Always => true,
+        }
+    }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_never(&self) -> bool {
+        *self == RestartPolicy::Never
+    }
+}
+
+/// Caps on the resources a module may use. Limits that are not set are left
+/// to the module runtime's defaults.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ResourceLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_limit_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_swap_bytes: Option<i64>,
+}
+
+impl ResourceLimits {
+    /// `memory_swap_bytes` is the limit of memory and swap together, so it
+    /// has to be at least `memory_limit_bytes`, or -1 for unlimited swap.
+    pub fn new(
+        cpu_shares: Option<u32>,
+        memory_limit_bytes: Option<u64>,
+        memory_swap_bytes: Option<i64>,
+    ) -> Result<Self> {
+        if let Some(swap) = memory_swap_bytes {
+            if swap != -1 {
+                let memory = memory_limit_bytes.unwrap_or(0);
+                #[allow(clippy::cast_sign_loss)]
+                let valid = swap >= 0 && swap as u64 >= memory;
+                if !valid {
+                    return Err(Error::from(ErrorKind::InvalidResourceLimits(format!(
+                        "memory_swap_bytes {} must be at least memory_limit_bytes {} or -1",
+                        swap, memory
+                    ))));
+                }
+            }
+        }
+
+        Ok(ResourceLimits {
+            cpu_shares,
+            memory_limit_bytes,
+            memory_swap_bytes,
+        })
+    }
+
+    pub fn cpu_shares(&self) -> Option<u32> {
+        self.cpu_shares
+    }
+
+    pub fn memory_limit_bytes(&self) -> Option<u64> {
+        self.memory_limit_bytes
+    }
+
+    pub fn memory_swap_bytes(&self) -> Option<i64> {
+        self.memory_swap_bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cpu_shares.is_none()
+            && self.memory_limit_bytes.is_none()
+            && self.memory_swap_bytes.is_none()
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -267,6 +469,9 @@ impl ToString for LogTail {
 pub struct LogOptions {
     follow: bool,
     tail: LogTail,
+    timestamps: bool,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
 }
 
 impl LogOptions {
@@ -274,6 +479,9 @@ impl LogOptions {
         LogOptions {
             follow: false,
             tail: LogTail::All,
+            timestamps: false,
+            since: None,
+            until: None,
         }
     }
 
@@ -287,6 +495,26 @@ impl LogOptions {
         self
     }
 
+    /// Asks the runtime to prefix every log line with the time it was written.
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Only returns logs written at or after `since`. Runtimes may round the
+    /// time, for example Docker only keeps whole seconds.
+    pub fn with_since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only returns logs written before `until`. A followed stream ends once
+    /// `until` has passed. Like `since`, precision depends on the runtime.
+    pub fn with_until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
     pub fn follow(&self) -> bool {
         self.follow
     }
@@ -294,6 +522,18 @@ impl LogOptions {
     pub fn tail(&self) -> &LogTail {
         &self.tail
     }
+
+    pub fn timestamps(&self) -> bool {
+        self.timestamps
+    }
+
+    pub fn since(&self) -> Option<DateTime<Utc>> {
+        self.since
+    }
+
+    pub fn until(&self) -> Option<DateTime<Utc>> {
+        self.until
+    }
 }
 
 pub trait Module {
@@ -304,6 +544,7 @@ pub trait Module {
     fn name(&self) -> &str;
     fn type_(&self) -> &str;
     fn config(&self) -> &Self::Config;
+    fn labels(&self) -> HashMap<String, String>;
     fn runtime_state(&self) -> Self::RuntimeStateFuture;
 }
 
@@ -317,7 +558,7 @@ pub trait ModuleRegistry {
     fn remove(&self, name: &str) -> Self::RemoveFuture;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct SystemInfo {
     /// OS Type of the Host. Example of value expected: \"linux\" and \"windows\".
     os_type: String,
@@ -325,6 +566,9 @@ pub struct SystemInfo {
     architecture: String,
     /// iotedge version string
     version: &'static str,
+    /// The cgroup hierarchy of the host, if it has cgroups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cgroup_version: Option<CgroupVersion>,
 }
 
 impl SystemInfo {
@@ -333,9 +577,15 @@ impl SystemInfo {
             os_type,
             architecture,
             version: super::version(),
+            cgroup_version: None,
         }
     }
 
+    pub fn with_cgroup_version(mut self, cgroup_version: CgroupVersion) -> Self {
+        self.cgroup_version = Some(cgroup_version);
+        self
+    }
+
     pub fn os_type(&self) -> &str {
         &self.os_type
     }
@@ -347,6 +597,145 @@ impl SystemInfo {
     pub fn version(&self) -> &str {
         self.version
     }
+
+    pub fn cgroup_version(&self) -> Option<CgroupVersion> {
+        self.cgroup_version
+    }
+}
+
+/// Whether a host has the legacy cgroup hierarchies, one per controller, or
+/// the unified hierarchy of cgroups v2.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CgroupVersion {
+    V1,
+    V2,
+}
+
+impl fmt::Display for CgroupVersion {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CgroupVersion::V1 => write!(formatter, "v1"),
+            CgroupVersion::V2 => write!(formatter, "v2"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SystemResources {
+    /// Number of logical CPUs available on the host.
+    total_cpu_count: u64,
+    /// Memory available for starting new processes, in bytes.
+    available_memory_bytes: u64,
+    /// Total size of the disk holding the module runtime's data, in bytes.
+    total_disk_bytes: u64,
+    /// Free space on the disk holding the module runtime's data, in bytes.
+    free_disk_bytes: u64,
+}
+
+impl SystemResources {
+    pub fn new(
+        total_cpu_count: u64,
+        available_memory_bytes: u64,
+        total_disk_bytes: u64,
+        free_disk_bytes: u64,
+    ) -> Self {
+        SystemResources {
+            total_cpu_count,
+            available_memory_bytes,
+            total_disk_bytes,
+            free_disk_bytes,
+        }
+    }
+
+    pub fn total_cpu_count(&self) -> u64 {
+        self.total_cpu_count
+    }
+
+    pub fn available_memory_bytes(&self) -> u64 {
+        self.available_memory_bytes
+    }
+
+    pub fn total_disk_bytes(&self) -> u64 {
+        self.total_disk_bytes
+    }
+
+    pub fn free_disk_bytes(&self) -> u64 {
+        self.free_disk_bytes
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ModuleDetails {
+    /// Name of the module. Example: tempSensor
+    name: String,
+    /// Current status of the module.
+    status: ModuleStatus,
+    /// Exit code of the module's last run, if it has exited.
+    exit_code: Option<i32>,
+    /// Time at which the module was last started.
+    started_at: Option<DateTime<Utc>>,
+    /// Time at which the module last exited.
+    finished_at: Option<DateTime<Utc>>,
+    /// Address of the module on the network it is attached to.
+    ip_address: Option<IpAddr>,
+}
+
+impl ModuleDetails {
+    pub fn new(name: String, status: ModuleStatus) -> Self {
+        ModuleDetails {
+            name,
+            status,
+            exit_code: None,
+            started_at: None,
+            finished_at: None,
+            ip_address: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn status(&self) -> &ModuleStatus {
+        &self.status
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub fn with_exit_code(mut self, exit_code: Option<i32>) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    pub fn started_at(&self) -> Option<&DateTime<Utc>> {
+        self.started_at.as_ref()
+    }
+
+    pub fn with_started_at(mut self, started_at: Option<DateTime<Utc>>) -> Self {
+        self.started_at = started_at;
+        self
+    }
+
+    pub fn finished_at(&self) -> Option<&DateTime<Utc>> {
+        self.finished_at.as_ref()
+    }
+
+    pub fn with_finished_at(mut self, finished_at: Option<DateTime<Utc>>) -> Self {
+        self.finished_at = finished_at;
+        self
+    }
+
+    pub fn ip_address(&self) -> Option<IpAddr> {
+        self.ip_address
+    }
+
+    pub fn with_ip_address(mut self, ip_address: Option<IpAddr>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -355,11 +744,26 @@ pub struct ModuleTop {
     name: String,
     /// A vector of process IDs (PIDs) representing a snapshot of all processes running inside the module.
     process_ids: Vec<Pid>,
+    /// Column titles of the process table. Example: ["PID", "USER", "CMD"]
+    titles: Vec<String>,
+    /// One row per process, with a value for each of the column titles.
+    processes: Vec<Vec<String>>,
 }
 
 impl ModuleTop {
     pub fn new(name: String, process_ids: Vec<Pid>) -> Self {
-        ModuleTop { name, process_ids }
+        ModuleTop {
+            name,
+            process_ids,
+            titles: Vec::new(),
+            processes: Vec::new(),
+        }
+    }
+
+    pub fn with_processes(mut self, titles: Vec<String>, processes: Vec<Vec<String>>) -> Self {
+        self.titles = titles;
+        self.processes = processes;
+        self
     }
 
     pub fn name(&self) -> &str {
@@ -369,6 +773,326 @@ impl ModuleTop {
     pub fn process_ids(&self) -> &[Pid] {
         &self.process_ids
     }
+
+    pub fn titles(&self) -> &[String] {
+        &self.titles
+    }
+
+    pub fn processes(&self) -> &[Vec<String>] {
+        &self.processes
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleStats {
+    /// Percentage of the host's CPU capacity used by the module.
+    cpu_percent: f64,
+    /// Memory currently used by the module, in bytes.
+    memory_usage_bytes: u64,
+    /// Memory limit of the module, in bytes.
+    memory_limit_bytes: u64,
+    /// Total bytes received by the module over all its network interfaces.
+    network_rx_bytes: u64,
+    /// Total bytes sent by the module over all its network interfaces.
+    network_tx_bytes: u64,
+}
+
+impl ModuleStats {
+    pub fn cpu_percent(&self) -> f64 {
+        self.cpu_percent
+    }
+
+    pub fn with_cpu_percent(mut self, cpu_percent: f64) -> Self {
+        self.cpu_percent = cpu_percent;
+        self
+    }
+
+    pub fn memory_usage_bytes(&self) -> u64 {
+        self.memory_usage_bytes
+    }
+
+    pub fn with_memory_usage_bytes(mut self, memory_usage_bytes: u64) -> Self {
+        self.memory_usage_bytes = memory_usage_bytes;
+        self
+    }
+
+    pub fn memory_limit_bytes(&self) -> u64 {
+        self.memory_limit_bytes
+    }
+
+    pub fn with_memory_limit_bytes(mut self, memory_limit_bytes: u64) -> Self {
+        self.memory_limit_bytes = memory_limit_bytes;
+        self
+    }
+
+    pub fn network_rx_bytes(&self) -> u64 {
+        self.network_rx_bytes
+    }
+
+    pub fn with_network_rx_bytes(mut self, network_rx_bytes: u64) -> Self {
+        self.network_rx_bytes = network_rx_bytes;
+        self
+    }
+
+    pub fn network_tx_bytes(&self) -> u64 {
+        self.network_tx_bytes
+    }
+
+    pub fn with_network_tx_bytes(mut self, network_tx_bytes: u64) -> Self {
+        self.network_tx_bytes = network_tx_bytes;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleEventType {
+    Started,
+    Stopped,
+    /// The module exited, whether it was stopped or crashed.
+    Died,
+    /// The module ran out of memory. Usually followed by `Died`.
+    OomKilled,
+    /// The module was created by promoting another one, for example a
+    /// canary, with `POST /modules/{name}/promote`. This is reported by the
+    /// management API rather than the runtime.
+    Promoted,
+    /// The module was updated back to the spec it had before its last
+    /// update with `POST /modules/{name}/rollback`. Like `Promoted`, this is
+    /// reported by the management API.
+    RolledBack,
+    /// The module was recreated by the management API for a change the
+    /// runtime doesn't report on its own, like clearing all of its labels
+    /// with `DELETE /modules/{name}/labels`.
+    Updated,
+    /// The processes of the module were frozen.
+    Paused,
+    /// The processes of a paused module were thawed.
+    Unpaused,
+    /// The module was renamed with `POST /modules/{name}/rename`. The event
+    /// has the new name, and the old one in its `old_name`. Like `Promoted`,
+    /// this is reported by the management API.
+    Renamed,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ModuleEvent {
+    event_type: ModuleEventType,
+    /// Name of the module. Example: tempSensor
+    module_name: String,
+    /// Time at which the runtime reported the event.
+    timestamp: DateTime<Utc>,
+    /// The name the module had before a `Renamed` event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_name: Option<String>,
+}
+
+impl ModuleEvent {
+    pub fn new(event_type: ModuleEventType, module_name: String, timestamp: DateTime<Utc>) -> Self {
+        ModuleEvent {
+            event_type,
+            module_name,
+            timestamp,
+            old_name: None,
+        }
+    }
+
+    pub fn with_old_name(mut self, old_name: String) -> Self {
+        self.old_name = Some(old_name);
+        self
+    }
+
+    pub fn event_type(&self) -> ModuleEventType {
+        self.event_type
+    }
+
+    pub fn module_name(&self) -> &str {
+        &self.module_name
+    }
+
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    pub fn old_name(&self) -> Option<&str> {
+        self.old_name.as_ref().map(AsRef::as_ref)
+    }
+}
+
+/// A progress update of an image pull.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PullProgress {
+    /// The layer the update is about, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    status: String,
+    /// Bytes of the layer that were transferred so far.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<u64>,
+    /// Size of the layer in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<u64>,
+}
+
+impl PullProgress {
+    pub fn new(status: String) -> Self {
+        PullProgress {
+            id: None,
+            status,
+            current: None,
+            total: None,
+        }
+    }
+
+    pub fn with_id(mut self, id: Option<String>) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_current(mut self, current: Option<u64>) -> Self {
+        self.current = current;
+        self
+    }
+
+    pub fn with_total(mut self, total: Option<u64>) -> Self {
+        self.total = total;
+        self
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    pub fn current(&self) -> Option<u64> {
+        self.current
+    }
+
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+}
+
+/// What pruning unused images freed up.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PruneImagesResult {
+    /// Disk space freed by removing the images, in bytes.
+    reclaimed_bytes: u64,
+    /// IDs of the images that were removed.
+    removed_images: Vec<String>,
+}
+
+impl PruneImagesResult {
+    pub fn new(reclaimed_bytes: u64, removed_images: Vec<String>) -> Self {
+        PruneImagesResult {
+            reclaimed_bytes,
+            removed_images,
+        }
+    }
+
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.reclaimed_bytes
+    }
+
+    pub fn removed_images(&self) -> &[String] {
+        &self.removed_images
+    }
+}
+
+/// A port of a module's container, and where on the host it is published if
+/// it is.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PortBinding {
+    container_port: u16,
+    /// `tcp`, `udp` or `sctp`.
+    protocol: String,
+    host_ip: Option<String>,
+    host_port: Option<u16>,
+}
+
+impl PortBinding {
+    pub fn new(container_port: u16, protocol: String) -> Self {
+        PortBinding {
+            container_port,
+            protocol,
+            host_ip: None,
+            host_port: None,
+        }
+    }
+
+    pub fn with_host_ip(mut self, host_ip: Option<String>) -> Self {
+        self.host_ip = host_ip;
+        self
+    }
+
+    pub fn with_host_port(mut self, host_port: Option<u16>) -> Self {
+        self.host_port = host_port;
+        self
+    }
+
+    pub fn container_port(&self) -> u16 {
+        self.container_port
+    }
+
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    pub fn host_ip(&self) -> Option<&str> {
+        self.host_ip.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn host_port(&self) -> Option<u16> {
+        self.host_port
+    }
+}
+
+/// A volume or bind mount of a module's container.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VolumeMount {
+    container_path: String,
+    /// For a named volume, the directory that holds its data on the host.
+    host_path: String,
+    /// `rw` or `ro`.
+    mode: String,
+}
+
+impl VolumeMount {
+    pub fn new(container_path: String, host_path: String, mode: String) -> Self {
+        VolumeMount {
+            container_path,
+            host_path,
+            mode,
+        }
+    }
+
+    pub fn container_path(&self) -> &str {
+        &self.container_path
+    }
+
+    pub fn host_path(&self) -> &str {
+        &self.host_path
+    }
+
+    pub fn mode(&self) -> &str {
+        &self.mode
+    }
+}
+
+/// The state of a circuit breaker guarding calls to a runtime. A runtime
+/// without a circuit breaker is always `Closed`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through to the runtime.
+    Closed,
+    /// The runtime failed too many times in a row, so calls fail right away.
+    Open,
+    /// A single call is let through to find out whether the runtime is back.
+    HalfOpen,
 }
 
 pub trait ModuleRuntime {
@@ -379,6 +1103,7 @@ pub trait ModuleRuntime {
     type ModuleRegistry: ModuleRegistry<Config = Self::Config, Error = Self::Error>;
     type Chunk: AsRef<[u8]>;
     type Logs: Stream<Item = Self::Chunk, Error = Self::Error> + Send;
+    type RegistryAuth: Send;
 
     type CreateFuture: Future<Item = (), Error = Self::Error> + Send;
     type GetFuture: Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send;
@@ -394,8 +1119,38 @@ pub trait ModuleRuntime {
     type StartFuture: Future<Item = (), Error = Self::Error> + Send;
     type StopFuture: Future<Item = (), Error = Self::Error> + Send;
     type SystemInfoFuture: Future<Item = SystemInfo, Error = Self::Error> + Send;
+    type SystemResourcesFuture: Future<Item = SystemResources, Error = Self::Error> + Send;
     type RemoveAllFuture: Future<Item = (), Error = Self::Error> + Send;
     type TopFuture: Future<Item = ModuleTop, Error = Self::Error> + Send;
+    type StatsFuture: Future<Item = ModuleStats, Error = Self::Error> + Send;
+    type InspectFuture: Future<Item = ModuleDetails, Error = Self::Error> + Send;
+    type EnvFuture: Future<Item = HashMap<String, String>, Error = Self::Error> + Send;
+    type UpdateEnvFuture: Future<Item = (), Error = Self::Error> + Send;
+    type UpdateResourcesFuture: Future<Item = (), Error = Self::Error> + Send;
+    type UpsertFuture: Future<Item = (Self::Module, bool), Error = Self::Error> + Send;
+    type PingFuture: Future<Item = (), Error = Self::Error> + Send;
+    type EventStream: Stream<Item = ModuleEvent, Error = Self::Error> + Send;
+    type GetConfigFuture: Future<Item = Option<ModuleSpec<Self::Config>>, Error = Self::Error>
+        + Send;
+    type ExecFuture: Future<Item = Self::Logs, Error = Self::Error> + Send;
+    type PullImageStream: Stream<Item = PullProgress, Error = Self::Error> + Send;
+    type PullImageFuture: Future<Item = Self::PullImageStream, Error = Self::Error> + Send;
+    type PruneImagesFuture: Future<Item = PruneImagesResult, Error = Self::Error> + Send;
+    type SignalFuture: Future<Item = (), Error = Self::Error> + Send;
+    type PortsFuture: Future<Item = Vec<PortBinding>, Error = Self::Error> + Send;
+    type WaitUntilStoppedFuture: Future<Item = (), Error = Self::Error> + Send;
+    type ConnectivityStatusFuture: Future<Item = ConnectivityStatus, Error = Self::Error> + Send;
+    type CheckpointFuture: Future<Item = (), Error = Self::Error> + Send;
+    type RestoreFuture: Future<Item = (), Error = Self::Error> + Send;
+    type ListCheckpointsFuture: Future<Item = Vec<String>, Error = Self::Error> + Send;
+    type VolumesFuture: Future<Item = Vec<VolumeMount>, Error = Self::Error> + Send;
+    type SnapshotFuture: Future<Item = SystemSnapshot, Error = Self::Error> + Send;
+    type StartAllFuture: Future<Item = Vec<StartResult>, Error = Error> + Send;
+    type SetBandwidthFuture: Future<Item = (), Error = Self::Error> + Send;
+    type PauseFuture: Future<Item = (), Error = Self::Error> + Send;
+    type UnpauseFuture: Future<Item = (), Error = Self::Error> + Send;
+    type CopyToContainerFuture: Future<Item = (), Error = Self::Error> + Send;
+    type RenameFuture: Future<Item = (), Error = Self::Error> + Send;
 
     fn init(&self) -> Self::InitFuture;
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture;
@@ -405,12 +1160,114 @@ pub trait ModuleRuntime {
     fn restart(&self, id: &str) -> Self::RestartFuture;
     fn remove(&self, id: &str) -> Self::RemoveFuture;
     fn system_info(&self) -> Self::SystemInfoFuture;
+    fn system_resources(&self) -> Self::SystemResourcesFuture;
     fn list(&self) -> Self::ListFuture;
     fn list_with_details(&self) -> Self::ListWithDetailsStream;
     fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture;
     fn registry(&self) -> &Self::ModuleRegistry;
     fn remove_all(&self) -> Self::RemoveAllFuture;
-    fn top(&self, id: &str) -> Self::TopFuture;
+    /// Lists the processes running in the module. `ps_args` are passed on to
+    /// `ps` to choose the columns of the process table.
+    fn top(&self, id: &str, ps_args: Option<&str>) -> Self::TopFuture;
+    fn stats(&self, id: &str) -> Self::StatsFuture;
+    fn inspect(&self, id: &str) -> Self::InspectFuture;
+    /// The environment the module was given by its spec, without the
+    /// variables that only its image sets.
+    fn env(&self, id: &str) -> Self::EnvFuture;
+    /// Replaces the environment of the module with `env`. Since a module's
+    /// environment can't be changed in place, the module is recreated with
+    /// the rest of its configuration unchanged, and started again if it was
+    /// running.
+    fn update_env(&self, id: &str, env: HashMap<String, String>) -> Self::UpdateEnvFuture;
+    /// Changes the resource limits of the running module in place. Limits
+    /// that are not set in `limits` are left as they are.
+    fn update_resources(&self, id: &str, limits: ResourceLimits) -> Self::UpdateResourcesFuture;
+    /// Creates the module if it doesn't exist and replaces it with `module`
+    /// otherwise. The flag is `true` when the module was created.
+    fn upsert(&self, module: ModuleSpec<Self::Config>) -> Self::UpsertFuture;
+    /// Checks that the runtime is reachable. This should be cheap enough to
+    /// be called by health probes.
+    fn ping(&self) -> Self::PingFuture;
+    /// Streams the lifecycle events of modules as they happen. The stream
+    /// only ends if the connection to the runtime is lost.
+    fn events(&self) -> Self::EventStream;
+    /// The spec the module was last created from, without any of its
+    /// runtime state, or `None` if no such module was ever created.
+    fn get_config(&self, id: &str) -> Self::GetConfigFuture;
+    /// Runs `cmd` in the running module and streams the standard output of
+    /// the command until it exits. With `tty` the command gets a
+    /// pseudo-terminal, whose output also includes standard error.
+    fn exec(&self, id: &str, cmd: &[String], tty: bool) -> Self::ExecFuture;
+    /// Pulls `image` without creating a module from it, so that a later
+    /// deployment doesn't have to wait for the pull. The stream reports the
+    /// progress of the pull and ends once the image is pulled.
+    fn pull_image(&self, image: &str, auth: Option<Self::RegistryAuth>) -> Self::PullImageFuture;
+    /// Removes images that no module uses to free up disk space. With
+    /// `dangling_only` only untagged images are removed. Images used by a
+    /// module are never removed, whether the module is running or not.
+    fn prune_images(&self, dangling_only: bool) -> Self::PruneImagesFuture;
+    /// Sends the POSIX signal numbered `signal` to the main process of the
+    /// running module.
+    fn signal(&self, id: &str, signal: u32) -> Self::SignalFuture;
+    /// The ports the module's container exposes, along with where they are
+    /// published on the host. A module that isn't running has no ports.
+    fn get_ports(&self, id: &str) -> Self::PortsFuture;
+    /// Resolves once the module is no longer running, which is right away if
+    /// it already isn't. Fails if the module is still running after
+    /// `timeout`.
+    fn wait_until_stopped(&self, id: &str, timeout: Duration) -> Self::WaitUntilStoppedFuture;
+    /// Whether the device could reach IoT Hub the last time it tried.
+    fn connectivity_status(&self) -> Self::ConnectivityStatusFuture;
+    /// Saves the state of the running module as checkpoint `checkpoint_id`,
+    /// which stops the module.
+    fn checkpoint(&self, id: &str, checkpoint_id: &str) -> Self::CheckpointFuture;
+    /// Starts the stopped module from the state saved in checkpoint
+    /// `checkpoint_id` rather than afresh.
+    fn restore(&self, id: &str, checkpoint_id: &str) -> Self::RestoreFuture;
+    /// The IDs of the checkpoints taken of the module.
+    fn list_checkpoints(&self, id: &str) -> Self::ListCheckpointsFuture;
+    /// The volumes and bind mounts of the module's container, whether it is
+    /// running or not.
+    fn get_volumes(&self, id: &str) -> Self::VolumesFuture;
+    /// The state of the host and of every module, with the most recent
+    /// module events. Taking a snapshot doesn't change anything.
+    fn snapshot(&self) -> Self::SnapshotFuture;
+    /// Starts the modules of `specs` after the modules they depend on, as
+    /// `start_in_dependency_order` does. The results are in the order the
+    /// modules were started in. Fails without starting anything if the
+    /// dependencies form a cycle.
+    fn start_all(&self, specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture;
+    /// Limits the network traffic of the running module to `ingress_kbps`
+    /// kilobits per second in and `egress_kbps` out. A rate of 0 lifts the
+    /// limit of that direction, so that both 0 remove the limits.
+    fn set_bandwidth(
+        &self,
+        id: &str,
+        ingress_kbps: u32,
+        egress_kbps: u32,
+    ) -> Self::SetBandwidthFuture;
+    /// Freezes the processes of the running module, without stopping it, so
+    /// that for example a debugger can be attached to them.
+    fn pause(&self, id: &str) -> Self::PauseFuture;
+    /// Thaws the processes of the paused module.
+    fn unpause(&self, id: &str) -> Self::UnpauseFuture;
+    /// Writes `content` to the file at the absolute `dest_path` in the
+    /// running module, replacing the file if there is one.
+    fn copy_to_container(
+        &self,
+        id: &str,
+        dest_path: &str,
+        content: Bytes,
+    ) -> Self::CopyToContainerFuture;
+    /// Renames module `old_name` to `new_name`. The module keeps running if
+    /// it is, but runtimes that store the spec with the module may recreate
+    /// it from that spec to give it the new name.
+    fn rename(&self, old_name: &str, new_name: &str) -> Self::RenameFuture;
+    /// The state of the circuit breaker guarding calls to the runtime.
+    fn circuit_state(&self) -> CircuitState;
+    /// The optional features this runtime supports. This doesn't call the
+    /// runtime, so it is cheap to ask before every use of a feature.
+    fn capabilities(&self) -> Capabilities;
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -452,35 +1309,129 @@ impl fmt::Display for RegistryOperation {
 // Useful for error contexts
 #[derive(Clone, Debug)]
 pub enum RuntimeOperation {
+    CheckpointModule(String),
+    CopyFileToModule(String),
     CreateModule(String),
+    Events,
+    ExecModule(String),
+    GetCapabilities,
+    GetConnectivityStatus,
     GetModule(String),
+    GetModuleConfig(String),
+    GetModuleDiff(String),
+    GetModuleEnv(String),
     GetModuleLogs(String),
+    GetModulePorts(String),
+    GetModuleStats(String),
+    GetModuleVolumes(String),
     Init,
+    InspectModule(String),
+    ListModuleCheckpoints(String),
     ListModules,
+    PauseModule(String),
+    Ping,
+    PrepareModuleImage(String),
+    PruneImages,
     RemoveModule(String),
+    RenameModule(String),
     RestartModule(String),
+    RestoreModule(String),
+    SetModuleBandwidth(String),
+    SignalModule(String),
+    Snapshot,
     StartModule(String),
     StopModule(String),
     SystemInfo,
+    SystemResources,
     TopModule(String),
+    UnpauseModule(String),
+    UpdateModuleEnv(String),
+    UpdateModuleResources(String),
+    UpsertModule(String),
+    WaitUntilStopped(String),
 }
 
 impl fmt::Display for RuntimeOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            RuntimeOperation::CheckpointModule(name) => {
+                write!(f, "Could not checkpoint module {}", name)
+            }
+            RuntimeOperation::CopyFileToModule(name) => {
+                write!(f, "Could not copy a file into module {}", name)
+            }
             RuntimeOperation::CreateModule(name) => write!(f, "Could not create module {}", name),
+            RuntimeOperation::Events => write!(f, "Could not watch module events"),
+            RuntimeOperation::ExecModule(name) => {
+                write!(f, "Could not run command in module {}", name)
+            }
+            RuntimeOperation::GetCapabilities => write!(f, "Could not get runtime capabilities"),
+            RuntimeOperation::GetConnectivityStatus => {
+                write!(f, "Could not get IoT Hub connectivity status")
+            }
             RuntimeOperation::GetModule(name) => write!(f, "Could not get module {}", name),
+            RuntimeOperation::GetModuleConfig(name) => {
+                write!(f, "Could not get config of module {}", name)
+            }
+            RuntimeOperation::GetModuleDiff(name) => {
+                write!(f, "Could not get diff of module {}", name)
+            }
+            RuntimeOperation::GetModuleEnv(name) => {
+                write!(f, "Could not get environment of module {}", name)
+            }
             RuntimeOperation::GetModuleLogs(name) => {
                 write!(f, "Could not get logs for module {}", name)
             }
+            RuntimeOperation::GetModulePorts(name) => {
+                write!(f, "Could not get ports of module {}", name)
+            }
+            RuntimeOperation::GetModuleStats(name) => {
+                write!(f, "Could not get stats for module {}", name)
+            }
+            RuntimeOperation::GetModuleVolumes(name) => {
+                write!(f, "Could not get volumes of module {}", name)
+            }
             RuntimeOperation::Init => write!(f, "Could not initialize module runtime"),
+            RuntimeOperation::InspectModule(name) => write!(f, "Could not inspect module {}", name),
+            RuntimeOperation::ListModuleCheckpoints(name) => {
+                write!(f, "Could not list checkpoints of module {}", name)
+            }
             RuntimeOperation::ListModules => write!(f, "Could not list modules"),
+            RuntimeOperation::PauseModule(name) => write!(f, "Could not pause module {}", name),
+            RuntimeOperation::Ping => write!(f, "Could not reach module runtime"),
+            RuntimeOperation::PrepareModuleImage(name) => {
+                write!(f, "Could not prepare image for module {}", name)
+            }
+            RuntimeOperation::PruneImages => write!(f, "Could not prune images"),
             RuntimeOperation::RemoveModule(name) => write!(f, "Could not remove module {}", name),
+            RuntimeOperation::RenameModule(name) => write!(f, "Could not rename module {}", name),
             RuntimeOperation::RestartModule(name) => write!(f, "Could not restart module {}", name),
+            RuntimeOperation::RestoreModule(name) => {
+                write!(f, "Could not restore module {} from checkpoint", name)
+            }
+            RuntimeOperation::SetModuleBandwidth(name) => {
+                write!(f, "Could not set bandwidth limits of module {}", name)
+            }
+            RuntimeOperation::SignalModule(name) => write!(f, "Could not signal module {}", name),
+            RuntimeOperation::Snapshot => write!(f, "Could not take a snapshot of the system"),
             RuntimeOperation::StartModule(name) => write!(f, "Could not start module {}", name),
             RuntimeOperation::StopModule(name) => write!(f, "Could not stop module {}", name),
             RuntimeOperation::SystemInfo => write!(f, "Could not query system info"),
+            RuntimeOperation::SystemResources => write!(f, "Could not query system resources"),
             RuntimeOperation::TopModule(name) => write!(f, "Could not top module {}", name),
+            RuntimeOperation::UnpauseModule(name) => write!(f, "Could not unpause module {}", name),
+            RuntimeOperation::UpdateModuleEnv(name) => {
+                write!(f, "Could not update environment of module {}", name)
+            }
+            RuntimeOperation::UpdateModuleResources(name) => {
+                write!(f, "Could not update resource limits of module {}", name)
+            }
+            RuntimeOperation::UpsertModule(name) => {
+                write!(f, "Could not create or update module {}", name)
+            }
+            RuntimeOperation::WaitUntilStopped(name) => {
+                write!(f, "Could not wait for module {} to stop", name)
+            }
         }
     }
 }
@@ -501,6 +1452,7 @@ mod tests {
             ("running", ModuleStatus::Running),
             ("stopped", ModuleStatus::Stopped),
             ("failed", ModuleStatus::Failed),
+            ("paused", ModuleStatus::Paused),
         ]
     }
 
@@ -550,6 +1502,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn module_names_follow_docker_rules() {
+        for name in &["edgeHub", "mod1", "temp_sensor.v2", "a-b"] {
+            assert!(validate_module_name(name, None).is_ok(), "{}", name);
+        }
+        for name in &["", "a", "-mod", "_mod", "mod 1", "mod/1", "$edgeAgent"] {
+            match validate_module_name(name, None) {
+                Err(ref err) => {
+                    if let ErrorKind::InvalidModuleName(s) = err.kind() {
+                        assert_eq!(s, name);
+                    } else {
+                        panic!("Expected `InvalidModuleName` but got {:?}", err);
+                    }
+                }
+                Ok(()) => panic!("Expected error for {:?}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn module_names_must_match_the_pattern() {
+        let pattern = Regex::new("^contoso-").unwrap();
+
+        assert!(validate_module_name("contoso-sensor", Some(&pattern)).is_ok());
+        match validate_module_name("sensor", Some(&pattern)) {
+            Err(ref err) => {
+                if let ErrorKind::ModuleNameNotAllowed(name, pattern) = err.kind() {
+                    assert_eq!("sensor", name);
+                    assert_eq!("^contoso-", pattern);
+                } else {
+                    panic!("Expected `ModuleNameNotAllowed` but got {:?}", err);
+                }
+            }
+            Ok(()) => panic!("Expected error"),
+        }
+    }
+
     #[test]
     fn module_config_empty_type_fails() {
         let type_ = "    ".to_string();
@@ -580,6 +1569,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn module_spec_labels_default_to_empty() {
+        let spec: ModuleSpec<i32> =
+            serde_json::from_str(r#"{"name":"m1","type":"docker","config":10}"#).unwrap();
+        assert!(spec.labels().is_empty());
+        assert!(!serde_json::to_string(&spec).unwrap().contains("labels"));
+
+        let spec: ModuleSpec<i32> = serde_json::from_str(
+            r#"{"name":"m1","type":"docker","config":10,"labels":{"app":"web"}}"#,
+        )
+        .unwrap();
+        assert_eq!(Some(&"web".to_string()), spec.labels().get("app"));
+    }
+
+    #[test]
+    fn module_spec_resource_limits_default_to_empty() {
+        let spec: ModuleSpec<i32> =
+            serde_json::from_str(r#"{"name":"m1","type":"docker","config":10}"#).unwrap();
+        assert!(spec.resource_limits().is_empty());
+        assert!(!serde_json::to_string(&spec)
+            .unwrap()
+            .contains("resource_limits"));
+    }
+
+    #[test]
+    fn module_spec_restart_policy_defaults_to_never() {
+        let spec: ModuleSpec<i32> =
+            serde_json::from_str(r#"{"name":"m1","type":"docker","config":10}"#).unwrap();
+        assert_eq!(RestartPolicy::Never, spec.restart_policy());
+        assert!(!serde_json::to_string(&spec)
+            .unwrap()
+            .contains("restart_policy"));
+
+        let spec: ModuleSpec<i32> = serde_json::from_str(
+            r#"{"name":"m1","type":"docker","config":10,"restart_policy":"on_failure"}"#,
+        )
+        .unwrap();
+        assert_eq!(RestartPolicy::OnFailure, spec.restart_policy());
+    }
+
+    #[test]
+    fn restart_policy_should_restart() {
+        assert!(!RestartPolicy::Never.should_restart(Some(1)));
+        assert!(!RestartPolicy::OnFailure.should_restart(Some(0)));
+        assert!(RestartPolicy::OnFailure.should_restart(Some(137)));
+        assert!(RestartPolicy::OnFailure.should_restart(None));
+        assert!(RestartPolicy::Always.should_restart(Some(0)));
+    }
+
+    #[test]
+    fn resource_limits_validate_swap() {
+        assert!(ResourceLimits::new(Some(512), Some(1024), Some(2048)).is_ok());
+        assert!(ResourceLimits::new(None, Some(1024), Some(1024)).is_ok());
+        assert!(ResourceLimits::new(None, Some(1024), Some(-1)).is_ok());
+        assert!(ResourceLimits::new(None, None, None).unwrap().is_empty());
+
+        let err = ResourceLimits::new(None, Some(1024), Some(512)).unwrap_err();
+        assert_eq!(
+            "Invalid resource limits: memory_swap_bytes 512 must be at least memory_limit_bytes 1024 or -1",
+            err.to_string()
+        );
+        assert!(ResourceLimits::new(None, None, Some(-2)).is_err());
+    }
+
     #[test]
     fn system_info_new_and_access_succeed() {
         //arrange
@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+
+/// The rules for the names of modules created through the management API, on
+/// top of Docker's rules for container names. Names have to match `pattern`
+/// when there is one, and only edgeAgent may create modules with one of the
+/// `reserved_names`, which are those of the edge runtime modules by default.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModuleNameRules {
+    #[serde(default, with = "pattern", skip_serializing_if = "Option::is_none")]
+    pattern: Option<Regex>,
+    #[serde(default = "default_reserved_names")]
+    reserved_names: Vec<String>,
+}
+
+impl Default for ModuleNameRules {
+    fn default() -> Self {
+        ModuleNameRules {
+            pattern: None,
+            reserved_names: default_reserved_names(),
+        }
+    }
+}
+
+impl ModuleNameRules {
+    pub fn new(pattern: Option<Regex>, reserved_names: Vec<String>) -> Self {
+        ModuleNameRules {
+            pattern,
+            reserved_names,
+        }
+    }
+
+    pub fn pattern(&self) -> Option<&Regex> {
+        self.pattern.as_ref()
+    }
+
+    pub fn reserved_names(&self) -> &[String] {
+        &self.reserved_names
+    }
+
+    pub fn is_reserved(&self, name: &str) -> bool {
+        self.reserved_names.iter().any(|reserved| reserved == name)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.pattern.is_none() && self.reserved_names == default_reserved_names()
+    }
+}
+
+fn default_reserved_names() -> Vec<String> {
+    vec!["edgeAgent".to_string(), "edgeHub".to_string()]
+}
+
+/// The pattern is compiled when the settings are loaded, so that a bad one
+/// fails iotedged at startup rather than every request.
+mod pattern {
+    use regex::Regex;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(pattern: &Option<Regex>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match pattern {
+            Some(pattern) => serializer.serialize_some(pattern.as_str()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(pattern) => Regex::new(&pattern).map(Some).map_err(D::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn edge_runtime_modules_are_reserved_by_default() {
+        let rules = ModuleNameRules::default();
+
+        assert!(rules.is_reserved("edgeAgent"));
+        assert!(rules.is_reserved("edgeHub"));
+        assert!(!rules.is_reserved("edgehub"));
+        assert!(rules.pattern().is_none());
+        assert!(rules.is_default());
+    }
+
+    #[test]
+    fn pattern_is_compiled_on_deserialize() {
+        let rules: ModuleNameRules =
+            serde_json::from_value(json!({ "pattern": "^contoso-" })).unwrap();
+        assert!(rules.pattern().unwrap().is_match("contoso-sensor"));
+        assert!(rules.is_reserved("edgeHub"));
+        assert_eq!(
+            json!({ "pattern": "^contoso-", "reserved_names": ["edgeAgent", "edgeHub"] }),
+            serde_json::to_value(&rules).unwrap()
+        );
+
+        assert!(serde_json::from_value::<ModuleNameRules>(json!({ "pattern": "(" })).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+
+use crate::module::{ModuleEvent, ModuleRuntimeState, SystemInfo, SystemResources};
+
+/// How many of the most recent module events a snapshot holds.
+pub const SNAPSHOT_EVENT_COUNT: usize = 100;
+
+/// The state of the host and of every module at one point in time, for
+/// troubleshooting.
+#[derive(Debug, Serialize)]
+pub struct SystemSnapshot {
+    timestamp: DateTime<Utc>,
+    system_info: SystemInfo,
+    system_resources: SystemResources,
+    disk_usage: Vec<DiskUsage>,
+    network_interfaces: Vec<NetworkInterface>,
+    modules: Vec<ModuleSnapshot>,
+    /// The most recent module events, oldest first.
+    events: Vec<ModuleEvent>,
+}
+
+impl SystemSnapshot {
+    pub fn new(
+        timestamp: DateTime<Utc>,
+        system_info: SystemInfo,
+        system_resources: SystemResources,
+    ) -> Self {
+        SystemSnapshot {
+            timestamp,
+            system_info,
+            system_resources,
+            disk_usage: Vec::new(),
+            network_interfaces: Vec::new(),
+            modules: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn with_disk_usage(mut self, disk_usage: Vec<DiskUsage>) -> Self {
+        self.disk_usage = disk_usage;
+        self
+    }
+
+    pub fn with_network_interfaces(mut self, network_interfaces: Vec<NetworkInterface>) -> Self {
+        self.network_interfaces = network_interfaces;
+        self
+    }
+
+    pub fn with_modules(mut self, modules: Vec<ModuleSnapshot>) -> Self {
+        self.modules = modules;
+        self
+    }
+
+    /// Keeps only the last `SNAPSHOT_EVENT_COUNT` of `events`.
+    pub fn with_events(mut self, mut events: Vec<ModuleEvent>) -> Self {
+        let excess = events.len().saturating_sub(SNAPSHOT_EVENT_COUNT);
+        events.drain(..excess);
+        self.events = events;
+        self
+    }
+
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    pub fn system_info(&self) -> &SystemInfo {
+        &self.system_info
+    }
+
+    pub fn system_resources(&self) -> &SystemResources {
+        &self.system_resources
+    }
+
+    pub fn disk_usage(&self) -> &[DiskUsage] {
+        &self.disk_usage
+    }
+
+    pub fn network_interfaces(&self) -> &[NetworkInterface] {
+        &self.network_interfaces
+    }
+
+    pub fn modules(&self) -> &[ModuleSnapshot] {
+        &self.modules
+    }
+
+    pub fn events(&self) -> &[ModuleEvent] {
+        &self.events
+    }
+}
+
+/// The size of a filesystem and the space free on it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DiskUsage {
+    mount_point: PathBuf,
+    total_bytes: u64,
+    free_bytes: u64,
+}
+
+impl DiskUsage {
+    pub fn new(mount_point: PathBuf, total_bytes: u64, free_bytes: u64) -> Self {
+        DiskUsage {
+            mount_point,
+            total_bytes,
+            free_bytes,
+        }
+    }
+
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.free_bytes
+    }
+}
+
+/// A network interface of the host and the addresses assigned to it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NetworkInterface {
+    name: String,
+    addresses: Vec<IpAddr>,
+}
+
+impl NetworkInterface {
+    pub fn new(name: String, addresses: Vec<IpAddr>) -> Self {
+        NetworkInterface { name, addresses }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn addresses(&self) -> &[IpAddr] {
+        &self.addresses
+    }
+}
+
+/// A module and its runtime state.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ModuleSnapshot {
+    name: String,
+    state: ModuleRuntimeState,
+}
+
+impl ModuleSnapshot {
+    pub fn new(name: String, state: ModuleRuntimeState) -> Self {
+        ModuleSnapshot { name, state }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> &ModuleRuntimeState {
+        &self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::module::ModuleEventType;
+
+    use super::*;
+
+    #[test]
+    fn only_the_most_recent_events_are_kept() {
+        let start = Utc::now();
+        let events: Vec<_> = (0..150)
+            .map(|i| {
+                ModuleEvent::new(
+                    ModuleEventType::Started,
+                    format!("m{}", i),
+                    start + chrono::Duration::seconds(i),
+                )
+            })
+            .collect();
+
+        let snapshot = SystemSnapshot::new(
+            start,
+            SystemInfo::new("linux".to_string(), "x86_64".to_string()),
+            SystemResources::default(),
+        )
+        .with_events(events);
+
+        assert_eq!(SNAPSHOT_EVENT_COUNT, snapshot.events().len());
+        assert_eq!("m50", snapshot.events()[0].module_name());
+        assert_eq!("m149", snapshot.events()[99].module_name());
+    }
+}
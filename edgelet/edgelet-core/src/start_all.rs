@@ -0,0 +1,255 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use failure::Fail;
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use log::info;
+use serde_derive::Serialize;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::module::{ModuleRuntime, ModuleSpec, ModuleStatus};
+
+/// Whether a module of `ModuleRuntime::start_all` is running, and why not if
+/// it isn't.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StartResult {
+    module_name: String,
+    started: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl StartResult {
+    pub fn started(module_name: String) -> Self {
+        StartResult {
+            module_name,
+            started: true,
+            error: None,
+        }
+    }
+
+    pub fn failed(module_name: String, error: String) -> Self {
+        StartResult {
+            module_name,
+            started: false,
+            error: Some(error),
+        }
+    }
+
+    pub fn module_name(&self) -> &str {
+        &self.module_name
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(String::as_str)
+    }
+}
+
+/// Starts the modules of `specs` on `runtime` in the order of their
+/// `depends_on`, for runtimes to implement `ModuleRuntime::start_all` with.
+///
+/// The modules are started in layers. A layer is started concurrently once
+/// every module of the layer before it is running, and a module whose
+/// dependency didn't start isn't started at all. Dependencies on modules that
+/// aren't in `specs` are left to the caller. The future fails only if the
+/// dependencies form a cycle, in which case no module is started.
+pub fn start_in_dependency_order<M>(
+    runtime: M,
+    specs: Vec<ModuleSpec<M::Config>>,
+) -> impl Future<Item = Vec<StartResult>, Error = Error> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    let dependencies: BTreeMap<String, Vec<String>> = specs
+        .into_iter()
+        .map(|spec| (spec.name().to_string(), spec.depends_on().to_vec()))
+        .collect();
+
+    let layers = match layers(&dependencies) {
+        Ok(layers) => layers,
+        Err(err) => return Either::A(future::err(err)),
+    };
+
+    Either::B(future::loop_fn(
+        (layers.into_iter(), Vec::with_capacity(dependencies.len())),
+        move |(mut layers, mut results)| match layers.next() {
+            Some(layer) => {
+                info!("Starting modules {:?}", layer);
+                let starts: Vec<_> = layer
+                    .into_iter()
+                    .map(
+                        |name| match failed_dependency(&dependencies[&name], &results) {
+                            Some(dependency) => Either::A(future::ok(StartResult::failed(
+                                name,
+                                format!("Dependency {} did not start", dependency),
+                            ))),
+                            None => Either::B(start_module(&runtime, name)),
+                        },
+                    )
+                    .collect();
+                Either::A(future::join_all(starts).map(move |started| {
+                    results.extend(started);
+                    Loop::Continue((layers, results))
+                }))
+            }
+            None => Either::B(future::ok(Loop::Break(results))),
+        },
+    ))
+}
+
+/// Splits the modules into the layers they are started in. A module is in
+/// the layer after the last of its dependencies, and the modules of a layer
+/// are in the order of their names.
+fn layers(dependencies: &BTreeMap<String, Vec<String>>) -> Result<Vec<Vec<String>>> {
+    let mut pending: BTreeMap<&str, BTreeSet<&str>> = dependencies
+        .iter()
+        .map(|(name, depends_on)| {
+            let depends_on = depends_on
+                .iter()
+                .filter(|dependency| dependencies.contains_key(dependency.as_str()))
+                .map(String::as_str)
+                .collect();
+            (name.as_str(), depends_on)
+        })
+        .collect();
+
+    let mut layers = Vec::new();
+    while !pending.is_empty() {
+        let layer: Vec<&str> = pending
+            .iter()
+            .filter(|(_, depends_on)| depends_on.is_empty())
+            .map(|(name, _)| *name)
+            .collect();
+        if layer.is_empty() {
+            return Err(Error::from(ErrorKind::ModuleDependencyCycle(
+                pending.keys().map(ToString::to_string).collect(),
+            )));
+        }
+
+        for name in &layer {
+            pending.remove(name);
+        }
+        for depends_on in pending.values_mut() {
+            for name in &layer {
+                depends_on.remove(name);
+            }
+        }
+        layers.push(layer.into_iter().map(ToString::to_string).collect());
+    }
+
+    Ok(layers)
+}
+
+fn failed_dependency<'a>(depends_on: &'a [String], results: &[StartResult]) -> Option<&'a str> {
+    depends_on
+        .iter()
+        .find(|dependency| {
+            results
+                .iter()
+                .any(|result| result.module_name == **dependency && !result.started)
+        })
+        .map(String::as_str)
+}
+
+/// Starts the module and checks that it is running afterwards, since a
+/// module can exit as soon as it is started.
+fn start_module<M>(runtime: &M, name: String) -> impl Future<Item = StartResult, Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    let get_runtime = runtime.clone();
+    let get_name = name.clone();
+
+    runtime
+        .start(&name)
+        .and_then(move |()| get_runtime.get(&get_name))
+        .then(move |result| -> Result<_> {
+            let result = match result {
+                Ok((_, ref state)) if *state.status() == ModuleStatus::Running => {
+                    StartResult::started(name)
+                }
+                Ok((_, state)) => StartResult::failed(
+                    name,
+                    format!("Module is {} after it was started", state.status()),
+                ),
+                Err(err) => StartResult::failed(name, error_message(&err)),
+            };
+            Ok(result)
+        })
+}
+
+fn error_message<F: Fail>(err: &F) -> String {
+    let mut message = err.to_string();
+    for cause in Fail::iter_causes(err) {
+        message.push_str(&format!("\n\tcaused by: {}", cause));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies(modules: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        modules
+            .iter()
+            .map(|(name, depends_on)| {
+                (
+                    name.to_string(),
+                    depends_on.iter().map(ToString::to_string).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layers_follow_dependencies() {
+        let dependencies = dependencies(&[
+            ("app", &["db", "cache"]),
+            ("cache", &[]),
+            ("db", &["storage", "elsewhere"]),
+            ("storage", &[]),
+        ]);
+
+        assert_eq!(
+            vec![
+                vec!["cache".to_string(), "storage".to_string()],
+                vec!["db".to_string()],
+                vec!["app".to_string()],
+            ],
+            layers(&dependencies).unwrap()
+        );
+    }
+
+    #[test]
+    fn cycle_lists_modules() {
+        let dependencies = dependencies(&[("a", &["b"]), ("b", &["a"]), ("c", &[])]);
+
+        match layers(&dependencies).unwrap_err().kind() {
+            ErrorKind::ModuleDependencyCycle(modules) => {
+                assert_eq!(&vec!["a".to_string(), "b".to_string()], modules)
+            }
+            kind => panic!("Expected a dependency cycle but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn dependency_failure_is_found() {
+        let results = vec![
+            StartResult::started("cache".to_string()),
+            StartResult::failed("db".to_string(), "General error".to_string()),
+        ];
+
+        assert_eq!(
+            Some("db"),
+            failed_dependency(&["cache".to_string(), "db".to_string()], &results)
+        );
+        assert_eq!(None, failed_dependency(&["cache".to_string()], &results));
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use futures::sync::oneshot::{self, Receiver, Sender};
+
+/// Asks the daemon to clear its cached state and start again from scratch,
+/// as it would when it is first provisioned. Clones share the request, and
+/// only the first request of a run of the daemon reaches it.
+#[derive(Clone, Debug)]
+pub struct StateReset {
+    sender: Arc<Mutex<Option<Sender<()>>>>,
+}
+
+impl StateReset {
+    /// The receiver completes once a reset is requested, or fails if every
+    /// clone of the `StateReset` is dropped first.
+    pub fn channel() -> (Self, Receiver<()>) {
+        let (sender, receiver) = oneshot::channel();
+        let reset = StateReset {
+            sender: Arc::new(Mutex::new(Some(sender))),
+        };
+        (reset, receiver)
+    }
+
+    /// Returns `false` if a reset was requested already, or the daemon no
+    /// longer listens for one.
+    pub fn request(&self) -> bool {
+        self.sender
+            .lock()
+            .unwrap()
+            .take()
+            .map_or(false, |sender| sender.send(()).is_ok())
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.sender.lock().unwrap().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+
+    use super::*;
+
+    #[test]
+    fn only_the_first_request_counts() {
+        let (reset, receiver) = StateReset::channel();
+        let clone = reset.clone();
+
+        assert!(!reset.is_requested());
+        assert!(clone.request());
+        assert!(reset.is_requested());
+        assert!(!reset.request());
+        assert_eq!(Ok(()), receiver.wait());
+    }
+
+    #[test]
+    fn request_fails_without_receiver() {
+        let (reset, receiver) = StateReset::channel();
+        drop(receiver);
+
+        assert!(!reset.request());
+    }
+}
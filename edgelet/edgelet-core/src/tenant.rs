@@ -0,0 +1,81 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Separates the tenant from the module name in the names of tenant modules.
+/// Container names can't contain `/`, so the scoped name of module `m1` of
+/// tenant `t1` is `t1.m1`.
+pub const TENANT_SEPARATOR: char = '.';
+
+/// The tenants whose modules can be managed under `/tenants/{tenant_id}`.
+/// No tenants are configured by default.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TenantConfig {
+    #[serde(default)]
+    tenants: Vec<String>,
+}
+
+impl TenantConfig {
+    pub fn new(tenants: Vec<String>) -> Self {
+        TenantConfig { tenants }
+    }
+
+    pub fn tenants(&self) -> &[String] {
+        &self.tenants
+    }
+
+    pub fn contains(&self, tenant_id: &str) -> bool {
+        self.tenants.iter().any(|tenant| tenant == tenant_id)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.tenants.is_empty()
+    }
+}
+
+/// The name that module `name` of `tenant_id` has in the module runtime.
+pub fn scoped_name(tenant_id: &str, name: &str) -> String {
+    format!("{}{}{}", tenant_id, TENANT_SEPARATOR, name)
+}
+
+/// The name of a module within `tenant_id`, if `scoped_name` is the name of
+/// one of the tenant's modules.
+pub fn unscoped_name<'a>(tenant_id: &str, scoped_name: &'a str) -> Option<&'a str> {
+    if scoped_name.len() > tenant_id.len() + 1
+        && scoped_name.starts_with(tenant_id)
+        && scoped_name[tenant_id.len()..].starts_with(TENANT_SEPARATOR)
+    {
+        Some(&scoped_name[tenant_id.len() + 1..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        let name = scoped_name("t1", "m1");
+        assert_eq!("t1.m1", name);
+        assert_eq!(Some("m1"), unscoped_name("t1", &name));
+    }
+
+    #[test]
+    fn names_of_other_tenants_are_not_unscoped() {
+        assert_eq!(None, unscoped_name("t1", "t2.m1"));
+        assert_eq!(None, unscoped_name("t1", "t10.m1"));
+        assert_eq!(None, unscoped_name("t1", "t1."));
+        assert_eq!(None, unscoped_name("t1", "t1"));
+        assert_eq!(None, unscoped_name("t1", "m1"));
+    }
+
+    #[test]
+    fn only_configured_tenants_are_contained() {
+        let config = TenantConfig::new(vec!["t1".to_string()]);
+        assert!(config.contains("t1"));
+        assert!(!config.contains("t2"));
+        assert!(!TenantConfig::default().contains("t1"));
+    }
+}
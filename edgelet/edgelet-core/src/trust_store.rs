@@ -0,0 +1,316 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use failure::{Fail, ResultExt};
+use openssl::hash::MessageDigest;
+use openssl::x509::{X509Ref, X509};
+use serde_derive::Serialize;
+
+use crate::certificate_manager::to_datetime;
+use crate::error::{Error, ErrorKind, Result};
+use crate::module::validate_module_name;
+
+/// The file a module's CA certificates are kept in.
+const STORE_FILE_NAME: &str = "cas.pem";
+
+/// The file with all of a module's CA certificates that is mounted into the
+/// module's container.
+pub const TRUST_BUNDLE_FILE_NAME: &str = "ca-bundle.pem";
+
+/// A CA certificate in the trust store of a module.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TrustedCa {
+    subject: String,
+    /// Hex of the SHA-1 of the certificate, like that of a `CertInfo`.
+    thumbprint: String,
+    not_after: DateTime<Utc>,
+}
+
+impl TrustedCa {
+    fn from_cert(cert: &X509Ref) -> Result<Self> {
+        let thumbprint = cert
+            .digest(MessageDigest::sha1())
+            .context(ErrorKind::ReadCertificate)?
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let subject = cert
+            .subject_name()
+            .entries()
+            .map(|entry| {
+                let key = entry.object().nid().short_name().unwrap_or("?");
+                let value = entry
+                    .data()
+                    .as_utf8()
+                    .map_or_else(|_| String::new(), |value| value.to_string());
+                format!("{}={}", key, value)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(TrustedCa {
+            subject,
+            thumbprint,
+            not_after: to_datetime(cert.not_after())?,
+        })
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn thumbprint(&self) -> &str {
+        &self.thumbprint
+    }
+
+    pub fn not_after(&self) -> &DateTime<Utc> {
+        &self.not_after
+    }
+}
+
+/// Keeps the CA certificates that each module trusts, in a directory of the
+/// module under `root`. Every change also writes all of the module's
+/// certificates to `ca-bundle.pem` in its `bundle_dir`, which the module
+/// runtime mounts into the module's container. The bundle is replaced with a
+/// rename, so a running module sees the new bundle the next time it reads
+/// it. Clones share the same lock.
+#[derive(Clone, Debug)]
+pub struct ModuleTrustStore {
+    root: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl ModuleTrustStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        ModuleTrustStore {
+            root: root.into(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// The directory with the trust bundle of `module`.
+    pub fn bundle_dir(&self, module: &str) -> Result<PathBuf> {
+        Ok(self.module_dir(module)?.join("bundle"))
+    }
+
+    pub fn list(&self, module: &str) -> Result<Vec<TrustedCa>> {
+        let _lock = self.lock.lock().unwrap();
+        self.read(module)?
+            .iter()
+            .map(|cert| TrustedCa::from_cert(cert))
+            .collect()
+    }
+
+    /// Adds the certificates of `pem` to the trust store of `module`, and
+    /// returns those that weren't already in it.
+    pub fn add(&self, module: &str, pem: &[u8]) -> Result<Vec<TrustedCa>> {
+        let added = X509::stack_from_pem(pem)
+            .map_err(|err| ErrorKind::InvalidTrustedCertificate(err.to_string()))?;
+        if added.is_empty() {
+            return Err(Error::from(ErrorKind::InvalidTrustedCertificate(
+                "no certificate found".to_string(),
+            )));
+        }
+
+        let _lock = self.lock.lock().unwrap();
+        let mut certs = self.read(module)?;
+        let mut thumbprints = certs
+            .iter()
+            .map(|cert| TrustedCa::from_cert(cert).map(|ca| ca.thumbprint))
+            .collect::<Result<Vec<_>>>()?;
+        let mut entries = vec![];
+        for cert in added {
+            let entry = TrustedCa::from_cert(&cert)?;
+            if !thumbprints.contains(&entry.thumbprint) {
+                thumbprints.push(entry.thumbprint.clone());
+                certs.push(cert);
+                entries.push(entry);
+            }
+        }
+        if !entries.is_empty() {
+            self.write(module, &certs)?;
+        }
+        Ok(entries)
+    }
+
+    /// Removes the certificate with `thumbprint`, in either case, from the
+    /// trust store of `module`.
+    pub fn remove(&self, module: &str, thumbprint: &str) -> Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let certs = self.read(module)?;
+        let count = certs.len();
+        let certs = certs
+            .into_iter()
+            .map(|cert| TrustedCa::from_cert(&cert).map(|ca| (ca, cert)))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|(ca, _)| !ca.thumbprint.eq_ignore_ascii_case(thumbprint))
+            .map(|(_, cert)| cert)
+            .collect::<Vec<_>>();
+        if certs.len() == count {
+            return Err(Error::from(ErrorKind::TrustedCertificateNotFound(
+                thumbprint.to_string(),
+                module.to_string(),
+            )));
+        }
+        self.write(module, &certs)
+    }
+
+    /// Checks the name of the module, so that it can't point outside of
+    /// `root`.
+    fn module_dir(&self, module: &str) -> Result<PathBuf> {
+        validate_module_name(module, None)?;
+        Ok(self.root.join(module))
+    }
+
+    fn read(&self, module: &str) -> Result<Vec<X509>> {
+        let path = self.module_dir(module)?.join(STORE_FILE_NAME);
+        let pem = match fs::read(&path) {
+            Ok(pem) => pem,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(Error::from(
+                    err.context(ErrorKind::ModuleTrustStore(module.to_string())),
+                ))
+            }
+        };
+        if pem.iter().all(u8::is_ascii_whitespace) {
+            return Ok(vec![]);
+        }
+        let certs = X509::stack_from_pem(&pem).context(ErrorKind::ReadCertificate)?;
+        Ok(certs)
+    }
+
+    /// Writes the store and then the bundle of `module`, which hold the same
+    /// certificates.
+    fn write(&self, module: &str, certs: &[X509]) -> Result<()> {
+        let mut pem = vec![];
+        for cert in certs {
+            pem.extend(cert.to_pem().context(ErrorKind::ReadCertificate)?);
+        }
+        let dir = self.module_dir(module)?;
+        let bundle_dir = self.bundle_dir(module)?;
+        write(&dir.join(STORE_FILE_NAME), &pem)
+            .and_then(|()| write(&bundle_dir.join(TRUST_BUNDLE_FILE_NAME), &pem))
+            .with_context(|_| ErrorKind::ModuleTrustStore(module.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Writes to a temporary file first and renames it over `path`, like the
+/// `FileBackedConfigStore` does.
+fn write(path: &Path, b: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, b)?;
+    fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::asn1::Asn1Time;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::X509NameBuilder;
+
+    use super::*;
+
+    fn pem(cn: &str) -> Vec<u8> {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "Contoso")
+            .unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(30).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        cert.build().to_pem().unwrap()
+    }
+
+    #[test]
+    fn added_certificates_are_listed_and_bundled() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModuleTrustStore::new(dir.path());
+        let ca1 = pem("ca1");
+
+        let added = store.add("mod1", &ca1).unwrap();
+        let again = store
+            .add("mod1", &[ca1.clone(), pem("ca2")].concat())
+            .unwrap();
+
+        assert_eq!(1, added.len());
+        assert_eq!("O=Contoso, CN=ca1", added[0].subject());
+        assert_eq!(40, added[0].thumbprint().len());
+        assert_eq!(1, again.len());
+        assert_eq!("O=Contoso, CN=ca2", again[0].subject());
+        assert_eq!(
+            vec![added[0].clone(), again[0].clone()],
+            store.list("mod1").unwrap()
+        );
+        assert!(store.list("mod2").unwrap().is_empty());
+
+        let bundle = fs::read(
+            store
+                .bundle_dir("mod1")
+                .unwrap()
+                .join(TRUST_BUNDLE_FILE_NAME),
+        )
+        .unwrap();
+        assert_eq!(2, X509::stack_from_pem(&bundle).unwrap().len());
+    }
+
+    #[test]
+    fn removed_certificate_leaves_the_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModuleTrustStore::new(dir.path());
+        let added = store.add("mod1", &pem("ca1")).unwrap();
+        let thumbprint = added[0].thumbprint().to_lowercase();
+
+        store.remove("mod1", &thumbprint).unwrap();
+
+        assert!(store.list("mod1").unwrap().is_empty());
+        let bundle = fs::read(
+            store
+                .bundle_dir("mod1")
+                .unwrap()
+                .join(TRUST_BUNDLE_FILE_NAME),
+        )
+        .unwrap();
+        assert!(bundle.is_empty());
+        match store.remove("mod1", &thumbprint).unwrap_err().kind() {
+            ErrorKind::TrustedCertificateNotFound(_, module) => assert_eq!("mod1", module),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn invalid_pem_and_module_name_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModuleTrustStore::new(dir.path());
+
+        match store.add("mod1", b"not a certificate").unwrap_err().kind() {
+            ErrorKind::InvalidTrustedCertificate(_) => (),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        match store.add("../mod1", &pem("ca1")).unwrap_err().kind() {
+            ErrorKind::InvalidModuleName(_) => (),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
@@ -1,20 +1,26 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use failure::Fail;
 use futures::future::{self, Either, FutureResult};
 use futures::Future;
-use log::{info, warn, Level};
+use log::{debug, info, warn, Level};
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
 use tokio::prelude::*;
-use tokio::timer::Interval;
+use tokio::timer::{Delay, Interval};
 
 use edgelet_utils::log_failure;
 
 use crate::error::{Error, ErrorKind};
 use crate::identity::{Identity, IdentityManager, IdentitySpec};
 use crate::module::{
-    Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleSpec, ModuleStatus,
+    Module, ModuleEvent, ModuleEventType, ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleSpec, ModuleStatus, RestartPolicy,
 };
 
 // Time to allow EdgeAgent to gracefully shutdown (including stopping all modules, and updating reported properties)
@@ -26,6 +32,17 @@ const MODULE_GENERATIONID: &str = "IOTEDGE_MODULEGENERATIONID";
 /// This is the frequency with which the watchdog checks for the status of the edge runtime module.
 const WATCHDOG_FREQUENCY_SECS: u64 = 60;
 
+/// The delay before the first restart of a module that exited.
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between restarts of a module. A module that ran for at
+/// least this long before it exited is restarted as if for the first time.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// How much the delay before a restart is randomly scaled up or down, in per
+/// mille, so that modules that exit together aren't all restarted together.
+const RESTART_JITTER_PERMILLE: u64 = 100;
+
 pub struct Watchdog<M, I> {
     runtime: M,
     id_mgr: I,
@@ -150,8 +167,11 @@ where
         })
         .and_then(move |state| match state {
             Some(state) => {
-                let res = if *state.status() == ModuleStatus::Running {
-                    info!("Edge runtime is running.");
+                // A paused edge runtime is left alone until it is unpaused.
+                let res = if *state.status() == ModuleStatus::Running
+                    || *state.status() == ModuleStatus::Paused
+                {
+                    info!("Edge runtime is {}.", *state.status());
                     future::Either::A(future::ok(()))
                 } else {
                     info!(
@@ -251,6 +271,268 @@ where
     })
 }
 
+/// Settings of the module watchdog.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct WatchdogConfig {
+    /// How many times in a row a module is restarted before the watchdog
+    /// gives up on it. Unlimited when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_restart_attempts: Option<u32>,
+}
+
+impl WatchdogConfig {
+    pub fn new() -> Self {
+        WatchdogConfig::default()
+    }
+
+    pub fn max_restart_attempts(&self) -> Option<u32> {
+        self.max_restart_attempts
+    }
+
+    pub fn with_max_restart_attempts(mut self, max_restart_attempts: Option<u32>) -> Self {
+        self.max_restart_attempts = max_restart_attempts;
+        self
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == WatchdogConfig::default()
+    }
+}
+
+/// Restarts modules that exit according to their `RestartPolicy`, for
+/// module runtimes that don't restart modules themselves. Restarts of a
+/// module are delayed exponentially, from one second up to five minutes.
+///
+/// A module that is stopped on purpose isn't restarted, and neither is one
+/// that was started again by someone else in the meantime.
+#[derive(Clone)]
+pub struct ModuleWatchdog<M> {
+    runtime: M,
+    config: WatchdogConfig,
+    restarts: Arc<Mutex<Restarts>>,
+}
+
+impl<M> ModuleWatchdog<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    pub fn new(runtime: M, config: WatchdogConfig) -> Self {
+        ModuleWatchdog {
+            runtime,
+            config,
+            restarts: Arc::new(Mutex::new(Restarts::default())),
+        }
+    }
+
+    /// Watches module events until `shutdown_signal` resolves or the event
+    /// stream fails. Restarts that are still pending then are dropped.
+    pub fn run_until<F>(self, shutdown_signal: F) -> impl Future<Item = (), Error = Error>
+    where
+        F: Future<Item = (), Error = ()> + 'static,
+    {
+        info!("Starting module watchdog...");
+
+        let watchdog = self.clone();
+        let events = self
+            .runtime
+            .events()
+            .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+            .for_each(move |event| {
+                watchdog.on_event(&event);
+                Ok(())
+            });
+
+        let restarts = self.restarts.clone();
+        shutdown_signal
+            .then(|_| Ok(()))
+            .select(events)
+            .then(move |result| {
+                restarts.lock().unwrap().shut_down = true;
+                match result {
+                    Ok(((), _)) => Ok(()),
+                    Err((err, _)) => Err(err),
+                }
+            })
+    }
+
+    fn on_event(&self, event: &ModuleEvent) {
+        let name = event.module_name();
+        match event.event_type() {
+            ModuleEventType::Started => self.restarts.lock().unwrap().started(name, Instant::now()),
+            ModuleEventType::Stopped => self.restarts.lock().unwrap().stopped(name),
+            ModuleEventType::Died => {
+                let restart = self
+                    .restart(name.to_string(), Instant::now())
+                    .or_else(|err| {
+                        warn!("Error in module watchdog when restarting module:");
+                        log_failure(Level::Warn, &err);
+                        Ok(())
+                    });
+                tokio::spawn(restart);
+            }
+            ModuleEventType::OomKilled
+            | ModuleEventType::Promoted
+            | ModuleEventType::RolledBack
+            | ModuleEventType::Updated
+            | ModuleEventType::Paused
+            | ModuleEventType::Unpaused
+            | ModuleEventType::Renamed => (),
+        }
+    }
+
+    fn restart(
+        &self,
+        name: String,
+        died_at: Instant,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let watchdog = self.clone();
+        self.runtime
+            .get_config(&name)
+            .join(self.runtime.get(&name))
+            .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+            .and_then(move |(spec, (_, state))| {
+                let policy = spec.map_or_else(RestartPolicy::default, |spec| spec.restart_policy());
+                if !policy.should_restart(state.exit_code()) {
+                    return Either::A(future::ok(()));
+                }
+
+                match watchdog.next_restart(&name) {
+                    Some(delay) => {
+                        info!(
+                            "Module {} exited with {:?}, restarting it in {:?}",
+                            name,
+                            state.exit_code(),
+                            delay
+                        );
+                        Either::B(
+                            Delay::new(Instant::now() + delay)
+                                .map_err(|err| {
+                                    Error::from(err.context(ErrorKind::ModuleWatchdogTimer))
+                                })
+                                .and_then(move |()| watchdog.start(&name, died_at)),
+                        )
+                    }
+                    None => Either::A(future::ok(())),
+                }
+            })
+    }
+
+    fn next_restart(&self, name: &str) -> Option<Duration> {
+        let jitter = rand::thread_rng().gen_range(
+            1000 - RESTART_JITTER_PERMILLE,
+            1000 + RESTART_JITTER_PERMILLE + 1,
+        );
+        let max_attempts = self.config.max_restart_attempts();
+        let delay = self
+            .restarts
+            .lock()
+            .unwrap()
+            .next(name, Instant::now(), max_attempts, jitter);
+        if delay.is_none() {
+            warn!(
+                "Module {} was restarted {} times in a row, not restarting it again",
+                name,
+                max_attempts.unwrap_or_default()
+            );
+        }
+        delay
+    }
+
+    fn start(&self, name: &str, died_at: Instant) -> impl Future<Item = (), Error = Error> {
+        if self.restarts.lock().unwrap().needs_restart(name, died_at) {
+            info!("Restarting module {}", name);
+            Either::A(
+                self.runtime
+                    .start(name)
+                    .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime))),
+            )
+        } else {
+            debug!("Module {} was stopped or started since it exited", name);
+            Either::B(future::ok(()))
+        }
+    }
+}
+
+#[derive(Default)]
+struct RestartState {
+    attempts: u32,
+    started_at: Option<Instant>,
+    stopped: bool,
+}
+
+/// The restarts of every module the watchdog has seen an event of.
+#[derive(Default)]
+struct Restarts {
+    modules: HashMap<String, RestartState>,
+    shut_down: bool,
+}
+
+impl Restarts {
+    fn started(&mut self, name: &str, now: Instant) {
+        let state = self.modules.entry(name.to_string()).or_default();
+        state.started_at = Some(now);
+        state.stopped = false;
+    }
+
+    /// The module was stopped on purpose, so it isn't restarted, and its
+    /// restarts start over once it is started again.
+    fn stopped(&mut self, name: &str) {
+        let state = self.modules.entry(name.to_string()).or_default();
+        state.attempts = 0;
+        state.stopped = true;
+    }
+
+    /// Counts a restart of the module and returns the delay before it, or
+    /// `None` once the module was restarted `max_attempts` times in a row.
+    fn next(
+        &mut self,
+        name: &str,
+        now: Instant,
+        max_attempts: Option<u32>,
+        jitter: u64,
+    ) -> Option<Duration> {
+        let state = self.modules.entry(name.to_string()).or_default();
+        if state
+            .started_at
+            .map_or(false, |started_at| now - started_at >= MAX_RESTART_BACKOFF)
+        {
+            state.attempts = 0;
+        }
+        if max_attempts.map_or(false, |max_attempts| state.attempts >= max_attempts) {
+            return None;
+        }
+
+        let delay = backoff(state.attempts, jitter);
+        state.attempts += 1;
+        Some(delay)
+    }
+
+    /// Whether the module that exited at `died_at` still has to be restarted.
+    fn needs_restart(&self, name: &str, died_at: Instant) -> bool {
+        !self.shut_down
+            && self.modules.get(name).map_or(true, |state| {
+                !state.stopped
+                    && state
+                        .started_at
+                        .map_or(true, |started_at| started_at < died_at)
+            })
+    }
+}
+
+/// The delay before restart number `attempt`, counting from 0, which doubles
+/// from `INITIAL_RESTART_BACKOFF` up to `MAX_RESTART_BACKOFF`. The delay is
+/// then scaled by `jitter` per mille.
+fn backoff(attempt: u32, jitter: u64) -> Duration {
+    let delay = 2_u32
+        .checked_pow(attempt)
+        .and_then(|factor| INITIAL_RESTART_BACKOFF.checked_mul(factor))
+        .map_or(MAX_RESTART_BACKOFF, |delay| {
+            cmp::min(delay, MAX_RESTART_BACKOFF)
+        });
+    let millis = delay.as_secs() * 1000 + u64::from(delay.subsec_millis());
+    Duration::from_millis(millis * jitter / 1000)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -362,6 +644,8 @@ mod tests {
         type ListFuture = FutureResult<Vec<Self::Identity>, Self::Error>;
         type GetFuture = FutureResult<Option<Self::Identity>, Self::Error>;
         type DeleteFuture = FutureResult<(), Self::Error>;
+        type GetTwinFuture = FutureResult<Option<serde_json::Value>, Self::Error>;
+        type GetTwinDesiredFuture = FutureResult<serde_json::Value, Self::Error>;
 
         fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture {
             self.state.borrow_mut().gen_id_sentinel += 1;
@@ -437,6 +721,14 @@ mod tests {
                 .map(|index| self.state.borrow_mut().identities.remove(index))
                 .map_or_else(|| future::err(Error::ModuleNotFound), |_| future::ok(()))
         }
+
+        fn get_twin(&self, _id: IdentitySpec) -> Self::GetTwinFuture {
+            unimplemented!()
+        }
+
+        fn get_twin_desired(&self) -> Self::GetTwinDesiredFuture {
+            unimplemented!()
+        }
     }
 
     #[test]
@@ -495,4 +787,82 @@ mod tests {
                 .auth_type
         );
     }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(Duration::from_secs(1), backoff(0, 1000));
+        assert_eq!(Duration::from_secs(2), backoff(1, 1000));
+        assert_eq!(Duration::from_secs(256), backoff(8, 1000));
+        assert_eq!(MAX_RESTART_BACKOFF, backoff(9, 1000));
+        assert_eq!(MAX_RESTART_BACKOFF, backoff(64, 1000));
+    }
+
+    #[test]
+    fn backoff_is_scaled_by_jitter() {
+        assert_eq!(Duration::from_millis(900), backoff(0, 900));
+        assert_eq!(Duration::from_millis(1100), backoff(0, 1100));
+        assert_eq!(Duration::from_secs(330), backoff(10, 1100));
+    }
+
+    #[test]
+    fn restarts_are_capped() {
+        let mut restarts = Restarts::default();
+        let now = Instant::now();
+
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            restarts.next("mod1", now, Some(2), 1000)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(2)),
+            restarts.next("mod1", now, Some(2), 1000)
+        );
+        assert_eq!(None, restarts.next("mod1", now, Some(2), 1000));
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            restarts.next("mod2", now, Some(2), 1000)
+        );
+    }
+
+    #[test]
+    fn restarts_start_over_after_stop_or_long_run() {
+        let mut restarts = Restarts::default();
+        let now = Instant::now();
+        restarts.next("mod1", now, None, 1000);
+        restarts.next("mod1", now, None, 1000);
+
+        restarts.stopped("mod1");
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            restarts.next("mod1", now, None, 1000)
+        );
+
+        restarts.started("mod1", now);
+        assert_eq!(
+            Some(Duration::from_secs(2)),
+            restarts.next("mod1", now + Duration::from_secs(10), None, 1000)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            restarts.next("mod1", now + MAX_RESTART_BACKOFF, None, 1000)
+        );
+    }
+
+    #[test]
+    fn stopped_or_started_module_does_not_need_restart() {
+        let mut restarts = Restarts::default();
+        let now = Instant::now();
+        assert!(restarts.needs_restart("mod1", now));
+
+        restarts.started("mod1", now);
+        assert!(restarts.needs_restart("mod1", now + Duration::from_secs(1)));
+        assert!(!restarts.needs_restart("mod1", now - Duration::from_secs(1)));
+
+        restarts.stopped("mod1");
+        assert!(!restarts.needs_restart("mod1", now + Duration::from_secs(1)));
+
+        restarts.started("mod2", now);
+        restarts.shut_down = true;
+        assert!(!restarts.needs_restart("mod2", now + Duration::from_secs(1)));
+    }
 }
@@ -0,0 +1,590 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use failure::Fail;
+use futures::{Async, Future, Poll, Stream};
+use log::warn;
+
+use edgelet_core::{
+    Capabilities, CircuitState, LogOptions, ModuleRuntime, ModuleSpec, RegistryOperation,
+    ResourceLimits, RuntimeOperation,
+};
+
+use crate::error::{Error, ErrorKind};
+
+/// How many calls in a row have to fail before the circuit opens.
+const FAILURE_THRESHOLD: u32 = 5;
+
+/// How long an open circuit fails calls before letting one through again.
+const RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// Wraps a runtime so that calls fail right away with `CircuitOpen` once the
+/// Docker daemon has stopped answering, instead of each waiting for the
+/// socket to time out.
+///
+/// The circuit opens after five calls in a row couldn't reach the daemon.
+/// Ten seconds later one call is let through as a probe, and the circuit
+/// closes again once a call reaches the daemon. Calls the daemon answered
+/// with an error, like a module that doesn't exist, don't count as failures.
+#[derive(Clone)]
+pub struct CircuitBreaker<R> {
+    runtime: R,
+    circuit: Circuit,
+}
+
+impl<R> CircuitBreaker<R> {
+    pub fn new(runtime: R) -> Self {
+        CircuitBreaker {
+            runtime,
+            circuit: Circuit::new(FAILURE_THRESHOLD, RETRY_AFTER),
+        }
+    }
+
+    fn call<F>(&self, context: ErrorKind, call: impl FnOnce(&R) -> F) -> Guarded<F> {
+        if self.circuit.acquire(Instant::now()) {
+            Guarded(GuardedState::Called(
+                call(&self.runtime),
+                self.circuit.clone(),
+            ))
+        } else {
+            Guarded(GuardedState::Rejected(Some(Error::from(
+                ErrorKind::CircuitOpen.context(context),
+            ))))
+        }
+    }
+
+    fn call_stream<S>(&self, context: ErrorKind, call: impl FnOnce(&R) -> S) -> GuardedStream<S> {
+        GuardedStream(self.call(context, call).0)
+    }
+}
+
+impl<R> ModuleRuntime for CircuitBreaker<R>
+where
+    R: ModuleRuntime<Error = Error>,
+{
+    type Error = Error;
+    type Config = R::Config;
+    type Module = R::Module;
+    type ModuleRegistry = R::ModuleRegistry;
+    type Chunk = R::Chunk;
+    type Logs = R::Logs;
+    type RegistryAuth = R::RegistryAuth;
+
+    type CreateFuture = Guarded<R::CreateFuture>;
+    type GetFuture = Guarded<R::GetFuture>;
+    type InitFuture = Guarded<R::InitFuture>;
+    type ListFuture = Guarded<R::ListFuture>;
+    type ListWithDetailsStream = GuardedStream<R::ListWithDetailsStream>;
+    type LogsFuture = Guarded<R::LogsFuture>;
+    type RemoveFuture = Guarded<R::RemoveFuture>;
+    type RestartFuture = Guarded<R::RestartFuture>;
+    type StartFuture = Guarded<R::StartFuture>;
+    type StopFuture = Guarded<R::StopFuture>;
+    type SystemInfoFuture = Guarded<R::SystemInfoFuture>;
+    type SystemResourcesFuture = Guarded<R::SystemResourcesFuture>;
+    type RemoveAllFuture = Guarded<R::RemoveAllFuture>;
+    type TopFuture = Guarded<R::TopFuture>;
+    type StatsFuture = Guarded<R::StatsFuture>;
+    type InspectFuture = Guarded<R::InspectFuture>;
+    type EnvFuture = Guarded<R::EnvFuture>;
+    type UpdateEnvFuture = Guarded<R::UpdateEnvFuture>;
+    type UpdateResourcesFuture = Guarded<R::UpdateResourcesFuture>;
+    type UpsertFuture = Guarded<R::UpsertFuture>;
+    type PingFuture = Guarded<R::PingFuture>;
+    type EventStream = GuardedStream<R::EventStream>;
+    type GetConfigFuture = Guarded<R::GetConfigFuture>;
+    type ExecFuture = Guarded<R::ExecFuture>;
+    type PullImageStream = R::PullImageStream;
+    type PullImageFuture = Guarded<R::PullImageFuture>;
+    type PruneImagesFuture = Guarded<R::PruneImagesFuture>;
+    type SignalFuture = Guarded<R::SignalFuture>;
+    type PortsFuture = Guarded<R::PortsFuture>;
+    type WaitUntilStoppedFuture = Guarded<R::WaitUntilStoppedFuture>;
+    type ConnectivityStatusFuture = R::ConnectivityStatusFuture;
+    type CheckpointFuture = Guarded<R::CheckpointFuture>;
+    type RestoreFuture = Guarded<R::RestoreFuture>;
+    type ListCheckpointsFuture = Guarded<R::ListCheckpointsFuture>;
+    type VolumesFuture = Guarded<R::VolumesFuture>;
+    type SnapshotFuture = Guarded<R::SnapshotFuture>;
+    type StartAllFuture = R::StartAllFuture;
+    type SetBandwidthFuture = Guarded<R::SetBandwidthFuture>;
+    type PauseFuture = Guarded<R::PauseFuture>;
+    type UnpauseFuture = Guarded<R::UnpauseFuture>;
+    type CopyToContainerFuture = Guarded<R::CopyToContainerFuture>;
+    type RenameFuture = Guarded<R::RenameFuture>;
+
+    fn init(&self) -> Self::InitFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::Init);
+        self.call(context, R::init)
+    }
+
+    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(module.name().to_string()));
+        self.call(context, |runtime| runtime.create(module))
+    }
+
+    fn get(&self, id: &str) -> Self::GetFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.to_string()));
+        self.call(context, |runtime| runtime.get(id))
+    }
+
+    fn start(&self, id: &str) -> Self::StartFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id.to_string()));
+        self.call(context, |runtime| runtime.start(id))
+    }
+
+    fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id.to_string()));
+        self.call(context, |runtime| runtime.stop(id, wait_before_kill))
+    }
+
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(id.to_string()));
+        self.call(context, |runtime| runtime.restart(id))
+    }
+
+    fn remove(&self, id: &str) -> Self::RemoveFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id.to_string()));
+        self.call(context, |runtime| runtime.remove(id))
+    }
+
+    fn system_info(&self) -> Self::SystemInfoFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo);
+        self.call(context, R::system_info)
+    }
+
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources);
+        self.call(context, R::system_resources)
+    }
+
+    fn list(&self) -> Self::ListFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::ListModules);
+        self.call(context, R::list)
+    }
+
+    fn list_with_details(&self) -> Self::ListWithDetailsStream {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::ListModules);
+        self.call_stream(context, R::list_with_details)
+    }
+
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(id.to_string()));
+        self.call(context, |runtime| runtime.logs(id, options))
+    }
+
+    fn registry(&self) -> &Self::ModuleRegistry {
+        self.runtime.registry()
+    }
+
+    fn remove_all(&self) -> Self::RemoveAllFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::ListModules);
+        self.call(context, R::remove_all)
+    }
+
+    fn top(&self, id: &str, ps_args: Option<&str>) -> Self::TopFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(id.to_string()));
+        self.call(context, |runtime| runtime.top(id, ps_args))
+    }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id.to_string()));
+        self.call(context, |runtime| runtime.stats(id))
+    }
+
+    fn inspect(&self, id: &str) -> Self::InspectFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::InspectModule(id.to_string()));
+        self.call(context, |runtime| runtime.inspect(id))
+    }
+
+    fn env(&self, id: &str) -> Self::EnvFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleEnv(id.to_string()));
+        self.call(context, |runtime| runtime.env(id))
+    }
+
+    fn update_env(&self, id: &str, env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::UpdateModuleEnv(id.to_string()));
+        self.call(context, |runtime| runtime.update_env(id, env))
+    }
+
+    fn update_resources(&self, id: &str, limits: ResourceLimits) -> Self::UpdateResourcesFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::UpdateModuleResources(id.to_string()));
+        self.call(context, |runtime| runtime.update_resources(id, limits))
+    }
+
+    fn upsert(&self, module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::UpsertModule(module.name().to_string()));
+        self.call(context, |runtime| runtime.upsert(module))
+    }
+
+    fn ping(&self) -> Self::PingFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::Ping);
+        self.call(context, R::ping)
+    }
+
+    fn events(&self) -> Self::EventStream {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::Events);
+        self.call_stream(context, R::events)
+    }
+
+    fn get_config(&self, id: &str) -> Self::GetConfigFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(id.to_string()));
+        self.call(context, |runtime| runtime.get_config(id))
+    }
+
+    fn exec(&self, id: &str, cmd: &[String], tty: bool) -> Self::ExecFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id.to_string()));
+        self.call(context, |runtime| runtime.exec(id, cmd, tty))
+    }
+
+    fn pull_image(&self, image: &str, auth: Option<Self::RegistryAuth>) -> Self::PullImageFuture {
+        let context = ErrorKind::RegistryOperation(RegistryOperation::PullImage(image.to_string()));
+        self.call(context, |runtime| runtime.pull_image(image, auth))
+    }
+
+    fn prune_images(&self, dangling_only: bool) -> Self::PruneImagesFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages);
+        self.call(context, |runtime| runtime.prune_images(dangling_only))
+    }
+
+    fn signal(&self, id: &str, signal: u32) -> Self::SignalFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::SignalModule(id.to_string()));
+        self.call(context, |runtime| runtime.signal(id, signal))
+    }
+
+    fn get_ports(&self, id: &str) -> Self::PortsFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::GetModulePorts(id.to_string()));
+        self.call(context, |runtime| runtime.get_ports(id))
+    }
+
+    fn wait_until_stopped(&self, id: &str, timeout: Duration) -> Self::WaitUntilStoppedFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::WaitUntilStopped(id.to_string()));
+        self.call(context, |runtime| runtime.wait_until_stopped(id, timeout))
+    }
+
+    fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+        // The connectivity to IoT Hub is recorded rather than asked of the
+        // daemon, so it's reported even while the circuit is open.
+        self.runtime.connectivity_status()
+    }
+
+    fn checkpoint(&self, id: &str, checkpoint_id: &str) -> Self::CheckpointFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::CheckpointModule(id.to_string()));
+        self.call(context, |runtime| runtime.checkpoint(id, checkpoint_id))
+    }
+
+    fn restore(&self, id: &str, checkpoint_id: &str) -> Self::RestoreFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::RestoreModule(id.to_string()));
+        self.call(context, |runtime| runtime.restore(id, checkpoint_id))
+    }
+
+    fn list_checkpoints(&self, id: &str) -> Self::ListCheckpointsFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::ListModuleCheckpoints(id.to_string()));
+        self.call(context, |runtime| runtime.list_checkpoints(id))
+    }
+
+    fn get_volumes(&self, id: &str) -> Self::VolumesFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleVolumes(id.to_string()));
+        self.call(context, |runtime| runtime.get_volumes(id))
+    }
+
+    fn snapshot(&self) -> Self::SnapshotFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::Snapshot);
+        self.call(context, R::snapshot)
+    }
+
+    // Not guarded: modules that fail to start are reported in the results
+    // rather than as an error of the call, so the circuit would learn
+    // nothing from it.
+    fn start_all(&self, specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+        self.runtime.start_all(specs)
+    }
+
+    fn set_bandwidth(
+        &self,
+        id: &str,
+        ingress_kbps: u32,
+        egress_kbps: u32,
+    ) -> Self::SetBandwidthFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::SetModuleBandwidth(id.to_string()));
+        self.call(context, |runtime| {
+            runtime.set_bandwidth(id, ingress_kbps, egress_kbps)
+        })
+    }
+
+    fn pause(&self, id: &str) -> Self::PauseFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::PauseModule(id.to_string()));
+        self.call(context, |runtime| runtime.pause(id))
+    }
+
+    fn unpause(&self, id: &str) -> Self::UnpauseFuture {
+        let context = ErrorKind::RuntimeOperation(RuntimeOperation::UnpauseModule(id.to_string()));
+        self.call(context, |runtime| runtime.unpause(id))
+    }
+
+    fn copy_to_container(
+        &self,
+        id: &str,
+        dest_path: &str,
+        content: Bytes,
+    ) -> Self::CopyToContainerFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::CopyFileToModule(id.to_string()));
+        self.call(context, |runtime| {
+            runtime.copy_to_container(id, dest_path, content)
+        })
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> Self::RenameFuture {
+        let context =
+            ErrorKind::RuntimeOperation(RuntimeOperation::RenameModule(old_name.to_string()));
+        self.call(context, |runtime| runtime.rename(old_name, new_name))
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        self.circuit.state()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.runtime.capabilities()
+    }
+}
+
+/// A call made through the circuit breaker, which records its outcome once
+/// it completes.
+pub struct Guarded<F>(GuardedState<F>);
+
+enum GuardedState<F> {
+    Rejected(Option<Error>),
+    Called(F, Circuit),
+}
+
+impl<F> Future for Guarded<F>
+where
+    F: Future<Error = Error>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match &mut self.0 {
+            GuardedState::Rejected(err) => Err(err.take().expect("future polled after completion")),
+            GuardedState::Called(inner, circuit) => {
+                let result = inner.poll();
+                match &result {
+                    Ok(Async::NotReady) => (),
+                    Ok(Async::Ready(_)) => circuit.record(true, Instant::now()),
+                    Err(err) => circuit.record(!is_unreachable(err), Instant::now()),
+                }
+                result
+            }
+        }
+    }
+}
+
+/// A stream read through the circuit breaker. Each item read counts as a
+/// success, and the error the stream fails with, if any, as a failure.
+pub struct GuardedStream<S>(GuardedState<S>);
+
+impl<S> Stream for GuardedStream<S>
+where
+    S: Stream<Error = Error>,
+{
+    type Item = S::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match &mut self.0 {
+            GuardedState::Rejected(err) => match err.take() {
+                Some(err) => Err(err),
+                None => Ok(Async::Ready(None)),
+            },
+            GuardedState::Called(inner, circuit) => {
+                let result = inner.poll();
+                match &result {
+                    Ok(Async::NotReady) | Ok(Async::Ready(None)) => (),
+                    Ok(Async::Ready(Some(_))) => circuit.record(true, Instant::now()),
+                    Err(err) => circuit.record(!is_unreachable(err), Instant::now()),
+                }
+                result
+            }
+        }
+    }
+}
+
+/// Whether the call failed because the daemon couldn't be reached, as
+/// opposed to the daemon answering with an error.
+fn is_unreachable(err: &Error) -> bool {
+    Fail::iter_causes(err).any(|cause| cause.downcast_ref::<hyper::Error>().is_some())
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { since: Instant },
+    HalfOpen { probe_since: Instant },
+}
+
+#[derive(Clone)]
+struct Circuit {
+    failure_threshold: u32,
+    retry_after: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl Circuit {
+    fn new(failure_threshold: u32, retry_after: Duration) -> Self {
+        Circuit {
+            failure_threshold,
+            retry_after,
+            state: Arc::new(Mutex::new(State::Closed { failures: 0 })),
+        }
+    }
+
+    /// Whether a call may go through to the runtime at `now`. Once an open
+    /// circuit has waited `retry_after`, a single call is let through. If
+    /// that probe never completes, another is let through after as long.
+    fn acquire(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().expect("circuit lock poisoned");
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { since } | State::HalfOpen { probe_since: since }
+                if now.duration_since(since) >= self.retry_after =>
+            {
+                *state = State::HalfOpen { probe_since: now };
+                true
+            }
+            State::Open { .. } | State::HalfOpen { .. } => false,
+        }
+    }
+
+    /// Records whether a call could reach the runtime.
+    fn record(&self, reached: bool, now: Instant) {
+        let mut state = self.state.lock().expect("circuit lock poisoned");
+        *state = match (*state, reached) {
+            (_, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (State::Closed { .. }, false) => {
+                warn!(
+                    "Container runtime failed {} times in a row, failing calls to it for {:?}",
+                    self.failure_threshold, self.retry_after
+                );
+                State::Open { since: now }
+            }
+            (State::Open { .. }, false) | (State::HalfOpen { .. }, false) => {
+                State::Open { since: now }
+            }
+        };
+    }
+
+    fn state(&self) -> CircuitState {
+        match *self.state.lock().expect("circuit lock poisoned") {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circuit() -> Circuit {
+        Circuit::new(FAILURE_THRESHOLD, RETRY_AFTER)
+    }
+
+    #[test]
+    fn opens_after_threshold_failures() {
+        let circuit = circuit();
+        let now = Instant::now();
+
+        for _ in 0..FAILURE_THRESHOLD - 1 {
+            assert!(circuit.acquire(now));
+            circuit.record(false, now);
+        }
+        assert_eq!(CircuitState::Closed, circuit.state());
+
+        circuit.record(false, now);
+        assert_eq!(CircuitState::Open, circuit.state());
+        assert!(!circuit.acquire(now + Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let circuit = circuit();
+        let now = Instant::now();
+
+        for _ in 0..FAILURE_THRESHOLD - 1 {
+            circuit.record(false, now);
+        }
+        circuit.record(true, now);
+        circuit.record(false, now);
+
+        assert_eq!(CircuitState::Closed, circuit.state());
+    }
+
+    #[test]
+    fn half_open_lets_one_probe_through() {
+        let circuit = circuit();
+        let now = Instant::now();
+        for _ in 0..FAILURE_THRESHOLD {
+            circuit.record(false, now);
+        }
+
+        let later = now + RETRY_AFTER;
+        assert!(circuit.acquire(later));
+        assert_eq!(CircuitState::HalfOpen, circuit.state());
+        assert!(!circuit.acquire(later));
+
+        circuit.record(true, later);
+        assert_eq!(CircuitState::Closed, circuit.state());
+        assert!(circuit.acquire(later));
+    }
+
+    #[test]
+    fn failed_probe_reopens() {
+        let circuit = circuit();
+        let now = Instant::now();
+        for _ in 0..FAILURE_THRESHOLD {
+            circuit.record(false, now);
+        }
+
+        let later = now + RETRY_AFTER;
+        assert!(circuit.acquire(later));
+        circuit.record(false, later);
+
+        assert_eq!(CircuitState::Open, circuit.state());
+        assert!(!circuit.acquire(later + Duration::from_secs(9)));
+        assert!(circuit.acquire(later + RETRY_AFTER));
+    }
+
+    #[test]
+    fn errors_from_the_daemon_are_not_unreachable() {
+        let err = Error::from(
+            ErrorKind::NotFound("No such container: m1".to_string()).context(
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule("m1".to_string())),
+            ),
+        );
+
+        assert!(!is_unreachable(&err));
+    }
+}
@@ -1,6 +1,9 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::fmt;
+
 use failure::ResultExt;
+use serde::de::{self, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 
 use docker::models::{AuthConfig, ContainerCreateBody};
@@ -19,6 +22,27 @@ pub struct DockerConfig {
     create_options: ContainerCreateBody,
     #[serde(skip_serializing_if = "Option::is_none")]
     auth: Option<AuthConfig>,
+    #[serde(default, skip_serializing)]
+    registry_auth: Option<RegistryAuth>,
+    /// The network to join instead of the default one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network_name: Option<String>,
+    /// Names other modules on the network can reach the module by. They stay
+    /// the same when the container is recreated.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    network_aliases: Vec<String>,
+    /// How much log data the container keeps before the oldest is dropped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_config: Option<DockerLogConfig>,
+    /// The cgroup the container's cgroup is created under, like a systemd
+    /// slice, so that the resource usage of modules can be accounted for
+    /// together.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_cgroup_parent",
+        skip_serializing_if = "Option::is_none"
+    )]
+    cgroup_parent: Option<String>,
 }
 
 impl DockerConfig {
@@ -34,6 +58,11 @@ impl DockerConfig {
             image_id: None,
             create_options,
             auth,
+            registry_auth: None,
+            network_name: None,
+            network_aliases: Vec::new(),
+            log_config: None,
+            cgroup_parent: None,
         };
         Ok(config)
     }
@@ -81,6 +110,224 @@ impl DockerConfig {
         self.auth = Some(auth);
         self
     }
+
+    pub fn registry_auth(&self) -> Option<&RegistryAuth> {
+        self.registry_auth.as_ref()
+    }
+
+    pub fn with_registry_auth(mut self, registry_auth: RegistryAuth) -> Self {
+        self.registry_auth = Some(registry_auth);
+        self
+    }
+
+    pub fn network_name(&self) -> Option<&str> {
+        self.network_name.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_network_name(mut self, network_name: String) -> Self {
+        self.network_name = Some(network_name);
+        self
+    }
+
+    pub fn network_aliases(&self) -> &[String] {
+        &self.network_aliases
+    }
+
+    pub fn with_network_aliases(mut self, network_aliases: Vec<String>) -> Self {
+        self.network_aliases = network_aliases;
+        self
+    }
+
+    pub fn log_config(&self) -> Option<&DockerLogConfig> {
+        self.log_config.as_ref()
+    }
+
+    pub fn with_log_config(mut self, log_config: DockerLogConfig) -> Self {
+        self.log_config = Some(log_config);
+        self
+    }
+
+    pub fn cgroup_parent(&self) -> Option<&str> {
+        self.cgroup_parent.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_cgroup_parent(mut self, cgroup_parent: String) -> Result<Self> {
+        if !is_cgroup_path(&cgroup_parent) {
+            return Err(ErrorKind::InvalidCgroupParent(cgroup_parent).into());
+        }
+
+        self.cgroup_parent = Some(cgroup_parent);
+        Ok(self)
+    }
+
+    /// The credentials to pull the image with. `registry_auth` takes
+    /// precedence over `auth`.
+    pub fn pull_auth(&self) -> Option<AuthConfig> {
+        self.registry_auth
+            .as_ref()
+            .map(RegistryAuth::to_auth_config)
+            .or_else(|| self.auth.clone())
+    }
+
+    /// The config without its registry credentials, for storing it where
+    /// anyone who can inspect the container can read it.
+    pub fn without_auth(mut self) -> Self {
+        self.auth = None;
+        self.registry_auth = None;
+        self
+    }
+}
+
+/// Log retention of a module, applied as options of the `json-file` log
+/// driver. `max_size` is a size in docker's format, like `10m`, and
+/// `max_file` the number of rotated files to keep.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DockerLogConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_file: Option<u32>,
+}
+
+impl DockerLogConfig {
+    pub fn new() -> Self {
+        DockerLogConfig::default()
+    }
+
+    pub fn max_size(&self) -> Option<&str> {
+        self.max_size.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_max_size(mut self, max_size: String) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn max_file(&self) -> Option<u32> {
+        self.max_file
+    }
+
+    pub fn with_max_file(mut self, max_file: u32) -> Self {
+        self.max_file = Some(max_file);
+        self
+    }
+}
+
+/// Credentials for the registry that a single module's image is pulled from.
+///
+/// These are accepted in module specs but never serialized, so they don't
+/// appear in API responses or in the specs stored with containers, and their
+/// `Debug` output leaves out the password.
+#[derive(Clone, PartialEq)]
+pub struct RegistryAuth {
+    server: String,
+    username: String,
+    password: String,
+}
+
+impl RegistryAuth {
+    /// `server` has to be a hostname, optionally with a port.
+    pub fn new(server: String, username: String, password: String) -> Result<Self> {
+        if !is_registry_server(&server) {
+            return Err(ErrorKind::InvalidRegistryServer(server).into());
+        }
+
+        Ok(RegistryAuth {
+            server,
+            username,
+            password,
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    pub(crate) fn to_auth_config(&self) -> AuthConfig {
+        AuthConfig::new()
+            .with_serveraddress(self.server.clone())
+            .with_username(self.username.clone())
+            .with_password(self.password.clone())
+    }
+}
+
+impl fmt::Debug for RegistryAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryAuth")
+            .field("server", &self.server)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for RegistryAuth {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Inner {
+            server: String,
+            username: String,
+            password: String,
+        }
+
+        let inner: Inner = serde::Deserialize::deserialize(deserializer)?;
+        RegistryAuth::new(inner.server, inner.username, inner.password).map_err(de::Error::custom)
+    }
+}
+
+fn deserialize_cgroup_parent<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let cgroup_parent: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    match cgroup_parent {
+        Some(path) if !is_cgroup_path(&path) => {
+            Err(de::Error::custom(ErrorKind::InvalidCgroupParent(path)))
+        }
+        cgroup_parent => Ok(cgroup_parent),
+    }
+}
+
+/// A cgroup parent has to be absolute, since docker would otherwise take it
+/// relative to the cgroup of its own init process, and can't climb out of
+/// the hierarchy with `..`.
+pub fn is_cgroup_path(path: &str) -> bool {
+    path.starts_with('/') && path.split('/').all(|segment| segment != "..")
+}
+
+fn is_registry_server(server: &str) -> bool {
+    let mut parts = server.rsplitn(2, ':');
+    let (host, port) = match (parts.next(), parts.next()) {
+        (Some(port), Some(host)) => (host, Some(port)),
+        (Some(host), None) => (host, None),
+        _ => return false,
+    };
+
+    let valid_port = port.map_or(true, |port| port.parse::<u16>().is_ok());
+    let valid_host = !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    valid_port && valid_host
 }
 
 #[cfg(test)]
@@ -202,6 +449,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn docker_config_deser_registry_auth() {
+        let input_json = json!({
+            "image": "repo.azurecr.io/ubuntu",
+            "registryAuth": {
+                "server": "repo.azurecr.io:5000",
+                "username": "username",
+                "password": "password"
+            }
+        });
+
+        let config = serde_json::from_value::<DockerConfig>(input_json).unwrap();
+        let registry_auth = config.registry_auth().unwrap();
+        assert_eq!("repo.azurecr.io:5000", registry_auth.server());
+        assert_eq!("username", registry_auth.username());
+        assert_eq!("password", registry_auth.password());
+
+        let auth = config.pull_auth().unwrap();
+        assert_eq!("repo.azurecr.io:5000", auth.serveraddress().unwrap());
+        assert_eq!("password", auth.password().unwrap());
+    }
+
+    #[test]
+    fn docker_config_registry_auth_is_not_serialized() {
+        let registry_auth = RegistryAuth::new(
+            "repo.azurecr.io".to_string(),
+            "username".to_string(),
+            "s3cr3t".to_string(),
+        )
+        .unwrap();
+        let config = DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None)
+            .unwrap()
+            .with_registry_auth(registry_auth);
+
+        let actual_json = serde_json::to_value(&config).unwrap();
+        assert_eq!(
+            json!({ "image": "ubuntu", "createOptions": {} }),
+            actual_json
+        );
+        assert!(!format!("{:?}", config).contains("s3cr3t"));
+        assert!(config.without_auth().pull_auth().is_none());
+    }
+
+    #[test]
+    fn docker_config_deser_rejects_invalid_registry_server() {
+        for server in &[
+            "",
+            "https://repo.azurecr.io",
+            "repo.azurecr.io/ns",
+            "repo:port",
+            "-repo",
+        ] {
+            let input_json = json!({
+                "image": "ubuntu",
+                "registryAuth": {
+                    "server": server,
+                    "username": "username",
+                    "password": "password"
+                }
+            });
+            assert!(
+                serde_json::from_value::<DockerConfig>(input_json).is_err(),
+                "{:?} was accepted",
+                server
+            );
+        }
+    }
+
     #[test]
     fn docker_config_deser_no_create_options() {
         let input_json = json!({
@@ -211,6 +526,88 @@ mod tests {
         assert_eq!(config.image, "ubuntu");
     }
 
+    #[test]
+    fn docker_config_network_round_trips() {
+        let input_json = json!({
+            "image": "ubuntu",
+            "createOptions": {},
+            "networkName": "sensors",
+            "networkAliases": ["temperature", "temp"]
+        });
+        let config = serde_json::from_value::<DockerConfig>(input_json.clone()).unwrap();
+        assert_eq!(Some("sensors"), config.network_name());
+        assert_eq!(
+            &["temperature".to_string(), "temp".to_string()],
+            config.network_aliases()
+        );
+        assert_eq!(input_json, serde_json::to_value(&config).unwrap());
+    }
+
+    #[test]
+    fn docker_config_log_config_round_trips() {
+        let input_json = json!({
+            "image": "ubuntu",
+            "createOptions": {},
+            "logConfig": { "maxSize": "10m", "maxFile": 5 }
+        });
+        let config = serde_json::from_value::<DockerConfig>(input_json.clone()).unwrap();
+        assert_eq!(
+            Some(
+                &DockerLogConfig::new()
+                    .with_max_size("10m".to_string())
+                    .with_max_file(5)
+            ),
+            config.log_config()
+        );
+        assert_eq!(input_json, serde_json::to_value(&config).unwrap());
+    }
+
+    #[test]
+    fn docker_config_cgroup_parent_round_trips() {
+        let input_json = json!({
+            "image": "ubuntu",
+            "createOptions": {},
+            "cgroupParent": "/iotedge.slice"
+        });
+        let config = serde_json::from_value::<DockerConfig>(input_json.clone()).unwrap();
+        assert_eq!(Some("/iotedge.slice"), config.cgroup_parent());
+        assert_eq!(input_json, serde_json::to_value(&config).unwrap());
+    }
+
+    #[test]
+    fn docker_config_rejects_invalid_cgroup_parent() {
+        for path in &["iotedge.slice", "/iotedge/../system.slice", ""] {
+            let input_json = json!({ "image": "ubuntu", "cgroupParent": path });
+            assert!(
+                serde_json::from_value::<DockerConfig>(input_json).is_err(),
+                "{} should be invalid",
+                path
+            );
+
+            let config =
+                DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None).unwrap();
+            assert!(config.with_cgroup_parent(path.to_string()).is_err());
+        }
+    }
+
+    #[test]
+    fn cgroup_paths() {
+        for path in &["/", "/iotedge.slice", "/iotedge.slice/modules..x", "/a/b"] {
+            assert!(is_cgroup_path(path), "{} should be valid", path);
+        }
+    }
+
+    #[test]
+    fn docker_config_network_defaults() {
+        let config = serde_json::from_value::<DockerConfig>(json!({ "image": "ubuntu" })).unwrap();
+        assert_eq!(None, config.network_name());
+        assert!(config.network_aliases().is_empty());
+        assert_eq!(
+            json!({ "image": "ubuntu", "createOptions": {} }),
+            serde_json::to_value(&config).unwrap()
+        );
+    }
+
     #[test]
     fn docker_config_deser_from_map() {
         let input_json = json!({
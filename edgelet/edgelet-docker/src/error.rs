@@ -2,6 +2,7 @@
 
 use std::fmt;
 use std::fmt::Display;
+use std::time::Duration;
 
 use failure::{Backtrace, Context, Fail};
 use hyper::StatusCode;
@@ -41,6 +42,22 @@ fn get_message(
 
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
+    /// The container runtime rejected the parameters of the request, with
+    /// its message.
+    #[fail(display = "{}", _0)]
+    BadParameter(String),
+
+    #[fail(display = "Bandwidth limits are only supported for Linux containers")]
+    BandwidthNotSupported,
+
+    #[fail(
+        display = "Container runtime does not support checkpoints; it needs CRIU and experimental features enabled"
+    )]
+    CheckpointNotSupported,
+
+    #[fail(display = "Container runtime is unreachable, so calls to it fail until it recovers")]
+    CircuitOpen,
+
     #[fail(display = "Could not clone create options")]
     CloneCreateOptions,
 
@@ -56,9 +73,18 @@ pub enum ErrorKind {
     #[fail(display = "{}", _0)]
     FormattedDockerRuntime(String),
 
+    #[fail(display = "Could not collect unused images")]
+    ImageGarbageCollection,
+
     #[fail(display = "Could not initialize module runtime")]
     Initialization,
 
+    #[fail(
+        display = "Invalid cgroup parent {:?}; it must be an absolute path without \"..\" segments",
+        _0
+    )]
+    InvalidCgroupParent(String),
+
     #[fail(display = "Invalid docker image {:?}", _0)]
     InvalidImage(String),
 
@@ -68,6 +94,21 @@ pub enum ErrorKind {
     #[fail(display = "Invalid module type {:?}", _0)]
     InvalidModuleType(String),
 
+    #[fail(display = "Invalid registry server {:?}", _0)]
+    InvalidRegistryServer(String),
+
+    #[fail(display = "Could not read log stream")]
+    LogStream,
+
+    #[fail(display = "Malformed log stream: {}", _0)]
+    MalformedLogs(String),
+
+    #[fail(display = "Malformed module spec stored with container {:?}", _0)]
+    MalformedSpecLabel(String),
+
+    #[fail(display = "No module spec is stored with container {:?}", _0)]
+    MissingSpecLabel(String),
+
     #[fail(display = "{}", _0)]
     ModuleOperation(ModuleOperation),
 
@@ -82,6 +123,13 @@ pub enum ErrorKind {
 
     #[fail(display = "{}", _0)]
     RuntimeOperation(RuntimeOperation),
+
+    #[fail(display = "Module did not stop within {:?}", _0)]
+    StopTimeout(Duration),
+
+    /// `tc` failed in the module's container, with its output.
+    #[fail(display = "Could not apply the bandwidth limits with tc: {}", _0)]
+    TrafficControl(String),
 }
 
 impl Fail for Error {
@@ -110,6 +158,10 @@ impl Error {
             DockerError::Hyper(error) => error.context(ErrorKind::Docker).context(context),
             DockerError::Serde(error) => error.context(ErrorKind::Docker).context(context),
             DockerError::Api(error) => match error.code {
+                StatusCode::BAD_REQUEST => match get_message(error) {
+                    Ok(message) => ErrorKind::BadParameter(message).context(context),
+                    Err(e) => ErrorKind::DockerRuntime(DockerError::Api(e)).context(context),
+                },
                 StatusCode::NOT_FOUND => match get_message(error) {
                     Ok(message) => ErrorKind::NotFound(message).context(context),
                     Err(e) => ErrorKind::DockerRuntime(DockerError::Api(e)).context(context),
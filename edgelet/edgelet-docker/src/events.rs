@@ -0,0 +1,196 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use failure::{Fail, ResultExt};
+use futures::{Async, Poll, Stream};
+use serde_json;
+
+use docker::models::InlineResponse20012 as DockerEvent;
+use edgelet_core::{ModuleEvent, ModuleEventType, RuntimeOperation};
+
+use crate::error::{Error, ErrorKind};
+
+/// The container actions that are reported as module events.
+pub const ACTIONS: &[&str] = &["start", "stop", "die", "oom", "pause", "unpause"];
+
+/// Turns the body of a Docker `/events` request into module events.
+///
+/// Docker writes one JSON object per line for as long as the request is
+/// open. Lines can be split across chunks of the body, so the trailing
+/// partial line is held back until the rest of it arrives. Events that don't
+/// map to a module event, such as ones for other actions, are skipped.
+pub struct ModuleEvents<S> {
+    inner: S,
+    pending: Vec<u8>,
+}
+
+impl<S> ModuleEvents<S> {
+    pub fn new(inner: S) -> Self {
+        ModuleEvents {
+            inner,
+            pending: Vec::new(),
+        }
+    }
+
+    fn next_event(&mut self) -> Result<Option<ModuleEvent>, Error> {
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let rest = self.pending.split_off(end + 1);
+            let line = std::mem::replace(&mut self.pending, rest);
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            let event: DockerEvent = serde_json::from_slice(&line)
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::Events))?;
+            if let Some(event) = to_module_event(&event) {
+                return Ok(Some(event));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<S> Stream for ModuleEvents<S>
+where
+    S: Stream,
+    S::Item: AsRef<[u8]>,
+    S::Error: Fail,
+{
+    type Item = ModuleEvent;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(event) = self.next_event()? {
+                return Ok(Async::Ready(Some(event)));
+            }
+
+            match self.inner.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(Some(chunk))) => self.pending.extend_from_slice(chunk.as_ref()),
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                Err(err) => {
+                    return Err(Error::from(
+                        err.context(ErrorKind::RuntimeOperation(RuntimeOperation::Events)),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn to_module_event(event: &DockerEvent) -> Option<ModuleEvent> {
+    if event._type().map_or(false, |type_| type_ != "container") {
+        return None;
+    }
+
+    let event_type = match event.action()? {
+        "start" => ModuleEventType::Started,
+        "stop" => ModuleEventType::Stopped,
+        "die" => ModuleEventType::Died,
+        "oom" => ModuleEventType::OomKilled,
+        "pause" => ModuleEventType::Paused,
+        "unpause" => ModuleEventType::Unpaused,
+        _ => return None,
+    };
+    let name = event.actor()?.attributes()?.get("name")?;
+
+    let timestamp = match (event.time_nano(), event.time()) {
+        // Docker never reports events from before the epoch, so the
+        // remainder is in [0, 1e9) and the cast can't truncate.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        (Some(nanos), _) => {
+            NaiveDateTime::from_timestamp_opt(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+        }
+        (None, Some(secs)) => NaiveDateTime::from_timestamp_opt(i64::from(secs), 0),
+        (None, None) => None,
+    }?;
+
+    Some(ModuleEvent::new(
+        event_type,
+        name.to_string(),
+        DateTime::from_utc(timestamp, Utc),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{stream, Future};
+    use hyper::Chunk;
+
+    use super::*;
+
+    fn events(chunks: Vec<&'static str>) -> Result<Vec<ModuleEvent>, Error> {
+        let chunks = chunks
+            .into_iter()
+            .map(|c| Ok::<_, hyper::Error>(Chunk::from(c)));
+        ModuleEvents::new(stream::iter_result(chunks))
+            .collect()
+            .wait()
+    }
+
+    #[test]
+    fn events_across_chunks() {
+        let events = events(vec![
+            r#"{"Type":"container","Action":"start","Actor":{"ID":"abc","Attributes":{"name":"mod1"}},"#,
+            r#""time":1548851696,"timeNano":1548851696123456789}"#,
+            "\n",
+            r#"{"Type":"container","Action":"oom","Actor":{"ID":"abc","Attributes":{"name":"mod1"}},"time":1548851697}"#,
+            "\n",
+        ])
+        .unwrap();
+
+        assert_eq!(2, events.len());
+        assert_eq!(ModuleEventType::Started, events[0].event_type());
+        assert_eq!("mod1", events[0].module_name());
+        assert_eq!(
+            "2019-01-30T12:34:56.123456789+00:00",
+            events[0].timestamp().to_rfc3339()
+        );
+        assert_eq!(ModuleEventType::OomKilled, events[1].event_type());
+        assert_eq!(
+            "2019-01-30T12:34:57+00:00",
+            events[1].timestamp().to_rfc3339()
+        );
+    }
+
+    #[test]
+    fn other_events_are_skipped() {
+        let events = events(vec![
+            r#"{"Type":"container","Action":"attach","Actor":{"Attributes":{"name":"mod1"}},"time":1}"#,
+            "\n",
+            r#"{"Type":"image","Action":"stop","Actor":{"Attributes":{"name":"mod1"}},"time":1}"#,
+            "\n",
+            r#"{"Type":"container","Action":"die","Actor":{"Attributes":{"name":"mod2"}},"time":1}"#,
+            "\n",
+        ])
+        .unwrap();
+
+        assert_eq!(1, events.len());
+        assert_eq!(ModuleEventType::Died, events[0].event_type());
+        assert_eq!("mod2", events[0].module_name());
+    }
+
+    #[test]
+    fn pause_and_unpause_are_reported() {
+        let events = events(vec![
+            r#"{"Type":"container","Action":"pause","Actor":{"Attributes":{"name":"mod1"}},"time":1}"#,
+            "\n",
+            r#"{"Type":"container","Action":"unpause","Actor":{"Attributes":{"name":"mod1"}},"time":2}"#,
+            "\n",
+        ])
+        .unwrap();
+
+        assert_eq!(2, events.len());
+        assert_eq!(ModuleEventType::Paused, events[0].event_type());
+        assert_eq!(ModuleEventType::Unpaused, events[1].event_type());
+    }
+
+    #[test]
+    fn invalid_event_is_an_error() {
+        let err = events(vec!["not json\n"]).unwrap_err();
+
+        assert_eq!("Could not watch module events", err.to_string());
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use failure::Fail;
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use log::{debug, info, Level};
+use tokio::timer::Interval;
+
+use edgelet_core::{GcPolicy, SharedGcPolicy};
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind};
+use crate::runtime::DockerModuleRuntime;
+
+/// How often the free disk space is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Removes unused module images while the disk holding the container
+/// runtime's data has less free space than the `GcPolicy` asks for. Only
+/// images older than the policy's maximum age that no container was created
+/// from are removed, so the images of stopped modules are kept.
+///
+/// The policy is read again on every check, so changes to it take effect
+/// without restarting the collector.
+pub struct ImageGarbageCollector {
+    runtime: DockerModuleRuntime,
+    policy: SharedGcPolicy,
+}
+
+impl ImageGarbageCollector {
+    pub fn new(runtime: DockerModuleRuntime, policy: SharedGcPolicy) -> Self {
+        ImageGarbageCollector { runtime, policy }
+    }
+
+    /// Checks the free disk space every minute until `shutdown_signal`
+    /// resolves. A check that fails is logged and doesn't stop the next.
+    pub fn run_until<F>(self, shutdown_signal: F) -> impl Future<Item = (), Error = Error>
+    where
+        F: Future<Item = (), Error = ()> + 'static,
+    {
+        info!("Starting image garbage collector...");
+
+        let checks = Interval::new(Instant::now() + POLL_INTERVAL, POLL_INTERVAL)
+            .map_err(|err| Error::from(err.context(ErrorKind::ImageGarbageCollection)))
+            .for_each(move |_| {
+                self.collect().or_else(|err| {
+                    log_failure(Level::Warn, &err);
+                    Ok(())
+                })
+            });
+
+        shutdown_signal
+            .then(|_| Ok(()))
+            .select(checks)
+            .then(|result| match result {
+                Ok(((), _)) => Ok(()),
+                Err((err, _)) => Err(err),
+            })
+    }
+
+    fn collect(&self) -> impl Future<Item = (), Error = Error> + Send {
+        let policy = self.policy.get();
+        if policy.min_free_bytes() == 0 {
+            return Either::A(future::ok(()));
+        }
+
+        let runtime = self.runtime.clone();
+        Either::B(
+            self.runtime
+                .free_disk_space()
+                .and_then(move |(mount_point, free_disk_bytes)| {
+                    if free_disk_bytes >= policy.min_free_bytes() {
+                        debug!(
+                            "{} has {} bytes free, not removing images",
+                            mount_point.display(),
+                            free_disk_bytes
+                        );
+                        return Either::A(future::ok(()));
+                    }
+
+                    info!(
+                        "{} has {} bytes free, less than the {} bytes of the image garbage collection policy; removing unused images older than {}s...",
+                        mount_point.display(),
+                        free_disk_bytes,
+                        policy.min_free_bytes(),
+                        policy.max_image_age_secs()
+                    );
+                    Either::B(
+                        runtime
+                            .prune_images_created_before(created_before(
+                                Utc::now().timestamp(),
+                                &policy,
+                            ))
+                            .map(|result| {
+                                for image in result.removed_images() {
+                                    info!("Garbage collected unused image {}", image);
+                                }
+                            }),
+                    )
+                }),
+        )
+    }
+}
+
+/// The creation time, in seconds since the Unix epoch, before which images
+/// are old enough to be removed under `policy` at `now`.
+#[allow(clippy::cast_possible_wrap)]
+fn created_before(now: i64, policy: &GcPolicy) -> i64 {
+    now.saturating_sub(policy.max_image_age_secs().min(i64::max_value() as u64) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_older_than_max_age_are_removed() {
+        assert_eq!(1000, created_before(4600, &GcPolicy::new(1, 3600)));
+        assert_eq!(4600, created_before(4600, &GcPolicy::new(1, 0)));
+        assert_eq!(
+            4600 - i64::max_value(),
+            created_before(4600, &GcPolicy::new(1, u64::max_value()))
+        );
+    }
+}
@@ -2,16 +2,30 @@
 
 #![deny(rust_2018_idioms, warnings)]
 #![deny(clippy::all, clippy::pedantic)]
-#![allow(clippy::module_name_repetitions, clippy::use_self)]
+#![allow(
+    clippy::doc_markdown, // clippy want the "IoT" of "IoT Hub" in a code fence
+    clippy::module_name_repetitions,
+    clippy::use_self,
+)]
 
+mod circuit;
 mod client;
 mod config;
 mod error;
+mod events;
+mod image_gc;
+mod logs;
 mod module;
+mod pull;
+mod resources;
 mod runtime;
 
-pub use config::DockerConfig;
+pub use circuit::{CircuitBreaker, Guarded, GuardedStream};
+pub use config::{is_cgroup_path, DockerConfig, DockerLogConfig, RegistryAuth};
 pub use error::{Error, ErrorKind};
+pub use image_gc::ImageGarbageCollector;
+pub use logs::{DockerFrames, DockerLogDemultiplexer, LogLine, LogSource};
 pub use module::{DockerModule, MODULE_TYPE};
+pub use pull::ImagePull;
 
 pub use runtime::DockerModuleRuntime;
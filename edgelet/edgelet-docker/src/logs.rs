@@ -0,0 +1,295 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::str;
+
+use chrono::{DateTime, Utc};
+use failure::Fail;
+use futures::{Async, Poll, Stream};
+
+use crate::error::{Error, ErrorKind};
+
+/// Size of the header that precedes every frame of a multiplexed log stream.
+const HEADER_LEN: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogSource {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LogLine {
+    source: LogSource,
+    timestamp: DateTime<Utc>,
+    message: String,
+}
+
+impl LogLine {
+    pub fn source(&self) -> LogSource {
+        self.source
+    }
+
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    /// The line without its trailing newline. Invalid UTF-8 is replaced
+    /// with U+FFFD.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Splits a multiplexed stream of a container into the payloads of its
+/// frames.
+///
+/// Docker multiplexes stdout and stderr of containers without a TTY into a
+/// single stream of frames, each made of an 8-byte header (the stream type
+/// and the big-endian length of the payload) followed by the payload.
+///
+/// Frames can be split across chunks of the inner stream, so incomplete
+/// frames are buffered until the rest arrives. A stream that ends in the
+/// middle of a frame yields an error.
+pub struct DockerFrames<S> {
+    inner: S,
+    pending: Vec<u8>,
+    done: bool,
+}
+
+impl<S> DockerFrames<S> {
+    pub fn new(inner: S) -> Self {
+        DockerFrames {
+            inner,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(LogSource, Vec<u8>)>, Error> {
+        if self.pending.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let len = u32::from_be_bytes([
+            self.pending[4],
+            self.pending[5],
+            self.pending[6],
+            self.pending[7],
+        ]) as usize;
+        if self.pending.len() < HEADER_LEN + len {
+            return Ok(None);
+        }
+
+        let source = match self.pending[0] {
+            0 => LogSource::Stdin,
+            1 => LogSource::Stdout,
+            2 => LogSource::Stderr,
+            stream => {
+                return Err(malformed(format!("unknown stream type {}", stream)));
+            }
+        };
+
+        let rest = self.pending.split_off(HEADER_LEN + len);
+        let mut frame = std::mem::replace(&mut self.pending, rest);
+        Ok(Some((source, frame.split_off(HEADER_LEN))))
+    }
+}
+
+impl<S> Stream for DockerFrames<S>
+where
+    S: Stream,
+    S::Item: AsRef<[u8]>,
+    S::Error: Fail,
+{
+    type Item = (LogSource, Vec<u8>);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return Ok(Async::Ready(Some(frame)));
+            }
+
+            if self.done {
+                if self.pending.is_empty() {
+                    return Ok(Async::Ready(None));
+                }
+
+                let reason = if self.pending.len() < HEADER_LEN {
+                    "truncated frame header"
+                } else {
+                    "truncated frame"
+                };
+                self.pending.clear();
+                return Err(malformed(reason.to_string()));
+            }
+
+            match self.inner.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(Some(chunk))) => self.pending.extend_from_slice(chunk.as_ref()),
+                Ok(Async::Ready(None)) => self.done = true,
+                Err(err) => return Err(Error::from(err.context(ErrorKind::LogStream))),
+            }
+        }
+    }
+}
+
+/// Splits the log stream of a container into lines.
+///
+/// The stream is split into frames like [`DockerFrames`] does. The logs have
+/// to be requested with timestamps, so that each payload starts with an
+/// RFC 3339 timestamp and a space.
+pub struct DockerLogDemultiplexer<S> {
+    frames: DockerFrames<S>,
+}
+
+impl<S> DockerLogDemultiplexer<S> {
+    pub fn new(inner: S) -> Self {
+        DockerLogDemultiplexer {
+            frames: DockerFrames::new(inner),
+        }
+    }
+}
+
+fn parse_payload(source: LogSource, payload: &[u8]) -> Result<LogLine, Error> {
+    let space = payload
+        .iter()
+        .position(|&b| b == b' ')
+        .ok_or_else(|| malformed("missing timestamp".to_string()))?;
+    let timestamp = str::from_utf8(&payload[..space])
+        .ok()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .ok_or_else(|| {
+            malformed(format!(
+                "invalid timestamp {:?}",
+                String::from_utf8_lossy(&payload[..space])
+            ))
+        })?;
+
+    let message = &payload[space + 1..];
+    let message = if message.ends_with(b"\n") {
+        &message[..message.len() - 1]
+    } else {
+        message
+    };
+
+    Ok(LogLine {
+        source,
+        timestamp: timestamp.with_timezone(&Utc),
+        message: String::from_utf8_lossy(message).into_owned(),
+    })
+}
+
+fn malformed(reason: String) -> Error {
+    Error::from(ErrorKind::MalformedLogs(reason))
+}
+
+impl<S> Stream for DockerLogDemultiplexer<S>
+where
+    S: Stream,
+    S::Item: AsRef<[u8]>,
+    S::Error: Fail,
+{
+    type Item = LogLine;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.frames.poll()? {
+            Async::Ready(Some((source, payload))) => {
+                parse_payload(source, &payload).map(|line| Async::Ready(Some(line)))
+            }
+            Async::Ready(None) => Ok(Async::Ready(None)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{stream, Future};
+    use hyper::Chunk;
+
+    use super::*;
+
+    fn frame(stream: u8, payload: &str) -> Vec<u8> {
+        #[allow(clippy::cast_possible_truncation)]
+        let len = payload.len() as u32;
+        let mut frame = vec![stream, 0, 0, 0];
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(payload.as_bytes());
+        frame
+    }
+
+    fn demux(chunks: Vec<Vec<u8>>) -> Result<Vec<LogLine>, Error> {
+        let chunks = chunks
+            .into_iter()
+            .map(|c| Ok::<_, hyper::Error>(Chunk::from(c)));
+        DockerLogDemultiplexer::new(stream::iter_result(chunks))
+            .collect()
+            .wait()
+    }
+
+    #[test]
+    fn frames_across_chunks() {
+        let mut bytes = frame(1, "2019-01-30T12:34:56.123456789Z hello\n");
+        bytes.extend(frame(2, "2019-01-30T13:34:56+01:00 world\n"));
+        let (first, second) = bytes.split_at(5);
+
+        let lines = demux(vec![first.to_vec(), second.to_vec()]).unwrap();
+
+        assert_eq!(2, lines.len());
+        assert_eq!(LogSource::Stdout, lines[0].source());
+        assert_eq!("hello", lines[0].message());
+        assert_eq!(
+            "2019-01-30T12:34:56.123456789+00:00",
+            lines[0].timestamp().to_rfc3339()
+        );
+        assert_eq!(LogSource::Stderr, lines[1].source());
+        assert_eq!("world", lines[1].message());
+        assert_eq!(
+            "2019-01-30T12:34:56+00:00",
+            lines[1].timestamp().to_rfc3339()
+        );
+    }
+
+    #[test]
+    fn truncated_header_is_an_error() {
+        let mut bytes = frame(1, "2019-01-30T12:34:56Z hello\n");
+        bytes.extend_from_slice(&[1, 0, 0]);
+
+        let err = demux(vec![bytes]).unwrap_err();
+
+        assert_eq!(
+            "Malformed log stream: truncated frame header",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn truncated_frame_is_an_error() {
+        let mut bytes = frame(1, "2019-01-30T12:34:56Z hello\n");
+        bytes.truncate(bytes.len() - 1);
+
+        let err = demux(vec![bytes]).unwrap_err();
+
+        assert_eq!("Malformed log stream: truncated frame", err.to_string());
+    }
+
+    #[test]
+    fn missing_timestamp_is_an_error() {
+        let err = demux(vec![frame(1, "hello\n")]).unwrap_err();
+
+        assert_eq!("Malformed log stream: missing timestamp", err.to_string());
+    }
+
+    #[test]
+    fn unknown_stream_type_is_an_error() {
+        let err = demux(vec![frame(7, "2019-01-30T12:34:56Z hello\n")]).unwrap_err();
+
+        assert_eq!(
+            "Malformed log stream: unknown stream type 7",
+            err.to_string()
+        );
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use chrono::prelude::*;
@@ -8,7 +9,7 @@ use hyper::client::connect::Connect;
 
 use edgelet_utils::ensure_not_empty_with_context;
 
-use docker::models::InlineResponse200State;
+use docker::models::InlineResponse200;
 use edgelet_core::pid::Pid;
 use edgelet_core::{Module, ModuleOperation, ModuleRuntimeState, ModuleStatus};
 
@@ -47,39 +48,46 @@ fn status_from_exit_code(exit_code: Option<i64>) -> Option<ModuleStatus> {
     })
 }
 
-pub fn runtime_state(
-    id: Option<&str>,
-    response_state: Option<&InlineResponse200State>,
-) -> ModuleRuntimeState {
-    response_state.map_or_else(ModuleRuntimeState::default, |state| {
-        let status = state
-            .status()
-            .and_then(|status| match status {
-                "created" | "paused" | "restarting" => Some(ModuleStatus::Stopped),
-                "removing" | "dead" | "exited" => status_from_exit_code(state.exit_code()),
-                "running" => Some(ModuleStatus::Running),
-                _ => Some(ModuleStatus::Unknown),
-            })
-            .unwrap_or_else(|| ModuleStatus::Unknown);
-        ModuleRuntimeState::default()
-            .with_status(status)
-            .with_exit_code(state.exit_code())
-            .with_status_description(state.status().map(ToOwned::to_owned))
-            .with_started_at(
-                state
-                    .started_at()
-                    .and_then(|d| if d == MIN_DATE { None } else { Some(d) })
-                    .and_then(|started_at| DateTime::from_str(started_at).ok()),
-            )
-            .with_finished_at(
-                state
-                    .finished_at()
-                    .and_then(|d| if d == MIN_DATE { None } else { Some(d) })
-                    .and_then(|finished_at| DateTime::from_str(finished_at).ok()),
-            )
-            .with_image_id(id.map(ToOwned::to_owned))
-            .with_pid(state.pid().map_or(Pid::None, Pid::Value))
-    })
+/// The runtime state of a container from its inspect response.
+#[allow(clippy::cast_sign_loss)]
+pub fn runtime_state(response: &InlineResponse200) -> ModuleRuntimeState {
+    response
+        .state()
+        .map_or_else(ModuleRuntimeState::default, |state| {
+            let status = state
+                .status()
+                .and_then(|status| match status {
+                    "created" | "restarting" => Some(ModuleStatus::Stopped),
+                    "paused" => Some(ModuleStatus::Paused),
+                    "removing" | "dead" | "exited" => status_from_exit_code(state.exit_code()),
+                    "running" => Some(ModuleStatus::Running),
+                    _ => Some(ModuleStatus::Unknown),
+                })
+                .unwrap_or_else(|| ModuleStatus::Unknown);
+            ModuleRuntimeState::default()
+                .with_status(status)
+                .with_exit_code(state.exit_code())
+                .with_status_description(state.status().map(ToOwned::to_owned))
+                .with_started_at(
+                    state
+                        .started_at()
+                        .and_then(|d| if d == MIN_DATE { None } else { Some(d) })
+                        .and_then(|started_at| DateTime::from_str(started_at).ok()),
+                )
+                .with_finished_at(
+                    state
+                        .finished_at()
+                        .and_then(|d| if d == MIN_DATE { None } else { Some(d) })
+                        .and_then(|finished_at| DateTime::from_str(finished_at).ok()),
+                )
+                .with_image_id(response.id().map(ToOwned::to_owned))
+                .with_pid(state.pid().map_or(Pid::None, Pid::Value))
+                .with_restart_count(
+                    response
+                        .restart_count()
+                        .map_or(0, |count| count.max(0) as u32),
+                )
+        })
 }
 
 impl<C: 'static + Connect> Module for DockerModule<C> {
@@ -100,12 +108,20 @@ impl<C: 'static + Connect> Module for DockerModule<C> {
         &self.config
     }
 
+    fn labels(&self) -> HashMap<String, String> {
+        self.config
+            .create_options()
+            .labels()
+            .cloned()
+            .unwrap_or_else(HashMap::new)
+    }
+
     fn runtime_state(&self) -> Self::RuntimeStateFuture {
         Box::new(
             self.client
                 .container_api()
                 .container_inspect(&self.name, false)
-                .map(|resp| runtime_state(resp.id(), resp.state()))
+                .map(|resp| runtime_state(&resp))
                 .map_err(|err| {
                     Error::from_docker_error(
                         err,
@@ -187,7 +203,7 @@ mod tests {
     fn get_inputs() -> Vec<(&'static str, i64, ModuleStatus)> {
         vec![
             ("created", 0, ModuleStatus::Stopped),
-            ("paused", 0, ModuleStatus::Stopped),
+            ("paused", 0, ModuleStatus::Paused),
             ("restarting", 0, ModuleStatus::Stopped),
             ("removing", 0, ModuleStatus::Stopped),
             ("dead", 0, ModuleStatus::Stopped),
@@ -241,7 +257,8 @@ mod tests {
                             .with_pid(1234),
                     )
                     .with_id("mod1".to_string())
-                    .with_exec_i_ds(vec!["id1".to_string(), "id2".to_string()]),
+                    .with_exec_i_ds(vec!["id1".to_string(), "id2".to_string()])
+                    .with_restart_count(3),
             ),
             "mod1".to_string(),
             DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None).unwrap(),
@@ -261,6 +278,7 @@ mod tests {
             runtime_state.finished_at().unwrap().to_rfc3339()
         );
         assert_eq!(Pid::Value(1234), runtime_state.pid());
+        assert_eq!(3, runtime_state.restart_count());
     }
 
     #[test]
@@ -0,0 +1,184 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Async, Poll, Stream};
+use serde_derive::Deserialize;
+use serde_json;
+
+use edgelet_core::{PullProgress, RegistryOperation};
+
+use crate::error::{Error, ErrorKind};
+
+#[derive(Deserialize)]
+struct ProgressMessage {
+    id: Option<String>,
+    status: Option<String>,
+    #[serde(rename = "progressDetail")]
+    progress_detail: Option<ProgressDetail>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProgressDetail {
+    current: Option<u64>,
+    total: Option<u64>,
+}
+
+/// Turns the body of a Docker `/images/create` request into pull progress.
+///
+/// Docker writes one JSON object per line until the pull is done. Lines can
+/// be split across chunks of the body, so the trailing partial line is held
+/// back until the rest of it arrives. A line that reports an error ends the
+/// stream with that error, since Docker has already answered with `200 OK`
+/// by the time the pull fails.
+pub struct ImagePull<S> {
+    image: String,
+    inner: S,
+    pending: Vec<u8>,
+    done: bool,
+}
+
+impl<S> ImagePull<S> {
+    pub fn new(image: String, inner: S) -> Self {
+        ImagePull {
+            image,
+            inner,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+
+    fn context(&self) -> ErrorKind {
+        ErrorKind::RegistryOperation(RegistryOperation::PullImage(self.image.clone()))
+    }
+
+    fn next_progress(&mut self) -> Result<Option<PullProgress>, Error> {
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let rest = self.pending.split_off(end + 1);
+            let line = std::mem::replace(&mut self.pending, rest);
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            let message: ProgressMessage =
+                serde_json::from_slice(&line).with_context(|_| self.context())?;
+            if let Some(error) = message.error {
+                self.pending.clear();
+                self.done = true;
+                return Err(Error::from(
+                    ErrorKind::FormattedDockerRuntime(error).context(self.context()),
+                ));
+            }
+
+            let detail = message.progress_detail;
+            let progress = PullProgress::new(message.status.unwrap_or_default())
+                .with_id(message.id)
+                .with_current(detail.as_ref().and_then(|detail| detail.current))
+                .with_total(detail.as_ref().and_then(|detail| detail.total));
+            return Ok(Some(progress));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<S> Stream for ImagePull<S>
+where
+    S: Stream,
+    S::Item: AsRef<[u8]>,
+    S::Error: Fail,
+{
+    type Item = PullProgress;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(progress) = self.next_progress()? {
+                return Ok(Async::Ready(Some(progress)));
+            }
+
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            match self.inner.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(Some(chunk))) => self.pending.extend_from_slice(chunk.as_ref()),
+                Ok(Async::Ready(None)) => {
+                    // The last line doesn't have to end with a newline.
+                    self.pending.push(b'\n');
+                    self.done = true;
+                }
+                Err(err) => return Err(Error::from(err.context(self.context()))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{stream, Future};
+    use hyper::Chunk;
+
+    use super::*;
+
+    fn pull(chunks: Vec<&'static str>) -> Result<Vec<PullProgress>, Error> {
+        let chunks = chunks
+            .into_iter()
+            .map(|c| Ok::<_, hyper::Error>(Chunk::from(c)));
+        ImagePull::new("alpine:3.9".to_string(), stream::iter_result(chunks))
+            .collect()
+            .wait()
+    }
+
+    #[test]
+    fn progress_across_chunks() {
+        let progress = pull(vec![
+            r#"{"status":"Pulling from library/alpine","id":"3.9"}"#,
+            "\r\n",
+            r#"{"status":"Downloading","progressDetail":{"current":1024,"#,
+            r#""total":2048},"id":"bdf0201b3a05"}"#,
+            "\r\n",
+            r#"{"status":"Status: Downloaded newer image for alpine:3.9"}"#,
+            "\r\n",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                PullProgress::new("Pulling from library/alpine".to_string())
+                    .with_id(Some("3.9".to_string())),
+                PullProgress::new("Downloading".to_string())
+                    .with_id(Some("bdf0201b3a05".to_string()))
+                    .with_current(Some(1024))
+                    .with_total(Some(2048)),
+                PullProgress::new("Status: Downloaded newer image for alpine:3.9".to_string()),
+            ],
+            progress
+        );
+    }
+
+    #[test]
+    fn reported_error_ends_the_stream() {
+        let err = pull(vec![
+            r#"{"status":"Pulling from library/alpine","id":"3.9"}"#,
+            "\r\n",
+            r#"{"errorDetail":{"message":"unauthorized"},"error":"unauthorized"}"#,
+            "\r\n",
+        ])
+        .unwrap_err();
+
+        assert_eq!("Could not pull image alpine:3.9", err.to_string());
+        assert_eq!(
+            "unauthorized",
+            Fail::iter_causes(&err).next().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_message_is_an_error() {
+        let err = pull(vec!["not json\n"]).unwrap_err();
+
+        assert_eq!("Could not pull image alpine:3.9", err.to_string());
+    }
+}
@@ -0,0 +1,313 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use edgelet_core::{DiskUsage, NetworkInterface, SystemResources};
+
+/// Queries the host for its CPU count, available memory, and the size and
+/// free space of the disk that holds `disk_path`.
+pub fn system_resources(disk_path: &Path) -> io::Result<SystemResources> {
+    let (total_disk_bytes, free_disk_bytes) = disk_space(disk_path)?;
+    Ok(SystemResources::new(
+        cpu_count()?,
+        available_memory()?,
+        total_disk_bytes,
+        free_disk_bytes,
+    ))
+}
+
+/// The mount point of the filesystem that holds `path`, and the space free
+/// on it.
+pub fn free_disk_space(path: &Path) -> io::Result<(PathBuf, u64)> {
+    let mount_point = mount_point(path);
+    let (_, free_disk_bytes) = disk_space(&mount_point)?;
+    Ok((mount_point, free_disk_bytes))
+}
+
+/// The mount point of the filesystem that holds `path`, with its size and
+/// the space free on it.
+pub fn disk_usage(path: &Path) -> io::Result<DiskUsage> {
+    let mount_point = mount_point(path);
+    let (total_disk_bytes, free_disk_bytes) = disk_space(&mount_point)?;
+    Ok(DiskUsage::new(
+        mount_point,
+        total_disk_bytes,
+        free_disk_bytes,
+    ))
+}
+
+#[cfg(unix)]
+pub const DEFAULT_DISK_PATH: &str = "/";
+
+#[cfg(windows)]
+pub const DEFAULT_DISK_PATH: &str = "C:\\";
+
+#[cfg(unix)]
+#[allow(clippy::cast_sign_loss)]
+fn cpu_count() -> io::Result<u64> {
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if count < 1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(count as u64)
+    }
+}
+
+#[cfg(unix)]
+fn available_memory() -> io::Result<u64> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo")?)
+}
+
+/// Kernels older than 3.14 don't report `MemAvailable`, in which case the
+/// same estimate `free` uses is computed from the free and cached memory.
+#[cfg(unix)]
+fn parse_meminfo(meminfo: &str) -> io::Result<u64> {
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            if parts.next() == Some(name) {
+                parts.next().and_then(|kb| kb.parse::<u64>().ok())
+            } else {
+                None
+            }
+        })
+    };
+
+    field("MemAvailable:")
+        .or_else(|| {
+            field("MemFree:")
+                .map(|free| free + field("Buffers:").unwrap_or(0) + field("Cached:").unwrap_or(0))
+        })
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "/proc/meminfo does not report available memory",
+            )
+        })
+}
+
+/// Looks the mount point up in `/proc/mounts`, or falls back to `path`
+/// itself where there is no such file.
+#[cfg(unix)]
+fn mount_point(path: &Path) -> PathBuf {
+    std::fs::read_to_string("/proc/mounts")
+        .ok()
+        .and_then(|mounts| parse_mounts(&mounts, path))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// The longest mount point in `mounts` that `path` is under. Mount points
+/// have spaces and other whitespace escaped as octal in `/proc/mounts`.
+#[cfg(unix)]
+fn parse_mounts(mounts: &str, path: &Path) -> Option<PathBuf> {
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|mount_point| {
+            PathBuf::from(
+                mount_point
+                    .replace("\\040", " ")
+                    .replace("\\011", "\t")
+                    .replace("\\012", "\n")
+                    .replace("\\134", "\\"),
+            )
+        })
+        .filter(|mount_point| path.starts_with(mount_point))
+        .max_by_key(|mount_point| mount_point.components().count())
+}
+
+#[cfg(unix)]
+#[allow(clippy::cast_lossless, clippy::identity_conversion)]
+fn disk_space(path: &Path) -> io::Result<(u64, u64)> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let block_size = stats.f_frsize as u64;
+    Ok((
+        stats.f_blocks as u64 * block_size,
+        stats.f_bavail as u64 * block_size,
+    ))
+}
+
+/// The network interfaces of the host with their IPv4 and IPv6 addresses,
+/// sorted by name.
+#[cfg(unix)]
+#[allow(clippy::cast_ptr_alignment)]
+pub fn network_interfaces() -> io::Result<Vec<NetworkInterface>> {
+    use std::collections::BTreeMap;
+    use std::ffi::CStr;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut interfaces: BTreeMap<String, Vec<IpAddr>> = BTreeMap::new();
+    let mut current = addrs;
+    while let Some(ifaddr) = unsafe { current.as_ref() } {
+        current = ifaddr.ifa_next;
+
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        let addresses = interfaces.entry(name).or_insert_with(Vec::new);
+        let addr = match unsafe { ifaddr.ifa_addr.as_ref() } {
+            Some(addr) => addr,
+            None => continue,
+        };
+        match i32::from(addr.sa_family) {
+            libc::AF_INET => {
+                let addr = unsafe { &*(addr as *const libc::sockaddr as *const libc::sockaddr_in) };
+                addresses.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                    addr.sin_addr.s_addr,
+                ))));
+            }
+            libc::AF_INET6 => {
+                let addr =
+                    unsafe { &*(addr as *const libc::sockaddr as *const libc::sockaddr_in6) };
+                addresses.push(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)));
+            }
+            _ => (),
+        }
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+
+    Ok(interfaces
+        .into_iter()
+        .map(|(name, addresses)| NetworkInterface::new(name, addresses))
+        .collect())
+}
+
+/// Enumerating the interfaces of a Windows host isn't supported yet, so
+/// none are reported.
+#[cfg(windows)]
+pub fn network_interfaces() -> io::Result<Vec<NetworkInterface>> {
+    Ok(Vec::new())
+}
+
+#[cfg(windows)]
+fn cpu_count() -> io::Result<u64> {
+    use winapi::um::sysinfoapi::{GetSystemInfo, SYSTEM_INFO};
+
+    let mut info: SYSTEM_INFO = unsafe { std::mem::zeroed() };
+    unsafe { GetSystemInfo(&mut info) };
+    Ok(u64::from(info.dwNumberOfProcessors))
+}
+
+#[cfg(windows)]
+#[allow(clippy::cast_possible_truncation)]
+fn available_memory() -> io::Result<u64> {
+    use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+
+    let mut status: MEMORYSTATUSEX = unsafe { std::mem::zeroed() };
+    status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+    if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(status.ullAvailPhys)
+}
+
+#[cfg(windows)]
+fn mount_point(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+#[cfg(windows)]
+fn disk_space(path: &Path) -> io::Result<(u64, u64)> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    use winapi::um::winnt::ULARGE_INTEGER;
+
+    let path: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut free: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let mut total: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut free, &mut total, ptr::null_mut()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { (*total.QuadPart(), *free.QuadPart()) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_meminfo_uses_mem_available() {
+        let meminfo = "MemTotal:       16303224 kB\nMemFree:         1234567 kB\nMemAvailable:   12000000 kB\n";
+        assert_eq!(12_000_000 * 1024, parse_meminfo(meminfo).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_meminfo_falls_back_to_free_and_cached() {
+        let meminfo = "MemTotal: 4000 kB\nMemFree: 1000 kB\nBuffers: 200 kB\nCached: 300 kB\n";
+        assert_eq!(1500 * 1024, parse_meminfo(meminfo).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_meminfo_fails_without_memory_fields() {
+        assert!(parse_meminfo("MemTotal: 4000 kB\n").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_mounts_finds_the_longest_mount_point() {
+        let mounts = "/dev/sda1 / ext4 rw 0 0\n\
+                      /dev/sdb1 /var/lib/docker ext4 rw 0 0\n\
+                      /dev/sdc1 /var/lib/docker\\040data ext4 rw 0 0\n";
+        assert_eq!(
+            Some(PathBuf::from("/var/lib/docker")),
+            parse_mounts(mounts, Path::new("/var/lib/docker/overlay2"))
+        );
+        assert_eq!(
+            Some(PathBuf::from("/var/lib/docker data")),
+            parse_mounts(mounts, Path::new("/var/lib/docker data"))
+        );
+        assert_eq!(
+            Some(PathBuf::from("/")),
+            parse_mounts(mounts, Path::new("/var/lib/dockerd"))
+        );
+    }
+
+    #[test]
+    fn disk_usage_reports_the_mount_point() {
+        let usage = disk_usage(Path::new(DEFAULT_DISK_PATH)).unwrap();
+        assert_eq!(Path::new(DEFAULT_DISK_PATH), usage.mount_point());
+        assert!(usage.total_bytes() >= usage.free_bytes());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn network_interfaces_include_loopback() {
+        let interfaces = network_interfaces().unwrap();
+        assert!(interfaces
+            .iter()
+            .flat_map(NetworkInterface::addresses)
+            .any(std::net::IpAddr::is_loopback));
+    }
+
+    #[test]
+    fn system_resources_reports_host_values() {
+        let resources = system_resources(Path::new(DEFAULT_DISK_PATH)).unwrap();
+        assert!(resources.total_cpu_count() > 0);
+        assert!(resources.total_disk_bytes() >= resources.free_disk_bytes());
+    }
+}
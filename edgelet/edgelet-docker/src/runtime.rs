@@ -1,54 +1,97 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
+use std::env;
+use std::fs;
+use std::net::IpAddr;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use base64;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use failure::{Fail, ResultExt};
 use futures::prelude::*;
 use futures::{future, stream, Async, Stream};
 use hyper::{Body, Chunk as HyperChunk, Client};
 use lazy_static::lazy_static;
-use log::{debug, info, Level};
+use log::{debug, info, warn, Level};
 use serde_json;
+use tokio::timer::Timeout as TimeoutFuture;
 use url::Url;
 
 use docker::apis::client::APIClient;
 use docker::apis::configuration::Configuration;
-use docker::models::{ContainerCreateBody, InlineResponse200, InlineResponse2001, NetworkConfig};
+use docker::models::{
+    AuthConfig, CheckpointCreateConfig, ContainerCreateBody, ContainerCreateBodyNetworkingConfig,
+    ContainerUpdateUpdate, EndpointSettings, ExecConfig, ExecStartConfig, HostConfig,
+    HostConfigLogConfig, ImageSummary, InlineResponse200, InlineResponse2001, NetworkConfig,
+};
 use edgelet_core::{
-    pid::Pid, LogOptions, Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
-    ModuleTop, RegistryOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, UrlExt,
+    pid::Pid, start_in_dependency_order, Capabilities, CgroupVersion, CircuitState, Connectivity,
+    ConnectivityStatus, Error as CoreError, LogOptions, Module, ModuleDetails, ModuleEvent,
+    ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSnapshot,
+    ModuleSpec, ModuleStats, ModuleStatus, ModuleTop, ModuleTrustStore,
+    PortBinding as CorePortBinding, PruneImagesResult, RegistryOperation, ResourceLimits,
+    RuntimeOperation, StartResult, SystemInfo as CoreSystemInfo, SystemResources, SystemSnapshot,
+    UrlExt, VolumeMount,
 };
 use edgelet_http::UrlConnector;
 use edgelet_utils::{ensure_not_empty_with_context, log_failure};
 
 use crate::client::DockerClient;
-use crate::config::DockerConfig;
+use crate::config::{DockerConfig, RegistryAuth};
 use crate::error::{Error, ErrorKind, Result};
+use crate::events::{self, ModuleEvents};
+use crate::logs::{DockerFrames, LogSource};
 use crate::module::{runtime_state, DockerModule, MODULE_TYPE as DOCKER_MODULE_TYPE};
+use crate::pull::ImagePull;
+use crate::resources;
 
 type Deserializer = &'static mut serde_json::Deserializer<serde_json::de::IoRead<std::io::Empty>>;
 
 const WAIT_BEFORE_KILL_SECONDS: i32 = 10;
 
+/// Where the cgroup hierarchies of the host are mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Where the trust bundle of a module is mounted in its container.
+const TRUST_BUNDLE_MOUNT_PATH: &str = "/etc/iotedge/truststore";
+
+/// The interface of a container on a Docker network.
+const CONTAINER_INTERFACE: &str = "eth0";
+
 static LABEL_KEY: &str = "net.azure-devices.edge.owner";
 static LABEL_VALUE: &str = "Microsoft.Azure.Devices.Edge.Agent";
 
+// Holds the spec the container was created from as JSON, so that the desired
+// state of a module can be told apart from what the container ended up with.
+static SPEC_LABEL_KEY: &str = "net.azure-devices.edge.spec";
+
 lazy_static! {
     static ref LABELS: Vec<&'static str> = {
         let mut labels = vec![];
         labels.push("net.azure-devices.edge.owner=Microsoft.Azure.Devices.Edge.Agent");
         labels
     };
+
+    // Docker checkpoints containers with CRIU, which it looks for on the
+    // PATH of the daemon. The daemon runs on this host, so it finds CRIU
+    // where iotedged would.
+    static ref CRIU_INSTALLED: bool = env::var_os("PATH").map_or(false, |paths| {
+        env::split_paths(&paths).any(|dir| dir.join("criu").is_file())
+    });
 }
 
 #[derive(Clone)]
 pub struct DockerModuleRuntime {
     client: DockerClient<UrlConnector>,
     network_id: Option<String>,
+    connectivity: Connectivity,
+    trust_store: Option<ModuleTrustStore>,
 }
 
 impl DockerModuleRuntime {
@@ -76,6 +119,8 @@ impl DockerModuleRuntime {
         Ok(DockerModuleRuntime {
             client: DockerClient::new(APIClient::new(configuration)),
             network_id: None,
+            connectivity: Connectivity::new(),
+            trust_store: None,
         })
     }
 
@@ -84,6 +129,145 @@ impl DockerModuleRuntime {
         self
     }
 
+    /// Mounts the trust bundle of every module that is created into its
+    /// container, read-only at `/etc/iotedge/truststore`.
+    pub fn with_trust_store(mut self, trust_store: ModuleTrustStore) -> Self {
+        self.trust_store = Some(trust_store);
+        self
+    }
+
+    /// The connectivity to IoT Hub that `connectivity_status` reports.
+    /// Docker doesn't talk to IoT Hub, so it has to be recorded by whatever
+    /// does.
+    pub fn connectivity(&self) -> &Connectivity {
+        &self.connectivity
+    }
+
+    /// Fails with `CheckpointNotSupported` unless the Docker daemon runs with
+    /// experimental features, which checkpoints need besides CRIU.
+    fn ensure_checkpoint_support(
+        &self,
+        context: RuntimeOperation,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        self.client
+            .system_api()
+            .system_info()
+            .then(move |result| match result {
+                Ok(system_info) if system_info.experimental_build() == Some(&true) => Ok(()),
+                Ok(_) => {
+                    let err = Error::from(
+                        ErrorKind::CheckpointNotSupported
+                            .context(ErrorKind::RuntimeOperation(context)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(err, ErrorKind::RuntimeOperation(context));
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            })
+    }
+
+    /// Removes the images matching `filters` that `prune` selects and no
+    /// container was created from.
+    fn prune_unused_images<P>(
+        &self,
+        filters: &str,
+        prune: P,
+    ) -> impl Future<Item = PruneImagesResult, Error = Error> + Send
+    where
+        P: Fn(&ImageSummary) -> bool + Send + 'static,
+    {
+        let client = self.client.clone();
+
+        // Docker's own prune can't be told which images to keep, so the
+        // images are removed one at a time, skipping every image that a
+        // container was created from. Modules that are stopped still have
+        // their container, so their images are kept too.
+        let in_use = self
+            .client
+            .container_api()
+            .container_list(true, 0, false, "")
+            .map(|containers| {
+                containers
+                    .iter()
+                    .map(|container| container.image_id().to_string())
+                    .collect::<HashSet<_>>()
+            });
+        let images = self.client.image_api().image_list(false, filters, false);
+
+        in_use
+            .join(images)
+            .map_err(|err| {
+                let err = Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages),
+                );
+                log_failure(Level::Warn, &err);
+                err
+            })
+            .and_then(move |(in_use, images)| {
+                let unused = images
+                    .into_iter()
+                    .filter(move |image| !in_use.contains(image.id()) && prune(image));
+                stream::iter_ok(unused)
+                    .and_then(move |image| remove_unused_image(&client, &image))
+                    .fold(
+                        (0, Vec::new()),
+                        |(reclaimed_bytes, mut removed_images), removed| {
+                            if let Some((id, size)) = removed {
+                                removed_images.push(id);
+                                Ok::<_, Error>((reclaimed_bytes + size, removed_images))
+                            } else {
+                                Ok((reclaimed_bytes, removed_images))
+                            }
+                        },
+                    )
+            })
+            .map(|(reclaimed_bytes, removed_images)| {
+                info!(
+                    "Successfully pruned {} images, reclaiming {} bytes",
+                    removed_images.len(),
+                    reclaimed_bytes
+                );
+                PruneImagesResult::new(reclaimed_bytes, removed_images)
+            })
+    }
+
+    /// The mount point of the disk holding the container runtime's data, and
+    /// the space free on it.
+    pub(crate) fn free_disk_space(
+        &self,
+    ) -> impl Future<Item = (PathBuf, u64), Error = Error> + Send {
+        self.client
+            .system_api()
+            .system_info()
+            .then(|result| -> Result<_> {
+                let system_info = result.map_err(|err| {
+                    Error::from_docker_error(err, ErrorKind::ImageGarbageCollection)
+                })?;
+                let disk_path = system_info
+                    .docker_root_dir()
+                    .unwrap_or(resources::DEFAULT_DISK_PATH);
+                let free_disk_space = resources::free_disk_space(Path::new(disk_path))
+                    .context(ErrorKind::ImageGarbageCollection)?;
+                Ok(free_disk_space)
+            })
+    }
+
+    /// Removes the unused images that were created before `created_before`,
+    /// in seconds since the Unix epoch.
+    pub(crate) fn prune_images_created_before(
+        &self,
+        created_before: i64,
+    ) -> impl Future<Item = PruneImagesResult, Error = Error> + Send {
+        self.prune_unused_images("", move |image| {
+            i64::from(*image.created()) < created_before
+        })
+    }
+
     fn merge_env(cur_env: Option<&[String]>, new_env: &HashMap<String, String>) -> Vec<String> {
         // build a new merged hashmap containing string slices for keys and values
         // pointing into String instances in new_env
@@ -118,17 +302,7 @@ impl ModuleRegistry for DockerModuleRuntime {
 
         info!("Pulling image {}...", image);
 
-        let creds: Result<String> = config.auth().map_or_else(
-            || Ok("".to_string()),
-            |a| {
-                let json = serde_json::to_string(a).with_context(|_| {
-                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(image.clone()))
-                })?;
-                Ok(base64::encode(&json))
-            },
-        );
-
-        let response = creds
+        let response = registry_credentials(&image, config.pull_auth())
             .map(|creds| {
                 self.client
                     .image_api()
@@ -241,6 +415,301 @@ where
     Ok(pids?)
 }
 
+/// Removes every tag of `image`, which removes the image once the last tag is
+/// gone, and resolves to the ID and size of the image if it was removed. An
+/// image Docker refuses to remove, for example because a container was
+/// created from it in the meantime, is skipped.
+#[allow(clippy::cast_sign_loss)]
+fn remove_unused_image(
+    client: &DockerClient<UrlConnector>,
+    image: &ImageSummary,
+) -> impl Future<Item = Option<(String, u64)>, Error = Error> {
+    let id = image.id().clone();
+    let size = (*image.size()).max(0) as u64;
+    let mut names: Vec<String> = image
+        .repo_tags()
+        .unwrap_or(&[])
+        .iter()
+        .filter(|tag| *tag != "<none>:<none>")
+        .cloned()
+        .collect();
+    if names.is_empty() {
+        names.push(id.clone());
+    }
+
+    let client = client.clone();
+    stream::iter_ok(names)
+        .and_then(move |name| client.image_api().image_delete(&name, false, false))
+        .collect()
+        .then(move |result| -> Result<_> {
+            match result {
+                Ok(responses) => {
+                    let removed = responses
+                        .iter()
+                        .flatten()
+                        .any(|item| item.deleted() == Some(id.as_str()));
+                    Ok(if removed {
+                        debug!("Removed unused image {}", id);
+                        Some((id, size))
+                    } else {
+                        None
+                    })
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(id)),
+                    );
+                    log_failure(Level::Info, &err);
+                    Ok(None)
+                }
+            }
+        })
+}
+
+/// Docker takes log times as whole seconds since the epoch, with 0 meaning
+/// no bound, so earlier times are moved up to the first second after it.
+#[allow(clippy::cast_possible_truncation)]
+fn unix_timestamp(time: Option<DateTime<Utc>>) -> i32 {
+    time.map_or(0, |time| {
+        time.timestamp().max(1).min(i64::from(i32::max_value())) as i32
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+/// The module's address is taken from the network modules are attached to, if
+/// one is configured and the container is on it. Otherwise the first address
+/// the container has on any network is used.
+#[allow(clippy::cast_possible_truncation)]
+fn parse_inspect_response(
+    name: &str,
+    resp: &InlineResponse200,
+    network_id: Option<&str>,
+) -> ModuleDetails {
+    let state = runtime_state(resp);
+
+    let ip_address = resp.network_settings().and_then(|settings| {
+        let networks = settings.networks();
+        network_id
+            .and_then(|network_id| networks.and_then(|networks| networks.get(network_id)))
+            .into_iter()
+            .chain(networks.into_iter().flat_map(|networks| networks.values()))
+            .filter_map(EndpointSettings::ip_address)
+            .chain(settings.ip_address())
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .next()
+    });
+
+    ModuleDetails::new(name.to_string(), *state.status())
+        .with_exit_code(state.exit_code().map(|code| code as i32))
+        .with_started_at(state.started_at().cloned())
+        .with_finished_at(state.finished_at().cloned())
+        .with_ip_address(ip_address)
+}
+
+/// Variables without a value are left out, as they only pass a variable of
+/// the same name through from the daemon's environment.
+fn parse_env(env: &[String]) -> HashMap<String, String> {
+    env.iter()
+        .filter_map(|var| {
+            let mut parts = var.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((key.to_string(), value.to_string())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Ports come from the network settings of the container, which only has
+/// them while the container is running. Each host port a container port is
+/// published on is a binding of its own, and a port that is exposed but not
+/// published is a binding without a host port. Bindings are sorted by
+/// container port.
+fn parse_ports(resp: &InlineResponse200) -> Vec<CorePortBinding> {
+    let running = resp
+        .state()
+        .and_then(|state| state.running())
+        .cloned()
+        .unwrap_or(false);
+    if !running {
+        return Vec::new();
+    }
+
+    let mut ports: Vec<_> = resp
+        .network_settings()
+        .and_then(|settings| settings.ports())
+        .into_iter()
+        .flat_map(|ports| ports.iter())
+        .flat_map(|(port, bindings)| {
+            let mut parts = port.splitn(2, '/');
+            let container_port = match parts.next().map(str::parse::<u16>) {
+                Some(Ok(container_port)) => container_port,
+                _ => return Vec::new(),
+            };
+            let protocol = parts.next().unwrap_or("tcp").to_string();
+            let port = CorePortBinding::new(container_port, protocol);
+            match bindings {
+                Some(bindings) if !bindings.is_empty() => bindings
+                    .iter()
+                    .map(|binding| {
+                        port.clone()
+                            .with_host_ip(binding.host_ip().map(ToString::to_string))
+                            .with_host_port(
+                                binding
+                                    .host_port()
+                                    .and_then(|port| port.parse::<u16>().ok()),
+                            )
+                    })
+                    .collect(),
+                _ => vec![port],
+            }
+        })
+        .collect();
+    ports.sort_by(|a, b| {
+        (a.container_port(), a.protocol(), a.host_ip(), a.host_port()).cmp(&(
+            b.container_port(),
+            b.protocol(),
+            b.host_ip(),
+            b.host_port(),
+        ))
+    });
+    ports
+}
+
+/// Lists the mounts of a container from its inspect response, sorted by the
+/// path they are mounted at in the container. Mounts are read-write unless
+/// Docker reports them as read-only.
+fn parse_volumes(resp: &InlineResponse200) -> Vec<VolumeMount> {
+    let mut volumes: Vec<_> = resp
+        .mounts()
+        .into_iter()
+        .flat_map(|mounts| mounts.iter())
+        .filter_map(|mount| {
+            let container_path = mount.destination()?.to_string();
+            let host_path = mount.source().unwrap_or_default().to_string();
+            let mode = if mount.RW().cloned().unwrap_or(true) {
+                "rw"
+            } else {
+                "ro"
+            };
+            Some(VolumeMount::new(
+                container_path,
+                host_path,
+                mode.to_string(),
+            ))
+        })
+        .collect();
+    volumes.sort_by(|a, b| a.container_path().cmp(b.container_path()));
+    volumes
+}
+
+fn spec_label(module: &ModuleSpec<DockerConfig>) -> Result<String> {
+    let spec = module
+        .clone()
+        .with_config(module.config().clone().without_auth());
+    let label = serde_json::to_string(&spec).with_context(|_| {
+        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(module.name().to_string()))
+    })?;
+    Ok(label)
+}
+
+fn parse_spec_label(id: &str, label: &str) -> Result<ModuleSpec<DockerConfig>> {
+    let spec = serde_json::from_str(label)
+        .with_context(|_| ErrorKind::MalformedSpecLabel(id.to_string()))?;
+    Ok(spec)
+}
+
+/// The spec stored with a container, if it was created with one.
+fn stored_spec(id: &str, resp: &InlineResponse200) -> Result<Option<ModuleSpec<DockerConfig>>> {
+    match resp
+        .config()
+        .and_then(|config| config.labels())
+        .and_then(|labels| labels.get(SPEC_LABEL_KEY))
+    {
+        Some(label) => parse_spec_label(id, label).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The environment a module was created with by its spec, which is that of
+/// its create options with the spec's own environment added, without what the
+/// image sets.
+fn spec_env(spec: &ModuleSpec<DockerConfig>) -> Result<HashMap<String, String>> {
+    let create_options = spec.config().clone_create_options()?;
+    Ok(parse_env(&DockerModuleRuntime::merge_env(
+        create_options.env(),
+        spec.env(),
+    )))
+}
+
+/// The spec of a module whose environment is replaced with `env`. As `env`
+/// replaces the environment from the create options as well, those are
+/// dropped from the spec.
+fn with_spec_env(
+    spec: ModuleSpec<DockerConfig>,
+    env: HashMap<String, String>,
+) -> Result<ModuleSpec<DockerConfig>> {
+    let mut create_options = spec.config().clone_create_options()?;
+    create_options.reset_env();
+    let config = spec.config().clone().with_create_options(create_options);
+    Ok(spec.with_config(config).with_env(env))
+}
+
+fn parse_stats_response(resp: &serde_json::Value) -> ModuleStats {
+    let cpu_usage = |key: &str| resp[key]["cpu_usage"]["total_usage"].as_u64().unwrap_or(0);
+    let system_usage = |key: &str| resp[key]["system_cpu_usage"].as_u64().unwrap_or(0);
+
+    // Same calculation as the `docker stats` CLI: the container's share of the
+    // total CPU time consumed on the host between the two samples, scaled by
+    // the number of CPUs.
+    let cpu_delta = cpu_usage("cpu_stats").saturating_sub(cpu_usage("precpu_stats"));
+    let system_delta = system_usage("cpu_stats").saturating_sub(system_usage("precpu_stats"));
+    let online_cpus = resp["cpu_stats"]["online_cpus"]
+        .as_u64()
+        .or_else(|| {
+            resp["cpu_stats"]["cpu_usage"]["percpu_usage"]
+                .as_array()
+                .map(|cpus| cpus.len() as u64)
+        })
+        .unwrap_or(1);
+    let cpu_percent = if cpu_delta > 0 && system_delta > 0 {
+        (cpu_delta as f64 / system_delta as f64) * online_cpus as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    let (network_rx_bytes, network_tx_bytes) =
+        resp["networks"].as_object().map_or((0, 0), |networks| {
+            networks.values().fold((0, 0), |(rx, tx), network| {
+                (
+                    rx + network["rx_bytes"].as_u64().unwrap_or(0),
+                    tx + network["tx_bytes"].as_u64().unwrap_or(0),
+                )
+            })
+        });
+
+    ModuleStats::default()
+        .with_cpu_percent(cpu_percent)
+        .with_memory_usage_bytes(resp["memory_stats"]["usage"].as_u64().unwrap_or(0))
+        .with_memory_limit_bytes(resp["memory_stats"]["limit"].as_u64().unwrap_or(0))
+        .with_network_rx_bytes(network_rx_bytes)
+        .with_network_tx_bytes(network_tx_bytes)
+}
+
+/// The value of the `X-Registry-Auth` header for pulling `image` with `auth`.
+fn registry_credentials(image: &str, auth: Option<AuthConfig>) -> Result<String> {
+    auth.map_or_else(
+        || Ok("".to_string()),
+        |a| {
+            let json = serde_json::to_string(&a).with_context(|_| {
+                ErrorKind::RegistryOperation(RegistryOperation::PullImage(image.to_string()))
+            })?;
+            Ok(base64::encode(&json))
+        },
+    )
+}
+
 impl ModuleRuntime for DockerModuleRuntime {
     type Error = Error;
     type Config = DockerConfig;
@@ -248,6 +717,7 @@ impl ModuleRuntime for DockerModuleRuntime {
     type ModuleRegistry = Self;
     type Chunk = Chunk;
     type Logs = Logs;
+    type RegistryAuth = RegistryAuth;
 
     type CreateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type GetFuture =
@@ -262,8 +732,40 @@ impl ModuleRuntime for DockerModuleRuntime {
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type SystemInfoFuture = Box<dyn Future<Item = CoreSystemInfo, Error = Self::Error> + Send>;
+    type SystemResourcesFuture =
+        Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type TopFuture = Box<dyn Future<Item = ModuleTop, Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
+    type InspectFuture = Box<dyn Future<Item = ModuleDetails, Error = Self::Error> + Send>;
+    type EnvFuture = Box<dyn Future<Item = HashMap<String, String>, Error = Self::Error> + Send>;
+    type UpdateEnvFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UpdateResourcesFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UpsertFuture = Box<dyn Future<Item = (Self::Module, bool), Error = Self::Error> + Send>;
+    type PingFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type EventStream = Box<dyn Stream<Item = ModuleEvent, Error = Self::Error> + Send>;
+    type GetConfigFuture =
+        Box<dyn Future<Item = Option<ModuleSpec<Self::Config>>, Error = Self::Error> + Send>;
+    type ExecFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type PullImageStream = ImagePull<Body>;
+    type PullImageFuture =
+        Box<dyn Future<Item = Self::PullImageStream, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PruneImagesResult, Error = Self::Error> + Send>;
+    type SignalFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type PortsFuture = Box<dyn Future<Item = Vec<CorePortBinding>, Error = Self::Error> + Send>;
+    type WaitUntilStoppedFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ConnectivityStatusFuture = future::FutureResult<ConnectivityStatus, Self::Error>;
+    type CheckpointFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RestoreFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ListCheckpointsFuture = Box<dyn Future<Item = Vec<String>, Error = Self::Error> + Send>;
+    type VolumesFuture = Box<dyn Future<Item = Vec<VolumeMount>, Error = Self::Error> + Send>;
+    type SnapshotFuture = Box<dyn Future<Item = SystemSnapshot, Error = Self::Error> + Send>;
+    type StartAllFuture = Box<dyn Future<Item = Vec<StartResult>, Error = CoreError> + Send>;
+    type SetBandwidthFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type PauseFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UnpauseFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type CopyToContainerFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn init(&self) -> Self::InitFuture {
         info!("Initializing module runtime...");
@@ -332,7 +834,9 @@ impl ModuleRuntime for DockerModuleRuntime {
                     .labels()
                     .cloned()
                     .unwrap_or_else(HashMap::new);
+                labels.extend(module.labels().clone());
                 labels.insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+                labels.insert(SPEC_LABEL_KEY.to_string(), spec_label(&module)?);
 
                 debug!(
                     "Creating container {} with image {}",
@@ -344,9 +848,29 @@ impl ModuleRuntime for DockerModuleRuntime {
                     .with_image(module.config().image().to_string())
                     .with_env(merged_env)
                     .with_labels(labels);
+                let create_options = with_resource_limits(create_options, module.resource_limits());
+                let create_options = with_log_config(create_options, module.config());
+                let create_options = with_cgroup_parent(
+                    create_options,
+                    module.name(),
+                    module.config(),
+                    cgroup_version(Path::new(CGROUP_ROOT)),
+                );
+                let create_options = match self.trust_store {
+                    Some(ref trust_store) => {
+                        with_trust_bundle(create_options, module.name(), trust_store)
+                    }
+                    None => create_options,
+                };
 
                 // Here we don't add the container to the iot edge docker network as the edge-agent is expected to do that.
                 // It contains the logic to add a container to the iot edge network only if a network is not already specified.
+                // Only a network or aliases in the module config are applied here.
+                let create_options = with_network(
+                    create_options,
+                    module.config(),
+                    self.network_id.as_ref().map(String::as_str),
+                );
 
                 Ok(self
                     .client
@@ -412,7 +936,7 @@ impl ModuleRuntime for DockerModuleRuntime {
                             DockerModule::new(client_copy, name, config).with_context(|_| {
                                 ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
                             })?;
-                        let state = runtime_state(container.id(), container.state());
+                        let state = runtime_state(&container);
                         Ok((module, state))
                     }
                     Err(err) => {
@@ -441,7 +965,7 @@ impl ModuleRuntime for DockerModuleRuntime {
         Box::new(
             self.client
                 .container_api()
-                .container_start(&id, "")
+                .container_start(&id, "", "")
                 .then(|result| match result {
                     Ok(_) => {
                         info!("Successfully started module {}", id);
@@ -507,7 +1031,7 @@ impl ModuleRuntime for DockerModuleRuntime {
                 .system_info()
                 .then(|result| match result {
                     Ok(system_info) => {
-                        let system_info = CoreSystemInfo::new(
+                        let mut system_info = CoreSystemInfo::new(
                             system_info
                                 .os_type()
                                 .unwrap_or(&String::from("Unknown"))
@@ -517,6 +1041,9 @@ impl ModuleRuntime for DockerModuleRuntime {
                                 .unwrap_or(&String::from("Unknown"))
                                 .to_string(),
                         );
+                        if let Some(cgroup_version) = cgroup_version(Path::new(CGROUP_ROOT)) {
+                            system_info = system_info.with_cgroup_version(cgroup_version);
+                        }
                         info!("Successfully queried system info");
                         Ok(system_info)
                     }
@@ -532,6 +1059,44 @@ impl ModuleRuntime for DockerModuleRuntime {
         )
     }
 
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
+        info!("Querying system resources...");
+
+        // Disk usage is reported for the volume holding the container
+        // runtime's data, since that is where module images and writable
+        // layers consume space.
+        Box::new(self.client.system_api().system_info().then(|result| {
+            let result = result
+                .map_err(|err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources),
+                    )
+                })
+                .and_then(|system_info| {
+                    let disk_path = system_info
+                        .docker_root_dir()
+                        .unwrap_or(resources::DEFAULT_DISK_PATH);
+                    resources::system_resources(Path::new(disk_path)).map_err(|err| {
+                        Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::SystemResources,
+                        )))
+                    })
+                });
+
+            match result {
+                Ok(system_resources) => {
+                    info!("Successfully queried system resources");
+                    Ok(system_resources)
+                }
+                Err(err) => {
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            }
+        }))
+    }
+
     fn restart(&self, id: &str) -> Self::RestartFuture {
         info!("Restarting module {}...", id);
 
@@ -678,11 +1243,20 @@ impl ModuleRuntime for DockerModuleRuntime {
         let result = self
             .client
             .container_api()
-            .container_logs(&id, options.follow(), true, true, 0, false, tail)
+            .container_logs(
+                &id,
+                options.follow(),
+                true,
+                true,
+                unix_timestamp(options.since()),
+                unix_timestamp(options.until()),
+                options.timestamps(),
+                tail,
+            )
             .then(|result| match result {
                 Ok(logs) => {
                     info!("Successfully got logs for module {}", id);
-                    Ok(Logs(id, logs))
+                    Ok(Logs(RuntimeOperation::GetModuleLogs(id), logs))
                 }
                 Err(err) => {
                     let err = Error::from_docker_error(
@@ -710,18 +1284,20 @@ impl ModuleRuntime for DockerModuleRuntime {
         }))
     }
 
-    fn top(&self, id: &str) -> Self::TopFuture {
+    fn top(&self, id: &str, ps_args: Option<&str>) -> Self::TopFuture {
         let id = id.to_string();
         Box::new(
             self.client
                 .container_api()
-                .container_top(&id, "")
+                .container_top(&id, ps_args.unwrap_or(""))
                 .then(|result| match result {
                     Ok(resp) => {
                         let p = parse_top_response::<Deserializer>(&resp).with_context(|_| {
                             ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(id.clone()))
                         })?;
-                        Ok(ModuleTop::new(id, p))
+                        let titles = resp.titles().map(<[_]>::to_vec).unwrap_or_default();
+                        let processes = resp.processes().map(<[_]>::to_vec).unwrap_or_default();
+                        Ok(ModuleTop::new(id, p).with_processes(titles, processes))
                     }
                     Err(err) => {
                         let err = Error::from_docker_error(
@@ -733,149 +1309,2341 @@ impl ModuleRuntime for DockerModuleRuntime {
                 }),
         )
     }
-}
 
-#[derive(Debug)]
-pub struct Logs(String, Body);
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        debug!("Getting stats for module {}...", id);
 
-impl Stream for Logs {
-    type Item = Chunk;
-    type Error = Error;
+        let id = id.to_string();
 
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        match self.1.poll() {
-            Ok(Async::Ready(chunk)) => Ok(Async::Ready(chunk.map(Chunk))),
-            Ok(Async::NotReady) => Ok(Async::NotReady),
-            Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
-                RuntimeOperation::GetModuleLogs(self.0.clone()),
-            )))),
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
         }
-    }
-}
 
-impl From<Logs> for Body {
-    fn from(logs: Logs) -> Self {
-        logs.1
-    }
-}
-
-#[derive(Debug, Default)]
-pub struct Chunk(HyperChunk);
+        let client_copy = self.client.clone();
+        let id_copy = id.clone();
 
-impl IntoIterator for Chunk {
-    type Item = u8;
-    type IntoIter = <HyperChunk as IntoIterator>::IntoIter;
+        // A stopped container has no meaningful stats, so report it as idle
+        // instead of asking the container runtime.
+        let result = self
+            .client
+            .container_api()
+            .container_inspect(&id, false)
+            .and_then(move |container| {
+                let state = runtime_state(&container);
+                if *state.status() == ModuleStatus::Running {
+                    future::Either::A(
+                        client_copy
+                            .container_api()
+                            .container_stats(&id_copy, false)
+                            .map(|resp| parse_stats_response(&resp)),
+                    )
+                } else {
+                    future::Either::B(future::ok(ModuleStats::default()))
+                }
+            });
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+        Box::new(result.then(|result| match result {
+            Ok(stats) => {
+                debug!("Successfully got stats for module {}", id);
+                Ok(stats)
+            }
+            Err(err) => {
+                let err = Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id)),
+                );
+                log_failure(Level::Warn, &err);
+                Err(err)
+            }
+        }))
     }
-}
 
-impl Extend<u8> for Chunk {
-    fn extend<T>(&mut self, iter: T)
-    where
-        T: IntoIterator<Item = u8>,
-    {
-        self.0.extend(iter)
-    }
-}
+    fn inspect(&self, id: &str) -> Self::InspectFuture {
+        debug!("Inspecting module {}...", id);
 
-impl AsRef<[u8]> for Chunk {
-    fn as_ref(&self) -> &[u8] {
-        self.0.as_ref()
-    }
-}
+        let id = id.to_string();
 
-/// Invokes `ModuleRuntime::list`, then `Module::runtime_state` on each Module.
-/// Modules whose `runtime_state` returns `NotFound` are filtered out from the result,
-/// instead of letting the whole `list_with_details` call fail.
-fn list_with_details<MR, M>(
-    runtime: &MR,
-) -> Box<dyn Stream<Item = (M, ModuleRuntimeState), Error = Error> + Send>
-where
-    MR: ModuleRuntime<Error = Error, Config = <M as Module>::Config, Module = M>,
-    <MR as ModuleRuntime>::ListFuture: 'static,
-    M: Module<Error = Error> + Send + 'static,
-    <M as Module>::Config: Send,
-{
-    Box::new(
-        runtime
-            .list()
-            .into_stream()
-            .map(|list| {
-                stream::futures_unordered(
-                    list.into_iter()
-                        .map(|module| module.runtime_state().map(|state| (module, state))),
-                )
-            })
-            .flatten()
-            .then(Ok::<_, Error>) // Ok(_) -> Ok(Ok(_)), Err(_) -> Ok(Err(_)), ! -> Err(_)
-            .filter_map(|value| match value {
-                Ok(value) => Some(Ok(value)),
-                Err(err) => match err.kind() {
-                    ErrorKind::NotFound(_) => None,
-                    _ => Some(Err(err)),
-                },
-            })
-            .then(Result::unwrap), // Ok(Ok(_)) -> Ok(_), Ok(Err(_)) -> Err(_), Err(_) -> !
-    )
-}
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::InspectModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let network_id = self.network_id.clone();
 
-    use futures::future::FutureResult;
-    use futures::stream::Empty;
-    #[cfg(unix)]
-    use tempfile::NamedTempFile;
-    use tokio;
-    use url::Url;
+        Box::new(
+            self.client
+                .container_api()
+                .container_inspect(&id, false)
+                .then(move |result| match result {
+                    Ok(container) => {
+                        debug!("Successfully inspected module {}", id);
+                        Ok(parse_inspect_response(
+                            &id,
+                            &container,
+                            network_id.as_ref().map(String::as_str),
+                        ))
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::InspectModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+    fn env(&self, id: &str) -> Self::EnvFuture {
+        debug!("Getting environment of module {}...", id);
 
-    use docker::models::ContainerCreateBody;
-    use edgelet_core::pid::Pid;
-    use edgelet_core::ModuleRegistry;
+        let id = id.to_string();
 
-    use crate::error::{Error, ErrorKind};
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleEnv(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
 
-    #[test]
-    #[should_panic(expected = "URL does not have a recognized scheme")]
-    fn invalid_uri_prefix_fails() {
-        let _mri =
+        Box::new(
+            self.client
+                .container_api()
+                .container_inspect(&id, false)
+                .then(move |result| {
+                    // Containers that weren't created from a spec only have
+                    // the environment Docker reports, image defaults and all.
+                    let env = match result {
+                        Ok(container) => match stored_spec(&id, &container) {
+                            Ok(Some(spec)) => spec_env(&spec),
+                            Ok(None) => Ok(parse_env(
+                                container
+                                    .config()
+                                    .and_then(|config| config.env())
+                                    .unwrap_or(&[]),
+                            )),
+                            Err(err) => Err(err),
+                        }
+                        .map_err(|err| {
+                            Error::from(err.context(ErrorKind::RuntimeOperation(
+                                RuntimeOperation::GetModuleEnv(id.clone()),
+                            )))
+                        }),
+                        Err(err) => Err(Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleEnv(id.clone())),
+                        )),
+                    };
+                    match env {
+                        Ok(env) => {
+                            debug!("Successfully got environment of module {}", id);
+                            Ok(env)
+                        }
+                        Err(err) => {
+                            log_failure(Level::Warn, &err);
+                            Err(err)
+                        }
+                    }
+                }),
+        )
+    }
+
+    fn update_env(&self, id: &str, env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+        info!("Updating environment of module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::UpdateModuleEnv(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let runtime = self.clone();
+        let log_id = id.clone();
+
+        let result = self
+            .client
+            .container_api()
+            .container_inspect(&id, false)
+            .then(move |result| -> Result<_> {
+                let container = result.map_err(|err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone())),
+                    )
+                })?;
+                let running = *runtime_state(&container).status() == ModuleStatus::Running;
+
+                // The module is recreated from its spec, so that it gets
+                // everything `create` adds to it, like its network and trust
+                // bundle, and keeps its spec with the new environment.
+                let spec = stored_spec(&id, &container)?
+                    .ok_or_else(|| ErrorKind::MissingSpecLabel(id.clone()))?;
+                let spec = with_spec_env(spec, env)?;
+
+                Ok((id, spec, running, runtime))
+            })
+            .and_then(|(id, spec, running, runtime)| {
+                let stop = if running {
+                    future::Either::A(runtime.stop(&id, None))
+                } else {
+                    future::Either::B(future::ok(()))
+                };
+                stop.map(move |_| (id, spec, running, runtime))
+            })
+            .and_then(|(id, spec, running, runtime)| {
+                ModuleRuntime::remove(&runtime, &id).map(move |_| (id, spec, running, runtime))
+            })
+            .and_then(|(id, spec, running, runtime)| {
+                runtime.create(spec).map(move |_| (id, running, runtime))
+            })
+            .and_then(|(id, running, runtime)| {
+                if running {
+                    future::Either::A(runtime.start(&id))
+                } else {
+                    future::Either::B(future::ok(()))
+                }
+            });
+
+        Box::new(result.then(move |result| match result {
+            Ok(_) => {
+                info!("Successfully updated environment of module {}", log_id);
+                Ok(())
+            }
+            Err(err) => {
+                let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+                    RuntimeOperation::UpdateModuleEnv(log_id),
+                )));
+                log_failure(Level::Warn, &err);
+                Err(err)
+            }
+        }))
+    }
+
+    fn update_resources(&self, id: &str, limits: ResourceLimits) -> Self::UpdateResourcesFuture {
+        info!("Updating resource limits of module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::UpdateModuleResources(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(
+            self.client
+                .container_api()
+                .container_update(&id, container_update(&limits))
+                .then(move |result| match result {
+                    Ok(_) => {
+                        info!("Successfully updated resource limits of module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::UpdateModuleResources(
+                                id,
+                            )),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn upsert(&self, module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
+        info!("Creating or updating module {}...", module.name());
+
+        let name = module.name().to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&name, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::UpsertModule(name.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let runtime = self.clone();
+        let log_name = name.clone();
+
+        let result = self
+            .get(&name)
+            .then(move |result| match result {
+                Ok(_) => future::Either::A(
+                    ModuleRuntime::remove(&runtime, &name)
+                        .map(move |_| (name, module, false, runtime)),
+                ),
+                Err(err) => match ModuleRuntimeErrorReason::from(&err) {
+                    ModuleRuntimeErrorReason::NotFound => {
+                        future::Either::B(future::ok((name, module, true, runtime)))
+                    }
+                    ModuleRuntimeErrorReason::Other => future::Either::B(future::err(err)),
+                },
+            })
+            .and_then(|(name, module, created, runtime)| {
+                runtime
+                    .create(module)
+                    .map(move |_| (name, created, runtime))
+            })
+            .and_then(|(name, created, runtime)| {
+                runtime.get(&name).map(move |(module, _)| (module, created))
+            });
+
+        Box::new(result.then(move |result| match result {
+            Ok((module, created)) => {
+                if created {
+                    info!("Successfully created module {}", log_name);
+                } else {
+                    info!("Successfully updated module {}", log_name);
+                }
+                Ok((module, created))
+            }
+            Err(err) => {
+                let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+                    RuntimeOperation::UpsertModule(log_name),
+                )));
+                log_failure(Level::Warn, &err);
+                Err(err)
+            }
+        }))
+    }
+
+    fn ping(&self) -> Self::PingFuture {
+        debug!("Pinging container runtime...");
+
+        Box::new(
+            self.client
+                .system_api()
+                .system_ping()
+                .then(|result| match result {
+                    Ok(_) => Ok(()),
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::Ping),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn events(&self) -> Self::EventStream {
+        debug!("Watching module events...");
+        module_events(&self.client, "")
+    }
+
+    fn get_config(&self, id: &str) -> Self::GetConfigFuture {
+        debug!("Getting config of module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(
+            self.client
+                .container_api()
+                .container_inspect(&id, false)
+                .then(move |result| {
+                    let err = match result {
+                        Ok(container) => match stored_spec(&id, &container) {
+                            Ok(spec) => return Ok(spec),
+                            Err(err) => Error::from(err.context(ErrorKind::RuntimeOperation(
+                                RuntimeOperation::GetModuleConfig(id),
+                            ))),
+                        },
+                        Err(err) => {
+                            let err = Error::from_docker_error(
+                                err,
+                                ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(id)),
+                            );
+                            if let ModuleRuntimeErrorReason::NotFound =
+                                ModuleRuntimeErrorReason::from(&err)
+                            {
+                                return Ok(None);
+                            }
+                            err
+                        }
+                    };
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }),
+        )
+    }
+
+    fn exec(&self, id: &str, cmd: &[String], tty: bool) -> Self::ExecFuture {
+        info!("Running command in module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let exec_config = ExecConfig::new()
+            .with_cmd(cmd.to_vec())
+            .with_tty(tty)
+            .with_attach_stdout(true);
+        let client_copy = self.client.clone();
+
+        let result = self
+            .client
+            .exec_api()
+            .container_exec(exec_config, &id)
+            .and_then(move |exec| {
+                client_copy
+                    .exec_api()
+                    .exec_start(exec.id(), ExecStartConfig::new().with_tty(tty))
+            })
+            .then(move |result| match result {
+                Ok(output) => {
+                    info!("Successfully started command in module {}", id);
+                    // Without a TTY the output is multiplexed into frames even
+                    // though only stdout is attached.
+                    let output = if tty {
+                        output
+                    } else {
+                        Body::wrap_stream(
+                            DockerFrames::new(output)
+                                .filter_map(|(source, payload)| match source {
+                                    LogSource::Stdout => Some(HyperChunk::from(payload)),
+                                    LogSource::Stdin | LogSource::Stderr => None,
+                                })
+                                .map_err(Fail::compat),
+                        )
+                    };
+                    Ok(Logs(RuntimeOperation::ExecModule(id), output))
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
+    fn pull_image(&self, image: &str, auth: Option<Self::RegistryAuth>) -> Self::PullImageFuture {
+        info!("Pulling image {}...", image);
+
+        let image = image.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&image, || {
+            ErrorKind::RegistryOperation(RegistryOperation::PullImage(image.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let creds =
+            match registry_credentials(&image, auth.as_ref().map(RegistryAuth::to_auth_config)) {
+                Ok(creds) => creds,
+                Err(err) => return Box::new(future::err(err)),
+            };
+
+        let result = self
+            .client
+            .image_api()
+            .image_create_progress(&image, &creds, "")
+            .then(move |result| match result {
+                Ok(body) => Ok(ImagePull::new(image, body)),
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
+    fn prune_images(&self, dangling_only: bool) -> Self::PruneImagesFuture {
+        info!("Pruning unused images...");
+
+        let filters = if dangling_only {
+            r#"{"dangling":["true"]}"#
+        } else {
+            ""
+        };
+        Box::new(self.prune_unused_images(filters, |_| true))
+    }
+
+    fn signal(&self, id: &str, signal: u32) -> Self::SignalFuture {
+        info!("Sending signal {} to module {}...", signal, id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::SignalModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(
+            self.client
+                .container_api()
+                .container_kill(&id, &signal.to_string())
+                .then(move |result| match result {
+                    Ok(_) => {
+                        info!("Successfully sent signal {} to module {}", signal, id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::SignalModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn get_ports(&self, id: &str) -> Self::PortsFuture {
+        debug!("Getting ports of module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModulePorts(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(
+            self.client
+                .container_api()
+                .container_inspect(&id, false)
+                .then(|result| match result {
+                    Ok(container) => {
+                        debug!("Successfully got ports of module {}", id);
+                        Ok(parse_ports(&container))
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModulePorts(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn wait_until_stopped(&self, id: &str, timeout: Duration) -> Self::WaitUntilStoppedFuture {
+        info!("Waiting for module {} to stop...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::WaitUntilStopped(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        // The "not-running" condition makes docker answer right away when the
+        // container has already stopped.
+        let wait = self
+            .client
+            .container_api()
+            .container_wait(&id, "not-running");
+
+        Box::new(
+            TimeoutFuture::new(wait, timeout).then(move |result| match result {
+                Ok(_) => {
+                    info!("Module {} stopped", id);
+                    Ok(())
+                }
+                Err(err) => {
+                    let context =
+                        ErrorKind::RuntimeOperation(RuntimeOperation::WaitUntilStopped(id));
+                    let err = if err.is_elapsed() {
+                        Error::from(ErrorKind::StopTimeout(timeout).context(context))
+                    } else if err.is_inner() {
+                        Error::from_docker_error(err.into_inner().expect("error is inner"), context)
+                    } else {
+                        let err = err.into_timer().expect("error is a timer error");
+                        Error::from(err.context(context))
+                    };
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            }),
+        )
+    }
+
+    fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+        future::ok(self.connectivity.status())
+    }
+
+    fn checkpoint(&self, id: &str, checkpoint_id: &str) -> Self::CheckpointFuture {
+        info!("Checkpointing module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::CheckpointModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let checkpoint_id = checkpoint_id.to_string();
+        let client = self.client.clone();
+
+        Box::new(
+            self.ensure_checkpoint_support(RuntimeOperation::CheckpointModule(id.clone()))
+                .and_then(move |_| {
+                    client
+                        .container_api()
+                        .container_checkpoint_create(
+                            &id,
+                            CheckpointCreateConfig::new(checkpoint_id.clone()).with_exit(true),
+                        )
+                        .then(move |result| match result {
+                            Ok(_) => {
+                                info!(
+                                    "Successfully checkpointed module {} as {}",
+                                    id, checkpoint_id
+                                );
+                                Ok(())
+                            }
+                            Err(err) => {
+                                let err = Error::from_docker_error(
+                                    err,
+                                    ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::CheckpointModule(id),
+                                    ),
+                                );
+                                log_failure(Level::Warn, &err);
+                                Err(err)
+                            }
+                        })
+                }),
+        )
+    }
+
+    fn restore(&self, id: &str, checkpoint_id: &str) -> Self::RestoreFuture {
+        info!("Restoring module {} from checkpoint...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::RestoreModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let checkpoint_id = checkpoint_id.to_string();
+        let client = self.client.clone();
+
+        Box::new(
+            self.ensure_checkpoint_support(RuntimeOperation::RestoreModule(id.clone()))
+                .and_then(move |_| {
+                    client
+                        .container_api()
+                        .container_start(&id, "", &checkpoint_id)
+                        .then(move |result| match result {
+                            Ok(_) => {
+                                info!(
+                                    "Successfully restored module {} from checkpoint {}",
+                                    id, checkpoint_id
+                                );
+                                Ok(())
+                            }
+                            Err(err) => {
+                                let err = Error::from_docker_error(
+                                    err,
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::RestoreModule(
+                                        id,
+                                    )),
+                                );
+                                log_failure(Level::Warn, &err);
+                                Err(err)
+                            }
+                        })
+                }),
+        )
+    }
+
+    fn list_checkpoints(&self, id: &str) -> Self::ListCheckpointsFuture {
+        info!("Listing checkpoints of module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::ListModuleCheckpoints(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let client = self.client.clone();
+
+        Box::new(
+            self.ensure_checkpoint_support(RuntimeOperation::ListModuleCheckpoints(id.clone()))
+                .and_then(move |_| {
+                    client
+                        .container_api()
+                        .container_checkpoint_list(&id)
+                        .then(move |result| match result {
+                            Ok(checkpoints) => {
+                                info!("Successfully listed checkpoints of module {}", id);
+                                Ok(checkpoints
+                                    .into_iter()
+                                    .map(|checkpoint| checkpoint.name().to_string())
+                                    .collect())
+                            }
+                            Err(err) => {
+                                let err = Error::from_docker_error(
+                                    err,
+                                    ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::ListModuleCheckpoints(id),
+                                    ),
+                                );
+                                log_failure(Level::Warn, &err);
+                                Err(err)
+                            }
+                        })
+                }),
+        )
+    }
+
+    fn get_volumes(&self, id: &str) -> Self::VolumesFuture {
+        debug!("Getting volumes of module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleVolumes(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(
+            self.client
+                .container_api()
+                .container_inspect(&id, false)
+                .then(|result| match result {
+                    Ok(container) => {
+                        debug!("Successfully got volumes of module {}", id);
+                        Ok(parse_volumes(&container))
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleVolumes(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn snapshot(&self) -> Self::SnapshotFuture {
+        info!("Taking a snapshot of the system...");
+
+        let now = Utc::now();
+        let disk_usage = self
+            .client
+            .system_api()
+            .system_info()
+            .then(|result| -> Result<_> {
+                let system_info = result.map_err(|err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::Snapshot),
+                    )
+                })?;
+                let disk_path = system_info
+                    .docker_root_dir()
+                    .unwrap_or(resources::DEFAULT_DISK_PATH);
+                let disk_usage = resources::disk_usage(Path::new(disk_path))
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::Snapshot))?;
+                Ok(vec![disk_usage])
+            });
+        let modules = self
+            .list_with_details()
+            .map(|(module, state)| ModuleSnapshot::new(module.name().to_string(), state))
+            .collect();
+        let events = module_events(&self.client, &now.timestamp().to_string()).collect();
+
+        Box::new(
+            self.system_info()
+                .join5(self.system_resources(), disk_usage, modules, events)
+                .and_then(
+                    move |(system_info, system_resources, disk_usage, modules, events)| -> Result<_> {
+                        let network_interfaces = resources::network_interfaces()
+                            .context(ErrorKind::RuntimeOperation(RuntimeOperation::Snapshot))?;
+                        Ok(
+                            SystemSnapshot::new(now, system_info, system_resources)
+                                .with_disk_usage(disk_usage)
+                                .with_network_interfaces(network_interfaces)
+                                .with_modules(modules)
+                                .with_events(events),
+                        )
+                    },
+                )
+                .then(|result| match result {
+                    Ok(snapshot) => {
+                        info!("Successfully took a snapshot of the system");
+                        Ok(snapshot)
+                    }
+                    Err(err) => {
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn start_all(&self, specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+        Box::new(start_in_dependency_order(self.clone(), specs))
+    }
+
+    // The limits are applied with `tc` run in the container, so that they
+    // only shape the traffic of the module's own network namespace. This
+    // needs `tc` in the module's image and the `NET_ADMIN` capability.
+    fn set_bandwidth(
+        &self,
+        id: &str,
+        ingress_kbps: u32,
+        egress_kbps: u32,
+    ) -> Self::SetBandwidthFuture {
+        info!(
+            "Setting bandwidth limits of module {} to {} kbps in and {} kbps out...",
+            id, ingress_kbps, egress_kbps
+        );
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::SetModuleBandwidth(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        if !cfg!(target_os = "linux") {
+            let err = Error::from(ErrorKind::BandwidthNotSupported.context(
+                ErrorKind::RuntimeOperation(RuntimeOperation::SetModuleBandwidth(id)),
+            ));
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        let runtime = self.clone();
+        let cmd = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            traffic_control_script(ingress_kbps, egress_kbps),
+        ];
+        let context = {
+            let id = id.clone();
+            move || ErrorKind::RuntimeOperation(RuntimeOperation::SetModuleBandwidth(id.clone()))
+        };
+
+        let result = self
+            .client
+            .container_api()
+            .container_inspect(&id, false)
+            .then({
+                let context = context.clone();
+                move |result| match result {
+                    // With the host's network, `tc` would limit the host.
+                    Ok(ref container)
+                        if container.host_config().and_then(HostConfig::network_mode)
+                            == Some("host") =>
+                    {
+                        Err(Error::from(
+                            ErrorKind::BadParameter(
+                                "Bandwidth limits need a module with its own network".to_string(),
+                            )
+                            .context(context()),
+                        ))
+                    }
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(Error::from_docker_error(err, context())),
+                }
+            })
+            .and_then(move |()| runtime.exec(&id, &cmd, false).map(|output| (id, output)))
+            .and_then(move |(id, output)| {
+                Body::from(output)
+                    .concat2()
+                    .then(move |result| -> Result<()> {
+                        let output = result.with_context(|_| context())?;
+                        // `tc` prints nothing when it succeeds, and the script
+                        // sends its errors to stdout.
+                        let output = String::from_utf8_lossy(&output).trim().to_string();
+                        if output.is_empty() {
+                            info!("Successfully set bandwidth limits of module {}", id);
+                            Ok(())
+                        } else {
+                            Err(Error::from(
+                                ErrorKind::TrafficControl(output).context(context()),
+                            ))
+                        }
+                    })
+            })
+            .map_err(|err| {
+                log_failure(Level::Warn, &err);
+                err
+            });
+        Box::new(result)
+    }
+
+    fn pause(&self, id: &str) -> Self::PauseFuture {
+        info!("Pausing module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::PauseModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(self.client.container_api().container_pause(&id).then(
+            move |result| match result {
+                Ok(_) => {
+                    info!("Successfully paused module {}", id);
+                    Ok(())
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::PauseModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            },
+        ))
+    }
+
+    fn unpause(&self, id: &str) -> Self::UnpauseFuture {
+        info!("Unpausing module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::UnpauseModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(self.client.container_api().container_unpause(&id).then(
+            move |result| match result {
+                Ok(_) => {
+                    info!("Successfully unpaused module {}", id);
+                    Ok(())
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::UnpauseModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            },
+        ))
+    }
+
+    fn copy_to_container(
+        &self,
+        id: &str,
+        dest_path: &str,
+        content: Bytes,
+    ) -> Self::CopyToContainerFuture {
+        info!("Copying a file to {} in module {}...", dest_path, id);
+
+        let id = id.to_string();
+        let context = {
+            let id = id.clone();
+            move || ErrorKind::RuntimeOperation(RuntimeOperation::CopyFileToModule(id.clone()))
+        };
+
+        if let Err(err) = ensure_not_empty_with_context(&id, context.clone()) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        // Docker extracts the archive into the directory of the file.
+        let (dir, archive) = match file_archive(dest_path, &content) {
+            Ok(archive) => archive,
+            Err(err) => return Box::new(future::err(Error::from(err.context(context())))),
+        };
+        let dest_path = dest_path.to_string();
+
+        Box::new(
+            self.client
+                .container_api()
+                .put_container_archive(&id, &dir, archive, "true")
+                .then(move |result| match result {
+                    Ok(_) => {
+                        info!(
+                            "Successfully copied a file to {} in module {}",
+                            dest_path, id
+                        );
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(err, context());
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> Self::RenameFuture {
+        info!("Renaming module {} to {}...", old_name, new_name);
+
+        let old_name = old_name.to_string();
+        let new_name = new_name.to_string();
+        let context = {
+            let old_name = old_name.clone();
+            move || ErrorKind::RuntimeOperation(RuntimeOperation::RenameModule(old_name.clone()))
+        };
+
+        if let Err(err) = ensure_not_empty_with_context(&old_name, context.clone()) {
+            return Box::new(future::err(Error::from(err)));
+        }
+        if let Err(err) = ensure_not_empty_with_context(&new_name, context.clone()) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let runtime = self.clone();
+        let log_names = (old_name.clone(), new_name.clone());
+
+        let result = self
+            .client
+            .container_api()
+            .container_inspect(&old_name, false)
+            .map_err({
+                let old_name = old_name.clone();
+                move |err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(old_name)),
+                    )
+                }
+            })
+            .and_then(move |container| {
+                let running = *runtime_state(&container).status() == ModuleStatus::Running;
+                match stored_spec(&old_name, &container) {
+                    // The spec label has the name of the module and docker
+                    // can't change the labels of a container, so a module
+                    // with a spec is recreated from it under the new name.
+                    // The new container is created before the old one is
+                    // removed, so that the module is never lost.
+                    Ok(Some(spec)) => future::Either::A(
+                        runtime
+                            .create(spec.with_name(new_name.clone()))
+                            .and_then({
+                                let runtime = runtime.clone();
+                                let old_name = old_name.clone();
+                                move |_| {
+                                    if running {
+                                        future::Either::A(runtime.stop(&old_name, None))
+                                    } else {
+                                        future::Either::B(future::ok(()))
+                                    }
+                                }
+                            })
+                            .and_then({
+                                let runtime = runtime.clone();
+                                move |_| ModuleRuntime::remove(&runtime, &old_name)
+                            })
+                            .and_then(move |_| {
+                                if running {
+                                    future::Either::A(runtime.start(&new_name))
+                                } else {
+                                    future::Either::B(future::ok(()))
+                                }
+                            }),
+                    ),
+                    Ok(None) => future::Either::B(future::Either::A(
+                        runtime
+                            .client
+                            .container_api()
+                            .container_rename(&old_name, &new_name)
+                            .map_err(move |err| Error::from_docker_error(err, context())),
+                    )),
+                    Err(err) => future::Either::B(future::Either::B(future::err(err))),
+                }
+            });
+
+        Box::new(result.then(move |result| {
+            let (old_name, new_name) = log_names;
+            match result {
+                Ok(()) => {
+                    info!("Successfully renamed module {} to {}", old_name, new_name);
+                    Ok(())
+                }
+                Err(err) => {
+                    let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::RenameModule(old_name),
+                    )));
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            }
+        }))
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        CircuitState::Closed
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+            .with_exec(true)
+            .with_checkpoint(*CRIU_INSTALLED)
+            .with_signal(true)
+            .with_top(true)
+            .with_stats(true)
+            .with_update_resources(true)
+            .with_pull_image(true)
+            .with_prune_images(true)
+            .with_events(true)
+            .with_bandwidth(cfg!(target_os = "linux"))
+    }
+}
+
+/// The shell script that replaces the `tc` queueing disciplines of the
+/// container's interface with ones that limit its traffic to the rates. A rate
+/// of 0 leaves that direction unlimited.
+///
+/// Outgoing traffic is shaped by a token bucket, and incoming traffic over the
+/// rate is dropped by a policer so that the sender backs off. Both allow a
+/// burst of about 10ms of traffic, but at least 32 kbit.
+fn traffic_control_script(ingress_kbps: u32, egress_kbps: u32) -> String {
+    let burst = |kbps: u32| cmp::max(kbps / 100, 32);
+
+    // Deleting disciplines that aren't there fails, which is fine.
+    let mut script = format!(
+        "tc qdisc del dev {0} root 2>/dev/null; tc qdisc del dev {0} ingress 2>/dev/null; true",
+        CONTAINER_INTERFACE
+    );
+    if egress_kbps > 0 {
+        script.push_str(&format!(
+            " && tc qdisc add dev {} root tbf rate {}kbit burst {}kbit latency 400ms 2>&1",
+            CONTAINER_INTERFACE,
+            egress_kbps,
+            burst(egress_kbps)
+        ));
+    }
+    if ingress_kbps > 0 {
+        script.push_str(&format!(
+            " && tc qdisc add dev {0} handle ffff: ingress 2>&1 && tc filter add dev {0} parent ffff: protocol all u32 match u32 0 0 police rate {1}kbit burst {2}kbit drop flowid :1 2>&1",
+            CONTAINER_INTERFACE,
+            ingress_kbps,
+            burst(ingress_kbps)
+        ));
+    }
+    script
+}
+
+/// The directory of the absolute `dest_path` of a file in a container, and a
+/// tar archive holding only that file with `content`, as Docker takes files
+/// to put into containers. The path is split on `/` rather than as a path of
+/// the host, since containers on Windows hosts are mostly Linux containers.
+#[allow(clippy::cast_sign_loss)]
+fn file_archive(dest_path: &str, content: &[u8]) -> Result<(String, Vec<u8>)> {
+    let mut parts = dest_path.rsplitn(2, '/');
+    let (name, dir) = match (parts.next(), parts.next()) {
+        (Some(name), Some(dir)) if !name.is_empty() => (name, dir),
+        _ => {
+            return Err(Error::from(ErrorKind::BadParameter(format!(
+                "{:?} is not an absolute path to a file",
+                dest_path
+            ))));
+        }
+    };
+    let dir = if dir.is_empty() { "/" } else { dir };
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    let mut builder = tar::Builder::new(vec![]);
+    builder
+        .append_data(&mut header, name, content)
+        .and_then(|()| builder.into_inner())
+        .map(|archive| (dir.to_string(), archive))
+        .map_err(|err| Error::from(err.context(ErrorKind::Docker)))
+}
+
+/// Joins the container to the network named in the module config, with the
+/// config's aliases. A config with aliases but no network uses the network of
+/// the create options, or else `default_network`. Endpoint settings already
+/// in the create options for that network are kept, and their aliases
+/// extended.
+fn with_network(
+    create_options: ContainerCreateBody,
+    config: &DockerConfig,
+    default_network: Option<&str>,
+) -> ContainerCreateBody {
+    let current_network = create_options
+        .host_config()
+        .and_then(HostConfig::network_mode)
+        .map(ToString::to_string);
+    let network = match (config.network_name(), config.network_aliases()) {
+        (Some(network), _) => network.to_string(),
+        (None, aliases) if !aliases.is_empty() => {
+            match current_network.or_else(|| default_network.map(ToString::to_string)) {
+                Some(network) => network,
+                None => return create_options,
+            }
+        }
+        _ => return create_options,
+    };
+
+    let mut endpoints = create_options
+        .networking_config()
+        .and_then(ContainerCreateBodyNetworkingConfig::endpoints_config)
+        .cloned()
+        .unwrap_or_else(HashMap::new);
+    // A container is created on a single network, so endpoints for other
+    // networks would only make the create fail.
+    endpoints.retain(|name, _| *name == network);
+    let mut endpoint = endpoints
+        .remove(&network)
+        .unwrap_or_else(EndpointSettings::new);
+    if !config.network_aliases().is_empty() {
+        let mut aliases = endpoint.aliases().map_or_else(Vec::new, <[String]>::to_vec);
+        aliases.extend(config.network_aliases().iter().cloned());
+        endpoint.set_aliases(aliases);
+    }
+    endpoints.insert(network.clone(), endpoint);
+
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new)
+        .with_network_mode(network);
+    create_options
+        .with_host_config(host_config)
+        .with_networking_config(
+            ContainerCreateBodyNetworkingConfig::new().with_endpoints_config(endpoints),
+        )
+}
+
+/// Applies the resource limits of a module spec on top of its create options,
+/// so that they take precedence over the limits in the create options.
+// Limits that don't fit the signed fields of the Docker API saturate.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn with_resource_limits(
+    create_options: ContainerCreateBody,
+    limits: &ResourceLimits,
+) -> ContainerCreateBody {
+    if limits.is_empty() {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    if let Some(cpu_shares) = limits.cpu_shares() {
+        host_config.set_cpu_shares(cpu_shares.min(i32::max_value() as u32) as i32);
+    }
+    if let Some(memory) = limits.memory_limit_bytes() {
+        host_config.set_memory(memory.min(i64::max_value() as u64) as i64);
+    }
+    if let Some(memory_swap) = limits.memory_swap_bytes() {
+        host_config.set_memory_swap(memory_swap);
+    }
+    create_options.with_host_config(host_config)
+}
+
+/// The update of a running container that applies the resource limits, with
+/// the same saturation as the limits applied when the container is created.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn container_update(limits: &ResourceLimits) -> ContainerUpdateUpdate {
+    let mut update = ContainerUpdateUpdate::new();
+    if let Some(cpu_shares) = limits.cpu_shares() {
+        update.set_cpu_shares(cpu_shares.min(i32::max_value() as u32) as i32);
+    }
+    if let Some(memory) = limits.memory_limit_bytes() {
+        update.set_memory(memory.min(i64::max_value() as u64) as i64);
+    }
+    if let Some(memory_swap) = limits.memory_swap_bytes() {
+        update.set_memory_swap(memory_swap);
+    }
+    update
+}
+
+/// Rotates the container's logs with the `json-file` driver when the module
+/// config has a log config, replacing any log config in the create options.
+fn with_log_config(
+    create_options: ContainerCreateBody,
+    config: &DockerConfig,
+) -> ContainerCreateBody {
+    let log_config = match config.log_config() {
+        Some(log_config) => log_config,
+        None => return create_options,
+    };
+
+    let mut options = HashMap::new();
+    if let Some(max_size) = log_config.max_size() {
+        options.insert("max-size".to_string(), max_size.to_string());
+    }
+    if let Some(max_file) = log_config.max_file() {
+        options.insert("max-file".to_string(), max_file.to_string());
+    }
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new)
+        .with_log_config(
+            HostConfigLogConfig::new()
+                .with__type("json-file".to_string())
+                .with_config(options),
+        );
+    create_options.with_host_config(host_config)
+}
+
+/// Creates the container's cgroup under the cgroup parent of the module
+/// config. Only the unified hierarchy of cgroups v2 accounts for the
+/// resources of a whole subtree at once, so a cgroup parent on a cgroups v1
+/// host is still applied but warned about.
+fn with_cgroup_parent(
+    create_options: ContainerCreateBody,
+    name: &str,
+    config: &DockerConfig,
+    cgroup_version: Option<CgroupVersion>,
+) -> ContainerCreateBody {
+    let cgroup_parent = match config.cgroup_parent() {
+        Some(cgroup_parent) => cgroup_parent,
+        None => return create_options,
+    };
+
+    if cgroup_version == Some(CgroupVersion::V1) {
+        warn!(
+            "Module {} has cgroup parent {}, but the host uses cgroups v1, so the resources of its \
+             cgroup parent are not accounted for in a unified hierarchy",
+            name, cgroup_parent
+        );
+    }
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new)
+        .with_cgroup_parent(cgroup_parent.to_string());
+    create_options.with_host_config(host_config)
+}
+
+/// Binds the directory with the module's trust bundle into the container,
+/// besides the binds of the module config. The directory rather than the
+/// bundle is bound, so that the container sees the bundle that replaces it
+/// after every change of the trust store, and so that a module can be
+/// created before anything is added to its trust store.
+fn with_trust_bundle(
+    create_options: ContainerCreateBody,
+    name: &str,
+    trust_store: &ModuleTrustStore,
+) -> ContainerCreateBody {
+    let bundle_dir = match trust_store.bundle_dir(name) {
+        Ok(bundle_dir) => bundle_dir,
+        Err(err) => {
+            warn!(
+                "Could not mount the trust bundle into module {}: {}",
+                name, err
+            );
+            return create_options;
+        }
+    };
+    if let Err(err) = fs::create_dir_all(&bundle_dir) {
+        warn!(
+            "Could not create the trust bundle directory {} of module {}: {}",
+            bundle_dir.display(),
+            name,
+            err
+        );
+        return create_options;
+    }
+
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    let mut binds = host_config
+        .binds()
+        .map_or_else(Vec::new, <[String]>::to_vec);
+    binds.push(format!(
+        "{}:{}:ro",
+        bundle_dir.display(),
+        TRUST_BUNDLE_MOUNT_PATH
+    ));
+    create_options.with_host_config(host_config.with_binds(binds))
+}
+
+/// cgroups v2 mount a single hierarchy with a `cgroup.controllers` file at
+/// its root, while cgroups v1 mount a hierarchy per controller below it. A
+/// host without cgroups, like Windows, has no version.
+fn cgroup_version(root: &Path) -> Option<CgroupVersion> {
+    if root.join("cgroup.controllers").is_file() {
+        Some(CgroupVersion::V2)
+    } else if root.is_dir() {
+        Some(CgroupVersion::V1)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub struct Logs(RuntimeOperation, Body);
+
+impl Stream for Logs {
+    type Item = Chunk;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.1.poll() {
+            Ok(Async::Ready(chunk)) => Ok(Async::Ready(chunk.map(Chunk))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => Err(Error::from(
+                err.context(ErrorKind::RuntimeOperation(self.0.clone())),
+            )),
+        }
+    }
+}
+
+impl From<Logs> for Body {
+    fn from(logs: Logs) -> Self {
+        logs.1
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk(HyperChunk);
+
+impl IntoIterator for Chunk {
+    type Item = u8;
+    type IntoIter = <HyperChunk as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Extend<u8> for Chunk {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = u8>,
+    {
+        self.0.extend(iter)
+    }
+}
+
+impl AsRef<[u8]> for Chunk {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+/// The events of modules up to `until`, a Unix timestamp, or for as long as
+/// the stream is polled if `until` is empty. Events Docker still holds from
+/// before the call are only returned when `until` is set.
+fn module_events(
+    client: &DockerClient<UrlConnector>,
+    until: &str,
+) -> Box<dyn Stream<Item = ModuleEvent, Error = Error> + Send> {
+    let mut filters = HashMap::new();
+    filters.insert("type", vec!["container"]);
+    filters.insert("event", events::ACTIONS.to_vec());
+    filters.insert("label", LABELS.clone());
+
+    let client_copy = client.clone();
+    let until = until.to_string();
+
+    let result = serde_json::to_string(&filters)
+        .context(ErrorKind::RuntimeOperation(RuntimeOperation::Events))
+        .map_err(Error::from)
+        .map(move |filters| {
+            client_copy
+                .system_api()
+                .system_events("", &until, &filters)
+                .map_err(|err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::Events),
+                    )
+                })
+                .map(ModuleEvents::new)
+                .flatten_stream()
+        });
+
+    match result {
+        Ok(events) => Box::new(events.map_err(|err| {
+            log_failure(Level::Warn, &err);
+            err
+        })),
+        Err(err) => {
+            log_failure(Level::Warn, &err);
+            Box::new(stream::once(Err(err)))
+        }
+    }
+}
+
+/// Invokes `ModuleRuntime::list`, then `Module::runtime_state` on each Module.
+/// Modules whose `runtime_state` returns `NotFound` are filtered out from the result,
+/// instead of letting the whole `list_with_details` call fail.
+fn list_with_details<MR, M>(
+    runtime: &MR,
+) -> Box<dyn Stream<Item = (M, ModuleRuntimeState), Error = Error> + Send>
+where
+    MR: ModuleRuntime<Error = Error, Config = <M as Module>::Config, Module = M>,
+    <MR as ModuleRuntime>::ListFuture: 'static,
+    M: Module<Error = Error> + Send + 'static,
+    <M as Module>::Config: Send,
+{
+    Box::new(
+        runtime
+            .list()
+            .into_stream()
+            .map(|list| {
+                stream::futures_unordered(
+                    list.into_iter()
+                        .map(|module| module.runtime_state().map(|state| (module, state))),
+                )
+            })
+            .flatten()
+            .then(Ok::<_, Error>) // Ok(_) -> Ok(Ok(_)), Err(_) -> Ok(Err(_)), ! -> Err(_)
+            .filter_map(|value| match value {
+                Ok(value) => Some(Ok(value)),
+                Err(err) => match err.kind() {
+                    ErrorKind::NotFound(_) => None,
+                    _ => Some(Err(err)),
+                },
+            })
+            .then(Result::unwrap), // Ok(Ok(_)) -> Ok(_), Ok(Err(_)) -> Err(_), Err(_) -> !
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use futures::future::FutureResult;
+    use futures::stream::Empty;
+    use serde_json::json;
+    #[cfg(unix)]
+    use tempfile::NamedTempFile;
+    use tokio;
+    use url::Url;
+
+    use docker::models::{
+        AuthConfig, ContainerConfig, ContainerCreateBody, HostConfig, InlineResponse200State,
+        MountPoint, NetworkSettings, PortBinding,
+    };
+    use edgelet_core::pid::Pid;
+    use edgelet_core::{ModuleRegistry, PruneImagesResult, PullProgress};
+
+    use crate::error::{Error, ErrorKind};
+
+    #[test]
+    #[should_panic(expected = "URL does not have a recognized scheme")]
+    fn invalid_uri_prefix_fails() {
+        let _mri =
             DockerModuleRuntime::new(&Url::parse("foo:///this/is/not/valid").unwrap()).unwrap();
     }
 
-    #[cfg(unix)]
     #[test]
-    #[should_panic(expected = "Socket file could not be found")]
-    fn invalid_uds_path_fails() {
-        let _mri =
-            DockerModuleRuntime::new(&Url::parse("unix:///this/file/does/not/exist").unwrap())
-                .unwrap();
+    fn log_times_are_whole_seconds() {
+        assert_eq!(0, unix_timestamp(None));
+        assert_eq!(
+            1_551_434_400,
+            unix_timestamp(Some(Utc.ymd(2019, 3, 1).and_hms_milli(10, 0, 0, 750)))
+        );
+        assert_eq!(
+            1,
+            unix_timestamp(Some(Utc.ymd(1960, 1, 1).and_hms(0, 0, 0)))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic(expected = "Socket file could not be found")]
+    fn invalid_uds_path_fails() {
+        let _mri =
+            DockerModuleRuntime::new(&Url::parse("unix:///this/file/does/not/exist").unwrap())
+                .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_with_uds_succeeds() {
+        let file = NamedTempFile::new().unwrap();
+        let file_path = file.path().to_str().unwrap();
+        let _mri = DockerModuleRuntime::new(&Url::parse(&format!("unix://{}", file_path)).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn connectivity_status_is_the_recorded_one() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+
+        mri.connectivity()
+            .disconnected("connection refused".to_string());
+        let status = mri.connectivity_status().wait().unwrap();
+
+        assert_eq!(
+            edgelet_core::ConnectivityState::Disconnected,
+            status.status()
+        );
+        assert_eq!(Some("connection refused"), status.last_error());
+    }
+
+    #[test]
+    fn image_remove_with_empty_name_fails() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = ModuleRegistry::remove(&mri, name).then(|res| match res {
+            Ok(_) => Err("Expected error but got a result.".to_string()),
+            Err(err) => match err.kind() {
+                ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(s)) if s == name => {
+                    Ok(())
+                }
+                kind => panic!(
+                    "Expected `RegistryOperation(RemoveImage)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn image_remove_with_white_space_name_fails() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "     ";
+
+        let task = ModuleRegistry::remove(&mri, name).then(|res| match res {
+            Ok(_) => Err("Expected error but got a result.".to_string()),
+            Err(err) => match err.kind() {
+                ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(s)) if s == name => {
+                    Ok(())
+                }
+                kind => panic!(
+                    "Expected `RegistryOperation(RemoveImage)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn resource_limits_override_create_options() {
+        let create_options = ContainerCreateBody::new().with_host_config(
+            HostConfig::new()
+                .with_memory(1024)
+                .with_network_mode("azure-iot-edge".to_string()),
+        );
+        let limits = ResourceLimits::new(Some(512), Some(2048), Some(-1)).unwrap();
+
+        let create_options = with_resource_limits(create_options, &limits);
+
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some(512), host_config.cpu_shares());
+        assert_eq!(Some(2048), host_config.memory());
+        assert_eq!(Some(-1), host_config.memory_swap());
+        assert_eq!(Some("azure-iot-edge"), host_config.network_mode());
+    }
+
+    #[test]
+    fn network_name_joins_network_with_aliases() {
+        let config = DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None)
+            .unwrap()
+            .with_network_name("sensors".to_string())
+            .with_network_aliases(vec!["temperature".to_string()]);
+
+        let create_options =
+            with_network(ContainerCreateBody::new(), &config, Some("azure-iot-edge"));
+
+        assert_eq!(
+            Some("sensors"),
+            create_options.host_config().unwrap().network_mode()
+        );
+        let endpoints = create_options
+            .networking_config()
+            .unwrap()
+            .endpoints_config()
+            .unwrap();
+        assert_eq!(1, endpoints.len());
+        assert_eq!(
+            Some(&["temperature".to_string()][..]),
+            endpoints["sensors"].aliases()
+        );
+    }
+
+    #[test]
+    fn network_aliases_extend_create_options() {
+        let mut endpoints = HashMap::new();
+        endpoints.insert(
+            "azure-iot-edge".to_string(),
+            EndpointSettings::new().with_aliases(vec!["temp".to_string()]),
+        );
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_network_mode("azure-iot-edge".to_string()))
+            .with_networking_config(
+                ContainerCreateBodyNetworkingConfig::new().with_endpoints_config(endpoints),
+            );
+        let config = DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None)
+            .unwrap()
+            .with_network_aliases(vec!["temperature".to_string()]);
+
+        let create_options = with_network(create_options, &config, Some("default-network"));
+
+        assert_eq!(
+            Some("azure-iot-edge"),
+            create_options.host_config().unwrap().network_mode()
+        );
+        let endpoints = create_options
+            .networking_config()
+            .unwrap()
+            .endpoints_config()
+            .unwrap();
+        assert_eq!(
+            Some(&["temp".to_string(), "temperature".to_string()][..]),
+            endpoints["azure-iot-edge"].aliases()
+        );
+    }
+
+    #[test]
+    fn no_network_config_leaves_create_options_alone() {
+        let config =
+            DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None).unwrap();
+
+        let create_options =
+            with_network(ContainerCreateBody::new(), &config, Some("azure-iot-edge"));
+
+        assert!(create_options.host_config().is_none());
+        assert!(create_options.networking_config().is_none());
+    }
+
+    #[test]
+    fn no_log_config_leaves_create_options_alone() {
+        let config =
+            DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None).unwrap();
+
+        let create_options = with_log_config(ContainerCreateBody::new(), &config);
+
+        assert!(create_options.host_config().is_none());
+    }
+
+    #[test]
+    fn cgroup_parent_is_set_in_host_config() {
+        let config = DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None)
+            .unwrap()
+            .with_cgroup_parent("/iotedge.slice".to_string())
+            .unwrap();
+        let create_options =
+            ContainerCreateBody::new().with_host_config(HostConfig::new().with_privileged(false));
+
+        let create_options =
+            with_cgroup_parent(create_options, "mod1", &config, Some(CgroupVersion::V1));
+
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some("/iotedge.slice"), host_config.cgroup_parent());
+        assert_eq!(Some(false), host_config.privileged().cloned());
+    }
+
+    #[test]
+    fn no_cgroup_parent_leaves_create_options_alone() {
+        let config =
+            DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None).unwrap();
+
+        let create_options = with_cgroup_parent(
+            ContainerCreateBody::new(),
+            "mod1",
+            &config,
+            Some(CgroupVersion::V2),
+        );
+
+        assert!(create_options.host_config().is_none());
+    }
+
+    #[test]
+    fn trust_bundle_is_bound_besides_other_binds() {
+        let dir = tempfile::tempdir().unwrap();
+        let trust_store = ModuleTrustStore::new(dir.path());
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_binds(vec!["/data:/data".to_string()]));
+
+        let create_options = with_trust_bundle(create_options, "mod1", &trust_store);
+
+        let bundle_dir = dir.path().join("mod1").join("bundle");
+        assert!(bundle_dir.is_dir());
+        assert_eq!(
+            Some(
+                &[
+                    "/data:/data".to_string(),
+                    format!("{}:/etc/iotedge/truststore:ro", bundle_dir.display()),
+                ][..]
+            ),
+            create_options.host_config().unwrap().binds()
+        );
+    }
+
+    #[test]
+    fn cgroup_version_is_read_from_hierarchy() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(Some(CgroupVersion::V1), cgroup_version(dir.path()));
+
+        std::fs::write(
+            dir.path().join("cgroup.controllers"),
+            "cpu io memory pids\n",
+        )
+        .unwrap();
+        assert_eq!(Some(CgroupVersion::V2), cgroup_version(dir.path()));
+
+        assert_eq!(None, cgroup_version(&dir.path().join("missing")));
+    }
+
+    #[test]
+    fn empty_resource_limits_leave_create_options_alone() {
+        let create_options =
+            with_resource_limits(ContainerCreateBody::new(), &ResourceLimits::default());
+
+        assert!(create_options.host_config().is_none());
+    }
+
+    #[test]
+    fn container_update_sets_only_given_limits() {
+        let limits = ResourceLimits::new(Some(512), Some(3 << 30), None).unwrap();
+
+        let update = container_update(&limits);
+
+        assert_eq!(Some(512), update.cpu_shares());
+        assert_eq!(Some(3 << 30), update.memory());
+        assert_eq!(None, update.memory_swap());
+    }
+
+    #[test]
+    fn merge_env_empty() {
+        let cur_env = Some(&[][..]);
+        let new_env = HashMap::new();
+        assert_eq!(0, DockerModuleRuntime::merge_env(cur_env, &new_env).len());
+    }
+
+    #[test]
+    fn merge_env_new_empty() {
+        let cur_env = Some(vec!["k1=v1".to_string(), "k2=v2".to_string()]);
+        let new_env = HashMap::new();
+        let mut merged_env =
+            DockerModuleRuntime::merge_env(cur_env.as_ref().map(AsRef::as_ref), &new_env);
+        merged_env.sort();
+        assert_eq!(vec!["k1=v1", "k2=v2"], merged_env);
+    }
+
+    #[test]
+    fn merge_env_extend_new() {
+        let cur_env = Some(vec!["k1=v1".to_string(), "k2=v2".to_string()]);
+        let mut new_env = HashMap::new();
+        new_env.insert("k3".to_string(), "v3".to_string());
+        let mut merged_env =
+            DockerModuleRuntime::merge_env(cur_env.as_ref().map(AsRef::as_ref), &new_env);
+        merged_env.sort();
+        assert_eq!(vec!["k1=v1", "k2=v2", "k3=v3"], merged_env);
+    }
+
+    #[test]
+    fn merge_env_extend_replace_new() {
+        let cur_env = Some(vec!["k1=v1".to_string(), "k2=v2".to_string()]);
+        let mut new_env = HashMap::new();
+        new_env.insert("k2".to_string(), "v02".to_string());
+        new_env.insert("k3".to_string(), "v3".to_string());
+        let mut merged_env =
+            DockerModuleRuntime::merge_env(cur_env.as_ref().map(AsRef::as_ref), &new_env);
+        merged_env.sort();
+        assert_eq!(vec!["k1=v1", "k2=v2", "k3=v3"], merged_env);
+    }
+
+    #[test]
+    fn create_fails_for_non_docker_type() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "not_docker".to_string();
+
+        let module_config = ModuleSpec::new(
+            "m1".to_string(),
+            name.clone(),
+            DockerConfig::new("nginx:latest".to_string(), ContainerCreateBody::new(), None)
+                .unwrap(),
+            HashMap::new(),
+        )
+        .unwrap();
+
+        let task = mri.create(module_config).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::InvalidModuleType(s) if s == &name => Ok::<_, Error>(()),
+                kind => panic!("Expected `InvalidModuleType` error but got {:?}.", kind),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn start_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = mri.start(name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn start_fails_for_white_space_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "      ";
+
+        let task = mri.start(name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn stop_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = mri.stop(name, None).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn stop_fails_for_white_space_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "     ";
+
+        let task = mri.stop(name, None).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn restart_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = mri.restart(name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn restart_fails_for_white_space_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "     ";
+
+        let task = mri.restart(name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn remove_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = ModuleRuntime::remove(&mri, name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(RemoveModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn remove_fails_for_white_space_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "    ";
+
+        let task = ModuleRuntime::remove(&mri, name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(RemoveModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
     }
 
-    #[cfg(unix)]
     #[test]
-    fn create_with_uds_succeeds() {
-        let file = NamedTempFile::new().unwrap();
-        let file_path = file.path().to_str().unwrap();
-        let _mri = DockerModuleRuntime::new(&Url::parse(&format!("unix://{}", file_path)).unwrap())
+    fn get_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = ModuleRuntime::get(&mri, name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(GetModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn get_fails_for_white_space_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "    ";
+
+        let task = ModuleRuntime::get(&mri, name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(GetModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn list_with_details_filters_out_deleted_containers() {
+        let runtime = TestModuleList {
+            modules: vec![
+                TestModule {
+                    name: "a".to_string(),
+                    runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
+                },
+                TestModule {
+                    name: "b".to_string(),
+                    runtime_state_behavior: TestModuleRuntimeStateBehavior::NotFound,
+                },
+                TestModule {
+                    name: "c".to_string(),
+                    runtime_state_behavior: TestModuleRuntimeStateBehavior::NotFound,
+                },
+                TestModule {
+                    name: "d".to_string(),
+                    runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
+                },
+            ],
+        };
+
+        assert_eq!(
+            runtime.list_with_details().collect().wait().unwrap(),
+            vec![
+                (
+                    TestModule {
+                        name: "a".to_string(),
+                        runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
+                    },
+                    ModuleRuntimeState::default().with_pid(Pid::Any)
+                ),
+                (
+                    TestModule {
+                        name: "d".to_string(),
+                        runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
+                    },
+                    ModuleRuntimeState::default().with_pid(Pid::Any)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = ModuleRuntime::top(&mri, name, None).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(TopModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn top_fails_for_white_space_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "    ";
+
+        let task = ModuleRuntime::top(&mri, name, None).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(TopModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
             .unwrap();
     }
 
     #[test]
-    fn image_remove_with_empty_name_fails() {
+    fn parse_get_response_returns_the_name() {
+        let response = InlineResponse200::new().with_name("hello".to_string());
+        let name = parse_get_response::<Deserializer>(&response);
+        assert!(name.is_ok());
+        assert_eq!("hello".to_string(), name.unwrap());
+    }
+
+    #[test]
+    fn parse_get_response_returns_error_when_name_is_missing() {
+        let response = InlineResponse200::new();
+        let name = parse_get_response::<Deserializer>(&response);
+        assert!(name.is_err());
+        assert_eq!("missing field `Name`", format!("{}", name.unwrap_err()));
+    }
+
+    #[test]
+    fn parse_top_response_returns_pid_array() {
+        let response = InlineResponse2001::new()
+            .with_titles(vec!["PID".to_string()])
+            .with_processes(vec![vec!["123".to_string()]]);
+        let pids = parse_top_response::<Deserializer>(&response);
+        assert!(pids.is_ok());
+        assert_eq!(vec![Pid::Value(123)], pids.unwrap());
+    }
+
+    #[test]
+    fn parse_top_response_returns_error_when_titles_is_missing() {
+        let response = InlineResponse2001::new().with_processes(vec![vec!["123".to_string()]]);
+        let pids = parse_top_response::<Deserializer>(&response);
+        assert!(pids.is_err());
+        assert_eq!("missing field `Titles`", format!("{}", pids.unwrap_err()));
+    }
+
+    #[test]
+    fn parse_top_response_returns_error_when_pid_title_is_missing() {
+        let response = InlineResponse2001::new().with_titles(vec!["Command".to_string()]);
+        let pids = parse_top_response::<Deserializer>(&response);
+        assert!(pids.is_err());
+        assert_eq!(
+            "invalid value: sequence, expected array including the column title \'PID\'",
+            format!("{}", pids.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn parse_top_response_returns_error_when_processes_is_missing() {
+        let response = InlineResponse2001::new().with_titles(vec!["PID".to_string()]);
+        let pids = parse_top_response::<Deserializer>(&response);
+        assert!(pids.is_err());
+        assert_eq!(
+            "missing field `Processes`",
+            format!("{}", pids.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn parse_top_response_returns_error_when_process_pid_is_missing() {
+        let response = InlineResponse2001::new()
+            .with_titles(vec!["Command".to_string(), "PID".to_string()])
+            .with_processes(vec![vec!["sh".to_string()]]);
+        let pids = parse_top_response::<Deserializer>(&response);
+        assert!(pids.is_err());
+        assert_eq!(
+            "invalid length 1, expected at least 2 columns",
+            format!("{}", pids.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn parse_top_response_returns_error_when_process_pid_is_not_i32() {
+        let response = InlineResponse2001::new()
+            .with_titles(vec!["PID".to_string()])
+            .with_processes(vec![vec!["xyz".to_string()]]);
+        let pids = parse_top_response::<Deserializer>(&response);
+        assert!(pids.is_err());
+        assert_eq!(
+            "invalid value: string \"xyz\", expected a process ID number",
+            format!("{}", pids.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn stats_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
         let name = "";
 
-        let task = ModuleRegistry::remove(&mri, name).then(|res| match res {
-            Ok(_) => Err("Expected error but got a result.".to_string()),
+        let task = ModuleRuntime::stats(&mri, name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(s)) if s == name => {
-                    Ok(())
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(s)) if s == name => {
+                    Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RegistryOperation(RemoveImage)` error but got {:?}.",
+                    "Expected `RuntimeOperation(GetModuleStats)` error but got {:?}.",
                     kind
                 ),
             },
@@ -888,18 +3656,44 @@ mod tests {
     }
 
     #[test]
-    fn image_remove_with_white_space_name_fails() {
+    fn parse_stats_response_computes_cpu_memory_and_network() {
+        let response = json!({
+            "cpu_stats": {
+                "cpu_usage": { "total_usage": 300 },
+                "system_cpu_usage": 2000,
+                "online_cpus": 2
+            },
+            "precpu_stats": {
+                "cpu_usage": { "total_usage": 100 },
+                "system_cpu_usage": 1000
+            },
+            "memory_stats": { "usage": 1024, "limit": 4096 },
+            "networks": {
+                "eth0": { "rx_bytes": 10, "tx_bytes": 20 },
+                "eth1": { "rx_bytes": 1, "tx_bytes": 2 }
+            }
+        });
+        let stats = parse_stats_response(&response);
+        assert!((stats.cpu_percent() - 40.0).abs() < std::f64::EPSILON);
+        assert_eq!(1024, stats.memory_usage_bytes());
+        assert_eq!(4096, stats.memory_limit_bytes());
+        assert_eq!(11, stats.network_rx_bytes());
+        assert_eq!(22, stats.network_tx_bytes());
+    }
+
+    #[test]
+    fn inspect_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "     ";
+        let name = "";
 
-        let task = ModuleRegistry::remove(&mri, name).then(|res| match res {
-            Ok(_) => Err("Expected error but got a result.".to_string()),
+        let task = ModuleRuntime::inspect(&mri, name).then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(s)) if s == name => {
-                    Ok(())
+                ErrorKind::RuntimeOperation(RuntimeOperation::InspectModule(s)) if s == name => {
+                    Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RegistryOperation(RemoveImage)` error but got {:?}.",
+                    "Expected `RuntimeOperation(InspectModule)` error but got {:?}.",
                     kind
                 ),
             },
@@ -912,64 +3706,70 @@ mod tests {
     }
 
     #[test]
-    fn merge_env_empty() {
-        let cur_env = Some(&[][..]);
-        let new_env = HashMap::new();
-        assert_eq!(0, DockerModuleRuntime::merge_env(cur_env, &new_env).len());
+    fn parse_inspect_response_prefers_the_edge_network_address() {
+        let mut networks = HashMap::new();
+        networks.insert(
+            "bridge".to_string(),
+            EndpointSettings::new().with_ip_address("172.17.0.2".to_string()),
+        );
+        networks.insert(
+            "azure-iot-edge".to_string(),
+            EndpointSettings::new().with_ip_address("172.18.0.3".to_string()),
+        );
+        let response = InlineResponse200::new()
+            .with_state(
+                InlineResponse200State::new()
+                    .with_status("exited".to_string())
+                    .with_exit_code(137),
+            )
+            .with_network_settings(
+                NetworkSettings::new()
+                    .with_ip_address(String::new())
+                    .with_networks(networks),
+            );
+
+        let details = parse_inspect_response("m1", &response, Some("azure-iot-edge"));
+
+        assert_eq!("m1", details.name());
+        assert_eq!(ModuleStatus::Failed, *details.status());
+        assert_eq!(Some(137), details.exit_code());
+        assert_eq!(Some("172.18.0.3".parse().unwrap()), details.ip_address());
     }
 
     #[test]
-    fn merge_env_new_empty() {
-        let cur_env = Some(vec!["k1=v1".to_string(), "k2=v2".to_string()]);
-        let new_env = HashMap::new();
-        let mut merged_env =
-            DockerModuleRuntime::merge_env(cur_env.as_ref().map(AsRef::as_ref), &new_env);
-        merged_env.sort();
-        assert_eq!(vec!["k1=v1", "k2=v2"], merged_env);
-    }
+    fn parse_inspect_response_falls_back_to_the_default_address() {
+        let response = InlineResponse200::new().with_network_settings(
+            NetworkSettings::new().with_ip_address("172.17.0.2".to_string()),
+        );
 
-    #[test]
-    fn merge_env_extend_new() {
-        let cur_env = Some(vec!["k1=v1".to_string(), "k2=v2".to_string()]);
-        let mut new_env = HashMap::new();
-        new_env.insert("k3".to_string(), "v3".to_string());
-        let mut merged_env =
-            DockerModuleRuntime::merge_env(cur_env.as_ref().map(AsRef::as_ref), &new_env);
-        merged_env.sort();
-        assert_eq!(vec!["k1=v1", "k2=v2", "k3=v3"], merged_env);
+        let details = parse_inspect_response("m1", &response, Some("azure-iot-edge"));
+
+        assert_eq!(ModuleStatus::Unknown, *details.status());
+        assert_eq!(None, details.exit_code());
+        assert_eq!(Some("172.17.0.2".parse().unwrap()), details.ip_address());
     }
 
     #[test]
-    fn merge_env_extend_replace_new() {
-        let cur_env = Some(vec!["k1=v1".to_string(), "k2=v2".to_string()]);
-        let mut new_env = HashMap::new();
-        new_env.insert("k2".to_string(), "v02".to_string());
-        new_env.insert("k3".to_string(), "v3".to_string());
-        let mut merged_env =
-            DockerModuleRuntime::merge_env(cur_env.as_ref().map(AsRef::as_ref), &new_env);
-        merged_env.sort();
-        assert_eq!(vec!["k1=v1", "k2=v2", "k3=v3"], merged_env);
+    fn parse_inspect_response_without_network_settings_has_no_address() {
+        let details = parse_inspect_response("m1", &InlineResponse200::new(), None);
+        assert_eq!(None, details.ip_address());
     }
 
     #[test]
-    fn create_fails_for_non_docker_type() {
+    fn env_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "not_docker".to_string();
-
-        let module_config = ModuleSpec::new(
-            "m1".to_string(),
-            name.clone(),
-            DockerConfig::new("nginx:latest".to_string(), ContainerCreateBody::new(), None)
-                .unwrap(),
-            HashMap::new(),
-        )
-        .unwrap();
+        let name = "";
 
-        let task = mri.create(module_config).then(|result| match result {
+        let task = ModuleRuntime::env(&mri, name).then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::InvalidModuleType(s) if s == &name => Ok::<_, Error>(()),
-                kind => panic!("Expected `InvalidModuleType` error but got {:?}.", kind),
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleEnv(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(GetModuleEnv)` error but got {:?}.",
+                    kind
+                ),
             },
         });
 
@@ -980,22 +3780,25 @@ mod tests {
     }
 
     #[test]
-    fn start_fails_for_empty_id() {
+    fn update_env_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
         let name = "";
 
-        let task = mri.start(name).then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
-                    kind
-                ),
-            },
-        });
+        let task =
+            ModuleRuntime::update_env(&mri, name, HashMap::new()).then(|result| match result {
+                Ok(_) => panic!("Expected test to fail but it didn't!"),
+                Err(err) => match err.kind() {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::UpdateModuleEnv(s))
+                        if s == name =>
+                    {
+                        Ok::<_, Error>(())
+                    }
+                    kind => panic!(
+                        "Expected `RuntimeOperation(UpdateModuleEnv)` error but got {:?}.",
+                        kind
+                    ),
+                },
+            });
 
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
@@ -1004,22 +3807,27 @@ mod tests {
     }
 
     #[test]
-    fn start_fails_for_white_space_id() {
+    fn update_resources_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "      ";
+        let name = "";
 
-        let task = mri.start(name).then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
-                    Ok::<_, Error>(())
+        let task =
+            ModuleRuntime::update_resources(&mri, name, ResourceLimits::default()).then(|result| {
+                match result {
+                    Ok(_) => panic!("Expected test to fail but it didn't!"),
+                    Err(err) => {
+                        match err.kind() {
+                            ErrorKind::RuntimeOperation(
+                                RuntimeOperation::UpdateModuleResources(s),
+                            ) if s == name => Ok::<_, Error>(()),
+                            kind => panic!(
+                        "Expected `RuntimeOperation(UpdateModuleResources)` error but got {:?}.",
+                        kind
+                    ),
+                        }
+                    }
                 }
-                kind => panic!(
-                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
-                    kind
-                ),
-            },
-        });
+            });
 
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
@@ -1028,18 +3836,18 @@ mod tests {
     }
 
     #[test]
-    fn stop_fails_for_empty_id() {
+    fn signal_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
         let name = "";
 
-        let task = mri.stop(name, None).then(|result| match result {
+        let task = mri.signal(name, 10).then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
+                ErrorKind::RuntimeOperation(RuntimeOperation::SignalModule(s)) if s == name => {
                     Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
+                    "Expected `RuntimeOperation(SignalModule)` error but got {:?}.",
                     kind
                 ),
             },
@@ -1052,22 +3860,40 @@ mod tests {
     }
 
     #[test]
-    fn stop_fails_for_white_space_id() {
+    fn pause_and_unpause_fail_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "     ";
+        let name = "";
 
-        let task = mri.stop(name, None).then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
-                    kind
-                ),
-            },
-        });
+        let task = mri
+            .pause(name)
+            .then(|result| match result {
+                Ok(_) => panic!("Expected test to fail but it didn't!"),
+                Err(err) => match err.kind() {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::PauseModule(s)) if s == name => {
+                        Ok::<_, Error>(())
+                    }
+                    kind => panic!(
+                        "Expected `RuntimeOperation(PauseModule)` error but got {:?}.",
+                        kind
+                    ),
+                },
+            })
+            .and_then(|()| {
+                mri.unpause(name).then(|result| match result {
+                    Ok(_) => panic!("Expected test to fail but it didn't!"),
+                    Err(err) => match err.kind() {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::UnpauseModule(s))
+                            if s == name =>
+                        {
+                            Ok::<_, Error>(())
+                        }
+                        kind => panic!(
+                            "Expected `RuntimeOperation(UnpauseModule)` error but got {:?}.",
+                            kind
+                        ),
+                    },
+                })
+            });
 
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
@@ -1076,22 +3902,26 @@ mod tests {
     }
 
     #[test]
-    fn restart_fails_for_empty_id() {
+    fn copy_to_container_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
         let name = "";
 
-        let task = mri.restart(name).then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
-                    kind
-                ),
-            },
-        });
+        let task = mri
+            .copy_to_container(name, "/etc/app.conf", Bytes::from("content"))
+            .then(|result| match result {
+                Ok(_) => panic!("Expected test to fail but it didn't!"),
+                Err(err) => match err.kind() {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::CopyFileToModule(s))
+                        if s == name =>
+                    {
+                        Ok::<_, Error>(())
+                    }
+                    kind => panic!(
+                        "Expected `RuntimeOperation(CopyFileToModule)` error but got {:?}.",
+                        kind
+                    ),
+                },
+            });
 
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
@@ -1100,18 +3930,17 @@ mod tests {
     }
 
     #[test]
-    fn restart_fails_for_white_space_id() {
+    fn rename_fails_for_empty_new_name() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "     ";
 
-        let task = mri.restart(name).then(|result| match result {
+        let task = mri.rename("mod1", "").then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
+                ErrorKind::RuntimeOperation(RuntimeOperation::RenameModule(s)) if s == "mod1" => {
                     Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
+                    "Expected `RuntimeOperation(RenameModule)` error but got {:?}.",
                     kind
                 ),
             },
@@ -1124,22 +3953,49 @@ mod tests {
     }
 
     #[test]
-    fn remove_fails_for_empty_id() {
+    fn file_archive_holds_the_file_for_its_directory() {
+        let (dir, archive) = file_archive("/etc/app/config.json", b"{}").unwrap();
+        assert_eq!("/etc/app", dir);
+        let mut archive = tar::Archive::new(&archive[..]);
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!("config.json", entry.path().unwrap().to_str().unwrap());
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut entry, &mut content).unwrap();
+        assert_eq!("{}", content);
+        drop(entry);
+        assert!(entries.next().is_none());
+
+        assert_eq!("/", file_archive("/app.conf", b"").unwrap().0);
+        for path in &["app.conf", "/etc/app/"] {
+            match file_archive(path, b"").unwrap_err().kind() {
+                ErrorKind::BadParameter(_) => (),
+                kind => panic!("Expected `BadParameter` error but got {:?}.", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn set_bandwidth_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
         let name = "";
 
-        let task = ModuleRuntime::remove(&mri, name).then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(RemoveModule)` error but got {:?}.",
-                    kind
-                ),
-            },
-        });
+        let task = mri
+            .set_bandwidth(name, 1024, 512)
+            .then(|result| match result {
+                Ok(_) => panic!("Expected test to fail but it didn't!"),
+                Err(err) => match err.kind() {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SetModuleBandwidth(s))
+                        if s == name =>
+                    {
+                        Ok::<_, Error>(())
+                    }
+                    kind => panic!(
+                        "Expected `RuntimeOperation(SetModuleBandwidth)` error but got {:?}.",
+                        kind
+                    ),
+                },
+            });
 
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
@@ -1148,22 +4004,45 @@ mod tests {
     }
 
     #[test]
-    fn remove_fails_for_white_space_id() {
+    fn traffic_control_script_limits_both_directions() {
+        assert_eq!(
+            "tc qdisc del dev eth0 root 2>/dev/null; tc qdisc del dev eth0 ingress 2>/dev/null; true \
+             && tc qdisc add dev eth0 root tbf rate 512kbit burst 32kbit latency 400ms 2>&1 \
+             && tc qdisc add dev eth0 handle ffff: ingress 2>&1 \
+             && tc filter add dev eth0 parent ffff: protocol all u32 match u32 0 0 police rate 10240kbit burst 102kbit drop flowid :1 2>&1",
+            traffic_control_script(10240, 512)
+        );
+    }
+
+    #[test]
+    fn traffic_control_script_resets_without_rates() {
+        assert_eq!(
+            "tc qdisc del dev eth0 root 2>/dev/null; tc qdisc del dev eth0 ingress 2>/dev/null; true",
+            traffic_control_script(0, 0)
+        );
+    }
+
+    #[test]
+    fn checkpoint_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "    ";
+        let name = "";
 
-        let task = ModuleRuntime::remove(&mri, name).then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(RemoveModule)` error but got {:?}.",
-                    kind
-                ),
-            },
-        });
+        let task = mri
+            .checkpoint(name, "checkpoint1")
+            .then(|result| match result {
+                Ok(_) => panic!("Expected test to fail but it didn't!"),
+                Err(err) => match err.kind() {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::CheckpointModule(s))
+                        if s == name =>
+                    {
+                        Ok::<_, Error>(())
+                    }
+                    kind => panic!(
+                        "Expected `RuntimeOperation(CheckpointModule)` error but got {:?}.",
+                        kind
+                    ),
+                },
+            });
 
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
@@ -1172,18 +4051,48 @@ mod tests {
     }
 
     #[test]
-    fn get_fails_for_empty_id() {
+    fn restore_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
         let name = "";
 
-        let task = ModuleRuntime::get(&mri, name).then(|result| match result {
+        let task = mri
+            .restore(name, "checkpoint1")
+            .then(|result| match result {
+                Ok(_) => panic!("Expected test to fail but it didn't!"),
+                Err(err) => match err.kind() {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::RestoreModule(s))
+                        if s == name =>
+                    {
+                        Ok::<_, Error>(())
+                    }
+                    kind => panic!(
+                        "Expected `RuntimeOperation(RestoreModule)` error but got {:?}.",
+                        kind
+                    ),
+                },
+            });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn list_checkpoints_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task = mri.list_checkpoints(name).then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(s)) if s == name => {
+                ErrorKind::RuntimeOperation(RuntimeOperation::ListModuleCheckpoints(s))
+                    if s == name =>
+                {
                     Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RuntimeOperation(GetModule)` error but got {:?}.",
+                    "Expected `RuntimeOperation(ListModuleCheckpoints)` error but got {:?}.",
                     kind
                 ),
             },
@@ -1196,18 +4105,18 @@ mod tests {
     }
 
     #[test]
-    fn get_fails_for_white_space_id() {
+    fn get_ports_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "    ";
+        let name = "";
 
-        let task = ModuleRuntime::get(&mri, name).then(|result| match result {
+        let task = mri.get_ports(name).then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(s)) if s == name => {
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModulePorts(s)) if s == name => {
                     Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RuntimeOperation(GetModule)` error but got {:?}.",
+                    "Expected `RuntimeOperation(GetModulePorts)` error but got {:?}.",
                     kind
                 ),
             },
@@ -1220,62 +4129,98 @@ mod tests {
     }
 
     #[test]
-    fn list_with_details_filters_out_deleted_containers() {
-        let runtime = TestModuleList {
-            modules: vec![
-                TestModule {
-                    name: "a".to_string(),
-                    runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
-                },
-                TestModule {
-                    name: "b".to_string(),
-                    runtime_state_behavior: TestModuleRuntimeStateBehavior::NotFound,
-                },
-                TestModule {
-                    name: "c".to_string(),
-                    runtime_state_behavior: TestModuleRuntimeStateBehavior::NotFound,
-                },
-                TestModule {
-                    name: "d".to_string(),
-                    runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
-                },
-            ],
-        };
+    fn wait_until_stopped_fails_for_empty_id() {
+        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
+        let name = "";
+
+        let task =
+            mri.wait_until_stopped(name, Duration::from_secs(1))
+                .then(|result| match result {
+                    Ok(_) => panic!("Expected test to fail but it didn't!"),
+                    Err(err) => match err.kind() {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::WaitUntilStopped(s))
+                            if s == name =>
+                        {
+                            Ok::<_, Error>(())
+                        }
+                        kind => panic!(
+                            "Expected `RuntimeOperation(WaitUntilStopped)` error but got {:?}.",
+                            kind
+                        ),
+                    },
+                });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    fn inspect_response_with_ports(running: bool) -> InlineResponse200 {
+        let mut ports = HashMap::new();
+        ports.insert(
+            "8080/tcp".to_string(),
+            Some(vec![
+                PortBinding::new()
+                    .with_host_ip("0.0.0.0".to_string())
+                    .with_host_port("8080".to_string()),
+                PortBinding::new()
+                    .with_host_ip("::".to_string())
+                    .with_host_port("8080".to_string()),
+            ]),
+        );
+        ports.insert("5671/tcp".to_string(), None);
+        ports.insert(
+            "53/udp".to_string(),
+            Some(vec![PortBinding::new()
+                .with_host_ip("127.0.0.1".to_string())
+                .with_host_port("5353".to_string())]),
+        );
+        InlineResponse200::new()
+            .with_state(InlineResponse200State::new().with_running(running))
+            .with_network_settings(NetworkSettings::new().with_ports(ports))
+    }
+
+    #[test]
+    fn parse_ports_lists_exposed_and_published_ports() {
+        let ports = parse_ports(&inspect_response_with_ports(true));
 
         assert_eq!(
-            runtime.list_with_details().collect().wait().unwrap(),
             vec![
-                (
-                    TestModule {
-                        name: "a".to_string(),
-                        runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
-                    },
-                    ModuleRuntimeState::default().with_pid(Pid::Any)
-                ),
-                (
-                    TestModule {
-                        name: "d".to_string(),
-                        runtime_state_behavior: TestModuleRuntimeStateBehavior::Default,
-                    },
-                    ModuleRuntimeState::default().with_pid(Pid::Any)
-                ),
-            ]
+                CorePortBinding::new(53, "udp".to_string())
+                    .with_host_ip(Some("127.0.0.1".to_string()))
+                    .with_host_port(Some(5353)),
+                CorePortBinding::new(5671, "tcp".to_string()),
+                CorePortBinding::new(8080, "tcp".to_string())
+                    .with_host_ip(Some("0.0.0.0".to_string()))
+                    .with_host_port(Some(8080)),
+                CorePortBinding::new(8080, "tcp".to_string())
+                    .with_host_ip(Some("::".to_string()))
+                    .with_host_port(Some(8080)),
+            ],
+            ports
         );
     }
 
     #[test]
-    fn top_fails_for_empty_id() {
+    fn parse_ports_is_empty_for_stopped_containers() {
+        assert!(parse_ports(&inspect_response_with_ports(false)).is_empty());
+        assert!(parse_ports(&InlineResponse200::new()).is_empty());
+    }
+
+    #[test]
+    fn get_volumes_fails_for_empty_id() {
         let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
         let name = "";
 
-        let task = ModuleRuntime::top(&mri, name).then(|result| match result {
+        let task = mri.get_volumes(name).then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(s)) if s == name => {
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleVolumes(s)) if s == name => {
                     Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RuntimeOperation(TopModule)` error but got {:?}.",
+                    "Expected `RuntimeOperation(GetModuleVolumes)` error but got {:?}.",
                     kind
                 ),
             },
@@ -1288,109 +4233,143 @@ mod tests {
     }
 
     #[test]
-    fn top_fails_for_white_space_id() {
-        let mri = DockerModuleRuntime::new(&Url::parse("http://localhost/").unwrap()).unwrap();
-        let name = "    ";
+    fn parse_volumes_lists_mounts_by_container_path() {
+        let response = InlineResponse200::new().with_mounts(vec![
+            MountPoint::new()
+                .with_source("/var/lib/edge/data".to_string())
+                .with_destination("/data".to_string())
+                .with_RW(true),
+            MountPoint::new()
+                .with_source("/etc/edge".to_string())
+                .with_destination("/config".to_string())
+                .with_RW(false),
+            MountPoint::new().with_source("/tmp".to_string()),
+        ]);
 
-        let task = ModuleRuntime::top(&mri, name).then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(TopModule)` error but got {:?}.",
-                    kind
+        assert_eq!(
+            vec![
+                VolumeMount::new(
+                    "/config".to_string(),
+                    "/etc/edge".to_string(),
+                    "ro".to_string()
                 ),
-            },
-        });
-
-        tokio::runtime::current_thread::Runtime::new()
-            .unwrap()
-            .block_on(task)
-            .unwrap();
+                VolumeMount::new(
+                    "/data".to_string(),
+                    "/var/lib/edge/data".to_string(),
+                    "rw".to_string()
+                ),
+            ],
+            parse_volumes(&response)
+        );
     }
 
     #[test]
-    fn parse_get_response_returns_the_name() {
-        let response = InlineResponse200::new().with_name("hello".to_string());
-        let name = parse_get_response::<Deserializer>(&response);
-        assert!(name.is_ok());
-        assert_eq!("hello".to_string(), name.unwrap());
+    fn parse_volumes_is_empty_without_mounts() {
+        assert!(parse_volumes(&InlineResponse200::new()).is_empty());
+        assert!(parse_volumes(&InlineResponse200::new().with_mounts(Vec::new())).is_empty());
     }
 
     #[test]
-    fn parse_get_response_returns_error_when_name_is_missing() {
-        let response = InlineResponse200::new();
-        let name = parse_get_response::<Deserializer>(&response);
-        assert!(name.is_err());
-        assert_eq!("missing field `Name`", format!("{}", name.unwrap_err()));
+    fn parse_env_splits_on_the_first_equals_sign() {
+        let env = parse_env(&[
+            "k1=v1".to_string(),
+            "k2=a=b".to_string(),
+            "k3=".to_string(),
+            "k4".to_string(),
+        ]);
+
+        assert_eq!(3, env.len());
+        assert_eq!("v1", env["k1"]);
+        assert_eq!("a=b", env["k2"]);
+        assert_eq!("", env["k3"]);
     }
 
-    #[test]
-    fn parse_top_response_returns_pid_array() {
-        let response = InlineResponse2001::new()
-            .with_titles(vec!["PID".to_string()])
-            .with_processes(vec![vec!["123".to_string()]]);
-        let pids = parse_top_response::<Deserializer>(&response);
-        assert!(pids.is_ok());
-        assert_eq!(vec![Pid::Value(123)], pids.unwrap());
+    fn docker_spec(env: &[(&str, &str)]) -> ModuleSpec<DockerConfig> {
+        let config = DockerConfig::new(
+            "nginx:latest".to_string(),
+            ContainerCreateBody::new().with_env(vec!["k1=v1".to_string()]),
+            Some(AuthConfig::new().with_password("secret".to_string())),
+        )
+        .unwrap();
+        let env = env
+            .iter()
+            .map(|&(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        ModuleSpec::new("mod1".to_string(), "docker".to_string(), config, env).unwrap()
     }
 
-    #[test]
-    fn parse_top_response_returns_error_when_titles_is_missing() {
-        let response = InlineResponse2001::new().with_processes(vec![vec!["123".to_string()]]);
-        let pids = parse_top_response::<Deserializer>(&response);
-        assert!(pids.is_err());
-        assert_eq!("missing field `Titles`", format!("{}", pids.unwrap_err()));
+    fn inspect_with_labels(labels: HashMap<String, String>) -> InlineResponse200 {
+        InlineResponse200::new().with_config(ContainerConfig::new().with_labels(labels))
     }
 
     #[test]
-    fn parse_top_response_returns_error_when_pid_title_is_missing() {
-        let response = InlineResponse2001::new().with_titles(vec!["Command".to_string()]);
-        let pids = parse_top_response::<Deserializer>(&response);
-        assert!(pids.is_err());
+    fn spec_label_round_trips_without_auth() {
+        let label = spec_label(&docker_spec(&[("k2", "v2")])).unwrap();
+        assert!(!label.contains("secret"));
+
+        let mut labels = HashMap::new();
+        labels.insert(SPEC_LABEL_KEY.to_string(), label);
+        let spec = stored_spec("mod1", &inspect_with_labels(labels))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!("mod1", spec.name());
+        assert_eq!("nginx:latest", spec.config().image());
+        assert!(spec.config().auth().is_none());
         assert_eq!(
-            "invalid value: sequence, expected array including the column title \'PID\'",
-            format!("{}", pids.unwrap_err())
+            Some(&["k1=v1".to_string()][..]),
+            spec.config().create_options().env()
         );
+        assert_eq!("v2", spec.env()["k2"]);
     }
 
     #[test]
-    fn parse_top_response_returns_error_when_processes_is_missing() {
-        let response = InlineResponse2001::new().with_titles(vec!["PID".to_string()]);
-        let pids = parse_top_response::<Deserializer>(&response);
-        assert!(pids.is_err());
-        assert_eq!(
-            "missing field `Processes`",
-            format!("{}", pids.unwrap_err())
-        );
+    fn stored_spec_is_none_without_label() {
+        let spec = stored_spec("mod1", &inspect_with_labels(HashMap::new())).unwrap();
+        assert!(spec.is_none());
+        assert!(stored_spec("mod1", &InlineResponse200::new())
+            .unwrap()
+            .is_none());
     }
 
     #[test]
-    fn parse_top_response_returns_error_when_process_pid_is_missing() {
-        let response = InlineResponse2001::new()
-            .with_titles(vec!["Command".to_string(), "PID".to_string()])
-            .with_processes(vec![vec!["sh".to_string()]]);
-        let pids = parse_top_response::<Deserializer>(&response);
-        assert!(pids.is_err());
-        assert_eq!(
-            "invalid length 1, expected at least 2 columns",
-            format!("{}", pids.unwrap_err())
-        );
+    fn stored_spec_fails_for_malformed_label() {
+        let mut labels = HashMap::new();
+        labels.insert(SPEC_LABEL_KEY.to_string(), "{".to_string());
+
+        let err = stored_spec("mod1", &inspect_with_labels(labels)).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::MalformedSpecLabel(id) => assert_eq!("mod1", id),
+            kind => panic!("Expected `MalformedSpecLabel` error but got {:?}.", kind),
+        }
     }
 
     #[test]
-    fn parse_top_response_returns_error_when_process_pid_is_not_i32() {
-        let response = InlineResponse2001::new()
-            .with_titles(vec!["PID".to_string()])
-            .with_processes(vec![vec!["xyz".to_string()]]);
-        let pids = parse_top_response::<Deserializer>(&response);
-        assert!(pids.is_err());
-        assert_eq!(
-            "invalid value: string \"xyz\", expected a process ID number",
-            format!("{}", pids.unwrap_err())
-        );
+    fn spec_env_adds_the_env_of_the_spec_to_the_create_options() {
+        let env = spec_env(&docker_spec(&[("k1", "v01"), ("k2", "v2")])).unwrap();
+
+        assert_eq!(2, env.len());
+        assert_eq!("v1", env["k1"]);
+        assert_eq!("v2", env["k2"]);
+    }
+
+    #[test]
+    fn with_spec_env_replaces_the_env_of_the_spec() {
+        let mut env = HashMap::new();
+        env.insert("k3".to_string(), "v3".to_string());
+
+        let spec = with_spec_env(docker_spec(&[("k2", "v2")]), env.clone()).unwrap();
+
+        assert_eq!(&env, spec.env());
+        assert!(spec.config().create_options().env().is_none());
+        assert_eq!(env, spec_env(&spec).unwrap());
+    }
+
+    #[test]
+    fn parse_stats_response_defaults_missing_fields_to_zero() {
+        let stats = parse_stats_response(&json!({}));
+        assert_eq!(ModuleStats::default(), stats);
     }
 
     struct TestConfig;
@@ -1424,6 +4403,10 @@ mod tests {
             &TestConfig
         }
 
+        fn labels(&self) -> HashMap<String, String> {
+            HashMap::new()
+        }
+
         fn runtime_state(&self) -> Self::RuntimeStateFuture {
             match self.runtime_state_behavior {
                 TestModuleRuntimeStateBehavior::Default => {
@@ -1463,6 +4446,7 @@ mod tests {
         type ModuleRegistry = Self;
         type Chunk = String;
         type Logs = Empty<Self::Chunk, Self::Error>;
+        type RegistryAuth = ();
 
         type CreateFuture = FutureResult<(), Self::Error>;
         type GetFuture = FutureResult<(Self::Module, ModuleRuntimeState), Self::Error>;
@@ -1476,8 +4460,37 @@ mod tests {
         type StartFuture = FutureResult<(), Self::Error>;
         type StopFuture = FutureResult<(), Self::Error>;
         type SystemInfoFuture = FutureResult<CoreSystemInfo, Self::Error>;
+        type SystemResourcesFuture = FutureResult<SystemResources, Self::Error>;
         type RemoveAllFuture = FutureResult<(), Self::Error>;
         type TopFuture = FutureResult<ModuleTop, Self::Error>;
+        type StatsFuture = FutureResult<ModuleStats, Self::Error>;
+        type InspectFuture = FutureResult<ModuleDetails, Self::Error>;
+        type EnvFuture = FutureResult<HashMap<String, String>, Self::Error>;
+        type UpdateEnvFuture = FutureResult<(), Self::Error>;
+        type UpdateResourcesFuture = FutureResult<(), Self::Error>;
+        type UpsertFuture = FutureResult<(Self::Module, bool), Self::Error>;
+        type PingFuture = FutureResult<(), Self::Error>;
+        type EventStream = Empty<ModuleEvent, Self::Error>;
+        type GetConfigFuture = FutureResult<Option<ModuleSpec<Self::Config>>, Self::Error>;
+        type ExecFuture = FutureResult<Self::Logs, Self::Error>;
+        type PullImageStream = Empty<PullProgress, Self::Error>;
+        type PullImageFuture = FutureResult<Self::PullImageStream, Self::Error>;
+        type PruneImagesFuture = FutureResult<PruneImagesResult, Self::Error>;
+        type SignalFuture = FutureResult<(), Self::Error>;
+        type PortsFuture = FutureResult<Vec<CorePortBinding>, Self::Error>;
+        type WaitUntilStoppedFuture = FutureResult<(), Self::Error>;
+        type ConnectivityStatusFuture = FutureResult<ConnectivityStatus, Self::Error>;
+        type CheckpointFuture = FutureResult<(), Self::Error>;
+        type RestoreFuture = FutureResult<(), Self::Error>;
+        type ListCheckpointsFuture = FutureResult<Vec<String>, Self::Error>;
+        type VolumesFuture = FutureResult<Vec<VolumeMount>, Self::Error>;
+        type SnapshotFuture = FutureResult<SystemSnapshot, Self::Error>;
+        type StartAllFuture = FutureResult<Vec<StartResult>, CoreError>;
+        type SetBandwidthFuture = FutureResult<(), Self::Error>;
+        type PauseFuture = FutureResult<(), Self::Error>;
+        type UnpauseFuture = FutureResult<(), Self::Error>;
+        type CopyToContainerFuture = FutureResult<(), Self::Error>;
+        type RenameFuture = FutureResult<(), Self::Error>;
 
         fn init(&self) -> Self::InitFuture {
             unimplemented!()
@@ -1503,6 +4516,10 @@ mod tests {
             unimplemented!()
         }
 
+        fn system_resources(&self) -> Self::SystemResourcesFuture {
+            unimplemented!()
+        }
+
         fn restart(&self, _id: &str) -> Self::RestartFuture {
             unimplemented!()
         }
@@ -1531,8 +4548,146 @@ mod tests {
             unimplemented!()
         }
 
-        fn top(&self, _id: &str) -> Self::TopFuture {
+        fn top(&self, _id: &str, _ps_args: Option<&str>) -> Self::TopFuture {
+            unimplemented!()
+        }
+
+        fn stats(&self, _id: &str) -> Self::StatsFuture {
+            unimplemented!()
+        }
+
+        fn inspect(&self, _id: &str) -> Self::InspectFuture {
+            unimplemented!()
+        }
+
+        fn env(&self, _id: &str) -> Self::EnvFuture {
+            unimplemented!()
+        }
+
+        fn update_env(&self, _id: &str, _env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+            unimplemented!()
+        }
+
+        fn update_resources(
+            &self,
+            _id: &str,
+            _limits: ResourceLimits,
+        ) -> Self::UpdateResourcesFuture {
+            unimplemented!()
+        }
+
+        fn upsert(&self, _module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
+            unimplemented!()
+        }
+
+        fn ping(&self) -> Self::PingFuture {
             unimplemented!()
         }
+
+        fn events(&self) -> Self::EventStream {
+            unimplemented!()
+        }
+
+        fn get_config(&self, _id: &str) -> Self::GetConfigFuture {
+            unimplemented!()
+        }
+
+        fn exec(&self, _id: &str, _cmd: &[String], _tty: bool) -> Self::ExecFuture {
+            unimplemented!()
+        }
+
+        fn pull_image(
+            &self,
+            _image: &str,
+            _auth: Option<Self::RegistryAuth>,
+        ) -> Self::PullImageFuture {
+            unimplemented!()
+        }
+
+        fn prune_images(&self, _dangling_only: bool) -> Self::PruneImagesFuture {
+            unimplemented!()
+        }
+
+        fn signal(&self, _id: &str, _signal: u32) -> Self::SignalFuture {
+            unimplemented!()
+        }
+
+        fn get_ports(&self, _id: &str) -> Self::PortsFuture {
+            unimplemented!()
+        }
+
+        fn wait_until_stopped(
+            &self,
+            _id: &str,
+            _timeout: Duration,
+        ) -> Self::WaitUntilStoppedFuture {
+            unimplemented!()
+        }
+
+        fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+            unimplemented!()
+        }
+
+        fn checkpoint(&self, _id: &str, _checkpoint_id: &str) -> Self::CheckpointFuture {
+            unimplemented!()
+        }
+
+        fn restore(&self, _id: &str, _checkpoint_id: &str) -> Self::RestoreFuture {
+            unimplemented!()
+        }
+
+        fn list_checkpoints(&self, _id: &str) -> Self::ListCheckpointsFuture {
+            unimplemented!()
+        }
+
+        fn get_volumes(&self, _id: &str) -> Self::VolumesFuture {
+            unimplemented!()
+        }
+
+        fn snapshot(&self) -> Self::SnapshotFuture {
+            unimplemented!()
+        }
+
+        fn start_all(&self, _specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+            unimplemented!()
+        }
+
+        fn set_bandwidth(
+            &self,
+            _id: &str,
+            _ingress_kbps: u32,
+            _egress_kbps: u32,
+        ) -> Self::SetBandwidthFuture {
+            unimplemented!()
+        }
+
+        fn pause(&self, _id: &str) -> Self::PauseFuture {
+            unimplemented!()
+        }
+
+        fn unpause(&self, _id: &str) -> Self::UnpauseFuture {
+            unimplemented!()
+        }
+
+        fn copy_to_container(
+            &self,
+            _id: &str,
+            _dest_path: &str,
+            _content: Bytes,
+        ) -> Self::CopyToContainerFuture {
+            unimplemented!()
+        }
+
+        fn rename(&self, _old_name: &str, _new_name: &str) -> Self::RenameFuture {
+            unimplemented!()
+        }
+
+        fn circuit_state(&self) -> CircuitState {
+            CircuitState::Closed
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
     }
 }
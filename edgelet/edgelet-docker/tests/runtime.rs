@@ -25,7 +25,7 @@ use docker::models::{
     HostConfig, HostConfigPortBindings, ImageDeleteResponseItem,
 };
 use edgelet_core::{LogOptions, LogTail, Module, ModuleRegistry, ModuleRuntime, ModuleSpec};
-use edgelet_docker::{DockerConfig, DockerModuleRuntime};
+use edgelet_docker::{DockerConfig, DockerLogConfig, DockerModuleRuntime};
 use edgelet_test_utils::{get_unused_tcp_port, run_tcp_server};
 
 const IMAGE_NAME: &str = "nginx:latest";
@@ -563,6 +563,13 @@ fn container_create_handler(
                     assert!(create_options.env().unwrap().contains(&v.to_string()));
                 }
 
+                let labels = create_options.labels().unwrap();
+                assert_eq!("web", labels["app"]);
+                assert_eq!(
+                    "Microsoft.Azure.Devices.Edge.Agent",
+                    labels["net.azure-devices.edge.owner"]
+                );
+
                 let port_bindings = create_options
                     .host_config()
                     .unwrap()
@@ -596,6 +603,8 @@ fn container_create_handler(
                 expected.insert("test1".to_string(), json!({}));
                 assert_eq!(*volumes, expected);
 
+                assert!(create_options.host_config().unwrap().log_config().is_none());
+
                 Ok(())
             })
             .map(move |_| {
@@ -622,6 +631,9 @@ fn container_create_succeeds() {
     env.insert("k2".to_string(), "v2".to_string());
     env.insert("k3".to_string(), "v3".to_string());
 
+    let mut labels = HashMap::new();
+    labels.insert("app".to_string(), "web".to_string());
+
     // add some create options
     let mut port_bindings = HashMap::new();
     port_bindings.insert(
@@ -658,7 +670,8 @@ fn container_create_succeeds() {
         DockerConfig::new("nginx:latest".to_string(), create_options, None).unwrap(),
         env,
     )
-    .unwrap();
+    .unwrap()
+    .with_labels(labels);
 
     let mri =
         DockerModuleRuntime::new(&Url::parse(&format!("http://localhost:{}/", port)).unwrap())
@@ -672,6 +685,80 @@ fn container_create_succeeds() {
     runtime.block_on(task).unwrap();
 }
 
+fn container_create_with_log_config_handler(
+    req: Request<Body>,
+) -> Box<dyn Future<Item = Response<Body>, Error = HyperError> + Send> {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/create");
+
+    let response = json!({
+        "Id": "12345",
+        "Warnings": []
+    })
+    .to_string();
+    let response_len = response.len();
+
+    Box::new(
+        req.into_body()
+            .concat2()
+            .and_then(|body| {
+                let create_options: ContainerCreateBody =
+                    serde_json::from_slice(body.as_ref()).unwrap();
+
+                let log_config = create_options.host_config().unwrap().log_config().unwrap();
+                assert_eq!(Some("json-file"), log_config._type());
+                let mut expected = HashMap::new();
+                expected.insert("max-size".to_string(), "10m".to_string());
+                expected.insert("max-file".to_string(), "5".to_string());
+                assert_eq!(Some(&expected), log_config.config());
+
+                Ok(())
+            })
+            .map(move |_| {
+                let mut response = Response::new(response.into());
+                response
+                    .headers_mut()
+                    .typed_insert(&ContentLength(response_len as u64));
+                response
+                    .headers_mut()
+                    .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                response
+            }),
+    )
+}
+
+#[test]
+fn container_create_with_log_config_succeeds() {
+    let port = get_unused_tcp_port();
+    let server = run_tcp_server("127.0.0.1", port, container_create_with_log_config_handler)
+        .map_err(|err| eprintln!("{}", err));
+
+    let config = DockerConfig::new("nginx:latest".to_string(), ContainerCreateBody::new(), None)
+        .unwrap()
+        .with_log_config(
+            DockerLogConfig::new()
+                .with_max_size("10m".to_string())
+                .with_max_file(5),
+        );
+    let module_config = ModuleSpec::new(
+        "m1".to_string(),
+        "docker".to_string(),
+        config,
+        HashMap::new(),
+    )
+    .unwrap();
+
+    let mri =
+        DockerModuleRuntime::new(&Url::parse(&format!("http://localhost:{}/", port)).unwrap())
+            .unwrap();
+
+    let task = mri.create(module_config);
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn container_start_handler(
     req: Request<Body>,
@@ -1255,3 +1342,235 @@ fn runtime_system_info_none_returns_unkown() {
     assert_eq!("Unknown", system_info.os_type());
     assert_eq!("Unknown", system_info.architecture());
 }
+
+fn json_response(body: &serde_json::Value) -> Response<Body> {
+    let body = body.to_string();
+    let mut response = Response::new(body.clone().into());
+    response
+        .headers_mut()
+        .typed_insert(&ContentLength(body.len() as u64));
+    response
+        .headers_mut()
+        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+    response
+}
+
+fn env_spec() -> ModuleSpec<DockerConfig> {
+    let config = DockerConfig::new(
+        IMAGE_NAME.to_string(),
+        ContainerCreateBody::new().with_env(vec!["k1=v1".to_string()]),
+        None,
+    )
+    .unwrap()
+    .with_network_name("azure-iot-edge".to_string())
+    .with_network_aliases(vec!["web".to_string()]);
+    let mut env = HashMap::new();
+    env.insert("k2".to_string(), "v2".to_string());
+    ModuleSpec::new("m1".to_string(), "docker".to_string(), config, env).unwrap()
+}
+
+fn env_inspect_response() -> Response<Body> {
+    json_response(&json!({
+        "Id": "12345",
+        "Name": "/m1",
+        "Config": {
+            "Image": IMAGE_NAME,
+            "Env": ["PATH=/usr/bin", "k1=v1", "k2=v2"],
+            "Labels": {
+                "net.azure-devices.edge.spec": serde_json::to_string(&env_spec()).unwrap(),
+            },
+        },
+        "State": {
+            "Status": "exited",
+        },
+    }))
+}
+
+#[test]
+fn env_is_the_env_of_the_spec() {
+    let port = get_unused_tcp_port();
+    let server = run_tcp_server("127.0.0.1", port, |req: Request<Body>| {
+        assert_eq!(req.method(), &Method::GET);
+        assert_eq!(req.uri().path(), "/containers/m1/json");
+        future::ok(env_inspect_response())
+    })
+    .map_err(|err| eprintln!("{}", err));
+
+    let mri =
+        DockerModuleRuntime::new(&Url::parse(&format!("http://localhost:{}/", port)).unwrap())
+            .unwrap();
+
+    let task = ModuleRuntime::env(&mri, "m1");
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let env = runtime.block_on(task).unwrap();
+
+    let mut expected = HashMap::new();
+    expected.insert("k1".to_string(), "v1".to_string());
+    expected.insert("k2".to_string(), "v2".to_string());
+    assert_eq!(expected, env);
+}
+
+#[test]
+fn update_env_recreates_the_module_from_its_spec() {
+    let requests = Arc::new(RwLock::new(Vec::new()));
+    let port = get_unused_tcp_port();
+    let server = {
+        let requests = requests.clone();
+        run_tcp_server("127.0.0.1", port, move |req: Request<Body>| {
+            requests
+                .write()
+                .unwrap()
+                .push(format!("{} {}", req.method(), req.uri().path()));
+            let response: Box<dyn Future<Item = _, Error = _> + Send> =
+                match (req.method(), req.uri().path()) {
+                    (&Method::GET, "/containers/m1/json") => {
+                        Box::new(future::ok(env_inspect_response()))
+                    }
+                    (&Method::DELETE, "/containers/m1") => {
+                        Box::new(future::ok(Response::new(Body::empty())))
+                    }
+                    (&Method::POST, "/containers/create") => {
+                        Box::new(req.into_body().concat2().map(|body| {
+                            let create_options: ContainerCreateBody =
+                                serde_json::from_slice(body.as_ref()).unwrap();
+
+                            assert_eq!(Some(&["k3=v3".to_string()][..]), create_options.env());
+                            let endpoints = create_options
+                                .networking_config()
+                                .unwrap()
+                                .endpoints_config()
+                                .unwrap();
+                            assert_eq!(
+                                Some(&["web".to_string()][..]),
+                                endpoints["azure-iot-edge"].aliases()
+                            );
+                            let spec: ModuleSpec<DockerConfig> = serde_json::from_str(
+                                &create_options.labels().unwrap()["net.azure-devices.edge.spec"],
+                            )
+                            .unwrap();
+                            assert_eq!("v3", spec.env()["k3"]);
+                            assert!(spec.config().create_options().env().is_none());
+
+                            json_response(&json!({ "Id": "12345", "Warnings": [] }))
+                        }))
+                    }
+                    (method, path) => panic!("Unexpected request {} {}", method, path),
+                };
+            response
+        })
+        .map_err(|err| eprintln!("{}", err))
+    };
+
+    let mri =
+        DockerModuleRuntime::new(&Url::parse(&format!("http://localhost:{}/", port)).unwrap())
+            .unwrap();
+
+    let mut env = HashMap::new();
+    env.insert("k3".to_string(), "v3".to_string());
+    let task = ModuleRuntime::update_env(&mri, "m1", env);
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    assert_eq!(
+        vec![
+            "GET /containers/m1/json",
+            "DELETE /containers/m1",
+            "POST /containers/create",
+        ],
+        *requests.read().unwrap()
+    );
+}
+
+#[test]
+fn rename_recreates_the_module_with_the_new_name() {
+    let requests = Arc::new(RwLock::new(Vec::new()));
+    let created = Arc::new(RwLock::new(None));
+    let port = get_unused_tcp_port();
+    let server = {
+        let requests = requests.clone();
+        let created = created.clone();
+        run_tcp_server("127.0.0.1", port, move |req: Request<Body>| {
+            requests
+                .write()
+                .unwrap()
+                .push(format!("{} {}", req.method(), req.uri().path()));
+            let created = created.clone();
+            let response: Box<dyn Future<Item = _, Error = _> + Send> =
+                match (req.method(), req.uri().path()) {
+                    (&Method::GET, "/containers/m1/json") => {
+                        Box::new(future::ok(env_inspect_response()))
+                    }
+                    (&Method::GET, "/containers/m2/json") => {
+                        let label = created.read().unwrap().clone().unwrap();
+                        Box::new(future::ok(json_response(&json!({
+                            "Id": "67890",
+                            "Name": "/m2",
+                            "Config": {
+                                "Image": IMAGE_NAME,
+                                "Labels": { "net.azure-devices.edge.spec": label },
+                            },
+                            "State": { "Status": "exited" },
+                        }))))
+                    }
+                    (&Method::DELETE, _) => Box::new(future::ok(Response::new(Body::empty()))),
+                    (&Method::POST, "/containers/create") => {
+                        assert_eq!(Some("name=m2"), req.uri().query());
+                        Box::new(req.into_body().concat2().map(move |body| {
+                            let create_options: ContainerCreateBody =
+                                serde_json::from_slice(body.as_ref()).unwrap();
+                            let label = create_options.labels().unwrap()
+                                ["net.azure-devices.edge.spec"]
+                                .clone();
+                            *created.write().unwrap() = Some(label);
+
+                            json_response(&json!({ "Id": "67890", "Warnings": [] }))
+                        }))
+                    }
+                    (method, path) => panic!("Unexpected request {} {}", method, path),
+                };
+            response
+        })
+        .map_err(|err| eprintln!("{}", err))
+    };
+
+    let mri =
+        DockerModuleRuntime::new(&Url::parse(&format!("http://localhost:{}/", port)).unwrap())
+            .unwrap();
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(mri.rename("m1", "m2")).unwrap();
+
+    let spec = runtime
+        .block_on(ModuleRuntime::get_config(&mri, "m2"))
+        .unwrap()
+        .unwrap();
+    assert_eq!("m2", spec.name());
+
+    let mut env = HashMap::new();
+    env.insert("k3".to_string(), "v3".to_string());
+    runtime
+        .block_on(ModuleRuntime::update_env(&mri, "m2", env))
+        .unwrap();
+
+    assert_eq!(
+        vec![
+            "GET /containers/m1/json",
+            "POST /containers/create",
+            "DELETE /containers/m1",
+            "GET /containers/m2/json",
+            "GET /containers/m2/json",
+            "DELETE /containers/m2",
+            "POST /containers/create",
+        ],
+        *requests.read().unwrap()
+    );
+    let spec: ModuleSpec<DockerConfig> =
+        serde_json::from_str(created.read().unwrap().as_ref().unwrap()).unwrap();
+    assert_eq!("m2", spec.name());
+    assert_eq!("v3", spec.env()["k3"]);
+}
@@ -1,10 +1,12 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use bytes::Bytes;
 use failure::{Fail, ResultExt};
 use futures::future::{self, FutureResult};
 use futures::prelude::*;
@@ -17,7 +19,10 @@ use serde_json;
 use url::Url;
 
 use edgelet_core::*;
-use edgelet_core::{ModuleOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, UrlExt};
+use edgelet_core::{
+    Error as CoreError, ErrorKind as CoreErrorKind, ModuleDetails as CoreModuleDetails,
+    ModuleOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, UrlExt,
+};
 use edgelet_docker::{self, DockerConfig};
 use edgelet_http::{UrlConnector, API_VERSION};
 
@@ -95,6 +100,10 @@ impl Module for ModuleDetails {
         &self.1
     }
 
+    fn labels(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
     fn runtime_state(&self) -> Self::RuntimeStateFuture {
         future::result(runtime_status(&self.0))
     }
@@ -142,6 +151,12 @@ impl ModuleRegistry for ModuleClient {
     }
 }
 
+/// The module client only implements what iotedge needs of the management
+/// API, so the other operations fail with this error.
+fn not_implemented(operation: &'static str) -> Error {
+    Error::from(ErrorKind::NotImplemented(operation))
+}
+
 impl ModuleRuntime for ModuleClient {
     type Error = Error;
     type Config = ModuleConfig;
@@ -149,6 +164,7 @@ impl ModuleRuntime for ModuleClient {
     type ModuleRegistry = Self;
     type Chunk = Chunk;
     type Logs = Logs;
+    type RegistryAuth = ();
 
     type CreateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type GetFuture =
@@ -163,13 +179,50 @@ impl ModuleRuntime for ModuleClient {
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type SystemInfoFuture = Box<dyn Future<Item = CoreSystemInfo, Error = Self::Error> + Send>;
+    type SystemResourcesFuture =
+        Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type TopFuture = Box<dyn Future<Item = ModuleTop, Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
+    type InspectFuture = Box<dyn Future<Item = CoreModuleDetails, Error = Self::Error> + Send>;
+    type EnvFuture = Box<dyn Future<Item = HashMap<String, String>, Error = Self::Error> + Send>;
+    type UpdateEnvFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UpdateResourcesFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UpsertFuture = Box<dyn Future<Item = (Self::Module, bool), Error = Self::Error> + Send>;
+    type PingFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type EventStream = Box<dyn Stream<Item = ModuleEvent, Error = Self::Error> + Send>;
+    type GetConfigFuture =
+        Box<dyn Future<Item = Option<ModuleSpec<Self::Config>>, Error = Self::Error> + Send>;
+    type ExecFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type PullImageStream = Box<dyn Stream<Item = PullProgress, Error = Self::Error> + Send>;
+    type PullImageFuture =
+        Box<dyn Future<Item = Self::PullImageStream, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PruneImagesResult, Error = Self::Error> + Send>;
+    type SignalFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type PortsFuture = Box<dyn Future<Item = Vec<PortBinding>, Error = Self::Error> + Send>;
+    type WaitUntilStoppedFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ConnectivityStatusFuture =
+        Box<dyn Future<Item = ConnectivityStatus, Error = Self::Error> + Send>;
+    type CheckpointFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RestoreFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ListCheckpointsFuture = Box<dyn Future<Item = Vec<String>, Error = Self::Error> + Send>;
+    type VolumesFuture = Box<dyn Future<Item = Vec<VolumeMount>, Error = Self::Error> + Send>;
+    type SnapshotFuture = Box<dyn Future<Item = SystemSnapshot, Error = Self::Error> + Send>;
+    type StartAllFuture = Box<dyn Future<Item = Vec<StartResult>, Error = CoreError> + Send>;
+    type SetBandwidthFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type PauseFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UnpauseFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type CopyToContainerFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn system_info(&self) -> Self::SystemInfoFuture {
         unimplemented!()
     }
 
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
+        Box::new(future::err(not_implemented("system_resources")))
+    }
+
     fn init(&self) -> Self::InitFuture {
         future::ok(())
     }
@@ -338,9 +391,137 @@ impl ModuleRuntime for ModuleClient {
         }))
     }
 
-    fn top(&self, _id: &str) -> Self::TopFuture {
+    fn top(&self, _id: &str, _ps_args: Option<&str>) -> Self::TopFuture {
         unimplemented!()
     }
+
+    fn stats(&self, _id: &str) -> Self::StatsFuture {
+        Box::new(future::err(not_implemented("stats")))
+    }
+
+    fn inspect(&self, _id: &str) -> Self::InspectFuture {
+        Box::new(future::err(not_implemented("inspect")))
+    }
+
+    fn env(&self, _id: &str) -> Self::EnvFuture {
+        Box::new(future::err(not_implemented("env")))
+    }
+
+    fn update_env(&self, _id: &str, _env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+        Box::new(future::err(not_implemented("update_env")))
+    }
+
+    fn update_resources(&self, _id: &str, _limits: ResourceLimits) -> Self::UpdateResourcesFuture {
+        Box::new(future::err(not_implemented("update_resources")))
+    }
+
+    fn upsert(&self, _module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
+        Box::new(future::err(not_implemented("upsert")))
+    }
+
+    fn ping(&self) -> Self::PingFuture {
+        Box::new(future::err(not_implemented("ping")))
+    }
+
+    fn events(&self) -> Self::EventStream {
+        Box::new(stream::once(Err(not_implemented("events"))))
+    }
+
+    fn get_config(&self, _id: &str) -> Self::GetConfigFuture {
+        Box::new(future::err(not_implemented("get_config")))
+    }
+
+    fn exec(&self, _id: &str, _cmd: &[String], _tty: bool) -> Self::ExecFuture {
+        Box::new(future::err(not_implemented("exec")))
+    }
+
+    fn pull_image(&self, _image: &str, _auth: Option<Self::RegistryAuth>) -> Self::PullImageFuture {
+        Box::new(future::err(not_implemented("pull_image")))
+    }
+
+    fn prune_images(&self, _dangling_only: bool) -> Self::PruneImagesFuture {
+        Box::new(future::err(not_implemented("prune_images")))
+    }
+
+    fn signal(&self, _id: &str, _signal: u32) -> Self::SignalFuture {
+        Box::new(future::err(not_implemented("signal")))
+    }
+
+    fn get_ports(&self, _id: &str) -> Self::PortsFuture {
+        Box::new(future::err(not_implemented("get_ports")))
+    }
+
+    fn wait_until_stopped(&self, _id: &str, _timeout: Duration) -> Self::WaitUntilStoppedFuture {
+        Box::new(future::err(not_implemented("wait_until_stopped")))
+    }
+
+    fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+        Box::new(future::err(not_implemented("connectivity_status")))
+    }
+
+    fn checkpoint(&self, _id: &str, _checkpoint_id: &str) -> Self::CheckpointFuture {
+        Box::new(future::err(not_implemented("checkpoint")))
+    }
+
+    fn restore(&self, _id: &str, _checkpoint_id: &str) -> Self::RestoreFuture {
+        Box::new(future::err(not_implemented("restore")))
+    }
+
+    fn list_checkpoints(&self, _id: &str) -> Self::ListCheckpointsFuture {
+        Box::new(future::err(not_implemented("list_checkpoints")))
+    }
+
+    fn get_volumes(&self, _id: &str) -> Self::VolumesFuture {
+        Box::new(future::err(not_implemented("get_volumes")))
+    }
+
+    fn snapshot(&self) -> Self::SnapshotFuture {
+        Box::new(future::err(not_implemented("snapshot")))
+    }
+
+    fn start_all(&self, _specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+        Box::new(future::err(CoreError::from(
+            not_implemented("start_all").context(CoreErrorKind::ModuleRuntime),
+        )))
+    }
+
+    fn set_bandwidth(
+        &self,
+        _id: &str,
+        _ingress_kbps: u32,
+        _egress_kbps: u32,
+    ) -> Self::SetBandwidthFuture {
+        Box::new(future::err(not_implemented("set_bandwidth")))
+    }
+
+    fn pause(&self, _id: &str) -> Self::PauseFuture {
+        Box::new(future::err(not_implemented("pause")))
+    }
+
+    fn unpause(&self, _id: &str) -> Self::UnpauseFuture {
+        Box::new(future::err(not_implemented("unpause")))
+    }
+
+    fn copy_to_container(
+        &self,
+        _id: &str,
+        _dest_path: &str,
+        _content: Bytes,
+    ) -> Self::CopyToContainerFuture {
+        Box::new(future::err(not_implemented("copy_to_container")))
+    }
+
+    fn rename(&self, _old_name: &str, _new_name: &str) -> Self::RenameFuture {
+        Box::new(future::err(not_implemented("rename")))
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        CircuitState::Closed
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
 }
 
 pub struct Logs(String, Body);
@@ -0,0 +1,141 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use edgelet_utils::with_correlation_id;
+use futures::{Future, Poll};
+use hyper::header::HeaderValue;
+use hyper::{Body, Request};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied request ID that is accepted as-is. Longer values
+/// are replaced with a generated ID so they can't bloat log lines.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Identifies a single request to the management service so that a caller can
+/// match its own records against the server's log output.
+///
+/// The ID is taken from the request's `X-Request-Id` header when the caller
+/// supplied a usable one, and generated otherwise. It is stored as a request
+/// extension so handlers can attach it to the errors they return.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorrelationId(String);
+
+impl CorrelationId {
+    pub fn from_request(req: &Request<Body>) -> Self {
+        req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+            .map_or_else(
+                || CorrelationId(Uuid::new_v4().to_string()),
+                |id| CorrelationId(id.to_string()),
+            )
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn header_value(&self) -> Option<HeaderValue> {
+        HeaderValue::from_str(&self.0).ok()
+    }
+}
+
+/// Returns the correlation ID that the management service attached to `req`,
+/// if any.
+pub fn correlation_id(req: &Request<Body>) -> Option<String> {
+    req.extensions()
+        .get::<CorrelationId>()
+        .map(|id| id.as_str().to_string())
+}
+
+/// Polls the future handling a request with the request's correlation ID set
+/// for the records logged meanwhile, so that log output can be matched to the
+/// request it was written for.
+pub struct Correlated<F> {
+    id: CorrelationId,
+    inner: F,
+}
+
+impl<F> Correlated<F> {
+    pub fn new(id: CorrelationId, inner: F) -> Self {
+        Correlated { id, inner }
+    }
+}
+
+impl<F: Future> Future for Correlated<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = &mut self.inner;
+        with_correlation_id(self.id.as_str(), || inner.poll())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_utils::correlation_id as logged_correlation_id;
+    use futures::future;
+
+    use super::*;
+
+    #[test]
+    fn uses_request_id_header() {
+        let req = Request::get("http://localhost/modules")
+            .header(REQUEST_ID_HEADER, "abc-123")
+            .body(Body::default())
+            .unwrap();
+        assert_eq!("abc-123", CorrelationId::from_request(&req).as_str());
+    }
+
+    #[test]
+    fn generates_id_when_header_is_missing() {
+        let req = Request::get("http://localhost/modules")
+            .body(Body::default())
+            .unwrap();
+        let id = CorrelationId::from_request(&req);
+        assert!(Uuid::parse_str(id.as_str()).is_ok());
+    }
+
+    #[test]
+    fn generates_id_when_header_is_too_long() {
+        let req = Request::get("http://localhost/modules")
+            .header(
+                REQUEST_ID_HEADER,
+                "a".repeat(MAX_REQUEST_ID_LEN + 1).as_str(),
+            )
+            .body(Body::default())
+            .unwrap();
+        let id = CorrelationId::from_request(&req);
+        assert!(Uuid::parse_str(id.as_str()).is_ok());
+    }
+
+    #[test]
+    fn correlation_id_reads_extension() {
+        let mut req = Request::get("http://localhost/modules")
+            .body(Body::default())
+            .unwrap();
+        assert_eq!(None, correlation_id(&req));
+
+        req.extensions_mut()
+            .insert(CorrelationId("abc-123".to_string()));
+        assert_eq!(Some("abc-123".to_string()), correlation_id(&req));
+    }
+
+    #[test]
+    fn correlated_future_logs_with_correlation_id() {
+        let id = CorrelationId("abc-123".to_string());
+        let logged = Correlated::new(
+            id,
+            future::lazy(|| future::ok::<_, ()>(logged_correlation_id())),
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(Some("abc-123".to_string()), logged);
+        assert_eq!(None, logged_correlation_id());
+    }
+}
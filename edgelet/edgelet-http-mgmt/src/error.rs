@@ -1,14 +1,18 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::fmt::{self, Display};
+use std::time::Duration;
 
 use edgelet_core::{IdentityOperation, ModuleOperation, RuntimeOperation};
 use edgelet_docker::ErrorKind as DockerErrorKind;
 use edgelet_iothub::Error as IoTHubError;
 use failure::{Backtrace, Context, Fail};
-use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Response, StatusCode};
 use log::error;
+use serde_derive::Serialize;
 use serde_json;
 
 use management::apis::Error as MgmtError;
@@ -16,57 +20,515 @@ use management::models::ErrorResponse;
 
 use crate::IntoResponse;
 
+/// Values of the `code` field of error responses.
+///
+/// These are part of the API contract, so existing values must not change.
+pub mod code {
+    pub const BAD_REQUEST: &str = "BadRequest";
+    pub const BANDWIDTH_NOT_SUPPORTED: &str = "BandwidthNotSupported";
+    pub const CERTIFICATE_NOT_FOUND: &str = "CertificateNotFound";
+    pub const CERTIFICATE_RENEWAL_IN_PROGRESS: &str = "CertificateRenewalInProgress";
+    pub const CHECKPOINT_NOT_FOUND: &str = "CheckpointNotFound";
+    pub const CHECKPOINT_NOT_SUPPORTED: &str = "CheckpointNotSupported";
+    pub const CONFLICTING_ENV_VAR: &str = "ConflictingEnvVar";
+    pub const IDENTITY_NOT_FOUND: &str = "IdentityNotFound";
+    pub const IDENTITY_TIMEOUT: &str = "IdentityTimeout";
+    pub const INTERNAL_ERROR: &str = "InternalError";
+    pub const INVALID_API_VERSION: &str = "InvalidApiVersion";
+    pub const INVALID_CERTIFICATE: &str = "InvalidCertificate";
+    pub const INVALID_COPY_PATH: &str = "InvalidCopyPath";
+    pub const INVALID_IDENTITY_PATCH: &str = "InvalidIdentityPatch";
+    pub const INVALID_LABEL_KEY: &str = "InvalidLabelKey";
+    pub const INVALID_LOG_PATH: &str = "InvalidLogPath";
+    pub const INVALID_MODULE_NAME: &str = "InvalidModuleName";
+    pub const INVALID_MODULE_PATCH: &str = "InvalidModulePatch";
+    pub const INVALID_NETWORK_ALIAS: &str = "InvalidNetworkAlias";
+    pub const INVALID_RESOURCE_LIMITS: &str = "InvalidResourceLimits";
+    pub const INVALID_SIGNAL: &str = "InvalidSignal";
+    pub const IOT_HUB_ERROR: &str = "IotHubError";
+    pub const IOT_HUB_UNAVAILABLE: &str = "IotHubUnavailable";
+    pub const LABEL_NOT_FOUND: &str = "LabelNotFound";
+    pub const MALFORMED_REQUEST_BODY: &str = "MalformedRequestBody";
+    pub const MALFORMED_REQUEST_HEADER: &str = "MalformedRequestHeader";
+    pub const MALFORMED_REQUEST_PARAMETER: &str = "MalformedRequestParameter";
+    pub const METRICS_PORT_NOT_FOUND: &str = "MetricsPortNotFound";
+    pub const MISSING_REQUIRED_PARAMETER: &str = "MissingRequiredParameter";
+    pub const MODULE_ALREADY_EXISTS: &str = "ModuleAlreadyExists";
+    pub const MODULE_ALREADY_PAUSED: &str = "ModuleAlreadyPaused";
+    pub const MODULE_DEPENDENCY_CYCLE: &str = "ModuleDependencyCycle";
+    pub const MODULE_METRICS_TIMEOUT: &str = "ModuleMetricsTimeout";
+    pub const MODULE_METRICS_UNAVAILABLE: &str = "ModuleMetricsUnavailable";
+    pub const MODULE_NAME_NOT_ALLOWED: &str = "ModuleNameNotAllowed";
+    pub const MODULE_NOT_FOUND: &str = "ModuleNotFound";
+    pub const MODULE_NOT_PAUSED: &str = "ModuleNotPaused";
+    pub const MODULE_NOT_RUNNING: &str = "ModuleNotRunning";
+    pub const MODULE_STOP_TIMEOUT: &str = "ModuleStopTimeout";
+    pub const NOT_FOUND: &str = "NotFound";
+    pub const NO_ROLLBACK_TARGET: &str = "NoRollbackTarget";
+    pub const NOT_MODIFIED: &str = "NotModified";
+    pub const REGISTRY_NOT_ALLOWED: &str = "RegistryNotAllowed";
+    pub const REJECTED_BY_RUNTIME: &str = "RejectedByRuntime";
+    pub const RESERVED_MODULE_NAME: &str = "ReservedModuleName";
+    pub const RESOURCE_LIMITS_EXCEED_HOST: &str = "ResourceLimitsExceedHost";
+    pub const RESTART_REQUIRED: &str = "RestartRequired";
+    pub const RUNTIME_UNAVAILABLE: &str = "RuntimeUnavailable";
+    pub const RUNTIME_TIMEOUT: &str = "RuntimeTimeout";
+    pub const SIGNAL_NOT_ALLOWED: &str = "SignalNotAllowed";
+    pub const STATE_RESET_IN_PROGRESS: &str = "StateResetInProgress";
+    pub const TEMPLATE_CYCLE: &str = "TemplateCycle";
+    pub const TENANT_NOT_FOUND: &str = "TenantNotFound";
+    pub const PAYLOAD_TOO_LARGE: &str = "PayloadTooLarge";
+    pub const TOO_MANY_REQUESTS: &str = "TooManyRequests";
+    pub const UNAUTHORIZED: &str = "Unauthorized";
+    pub const VALIDATION_ERROR: &str = "ValidationError";
+}
+
+/// A field of a request body that is invalid, named by its path in the body
+/// like `config.env[1].key`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldError {
+    field: String,
+    message: String,
+}
+
+impl FieldError {
+    pub fn new(field: String, message: String) -> Self {
+        FieldError { field, message }
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The body of the response to a request whose body failed validation. It is
+/// an `ErrorResponse` that also lists every invalid field, not just the first.
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    code: &'static str,
+    message: String,
+    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    fields: Vec<FieldError>,
+}
+
+/// The body of the response to a failed promotion. It is an
+/// `ErrorResponse` that also tells whether the source module was put back
+/// the way it was.
+#[derive(Debug, Serialize)]
+pub struct PromotionError {
+    code: &'static str,
+    message: String,
+    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    rolled_back: bool,
+}
+
+/// The body of the response to a module creation from a registry that isn't
+/// allowed. It is an `ErrorResponse` that also names the registry.
+#[derive(Debug, Serialize)]
+pub struct RegistryError {
+    code: &'static str,
+    message: String,
+    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    registry: String,
+}
+
+/// The body of the response to a change of daemon settings that only take
+/// effect after a restart. It is an `ErrorResponse` that also lists those
+/// settings.
+#[derive(Debug, Serialize)]
+pub struct RestartRequiredError {
+    code: &'static str,
+    message: String,
+    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    keys: Vec<String>,
+}
+
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
+    correlation_id: Option<String>,
 }
 
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Could not read the audit log")]
+    AuditLog,
+
+    #[fail(display = "Certificate {:?} not found", _0)]
+    CertificateNotFound(String),
+
+    #[fail(display = "Certificate {:?} is already being renewed", _0)]
+    CertificateRenewalInProgress(String),
+
+    #[fail(display = "Module {} has no checkpoint {:?}", _0, _1)]
+    CheckpointNotFound(String, String),
+
     // Note: This errorkind is always wrapped in another errorkind context
     #[fail(display = "Client error")]
     Client(MgmtError<serde_json::Value>),
 
+    #[fail(display = "Environment variable {:?} is both set and unset", _0)]
+    ConflictingEnvVar(String),
+
+    #[fail(display = "Could not copy logs of module {:?}", _0)]
+    CopyModuleLogs(String),
+
+    #[fail(display = "Could not describe the management API")]
+    DescribeApi,
+
+    #[fail(
+        display = "File is larger than the {} bytes that can be copied into a module",
+        _0
+    )]
+    FileTooLarge(usize),
+
+    #[fail(display = "Could not get or set the image garbage collection policy")]
+    GcPolicy,
+
+    #[fail(display = "Could not get the daemon settings")]
+    GetDaemonConfig,
+
+    #[fail(display = "Could not check health")]
+    Health,
+
+    #[fail(display = "HMAC authentication of the management API is not enabled")]
+    HmacAuthDisabled,
+
+    #[fail(display = "Identity {} not found", _0)]
+    IdentityNotFound(String),
+
     #[fail(display = "{}", _0)]
     IdentityOperation(IdentityOperation),
 
+    #[fail(display = "The identity manager did not respond within {:?}", _0)]
+    IdentityTimeout(Duration),
+
     #[fail(display = "Could not initialize module client")]
     InitializeModuleClient,
 
     #[fail(display = "Invalid API version {:?}", _0)]
     InvalidApiVersion(String),
 
+    #[fail(
+        display = "Path {:?} is invalid; it has to be an absolute path to a file without `..` components",
+        _0
+    )]
+    InvalidCopyPath(String),
+
+    #[fail(display = "Invalid CA certificate: {}", _0)]
+    InvalidCertificate(String),
+
+    #[fail(display = "Patch of identity {:?} results in an invalid identity", _0)]
+    InvalidIdentityPatch(String),
+
+    #[fail(
+        display = "Label key {:?} is invalid; it may only contain letters, digits, '.', '_' and '-'",
+        _0
+    )]
+    InvalidLabelKey(String),
+
+    #[fail(
+        display = "Log path {:?} is invalid; it has to be an absolute path to a file under one of /var/log/ and /tmp/",
+        _0
+    )]
+    InvalidLogPath(String),
+
+    #[fail(
+        display = "Module name {:?} is invalid; it has to start with a letter or digit, followed by at least one letter, digit, '.', '_' or '-'",
+        _0
+    )]
+    InvalidModuleName(String),
+
+    #[fail(display = "Patch of module {:?} results in an invalid module spec", _0)]
+    InvalidModulePatch(String),
+
+    #[fail(display = "Module spec is invalid")]
+    InvalidModuleSpec(Vec<FieldError>),
+
+    #[fail(
+        display = "Invalid network alias {:?}, aliases can't contain spaces or `/`",
+        _0
+    )]
+    InvalidNetworkAlias(String),
+
+    #[fail(display = "Invalid resource limits for module {:?}", _0)]
+    InvalidResourceLimits(String),
+
+    #[fail(display = "Invalid SAS token: {}", _0)]
+    InvalidSasToken(&'static str),
+
+    #[fail(display = "Invalid signal {:?}", _0)]
+    InvalidSignal(String),
+
     #[fail(display = "A request to Azure IoT Hub failed")]
     IotHub,
 
+    #[fail(display = "Azure IoT Hub is unreachable")]
+    IotHubUnavailable,
+
+    #[fail(display = "Could not issue an authentication token")]
+    IssueAuthToken,
+
+    #[fail(display = "Module {} has no label {:?}", _0, _1)]
+    LabelNotFound(String, String),
+
+    #[fail(display = "Could not list certificates")]
+    ListCertificates,
+
     #[fail(display = "Request body is malformed")]
     MalformedRequestBody,
 
+    #[fail(display = "The request header `{}` is malformed", _0)]
+    MalformedRequestHeader(&'static str),
+
     #[fail(display = "The request parameter `{}` is malformed", _0)]
     MalformedRequestParameter(&'static str),
 
     #[fail(display = "The request is missing required parameter `{}`", _0)]
     MissingRequiredParameter(&'static str),
 
+    #[fail(display = "Could not collect metrics")]
+    Metrics,
+
+    #[fail(display = "Module {} has no metrics_port label", _0)]
+    MetricsPortNotFound(String),
+
+    #[fail(display = "Module {} already exists", _0)]
+    ModuleAlreadyExists(String),
+
+    #[fail(display = "Module {} is already paused", _0)]
+    ModuleAlreadyPaused(String),
+
+    #[fail(
+        display = "Modules {:?} can't be started because their dependencies form a cycle",
+        _0
+    )]
+    ModuleDependencyCycle(Vec<String>),
+
+    #[fail(display = "Module {} did not return its metrics within {:?}", _0, _1)]
+    ModuleMetricsTimeout(String, Duration),
+
+    #[fail(display = "Could not scrape the metrics of module {}", _0)]
+    ModuleMetricsUnavailable(String),
+
+    #[fail(
+        display = "Module name {:?} does not match the pattern {:?} of allowed module names",
+        _0, _1
+    )]
+    ModuleNameNotAllowed(String, String),
+
+    #[fail(display = "Module {} not found", _0)]
+    ModuleNotFound(String),
+
+    #[fail(display = "Module {} is not paused", _0)]
+    ModuleNotPaused(String),
+
+    #[fail(display = "Module {} is not running", _0)]
+    ModuleNotRunning(String),
+
     #[fail(display = "{}", _0)]
     ModuleOperation(ModuleOperation),
 
+    #[fail(display = "Could not get or change the trust store of module {:?}", _0)]
+    ModuleTrustStore(String),
+
+    #[fail(display = "Module {} has no earlier spec to roll back to", _0)]
+    NoRollbackTarget(String),
+
+    #[fail(display = "The module client does not implement {}", _0)]
+    NotImplemented(&'static str),
+
     #[fail(display = "State not modified")]
     NotModified,
 
     #[fail(display = "Could not prepare update for module {:?}", _0)]
     PrepareUpdateModule(String),
 
+    /// Whether the source module was rolled back is part of the response.
+    #[fail(display = "Could not promote module {:?}", _0)]
+    PromoteModule(String, bool),
+
+    #[fail(display = "Could not get or set the proxy settings")]
+    ProxySettings,
+
+    /// The registry is part of the response.
+    #[fail(display = "Images from registry {:?} are not allowed", _0)]
+    RegistryNotAllowed(String),
+
+    #[fail(display = "Could not remove identities")]
+    RemoveIdentities,
+
+    #[fail(display = "Could not remove modules")]
+    RemoveModules,
+
+    #[fail(display = "Could not renew certificate {:?}", _0)]
+    RenewCertificate(String),
+
+    #[fail(
+        display = "Module name {:?} is reserved for the edge runtime and can't be used",
+        _0
+    )]
+    ReservedModuleName(String),
+
+    #[fail(display = "Could not reset the daemon state")]
+    ResetState,
+
+    #[fail(
+        display = "Memory limit of {} bytes for module {:?} exceeds the {} bytes available on the host",
+        _1, _0, _2
+    )]
+    ResourceLimitsExceedHost(String, u64, u64),
+
+    /// The settings are part of the response.
+    #[fail(
+        display = "Settings {:?} can only be changed with a restart of the daemon",
+        _0
+    )]
+    RestartRequired(Vec<String>),
+
     #[fail(display = "{}", _0)]
     RuntimeOperation(RuntimeOperation),
 
+    #[fail(display = "The module runtime did not respond within {:?}", _0)]
+    RuntimeTimeout(Duration),
+
+    #[fail(display = "The management service is shutting down")]
+    ShuttingDown,
+
+    #[fail(
+        display = "Signal {} can't be sent to a module, use DELETE or stop endpoint instead",
+        _0
+    )]
+    SignalNotAllowed(u32),
+
+    #[fail(display = "Could not start modules")]
+    StartModules,
+
+    #[fail(display = "A reset of the daemon state is already in progress")]
+    StateResetInProgress,
+
     #[fail(display = "Could not start management service")]
     StartService,
 
+    #[fail(display = "Could not stop modules")]
+    StopModules,
+
+    #[fail(
+        display = "Environment variables {:?} referenced by the module spec refer to each other in a cycle",
+        _0
+    )]
+    TemplateCycle(Vec<String>),
+
+    #[fail(display = "Tenant {:?} not found", _0)]
+    TenantNotFound(String),
+
+    #[fail(
+        display = "CA certificate {:?} is not in the trust store of module {:?}",
+        _0, _1
+    )]
+    TrustedCertificateNotFound(String, String),
+
+    #[fail(display = "Could not update the daemon settings")]
+    UpdateDaemonConfig,
+
     #[fail(display = "Could not update module {:?}", _0)]
     UpdateModule(String),
 }
 
+impl ErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::CertificateNotFound(_) => code::CERTIFICATE_NOT_FOUND,
+            ErrorKind::CertificateRenewalInProgress(_) => code::CERTIFICATE_RENEWAL_IN_PROGRESS,
+            ErrorKind::CheckpointNotFound(..) => code::CHECKPOINT_NOT_FOUND,
+            ErrorKind::ConflictingEnvVar(_) => code::CONFLICTING_ENV_VAR,
+            ErrorKind::FileTooLarge(_) => code::PAYLOAD_TOO_LARGE,
+            ErrorKind::HmacAuthDisabled => code::NOT_FOUND,
+            ErrorKind::IdentityNotFound(_) => code::IDENTITY_NOT_FOUND,
+            ErrorKind::IdentityTimeout(_) => code::IDENTITY_TIMEOUT,
+            ErrorKind::InvalidApiVersion(_) => code::INVALID_API_VERSION,
+            ErrorKind::InvalidCertificate(_) => code::INVALID_CERTIFICATE,
+            ErrorKind::InvalidCopyPath(_) => code::INVALID_COPY_PATH,
+            ErrorKind::InvalidIdentityPatch(_) => code::INVALID_IDENTITY_PATCH,
+            ErrorKind::InvalidLabelKey(_) => code::INVALID_LABEL_KEY,
+            ErrorKind::InvalidLogPath(_) => code::INVALID_LOG_PATH,
+            ErrorKind::InvalidModuleName(_) => code::INVALID_MODULE_NAME,
+            ErrorKind::InvalidModulePatch(_) => code::INVALID_MODULE_PATCH,
+            ErrorKind::InvalidModuleSpec(_) => code::VALIDATION_ERROR,
+            ErrorKind::InvalidNetworkAlias(_) => code::INVALID_NETWORK_ALIAS,
+            ErrorKind::InvalidResourceLimits(_) => code::INVALID_RESOURCE_LIMITS,
+            ErrorKind::InvalidSasToken(_) => code::UNAUTHORIZED,
+            ErrorKind::InvalidSignal(_) => code::INVALID_SIGNAL,
+            ErrorKind::IotHub => code::IOT_HUB_ERROR,
+            ErrorKind::IotHubUnavailable => code::IOT_HUB_UNAVAILABLE,
+            ErrorKind::LabelNotFound(..) => code::LABEL_NOT_FOUND,
+            ErrorKind::MalformedRequestBody => code::MALFORMED_REQUEST_BODY,
+            ErrorKind::MalformedRequestHeader(_) => code::MALFORMED_REQUEST_HEADER,
+            ErrorKind::MalformedRequestParameter(_) => code::MALFORMED_REQUEST_PARAMETER,
+            ErrorKind::MetricsPortNotFound(_) => code::METRICS_PORT_NOT_FOUND,
+            ErrorKind::MissingRequiredParameter(_) => code::MISSING_REQUIRED_PARAMETER,
+            ErrorKind::ModuleAlreadyExists(_) => code::MODULE_ALREADY_EXISTS,
+            ErrorKind::ModuleAlreadyPaused(_) => code::MODULE_ALREADY_PAUSED,
+            ErrorKind::ModuleDependencyCycle(_) => code::MODULE_DEPENDENCY_CYCLE,
+            ErrorKind::ModuleMetricsTimeout(..) => code::MODULE_METRICS_TIMEOUT,
+            ErrorKind::ModuleMetricsUnavailable(_) => code::MODULE_METRICS_UNAVAILABLE,
+            ErrorKind::ModuleNameNotAllowed(..) => code::MODULE_NAME_NOT_ALLOWED,
+            ErrorKind::ModuleNotFound(_) => code::MODULE_NOT_FOUND,
+            ErrorKind::ModuleNotPaused(_) => code::MODULE_NOT_PAUSED,
+            ErrorKind::ModuleNotRunning(_) => code::MODULE_NOT_RUNNING,
+            ErrorKind::NoRollbackTarget(_) => code::NO_ROLLBACK_TARGET,
+            ErrorKind::NotModified => code::NOT_MODIFIED,
+            ErrorKind::RegistryNotAllowed(_) => code::REGISTRY_NOT_ALLOWED,
+            ErrorKind::ReservedModuleName(_) => code::RESERVED_MODULE_NAME,
+            ErrorKind::ResourceLimitsExceedHost(..) => code::RESOURCE_LIMITS_EXCEED_HOST,
+            ErrorKind::RestartRequired(_) => code::RESTART_REQUIRED,
+            ErrorKind::RuntimeTimeout(_) => code::RUNTIME_TIMEOUT,
+            ErrorKind::SignalNotAllowed(_) => code::SIGNAL_NOT_ALLOWED,
+            ErrorKind::StateResetInProgress => code::STATE_RESET_IN_PROGRESS,
+            ErrorKind::TemplateCycle(_) => code::TEMPLATE_CYCLE,
+            ErrorKind::TenantNotFound(_) => code::TENANT_NOT_FOUND,
+            ErrorKind::TrustedCertificateNotFound(..) => code::CERTIFICATE_NOT_FOUND,
+            ErrorKind::AuditLog
+            | ErrorKind::Client(_)
+            | ErrorKind::CopyModuleLogs(_)
+            | ErrorKind::DescribeApi
+            | ErrorKind::GcPolicy
+            | ErrorKind::GetDaemonConfig
+            | ErrorKind::Health
+            | ErrorKind::IdentityOperation(_)
+            | ErrorKind::InitializeModuleClient
+            | ErrorKind::IssueAuthToken
+            | ErrorKind::ListCertificates
+            | ErrorKind::Metrics
+            | ErrorKind::ModuleOperation(_)
+            | ErrorKind::ModuleTrustStore(_)
+            | ErrorKind::NotImplemented(_)
+            | ErrorKind::PrepareUpdateModule(_)
+            | ErrorKind::PromoteModule(..)
+            | ErrorKind::ProxySettings
+            | ErrorKind::RemoveIdentities
+            | ErrorKind::RemoveModules
+            | ErrorKind::RenewCertificate(_)
+            | ErrorKind::ResetState
+            | ErrorKind::RuntimeOperation(_)
+            | ErrorKind::ShuttingDown
+            | ErrorKind::StartModules
+            | ErrorKind::StartService
+            | ErrorKind::StopModules
+            | ErrorKind::UpdateDaemonConfig
+            | ErrorKind::UpdateModule(_) => code::INTERNAL_ERROR,
+        }
+    }
+}
+
 impl Fail for Error {
     fn cause(&self) -> Option<&dyn Fail> {
         self.inner.cause()
@@ -88,6 +550,33 @@ impl Error {
         self.inner.get_context()
     }
 
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_ref().map(AsRef::as_ref)
+    }
+
+    /// Like the status code of the response, this is decided by the
+    /// underlying docker runtime error, if any, before the kind of the error.
+    pub fn code(&self) -> &'static str {
+        match Fail::find_root_cause(self).downcast_ref::<DockerErrorKind>() {
+            Some(DockerErrorKind::BadParameter(_)) => code::REJECTED_BY_RUNTIME,
+            Some(DockerErrorKind::BandwidthNotSupported) => code::BANDWIDTH_NOT_SUPPORTED,
+            Some(DockerErrorKind::CheckpointNotSupported) => code::CHECKPOINT_NOT_SUPPORTED,
+            Some(DockerErrorKind::CircuitOpen) => code::RUNTIME_UNAVAILABLE,
+            Some(DockerErrorKind::NotFound(_)) => code::MODULE_NOT_FOUND,
+            Some(DockerErrorKind::Conflict) => code::MODULE_ALREADY_EXISTS,
+            Some(DockerErrorKind::InvalidModuleName(_)) => code::INVALID_MODULE_NAME,
+            Some(DockerErrorKind::NotModified) => code::NOT_MODIFIED,
+            Some(DockerErrorKind::StopTimeout(_)) => code::MODULE_STOP_TIMEOUT,
+            Some(_) => code::INTERNAL_ERROR,
+            None => self.kind().code(),
+        }
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: Option<String>) -> Self {
+        self.correlation_id = correlation_id;
+        self
+    }
+
     pub fn from_mgmt_error(error: MgmtError<serde_json::Value>, context: ErrorKind) -> Self {
         match error {
             MgmtError::Hyper(h) => Error::from(h.context(context)),
@@ -104,13 +593,17 @@ impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Self {
         Error {
             inner: Context::new(kind),
+            correlation_id: None,
         }
     }
 }
 
 impl From<Context<ErrorKind>> for Error {
     fn from(inner: Context<ErrorKind>) -> Self {
-        Error { inner }
+        Error {
+            inner,
+            correlation_id: None,
+        }
     }
 }
 
@@ -121,34 +614,127 @@ impl IntoResponse for Error {
             message.push_str(&format!("\n\tcaused by: {}", cause));
         }
 
-        // Specialize status code based on the underlying docker runtime error, if any
-        let status_code =
-            if let Some(cause) = Fail::find_root_cause(&self).downcast_ref::<DockerErrorKind>() {
-                match cause {
-                    DockerErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
-                    DockerErrorKind::Conflict => StatusCode::CONFLICT,
-                    DockerErrorKind::NotModified => StatusCode::NOT_MODIFIED,
-                    _ => StatusCode::INTERNAL_SERVER_ERROR,
-                }
-            } else {
-                match self.kind() {
-                    ErrorKind::InvalidApiVersion(_)
-                    | ErrorKind::MalformedRequestBody
-                    | ErrorKind::MalformedRequestParameter(_)
-                    | ErrorKind::MissingRequiredParameter(_) => StatusCode::BAD_REQUEST,
-                    _ => {
-                        error!("Internal server error: {}", message);
-                        StatusCode::INTERNAL_SERVER_ERROR
-                    }
+        // Specialize status code based on the underlying docker runtime error, if any.
+        // A failed promotion is always a server error, as some of its steps
+        // may have been carried out.
+        let status_code = if let ErrorKind::PromoteModule(..) = self.kind() {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else if let Some(cause) = Fail::find_root_cause(&self).downcast_ref::<DockerErrorKind>() {
+            match cause {
+                DockerErrorKind::BadParameter(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                DockerErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+                DockerErrorKind::Conflict => StatusCode::CONFLICT,
+                DockerErrorKind::NotModified => StatusCode::NOT_MODIFIED,
+                DockerErrorKind::StopTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+                DockerErrorKind::CheckpointNotSupported
+                | DockerErrorKind::BandwidthNotSupported => StatusCode::NOT_IMPLEMENTED,
+                DockerErrorKind::CircuitOpen => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        } else {
+            match self.kind() {
+                ErrorKind::ConflictingEnvVar(_)
+                | ErrorKind::InvalidApiVersion(_)
+                | ErrorKind::InvalidCertificate(_)
+                | ErrorKind::InvalidCopyPath(_)
+                | ErrorKind::InvalidIdentityPatch(_)
+                | ErrorKind::InvalidLabelKey(_)
+                | ErrorKind::InvalidLogPath(_)
+                | ErrorKind::InvalidModuleName(_)
+                | ErrorKind::InvalidSignal(_)
+                | ErrorKind::MalformedRequestBody
+                | ErrorKind::MalformedRequestHeader(_)
+                | ErrorKind::MalformedRequestParameter(_)
+                | ErrorKind::MissingRequiredParameter(_)
+                | ErrorKind::ModuleNameNotAllowed(..)
+                | ErrorKind::ReservedModuleName(_)
+                | ErrorKind::SignalNotAllowed(_) => StatusCode::BAD_REQUEST,
+                ErrorKind::InvalidSasToken(_) => StatusCode::UNAUTHORIZED,
+                ErrorKind::RegistryNotAllowed(_) => StatusCode::FORBIDDEN,
+                ErrorKind::FileTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                ErrorKind::CertificateNotFound(_)
+                | ErrorKind::CheckpointNotFound(..)
+                | ErrorKind::HmacAuthDisabled
+                | ErrorKind::IdentityNotFound(_)
+                | ErrorKind::LabelNotFound(..)
+                | ErrorKind::MetricsPortNotFound(_)
+                | ErrorKind::ModuleNotFound(_)
+                | ErrorKind::TenantNotFound(_)
+                | ErrorKind::TrustedCertificateNotFound(..) => StatusCode::NOT_FOUND,
+                ErrorKind::CertificateRenewalInProgress(_)
+                | ErrorKind::ModuleAlreadyExists(_)
+                | ErrorKind::ModuleAlreadyPaused(_)
+                | ErrorKind::ModuleNotPaused(_)
+                | ErrorKind::ModuleNotRunning(_)
+                | ErrorKind::NoRollbackTarget(_)
+                | ErrorKind::RestartRequired(_)
+                | ErrorKind::StateResetInProgress => StatusCode::CONFLICT,
+                ErrorKind::InvalidModulePatch(_)
+                | ErrorKind::InvalidModuleSpec(_)
+                | ErrorKind::InvalidNetworkAlias(_)
+                | ErrorKind::InvalidResourceLimits(_)
+                | ErrorKind::ModuleDependencyCycle(_)
+                | ErrorKind::ResourceLimitsExceedHost(..)
+                | ErrorKind::TemplateCycle(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorKind::IdentityTimeout(_)
+                | ErrorKind::ModuleMetricsTimeout(..)
+                | ErrorKind::RuntimeTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+                ErrorKind::ModuleMetricsUnavailable(_) => StatusCode::BAD_GATEWAY,
+                ErrorKind::IotHubUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+                _ => {
+                    error!(
+                        "Internal server error (request {}): {}",
+                        self.correlation_id().unwrap_or("-"),
+                        message
+                    );
+                    StatusCode::INTERNAL_SERVER_ERROR
                 }
-            };
+            }
+        };
 
         // Per the RFC, status code NotModified should not have a body
         let body = if status_code == StatusCode::NOT_MODIFIED {
             String::new()
+        } else if let ErrorKind::InvalidModuleSpec(fields) = self.kind() {
+            let error_response = ValidationError {
+                code: self.code(),
+                message,
+                correlation_id: self.correlation_id.clone(),
+                fields: fields.clone(),
+            };
+            serde_json::to_string(&error_response)
+                .expect("serialization of ValidationError failed.")
+        } else if let ErrorKind::PromoteModule(_, rolled_back) = self.kind() {
+            let error_response = PromotionError {
+                code: self.code(),
+                message,
+                correlation_id: self.correlation_id.clone(),
+                rolled_back: *rolled_back,
+            };
+            serde_json::to_string(&error_response).expect("serialization of PromotionError failed.")
+        } else if let ErrorKind::RegistryNotAllowed(registry) = self.kind() {
+            let error_response = RegistryError {
+                code: self.code(),
+                message,
+                correlation_id: self.correlation_id.clone(),
+                registry: registry.clone(),
+            };
+            serde_json::to_string(&error_response).expect("serialization of RegistryError failed.")
+        } else if let ErrorKind::RestartRequired(keys) = self.kind() {
+            let error_response = RestartRequiredError {
+                code: self.code(),
+                message,
+                correlation_id: self.correlation_id.clone(),
+                keys: keys.clone(),
+            };
+            serde_json::to_string(&error_response)
+                .expect("serialization of RestartRequiredError failed.")
         } else {
-            serde_json::to_string(&ErrorResponse::new(message))
-                .expect("serialization of ErrorResponse failed.")
+            let mut error_response = ErrorResponse::new(message).with_code(self.code().to_string());
+            if let Some(correlation_id) = self.correlation_id {
+                error_response.set_correlation_id(correlation_id);
+            }
+            serde_json::to_string(&error_response).expect("serialization of ErrorResponse failed.")
         };
 
         let mut response = Response::builder();
@@ -164,6 +750,69 @@ impl IntoResponse for Error {
     }
 }
 
+/// Gives error responses that were not created by `Error::into_response`,
+/// such as the ones returned by the router or the authorization and rate
+/// limiting layers, an `ErrorResponse` body with a code derived from their
+/// status.
+///
+/// Bodies that are JSON objects other than an `ErrorResponse`, like the one
+/// of an unhealthy `GET /healthz`, are part of the endpoint's contract and are
+/// left alone.
+pub fn with_error_code<E>(
+    response: Response<Body>,
+) -> impl Future<Item = Response<Body>, Error = E> {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Either::A(future::ok(response));
+    }
+
+    let (mut parts, body) = response.into_parts();
+    Either::B(body.concat2().then(move |body| {
+        let body = body.map(|b| b.to_vec()).unwrap_or_default();
+        let error_response = match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(ref error_response) if error_response.code().is_some() => {
+                return Ok(Response::from_parts(parts, body.into()));
+            }
+            Ok(error_response) => error_response,
+            Err(_)
+                if serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&body)
+                    .is_ok() =>
+            {
+                return Ok(Response::from_parts(parts, body.into()));
+            }
+            Err(_) => {
+                let message = String::from_utf8(body)
+                    .ok()
+                    .filter(|message| !message.is_empty())
+                    .unwrap_or_else(|| status.canonical_reason().unwrap_or_default().to_string());
+                ErrorResponse::new(message)
+            }
+        }
+        .with_code(code_for_status(status).to_string());
+
+        let body =
+            serde_json::to_string(&error_response).expect("serialization of ErrorResponse failed.");
+        parts
+            .headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+        Ok(Response::from_parts(parts, body.into()))
+    }))
+}
+
+fn code_for_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => code::BAD_REQUEST,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => code::UNAUTHORIZED,
+        StatusCode::NOT_FOUND => code::NOT_FOUND,
+        StatusCode::PAYLOAD_TOO_LARGE => code::PAYLOAD_TOO_LARGE,
+        StatusCode::TOO_MANY_REQUESTS => code::TOO_MANY_REQUESTS,
+        _ => code::INTERNAL_ERROR,
+    }
+}
+
 impl IntoResponse for IoTHubError {
     fn into_response(self) -> Response<Body> {
         Error::from(self.context(ErrorKind::IotHub)).into_response()
@@ -2,18 +2,30 @@
 
 #![deny(rust_2018_idioms, warnings)]
 #![deny(clippy::all, clippy::pedantic)]
-#![allow(clippy::module_name_repetitions, clippy::use_self)]
+#![allow(
+    clippy::doc_markdown, // clippy want the "IoT" of "IoT Hub" in a code fence
+    clippy::module_name_repetitions,
+    clippy::use_self,
+)]
+// The json! of the OpenAPI schemas nests deeper than the default allows.
+#![recursion_limit = "256"]
 
 use hyper::{Body, Response};
 
 mod client;
+mod correlation;
 mod error;
 mod server;
 
 pub use client::ModuleClient;
-pub use error::{Error, ErrorKind};
+pub use error::{code, Error, ErrorKind};
 pub use server::ListModules;
-pub use server::ManagementService;
+pub use server::{
+    ManagementService, ManagementServiceBuilder, ManagementStores, ModuleHistory,
+    ModuleNameValidator, RegistryValidator, SecretFilter, Timeouts, DEFAULT_AUDIT_LOG_SIZE,
+    DEFAULT_IDEMPOTENCY_TTL, DEFAULT_IDENTITY_TIMEOUT, DEFAULT_RUNTIME_TIMEOUT,
+    DEFAULT_SECRET_PATTERNS,
+};
 
 pub trait IntoResponse {
     fn into_response(self) -> Response<Body>;
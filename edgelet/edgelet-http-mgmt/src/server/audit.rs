@@ -0,0 +1,287 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
+use serde_derive::Serialize;
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// How many requests the audit log remembers when no other size is configured.
+pub const DEFAULT_AUDIT_LOG_SIZE: usize = 1000;
+
+const REDACTED: &str = "REDACTED";
+
+/// Query parameters whose values are credentials, matched case-insensitively
+/// against the parameter name.
+const SENSITIVE_PARAMETERS: &[&str] = &["sig", "signature", "sas", "token", "key", "secret"];
+
+/// A request the management service answered.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditEntry {
+    timestamp: DateTime<Utc>,
+    method: String,
+    path: String,
+    source_module: Option<String>,
+    status_code: u16,
+    correlation_id: String,
+}
+
+impl AuditEntry {
+    /// Credentials in `uri` are redacted, so the entry can be stored and
+    /// handed out as is.
+    pub fn new(
+        timestamp: DateTime<Utc>,
+        method: &Method,
+        uri: &Uri,
+        source_module: Option<String>,
+        status_code: StatusCode,
+        correlation_id: String,
+    ) -> Self {
+        AuditEntry {
+            timestamp,
+            method: method.to_string(),
+            path: redact(uri),
+            source_module,
+            status_code: status_code.as_u16(),
+            correlation_id,
+        }
+    }
+}
+
+/// The most recent requests to the management service, for incident
+/// forensics.
+///
+/// Entries are kept in memory only and the oldest entry is dropped once
+/// `max_entries` are stored. Clones share the same entries.
+#[derive(Clone)]
+pub struct AuditLog {
+    max_entries: usize,
+    entries: Arc<Mutex<VecDeque<AuditEntry>>>,
+}
+
+impl AuditLog {
+    pub fn new(max_entries: usize) -> Self {
+        AuditLog {
+            max_entries,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(max_entries))),
+        }
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        while entries.len() >= self.max_entries {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// The `limit` most recent entries, or all of them, oldest first.
+    pub fn recent(&self, limit: Option<usize>) -> Vec<AuditEntry> {
+        let entries = self.entries.lock().unwrap();
+        let skip = limit.map_or(0, |limit| entries.len().saturating_sub(limit));
+        entries.iter().skip(skip).cloned().collect()
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog::new(DEFAULT_AUDIT_LOG_SIZE)
+    }
+}
+
+/// The path and query of `uri`, with the values of sensitive query
+/// parameters and any `SharedAccessSignature` path segment replaced.
+fn redact(uri: &Uri) -> String {
+    let path = uri
+        .path()
+        .split('/')
+        .map(|segment| {
+            if segment.to_lowercase().starts_with("sharedaccesssignature") {
+                REDACTED
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    match uri.query() {
+        Some(query) => {
+            let query = form_urlencoded::parse(query.as_bytes())
+                .fold(
+                    form_urlencoded::Serializer::new(String::new()),
+                    |mut query, (key, value)| {
+                        let sensitive = SENSITIVE_PARAMETERS
+                            .iter()
+                            .any(|name| key.eq_ignore_ascii_case(name));
+                        query.append_pair(&key, if sensitive { REDACTED } else { &*value });
+                        query
+                    },
+                )
+                .finish();
+            format!("{}?{}", path, query)
+        }
+        None => path,
+    }
+}
+
+#[derive(Serialize)]
+struct AuditLogResponse {
+    entries: Vec<AuditEntry>,
+}
+
+pub struct GetAuditLog {
+    log: AuditLog,
+}
+
+impl GetAuditLog {
+    pub fn new(log: AuditLog) -> Self {
+        GetAuditLog { log }
+    }
+}
+
+impl Handler<Parameters> for GetAuditLog {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = parse_limit(req.uri().query().unwrap_or(""))
+            .and_then(|limit| {
+                let entries = AuditLogResponse {
+                    entries: self.log.recent(limit),
+                };
+                let b = serde_json::to_string(&entries).context(ErrorKind::AuditLog)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::AuditLog)?;
+                Ok(response)
+            })
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+fn parse_limit(query: &str) -> Result<Option<usize>, Error> {
+    match form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "limit") {
+        Some((_, val)) => match val.parse::<usize>() {
+            Ok(limit) if limit > 0 => Ok(Some(limit)),
+            _ => Err(Error::from(ErrorKind::MalformedRequestParameter("limit"))),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use futures::Stream;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn entry(path: &str, correlation_id: &str) -> AuditEntry {
+        AuditEntry::new(
+            Utc.ymd(2019, 4, 13).and_hms(14, 20, 0),
+            &Method::GET,
+            &path.parse().unwrap(),
+            Some("edgeAgent".to_string()),
+            StatusCode::OK,
+            correlation_id.to_string(),
+        )
+    }
+
+    fn correlation_ids(entries: &[AuditEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.correlation_id.as_str()).collect()
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted() {
+        let log = AuditLog::new(2);
+        log.record(entry("/modules", "1"));
+        log.record(entry("/modules", "2"));
+        log.clone().record(entry("/modules", "3"));
+
+        assert_eq!(vec!["2", "3"], correlation_ids(&log.recent(None)));
+        assert_eq!(vec!["3"], correlation_ids(&log.recent(Some(1))));
+        assert_eq!(vec!["2", "3"], correlation_ids(&log.recent(Some(10))));
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        assert_eq!(
+            "/modules?api-version=2019-01-30&sig=REDACTED&Token=REDACTED",
+            entry("/modules?api-version=2019-01-30&sig=abc%3D&Token=xyz", "1").path
+        );
+        assert_eq!(
+            "/identities/REDACTED",
+            entry("/identities/SharedAccessSignature%20sr=hub", "1").path
+        );
+        assert_eq!("/modules/mod1", entry("/modules/mod1", "1").path);
+    }
+
+    #[test]
+    fn handler_returns_recent_entries() {
+        let log = AuditLog::new(10);
+        log.record(entry("/modules", "1"));
+        log.record(entry("/systeminfo", "2"));
+        let handler = GetAuditLog::new(log);
+        let request = Request::get("http://localhost/auditlog?api-version=2019-01-30&limit=1")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let entries: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!({
+                "entries": [{
+                    "timestamp": "2019-04-13T14:20:00Z",
+                    "method": "GET",
+                    "path": "/systeminfo",
+                    "source_module": "edgeAgent",
+                    "status_code": 200,
+                    "correlation_id": "2",
+                }],
+            }),
+            entries
+        );
+    }
+
+    #[test]
+    fn handler_rejects_bad_limit() {
+        let handler = GetAuditLog::new(AuditLog::default());
+        let request = Request::get("http://localhost/auditlog?api-version=2019-01-30&limit=0")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
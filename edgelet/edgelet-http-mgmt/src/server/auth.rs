@@ -0,0 +1,401 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use base64;
+use chrono::{DateTime, Duration, Utc};
+use failure::ResultExt;
+use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use openssl::memcmp;
+use serde_derive::{Deserialize, Serialize};
+use serde_json;
+use url::percent_encoding::percent_decode;
+
+use edgelet_core::crypto::{KeyIdentity, KeyStore, Sign, Signature, SignatureAlgorithm};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::{Error as HttpError, HmacAuthMiddleware, HMAC_TOKEN_LIFETIME_SECS};
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+const SAS_PREFIX: &str = "SharedAccessSignature ";
+
+#[derive(Debug, Deserialize)]
+struct TokenRequest {
+    module_id: String,
+    generation_id: String,
+    sas_token: String,
+    method: String,
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenResponse {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Issues the HMAC token for a management API request to modules that prove
+/// who they are with a SAS token signed by their identity key, the same way
+/// they authenticate with IoT Hub. This lets modules that can only reach the
+/// API over TCP call it when `HmacAuthMiddleware` is enabled.
+///
+/// The token is issued to the module, so the routes only a certain module
+/// may call, like those of the agent, still refuse it from any other module.
+pub struct IssueAuthToken<K> {
+    key_store: K,
+    hmac_auth: HmacAuthMiddleware,
+}
+
+impl<K> IssueAuthToken<K> {
+    pub fn new(key_store: K, hmac_auth: HmacAuthMiddleware) -> Self {
+        IssueAuthToken {
+            key_store,
+            hmac_auth,
+        }
+    }
+}
+
+impl<K> Handler<Parameters> for IssueAuthToken<K>
+where
+    K: 'static + KeyStore + Clone + Send + Sync,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let key_store = self.key_store.clone();
+        let hmac_auth = self.hmac_auth.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(move |b| -> Result<_, Error> {
+                if !hmac_auth.is_enabled() {
+                    return Err(Error::from(ErrorKind::HmacAuthDisabled));
+                }
+
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let request = serde_json::from_slice::<TokenRequest>(&b)
+                    .context(ErrorKind::MalformedRequestBody)?;
+                let method = request
+                    .method
+                    .parse::<Method>()
+                    .context(ErrorKind::MalformedRequestBody)?;
+
+                let now = Utc::now();
+                let key = key_store
+                    .get(
+                        &KeyIdentity::Module(request.module_id.clone()),
+                        &format!("primary{}", request.generation_id),
+                    )
+                    .map_err(|_| ErrorKind::InvalidSasToken("the module has no identity key"))?;
+                verify_sas_token(&key, &request.module_id, &request.sas_token, now)?;
+
+                let token = hmac_auth
+                    .token(&request.module_id, &method, &request.path, now)
+                    .context(ErrorKind::IssueAuthToken)?
+                    .ok_or(ErrorKind::HmacAuthDisabled)?;
+                let response = TokenResponse {
+                    token,
+                    expires_at: now + Duration::seconds(HMAC_TOKEN_LIFETIME_SECS),
+                };
+
+                let b = serde_json::to_string(&response).context(ErrorKind::IssueAuthToken)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::IssueAuthToken)?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Checks that `sas_token` is an unexpired token for the resource of module
+/// `module_id` that is signed with `key`, like a token of the module for IoT
+/// Hub.
+fn verify_sas_token<K: Sign>(
+    key: &K,
+    module_id: &str,
+    sas_token: &str,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let token = sas_token.trim();
+    let token = if token.starts_with(SAS_PREFIX) {
+        &token[SAS_PREFIX.len()..]
+    } else {
+        token
+    };
+
+    let (mut resource_uri, mut signature, mut expiry) = (None, None, None);
+    for pair in token.split('&') {
+        let mut parts = pair.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("sr"), Some(value)) => resource_uri = Some(value),
+            (Some("sig"), Some(value)) => signature = Some(value),
+            (Some("se"), Some(value)) => expiry = Some(value),
+            _ => (),
+        }
+    }
+    let (resource_uri, signature, expiry) = match (resource_uri, signature, expiry) {
+        (Some(resource_uri), Some(signature), Some(expiry)) => (resource_uri, signature, expiry),
+        _ => return Err(Error::from(ErrorKind::InvalidSasToken("malformed token"))),
+    };
+
+    let expires_at = expiry
+        .parse::<i64>()
+        .map_err(|_| ErrorKind::InvalidSasToken("malformed expiry"))?;
+    if expires_at <= now.timestamp() {
+        return Err(Error::from(ErrorKind::InvalidSasToken("the token expired")));
+    }
+
+    let audience = percent_decode(resource_uri.as_bytes())
+        .decode_utf8()
+        .map_err(|_| ErrorKind::InvalidSasToken("malformed resource"))?
+        .to_lowercase();
+    if !audience.ends_with(&format!("/modules/{}", module_id.to_lowercase())) {
+        return Err(Error::from(ErrorKind::InvalidSasToken(
+            "the token is for another resource",
+        )));
+    }
+
+    let signature = percent_decode(signature.as_bytes())
+        .decode_utf8()
+        .ok()
+        .and_then(|signature| base64::decode(signature.as_ref()).ok())
+        .ok_or(ErrorKind::InvalidSasToken("malformed signature"))?;
+    let expected = key
+        .sign(
+            SignatureAlgorithm::HMACSHA256,
+            format!("{}\n{}", resource_uri, expiry).as_bytes(),
+        )
+        .context(ErrorKind::IssueAuthToken)?;
+    let expected = expected.as_bytes();
+    if expected.len() != signature.len() || !memcmp::eq(expected, &signature) {
+        return Err(Error::from(ErrorKind::InvalidSasToken(
+            "the signature doesn't match",
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use edgelet_core::crypto::MemoryKey;
+    use edgelet_core::pid::Pid;
+    use edgelet_core::{
+        Error as CoreError, ErrorKind as CoreErrorKind, ModuleRuntimeState, Policy,
+    };
+    use edgelet_http::authorization::Authorization;
+    use edgelet_http::PeerAddr;
+    use edgelet_test_utils::module::*;
+    use serde_json::{json, Value};
+    use url::form_urlencoded::Serializer as UrlSerializer;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestKeyStore;
+
+    impl KeyStore for TestKeyStore {
+        type Key = MemoryKey;
+
+        fn get(&self, identity: &KeyIdentity, key_name: &str) -> Result<MemoryKey, CoreError> {
+            match identity {
+                KeyIdentity::Module(id) if key_name == "primaryg1" => {
+                    Ok(MemoryKey::new(format!("{} key", id)))
+                }
+                _ => Err(CoreError::from(CoreErrorKind::KeyStoreItemNotFound)),
+            }
+        }
+    }
+
+    fn sas_token(key: &str, resource_uri: &str, expiry: DateTime<Utc>) -> String {
+        let expiry = expiry.timestamp().to_string();
+        let signature = MemoryKey::new(key)
+            .sign(
+                SignatureAlgorithm::HMACSHA256,
+                format!("{}\n{}", resource_uri, expiry).as_bytes(),
+            )
+            .unwrap();
+        let token = UrlSerializer::new(format!("sr={}", resource_uri))
+            .append_pair("sig", &base64::encode(signature.as_bytes()))
+            .append_pair("se", &expiry)
+            .finish();
+        format!("{}{}", SAS_PREFIX, token)
+    }
+
+    fn request(sas_token: &str) -> Request<Body> {
+        token_request("mod1", sas_token, "GET", "/modules")
+    }
+
+    fn token_request(module_id: &str, sas_token: &str, method: &str, path: &str) -> Request<Body> {
+        let body = json!({
+            "module_id": module_id,
+            "generation_id": "g1",
+            "sas_token": sas_token,
+            "method": method,
+            "path": path,
+        });
+        Request::post("http://localhost/auth/token")
+            .body(body.to_string().into())
+            .unwrap()
+    }
+
+    fn handler() -> IssueAuthToken<TestKeyStore> {
+        IssueAuthToken::new(
+            TestKeyStore,
+            HmacAuthMiddleware::new(MemoryKey::new("management key")),
+        )
+    }
+
+    #[test]
+    fn valid_sas_token_gets_a_token() {
+        // arrange
+        let handler = handler();
+        let sas_token = sas_token(
+            "mod1 key",
+            "hub.azure-devices.net%2Fdevices%2Fdevice1%2Fmodules%2Fmod1",
+            Utc::now() + Duration::hours(1),
+        );
+
+        // act
+        let response = handler
+            .handle(request(&sas_token), Parameters::new())
+            .wait()
+            .unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let body: Value = serde_json::from_slice(&b).unwrap();
+        let token = body["token"].as_str().unwrap();
+        let mut req = Request::get("http://localhost/modules")
+            .header("Authorization", format!("HMAC {}", token))
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(PeerAddr("127.0.0.1:8080".parse::<SocketAddr>().unwrap()));
+        assert!(handler.hmac_auth.authenticate(&mut req, Utc::now()).is_ok());
+    }
+
+    #[test]
+    fn token_only_works_for_routes_the_module_may_call() {
+        let handler = handler();
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let state = ModuleRuntimeState::default().with_pid(Pid::Value(42));
+        let agent = TestModule::new("edgeAgent".to_string(), config, Ok(state));
+        let runtime: TestRuntime<crate::server::module::tests::Error> = TestRuntime::new(Ok(agent));
+        let delete_module = Authorization::new(
+            |_req, _params| -> Box<dyn Future<Item = _, Error = _> + Send> {
+                Box::new(futures::future::ok(Response::new(Body::empty())))
+            },
+            Policy::Module("edgeAgent"),
+            runtime,
+        );
+
+        let status = |module_id: &str| {
+            // arrange
+            let sas_token = sas_token(
+                &format!("{} key", module_id),
+                &format!(
+                    "hub.azure-devices.net%2Fdevices%2Fdevice1%2Fmodules%2F{}",
+                    module_id
+                ),
+                Utc::now() + Duration::hours(1),
+            );
+            let response = handler
+                .handle(
+                    token_request(module_id, &sas_token, "DELETE", "/modules/mod2"),
+                    Parameters::new(),
+                )
+                .wait()
+                .unwrap();
+            assert_eq!(StatusCode::OK, response.status());
+            let b = response.into_body().concat2().wait().unwrap();
+            let body: Value = serde_json::from_slice(&b).unwrap();
+
+            let mut req = Request::delete("http://localhost/modules/mod2")
+                .header(
+                    "Authorization",
+                    format!("HMAC {}", body["token"].as_str().unwrap()),
+                )
+                .body(Body::empty())
+                .unwrap();
+            req.extensions_mut()
+                .insert(PeerAddr("127.0.0.1:8080".parse::<SocketAddr>().unwrap()));
+            req.extensions_mut().insert(Pid::Any);
+            handler
+                .hmac_auth
+                .authenticate(&mut req, Utc::now())
+                .unwrap();
+
+            // act
+            let params =
+                Parameters::with_captures(vec![(Some("name".to_string()), "mod2".to_string())]);
+            delete_module.handle(req, params).wait().unwrap().status()
+        };
+
+        // assert
+        assert_eq!(StatusCode::OK, status("edgeAgent"));
+        assert_eq!(StatusCode::NOT_FOUND, status("mod1"));
+    }
+
+    #[test]
+    fn invalid_sas_tokens_are_refused() {
+        let now = Utc::now();
+        let resource_uri = "hub.azure-devices.net%2Fdevices%2Fdevice1%2Fmodules%2Fmod1";
+
+        for sas_token in &[
+            "not a token".to_string(),
+            sas_token("mod1 key", resource_uri, now - Duration::minutes(1)),
+            sas_token(
+                "mod1 key",
+                "hub.azure-devices.net%2Fdevices%2Fdevice1%2Fmodules%2Fmod2",
+                now + Duration::hours(1),
+            ),
+            sas_token("other key", resource_uri, now + Duration::hours(1)),
+        ] {
+            // arrange
+            let handler = handler();
+
+            // act
+            let response = handler
+                .handle(request(sas_token), Parameters::new())
+                .wait()
+                .unwrap();
+
+            // assert
+            assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        }
+    }
+
+    #[test]
+    fn disabled_auth_is_not_found() {
+        // arrange
+        let handler = IssueAuthToken::new(TestKeyStore, HmacAuthMiddleware::default());
+
+        // act
+        let response = handler
+            .handle(
+                request("SharedAccessSignature sr=a&sig=b&se=1"),
+                Parameters::new(),
+            )
+            .wait()
+            .unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{stream, Future, IntoFuture, Stream};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use log::debug;
+use serde_json;
+
+use edgelet_core::{ModuleEvent, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Streams module lifecycle events as Server-Sent Events.
+///
+/// Every event is sent as a `data:` event holding the event as JSON, for
+/// example `{"event_type":"died","module_name":"mod1","timestamp":"..."}`.
+/// The response stays open until the client goes away, and ends early if
+/// the runtime stops reporting events. Events that the management API
+/// raises itself, like promotions, are sent along with the runtime's.
+pub struct WatchEvents<M> {
+    runtime: M,
+    local_events: LocalEvents,
+}
+
+impl<M> WatchEvents<M> {
+    pub fn new(runtime: M) -> Self {
+        WatchEvents {
+            runtime,
+            local_events: LocalEvents::default(),
+        }
+    }
+
+    pub(crate) fn with_local_events(mut self, local_events: LocalEvents) -> Self {
+        self.local_events = local_events;
+        self
+    }
+}
+
+/// Module events that are raised by the management API rather than reported
+/// by the runtime. Clones share their subscribers.
+#[derive(Clone, Default)]
+pub(crate) struct LocalEvents {
+    subscribers: Arc<Mutex<Vec<UnboundedSender<ModuleEvent>>>>,
+}
+
+impl LocalEvents {
+    /// Sends `event` to every subscriber that is still listening.
+    pub(crate) fn publish(&self, event: &ModuleEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
+    pub(crate) fn subscribe(&self) -> UnboundedReceiver<ModuleEvent> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+impl<M> Handler<Parameters> for WatchEvents<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        debug!("Watch module events");
+
+        // The local events never end, so the end of the runtime's events is
+        // marked with `None` to end the response with them.
+        let runtime_events = self
+            .runtime
+            .events()
+            .then(|event| -> Result<_, Error> {
+                let event = event.context(ErrorKind::RuntimeOperation(RuntimeOperation::Events))?;
+                Ok(Some(event))
+            })
+            .chain(stream::once(Ok(None)));
+        let local_events = self
+            .local_events
+            .subscribe()
+            .map(Some)
+            .map_err(|()| Error::from(ErrorKind::RuntimeOperation(RuntimeOperation::Events)));
+        let events = runtime_events
+            .select(local_events)
+            .take_while(|event| Ok(event.is_some()))
+            .filter_map(|event| event)
+            .and_then(|event| sse_event(&event));
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(Body::wrap_stream(events.map_err(Fail::compat)))
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::Events))
+            .map_err(Error::from)
+            .into_future()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn sse_event(event: &ModuleEvent) -> Result<Chunk, Error> {
+    let data = serde_json::to_string(event)
+        .context(ErrorKind::RuntimeOperation(RuntimeOperation::Events))?;
+    Ok(Chunk::from(format!("data: {}\n\n", data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use edgelet_core::{ModuleEventType, ModuleRuntimeState};
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn event(event_type: ModuleEventType, name: &str) -> ModuleEvent {
+        ModuleEvent::new(
+            event_type,
+            name.to_string(),
+            Utc.ymd(2019, 1, 30).and_hms(12, 34, 56),
+        )
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        )
+        .with_events(vec![
+            event(ModuleEventType::Started, "mod1"),
+            event(ModuleEventType::OomKilled, "mod1"),
+        ]);
+        let handler = WatchEvents::new(TestRuntime::new(Ok(module)));
+        let request = Request::get("http://localhost/events")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("text/event-stream", response.headers()[CONTENT_TYPE]);
+        assert_eq!("no-cache", response.headers()[CACHE_CONTROL]);
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_eq!(
+            "data: {\"event_type\":\"started\",\"module_name\":\"mod1\",\"timestamp\":\"2019-01-30T12:34:56Z\"}\n\n\
+             data: {\"event_type\":\"oom_killed\",\"module_name\":\"mod1\",\"timestamp\":\"2019-01-30T12:34:56Z\"}\n\n",
+            std::str::from_utf8(&b).unwrap()
+        );
+    }
+
+    #[test]
+    fn local_events_are_sent_too() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        )
+        .with_events(vec![event(ModuleEventType::Started, "mod1")]);
+        let local_events = LocalEvents::default();
+        let handler =
+            WatchEvents::new(TestRuntime::new(Ok(module))).with_local_events(local_events.clone());
+        let request = Request::get("http://localhost/events")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        local_events.publish(&event(ModuleEventType::Promoted, "mod2"));
+
+        // assert
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_eq!(
+            "data: {\"event_type\":\"started\",\"module_name\":\"mod1\",\"timestamp\":\"2019-01-30T12:34:56Z\"}\n\n\
+             data: {\"event_type\":\"promoted\",\"module_name\":\"mod2\",\"timestamp\":\"2019-01-30T12:34:56Z\"}\n\n",
+            std::str::from_utf8(&b).unwrap()
+        );
+    }
+
+    #[test]
+    fn runtime_error_ends_stream() {
+        // arrange
+        let handler = WatchEvents::new(TestRuntime::new(Err(Error::General)));
+        let request = Request::get("http://localhost/events")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(response.into_body().concat2().wait().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::{debug, warn};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::HealthStatus;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+const HEALTHY: &str = "healthy";
+const UNHEALTHY: &str = "unhealthy";
+
+/// Readiness probe, served on both `GET /healthz` and `GET /healthz/ready`.
+///
+/// The service is ready when the module runtime answers a ping. Otherwise the
+/// response is `503 Service Unavailable` with the reason the ping failed.
+pub struct GetHealth<M> {
+    runtime: M,
+}
+
+impl<M> GetHealth<M> {
+    pub fn new(runtime: M) -> Self {
+        GetHealth { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetHealth<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        debug!("Get health");
+
+        let response = self
+            .runtime
+            .ping()
+            .then(|result| -> Result<_, Error> {
+                match result {
+                    Ok(()) => {
+                        health_response(StatusCode::OK, HealthStatus::new(HEALTHY.to_string()))
+                    }
+                    Err(err) => {
+                        let err = Error::from(
+                            err.context(ErrorKind::RuntimeOperation(RuntimeOperation::Ping)),
+                        );
+                        let mut reason = err.to_string();
+                        for cause in Fail::iter_causes(&err) {
+                            reason.push_str(&format!("\n\tcaused by: {}", cause));
+                        }
+                        warn!("Health check failed: {}", reason);
+
+                        health_response(
+                            StatusCode::SERVICE_UNAVAILABLE,
+                            HealthStatus::new(UNHEALTHY.to_string()).with_reason(reason),
+                        )
+                    }
+                }
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Liveness probe, served on `GET /healthz/live`.
+///
+/// Answering at all shows that the server loop is running, so this never
+/// touches the module runtime and always succeeds.
+pub struct GetLiveness;
+
+impl Handler<Parameters> for GetLiveness {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = health_response(StatusCode::OK, HealthStatus::new(HEALTHY.to_string()))
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+fn health_response(status: StatusCode, health: HealthStatus) -> Result<Response<Body>, Error> {
+    let b = serde_json::to_string(&health).context(ErrorKind::Health)?;
+    let response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::Health)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn body_health(response: Response<Body>) -> HealthStatus {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn ready() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = GetHealth::new(TestRuntime::new(Ok(module)));
+        let request = Request::get("http://localhost/healthz")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let health = body_health(response);
+        assert_eq!("healthy", health.status());
+        assert_eq!(None, health.reason());
+    }
+
+    #[test]
+    fn not_ready() {
+        // arrange
+        let handler = GetHealth::new(TestRuntime::new(Err(Error::General)));
+        let request = Request::get("http://localhost/healthz/ready")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+        let health = body_health(response);
+        assert_eq!("unhealthy", health.status());
+        assert_eq!(
+            Some("Could not reach module runtime\n\tcaused by: General error"),
+            health.reason()
+        );
+    }
+
+    #[test]
+    fn live() {
+        // arrange
+        let request = Request::get("http://localhost/healthz/live")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = GetLiveness
+            .handle(request, Parameters::new())
+            .wait()
+            .unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("healthy", body_health(response).status());
+    }
+}
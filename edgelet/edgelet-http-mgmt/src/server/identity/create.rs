@@ -9,11 +9,14 @@ use hyper::{Body, Request, Response, StatusCode};
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_core::{
+    Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec, X509Thumbprint,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::{Identity, IdentitySpec as CreateIdentitySpec};
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -39,6 +42,8 @@ where
         req: Request<Body>,
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         let id_mgr = self.id_manager.clone();
         let response = read_request(req)
             .and_then(move |spec| {
@@ -78,7 +83,7 @@ where
                     Ok(response)
                 })
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
@@ -93,6 +98,11 @@ fn read_request(req: Request<Body>) -> impl Future<Item = IdentitySpec, Error =
         if let Some(m) = create_req.managed_by() {
             spec = spec.with_managed_by(m.to_string());
         }
+        if let Some(t) = create_req.x509_thumbprint() {
+            let thumbprint =
+                X509Thumbprint::new(t.to_string()).context(ErrorKind::MalformedRequestBody)?;
+            spec = spec.with_x509_thumbprint(thumbprint);
+        }
         Ok(spec)
     })
 }
@@ -185,6 +195,61 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn create_with_x509_thumbprint_succeeds() {
+        let manager = TestIdentityManager::new(vec![]);
+        let handler = CreateIdentity::new(manager);
+        let val = json!({
+            "moduleId": "m1",
+            "x509Thumbprint": "0123456789abcdef0123456789abcdef01234567",
+        });
+        let request = Request::post("http://localhost/identities")
+            .body(serde_json::to_string(&val).unwrap().into())
+            .unwrap();
+
+        let response = handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let identity: TestIdentity = serde_json::from_slice(&body).unwrap();
+        assert_eq!("m1", identity.module_id());
+        assert_eq!(AuthType::X509, identity.auth_type());
+    }
+
+    #[test]
+    fn create_with_invalid_x509_thumbprint() {
+        let manager = TestIdentityManager::new(vec![]);
+        let handler = CreateIdentity::new(manager);
+        let val = json!({ "moduleId": "m1", "x509Thumbprint": "0123456789abcdef" });
+        let request = Request::post("http://localhost/identities")
+            .body(serde_json::to_string(&val).unwrap().into())
+            .unwrap();
+
+        let response = handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Request body is malformed\n\tcaused by: Invalid X.509 thumbprint \"0123456789abcdef\": expected 40 (SHA-1) or 64 (SHA-256) hex characters",
+            error.message()
+        );
+        assert!(handler
+            .id_manager
+            .lock()
+            .unwrap()
+            .list()
+            .wait()
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn create_no_body() {
         let manager = TestIdentityManager::new(vec![]);
@@ -10,6 +10,7 @@ use edgelet_core::{IdentityManager, IdentityOperation, IdentitySpec};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -31,9 +32,11 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         let response = params
             .name("name")
             .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
@@ -61,7 +64,7 @@ where
                         IdentityOperation::DeleteIdentity(name),
                     ))?)
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
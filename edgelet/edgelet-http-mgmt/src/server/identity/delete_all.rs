@@ -0,0 +1,252 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{Identity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Deletes every module identity of the device, for de-provisioning.
+///
+/// The device's own identity has no module id and is left alone. Each
+/// identity is deleted independently and concurrently. When every identity
+/// was deleted the response is `204 No Content`, otherwise it is
+/// `207 Multi-Status` with a body that maps every identity to either `"ok"`
+/// or the error that occurred. An identity that no longer exists by the time
+/// it is deleted counts as deleted.
+pub struct DeleteAllIdentities<I> {
+    id_manager: Mutex<I>,
+}
+
+impl<I> DeleteAllIdentities<I> {
+    pub fn new(id_manager: I) -> Self {
+        DeleteAllIdentities {
+            id_manager: Mutex::new(id_manager),
+        }
+    }
+}
+
+impl<I> Handler<Parameters> for DeleteAllIdentities<I>
+where
+    I: 'static + IdentityManager + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let id_manager = self.id_manager.lock().unwrap().clone();
+
+        let response = delete_all(id_manager)
+            .and_then(response_for)
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Deletes every module identity, and maps each to the outcome of its
+/// deletion. Only failing to list the identities fails the future.
+pub(crate) fn delete_all<I>(
+    mut id_manager: I,
+) -> impl Future<Item = BTreeMap<String, Result<(), Error>>, Error = Error>
+where
+    I: 'static + IdentityManager + Clone + Send,
+{
+    id_manager
+        .list()
+        .then(|identities| -> Result<_, Error> {
+            let identities = identities.context(ErrorKind::RemoveIdentities)?;
+            Ok(module_ids(&identities))
+        })
+        .and_then(move |names| {
+            let operations = names
+                .into_iter()
+                .map(|name| {
+                    delete(&mut id_manager, name.clone())
+                        .then(move |result| Ok::<_, Error>((name, result)))
+                })
+                .collect::<Vec<_>>();
+
+            future::join_all(operations)
+        })
+        .map(|outcomes| outcomes.into_iter().collect())
+}
+
+/// The ids of the module identities among `identities`, which excludes the
+/// device identity.
+fn module_ids<I: Identity>(identities: &[I]) -> Vec<String> {
+    identities
+        .iter()
+        .map(|identity| identity.module_id().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Deletes the identity `name`. If that fails because the identity is gone,
+/// the deletion counts as successful.
+fn delete<I>(id_manager: &mut I, name: String) -> impl Future<Item = (), Error = Error>
+where
+    I: 'static + IdentityManager + Clone + Send,
+{
+    let lookup = id_manager.clone();
+
+    id_manager
+        .delete(IdentitySpec::new(name.clone()))
+        .or_else(move |err| {
+            lookup
+                .get(IdentitySpec::new(name.clone()))
+                .then(move |identity| match identity {
+                    Ok(None) => Ok(()),
+                    Ok(Some(_)) | Err(_) => Err(Error::from(err.context(
+                        ErrorKind::IdentityOperation(IdentityOperation::DeleteIdentity(name)),
+                    ))),
+                })
+        })
+}
+
+fn response_for(outcomes: BTreeMap<String, Result<(), Error>>) -> Result<Response<Body>, Error> {
+    if outcomes.values().all(Result::is_ok) {
+        let response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::default())
+            .context(ErrorKind::RemoveIdentities)?;
+        return Ok(response);
+    }
+
+    let outcomes: BTreeMap<String, String> = outcomes
+        .into_iter()
+        .map(|(name, result)| {
+            let outcome = match result {
+                Ok(()) => "ok".to_string(),
+                Err(err) => {
+                    let mut message = err.to_string();
+                    for cause in Fail::iter_causes(&err) {
+                        message.push_str(&format!("\n\tcaused by: {}", cause));
+                    }
+                    message
+                }
+            };
+            (name, outcome)
+        })
+        .collect();
+
+    let b = serde_json::to_string(&outcomes).context(ErrorKind::RemoveIdentities)?;
+    let response = Response::builder()
+        .status(StatusCode::MULTI_STATUS)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::RemoveIdentities)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::AuthType;
+    use edgelet_test_utils::identity::{TestIdentity, TestIdentityManager};
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    fn handle(manager: TestIdentityManager) -> Response<Body> {
+        let handler = DeleteAllIdentities::new(manager);
+        let request = Request::delete("http://localhost/identities/all")
+            .body(Body::default())
+            .unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![
+            TestIdentity::new("m1", "iotedge", "1", AuthType::Sas),
+            TestIdentity::new("m2", "iotedge", "2", AuthType::Sas),
+        ]);
+
+        // act
+        let response = handle(manager);
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn device_identity_is_kept() {
+        let identities = vec![
+            TestIdentity::new("", "iotedge", "1", AuthType::Sas),
+            TestIdentity::new("m1", "iotedge", "2", AuthType::Sas),
+        ];
+
+        assert_eq!(vec!["m1".to_string()], module_ids(&identities));
+    }
+
+    #[test]
+    fn vanished_identity_counts_as_deleted() {
+        // arrange
+        let mut manager = TestIdentityManager::new(vec![]).with_fail_get(false);
+
+        // act
+        let result = delete(&mut manager, "m1".to_string()).wait();
+
+        // assert
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn list_error() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![]).with_fail_list(true);
+
+        // act
+        let response = handle(manager);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not remove identities\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn partial_failure_is_multi_status() {
+        // arrange
+        let mut manager = TestIdentityManager::new(vec![]);
+        let mut outcomes = BTreeMap::new();
+        outcomes.insert("m1".to_string(), Ok(()));
+        outcomes.insert(
+            "m2".to_string(),
+            delete(&mut manager, "m2".to_string()).wait(),
+        );
+
+        // act
+        let response = response_for(outcomes).unwrap();
+
+        // assert
+        assert_eq!(StatusCode::MULTI_STATUS, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let outcomes: BTreeMap<String, String> = serde_json::from_slice(&b).unwrap();
+        assert_eq!("ok", outcomes["m1"]);
+        assert_eq!(
+            "Could not delete identity m2\n\tcaused by: Module not found",
+            outcomes["m2"]
+        );
+    }
+}
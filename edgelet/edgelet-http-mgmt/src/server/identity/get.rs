@@ -0,0 +1,182 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Mutex;
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::Identity;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetIdentity<I> {
+    id_manager: Mutex<I>,
+}
+
+impl<I> GetIdentity<I> {
+    pub fn new(id_manager: I) -> Self {
+        GetIdentity {
+            id_manager: Mutex::new(id_manager),
+        }
+    }
+}
+
+impl<I> Handler<Parameters> for GetIdentity<I>
+where
+    I: 'static + IdentityManager + Send,
+    I::Identity: CoreIdentity,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.id_manager
+                    .lock()
+                    .unwrap()
+                    .get(IdentitySpec::new(name.clone()))
+                    .then(|result| match result {
+                        Ok(Some(identity)) => write_response(&identity),
+                        Ok(None) => Err(Error::from(ErrorKind::IdentityNotFound(name))),
+                        Err(err) => Err(Error::from(err.context(ErrorKind::IdentityOperation(
+                            IdentityOperation::GetIdentity(name),
+                        )))),
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn write_response<I>(identity: &I) -> Result<Response<Body>, Error>
+where
+    I: CoreIdentity,
+{
+    let module_id = identity.module_id().to_string();
+    let identity = Identity::new(
+        module_id.clone(),
+        identity.managed_by().to_string(),
+        identity.generation_id().to_string(),
+        identity.auth_type().to_string(),
+    );
+
+    let b = serde_json::to_string(&identity).with_context(|_| {
+        ErrorKind::IdentityOperation(IdentityOperation::GetIdentity(module_id.clone()))
+    })?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::IdentityOperation(
+            IdentityOperation::GetIdentity(module_id),
+        ))?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::AuthType;
+    use edgelet_test_utils::identity::{TestIdentity, TestIdentityManager};
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    fn request() -> Request<Body> {
+        Request::get("http://localhost/identities/m1")
+            .body(Body::default())
+            .unwrap()
+    }
+
+    fn name(name: &str) -> Parameters {
+        Parameters::with_captures(vec![(Some("name".to_string()), name.to_string())])
+    }
+
+    #[test]
+    fn get_round_trips_created_identity() {
+        let mut manager = TestIdentityManager::new(vec![]).with_fail_get(false);
+        manager
+            .create(IdentitySpec::new("m1".to_string()).with_managed_by("foo".to_string()))
+            .wait()
+            .unwrap();
+        let handler = GetIdentity::new(manager);
+
+        let response = handler.handle(request(), name("m1")).wait().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let identity: TestIdentity = serde_json::from_slice(&body).unwrap();
+        assert_eq!("m1", identity.module_id());
+        assert_eq!("foo", identity.managed_by());
+        assert_eq!("1", identity.generation_id());
+        assert_eq!(AuthType::Sas, identity.auth_type());
+    }
+
+    #[test]
+    fn get_unknown_identity_is_not_found() {
+        let manager =
+            TestIdentityManager::new(vec![TestIdentity::new("m2", "iotedge", "1", AuthType::Sas)])
+                .with_fail_get(false);
+        let handler = GetIdentity::new(manager);
+
+        let response = handler.handle(request(), name("m1")).wait().unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!("Identity m1 not found", error.message());
+    }
+
+    #[test]
+    fn get_no_name_param() {
+        let handler = GetIdentity::new(TestIdentityManager::new(vec![]));
+
+        let response = handler
+            .handle(request(), Parameters::default())
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "The request is missing required parameter `name`",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn get_fails() {
+        let handler = GetIdentity::new(TestIdentityManager::new(vec![]));
+
+        let response = handler.handle(request(), name("m1")).wait().unwrap();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not get identity m1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
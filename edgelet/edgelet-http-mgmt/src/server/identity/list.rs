@@ -12,6 +12,7 @@ use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::{Identity, IdentityList};
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -32,9 +33,11 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         let response = self
             .id_manager
             .list()
@@ -68,7 +71,7 @@ where
                     ))?;
                 Ok(response)
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
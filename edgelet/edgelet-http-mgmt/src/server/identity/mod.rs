@@ -2,12 +2,19 @@
 
 mod create;
 mod delete;
+mod delete_all;
+mod get;
 mod list;
+mod patch;
 mod update;
 
 pub use self::create::CreateIdentity;
 pub use self::delete::DeleteIdentity;
+pub(crate) use self::delete_all::delete_all;
+pub use self::delete_all::DeleteAllIdentities;
+pub use self::get::GetIdentity;
 pub use self::list::ListIdentities;
+pub use self::patch::PatchIdentity;
 pub use self::update::UpdateIdentity;
 
 #[cfg(test)]
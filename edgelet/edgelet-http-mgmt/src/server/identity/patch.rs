@@ -0,0 +1,295 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use failure::{err_msg, Fail, ResultExt};
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Request, Response};
+use serde::Serialize;
+use serde_json::{self, json, Value};
+
+use edgelet_core::{
+    AuthType, Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::UpdateIdentity as UpdateIdentityRequest;
+
+use super::update::{identity_spec, write_response};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::server::module::merge;
+use crate::IntoResponse;
+
+/// Applies a JSON Merge Patch (RFC 7396) to an existing identity.
+///
+/// The patch is merged onto the identity as it would be sent to
+/// `PUT /identities/{name}` plus its `name`, so `{"managedBy":"me"}` only
+/// changes who manages it. Unlike `PUT`, an identity that doesn't exist is
+/// not created and `404` is returned. A `name` in the patch has to match the
+/// one in the path, and a patch whose result is not a valid identity is
+/// rejected with `400 Bad Request`. The thumbprint of an X.509 identity
+/// can't be read back, so patches of those have to include `x509Thumbprint`.
+pub struct PatchIdentity<I> {
+    id_manager: Arc<Mutex<I>>,
+}
+
+impl<I> PatchIdentity<I> {
+    pub fn new(id_manager: I) -> Self {
+        PatchIdentity {
+            id_manager: Arc::new(Mutex::new(id_manager)),
+        }
+    }
+}
+
+impl<I> Handler<Parameters> for PatchIdentity<I>
+where
+    I: 'static + IdentityManager + Send,
+    I::Identity: CoreIdentity + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let id_manager = self.id_manager.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(move |b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let patch: Value =
+                            serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
+                        check_name(&name, &patch)?;
+                        Ok((name, patch))
+                    })
+                    .and_then(move |(name, patch)| {
+                        // The identity is patched as soon as it is read, so that
+                        // the future only holds on to the spec.
+                        let spec = id_manager
+                            .lock()
+                            .unwrap()
+                            .get(IdentitySpec::new(name.clone()))
+                            .then(move |result| match result {
+                                Ok(Some(identity)) => patched_spec(name, &identity, patch),
+                                Ok(None) => Err(Error::from(ErrorKind::IdentityNotFound(name))),
+                                Err(err) => {
+                                    Err(Error::from(err.context(ErrorKind::IdentityOperation(
+                                        IdentityOperation::GetIdentity(name),
+                                    ))))
+                                }
+                            });
+
+                        spec.map(|spec| (spec, id_manager))
+                            .and_then(|(spec, id_manager)| {
+                                let name = spec.module_id().to_string();
+                                let mut rid = id_manager.lock().unwrap();
+                                rid.update(spec).map_err(|err| {
+                                    Error::from(err.context(ErrorKind::IdentityOperation(
+                                        IdentityOperation::UpdateIdentity(name),
+                                    )))
+                                })
+                            })
+                    })
+                    .map(|identity| write_response(&identity))
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn check_name(name: &str, patch: &Value) -> Result<(), Error> {
+    match patch.get("name") {
+        Some(Value::String(patch_name)) if patch_name == name => Ok(()),
+        Some(_) => Err(Error::from(
+            err_msg("the name in the body does not match the name in the path")
+                .context(ErrorKind::InvalidIdentityPatch(name.to_string())),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn patched_spec<I>(name: String, identity: &I, patch: Value) -> Result<IdentitySpec, Error>
+where
+    I: CoreIdentity,
+{
+    let mut merged = json!({
+        "name": identity.module_id(),
+        "generationId": identity.generation_id(),
+        "managedBy": identity.managed_by(),
+    });
+    merge(&mut merged, patch);
+
+    let update_req: UpdateIdentityRequest = serde_json::from_value(merged)
+        .with_context(|_| ErrorKind::InvalidIdentityPatch(name.clone()))?;
+    if identity.auth_type() == AuthType::X509 && update_req.x509_thumbprint().is_none() {
+        return Err(Error::from(
+            err_msg("the x509Thumbprint of an X.509 identity has to be given")
+                .context(ErrorKind::InvalidIdentityPatch(name)),
+        ));
+    }
+    identity_spec(name, &update_req)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_test_utils::identity::{TestIdentity, TestIdentityManager};
+    use hyper::StatusCode;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    fn manager(auth_type: AuthType) -> TestIdentityManager {
+        TestIdentityManager::new(vec![TestIdentity::new("m1", "iotedge", "g1", auth_type)])
+            .with_fail_get(false)
+    }
+
+    fn handle(manager: TestIdentityManager, body: &'static str) -> Response<Body> {
+        let handler = PatchIdentity::new(manager);
+        let request = Request::patch("http://localhost/identities/m1")
+            .body(body.into())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "m1".to_string())]);
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body_json(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    fn body_error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let manager = manager(AuthType::Sas);
+
+        // act
+        let response = handle(manager, r#"{"managedBy":"someone"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({
+                "moduleId": "m1",
+                "managedBy": "someone",
+                "generationId": "g1",
+                "authType": "Sas",
+            }),
+            body_json(response)
+        );
+    }
+
+    #[test]
+    fn matching_name_is_accepted() {
+        // arrange
+        let manager = manager(AuthType::Sas);
+
+        // act
+        let response = handle(manager, r#"{"name":"m1","managedBy":"someone"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn mismatched_name_is_bad_request() {
+        // arrange
+        let manager = manager(AuthType::Sas);
+
+        // act
+        let response = handle(manager, r#"{"name":"m2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let error = body_error(response);
+        assert_eq!(Some("InvalidIdentityPatch"), error.code());
+        assert_eq!(
+            "Patch of identity \"m1\" results in an invalid identity\n\tcaused by: the name in the body does not match the name in the path",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn invalid_result_is_bad_request() {
+        // arrange
+        let manager = manager(AuthType::Sas);
+
+        // act
+        let response = handle(manager, r#"{"generationId":null}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("InvalidIdentityPatch"), body_error(response).code());
+    }
+
+    #[test]
+    fn x509_identity_needs_thumbprint() {
+        // arrange
+        let manager = manager(AuthType::X509);
+
+        // act
+        let response = handle(manager, r#"{"managedBy":"someone"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("InvalidIdentityPatch"), body_error(response).code());
+    }
+
+    #[test]
+    fn not_found() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![]).with_fail_get(false);
+
+        // act
+        let response = handle(manager, r#"{"managedBy":"someone"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(Some("IdentityNotFound"), body_error(response).code());
+    }
+
+    #[test]
+    fn bad_body() {
+        // arrange
+        let manager = manager(AuthType::Sas);
+
+        // act
+        let response = handle(manager, "invalid");
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn get_error() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![]);
+
+        // act
+        let response = handle(manager, r#"{"managedBy":"someone"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not get identity m1\n\tcaused by: General error",
+            body_error(response).message()
+        );
+    }
+}
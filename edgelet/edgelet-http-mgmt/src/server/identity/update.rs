@@ -9,11 +9,14 @@ use hyper::{Body, Request, Response, StatusCode};
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_core::{
+    Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec, X509Thumbprint,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::{Identity, UpdateIdentity as UpdateIdentityRequest};
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -39,6 +42,8 @@ where
         req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         let id_manager = self.id_manager.clone();
 
         let response = params
@@ -59,13 +64,13 @@ where
                 })
             })
             .and_then(|id| Ok(write_response(&id)))
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
 }
 
-fn write_response<I>(identity: &I) -> Response<Body>
+pub(super) fn write_response<I>(identity: &I) -> Response<Body>
 where
     I: 'static + CoreIdentity + Serialize,
 {
@@ -103,15 +108,27 @@ fn read_request(
         let b = b.context(ErrorKind::MalformedRequestBody)?;
         let update_req = serde_json::from_slice::<UpdateIdentityRequest>(&b)
             .context(ErrorKind::MalformedRequestBody)?;
-        let mut spec =
-            IdentitySpec::new(name).with_generation_id(update_req.generation_id().to_string());
-        if let Some(m) = update_req.managed_by() {
-            spec = spec.with_managed_by(m.to_string());
-        }
-        Ok(spec)
+        identity_spec(name, &update_req)
     })
 }
 
+pub(super) fn identity_spec(
+    name: String,
+    update_req: &UpdateIdentityRequest,
+) -> Result<IdentitySpec, Error> {
+    let mut spec =
+        IdentitySpec::new(name).with_generation_id(update_req.generation_id().to_string());
+    if let Some(m) = update_req.managed_by() {
+        spec = spec.with_managed_by(m.to_string());
+    }
+    if let Some(t) = update_req.x509_thumbprint() {
+        let thumbprint =
+            X509Thumbprint::new(t.to_string()).context(ErrorKind::MalformedRequestBody)?;
+        spec = spec.with_x509_thumbprint(thumbprint);
+    }
+    Ok(spec)
+}
+
 #[cfg(test)]
 mod tests {
     use edgelet_core::AuthType;
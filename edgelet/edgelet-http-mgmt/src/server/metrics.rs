@@ -0,0 +1,232 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use failure::ResultExt;
+use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::debug;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use edgelet_core::{ModuleRuntime, ModuleStatus};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The collectors exposed on `GET /metrics`.
+///
+/// A single instance is created by `ManagementServiceBuilder::build` and shared by
+/// every clone of the service, so the counters accumulate across connections.
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    modules: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "edgelet_mgmt_requests_total",
+                "Total number of management API calls",
+            ),
+            &["method"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "edgelet_mgmt_request_duration_seconds",
+                "Time taken to handle a management API call",
+            ),
+            &["method"],
+        )?;
+        let modules = IntGaugeVec::new(
+            Opts::new("edgelet_modules", "Number of modules by status"),
+            &["status"],
+        )?;
+
+        let registry = Registry::new();
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(modules.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            requests,
+            request_duration,
+            modules,
+        })
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn observe_request(&self, method: &Method, elapsed: Duration) {
+        let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        self.requests.with_label_values(&[method.as_str()]).inc();
+        self.request_duration
+            .with_label_values(&[method.as_str()])
+            .observe(seconds);
+    }
+
+    /// Modules that are not running, including failed ones, are counted as
+    /// stopped.
+    fn set_module_counts(&self, running: i64, stopped: i64) {
+        self.modules.with_label_values(&["running"]).set(running);
+        self.modules.with_label_values(&["stopped"]).set(stopped);
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, prometheus::Error> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+pub struct GetMetrics<M> {
+    runtime: M,
+    metrics: Arc<Metrics>,
+}
+
+impl<M> GetMetrics<M> {
+    pub fn new(runtime: M, metrics: Arc<Metrics>) -> Self {
+        GetMetrics { runtime, metrics }
+    }
+}
+
+impl<M> Handler<Parameters> for GetMetrics<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        debug!("Get metrics");
+
+        let metrics = self.metrics.clone();
+        let response = self
+            .runtime
+            .list_with_details()
+            .collect()
+            .then(move |modules| -> Result<_, Error> {
+                let modules = modules.context(ErrorKind::Metrics)?;
+                let running = modules
+                    .iter()
+                    .filter(|(_, state)| *state.status() == ModuleStatus::Running)
+                    .count();
+                #[allow(clippy::cast_possible_wrap)]
+                let (running, stopped) = (running as i64, (modules.len() - running) as i64);
+                metrics.set_module_counts(running, stopped);
+
+                let b = metrics.encode().context(ErrorKind::Metrics)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, prometheus::TEXT_FORMAT)
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::Metrics)?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn body_text(response: Response<Body>) -> String {
+        let b = response.into_body().concat2().wait().unwrap();
+        String::from_utf8(b.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn observe_request_accumulates() {
+        // arrange
+        let metrics = Metrics::new().unwrap();
+
+        // act
+        metrics.observe_request(&Method::GET, Duration::from_millis(5));
+        metrics.observe_request(&Method::GET, Duration::from_millis(5));
+        metrics.observe_request(&Method::POST, Duration::from_millis(5));
+
+        // assert
+        let text = String::from_utf8(metrics.encode().unwrap()).unwrap();
+        assert!(text.contains("edgelet_mgmt_requests_total{method=\"GET\"} 2"));
+        assert!(text.contains("edgelet_mgmt_requests_total{method=\"POST\"} 1"));
+        assert!(text.contains("edgelet_mgmt_request_duration_seconds_count{method=\"GET\"} 2"));
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let handler = GetMetrics::new(runtime, metrics);
+        let request = Request::get("http://localhost/metrics")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            "text/plain; version=0.0.4",
+            response.headers()[CONTENT_TYPE]
+        );
+        let text = body_text(response);
+        assert!(text.contains("edgelet_modules{status=\"running\"} 1"));
+        assert!(text.contains("edgelet_modules{status=\"stopped\"} 0"));
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let handler = GetMetrics::new(runtime, metrics);
+        let request = Request::get("http://localhost/metrics")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not collect metrics\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+}
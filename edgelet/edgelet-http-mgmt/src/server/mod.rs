@@ -1,71 +1,456 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use failure::{Compat, ResultExt};
-use futures::{future, Future};
+use std::cmp;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use failure::{Compat, Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::Future;
 use hyper::service::{NewService, Service};
-use hyper::{Body, Request};
+use hyper::{Body, Request, Response};
 use lazy_static::lazy_static;
+use log::{debug, info};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use edgelet_core::{IdentityManager, Module, ModuleRuntime, ModuleRuntimeErrorReason, Policy};
-use edgelet_http::authorization::Authorization;
+use edgelet_core::crypto::KeyStore;
+use edgelet_core::{
+    CertificateManager, CertificateManagerErrorReason, DaemonConfigStore, IdentityManager, Module,
+    ModuleConfigStore, ModuleRuntime, ModuleRuntimeErrorReason, ModuleTrustStore, Policy,
+    ProxySettingsStore, SharedGcPolicy, StateReset, TenantConfig,
+};
+use edgelet_http::authorization::{Authorization, AuthorizedCaller};
 use edgelet_http::route::*;
 use edgelet_http::router;
+use edgelet_utils::with_correlation_id;
 
+mod audit;
+mod auth;
+mod events;
+mod health;
 mod identity;
+mod metrics;
 mod module;
+mod openapi;
+mod system;
 mod system_info;
+mod tenant;
+mod timeout;
+mod versions;
 
+pub use self::audit::DEFAULT_AUDIT_LOG_SIZE;
+use self::audit::{AuditEntry, AuditLog, GetAuditLog};
+use self::auth::IssueAuthToken;
+use self::events::{LocalEvents, WatchEvents};
+use self::health::{GetHealth, GetLiveness};
 use self::identity::*;
+use self::metrics::{GetMetrics, Metrics};
 pub use self::module::*;
+use self::openapi::GetOpenApi;
+use self::system::{
+    GetCapabilities, GetCertificateInventory, GetConnectivityStatus, GetDaemonConfig,
+    GetEdgeAgentState, GetGcPolicy, GetProxyConfig, GetSystemSnapshot, GetTwinDesired, PruneImages,
+    PutGcPolicy, PutProxyConfig, RenewCertificate, ResetDaemonState, UpdateDaemonConfig,
+};
 use self::system_info::*;
-use crate::error::{Error, ErrorKind};
-use edgelet_http::Version;
+use self::tenant::TenantScope;
+use self::timeout::Timeout;
+pub use self::timeout::{Timeouts, DEFAULT_IDENTITY_TIMEOUT, DEFAULT_RUNTIME_TIMEOUT};
+use self::versions::GetVersions;
+use crate::correlation::{Correlated, CorrelationId, REQUEST_ID_HEADER};
+use crate::error::{with_error_code, Error, ErrorKind};
+use edgelet_http::{
+    AccessLogMiddleware, BodySizeLimit, CorsMiddleware, HmacAuthMiddleware, Version,
+    DEFAULT_MAX_BODY_BYTES,
+};
 
 lazy_static! {
     static ref AGENT_NAME: String = "edgeAgent".to_string();
 }
 
+/// The path that issues HMAC tokens, which callers need before they can have
+/// one.
+const AUTH_TOKEN_PATH: &str = "/auth/token";
+
+/// Files copied into modules may be larger than the bodies of other requests.
+fn body_limit(limit: BodySizeLimit, path: &str) -> BodySizeLimit {
+    if path.starts_with("/modules/") && path.ends_with("/copy-file") {
+        BodySizeLimit::new(cmp::max(
+            limit.max_body_bytes(),
+            MAX_COPY_FILE_BYTES + COPY_FILE_BODY_OVERHEAD_BYTES,
+        ))
+    } else {
+        limit
+    }
+}
+
 #[derive(Clone)]
 pub struct ManagementService {
-    inner: RouterService<RegexRecognizer>,
+    inner: Inner,
+    body_limit: BodySizeLimit,
+    cors: CorsMiddleware,
+    hmac_auth: HmacAuthMiddleware,
+    access_log: AccessLogMiddleware,
+    metrics: Arc<Metrics>,
+    audit_log: AuditLog,
+    shutting_down: Arc<AtomicBool>,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Router(RouterService<RegexRecognizer>),
+    RateLimited(RateLimiter<RegexRecognizer>),
+}
+
+/// The stores the management API reads and changes besides the modules and
+/// identities.
+pub struct ManagementStores<S, C, P, D, K> {
+    /// Answers `GET /modules/{name}/config`, and forgets a module's spec
+    /// whenever the module is changed through the API.
+    pub config_store: S,
+    /// Listed by `GET /system/certificates` and renewed by
+    /// `POST /system/certificates/renew`.
+    pub certificates: C,
+    /// The upstream proxies of `/system/proxy`.
+    pub proxy: P,
+    /// The settings of `/system/config`.
+    pub daemon_config: D,
+    /// The keys of the modules that `POST /auth/token` checks SAS tokens with.
+    pub key_store: K,
+    /// The CA certificates of `/modules/{name}/truststore`.
+    pub trust_store: ModuleTrustStore,
+}
+
+/// Builds a `ManagementService`. Everything but the runtime, the identity
+/// manager and the stores has a default, like `DEFAULT_MAX_BODY_BYTES` for
+/// the body limit, no rate limit and no tenants.
+pub struct ManagementServiceBuilder<M, I, S, C, P, D, K> {
+    runtime: M,
+    identity: I,
+    stores: ManagementStores<S, C, P, D, K>,
+    rate_limit: Option<RateLimiterConfig>,
+    max_body_bytes: usize,
+    cors: CorsMiddleware,
+    idempotency_ttl: Duration,
+    timeouts: Timeouts,
+    audit_log_size: usize,
+    secret_filter: SecretFilter,
+    tenants: TenantConfig,
+    gc_policy: SharedGcPolicy,
+    state_reset: StateReset,
+    history: ModuleHistory,
+    registries: RegistryValidator,
+    names: ModuleNameValidator,
+    hmac_auth: HmacAuthMiddleware,
 }
 
 impl ManagementService {
-    pub fn new<M, I>(runtime: &M, identity: &I) -> impl Future<Item = Self, Error = Error>
+    pub fn builder<M, I, S, C, P, D, K>(
+        runtime: &M,
+        identity: &I,
+        stores: ManagementStores<S, C, P, D, K>,
+    ) -> ManagementServiceBuilder<M, I, S, C, P, D, K>
+    where
+        M: Clone,
+        I: Clone,
+    {
+        // Nobody listens for the resets of this one, so they are refused.
+        let (state_reset, _) = StateReset::channel();
+        ManagementServiceBuilder {
+            runtime: runtime.clone(),
+            identity: identity.clone(),
+            stores,
+            rate_limit: None,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            cors: CorsMiddleware::default(),
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL,
+            timeouts: Timeouts::default(),
+            audit_log_size: DEFAULT_AUDIT_LOG_SIZE,
+            secret_filter: SecretFilter::default(),
+            tenants: TenantConfig::default(),
+            gc_policy: SharedGcPolicy::default(),
+            state_reset,
+            history: ModuleHistory::default(),
+            registries: RegistryValidator::default(),
+            names: ModuleNameValidator::default(),
+            hmac_auth: HmacAuthMiddleware::default(),
+        }
+    }
+
+    /// Once this flag is set the service refuses to serve new connections,
+    /// which lets a server that is shutting down drain the connections it
+    /// already has. Clones of the service share the flag.
+    pub fn shutting_down(&self) -> Arc<AtomicBool> {
+        self.shutting_down.clone()
+    }
+}
+
+impl<M, I, S, C, P, D, K> ManagementServiceBuilder<M, I, S, C, P, D, K> {
+    /// Clients that exceed the rate limit get a `429` response without the
+    /// request reaching the router.
+    pub fn with_rate_limit(mut self, rate_limit: RateLimiterConfig) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Requests with a larger body get a `413` response.
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    /// Decides which browser origins may call the API over TCP.
+    pub fn with_cors(mut self, cors: CorsMiddleware) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    /// How long responses to module creations with an idempotency key are
+    /// replayed.
+    pub fn with_idempotency_ttl(mut self, idempotency_ttl: Duration) -> Self {
+        self.idempotency_ttl = idempotency_ttl;
+        self
+    }
+
+    /// Requests that aren't answered in time get a `504` response.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// How many requests are kept for `GET /auditlog`.
+    pub fn with_audit_log_size(mut self, audit_log_size: usize) -> Self {
+        self.audit_log_size = audit_log_size;
+        self
+    }
+
+    /// Redacts the environment variables it matches in module specs.
+    pub fn with_secret_filter(mut self, secret_filter: SecretFilter) -> Self {
+        self.secret_filter = secret_filter;
+        self
+    }
+
+    /// The tenants whose modules manage their modules under
+    /// `/tenants/{tenant}`.
+    pub fn with_tenants(mut self, tenants: TenantConfig) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    /// Read and replaced through `/system/gcpolicy`.
+    pub fn with_gc_policy(mut self, gc_policy: SharedGcPolicy) -> Self {
+        self.gc_policy = gc_policy;
+        self
+    }
+
+    /// Requested by `DELETE /system/state`.
+    pub fn with_state_reset(mut self, state_reset: StateReset) -> Self {
+        self.state_reset = state_reset;
+        self
+    }
+
+    /// Records the updates of modules for `GET /modules/{name}/ancestry`.
+    pub fn with_history(mut self, history: ModuleHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// The registries that the images of created or updated modules may
+    /// come from.
+    pub fn with_registry_validator(mut self, registries: RegistryValidator) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    /// The names that created or renamed modules may have.
+    pub fn with_name_validator(mut self, names: ModuleNameValidator) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Requests over TCP need one of its tokens when it has a key.
+    pub fn with_hmac_auth(mut self, hmac_auth: HmacAuthMiddleware) -> Self {
+        self.hmac_auth = hmac_auth;
+        self
+    }
+
+    /// Every request is also written to the access log if
+    /// `IOTEDGE_ACCESS_LOG` is `1`.
+    pub fn build(self) -> impl Future<Item = ManagementService, Error = Error>
     where
         M: 'static + ModuleRuntime + Clone + Send + Sync,
         for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
         <M::Module as Module>::Config: DeserializeOwned + Serialize,
         M::Logs: Into<Body>,
+        M::RegistryAuth: DeserializeOwned,
+        M::PullImageStream: 'static,
         I: 'static + IdentityManager + Clone + Send + Sync,
         I::Identity: Serialize,
+        S: 'static
+            + ModuleConfigStore<Config = <M::Module as Module>::Config>
+            + Clone
+            + Send
+            + Sync,
+        C: 'static + CertificateManager + Clone + Send + Sync,
+        for<'r> &'r C::Error: Into<CertificateManagerErrorReason>,
+        P: 'static + ProxySettingsStore + Clone + Send + Sync,
+        D: 'static + DaemonConfigStore + Clone + Send + Sync,
+        K: 'static + KeyStore + Clone + Send + Sync,
     {
+        let ManagementServiceBuilder {
+            runtime,
+            identity,
+            stores:
+                ManagementStores {
+                    config_store,
+                    certificates,
+                    proxy,
+                    daemon_config,
+                    key_store,
+                    trust_store,
+                },
+            rate_limit,
+            max_body_bytes,
+            cors,
+            idempotency_ttl,
+            timeouts,
+            audit_log_size,
+            secret_filter,
+            tenants,
+            gc_policy,
+            state_reset,
+            history,
+            registries,
+            names,
+            hmac_auth,
+        } = self;
+
+        let metrics = match Metrics::new().context(ErrorKind::StartService) {
+            Ok(metrics) => Arc::new(metrics),
+            Err(err) => return Either::A(future::err(Error::from(err))),
+        };
+        let audit_log = AuditLog::new(audit_log_size);
+        let local_events = LocalEvents::default();
+
         let router = router!(
-            get     Version2018_06_28,  "/modules"                         => Authorization::new(ListModules::new(runtime.clone()), Policy::Anonymous, runtime.clone()),
-            post    Version2018_06_28,  "/modules"                         => Authorization::new(CreateModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-            get     Version2018_06_28,  "/modules/(?P<name>[^/]+)"         => Authorization::new(GetModule, Policy::Anonymous, runtime.clone()),
-            put     Version2018_06_28,  "/modules/(?P<name>[^/]+)"         => Authorization::new(UpdateModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/prepareupdate"        => Authorization::new(PrepareUpdateModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-            delete  Version2018_06_28,  "/modules/(?P<name>[^/]+)"         => Authorization::new(DeleteModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-            post    Version2018_06_28,  "/modules/(?P<name>[^/]+)/start"   => Authorization::new(StartModule::new(runtime.clone()), Policy::Anonymous, runtime.clone()),
-            post    Version2018_06_28,  "/modules/(?P<name>[^/]+)/stop"    => Authorization::new(StopModule::new(runtime.clone()), Policy::Anonymous, runtime.clone()),
-            post    Version2018_06_28,  "/modules/(?P<name>[^/]+)/restart" => Authorization::new(RestartModule::new(runtime.clone()), Policy::Anonymous, runtime.clone()),
-            get     Version2018_06_28,  "/modules/(?P<name>[^/]+)/logs"    => Authorization::new(ModuleLogs::new(runtime.clone()), Policy::Anonymous, runtime.clone()),
-
-            get     Version2018_06_28,  "/identities"                      => Authorization::new(ListIdentities::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-            post    Version2018_06_28,  "/identities"                      => Authorization::new(CreateIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-            put     Version2018_06_28,  "/identities/(?P<name>[^/]+)"      => Authorization::new(UpdateIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-            delete  Version2018_06_28,  "/identities/(?P<name>[^/]+)"      => Authorization::new(DeleteIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()),
-
-            get     Version2018_06_28,  "/systeminfo"                      => Authorization::new(GetSystemInfo::new(runtime.clone()), Policy::Anonymous, runtime.clone()),
+            get     Version2018_06_28,  "/modules"                         => Timeout::runtime(Authorization::new(ListModules::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            post    Version2018_06_28,  "/modules"                         => Timeout::runtime(Authorization::new(CreateModule::new(runtime.clone()).with_idempotency_ttl(idempotency_ttl).with_secret_filter(secret_filter.clone()).with_registry_validator(registries.clone()).with_name_validator(names.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/bulk/start"              => Timeout::runtime(Authorization::new(BulkStartModules::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/bulk/stop"               => Timeout::runtime(Authorization::new(BulkStopModules::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            delete  Version2019_01_30,  "/modules/all"                     => InvalidateConfig::new(Timeout::runtime(Authorization::new(DeleteAllModules::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            get     Version2019_01_30,  "/modules/graph"                   => Timeout::runtime(Authorization::new(GetModuleGraph::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/watch"                   => Timeout::runtime(Authorization::new(WatchModules::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2018_06_28,  "/modules/(?P<name>[^/]+)"         => Timeout::runtime(Authorization::new(GetModule, Policy::Anonymous, runtime.clone()), timeouts),
+            put     Version2018_06_28,  "/modules/(?P<name>[^/]+)"         => InvalidateConfig::new(Timeout::runtime(Authorization::new(UpdateModule::new(runtime.clone()).with_secret_filter(secret_filter.clone()).with_history(history.clone()).with_registry_validator(registries.clone()).with_name_validator(names.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            patch   Version2019_01_30,  "/modules/(?P<name>[^/]+)"         => InvalidateConfig::new(Timeout::runtime(Authorization::new(PatchModule::new(runtime.clone()).with_secret_filter(secret_filter.clone()).with_registry_validator(registries.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/prepareupdate"        => InvalidateConfig::new(Timeout::runtime(Authorization::new(PrepareUpdateModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/rollback" => InvalidateConfig::new(Timeout::runtime(Authorization::new(RollbackModule::new(runtime.clone(), history.clone()).with_secret_filter(secret_filter.clone()).with_registry_validator(registries.clone()).with_local_events(local_events.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/promote" => InvalidateConfig::new(Timeout::runtime(Authorization::new(PromoteModule::new(runtime.clone()).with_secret_filter(secret_filter.clone()).with_registry_validator(registries.clone()).with_local_events(local_events.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/update-image" => InvalidateConfig::new(Timeout::runtime(Authorization::new(UpdateModuleImage::new(runtime.clone()).with_secret_filter(secret_filter.clone()).with_history(history.clone()).with_registry_validator(registries.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/prepareimage"         => Timeout::runtime(Authorization::new(PrepareModuleImage::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            delete  Version2018_06_28,  "/modules/(?P<name>[^/]+)"         => InvalidateConfig::new(Timeout::runtime(Authorization::new(DeleteModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            post    Version2018_06_28,  "/modules/(?P<name>[^/]+)/start"   => Timeout::runtime(Authorization::new(StartModule::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            post    Version2018_06_28,  "/modules/(?P<name>[^/]+)/stop"    => Timeout::runtime(Authorization::new(StopModule::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts.with_extra_runtime(MAX_STOP_WAIT_TIMEOUT)),
+            post    Version2018_06_28,  "/modules/(?P<name>[^/]+)/restart" => Timeout::runtime(Authorization::new(RestartModule::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2018_06_28,  "/modules/(?P<name>[^/]+)/logs"    => Timeout::runtime(Authorization::new(ModuleLogs::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/copy-logs" => Timeout::runtime(Authorization::new(CopyModuleLogs::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/metrics" => Timeout::runtime(Authorization::new(GetModuleMetrics::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/top"     => Timeout::runtime(Authorization::new(GetModuleTop::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/stats"   => Timeout::runtime(Authorization::new(ModuleStats::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/config"  => Timeout::runtime(Authorization::new(GetModuleConfig::new(runtime.clone(), config_store.clone()).with_secret_filter(secret_filter.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/diff"    => Timeout::runtime(Authorization::new(GetModuleDiff::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/status"  => Timeout::runtime(Authorization::new(GetModuleStatus::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/inspect" => Timeout::runtime(Authorization::new(InspectModule::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/ports"   => Timeout::runtime(Authorization::new(GetModulePorts::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/volumes" => Timeout::runtime(Authorization::new(GetModuleVolumes::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/ancestry" => Timeout::runtime(Authorization::new(GetModuleAncestry::new(history.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/env"     => Timeout::runtime(Authorization::new(GetModuleEnv::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            patch   Version2019_01_30,  "/modules/(?P<name>[^/]+)/env"     => InvalidateConfig::new(Timeout::runtime(Authorization::new(PatchModuleEnv::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/labels/(?P<key>[^/]+)" => Timeout::runtime(Authorization::new(GetModuleLabel::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            put     Version2019_01_30,  "/modules/(?P<name>[^/]+)/labels/(?P<key>[^/]+)" => InvalidateConfig::new(Timeout::runtime(Authorization::new(SetModuleLabel::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            delete  Version2019_01_30,  "/modules/(?P<name>[^/]+)/labels/(?P<key>[^/]+)" => InvalidateConfig::new(Timeout::runtime(Authorization::new(DeleteModuleLabel::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            delete  Version2019_01_30,  "/modules/(?P<name>[^/]+)/labels"  => InvalidateConfig::new(Timeout::runtime(Authorization::new(DeleteAllModuleLabels::new(runtime.clone()).with_secret_filter(secret_filter.clone()).with_local_events(local_events.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/exec"    => Timeout::runtime(Authorization::new(ExecModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/signal"  => Timeout::runtime(Authorization::new(SignalModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/pause"   => Timeout::runtime(Authorization::new(PauseModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/unpause" => Timeout::runtime(Authorization::new(UnpauseModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/rename"  => Timeout::runtime(Authorization::new(RenameModule::new(runtime.clone(), config_store.clone(), history.clone()).with_name_validator(names.clone()).with_local_events(local_events.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/copy-file" => Timeout::runtime(Authorization::new(CopyFileToModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/modules/(?P<name>[^/]+)/truststore" => Timeout::runtime(Authorization::new(GetModuleTrustStore::new(trust_store.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/truststore" => Timeout::runtime(Authorization::new(AddModuleTrustStore::new(trust_store.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            delete  Version2019_01_30,  "/modules/(?P<name>[^/]+)/truststore" => Timeout::runtime(Authorization::new(RemoveModuleTrustStore::new(trust_store), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/checkpoint" => Timeout::runtime(Authorization::new(CheckpointModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/modules/(?P<name>[^/]+)/restore" => InvalidateConfig::new(Timeout::runtime(Authorization::new(RestoreModule::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            patch   Version2019_01_30,  "/modules/(?P<name>[^/]+)/resources" => InvalidateConfig::new(Timeout::runtime(Authorization::new(PatchModuleResources::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts), config_store.clone()),
+            put     Version2019_01_30,  "/modules/(?P<name>[^/]+)/bandwidth" => Timeout::runtime(Authorization::new(SetModuleBandwidth::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+
+            get     Version2019_01_30,  "/tenants/(?P<tenant>[^/]+)/modules"                  => Timeout::runtime(TenantScope::new(Authorization::new(ListModules::new(runtime.clone()).with_secret_filter(secret_filter.clone()), Policy::Tenant, runtime.clone()), tenants.clone()), timeouts),
+            post    Version2019_01_30,  "/tenants/(?P<tenant>[^/]+)/modules"                  => Timeout::runtime(TenantScope::new(Authorization::new(CreateModule::new(runtime.clone()).with_idempotency_ttl(idempotency_ttl).with_secret_filter(secret_filter.clone()).with_registry_validator(registries.clone()).with_name_validator(names.clone()), Policy::Tenant, runtime.clone()), tenants.clone()), timeouts),
+            put     Version2019_01_30,  "/tenants/(?P<tenant>[^/]+)/modules/(?P<name>[^/]+)"  => InvalidateConfig::new(Timeout::runtime(TenantScope::new(Authorization::new(UpdateModule::new(runtime.clone()).with_secret_filter(secret_filter.clone()).with_registry_validator(registries.clone()).with_name_validator(names.clone()), Policy::Tenant, runtime.clone()), tenants.clone()), timeouts), config_store.clone()),
+            delete  Version2019_01_30,  "/tenants/(?P<tenant>[^/]+)/modules/(?P<name>[^/]+)"  => InvalidateConfig::new(Timeout::runtime(TenantScope::new(Authorization::new(DeleteModule::new(runtime.clone()), Policy::Tenant, runtime.clone()), tenants), timeouts), config_store.clone()),
+
+            get     Version2018_06_28,  "/identities"                      => Timeout::identity(Authorization::new(ListIdentities::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2018_06_28,  "/identities"                      => Timeout::identity(Authorization::new(CreateIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            delete  Version2019_01_30,  "/identities/all"                  => Timeout::identity(Authorization::new(DeleteAllIdentities::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/identities/(?P<name>[^/]+)"      => Timeout::identity(Authorization::new(GetIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            put     Version2018_06_28,  "/identities/(?P<name>[^/]+)"      => Timeout::identity(Authorization::new(UpdateIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            patch   Version2019_01_30,  "/identities/(?P<name>[^/]+)"      => Timeout::identity(Authorization::new(PatchIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            delete  Version2018_06_28,  "/identities/(?P<name>[^/]+)"      => Timeout::identity(Authorization::new(DeleteIdentity::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+
+            get     Version2018_06_28,  "/systeminfo"                      => Timeout::runtime(Authorization::new(GetSystemInfo::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/systeminfo/resources"            => Timeout::runtime(Authorization::new(GetSystemResources::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/system/pruneimages"              => Timeout::runtime(Authorization::new(PruneImages::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/edgeagent"                => Timeout::identity(Authorization::new(GetEdgeAgentState::new(identity.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/twin/desired"             => Timeout::identity(Authorization::new(GetTwinDesired::new(identity.clone(), runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/capabilities"             => Timeout::runtime(Authorization::new(GetCapabilities::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/connectivity"             => Timeout::runtime(Authorization::new(GetConnectivityStatus::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/gcpolicy"                 => Timeout::runtime(Authorization::new(GetGcPolicy::new(gc_policy.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            put     Version2019_01_30,  "/system/gcpolicy"                 => Timeout::runtime(Authorization::new(PutGcPolicy::new(gc_policy), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/certificates"             => Timeout::runtime(Authorization::new(GetCertificateInventory::new(certificates.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            post    Version2019_01_30,  "/system/certificates/renew"       => Timeout::runtime(Authorization::new(RenewCertificate::new(certificates.clone(), runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/proxy"                    => Timeout::runtime(Authorization::new(GetProxyConfig::new(proxy.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            put     Version2019_01_30,  "/system/proxy"                    => Timeout::runtime(Authorization::new(PutProxyConfig::new(proxy), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/config"                   => Timeout::runtime(Authorization::new(GetDaemonConfig::new(daemon_config.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            put     Version2019_01_30,  "/system/config"                   => Timeout::runtime(Authorization::new(UpdateDaemonConfig::new(daemon_config), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/system/snapshot"                 => Timeout::runtime(Authorization::new(GetSystemSnapshot::new(runtime.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            delete  Version2019_01_30,  "/system/state"                    => Timeout::identity(Authorization::new(ResetDaemonState::new(identity.clone(), state_reset), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+
+            get     Version2019_01_30,  "/auditlog"                        => Timeout::runtime(Authorization::new(GetAuditLog::new(audit_log.clone()), Policy::Module(&*AGENT_NAME), runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/metrics"                         => Timeout::runtime(Authorization::new(GetMetrics::new(runtime.clone(), metrics.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+
+            get     Version2019_01_30,  "/healthz"                         => Timeout::runtime(Authorization::new(GetHealth::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/healthz/live"                    => Timeout::runtime(Authorization::new(GetLiveness, Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2019_01_30,  "/healthz/ready"                   => Timeout::runtime(Authorization::new(GetHealth::new(runtime.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+
+            post    Version2019_01_30,  "/auth/token"                      => Timeout::runtime(Authorization::new(IssueAuthToken::new(key_store, hmac_auth.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+
+            get     Version2019_01_30,  "/events"                          => Timeout::runtime(Authorization::new(WatchEvents::new(runtime.clone()).with_local_events(local_events.clone()), Policy::Anonymous, runtime.clone()), timeouts),
+
+            // Routes added here must also be described in `openapi::ROUTES`.
+            get     Unversioned,        "/versions"                        => Timeout::runtime(Authorization::new(GetVersions, Policy::Anonymous, runtime.clone()), timeouts),
+            get     Version2018_06_28,  "/swagger.json"                    => Timeout::runtime(Authorization::new(GetOpenApi, Policy::Anonymous, runtime.clone()), timeouts),
         );
 
-        router.new_service().then(|inner| {
+        Either::B(router.new_service().then(move |inner| {
             let inner = inner.context(ErrorKind::StartService)?;
-            Ok(ManagementService { inner })
-        })
+            let inner = match rate_limit {
+                Some(config) => Inner::RateLimited(RateLimiter::new(inner, config)),
+                None => Inner::Router(inner),
+            };
+            Ok(ManagementService {
+                inner,
+                body_limit: BodySizeLimit::new(max_body_bytes),
+                cors,
+                hmac_auth,
+                access_log: AccessLogMiddleware::from_env(),
+                metrics,
+                audit_log,
+                shutting_down: Arc::new(AtomicBool::new(false)),
+            })
+        }))
     }
 }
 
@@ -73,10 +458,94 @@ impl Service for ManagementService {
     type ReqBody = <RouterService<RegexRecognizer> as Service>::ReqBody;
     type ResBody = <RouterService<RegexRecognizer> as Service>::ResBody;
     type Error = <RouterService<RegexRecognizer> as Service>::Error;
-    type Future = <RouterService<RegexRecognizer> as Service>::Future;
+    type Future = Box<dyn Future<Item = Response<Self::ResBody>, Error = Self::Error> + Send>;
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let access_log = self.access_log.request(&req);
+        if let Some(response) = self.cors.preflight(&req) {
+            access_log.log(&response);
+            return Box::new(with_error_code(response));
+        }
+        let cors_headers = self.cors.headers(&req);
+
+        let correlation_id = CorrelationId::from_request(&req);
+        debug!(
+            "Handling request {} {} {}",
+            correlation_id.as_str(),
+            req.method(),
+            req.uri().path()
+        );
+        req.extensions_mut().insert(correlation_id.clone());
+        let request_id = correlation_id.as_str().to_string();
+
+        let metrics = self.metrics.clone();
+        let audit_log = self.audit_log.clone();
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        let started = Instant::now();
+
+        // Requests refused for their HMAC token are answered like any other,
+        // so they are audited and have the CORS headers.
+        let authenticated = if uri.path() == AUTH_TOKEN_PATH {
+            Ok(())
+        } else {
+            self.hmac_auth
+                .authenticate(&mut req, Utc::now())
+                .map_err(|response| {
+                    info!(
+                        "Refused {} {} without a valid HMAC token",
+                        method,
+                        uri.path()
+                    );
+                    response
+                })
+        };
+        let limit = body_limit(self.body_limit, uri.path());
+        let response = match authenticated.and_then(|()| limit.limit(req)) {
+            Ok((req, body_size)) => {
+                // Handlers start talking to the runtime as they are called,
+                // before the response future is first polled.
+                let inner = &mut self.inner;
+                let response = with_correlation_id(correlation_id.as_str(), || match inner {
+                    Inner::Router(inner) => inner.call(req),
+                    Inner::RateLimited(inner) => inner.call(req),
+                });
+                Either::A(response.map(move |response| body_size.check(response)))
+            }
+            Err(response) => Either::B(future::ok(response)),
+        };
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
-        self.inner.call(req)
+        Box::new(Correlated::new(
+            correlation_id.clone(),
+            response
+                .then(move |result| {
+                    metrics.observe_request(&method, started.elapsed());
+                    if let Ok(ref response) = result {
+                        let source_module = response
+                            .extensions()
+                            .get::<AuthorizedCaller>()
+                            .map(|caller| caller.name().to_string());
+                        audit_log.record(AuditEntry::new(
+                            Utc::now(),
+                            &method,
+                            &uri,
+                            source_module,
+                            response.status(),
+                            request_id,
+                        ));
+                    }
+                    result
+                })
+                .and_then(with_error_code)
+                .map(move |mut response| {
+                    if let Some(value) = correlation_id.header_value() {
+                        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                    }
+                    let response = cors_headers.apply(response);
+                    access_log.log(&response);
+                    response
+                }),
+        ))
     }
 }
 
@@ -89,6 +558,10 @@ impl NewService for ManagementService {
     type InitError = Compat<Error>;
 
     fn new_service(&self) -> Self::Future {
-        future::ok(self.clone())
+        if self.shutting_down.load(Ordering::SeqCst) {
+            future::err(Error::from(ErrorKind::ShuttingDown).compat())
+        } else {
+            future::ok(self.clone())
+        }
     }
 }
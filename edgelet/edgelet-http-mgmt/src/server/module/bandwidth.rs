@@ -0,0 +1,219 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_derive::Deserialize;
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+struct BandwidthRequest {
+    ingress_kbps: u32,
+    egress_kbps: u32,
+}
+
+/// Limits the network traffic of a running module, in kilobits per second in
+/// each direction, so that it can't saturate the uplink of the device. A rate
+/// of 0 leaves that direction unlimited, and with `?reset=true` the limits
+/// are removed and the body is ignored.
+///
+/// Like changes of resource limits, the limits only last until the module's
+/// container is next created.
+pub struct SetModuleBandwidth<M> {
+    runtime: M,
+}
+
+impl<M> SetModuleBandwidth<M> {
+    pub fn new(runtime: M) -> Self {
+        SetModuleBandwidth { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for SetModuleBandwidth<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                let reset = parse_reset(req.uri().query().unwrap_or(""))?;
+                Ok((name.to_string(), reset))
+            })
+            .map(|(name, reset)| {
+                let operation = {
+                    let name = name.clone();
+                    move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::SetModuleBandwidth(
+                            name.clone(),
+                        ))
+                    }
+                };
+
+                req.into_body()
+                    .concat2()
+                    .then(move |b| -> Result<_, Error> {
+                        if reset {
+                            return Ok((0, 0));
+                        }
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let request = serde_json::from_slice::<BandwidthRequest>(&b)
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        Ok((request.ingress_kbps, request.egress_kbps))
+                    })
+                    .and_then({
+                        let operation = operation.clone();
+                        move |rates| {
+                            runtime.get(&name).then(move |result| -> Result<_, Error> {
+                                let (_, state) = result.with_context(|_| operation())?;
+                                if *state.status() != ModuleStatus::Running {
+                                    return Err(Error::from(ErrorKind::ModuleNotRunning(name)));
+                                }
+                                Ok((name, rates, runtime))
+                            })
+                        }
+                    })
+                    .and_then(move |(name, (ingress_kbps, egress_kbps), runtime)| {
+                        runtime
+                            .set_bandwidth(&name, ingress_kbps, egress_kbps)
+                            .then(move |result| -> Result<_, Error> {
+                                result.with_context(|_| operation())?;
+                                let response = Response::builder()
+                                    .status(StatusCode::NO_CONTENT)
+                                    .body(Body::default())
+                                    .with_context(|_| operation())?;
+                                Ok(response)
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn parse_reset(query: &str) -> Result<bool, Error> {
+    match form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "reset") {
+        Some((_, val)) => Ok(val
+            .parse::<bool>()
+            .context(ErrorKind::MalformedRequestParameter("reset"))?),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(runtime: TestRuntime<Error>, uri: &str, body: &str) -> Response<Body> {
+        let handler = SetModuleBandwidth::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::put(uri).body(body.to_string().into()).unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(
+            runtime.clone(),
+            "http://localhost/modules/mod1/bandwidth",
+            r#"{"ingress_kbps":1024,"egress_kbps":512}"#,
+        );
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(vec![("mod1".to_string(), 1024, 512)], runtime.bandwidths());
+    }
+
+    #[test]
+    fn reset_ignores_body() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(
+            runtime.clone(),
+            "http://localhost/modules/mod1/bandwidth?reset=true",
+            "",
+        );
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(vec![("mod1".to_string(), 0, 0)], runtime.bandwidths());
+    }
+
+    #[test]
+    fn missing_rate_is_bad_request() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(
+            runtime.clone(),
+            "http://localhost/modules/mod1/bandwidth",
+            r#"{"ingress_kbps":1024}"#,
+        );
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert!(runtime.bandwidths().is_empty());
+    }
+
+    #[test]
+    fn not_running() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Stopped);
+
+        // act
+        let response = handle(
+            runtime,
+            "http://localhost/modules/mod1/bandwidth",
+            r#"{"ingress_kbps":1024,"egress_kbps":512}"#,
+        );
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        assert_eq!(Some("ModuleNotRunning"), error(response).code());
+    }
+}
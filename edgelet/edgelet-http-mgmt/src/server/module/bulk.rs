@@ -0,0 +1,430 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+use url::form_urlencoded::parse as parse_query;
+
+use edgelet_core::{
+    Error as CoreError, ErrorKind as CoreErrorKind, ModuleRuntime, RuntimeOperation, StartResult,
+};
+use edgelet_docker::ErrorKind as DockerErrorKind;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::BulkModuleRequest;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Starts every module named in the request body.
+///
+/// The operations are issued concurrently and are not atomic: each module is
+/// started independently and the response maps every name to either `"ok"`,
+/// `"not found"` or the error that occurred. No ordering guarantee is made
+/// about the order in which the modules are started.
+///
+/// With `?ordered=true` the modules are started with
+/// `ModuleRuntime::start_all` instead, after the modules they depend on. A
+/// module whose dependency didn't start isn't started, and a cycle in the
+/// dependencies fails the whole request without starting anything.
+pub struct BulkStartModules<M> {
+    runtime: M,
+}
+
+impl<M> BulkStartModules<M> {
+    pub fn new(runtime: M) -> Self {
+        BulkStartModules { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for BulkStartModules<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        if parse_ordered(req.uri().query()) {
+            handle_ordered_start(self.runtime.clone(), req)
+        } else {
+            handle_bulk(self.runtime.clone(), req, BulkOperation::Start)
+        }
+    }
+}
+
+/// Stops every module named in the request body.
+///
+/// The operations are issued concurrently and are not atomic: each module is
+/// stopped independently and the response maps every name to either `"ok"`,
+/// `"not found"` or the error that occurred. No ordering guarantee is made
+/// about the order in which the modules are stopped.
+pub struct BulkStopModules<M> {
+    runtime: M,
+}
+
+impl<M> BulkStopModules<M> {
+    pub fn new(runtime: M) -> Self {
+        BulkStopModules { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for BulkStopModules<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        handle_bulk(self.runtime.clone(), req, BulkOperation::Stop)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BulkOperation {
+    Start,
+    Stop,
+}
+
+impl BulkOperation {
+    fn context(self) -> ErrorKind {
+        match self {
+            BulkOperation::Start => ErrorKind::StartModules,
+            BulkOperation::Stop => ErrorKind::StopModules,
+        }
+    }
+
+    fn module_context(self, name: String) -> ErrorKind {
+        match self {
+            BulkOperation::Start => {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(name))
+            }
+            BulkOperation::Stop => ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(name)),
+        }
+    }
+}
+
+fn handle_bulk<M>(
+    runtime: M,
+    req: Request<Body>,
+    operation: BulkOperation,
+) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    let correlation_id = correlation_id(&req);
+
+    let response = request_names(req)
+        .and_then(move |names| {
+            let operations = names.into_iter().map(move |name| {
+                let result = match operation {
+                    BulkOperation::Start => Either::A(runtime.start(&name)),
+                    BulkOperation::Stop => Either::B(runtime.stop(&name, None)),
+                };
+                result.then(move |result| {
+                    let outcome = match result {
+                        Ok(_) => "ok".to_string(),
+                        Err(err) => outcome_for_error(&Error::from(
+                            err.context(operation.module_context(name.clone())),
+                        )),
+                    };
+                    Ok::<_, Error>((name, outcome))
+                })
+            });
+
+            future::join_all(operations).and_then(move |outcomes| {
+                outcomes_response(outcomes.into_iter().collect(), operation)
+            })
+        })
+        .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+    Box::new(response)
+}
+
+/// Starts the named modules from their stored specs in the order of their
+/// dependencies. Modules without a stored spec are `"not found"`.
+fn handle_ordered_start<M>(
+    runtime: M,
+    req: Request<Body>,
+) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    let correlation_id = correlation_id(&req);
+
+    let response = request_names(req)
+        .and_then(move |names| {
+            let specs = names
+                .into_iter()
+                .map(|name| {
+                    runtime.get_config(&name).then(move |spec| {
+                        let spec = spec.map_err(|err| {
+                            outcome_for_error(&Error::from(err.context(
+                                ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(
+                                    name.clone(),
+                                )),
+                            )))
+                        });
+                        Ok::<_, Error>((name, spec))
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            future::join_all(specs).and_then(move |specs| {
+                let mut outcomes = BTreeMap::new();
+                let mut found = Vec::new();
+                for (name, spec) in specs {
+                    match spec {
+                        Ok(Some(spec)) => found.push(spec),
+                        Ok(None) => {
+                            outcomes.insert(name, "not found".to_string());
+                        }
+                        Err(outcome) => {
+                            outcomes.insert(name, outcome);
+                        }
+                    }
+                }
+
+                runtime
+                    .start_all(found)
+                    .map_err(start_all_error)
+                    .and_then(move |results| {
+                        outcomes.extend(results.iter().map(|result| {
+                            (result.module_name().to_string(), start_outcome(result))
+                        }));
+                        outcomes_response(outcomes, BulkOperation::Start)
+                    })
+            })
+        })
+        .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+    Box::new(response)
+}
+
+/// The module names of the request body, without duplicates.
+fn request_names(req: Request<Body>) -> impl Future<Item = BTreeSet<String>, Error = Error> {
+    req.into_body().concat2().then(|b| -> Result<_, Error> {
+        let b = b.context(ErrorKind::MalformedRequestBody)?;
+        let request = serde_json::from_slice::<BulkModuleRequest>(&b)
+            .context(ErrorKind::MalformedRequestBody)?;
+        Ok(request.names().iter().cloned().collect())
+    })
+}
+
+fn outcomes_response(
+    outcomes: BTreeMap<String, String>,
+    operation: BulkOperation,
+) -> Result<Response<Body>, Error> {
+    let b = serde_json::to_string(&outcomes).context(operation.context())?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(operation.context())?;
+    Ok(response)
+}
+
+/// Modules are started in order when the `ordered` query parameter is
+/// `true`.
+fn parse_ordered(query: Option<&str>) -> bool {
+    query.map_or(false, |query| {
+        parse_query(query.as_bytes()).any(|(key, value)| key == "ordered" && value == "true")
+    })
+}
+
+fn start_outcome(result: &StartResult) -> String {
+    if result.is_started() {
+        "ok".to_string()
+    } else {
+        result.error().unwrap_or("not started").to_string()
+    }
+}
+
+fn start_all_error(err: CoreError) -> Error {
+    match err.kind() {
+        CoreErrorKind::ModuleDependencyCycle(modules) => {
+            Error::from(ErrorKind::ModuleDependencyCycle(modules.clone()))
+        }
+        _ => Error::from(err.context(ErrorKind::StartModules)),
+    }
+}
+
+pub(super) fn outcome_for_error(err: &Error) -> String {
+    if let Some(DockerErrorKind::NotFound(_)) =
+        Fail::find_root_cause(err).downcast_ref::<DockerErrorKind>()
+    {
+        return "not found".to_string();
+    }
+
+    let mut message = err.to_string();
+    for cause in Fail::iter_causes(err) {
+        message.push_str(&format!("\n\tcaused by: {}", cause));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    #[test]
+    fn start_success() {
+        // arrange
+        let handler = BulkStartModules::new(runtime());
+        let body = serde_json::to_string(&BulkModuleRequest::new(vec![
+            "mod-b".to_string(),
+            "mod-a".to_string(),
+        ]))
+        .unwrap();
+        let request = Request::post("http://localhost/modules/bulk/start")
+            .body(body.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let outcomes: BTreeMap<String, String> = serde_json::from_slice(&b).unwrap();
+                assert_eq!(2, outcomes.len());
+                assert_eq!("ok", outcomes["mod-a"]);
+                assert_eq!("ok", outcomes["mod-b"]);
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn stop_runtime_error() {
+        // arrange
+        let handler = BulkStopModules::new(TestRuntime::new(Err(Error::General)));
+        let body =
+            serde_json::to_string(&BulkModuleRequest::new(vec!["mod-a".to_string()])).unwrap();
+        let request = Request::post("http://localhost/modules/bulk/stop")
+            .body(body.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let outcomes: BTreeMap<String, String> = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not stop module mod-a\n\tcaused by: General error",
+                    outcomes["mod-a"]
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    fn outcomes(response: Response<Body>) -> BTreeMap<String, String> {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn ordered_start_success() {
+        // arrange
+        let handler = BulkStartModules::new(runtime());
+        let body = serde_json::to_string(&BulkModuleRequest::new(vec![
+            "test-module".to_string(),
+            "missing".to_string(),
+        ]))
+        .unwrap();
+        let request = Request::post("http://localhost/modules/bulk/start?ordered=true")
+            .body(body.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let outcomes = outcomes(response);
+        assert_eq!(2, outcomes.len());
+        assert_eq!("ok", outcomes["test-module"]);
+        assert_eq!("not found", outcomes["missing"]);
+    }
+
+    #[test]
+    fn ordered_start_error() {
+        // arrange
+        let handler = BulkStartModules::new(runtime().with_start_error(Error::General));
+        let body = serde_json::to_string(&BulkModuleRequest::new(vec!["test-module".to_string()]))
+            .unwrap();
+        let request = Request::post("http://localhost/modules/bulk/start?ordered=true")
+            .body(body.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("General error", outcomes(response)["test-module"]);
+    }
+
+    #[test]
+    fn bad_body() {
+        // arrange
+        let handler = BulkStartModules::new(runtime());
+        let request = Request::post("http://localhost/modules/bulk/start")
+            .body("{}".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Request body is malformed\n\tcaused by: missing field `names` at line 1 column 2",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use serde_derive::Deserialize;
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+struct CheckpointRequest {
+    checkpoint_id: String,
+}
+
+fn checkpoint_id(b: Result<Chunk, hyper::Error>) -> Result<String, Error> {
+    let b = b.context(ErrorKind::MalformedRequestBody)?;
+    let request =
+        serde_json::from_slice::<CheckpointRequest>(&b).context(ErrorKind::MalformedRequestBody)?;
+    if request.checkpoint_id.is_empty() {
+        return Err(Error::from(ErrorKind::MalformedRequestBody));
+    }
+    Ok(request.checkpoint_id)
+}
+
+/// Saves the state of a running module as a checkpoint with the given ID.
+/// The module is stopped once the checkpoint is taken.
+///
+/// Checkpoints need the runtime to support them; Docker only does with CRIU
+/// installed and experimental features enabled, and 501 is returned otherwise.
+pub struct CheckpointModule<M> {
+    runtime: M,
+}
+
+impl<M> CheckpointModule<M> {
+    pub fn new(runtime: M) -> Self {
+        CheckpointModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for CheckpointModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+                let operation = {
+                    let name = name.clone();
+                    move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CheckpointModule(
+                            name.clone(),
+                        ))
+                    }
+                };
+
+                req.into_body()
+                    .concat2()
+                    .then(checkpoint_id)
+                    .and_then({
+                        let operation = operation.clone();
+                        move |checkpoint_id| {
+                            runtime.get(&name).then(move |result| -> Result<_, Error> {
+                                let (_, state) = result.with_context(|_| operation())?;
+                                if *state.status() != ModuleStatus::Running {
+                                    return Err(Error::from(ErrorKind::ModuleNotRunning(name)));
+                                }
+                                Ok((name, checkpoint_id, runtime))
+                            })
+                        }
+                    })
+                    .and_then(move |(name, checkpoint_id, runtime)| {
+                        runtime.checkpoint(&name, &checkpoint_id).then(
+                            move |result| -> Result<_, Error> {
+                                result.with_context(|_| operation())?;
+                                let response = Response::builder()
+                                    .status(StatusCode::NO_CONTENT)
+                                    .body(Body::default())
+                                    .with_context(|_| operation())?;
+                                Ok(response)
+                            },
+                        )
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Starts a stopped module from the checkpoint with the given ID, which has
+/// to be one taken of that module.
+pub struct RestoreModule<M> {
+    runtime: M,
+}
+
+impl<M> RestoreModule<M> {
+    pub fn new(runtime: M) -> Self {
+        RestoreModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for RestoreModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+                let operation = {
+                    let name = name.clone();
+                    move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::RestoreModule(name.clone()))
+                    }
+                };
+
+                req.into_body()
+                    .concat2()
+                    .then(checkpoint_id)
+                    .and_then({
+                        let operation = operation.clone();
+                        move |checkpoint_id| {
+                            runtime.list_checkpoints(&name).then(
+                                move |result| -> Result<_, Error> {
+                                    let checkpoints = result.with_context(|_| operation())?;
+                                    if !checkpoints.contains(&checkpoint_id) {
+                                        return Err(Error::from(ErrorKind::CheckpointNotFound(
+                                            name,
+                                            checkpoint_id,
+                                        )));
+                                    }
+                                    Ok((name, checkpoint_id, runtime))
+                                },
+                            )
+                        }
+                    })
+                    .and_then(move |(name, checkpoint_id, runtime)| {
+                        runtime.restore(&name, &checkpoint_id).then(
+                            move |result| -> Result<_, Error> {
+                                result.with_context(|_| operation())?;
+                                let response = Response::builder()
+                                    .status(StatusCode::NO_CONTENT)
+                                    .body(Body::default())
+                                    .with_context(|_| operation())?;
+                                Ok(response)
+                            },
+                        )
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle<H>(handler: H, path: &str, body: &'static str) -> Response<Body>
+    where
+        H: Handler<Parameters>,
+    {
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::post(format!("http://localhost/modules/mod1/{}", path))
+            .body(body.into())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body_error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn checkpoint_success() {
+        // arrange
+        let handler = CheckpointModule::new(runtime(ModuleStatus::Running));
+
+        // act
+        let response = handle(handler, "checkpoint", r#"{"checkpoint_id":"checkpoint1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn checkpoint_of_stopped_module_conflicts() {
+        // arrange
+        let handler = CheckpointModule::new(runtime(ModuleStatus::Stopped));
+
+        // act
+        let response = handle(handler, "checkpoint", r#"{"checkpoint_id":"checkpoint1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        assert_eq!(Some("ModuleNotRunning"), body_error(response).code());
+    }
+
+    #[test]
+    fn checkpoint_runtime_error() {
+        // arrange
+        let handler = CheckpointModule::new(TestRuntime::new(Err(Error::General)));
+
+        // act
+        let response = handle(handler, "checkpoint", r#"{"checkpoint_id":"checkpoint1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not checkpoint module mod1\n\tcaused by: General error",
+            body_error(response).message()
+        );
+    }
+
+    #[test]
+    fn empty_checkpoint_id_is_bad_request() {
+        // arrange
+        let handler = CheckpointModule::new(runtime(ModuleStatus::Running));
+
+        // act
+        let response = handle(handler, "checkpoint", r#"{"checkpoint_id":""}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn restore_success() {
+        // arrange
+        let handler = RestoreModule::new(runtime(ModuleStatus::Stopped));
+
+        // act
+        let response = handle(handler, "restore", r#"{"checkpoint_id":"checkpoint1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn restore_unknown_checkpoint_is_not_found() {
+        // arrange
+        let handler = RestoreModule::new(runtime(ModuleStatus::Stopped));
+
+        // act
+        let response = handle(handler, "restore", r#"{"checkpoint_id":"checkpoint2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let error = body_error(response);
+        assert_eq!(Some("CheckpointNotFound"), error.code());
+        assert_eq!(
+            "Module mod1 has no checkpoint \"checkpoint2\"",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn restore_runtime_error() {
+        // arrange
+        let handler = RestoreModule::new(TestRuntime::new(Err(Error::General)));
+
+        // act
+        let response = handle(handler, "restore", r#"{"checkpoint_id":"checkpoint1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not restore module mod1 from checkpoint\n\tcaused by: General error",
+            body_error(response).message()
+        );
+    }
+}
@@ -0,0 +1,422 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::warn;
+use serde::Serialize;
+use serde_json;
+
+use edgelet_core::{
+    Module, ModuleConfigStore, ModuleRuntime, ModuleSpec as CoreModuleSpec, RuntimeOperation,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::{core_to_spec, SecretFilter};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Returns the spec a module was last created from, as sent to
+/// `POST /modules` or `PUT /modules/{name}`, without asking the runtime for
+/// the state of the module. This is the desired state, which can differ from
+/// what is running, for example after the runtime restarted the module.
+///
+/// Specs are looked up in the config store first. A spec the store doesn't
+/// have is read from the runtime and kept in the store for the next request.
+pub struct GetModuleConfig<M, S> {
+    runtime: M,
+    store: S,
+    secrets: SecretFilter,
+}
+
+impl<M, S> GetModuleConfig<M, S> {
+    pub fn new(runtime: M, store: S) -> Self {
+        GetModuleConfig {
+            runtime,
+            store,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M, S> Handler<Parameters> for GetModuleConfig<M, S>
+where
+    M: 'static + ModuleRuntime + Send,
+    <M::Module as Module>::Config: Serialize,
+    S: 'static + ModuleConfigStore<Config = <M::Module as Module>::Config> + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response =
+            params
+                .name("name")
+                .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+                .map(|name| {
+                    let name = name.to_string();
+
+                    let secrets = self.secrets.clone();
+
+                    if let Some(spec) = self.store.get(&name) {
+                        return Either::A(future::result(write_response(&name, &spec, &secrets)));
+                    }
+
+                    let store = self.store.clone();
+                    Either::B(self.runtime.get_config(&name).then(
+                        move |spec| -> Result<_, Error> {
+                            let spec = spec
+                                .with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(
+                                        name.clone(),
+                                    ))
+                                })?
+                                .ok_or_else(|| ErrorKind::ModuleNotFound(name.clone()))?;
+
+                            let response = write_response(&name, &spec, &secrets)?;
+                            if let Err(err) = store.set(&name, spec) {
+                                warn!("Could not keep the config of module {}: {}", name, err);
+                            }
+                            Ok(response)
+                        },
+                    ))
+                })
+                .into_future()
+                .flatten()
+                .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn write_response<T>(
+    name: &str,
+    spec: &CoreModuleSpec<T>,
+    secrets: &SecretFilter,
+) -> Result<Response<Body>, Error>
+where
+    T: Serialize,
+{
+    let context =
+        || ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(name.to_string()));
+
+    let b = core_to_spec(spec)
+        .and_then(|spec| serde_json::to_string(&secrets.redact(&spec)))
+        .with_context(|_| context())?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| context())?;
+    Ok(response)
+}
+
+/// Makes the config store forget the spec of module `name` once the inner
+/// handler has answered, so that `GET /modules/{name}/config` reads it from
+/// the runtime again after the module was changed. Routes without a `name`
+/// make the store forget all specs.
+///
+/// The spec is forgotten whether the request succeeded or not, since a
+/// failed update can still have recreated the module.
+pub struct InvalidateConfig<H, S> {
+    inner: H,
+    store: S,
+}
+
+impl<H, S> InvalidateConfig<H, S> {
+    pub fn new(inner: H, store: S) -> Self {
+        InvalidateConfig { inner, store }
+    }
+}
+
+impl<H, S> Handler<Parameters> for InvalidateConfig<H, S>
+where
+    H: Handler<Parameters>,
+    S: 'static + ModuleConfigStore + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let name = params.name("name").map(ToString::to_string);
+        let store = self.store.clone();
+
+        let response = self.inner.handle(req, params).then(move |response| {
+            let forgotten = match &name {
+                Some(name) => store.delete(name),
+                None => store.clear(),
+            };
+            if let Err(err) = forgotten {
+                warn!("Could not forget module configs: {}", err);
+            }
+            response
+        });
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use edgelet_core::{InMemoryConfigStore, ModuleRuntimeState};
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    type Store = InMemoryConfigStore<TestConfig>;
+
+    fn handle(module: TestModule<Error>, name: &str) -> Response<Body> {
+        handle_with_store(module, name, InMemoryConfigStore::new())
+    }
+
+    fn handle_with_store(module: TestModule<Error>, name: &str, store: Store) -> Response<Body> {
+        let handler = GetModuleConfig::new(TestRuntime::new(Ok(module)), store);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), name.to_string())]);
+        let request = Request::get(format!("http://localhost/modules/{}/config", name))
+            .body(Body::default())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let mut env = HashMap::new();
+        env.insert("k2".to_string(), "v2".to_string());
+        env.insert("k1".to_string(), "v1".to_string());
+        let mut labels = HashMap::new();
+        labels.insert("app".to_string(), "web".to_string());
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        )
+        .with_env(env)
+        .with_labels(labels);
+
+        // act
+        let response = handle(module, "mod1");
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        let b = response.into_body().concat2().wait().unwrap();
+        let spec: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!({
+                "name": "mod1",
+                "type": "test",
+                "config": {
+                    "settings": { "image": "microsoft/test-image" },
+                    "env": [
+                        { "key": "k1", "value": "v1" },
+                        { "key": "k2", "value": "v2" },
+                    ],
+                },
+                "labels": { "app": "web" },
+            }),
+            spec
+        );
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        // arrange
+        let mut env = HashMap::new();
+        env.insert("API_KEY".to_string(), "abc".to_string());
+        env.insert("MODE".to_string(), "test".to_string());
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        )
+        .with_env(env);
+
+        // act
+        let response = handle(module, "mod1");
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let spec: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!([
+                { "key": "API_KEY", "value": "***" },
+                { "key": "MODE", "value": "test" },
+            ]),
+            spec["config"]["env"]
+        );
+    }
+
+    #[test]
+    fn never_created() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+
+        // act
+        let response = handle(module, "mod2");
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!("Module mod2 not found", error.message());
+        assert_eq!(Some("ModuleNotFound"), error.code());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = GetModuleConfig::new(
+            TestRuntime::new(Err(Error::General)),
+            InMemoryConfigStore::new(),
+        );
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/config")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not get config of module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn store_is_used_before_runtime() {
+        // arrange
+        let store = Store::new();
+        let stored = CoreModuleSpec::new(
+            "mod1".to_string(),
+            "test".to_string(),
+            TestConfig::new("microsoft/stored-image".to_string()),
+            HashMap::new(),
+        )
+        .unwrap();
+        store.set("mod1", stored).unwrap();
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+
+        // act
+        let response = handle_with_store(module, "mod1", store);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let spec: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "microsoft/stored-image",
+            spec["config"]["settings"]["image"]
+        );
+    }
+
+    #[test]
+    fn runtime_config_is_kept_in_store() {
+        // arrange
+        let store = Store::new();
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+
+        // act
+        let response = handle_with_store(module, "mod1", store.clone());
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let spec = store.get("mod1").unwrap();
+        assert_eq!("microsoft/test-image", spec.config().image());
+    }
+
+    #[test]
+    fn invalidate_forgets_specs() {
+        // arrange
+        let store = Store::new();
+        for name in &["mod1", "mod2"] {
+            let spec = CoreModuleSpec::new(
+                name.to_string(),
+                "test".to_string(),
+                TestConfig::new("microsoft/test-image".to_string()),
+                HashMap::new(),
+            )
+            .unwrap();
+            store.set(name, spec).unwrap();
+        }
+        let handler = InvalidateConfig::new(
+            |_req, _params| -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+                Box::new(future::ok(
+                    Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::default())
+                        .unwrap(),
+                ))
+            },
+            store.clone(),
+        );
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::put("http://localhost/modules/mod1")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert!(store.get("mod1").is_none());
+        assert!(store.get("mod2").is_some());
+
+        let request = Request::delete("http://localhost/modules/all")
+            .body(Body::default())
+            .unwrap();
+        handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap();
+        assert!(store.get("mod2").is_none());
+    }
+}
@@ -0,0 +1,365 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+
+use edgelet_core::{ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The largest file that can be copied into a module, 10 MiB.
+pub const MAX_COPY_FILE_BYTES: usize = 10 * 1024 * 1024;
+
+/// How much larger than `MAX_COPY_FILE_BYTES` the body of a request to copy a
+/// file may be, for the rest of the multipart body.
+pub const COPY_FILE_BODY_OVERHEAD_BYTES: usize = 64 * 1024;
+
+/// Writes a file into a running module, for example a config file or a
+/// certificate, without rebuilding its image.
+///
+/// The body is `multipart/form-data` with a `path` field, the absolute path
+/// of the file in the module, and a `file` field with its content, of up to
+/// `MAX_COPY_FILE_BYTES`. Paths with `..` components are rejected with `400
+/// Bad Request`. The directory of the file has to exist in the module, and
+/// the file is replaced if there is one.
+pub struct CopyFileToModule<M> {
+    runtime: M,
+}
+
+impl<M> CopyFileToModule<M> {
+    pub fn new(runtime: M) -> Self {
+        CopyFileToModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for CopyFileToModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                let boundary = req
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(multipart_boundary)
+                    .ok_or_else(|| {
+                        Error::from(ErrorKind::MalformedRequestHeader("content-type"))
+                    })?;
+                Ok((name.to_string(), boundary))
+            })
+            .map(|(name, boundary)| {
+                let operation = {
+                    let name = name.clone();
+                    move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CopyFileToModule(
+                            name.clone(),
+                        ))
+                    }
+                };
+
+                req.into_body()
+                    .concat2()
+                    .then(move |b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        copy_request(&b, &boundary)
+                    })
+                    .and_then({
+                        let operation = operation.clone();
+                        move |(path, content)| {
+                            runtime.get(&name).then(move |result| -> Result<_, Error> {
+                                let (_, state) = result.with_context(|_| operation())?;
+                                if *state.status() != ModuleStatus::Running {
+                                    return Err(Error::from(ErrorKind::ModuleNotRunning(name)));
+                                }
+                                Ok((name, path, content, runtime))
+                            })
+                        }
+                    })
+                    .and_then(move |(name, path, content, runtime)| {
+                        info!(
+                            "Copying {} bytes to {} in module {}",
+                            content.len(),
+                            path,
+                            name
+                        );
+                        runtime.copy_to_container(&name, &path, content).then(
+                            move |result| -> Result<_, Error> {
+                                result.with_context(|_| operation())?;
+                                let response = Response::builder()
+                                    .status(StatusCode::NO_CONTENT)
+                                    .body(Body::default())
+                                    .with_context(|_| operation())?;
+                                Ok(response)
+                            },
+                        )
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// The path and content of the file to copy, from the fields of the
+/// multipart body `b`.
+fn copy_request(b: &[u8], boundary: &str) -> Result<(String, Bytes), Error> {
+    let mut fields = multipart_fields(b, boundary)?;
+    let path = fields
+        .remove("path")
+        .and_then(|path| String::from_utf8(path).ok())
+        .ok_or_else(|| Error::from(ErrorKind::MalformedRequestBody))?;
+    let content = fields
+        .remove("file")
+        .ok_or_else(|| Error::from(ErrorKind::MalformedRequestBody))?;
+
+    if !path.starts_with('/') || path.ends_with('/') || path.split('/').any(|part| part == "..") {
+        return Err(Error::from(ErrorKind::InvalidCopyPath(path)));
+    }
+    if content.len() > MAX_COPY_FILE_BYTES {
+        return Err(Error::from(ErrorKind::FileTooLarge(MAX_COPY_FILE_BYTES)));
+    }
+    Ok((path, Bytes::from(content)))
+}
+
+/// The boundary of a `multipart/form-data` content type, if it is one.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| {
+            let mut parts = param.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case("boundary") => {
+                    Some(value.trim().trim_matches('"').to_string())
+                }
+                _ => None,
+            }
+        })
+        .find(|boundary| !boundary.is_empty())
+}
+
+/// The content of every part of the multipart body `b`, by the name in its
+/// `Content-Disposition` header. Parts without a name are left out.
+fn multipart_fields(b: &[u8], boundary: &str) -> Result<HashMap<String, Vec<u8>>, Error> {
+    let malformed = || Error::from(ErrorKind::MalformedRequestBody);
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let separator = [b"\r\n".as_ref(), &delimiter].concat();
+
+    let mut fields = HashMap::new();
+    let mut rest = &b[find(b, &delimiter).ok_or_else(malformed)? + delimiter.len()..];
+    loop {
+        // The last delimiter is followed by `--`, every other one by a part.
+        if rest.starts_with(b"--") {
+            return Ok(fields);
+        }
+        if !rest.starts_with(b"\r\n") {
+            return Err(malformed());
+        }
+        rest = &rest[2..];
+
+        let headers_end = find(rest, b"\r\n\r\n").ok_or_else(malformed)?;
+        let headers = std::str::from_utf8(&rest[..headers_end]).map_err(|_| malformed())?;
+        rest = &rest[headers_end + 4..];
+        let content_end = find(rest, &separator).ok_or_else(malformed)?;
+        if let Some(name) = headers.split("\r\n").find_map(field_name) {
+            fields.insert(name, rest[..content_end].to_vec());
+        }
+        rest = &rest[content_end + separator.len()..];
+    }
+}
+
+/// The name of a part, if `header` is a `Content-Disposition` header with one.
+fn field_name(header: &str) -> Option<String> {
+    let mut parts = header.splitn(2, ':');
+    let (key, value) = (parts.next()?, parts.next()?);
+    if !key.trim().eq_ignore_ascii_case("content-disposition") {
+        return None;
+    }
+    value.split(';').map(str::trim).find_map(|param| {
+        if param.starts_with("name=") {
+            Some(param["name=".len()..].trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    const BOUNDARY: &str = "X-BOUNDARY";
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn body(path: &str, content: &[u8]) -> Vec<u8> {
+        [
+            format!(
+                "--{0}\r\nContent-Disposition: form-data; name=\"path\"\r\n\r\n{1}\r\n\
+                 --{0}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"f\"\r\n\
+                 Content-Type: application/octet-stream\r\n\r\n",
+                BOUNDARY, path
+            )
+            .as_bytes(),
+            content,
+            format!("\r\n--{}--\r\n", BOUNDARY).as_bytes(),
+        ]
+        .concat()
+    }
+
+    fn handle(runtime: &TestRuntime<Error>, body: Vec<u8>) -> Response<Body> {
+        let handler = CopyFileToModule::new(runtime.clone());
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::post("http://localhost/modules/mod1/copy-file")
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(body.into())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn file_is_copied() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+        let content = b"line 1\r\n--not a boundary\r\n\x00\xff";
+
+        // act
+        let response = handle(&runtime, body("/etc/app/config.json", content));
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(
+            vec![(
+                "mod1".to_string(),
+                "/etc/app/config.json".to_string(),
+                Bytes::from(&content[..])
+            )],
+            runtime.copies()
+        );
+    }
+
+    #[test]
+    fn parent_components_are_bad_request() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        for path in &["/etc/../../root/.ssh/keys", "etc/app.conf", "/etc/app/"] {
+            // act
+            let response = handle(&runtime, body(path, b"content"));
+
+            // assert
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+            assert_eq!(Some("InvalidCopyPath"), error(response).code());
+        }
+        assert!(runtime.copies().is_empty());
+    }
+
+    #[test]
+    fn large_file_is_payload_too_large() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+        let content = vec![0; MAX_COPY_FILE_BYTES + 1];
+
+        // act
+        let response = handle(&runtime, body("/etc/app.conf", &content));
+
+        // assert
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, response.status());
+        assert!(runtime.copies().is_empty());
+    }
+
+    #[test]
+    fn stopped_module_is_a_conflict() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Stopped);
+
+        // act
+        let response = handle(&runtime, body("/etc/app.conf", b"content"));
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        assert_eq!(Some("ModuleNotRunning"), error(response).code());
+        assert!(runtime.copies().is_empty());
+    }
+
+    #[test]
+    fn body_without_file_is_bad_request() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+        let body = format!(
+            "--{0}\r\nContent-Disposition: form-data; name=\"path\"\r\n\r\n/etc/app.conf\r\n--{0}--\r\n",
+            BOUNDARY
+        );
+
+        // act
+        let response = handle(&runtime, body.into_bytes());
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("MalformedRequestBody"), error(response).code());
+    }
+
+    #[test]
+    fn boundary_is_read_from_the_content_type() {
+        assert_eq!(
+            Some("abc".to_string()),
+            multipart_boundary("multipart/form-data; boundary=abc")
+        );
+        assert_eq!(
+            Some("a b".to_string()),
+            multipart_boundary("Multipart/Form-Data; charset=utf-8; boundary=\"a b\"")
+        );
+        assert_eq!(None, multipart_boundary("application/json"));
+        assert_eq!(None, multipart_boundary("multipart/form-data"));
+    }
+}
@@ -0,0 +1,423 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use serde_json;
+
+use edgelet_core::{LogOptions, LogTail, ModuleRuntime, RuntimeOperation};
+use edgelet_docker::DockerFrames;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The directories logs may be copied to.
+const ALLOWED_LOG_DIRS: &[&str] = &["/var/log/", "/tmp/"];
+
+#[cfg(unix)]
+const LOG_FILE_MODE: u32 = 0o640;
+
+#[derive(Deserialize)]
+struct CopyLogsRequest {
+    path: String,
+    tail: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct CopyLogsResponse {
+    bytes_written: u64,
+    path: String,
+}
+
+fn copy_request(b: Result<Chunk, hyper::Error>) -> Result<CopyLogsRequest, Error> {
+    let b = b.context(ErrorKind::MalformedRequestBody)?;
+    let request =
+        serde_json::from_slice::<CopyLogsRequest>(&b).context(ErrorKind::MalformedRequestBody)?;
+    Ok(request)
+}
+
+/// Writes the logs of a module to a file on the host, replacing the file if
+/// it exists, and responds with the number of bytes written.
+///
+/// The file has to be under one of `ALLOWED_LOG_DIRS`, and paths with `..`
+/// components are rejected with `400 Bad Request`. Missing parent
+/// directories are created, and the directory the file ends up in is checked
+/// again once symlinks are resolved. The logs are copied without the
+/// headers Docker multiplexes stdout and stderr with, and the file is only
+/// readable by its owner and group.
+pub struct CopyModuleLogs<M> {
+    runtime: M,
+    allowed_dirs: Vec<PathBuf>,
+}
+
+impl<M> CopyModuleLogs<M> {
+    pub fn new(runtime: M) -> Self {
+        CopyModuleLogs {
+            runtime,
+            allowed_dirs: ALLOWED_LOG_DIRS.iter().map(PathBuf::from).collect(),
+        }
+    }
+}
+
+impl<M> Handler<Parameters> for CopyModuleLogs<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::Logs: Into<Body>,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let allowed_dirs = self.allowed_dirs.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(copy_request)
+                    .and_then({
+                        let allowed_dirs = allowed_dirs.clone();
+                        move |request| -> Result<_, Error> {
+                            check_path(&request.path, &allowed_dirs)?;
+                            Ok((name, request))
+                        }
+                    })
+                    .and_then(move |(name, request)| {
+                        let tail = request.tail.map_or(LogTail::All, LogTail::Num);
+                        let options = LogOptions::new().with_tail(tail);
+                        runtime
+                            .logs(&name, &options)
+                            .then(move |logs| -> Result<_, Error> {
+                                let logs = logs.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                Ok((name, request, logs))
+                            })
+                    })
+                    .and_then(move |(name, request, logs)| -> Result<_, Error> {
+                        let operation = {
+                            let name = name.clone();
+                            move || ErrorKind::CopyModuleLogs(name.clone())
+                        };
+                        let file = create_file(&name, Path::new(&request.path), &allowed_dirs)?;
+                        info!("Copying logs of module {} to {}", name, request.path);
+
+                        let body: Body = logs.into();
+                        let copy = DockerFrames::new(body)
+                            .map_err({
+                                let operation = operation.clone();
+                                move |err| Error::from(err.context(operation()))
+                            })
+                            .fold((file, 0_u64), move |(mut file, written), (_, payload)| {
+                                file.write_all(&payload).with_context(|_| operation())?;
+                                Ok::<_, Error>((file, written + payload.len() as u64))
+                            })
+                            .and_then(move |(_, bytes_written)| -> Result<_, Error> {
+                                let body = CopyLogsResponse {
+                                    bytes_written,
+                                    path: request.path,
+                                };
+                                let b = serde_json::to_string(&body)
+                                    .with_context(|_| ErrorKind::CopyModuleLogs(name.clone()))?;
+                                let response = Response::builder()
+                                    .status(StatusCode::OK)
+                                    .header(CONTENT_TYPE, "application/json")
+                                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                                    .body(b.into())
+                                    .context(ErrorKind::CopyModuleLogs(name))?;
+                                Ok(response)
+                            });
+                        Ok(copy)
+                    })
+                    .flatten()
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Checks the path as given, before anything is created for it.
+fn check_path(path: &str, allowed_dirs: &[PathBuf]) -> Result<(), Error> {
+    let invalid = || Error::from(ErrorKind::InvalidLogPath(path.to_string()));
+    let path = Path::new(path);
+    if !path.is_absolute() || path.file_name().is_none() {
+        return Err(invalid());
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(invalid());
+    }
+    if !allowed_dirs.iter().any(|dir| path.starts_with(dir)) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Creates the parent directories of `path` and then the file itself. The
+/// directories are only created under an existing directory that is known to
+/// be allowed with symlinks resolved, and the directory the file goes into is
+/// checked again once they are.
+fn create_file(name: &str, path: &Path, allowed_dirs: &[PathBuf]) -> Result<File, Error> {
+    let invalid = || Error::from(ErrorKind::InvalidLogPath(path.display().to_string()));
+    let operation = || ErrorKind::CopyModuleLogs(name.to_string());
+    let (parent, file_name) = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => (parent, file_name),
+        _ => return Err(invalid()),
+    };
+
+    let existing = parent
+        .ancestors()
+        .find(|dir| dir.exists())
+        .ok_or_else(invalid)?;
+    if !is_allowed(existing, allowed_dirs).with_context(|_| operation())? {
+        return Err(invalid());
+    }
+    fs::create_dir_all(parent).with_context(|_| operation())?;
+    if !is_allowed(parent, allowed_dirs).with_context(|_| operation())? {
+        return Err(invalid());
+    }
+
+    // Only a regular file is replaced. Symlinks in particular aren't
+    // followed, so the file can't be redirected out of the allowed
+    // directories.
+    let path = parent
+        .canonicalize()
+        .with_context(|_| operation())?
+        .join(file_name);
+    match fs::symlink_metadata(&path) {
+        Ok(ref metadata) if !metadata.file_type().is_file() => return Err(invalid()),
+        _ => (),
+    }
+    let file = open(&path).with_context(|_| operation())?;
+    if !file.metadata().with_context(|_| operation())?.is_file() {
+        return Err(invalid());
+    }
+    Ok(file)
+}
+
+/// Whether `dir` is under one of `allowed_dirs` once symlinks are resolved.
+fn is_allowed(dir: &Path, allowed_dirs: &[PathBuf]) -> std::io::Result<bool> {
+    let dir = dir.canonicalize()?;
+    Ok(allowed_dirs.iter().any(|allowed| {
+        allowed
+            .canonicalize()
+            .map(|allowed| dir.starts_with(allowed))
+            .unwrap_or(false)
+    }))
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(LOG_FILE_MODE)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    // The mode only applies to new files.
+    file.set_permissions(fs::Permissions::from_mode(LOG_FILE_MODE))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn open(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn handler(allowed_dir: &Path) -> CopyModuleLogs<TestRuntime<Error>> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let mut handler = CopyModuleLogs::new(TestRuntime::new(Ok(module)));
+        handler.allowed_dirs = vec![allowed_dir.to_path_buf()];
+        handler
+    }
+
+    fn handle(handler: &CopyModuleLogs<TestRuntime<Error>>, body: &Value) -> Response<Body> {
+        let request = Request::post("http://localhost/modules/mod1/copy-logs")
+            .body(serde_json::to_string(body).unwrap().into())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path());
+        let path = dir.path().join("edge").join("mod1.log");
+        let path = path.to_str().unwrap();
+
+        // act
+        let response = handle(&handler, &json!({ "path": path, "tail": 1000 }));
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let body: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(json!({ "bytes_written": 0, "path": path }), body);
+        assert!(Path::new(path).is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_is_not_world_readable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path());
+        let path = dir.path().join("mod1.log");
+        fs::write(&path, "old logs").unwrap();
+
+        // act
+        let response = handle(&handler, &json!({ "path": path.to_str().unwrap() }));
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(0o640, metadata.permissions().mode() & 0o777);
+        assert_eq!(0, metadata.len());
+    }
+
+    #[test]
+    fn path_traversal_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path());
+        let inside = dir.path().to_str().unwrap();
+
+        for path in &[
+            format!("{}/../escaped.log", inside),
+            format!("{}/edge/../../escaped.log", inside),
+            format!("{}-other/mod1.log", inside),
+            "/etc/passwd".to_string(),
+            "mod1.log".to_string(),
+            inside.to_string() + "/",
+        ] {
+            // act
+            let response = handle(&handler, &json!({ "path": path }));
+
+            // assert
+            assert_eq!(StatusCode::BAD_REQUEST, response.status(), "{}", path);
+            let b = response.into_body().concat2().wait().unwrap();
+            let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+            assert_eq!(Some("InvalidLogPath"), error.code());
+        }
+        assert!(!dir.path().parent().unwrap().join("escaped.log").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_out_of_allowed_dir_is_rejected() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+        let handler = handler(dir.path());
+        let path = dir.path().join("link").join("mod1.log");
+
+        // act
+        let response = handle(&handler, &json!({ "path": path.to_str().unwrap() }));
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert!(!outside.path().join("mod1.log").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_file_is_rejected() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().join("passwd");
+        fs::write(&target, "root:x:0:0").unwrap();
+        std::os::unix::fs::symlink(&target, dir.path().join("mod1.log")).unwrap();
+        let handler = handler(dir.path());
+        let path = dir.path().join("mod1.log");
+
+        // act
+        let response = handle(&handler, &json!({ "path": path.to_str().unwrap() }));
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!("root:x:0:0", fs::read_to_string(&target).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_are_not_created_out_of_allowed_dir() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+        let handler = handler(dir.path());
+        let path = dir.path().join("link").join("edge").join("mod1.log");
+
+        // act
+        let response = handle(&handler, &json!({ "path": path.to_str().unwrap() }));
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert!(!outside.path().join("edge").exists());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let mut handler = CopyModuleLogs::new(TestRuntime::new(Err(Error::General)));
+        handler.allowed_dirs = vec![dir.path().to_path_buf()];
+        let path = dir.path().join("mod1.log");
+
+        // act
+        let response = handle(&handler, &json!({ "path": path.to_str().unwrap() }));
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert!(!path.exists());
+    }
+}
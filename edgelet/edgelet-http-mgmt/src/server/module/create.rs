@@ -1,29 +1,82 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::time::{Duration, Instant};
+
 use failure::{Fail, ResultExt};
-use futures::{Future, Stream};
-use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use futures::{future, Future, Stream};
 use hyper::{Body, Request, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
 
 use edgelet_core::{Module, ModuleRegistry, ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::authorization::AuthorizedCaller;
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
 
-use super::{spec_to_core, spec_to_details};
+use super::idempotency::{idempotency_key, json_response, IdempotencyCache};
+use super::names::ModuleNameValidator;
+use super::registry::RegistryValidator;
+use super::template::{dry_run_response, parse_dry_run, resolve_templates};
+use super::validation::validate_module_spec;
+use super::{runtime_name, spec_to_core, spec_to_details, SecretFilter};
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
+/// Creates a module from the spec in the body.
+///
+/// A request can carry an `Idempotency-Key` header with a UUID. The response
+/// to a successful request with a key is stored, and a retry with the same
+/// key gets that response back, with an `X-Idempotent-Replayed: true`
+/// header, instead of creating the module again. Stored responses expire
+/// after the idempotency TTL, five minutes by default.
+///
+/// `${NAME}` placeholders in the spec are replaced with environment
+/// variables of iotedged first. With `?dry_run=true` the resolved spec is
+/// validated and returned instead of creating the module.
+///
+/// Images from registries that the registry validator doesn't allow get a
+/// `403` response, and names that the name validator refuses, like reserved
+/// ones, get a `400` response.
 pub struct CreateModule<M> {
     runtime: M,
+    idempotency: IdempotencyCache,
+    secrets: SecretFilter,
+    registries: RegistryValidator,
+    names: ModuleNameValidator,
 }
 
 impl<M> CreateModule<M> {
     pub fn new(runtime: M) -> Self {
-        CreateModule { runtime }
+        CreateModule {
+            runtime,
+            idempotency: IdempotencyCache::default(),
+            secrets: SecretFilter::default(),
+            registries: RegistryValidator::default(),
+            names: ModuleNameValidator::default(),
+        }
+    }
+
+    pub fn with_idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency = IdempotencyCache::new(ttl);
+        self
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_registry_validator(mut self, registries: RegistryValidator) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    pub fn with_name_validator(mut self, names: ModuleNameValidator) -> Self {
+        self.names = names;
+        self
     }
 }
 
@@ -35,21 +88,56 @@ where
     fn handle(
         &self,
         req: Request<Body>,
-        _params: Parameters,
+        params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let key = match idempotency_key(&req) {
+            Ok(key) => key,
+            Err(e) => {
+                return Box::new(future::ok(
+                    e.with_correlation_id(correlation_id).into_response(),
+                ))
+            }
+        };
+        if let Some(response) = key
+            .as_ref()
+            .and_then(|key| self.idempotency.get(key, Instant::now()))
+        {
+            return Box::new(future::ok(response));
+        }
+
         let runtime = self.runtime.clone();
-        let response =
-            req.into_body()
-                .concat2()
-                .then(|b| {
-                    let b = b.context(ErrorKind::MalformedRequestBody)?;
-                    let spec = serde_json::from_slice::<ModuleSpec>(&b)
-                        .context(ErrorKind::MalformedRequestBody)?;
-                    let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
-                    Ok((spec, core_spec))
-                })
-                .and_then(move |(spec, core_spec)| {
-                    let module_name = spec.name().to_string();
+        let idempotency = self.idempotency.clone();
+        let secrets = self.secrets.clone();
+        let registries = self.registries.clone();
+        let names = self.names.clone();
+        let caller = req.extensions().get::<AuthorizedCaller>().cloned();
+        let dry_run = parse_dry_run(req.uri().query());
+        let response = req
+            .into_body()
+            .concat2()
+            .then(move |b| {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let b = resolve_templates(&b)?;
+                let spec = validate_module_spec(&b)?;
+                names.validate_new(spec.name(), caller.as_ref())?;
+                validate_network_aliases(&spec)?;
+                registries.validate(&spec)?;
+                let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?
+                    .with_name(runtime_name(&params, spec.name()));
+                Ok((spec, core_spec))
+            })
+            .and_then(move |(spec, core_spec)| {
+                let module_name = spec.name().to_string();
+                if dry_run {
+                    return future::Either::A(future::result(dry_run_response(
+                        &spec,
+                        &secrets,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(module_name)),
+                    )));
+                }
+                future::Either::B(
                     runtime
                         .registry()
                         .pull(core_spec.config())
@@ -62,45 +150,69 @@ where
                                         ))
                                     })?;
                                     let details = spec_to_details(&spec, ModuleStatus::Stopped);
-                                    let b = serde_json::to_string(&details).with_context(|_| {
-                                        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                            module_name.clone(),
-                                        ))
-                                    })?;
-                                    let response = Response::builder()
-                                        .status(StatusCode::CREATED)
-                                        .header(CONTENT_TYPE, "application/json")
-                                        .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                                        .body(b.into())
-                                        .context(ErrorKind::RuntimeOperation(
-                                            RuntimeOperation::CreateModule(module_name),
-                                        ))?;
-                                    Ok(response)
+                                    let b = serde_json::to_string(&secrets.redact(&details))
+                                        .with_context(|_| {
+                                            ErrorKind::RuntimeOperation(
+                                                RuntimeOperation::CreateModule(module_name.clone()),
+                                            )
+                                        })?;
+                                    if let Some(key) = key {
+                                        idempotency.insert(
+                                            key,
+                                            StatusCode::CREATED,
+                                            b.clone(),
+                                            Instant::now(),
+                                        );
+                                    }
+                                    Ok(json_response(StatusCode::CREATED, b))
                                 },
                             )),
                             Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
                                 RuntimeOperation::CreateModule(module_name),
                             )))),
                         })
-                })
-                .flatten()
-                .or_else(|e| Ok(e.into_response()));
+                        .flatten(),
+                )
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
 }
 
+/// Network aliases are used as host names by the other modules on the
+/// network, so they can't contain spaces or path separators.
+fn validate_network_aliases(spec: &ModuleSpec) -> Result<(), Error> {
+    let aliases = spec
+        .config()
+        .settings()
+        .get("networkAliases")
+        .and_then(serde_json::Value::as_array);
+    let invalid = aliases
+        .into_iter()
+        .flatten()
+        .filter_map(serde_json::Value::as_str)
+        .find(|alias| alias.contains(|c: char| c.is_whitespace() || c == '/'));
+    match invalid {
+        Some(alias) => Err(Error::from(ErrorKind::InvalidNetworkAlias(
+            alias.to_string(),
+        ))),
+        None => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
     use hyper::Request;
     use lazy_static::lazy_static;
-    use serde_json::json;
+    use serde_json::{json, Value};
 
-    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_core::{AllowedRegistries, ModuleRuntimeState, ModuleStatus};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::module::*;
-    use management::models::{Config, ErrorResponse};
+    use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+    use management::models::{Config, ErrorResponse, ResourceLimits};
 
     use super::*;
     use crate::server::module::tests::Error;
@@ -159,6 +271,143 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn dry_run_returns_resolved_spec_without_creating() {
+        std::env::set_var("IOTEDGE_TEST_CREATE_TEMPLATE_IMAGE", "microsoft/test-image");
+        let runtime = TestRuntime::new(Err(Error::General));
+        let handler = CreateModule::new(runtime);
+        let config = Config::new(json!({"image":"${IOTEDGE_TEST_CREATE_TEMPLATE_IMAGE}"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::post("http://localhost/modules?dry_run=true")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let spec: ModuleSpec = serde_json::from_slice(&b).unwrap();
+                assert_eq!("test-module", spec.name());
+                assert_eq!(
+                    "microsoft/test-image",
+                    spec.config().settings().get("image").unwrap()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn registry_auth_is_not_echoed() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({
+            "image": "repo.azurecr.io/test-image",
+            "registryAuth": {
+                "server": "repo.azurecr.io",
+                "username": "username",
+                "password": "password",
+            },
+        }));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::post("http://localhost/modules")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::CREATED, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            &json!({ "image": "repo.azurecr.io/test-image" }),
+            details.config().settings()
+        );
+    }
+
+    #[test]
+    fn registry_not_allowed_is_forbidden() {
+        let registries =
+            RegistryValidator::new(AllowedRegistries::new(
+                vec!["mcr.microsoft.com".to_string()],
+            ));
+        let handler = CreateModule::new(RUNTIME.clone()).with_registry_validator(registries);
+        let config = Config::new(json!({"image":"contoso.azurecr.io/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::post("http://localhost/modules")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!("RegistryNotAllowed", error["code"]);
+        assert_eq!("contoso.azurecr.io", error["registry"]);
+    }
+
+    #[test]
+    fn invalid_name_is_bad_request() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test module".to_string(), "docker".to_string(), config);
+        let request = Request::post("http://localhost/modules")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("InvalidModuleName"), error.code());
+    }
+
+    #[test]
+    fn reserved_name_is_only_allowed_for_the_agent() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("edgeHub".to_string(), "docker".to_string(), config);
+        let body = serde_json::to_string(&spec).unwrap();
+
+        // act
+        let response = handler
+            .handle(
+                Request::post("http://localhost/modules")
+                    .body(body.clone().into())
+                    .unwrap(),
+                Parameters::new(),
+            )
+            .wait()
+            .unwrap();
+        let mut request = Request::post("http://localhost/modules")
+            .body(body.into())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(AuthorizedCaller::new("edgeAgent".to_string()));
+        let agent_response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("ReservedModuleName"), error.code());
+        assert_eq!(StatusCode::CREATED, agent_response.status());
+    }
+
     #[test]
     fn bad_body() {
         let handler = CreateModule::new(RUNTIME.clone());
@@ -245,4 +494,118 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn invalid_resource_limits() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config)
+            .with_resource_limits(
+                ResourceLimits::new()
+                    .with_memory_limit_bytes(1024)
+                    .with_memory_swap_bytes(512),
+            );
+        let request = Request::post("http://localhost/modules")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: Value = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    json!({
+                        "code": "ValidationError",
+                        "message": "Module spec is invalid",
+                        "fields": [{
+                            "field": "resource_limits.memory_swap_bytes",
+                            "message": "Invalid resource limits: memory_swap_bytes 512 must be at least memory_limit_bytes 1024 or -1",
+                        }],
+                    }),
+                    error
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn invalid_network_alias() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        for alias in &["temp sensor", "sensors/temp"] {
+            let config = Config::new(json!({
+                "image": "microsoft/test-image",
+                "networkAliases": ["temperature", alias],
+            }));
+            let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+            let request = Request::post("http://localhost/modules")
+                .body(serde_json::to_string(&spec).unwrap().into())
+                .unwrap();
+
+            // act
+            let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+            // assert
+            assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+            let b = response.into_body().concat2().wait().unwrap();
+            let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+            assert_eq!(Some("InvalidNetworkAlias"), error.code());
+            assert_eq!(
+                format!(
+                    "Invalid network alias {:?}, aliases can't contain spaces or `/`",
+                    alias
+                ),
+                *error.message()
+            );
+        }
+    }
+
+    #[test]
+    fn idempotency_key_replays_response() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let body = serde_json::to_string(&spec).unwrap();
+        let request = || {
+            Request::post("http://localhost/modules")
+                .header("Idempotency-Key", "0a4e6f24-3e3c-4bd6-8e4b-2b1c1f0e5a7d")
+                .body(body.clone().into())
+                .unwrap()
+        };
+
+        // act
+        let first = handler.handle(request(), Parameters::new()).wait().unwrap();
+        let second = handler.handle(request(), Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::CREATED, first.status());
+        assert!(first.headers().get("x-idempotent-replayed").is_none());
+        assert_eq!(StatusCode::CREATED, second.status());
+        assert_eq!("true", second.headers()["x-idempotent-replayed"]);
+        let first = first.into_body().concat2().wait().unwrap();
+        let second = second.into_body().concat2().wait().unwrap();
+        assert_eq!(first.to_vec(), second.to_vec());
+    }
+
+    #[test]
+    fn bad_idempotency_key() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let request = Request::post("http://localhost/modules")
+            .header("Idempotency-Key", "not-a-uuid")
+            .body("{}".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
 }
@@ -8,6 +8,8 @@ use edgelet_core::{ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use super::runtime_name;
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -27,14 +29,16 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         let response = params
             .name("name")
             .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
             .map(|name| {
-                let name = name.to_string();
+                let name = runtime_name(&params, name);
 
                 self.runtime.remove(&name).then(|result| match result {
                     Ok(_) => Ok(name),
@@ -53,7 +57,7 @@ where
                         name,
                     )))?)
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::bulk::outcome_for_error;
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Modules that make up the runtime itself and are left alone.
+const SYSTEM_MODULES: &[&str] = &["edgeAgent", "edgeHub"];
+
+/// Stops and removes every module except the system modules.
+///
+/// Each module is stopped and removed independently and concurrently. When
+/// every module was removed the response is `204 No Content`, otherwise it is
+/// `207 Multi-Status` with a body that maps every module name to either
+/// `"ok"`, `"not found"` or the error that occurred. With `force=true` the
+/// modules are removed without being stopped first.
+pub struct DeleteAllModules<M> {
+    runtime: M,
+}
+
+impl<M> DeleteAllModules<M> {
+    pub fn new(runtime: M) -> Self {
+        DeleteAllModules { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for DeleteAllModules<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = req
+            .uri()
+            .query()
+            .map_or(Ok(false), parse_force)
+            .into_future()
+            .and_then(move |force| {
+                runtime
+                    .list()
+                    .then(|modules| -> Result<_, Error> {
+                        Ok(modules.context(ErrorKind::RemoveModules)?)
+                    })
+                    .and_then(move |modules| {
+                        let operations = modules
+                            .into_iter()
+                            .map(|module| module.name().to_string())
+                            .filter(|name| !SYSTEM_MODULES.contains(&name.as_str()))
+                            .map(move |name| {
+                                let result = if force {
+                                    Either::A(remove(&runtime, name.clone()))
+                                } else {
+                                    let runtime_copy = runtime.clone();
+                                    let stop_name = name.clone();
+                                    let remove_name = name.clone();
+                                    Either::B(
+                                        runtime
+                                            .stop(&name, None)
+                                            .map_err(|err| {
+                                                Error::from(err.context(
+                                                    ErrorKind::RuntimeOperation(
+                                                        RuntimeOperation::StopModule(stop_name),
+                                                    ),
+                                                ))
+                                            })
+                                            .and_then(move |_| remove(&runtime_copy, remove_name)),
+                                    )
+                                };
+                                result.then(move |result| Ok::<_, Error>((name, result)))
+                            });
+
+                        future::join_all(operations)
+                    })
+            })
+            .and_then(|outcomes| response_for(outcomes.into_iter().collect()))
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn remove<M>(runtime: &M, name: String) -> impl Future<Item = (), Error = Error>
+where
+    M: ModuleRuntime,
+{
+    runtime.remove(&name).map_err(|err| {
+        Error::from(
+            err.context(ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(
+                name,
+            ))),
+        )
+    })
+}
+
+fn parse_force(query: &str) -> Result<bool, Error> {
+    match form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "force") {
+        Some((_, val)) => Ok(val
+            .parse::<bool>()
+            .context(ErrorKind::MalformedRequestParameter("force"))?),
+        None => Ok(false),
+    }
+}
+
+fn response_for(outcomes: BTreeMap<String, Result<(), Error>>) -> Result<Response<Body>, Error> {
+    if outcomes.values().all(Result::is_ok) {
+        let response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::default())
+            .context(ErrorKind::RemoveModules)?;
+        return Ok(response);
+    }
+
+    let outcomes: BTreeMap<String, String> = outcomes
+        .into_iter()
+        .map(|(name, result)| {
+            let outcome = match result {
+                Ok(()) => "ok".to_string(),
+                Err(err) => outcome_for_error(&err),
+            };
+            (name, outcome)
+        })
+        .collect();
+
+    let b = serde_json::to_string(&outcomes).context(ErrorKind::RemoveModules)?;
+    let response = Response::builder()
+        .status(StatusCode::MULTI_STATUS)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::RemoveModules)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(name: &str) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(name.to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let handler = DeleteAllModules::new(runtime("mod1"));
+        let request = Request::delete("http://localhost/modules/all")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn force_success() {
+        // arrange
+        let handler = DeleteAllModules::new(runtime("mod1"));
+        let request = Request::delete("http://localhost/modules/all?force=true")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn bad_force() {
+        // arrange
+        let handler = DeleteAllModules::new(runtime("mod1"));
+        let request = Request::delete("http://localhost/modules/all?force=yes")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = DeleteAllModules::new(TestRuntime::new(Err(Error::General)));
+        let request = Request::delete("http://localhost/modules/all")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not remove modules\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn partial_failure_is_multi_status() {
+        // arrange
+        let mut outcomes = BTreeMap::new();
+        outcomes.insert("mod-a".to_string(), Ok(()));
+        outcomes.insert(
+            "mod-b".to_string(),
+            Err(crate::error::Error::from(Error::General.context(
+                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule("mod-b".to_string())),
+            ))),
+        );
+
+        // act
+        let response = response_for(outcomes).unwrap();
+
+        // assert
+        assert_eq!(StatusCode::MULTI_STATUS, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let outcomes: BTreeMap<String, String> = serde_json::from_slice(&b).unwrap();
+                assert_eq!("ok", outcomes["mod-a"]);
+                assert_eq!(
+                    "Could not stop module mod-b\n\tcaused by: General error",
+                    outcomes["mod-b"]
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{self, json, Value};
+
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::SecretFilter;
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Compares the spec a module was last created from with the module the
+/// runtime is running, and returns the differences as a JSON Patch
+/// (RFC 6902). Applying the patch to `{"image": ..., "env": {...}}` of the
+/// spec gives the same of the running module.
+///
+/// Only the image and environment are compared, since that is all the runtime
+/// reports of a running module. Variables that are only in the running
+/// environment were added by the image or the runtime, so they aren't
+/// reported. Values of secret variables are redacted.
+pub struct GetModuleDiff<M> {
+    runtime: M,
+    secrets: SecretFilter,
+}
+
+impl<M> GetModuleDiff<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleDiff {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleDiff<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+                let operation = {
+                    let name = name.clone();
+                    move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleDiff(name.clone()))
+                    }
+                };
+
+                let secrets = self.secrets.clone();
+                let runtime = self.runtime.clone();
+
+                self.runtime
+                    .get_config(&name)
+                    .then({
+                        let name = name.clone();
+                        let operation = operation.clone();
+                        move |spec| -> Result<_, Error> {
+                            let spec = spec
+                                .with_context(|_| operation())?
+                                .ok_or_else(|| ErrorKind::ModuleNotFound(name))?;
+                            Ok(spec)
+                        }
+                    })
+                    .and_then(move |spec| {
+                        runtime.list().join(runtime.env(&name)).then(
+                            move |running| -> Result<_, Error> {
+                                let (modules, env) = running.with_context(|_| operation())?;
+                                let module = modules
+                                    .into_iter()
+                                    .find(|module| module.name() == name)
+                                    .ok_or_else(|| ErrorKind::ModuleNotFound(name.clone()))?;
+
+                                let patch = diff(
+                                    image(spec.config()).as_ref().map(AsRef::as_ref),
+                                    image(module.config()).as_ref().map(AsRef::as_ref),
+                                    spec.env(),
+                                    &env,
+                                    &secrets,
+                                );
+
+                                let b =
+                                    serde_json::to_string(&patch).with_context(|_| operation())?;
+                                let response = Response::builder()
+                                    .status(StatusCode::OK)
+                                    .header(CONTENT_TYPE, "application/json-patch+json")
+                                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                                    .body(b.into())
+                                    .with_context(|_| operation())?;
+                                Ok(response)
+                            },
+                        )
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// The `image` of a module config, for the runtimes whose configs have one.
+fn image<T: Serialize>(config: &T) -> Option<String> {
+    serde_json::to_value(config)
+        .ok()?
+        .get("image")?
+        .as_str()
+        .map(ToOwned::to_owned)
+}
+
+/// The JSON Patch from the desired image and environment to the running ones.
+fn diff(
+    desired_image: Option<&str>,
+    running_image: Option<&str>,
+    desired_env: &HashMap<String, String>,
+    running_env: &HashMap<String, String>,
+    secrets: &SecretFilter,
+) -> Vec<Value> {
+    let mut patch = vec![];
+
+    if desired_image != running_image {
+        patch.push(match running_image {
+            Some(image) => json!({ "op": "replace", "path": "/image", "value": image }),
+            None => json!({ "op": "remove", "path": "/image" }),
+        });
+    }
+
+    let redacted = secrets.redact_env(running_env.clone());
+    let mut desired_env: Vec<_> = desired_env.iter().collect();
+    desired_env.sort();
+    for (key, desired) in desired_env {
+        let path = format!("/env/{}", escape(key));
+        match running_env.get(key) {
+            Some(running) if running != desired => {
+                patch.push(json!({ "op": "replace", "path": path, "value": redacted[key] }));
+            }
+            Some(_) => (),
+            None => patch.push(json!({ "op": "remove", "path": path })),
+        }
+    }
+
+    patch
+}
+
+/// The JSON Pointers of the values that differ between `from` and `to`,
+/// down to the members of objects, in key order. A missing member is the
+/// same as a `null` one.
+pub(crate) fn changed_paths(from: &Value, to: &Value) -> Vec<String> {
+    fn walk(path: &str, from: &Value, to: &Value, paths: &mut Vec<String>) {
+        match (from, to) {
+            (Value::Object(from), Value::Object(to)) => {
+                let mut keys: Vec<_> = from.keys().chain(to.keys()).collect();
+                keys.sort();
+                keys.dedup();
+                for key in keys {
+                    walk(
+                        &format!("{}/{}", path, escape(key)),
+                        from.get(key).unwrap_or(&Value::Null),
+                        to.get(key).unwrap_or(&Value::Null),
+                        paths,
+                    );
+                }
+            }
+            (from, to) if from != to => paths.push(path.to_string()),
+            _ => (),
+        }
+    }
+
+    let mut paths = vec![];
+    walk("", from, to, &mut paths);
+    paths
+}
+
+/// Escapes a key for use as a JSON Pointer (RFC 6901) reference token.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn handle(runtime: TestRuntime<Error>, name: &str) -> Response<Body> {
+        let handler = GetModuleDiff::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), name.to_string())]);
+        let request = Request::get(format!("http://localhost/modules/{}/diff", name))
+            .body(Body::default())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn runtime() -> TestRuntime<Error> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        )
+        .with_env(env(&[("KEY", "value")]));
+        TestRuntime::new(Ok(module))
+    }
+
+    #[test]
+    fn matching_module_has_empty_patch() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, "mod1");
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let patch: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(json!([]), patch);
+    }
+
+    #[test]
+    fn unknown_module_is_not_found() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, "mod2");
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, "mod1");
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not get diff of module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn diff_reports_changed_image_and_env() {
+        let desired = env(&[("CHANGED", "1"), ("MISSING", "2"), ("SAME", "3")]);
+        let running = env(&[("ADDED", "0"), ("CHANGED", "10"), ("SAME", "3")]);
+
+        let patch = diff(
+            Some("image:1.0"),
+            Some("image:0.9"),
+            &desired,
+            &running,
+            &SecretFilter::default(),
+        );
+
+        assert_eq!(
+            vec![
+                json!({ "op": "replace", "path": "/image", "value": "image:0.9" }),
+                json!({ "op": "replace", "path": "/env/CHANGED", "value": "10" }),
+                json!({ "op": "remove", "path": "/env/MISSING" }),
+            ],
+            patch
+        );
+    }
+
+    #[test]
+    fn diff_redacts_secret_values() {
+        let desired = env(&[("DB_PASSWORD", "old")]);
+        let running = env(&[("DB_PASSWORD", "new")]);
+
+        let patch = diff(
+            Some("image"),
+            Some("image"),
+            &desired,
+            &running,
+            &SecretFilter::new(vec!["*PASSWORD*".to_string()]),
+        );
+
+        assert_eq!(
+            vec![json!({ "op": "replace", "path": "/env/DB_PASSWORD", "value": "***" })],
+            patch
+        );
+    }
+
+    #[test]
+    fn changed_paths_reach_into_objects() {
+        let from = json!({ "a": { "b": 1, "c": [1] }, "d": "x", "e/f": null });
+        let to = json!({ "a": { "b": 2, "c": [1] }, "e/f": true, "g": {} });
+
+        assert_eq!(vec!["/a/b", "/d", "/e~1f", "/g"], changed_paths(&from, &to));
+    }
+
+    #[test]
+    fn env_keys_are_escaped() {
+        assert_eq!("a~1b~0c", escape("a/b~c"));
+    }
+}
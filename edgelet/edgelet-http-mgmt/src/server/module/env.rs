@@ -0,0 +1,393 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleEnvPatch;
+
+use super::SecretFilter;
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetModuleEnv<M> {
+    runtime: M,
+    secrets: SecretFilter,
+}
+
+impl<M> GetModuleEnv<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleEnv {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleEnv<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                let secrets = self.secrets.clone();
+
+                self.runtime
+                    .env(&name)
+                    .then(move |env| -> Result<_, Error> {
+                        let env = env.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleEnv(
+                                name.clone(),
+                            ))
+                        })?;
+                        env_response(&secrets, env, RuntimeOperation::GetModuleEnv(name))
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Applies a `set`/`unset` diff to the environment of a module.
+///
+/// The module is recreated with the resulting environment, so the patch only
+/// takes effect once the runtime has finished updating it. Naming the same
+/// variable in both `set` and `unset` is rejected before the runtime is
+/// touched.
+pub struct PatchModuleEnv<M> {
+    runtime: M,
+    secrets: SecretFilter,
+}
+
+impl<M> PatchModuleEnv<M> {
+    pub fn new(runtime: M) -> Self {
+        PatchModuleEnv {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for PatchModuleEnv<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(|b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let patch = serde_json::from_slice::<ModuleEnvPatch>(&b)
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        check_conflicts(&patch)?;
+                        Ok(patch)
+                    })
+                    .and_then(move |patch| {
+                        runtime
+                            .env(&name)
+                            .then(move |env| -> Result<_, Error> {
+                                let env = env.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleEnv(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                Ok((name, apply_patch(env, &patch), runtime))
+                            })
+                            .and_then(|(name, env, runtime)| {
+                                runtime.update_env(&name, env.clone()).then(
+                                    move |result| -> Result<_, Error> {
+                                        result.with_context(|_| {
+                                            ErrorKind::RuntimeOperation(
+                                                RuntimeOperation::UpdateModuleEnv(name.clone()),
+                                            )
+                                        })?;
+                                        env_response(
+                                            &secrets,
+                                            env,
+                                            RuntimeOperation::UpdateModuleEnv(name),
+                                        )
+                                    },
+                                )
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn check_conflicts(patch: &ModuleEnvPatch) -> Result<(), Error> {
+    if let (Some(set), Some(unset)) = (patch.set(), patch.unset()) {
+        let mut conflicts: Vec<&String> = unset.iter().filter(|k| set.contains_key(*k)).collect();
+        conflicts.sort();
+        if let Some(key) = conflicts.first() {
+            return Err(Error::from(ErrorKind::ConflictingEnvVar((*key).clone())));
+        }
+    }
+    Ok(())
+}
+
+fn apply_patch(
+    mut env: HashMap<String, String>,
+    patch: &ModuleEnvPatch,
+) -> HashMap<String, String> {
+    for key in patch.unset().unwrap_or(&[]) {
+        env.remove(key);
+    }
+    if let Some(set) = patch.set() {
+        env.extend(set.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    env
+}
+
+fn env_response(
+    secrets: &SecretFilter,
+    env: HashMap<String, String>,
+    operation: RuntimeOperation,
+) -> Result<Response<Body>, Error> {
+    // Sort the keys so that the response is stable across calls
+    let env = secrets.redact_env(env);
+    let b = serde_json::to_string(&env)
+        .with_context(|_| ErrorKind::RuntimeOperation(operation.clone()))?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::RuntimeOperation(operation))?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let mut env = HashMap::new();
+        env.insert("KEEP".to_string(), "1".to_string());
+        env.insert("DROP".to_string(), "2".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("mod1".to_string(), config, Ok(state)).with_env(env);
+        TestRuntime::new(Ok(module))
+    }
+
+    fn parameters() -> Parameters {
+        Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())])
+    }
+
+    fn body_env(response: Response<Body>) -> BTreeMap<String, String> {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    fn body_error(response: Response<Body>) -> String {
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        error.message().to_string()
+    }
+
+    #[test]
+    fn get_success() {
+        // arrange
+        let handler = GetModuleEnv::new(runtime());
+        let request = Request::get("http://localhost/modules/mod1/env")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        let env = body_env(response);
+        assert_eq!(2, env.len());
+        assert_eq!("1", env["KEEP"]);
+        assert_eq!("2", env["DROP"]);
+    }
+
+    #[test]
+    fn get_redacts_secrets() {
+        // arrange
+        let handler = GetModuleEnv::new(runtime())
+            .with_secret_filter(SecretFilter::new(vec!["DR?P".to_string()]));
+        let request = Request::get("http://localhost/modules/mod1/env")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let env = body_env(response);
+        assert_eq!("1", env["KEEP"]);
+        assert_eq!("***", env["DROP"]);
+    }
+
+    #[test]
+    fn get_runtime_error() {
+        // arrange
+        let handler = GetModuleEnv::new(TestRuntime::new(Err(Error::General)));
+        let request = Request::get("http://localhost/modules/mod1/env")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not get environment of module mod1\n\tcaused by: General error",
+            body_error(response)
+        );
+    }
+
+    #[test]
+    fn get_bad_params() {
+        // arrange
+        let handler = GetModuleEnv::new(runtime());
+        let request = Request::get("http://localhost/modules/mod1/env")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn patch_success() {
+        // arrange
+        let handler = PatchModuleEnv::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1/env")
+            .body(r#"{"set":{"KEEP":"3","NEW":"4"},"unset":["DROP","MISSING"]}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let env = body_env(response);
+        assert_eq!(2, env.len());
+        assert_eq!("3", env["KEEP"]);
+        assert_eq!("4", env["NEW"]);
+    }
+
+    #[test]
+    fn patch_conflict() {
+        // arrange
+        let handler = PatchModuleEnv::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1/env")
+            .body(r#"{"set":{"B":"1","A":"2"},"unset":["B","A"]}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            "Environment variable \"A\" is both set and unset",
+            body_error(response)
+        );
+    }
+
+    #[test]
+    fn patch_bad_body() {
+        // arrange
+        let handler = PatchModuleEnv::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1/env")
+            .body(r#"{"set":["A"]}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn patch_runtime_error() {
+        // arrange
+        let handler = PatchModuleEnv::new(TestRuntime::new(Err(Error::General)));
+        let request = Request::patch("http://localhost/modules/mod1/env")
+            .body(r#"{"set":{"A":"1"}}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not get environment of module mod1\n\tcaused by: General error",
+            body_error(response)
+        );
+    }
+}
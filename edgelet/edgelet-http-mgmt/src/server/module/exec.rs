@@ -0,0 +1,191 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ExecRequest;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Runs a command in a running module and streams its standard output as the
+/// response body, which ends when the command exits.
+pub struct ExecModule<M> {
+    runtime: M,
+}
+
+impl<M> ExecModule<M> {
+    pub fn new(runtime: M) -> Self {
+        ExecModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ExecModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::Logs: Into<Body>,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(|b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let request = serde_json::from_slice::<ExecRequest>(&b)
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        if request.cmd().is_empty() {
+                            return Err(Error::from(ErrorKind::MalformedRequestBody));
+                        }
+                        Ok(request)
+                    })
+                    .and_then(move |request| {
+                        runtime
+                            .get(&name)
+                            .then(move |result| -> Result<_, Error> {
+                                let (_, state) = result.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                if *state.status() != ModuleStatus::Running {
+                                    return Err(Error::from(ErrorKind::ModuleNotRunning(name)));
+                                }
+                                Ok((name, request, runtime))
+                            })
+                            .and_then(|(name, request, runtime)| {
+                                let tty = request.tty().cloned().unwrap_or(false);
+                                runtime.exec(&name, request.cmd(), tty).then(
+                                    move |output| -> Result<_, Error> {
+                                        let output = output.with_context(|_| {
+                                            ErrorKind::RuntimeOperation(
+                                                RuntimeOperation::ExecModule(name.clone()),
+                                            )
+                                        })?;
+                                        let response = Response::builder()
+                                            .status(StatusCode::OK)
+                                            .header(CONTENT_TYPE, "application/octet-stream")
+                                            .body(output.into())
+                                            .context(ErrorKind::RuntimeOperation(
+                                                RuntimeOperation::ExecModule(name),
+                                            ))?;
+                                        Ok(response)
+                                    },
+                                )
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(runtime: TestRuntime<Error>, body: &str) -> Response<Body> {
+        let handler = ExecModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::post("http://localhost/modules/mod1/exec")
+            .body(body.to_string().into())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"cmd":["cat","/etc/hostname"],"tty":false}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/octet-stream", response.headers()[CONTENT_TYPE]);
+    }
+
+    #[test]
+    fn not_running() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Stopped);
+
+        // act
+        let response = handle(runtime, r#"{"cmd":["cat","/etc/hostname"]}"#);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let error = error(response);
+        assert_eq!("Module mod1 is not running", error.message());
+        assert_eq!(Some("ModuleNotRunning"), error.code());
+    }
+
+    #[test]
+    fn empty_cmd() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"cmd":[]}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("MalformedRequestBody"), error(response).code());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, r#"{"cmd":["ls"]}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not run command in module mod1\n\tcaused by: General error",
+            error(response).message()
+        );
+    }
+}
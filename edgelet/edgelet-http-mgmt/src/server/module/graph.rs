@@ -0,0 +1,286 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_derive::Serialize;
+use serde_json;
+
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Returns the modules and the `depends_on` edges between them, as stored
+/// with their specs, along with an order the modules can be started in.
+pub struct GetModuleGraph<M> {
+    runtime: M,
+}
+
+impl<M> GetModuleGraph<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleGraph { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleGraph<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let runtime = self.runtime.clone();
+
+        let response = self
+            .runtime
+            .list()
+            .then(|modules| {
+                modules
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))
+                    .map_err(Error::from)
+            })
+            .and_then(move |modules| {
+                let dependencies: Vec<_> = modules
+                    .iter()
+                    .map(|module| {
+                        let name = module.name().to_string();
+                        runtime
+                            .get_config(&name)
+                            .then(move |spec| -> Result<_, Error> {
+                                let spec = spec.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                let depends_on =
+                                    spec.map_or_else(Vec::new, |spec| spec.depends_on().to_vec());
+                                Ok((name, depends_on))
+                            })
+                    })
+                    .collect();
+                future::join_all(dependencies)
+            })
+            .and_then(|dependencies| -> Result<_, Error> {
+                let graph = ModuleGraph::new(dependencies)?;
+                let b = serde_json::to_string(&graph)
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct ModuleGraph {
+    nodes: Vec<String>,
+    edges: Vec<Edge>,
+    start_order: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Edge {
+    from: String,
+    to: String,
+    #[serde(rename = "type")]
+    type_: &'static str,
+}
+
+impl ModuleGraph {
+    /// Builds the graph of the modules, given as their names and the names
+    /// they depend on. Dependencies on modules that don't exist are edges,
+    /// but don't hold up the start of the module that has them.
+    fn new(mut modules: Vec<(String, Vec<String>)>) -> Result<Self, Error> {
+        modules.sort();
+
+        let nodes: Vec<String> = modules.iter().map(|(name, _)| name.clone()).collect();
+
+        let edges = modules
+            .iter()
+            .flat_map(|(name, depends_on)| {
+                depends_on.iter().map(move |dependency| Edge {
+                    from: name.clone(),
+                    to: dependency.clone(),
+                    type_: "depends_on",
+                })
+            })
+            .collect();
+
+        // Modules are started as soon as everything they depend on has
+        // started, in the order of their names when several can start.
+        let mut pending: BTreeMap<&str, BTreeSet<&str>> = modules
+            .iter()
+            .map(|(name, depends_on)| {
+                let depends_on = depends_on
+                    .iter()
+                    .filter(|dependency| nodes.contains(dependency))
+                    .map(String::as_str)
+                    .collect();
+                (name.as_str(), depends_on)
+            })
+            .collect();
+        let mut start_order = Vec::with_capacity(nodes.len());
+        while let Some(next) = pending
+            .iter()
+            .find(|(_, depends_on)| depends_on.is_empty())
+            .map(|(name, _)| *name)
+        {
+            pending.remove(next);
+            for depends_on in pending.values_mut() {
+                depends_on.remove(next);
+            }
+            start_order.push(next.to_string());
+        }
+
+        if !pending.is_empty() {
+            return Err(Error::from(ErrorKind::ModuleDependencyCycle(
+                pending.keys().map(ToString::to_string).collect(),
+            )));
+        }
+
+        Ok(ModuleGraph {
+            nodes,
+            edges,
+            start_order,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn handle(runtime: TestRuntime<Error>) -> Response<Body> {
+        let handler = GetModuleGraph::new(runtime);
+        let request = Request::get("http://localhost/modules/graph")
+            .body(Body::default())
+            .unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    fn modules(modules: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+        modules
+            .iter()
+            .map(|(name, depends_on)| {
+                (
+                    name.to_string(),
+                    depends_on.iter().map(ToString::to_string).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::new(Ok(module));
+
+        // act
+        let response = handle(runtime);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let graph: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!({ "nodes": ["mod1"], "edges": [], "start_order": ["mod1"] }),
+            graph
+        );
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not list modules\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn dependencies_start_first() {
+        let graph = ModuleGraph::new(modules(&[
+            ("web", &["db", "cache"]),
+            ("db", &[]),
+            ("cache", &["db", "metrics"]),
+        ]))
+        .unwrap();
+
+        assert_eq!(vec!["cache", "db", "web"], graph.nodes);
+        assert_eq!(vec!["db", "cache", "web"], graph.start_order);
+        assert_eq!(
+            vec![
+                ("cache", "db"),
+                ("cache", "metrics"),
+                ("web", "db"),
+                ("web", "cache")
+            ],
+            graph
+                .edges
+                .iter()
+                .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn cycles_are_unprocessable() {
+        let err = ModuleGraph::new(modules(&[
+            ("a", &["b"]),
+            ("b", &["c"]),
+            ("c", &["a"]),
+            ("d", &[]),
+        ]))
+        .unwrap_err();
+
+        let response = err.into_response();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("ModuleDependencyCycle"), error.code());
+        assert_eq!(
+            "Modules [\"a\", \"b\", \"c\"] can't be started because their dependencies form a cycle",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{self, Value};
+use sha2::{Digest, Sha256};
+
+use edgelet_core::RuntimeOperation;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleSpec;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// How many updates of each module the history remembers.
+pub const MODULE_HISTORY_SIZE: usize = 10;
+
+/// An update of a module through `PUT /modules/{name}`, its image or a
+/// rollback.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ModuleHistoryEntry {
+    timestamp: DateTime<Utc>,
+    image: String,
+    /// Base64 of the SHA-256 of the spec the module was updated with, to
+    /// tell updates that only changed other settings than the image apart.
+    spec_hash: String,
+    /// The spec itself, for rolling back to it. It holds the values of
+    /// secrets, so it is left out of `GET /modules/{name}/ancestry`.
+    spec: Value,
+}
+
+/// What `GET /modules/{name}/ancestry` shows of an entry.
+#[derive(Serialize)]
+struct AncestryEntry<'a> {
+    timestamp: &'a DateTime<Utc>,
+    image: &'a str,
+    spec_hash: &'a str,
+}
+
+impl ModuleHistoryEntry {
+    pub fn new(timestamp: DateTime<Utc>, spec: &ModuleSpec) -> Self {
+        let image = spec
+            .config()
+            .settings()
+            .get("image")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        // Going through a `Value` sorts the keys of maps like the labels, so
+        // that the same spec always hashes the same.
+        let spec = serde_json::to_value(spec).expect("serialization of ModuleSpec failed.");
+        ModuleHistoryEntry {
+            timestamp,
+            image,
+            spec_hash: base64::encode(&Sha256::digest_str(&spec.to_string())),
+            spec,
+        }
+    }
+
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    pub fn spec_hash(&self) -> &str {
+        &self.spec_hash
+    }
+
+    pub fn spec(&self) -> &Value {
+        &self.spec
+    }
+
+    fn to_ancestry(&self) -> AncestryEntry<'_> {
+        AncestryEntry {
+            timestamp: &self.timestamp,
+            image: &self.image,
+            spec_hash: &self.spec_hash,
+        }
+    }
+}
+
+/// The last `MODULE_HISTORY_SIZE` updates of every module, so operators can
+/// tell what a module ran before its last update.
+///
+/// With a path, the history is read from that file when it is created and
+/// written back to it after every update, so it survives restarts of the
+/// daemon. Clones share the same history.
+#[derive(Clone, Default)]
+pub struct ModuleHistory {
+    path: Option<PathBuf>,
+    entries: Arc<Mutex<HashMap<String, VecDeque<ModuleHistoryEntry>>>>,
+}
+
+impl ModuleHistory {
+    /// A history that is only kept in memory.
+    pub fn new() -> Self {
+        ModuleHistory::default()
+    }
+
+    /// A missing or unreadable file starts an empty history.
+    pub fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let entries = match read(&path) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                warn!(
+                    "Could not read module history from {}, starting a new one: {}",
+                    path.display(),
+                    err
+                );
+                HashMap::new()
+            }
+        };
+        ModuleHistory {
+            path: Some(path),
+            entries: Arc::new(Mutex::new(entries)),
+        }
+    }
+
+    pub fn record(&self, name: &str, entry: ModuleHistoryEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        let history = entries.entry(name.to_string()).or_default();
+        while history.len() >= MODULE_HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(entry);
+        self.save(&entries);
+    }
+
+    /// Moves the updates of module `old_name` to `new_name`, replacing any
+    /// that a module of that name left behind.
+    pub fn rename(&self, old_name: &str, new_name: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(new_name);
+        if let Some(history) = entries.remove(old_name) {
+            entries.insert(new_name.to_string(), history);
+        }
+        self.save(&entries);
+    }
+
+    fn save(&self, entries: &HashMap<String, VecDeque<ModuleHistoryEntry>>) {
+        if let Some(path) = &self.path {
+            if let Err(err) = write(path, entries) {
+                warn!(
+                    "Could not save module history to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// The updates of module `name`, newest first.
+    pub fn ancestry(&self, name: &str) -> Vec<ModuleHistoryEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(name)
+            .map_or_else(Vec::new, |history| history.iter().rev().cloned().collect())
+    }
+
+    /// The update of module `name` before the latest one, which is what a
+    /// rollback goes back to.
+    pub fn previous(&self, name: &str) -> Option<ModuleHistoryEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(name)
+            .and_then(|history| history.iter().rev().nth(1).cloned())
+    }
+}
+
+fn read(path: &Path) -> io::Result<HashMap<String, VecDeque<ModuleHistoryEntry>>> {
+    let b = fs::read(path)?;
+    let entries = serde_json::from_slice(&b)?;
+    Ok(entries)
+}
+
+/// Writes to a temporary file first so that a crash can't leave a partly
+/// written history behind.
+fn write(path: &Path, entries: &HashMap<String, VecDeque<ModuleHistoryEntry>>) -> io::Result<()> {
+    let b = serde_json::to_vec(entries)?;
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, b)?;
+    fs::rename(temp_path, path)
+}
+
+/// Returns the recorded updates of a module as a JSON array, newest first.
+/// A module that was never updated has an empty history.
+pub struct GetModuleAncestry {
+    history: ModuleHistory,
+}
+
+impl GetModuleAncestry {
+    pub fn new(history: ModuleHistory) -> Self {
+        GetModuleAncestry { history }
+    }
+}
+
+impl Handler<Parameters> for GetModuleAncestry {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                let context =
+                    || ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.to_string()));
+                let ancestry = self.history.ancestry(name);
+                let ancestry: Vec<_> = ancestry
+                    .iter()
+                    .map(ModuleHistoryEntry::to_ancestry)
+                    .collect();
+                let b = serde_json::to_string(&ancestry).with_context(|_| context())?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .with_context(|_| context())?;
+                Ok(response)
+            })
+            .or_else(|e| -> Result<_, HttpError> {
+                Ok(e.with_correlation_id(correlation_id).into_response())
+            });
+
+        Box::new(future::result(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use futures::Stream;
+    use management::models::Config;
+    use serde_json::json;
+
+    use super::*;
+
+    fn spec(image: &str) -> ModuleSpec {
+        ModuleSpec::new(
+            "mod1".to_string(),
+            "docker".to_string(),
+            Config::new(json!({ "image": image })),
+        )
+    }
+
+    fn entry(minute: u32, image: &str) -> ModuleHistoryEntry {
+        ModuleHistoryEntry::new(Utc.ymd(2019, 1, 30).and_hms(12, minute, 0), &spec(image))
+    }
+
+    #[test]
+    fn entries_hash_the_spec() {
+        let first = entry(0, "img:1");
+        let second = entry(1, "img:1");
+        let third = entry(2, "img:2");
+
+        assert_eq!("img:1", first.image());
+        assert_eq!(first.spec_hash(), second.spec_hash());
+        assert_ne!(first.spec_hash(), third.spec_hash());
+    }
+
+    #[test]
+    fn only_the_most_recent_updates_are_kept() {
+        let history = ModuleHistory::new();
+        for i in 0..15 {
+            history.record("mod1", entry(i, &format!("img:{}", i)));
+        }
+        history.record("mod2", entry(0, "other:1"));
+
+        let ancestry = history.ancestry("mod1");
+        assert_eq!(MODULE_HISTORY_SIZE, ancestry.len());
+        assert_eq!("img:14", ancestry[0].image());
+        assert_eq!("img:5", ancestry[9].image());
+        assert!(history.ancestry("mod3").is_empty());
+    }
+
+    #[test]
+    fn previous_is_the_update_before_the_latest() {
+        let history = ModuleHistory::new();
+        history.record("mod1", entry(0, "img:1"));
+        assert_eq!(None, history.previous("mod1"));
+
+        history.record("mod1", entry(1, "img:2"));
+        history.record("mod1", entry(2, "img:3"));
+
+        let previous = history.previous("mod1").unwrap();
+        assert_eq!("img:2", previous.image());
+        assert_eq!("img:2", previous.spec()["config"]["settings"]["image"]);
+        assert_eq!(None, history.previous("mod2"));
+    }
+
+    #[test]
+    fn renamed_module_keeps_its_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("module_history.json");
+        let history = ModuleHistory::load(&path);
+        history.record("mod1", entry(0, "img:1"));
+        history.record("mod1", entry(1, "img:2"));
+        history.record("mod2", entry(2, "other:1"));
+
+        history.rename("mod1", "mod2");
+
+        assert!(history.ancestry("mod1").is_empty());
+        let ancestry = history.ancestry("mod2");
+        assert_eq!(2, ancestry.len());
+        assert_eq!("img:2", ancestry[0].image());
+        assert_eq!(ancestry, ModuleHistory::load(&path).ancestry("mod2"));
+    }
+
+    #[test]
+    fn history_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("module_history.json");
+
+        let history = ModuleHistory::load(&path);
+        history.record("mod1", entry(0, "img:1"));
+        history.record("mod1", entry(1, "img:2"));
+
+        let reloaded = ModuleHistory::load(&path);
+        assert_eq!(history.ancestry("mod1"), reloaded.ancestry("mod1"));
+    }
+
+    #[test]
+    fn unreadable_file_starts_empty_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("module_history.json");
+        fs::write(&path, "not json").unwrap();
+
+        let history = ModuleHistory::load(&path);
+
+        assert!(history.ancestry("mod1").is_empty());
+    }
+
+    #[test]
+    fn get_ancestry() {
+        // arrange
+        let history = ModuleHistory::new();
+        history.record("mod1", entry(0, "img:1"));
+        history.record("mod1", entry(1, "img:2"));
+        let handler = GetModuleAncestry::new(history);
+        let request = Request::get("http://localhost/modules/mod1/ancestry")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let ancestry: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!("img:2", ancestry[0]["image"]);
+        assert_eq!("2019-01-30T12:01:00Z", ancestry[0]["timestamp"]);
+        assert_eq!("img:1", ancestry[1]["image"]);
+        assert_eq!(None, ancestry[0].get("spec"));
+    }
+}
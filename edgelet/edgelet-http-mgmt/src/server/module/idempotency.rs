@@ -0,0 +1,187 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use uuid::Uuid;
+
+use crate::error::{Error, ErrorKind};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "x-idempotent-replayed";
+
+/// How long a response is replayed for when no other TTL is configured.
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Remembers the responses to requests that carried an `Idempotency-Key`,
+/// so that a client retrying such a request gets the original response
+/// instead of the request being executed again.
+///
+/// Entries are kept in memory only, so they don't survive a restart of the
+/// daemon, and are dropped once they are older than the TTL. Clones share
+/// the same entries.
+#[derive(Clone)]
+pub struct IdempotencyCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, CachedResponse>>>,
+}
+
+struct CachedResponse {
+    status: StatusCode,
+    body: String,
+    stored: Instant,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration) -> Self {
+        IdempotencyCache {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The response stored for `key`, marked as replayed.
+    pub fn get(&self, key: &str, now: Instant) -> Option<Response<Body>> {
+        let mut entries = self.entries.lock().unwrap();
+        self.expire(&mut entries, now);
+
+        entries.get(key).map(|cached| {
+            let mut response = json_response(cached.status, cached.body.clone());
+            response
+                .headers_mut()
+                .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+            response
+        })
+    }
+
+    /// Stores a JSON response for `key`.
+    pub fn insert(&self, key: String, status: StatusCode, body: String, now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        self.expire(&mut entries, now);
+
+        entries.insert(
+            key,
+            CachedResponse {
+                status,
+                body,
+                stored: now,
+            },
+        );
+    }
+
+    fn expire(&self, entries: &mut HashMap<String, CachedResponse>, now: Instant) {
+        let ttl = self.ttl;
+        entries.retain(|_, cached| now.duration_since(cached.stored) < ttl);
+    }
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        IdempotencyCache::new(DEFAULT_IDEMPOTENCY_TTL)
+    }
+}
+
+/// The `Idempotency-Key` of the request, if any. The key has to be a UUID.
+pub fn idempotency_key(req: &Request<Body>) -> Result<Option<String>, Error> {
+    match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => {
+            let key = value
+                .to_str()
+                .ok()
+                .and_then(|value| Uuid::parse_str(value.trim()).ok())
+                .ok_or_else(|| {
+                    Error::from(ErrorKind::MalformedRequestHeader(IDEMPOTENCY_KEY_HEADER))
+                })?;
+            Ok(Some(key.to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, body.len().to_string().as_str())
+        .body(body.into())
+        .expect("response builder failure")
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+
+    use super::*;
+
+    const KEY: &str = "0a4e6f24-3e3c-4bd6-8e4b-2b1c1f0e5a7d";
+
+    #[test]
+    fn replays_stored_response() {
+        let cache = IdempotencyCache::default();
+        let now = Instant::now();
+        assert!(cache.get(KEY, now).is_none());
+
+        cache.insert(
+            KEY.to_string(),
+            StatusCode::CREATED,
+            r#"{"name":"mod1"}"#.to_string(),
+            now,
+        );
+        let response = cache.get(KEY, now + Duration::from_secs(1)).unwrap();
+
+        assert_eq!(StatusCode::CREATED, response.status());
+        assert_eq!("true", response.headers()[REPLAYED_HEADER]);
+        assert_eq!("15", response.headers()[CONTENT_LENGTH]);
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_eq!(r#"{"name":"mod1"}"#, std::str::from_utf8(&b).unwrap());
+    }
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let now = Instant::now();
+        cache.insert(KEY.to_string(), StatusCode::CREATED, "{}".to_string(), now);
+
+        assert!(cache.get(KEY, now + Duration::from_secs(59)).is_some());
+        assert!(cache.get(KEY, now + Duration::from_secs(60)).is_none());
+    }
+
+    #[test]
+    fn clones_share_entries() {
+        let cache = IdempotencyCache::default();
+        let now = Instant::now();
+        cache
+            .clone()
+            .insert(KEY.to_string(), StatusCode::CREATED, "{}".to_string(), now);
+
+        assert!(cache.get(KEY, now).is_some());
+    }
+
+    #[test]
+    fn key_must_be_a_uuid() {
+        let request = |key: &str| {
+            Request::post("http://localhost/modules")
+                .header(IDEMPOTENCY_KEY_HEADER, key)
+                .body(Body::default())
+                .unwrap()
+        };
+
+        assert_eq!(
+            Some(KEY.to_string()),
+            idempotency_key(&request(&KEY.to_uppercase())).unwrap()
+        );
+        assert!(idempotency_key(&request("not-a-uuid")).is_err());
+        assert_eq!(
+            None,
+            idempotency_key(
+                &Request::post("http://localhost/modules")
+                    .body(Body::default())
+                    .unwrap()
+            )
+            .unwrap()
+        );
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::SecretFilter;
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct InspectModule<M> {
+    runtime: M,
+    secrets: SecretFilter,
+}
+
+impl<M> InspectModule<M> {
+    pub fn new(runtime: M) -> Self {
+        InspectModule {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for InspectModule<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                let secrets = self.secrets.clone();
+
+                self.runtime
+                    .inspect(&name)
+                    .then(move |details| -> Result<_, Error> {
+                        let details = details.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::InspectModule(
+                                name.clone(),
+                            ))
+                        })?;
+
+                        let b = serde_json::to_string(&secrets.redact(&details)).with_context(
+                            |_| {
+                                ErrorKind::RuntimeOperation(RuntimeOperation::InspectModule(
+                                    name.clone(),
+                                ))
+                            },
+                        )?;
+
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .context(ErrorKind::RuntimeOperation(
+                                RuntimeOperation::InspectModule(name),
+                            ))?;
+                        Ok(response)
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    #[test]
+    fn success() {
+        // arrange
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Failed)
+            .with_exit_code(Some(137))
+            .with_started_at(Some(Utc.ymd(2019, 4, 13).and_hms(14, 20, 0)))
+            .with_finished_at(Some(Utc.ymd(2019, 4, 13).and_hms(15, 20, 0)));
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let handler = InspectModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/inspect")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let details: Value = serde_json::from_slice(&b).unwrap();
+                assert_eq!("mod1", details["name"]);
+                assert_eq!("failed", details["status"]);
+                assert_eq!(137, details["exit_code"]);
+                assert_eq!("2019-04-13T14:20:00Z", details["started_at"]);
+                assert_eq!("2019-04-13T15:20:00Z", details["finished_at"]);
+                assert_eq!(Value::Null, details["ip_address"]);
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+        let handler = InspectModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/inspect")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not inspect module mod1\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn bad_params() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+        let handler = InspectModule::new(runtime);
+        let request = Request::get("http://localhost/modules/mod1/inspect")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
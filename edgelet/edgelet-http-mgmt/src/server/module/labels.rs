@@ -0,0 +1,591 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{
+    Module, ModuleEvent, ModuleEventType, ModuleRuntime, ModuleRuntimeState, ModuleStatus,
+    RuntimeOperation,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleSpec;
+
+use super::patch::current_spec;
+use super::update::update;
+use super::{spec_to_core, SecretFilter};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::server::events::LocalEvents;
+use crate::IntoResponse;
+
+/// Returns the value of a label of a module as `text/plain`.
+pub struct GetModuleLabel<M> {
+    runtime: M,
+}
+
+impl<M> GetModuleLabel<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleLabel { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleLabel<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = name_and_key(&params)
+            .map(|(name, key)| {
+                self.runtime
+                    .get(&name)
+                    .then(move |result| -> Result<_, Error> {
+                        let (module, _) = result.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone()))
+                        })?;
+                        let value = module
+                            .labels()
+                            .remove(&key)
+                            .ok_or_else(|| ErrorKind::LabelNotFound(name.clone(), key))?;
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "text/plain")
+                            .header(CONTENT_LENGTH, value.len().to_string().as_str())
+                            .body(value.into())
+                            .with_context(|_| {
+                                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name))
+                            })?;
+                        Ok(response)
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Sets a label of a module to the `text/plain` request body.
+///
+/// Docker can't change the labels of a container, so like `PUT
+/// /modules/{name}` this recreates the module, and responds with its
+/// details. A module that was running is started again.
+pub struct SetModuleLabel<M> {
+    runtime: M,
+    secrets: SecretFilter,
+}
+
+impl<M> SetModuleLabel<M> {
+    pub fn new(runtime: M) -> Self {
+        SetModuleLabel {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for SetModuleLabel<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+
+        let response = name_and_key(&params)
+            .map(|(name, key)| {
+                req.into_body()
+                    .concat2()
+                    .then(|b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let value = String::from_utf8(b.to_vec())
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        Ok(value)
+                    })
+                    .and_then(move |value| {
+                        update_labels(runtime, name, secrets, move |_, labels| {
+                            labels.insert(key, value);
+                            Ok(())
+                        })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Removes a label from a module. Like `SetModuleLabel`, this recreates the
+/// module and responds with its details.
+pub struct DeleteModuleLabel<M> {
+    runtime: M,
+    secrets: SecretFilter,
+}
+
+impl<M> DeleteModuleLabel<M> {
+    pub fn new(runtime: M) -> Self {
+        DeleteModuleLabel {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for DeleteModuleLabel<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+
+        let response = name_and_key(&params)
+            .map(|(name, key)| {
+                update_labels(runtime, name, secrets, move |name, labels| {
+                    labels
+                        .remove(&key)
+                        .map(|_| ())
+                        .ok_or_else(|| Error::from(ErrorKind::LabelNotFound(name.to_string(), key)))
+                })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Removes all labels from a module, except for the keys listed in the
+/// optional `except` query parameter, like `?except=owner,tier`. The module
+/// is recreated once for all of them, which is reported to `GET /events` as
+/// a single `updated` event.
+pub struct DeleteAllModuleLabels<M> {
+    runtime: M,
+    secrets: SecretFilter,
+    events: LocalEvents,
+}
+
+impl<M> DeleteAllModuleLabels<M> {
+    pub fn new(runtime: M) -> Self {
+        DeleteAllModuleLabels {
+            runtime,
+            secrets: SecretFilter::default(),
+            events: LocalEvents::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub(crate) fn with_local_events(mut self, events: LocalEvents) -> Self {
+        self.events = events;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for DeleteAllModuleLabels<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+        let events = self.events.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| -> Result<_, Error> {
+                let except = parse_except(req.uri().query())?;
+                Ok((name.to_string(), except))
+            })
+            .map(|(name, except)| {
+                update_labels(runtime, name.clone(), secrets, move |_, labels| {
+                    labels.retain(|key, _| except.contains(key));
+                    Ok(())
+                })
+                .and_then(move |_| -> Result<_, Error> {
+                    events.publish(&ModuleEvent::new(
+                        ModuleEventType::Updated,
+                        name.clone(),
+                        Utc::now(),
+                    ));
+                    let response = Response::builder()
+                        .status(StatusCode::NO_CONTENT)
+                        .body(Body::default())
+                        .context(ErrorKind::UpdateModule(name))?;
+                    Ok(response)
+                })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// The label keys of the comma-separated `except` query parameter.
+fn parse_except(query: Option<&str>) -> Result<Vec<String>, Error> {
+    let except = query.and_then(|query| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "except")
+            .map(|(_, value)| value.into_owned())
+    });
+    except
+        .iter()
+        .flat_map(|except| except.split(','))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            if is_valid_key(key) {
+                Ok(key.to_string())
+            } else {
+                Err(Error::from(ErrorKind::InvalidLabelKey(key.to_string())))
+            }
+        })
+        .collect()
+}
+
+fn name_and_key(params: &Parameters) -> Result<(String, String), Error> {
+    let name = params
+        .name("name")
+        .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))?;
+    let key = params
+        .name("key")
+        .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("key")))?;
+    if !is_valid_key(key) {
+        return Err(Error::from(ErrorKind::InvalidLabelKey(key.to_string())));
+    }
+    Ok((name.to_string(), key.to_string()))
+}
+
+/// Whether `key` follows Docker's label key syntax, `[a-zA-Z0-9._-]+`.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+/// Applies `change` to the labels of the module's current spec and updates
+/// the module with the result, starting it again if it was running.
+fn update_labels<M, F>(
+    runtime: M,
+    name: String,
+    secrets: SecretFilter,
+    change: F,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+    F: FnOnce(&str, &mut HashMap<String, String>) -> Result<(), Error>,
+{
+    let get_name = name.clone();
+    runtime
+        .get(&name)
+        .join(runtime.env(&name))
+        .then(move |result| -> Result<_, Error> {
+            let ((module, state), env) = result.with_context(|_| {
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(get_name))
+            })?;
+            let mut spec = current_spec(&module, env)
+                .and_then(serde_json::from_value::<ModuleSpec>)
+                .with_context(|_| {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone()))
+                })?;
+
+            let mut labels = spec.labels().cloned().unwrap_or_default();
+            change(&name, &mut labels)?;
+            spec.set_labels(labels);
+
+            let core_spec = spec_to_core::<M>(&spec, ErrorKind::UpdateModule(name))?;
+            Ok((core_spec, spec, is_running(&state), runtime))
+        })
+        .and_then(move |(core_spec, spec, start, runtime)| {
+            update(runtime, core_spec, spec, start, secrets)
+        })
+}
+
+fn is_running(state: &ModuleRuntimeState) -> bool {
+    *state.status() == ModuleStatus::Running
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_test_utils::module::*;
+    use management::models::{ErrorResponse, ModuleDetails};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let mut labels = HashMap::new();
+        labels.insert("owner".to_string(), "team-a".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("mod1".to_string(), config, Ok(state)).with_labels(labels);
+        TestRuntime::new(Ok(module))
+    }
+
+    fn parameters(key: &str) -> Parameters {
+        Parameters::with_captures(vec![
+            (Some("name".to_string()), "mod1".to_string()),
+            (Some("key".to_string()), key.to_string()),
+        ])
+    }
+
+    fn body_details(response: Response<Body>) -> ModuleDetails {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    fn body_error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn key_syntax() {
+        assert!(is_valid_key("com.example.owner_1-a"));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("owner name"));
+        assert!(!is_valid_key("owner=a"));
+    }
+
+    #[test]
+    fn get_success() {
+        // arrange
+        let handler = GetModuleLabel::new(runtime());
+        let request = Request::get("http://localhost/modules/mod1/labels/owner")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("owner")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("text/plain", *response.headers().get(CONTENT_TYPE).unwrap());
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_eq!(b"team-a", &*b);
+    }
+
+    #[test]
+    fn get_missing_label_is_not_found() {
+        // arrange
+        let handler = GetModuleLabel::new(runtime());
+        let request = Request::get("http://localhost/modules/mod1/labels/tier")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("tier")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let error = body_error(response);
+        assert_eq!(Some("LabelNotFound"), error.code());
+        assert_eq!("Module mod1 has no label \"tier\"", error.message());
+    }
+
+    #[test]
+    fn invalid_key_is_bad_request() {
+        // arrange
+        let handler = GetModuleLabel::new(runtime());
+        let request = Request::get("http://localhost/modules/mod1/labels/a%20b")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("a b")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("InvalidLabelKey"), body_error(response).code());
+    }
+
+    #[test]
+    fn set_success() {
+        // arrange
+        let handler = SetModuleLabel::new(runtime());
+        let request = Request::put("http://localhost/modules/mod1/labels/tier")
+            .body("backend".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("tier")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let details = body_details(response);
+        assert_eq!("running", details.status().runtime_status().status());
+    }
+
+    #[test]
+    fn set_invalid_key_is_bad_request() {
+        // arrange
+        let handler = SetModuleLabel::new(runtime());
+        let request = Request::put("http://localhost/modules/mod1/labels/a=b")
+            .body("backend".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("a=b")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn delete_success() {
+        // arrange
+        let handler = DeleteModuleLabel::new(runtime());
+        let request = Request::delete("http://localhost/modules/mod1/labels/owner")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("owner")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn delete_missing_label_is_not_found() {
+        // arrange
+        let handler = DeleteModuleLabel::new(runtime());
+        let request = Request::delete("http://localhost/modules/mod1/labels/tier")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("tier")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(Some("LabelNotFound"), body_error(response).code());
+    }
+
+    #[test]
+    fn delete_all_success() {
+        // arrange
+        let events = LocalEvents::default();
+        let receiver = events.subscribe();
+        let handler = DeleteAllModuleLabels::new(runtime()).with_local_events(events);
+        let request = Request::delete("http://localhost/modules/mod1/labels")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        drop(handler);
+        let events: Vec<_> = receiver.collect().wait().unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(ModuleEventType::Updated, events[0].event_type());
+        assert_eq!("mod1", events[0].module_name());
+    }
+
+    #[test]
+    fn delete_all_invalid_except_is_bad_request() {
+        // arrange
+        let handler = DeleteAllModuleLabels::new(runtime());
+        let request = Request::delete("http://localhost/modules/mod1/labels?except=owner,a%3Db")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("InvalidLabelKey"), body_error(response).code());
+    }
+
+    #[test]
+    fn except_parses() {
+        assert_eq!(
+            vec!["owner".to_string(), "tier".to_string()],
+            parse_except(Some("except=owner,%20tier,")).unwrap()
+        );
+        assert!(parse_except(None).unwrap().is_empty());
+        assert!(parse_except(Some("other=owner")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = SetModuleLabel::new(TestRuntime::new(Err(Error::General)));
+        let request = Request::put("http://localhost/modules/mod1/labels/tier")
+            .body("backend".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters("tier")).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not get module mod1\n\tcaused by: General error",
+            body_error(response).message()
+        );
+    }
+}
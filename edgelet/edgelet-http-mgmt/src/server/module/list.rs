@@ -1,71 +1,329 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use failure::ResultExt;
-use futures::{Future, Stream};
-use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
-use hyper::{Body, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use failure::{Fail, ResultExt};
+use futures::future::Either;
+use futures::{stream, Future, IntoFuture, Stream};
+use hyper::header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
 use log::debug;
 use serde::Serialize;
 use serde_json;
+use tokio::timer::Delay;
+use url::form_urlencoded;
 
+use edgelet_core::tenant::unscoped_name;
 use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
 
+use super::SecretFilter;
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
+/// How long `?watch=true` holds the response open when the request doesn't
+/// say, and the longest it may ask for with `timeout_secs`.
+const DEFAULT_WATCH_TIMEOUT_SECS: u64 = 30;
+const MAX_WATCH_TIMEOUT_SECS: u64 = 120;
+
+/// Lists the modules, or with `?watch=true` streams the list as
+/// newline-delimited JSON: once right away, again after every module event,
+/// and a last time when `timeout_secs` are up, after which the response
+/// ends. This serves clients that can't use the events of `GET /events`,
+/// like those behind proxies that buffer Server-Sent Events.
 pub struct ListModules<M> {
     runtime: M,
+    secrets: SecretFilter,
 }
 
 impl<M> ListModules<M> {
     pub fn new(runtime: M) -> Self {
-        ListModules { runtime }
+        ListModules {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
     }
 }
 
 impl<M> Handler<Parameters> for ListModules<M>
 where
-    M: 'static + ModuleRuntime + Send,
+    M: 'static + ModuleRuntime + Clone + Send,
     <M::Module as Module>::Config: Serialize,
 {
     fn handle(
         &self,
-        _req: Request<Body>,
-        _params: Parameters,
+        req: Request<Body>,
+        params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         debug!("List modules");
 
-        let response = self
-            .runtime
-            .list_with_details()
-            .collect()
-            .then(|result| -> Result<_, Error> {
-                let details: Result<_, Error> = result
-                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
-                    .into_iter()
-                    .map(|(module, state)| core_to_details(&module, &state))
-                    .collect();
-                let body = ModuleList::new(details?);
-                let b = serde_json::to_string(&body)
-                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
-                let response = Response::builder()
-                    .status(StatusCode::OK)
-                    .header(CONTENT_TYPE, "application/json")
-                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                    .body(b.into())
-                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
-                Ok(response)
+        // Under `/tenants/{tenant}`, only the tenant's modules are listed, by
+        // their names within the tenant.
+        let tenant = params.name("tenant").map(ToString::to_string);
+
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+
+        let query = req.uri().query().unwrap_or("");
+        let response = parse_pagination(query)
+            .and_then(|pagination| {
+                let filters = parse_label_filters(query)?;
+                let list = ListQuery {
+                    tenant,
+                    pagination,
+                    filters,
+                };
+                Ok((list, parse_watch(query)?))
+            })
+            .into_future()
+            .and_then(move |(list, watch)| match watch {
+                Some(timeout) => {
+                    Either::A(watch_response(runtime, list, secrets, timeout).into_future())
+                }
+                None => Either::B(list_modules(&runtime, &list, &secrets).and_then(
+                    |b| -> Result<_, Error> {
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+                        Ok(response)
+                    },
+                )),
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
 }
 
-fn core_to_details<M>(module: &M, state: &ModuleRuntimeState) -> Result<ModuleDetails, Error>
+/// Which modules to list, and which page of them.
+#[derive(Clone, Debug)]
+struct ListQuery {
+    tenant: Option<String>,
+    pagination: Pagination,
+    filters: Vec<LabelFilter>,
+}
+
+/// The JSON of the modules of `list`, with their secrets redacted.
+fn list_modules<M>(
+    runtime: &M,
+    list: &ListQuery,
+    secrets: &SecretFilter,
+) -> impl Future<Item = String, Error = Error>
+where
+    M: 'static + ModuleRuntime,
+    <M::Module as Module>::Config: Serialize,
+{
+    let list = list.clone();
+    let secrets = secrets.clone();
+    runtime
+        .list_with_details()
+        .collect()
+        .then(move |result| -> Result<_, Error> {
+            let modules: Vec<_> = result
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
+                .into_iter()
+                .filter_map(|(module, state)| {
+                    let name = match list.tenant {
+                        Some(ref tenant) => unscoped_name(tenant, module.name())?,
+                        None => module.name(),
+                    }
+                    .to_string();
+                    let labels = module.labels();
+                    if list.filters.iter().all(|filter| filter.matches(&labels)) {
+                        Some((name, module, state))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            let total_count = modules.len();
+            let pagination = list.pagination;
+            let details: Result<_, Error> = modules
+                .into_iter()
+                .skip(pagination.offset)
+                .take(pagination.limit.unwrap_or(usize::max_value()))
+                .map(|(name, module, state)| core_to_details(name, &module, &state))
+                .collect();
+            let body = pagination.to_list(details?, total_count);
+            let b = serde_json::to_string(&secrets.redact(&body))
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+            Ok(b)
+        })
+}
+
+/// What sends the list of modules again while watching it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WatchTrigger {
+    Changed,
+    Timeout,
+    End,
+}
+
+/// The response stays open until `timeout`, or until the client goes away,
+/// which drops the subscription to the events. An error of the events ends
+/// the response early.
+fn watch_response<M>(
+    runtime: M,
+    list: ListQuery,
+    secrets: SecretFilter,
+    timeout: Duration,
+) -> Result<Response<Body>, Error>
+where
+    M: 'static + ModuleRuntime + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    let changes = stream::once(Ok(WatchTrigger::Changed)).chain(
+        runtime
+            .events()
+            .map(|_| WatchTrigger::Changed)
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::RuntimeOperation(RuntimeOperation::Events)))
+            }),
+    );
+    // The events may end long before the timeout, so the end of the response
+    // is marked by the timeout instead.
+    let timeout = Delay::new(Instant::now() + timeout)
+        .map(|()| WatchTrigger::Timeout)
+        .map_err(|err| {
+            Error::from(err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)))
+        })
+        .into_stream()
+        .chain(stream::once(Ok(WatchTrigger::End)));
+    let lists = changes
+        .select(timeout)
+        .take_while(|trigger| Ok(*trigger != WatchTrigger::End))
+        .and_then(move |_| list_modules(&runtime, &list, &secrets))
+        .map(|b| Chunk::from(b + "\n"));
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Body::wrap_stream(lists.map_err(Fail::compat)))
+        .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+    Ok(response)
+}
+
+/// Pages are sliced out of the full list in the order the runtime reports the
+/// modules, so callers paging through a changing set of modules may see a
+/// module twice or not at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Pagination {
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl Pagination {
+    #[allow(clippy::cast_possible_wrap)]
+    fn to_list(self, modules: Vec<ModuleDetails>, total_count: usize) -> ModuleList {
+        let list = ModuleList::new(modules)
+            .with_total_count(total_count as i64)
+            .with_offset(self.offset as i64);
+        match self.limit {
+            Some(limit) => list.with_limit(limit as i64),
+            None => list,
+        }
+    }
+}
+
+fn parse_pagination(query: &str) -> Result<Pagination, Error> {
+    let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+    let limit = match parse.iter().find(|&(ref key, _)| key == "limit") {
+        Some((_, val)) => match val.parse::<usize>() {
+            Ok(limit) if limit > 0 => Some(limit),
+            _ => return Err(Error::from(ErrorKind::MalformedRequestParameter("limit"))),
+        },
+        None => None,
+    };
+    let offset = parse
+        .iter()
+        .find(|&(ref key, _)| key == "offset")
+        .map_or_else(|| Ok(0), |(_, val)| val.parse::<usize>())
+        .context(ErrorKind::MalformedRequestParameter("offset"))?;
+    Ok(Pagination { limit, offset })
+}
+
+/// `Some` with the time to watch the list for with `watch=true`, which is
+/// `timeout_secs` when it is given and between 1 and 120.
+fn parse_watch(query: &str) -> Result<Option<Duration>, Error> {
+    let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+    let watch = parse
+        .iter()
+        .find(|&(ref key, _)| key == "watch")
+        .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("watch"))?;
+    if !watch {
+        return Ok(None);
+    }
+    let timeout_secs = match parse.iter().find(|&(ref key, _)| key == "timeout_secs") {
+        Some((_, val)) => match val.parse::<u64>() {
+            Ok(secs) if secs > 0 && secs <= MAX_WATCH_TIMEOUT_SECS => secs,
+            _ => {
+                return Err(Error::from(ErrorKind::MalformedRequestParameter(
+                    "timeout_secs",
+                )))
+            }
+        },
+        None => DEFAULT_WATCH_TIMEOUT_SECS,
+    };
+    Ok(Some(Duration::from_secs(timeout_secs)))
+}
+
+/// A `label` query parameter. As with `docker ps --filter`, `key=value`
+/// matches modules whose label `key` has that value and a bare `key` matches
+/// modules that have the label at all. Multiple filters must all match.
+#[derive(Clone, Debug, PartialEq)]
+struct LabelFilter {
+    key: String,
+    value: Option<String>,
+}
+
+impl LabelFilter {
+    fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        match (labels.get(&self.key), &self.value) {
+            (Some(actual), Some(expected)) => actual == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+fn parse_label_filters(query: &str) -> Result<Vec<LabelFilter>, Error> {
+    form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| key == "label")
+        .map(|(_, val)| {
+            let mut parts = val.splitn(2, '=');
+            let key = parts.next().unwrap_or("");
+            if key.is_empty() {
+                return Err(Error::from(ErrorKind::MalformedRequestParameter("label")));
+            }
+            Ok(LabelFilter {
+                key: key.to_string(),
+                value: parts.next().map(ToString::to_string),
+            })
+        })
+        .collect()
+}
+
+pub(super) fn core_to_details<M>(
+    name: String,
+    module: &M,
+    state: &ModuleRuntimeState,
+) -> Result<ModuleDetails, Error>
 where
     M: 'static + Module + Send,
     M::Config: Serialize,
@@ -89,7 +347,7 @@ where
 
     Ok(ModuleDetails::new(
         "id".to_string(),
-        module.name().to_string(),
+        name,
         module.type_().to_string(),
         config,
         status,
@@ -99,11 +357,12 @@ where
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
-    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_core::{ModuleEvent, ModuleEventType, ModuleRuntimeState, ModuleStatus};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::module::*;
     use futures::Stream;
     use management::models::ModuleList;
+    use tokio::runtime::current_thread::Runtime;
 
     use super::*;
     use crate::server::module::tests::Error;
@@ -167,6 +426,247 @@ mod tests {
             .unwrap();
     }
 
+    fn runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    #[test]
+    fn pagination_defaults() {
+        let pagination = parse_pagination("").unwrap();
+        assert_eq!(Pagination::default(), pagination);
+    }
+
+    #[test]
+    fn pagination_limit_and_offset() {
+        let pagination = parse_pagination("limit=10&offset=20").unwrap();
+        assert_eq!(Some(10), pagination.limit);
+        assert_eq!(20, pagination.offset);
+    }
+
+    #[test]
+    fn pagination_offset_error() {
+        let pagination = parse_pagination("offset=-1");
+        assert_eq!(
+            "The request parameter `offset` is malformed",
+            pagination.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn paged_success() {
+        // arrange
+        let handler = ListModules::new(runtime());
+        let request = Request::get("http://localhost/modules?limit=5&offset=0")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let list: ModuleList = serde_json::from_slice(&b).unwrap();
+                assert_eq!(1, list.modules().len());
+                assert_eq!("test-module", list.modules()[0].name());
+                assert_eq!(Some(&1), list.total_count());
+                assert_eq!(Some(&5), list.limit());
+                assert_eq!(Some(&0), list.offset());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn tenant_modules_are_listed_by_their_names_in_the_tenant() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("t1.test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let list = |tenant: &str| {
+            let request = Request::get(format!("http://localhost/tenants/{}/modules", tenant))
+                .body(Body::default())
+                .unwrap();
+            let params =
+                Parameters::with_captures(vec![(Some("tenant".to_string()), tenant.to_string())]);
+            let response = ListModules::new(runtime.clone())
+                .handle(request, params)
+                .wait()
+                .unwrap();
+            assert_eq!(StatusCode::OK, response.status());
+            let body = response.into_body().concat2().wait().unwrap();
+            serde_json::from_slice::<ModuleList>(&body).unwrap()
+        };
+
+        // act
+        let t1 = list("t1");
+        let t2 = list("t2");
+
+        // assert
+        assert_eq!(1, t1.modules().len());
+        assert_eq!("test-module", t1.modules()[0].name());
+        assert!(t2.modules().is_empty());
+    }
+
+    #[test]
+    fn offset_beyond_total_count_returns_empty_list() {
+        // arrange
+        let handler = ListModules::new(runtime());
+        let request = Request::get("http://localhost/modules?offset=10")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let list: ModuleList = serde_json::from_slice(&b).unwrap();
+                assert!(list.modules().is_empty());
+                assert_eq!(Some(&1), list.total_count());
+                assert_eq!(None, list.limit());
+                assert_eq!(Some(&10), list.offset());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn zero_limit_is_bad_request() {
+        // arrange
+        let handler = ListModules::new(runtime());
+        let request = Request::get("http://localhost/modules?limit=0")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "The request parameter `limit` is malformed",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    fn labelled_runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let mut labels = HashMap::new();
+        labels.insert("app".to_string(), "web".to_string());
+        labels.insert("tier".to_string(), "frontend".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state)).with_labels(labels);
+        TestRuntime::new(Ok(module))
+    }
+
+    fn list_names(handler: &ListModules<TestRuntime<Error>>, uri: &str) -> Vec<String> {
+        let request = Request::get(uri).body(Body::default()).unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let list: ModuleList = serde_json::from_slice(&b).unwrap();
+        list.modules().iter().map(|m| m.name().clone()).collect()
+    }
+
+    #[test]
+    fn label_filters_parse() {
+        let filters = parse_label_filters("label=app%3Dweb&label=tier&limit=1").unwrap();
+        assert_eq!(
+            vec![
+                LabelFilter {
+                    key: "app".to_string(),
+                    value: Some("web".to_string()),
+                },
+                LabelFilter {
+                    key: "tier".to_string(),
+                    value: None,
+                },
+            ],
+            filters
+        );
+    }
+
+    #[test]
+    fn label_filters_are_anded() {
+        let handler = ListModules::new(labelled_runtime());
+
+        assert_eq!(
+            vec!["test-module".to_string()],
+            list_names(&handler, "http://localhost/modules?label=app=web")
+        );
+        assert_eq!(
+            vec!["test-module".to_string()],
+            list_names(
+                &handler,
+                "http://localhost/modules?label=app=web&label=tier=frontend"
+            )
+        );
+        assert_eq!(
+            vec!["test-module".to_string()],
+            list_names(&handler, "http://localhost/modules?label=tier")
+        );
+        assert!(list_names(
+            &handler,
+            "http://localhost/modules?label=app=web&label=tier=backend"
+        )
+        .is_empty());
+        assert!(list_names(&handler, "http://localhost/modules?label=owner").is_empty());
+    }
+
+    #[test]
+    fn empty_label_key_is_bad_request() {
+        // arrange
+        let handler = ListModules::new(labelled_runtime());
+        let request = Request::get("http://localhost/modules?label=%3Dweb")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "The request parameter `label` is malformed",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn list_failed() {
         // arrange
@@ -224,4 +724,66 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn watch_sends_the_list_after_every_event_until_the_timeout() {
+        // arrange
+        let event = |event_type| {
+            ModuleEvent::new(
+                event_type,
+                "test-module".to_string(),
+                Utc.ymd(2019, 1, 30).and_hms(12, 34, 56),
+            )
+        };
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state)).with_events(vec![
+                event(ModuleEventType::Started),
+                event(ModuleEventType::Died),
+            ]);
+        let handler = ListModules::new(TestRuntime::new(Ok(module)));
+        let request = Request::get("http://localhost/modules?watch=true&timeout_secs=1")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/x-ndjson", response.headers()[CONTENT_TYPE]);
+        let b = Runtime::new()
+            .unwrap()
+            .block_on(response.into_body().concat2())
+            .unwrap();
+        let lists: Vec<ModuleList> = std::str::from_utf8(&b)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(4, lists.len());
+        for list in lists {
+            assert_eq!("test-module", list.modules()[0].name());
+        }
+    }
+
+    #[test]
+    fn watch_timeout_out_of_range_is_bad_request() {
+        for uri in &[
+            "http://localhost/modules?watch=true&timeout_secs=0",
+            "http://localhost/modules?watch=true&timeout_secs=121",
+            "http://localhost/modules?watch=yes",
+        ] {
+            // arrange
+            let handler = ListModules::new(runtime());
+            let request = Request::get(*uri).body(Body::default()).unwrap();
+
+            // act
+            let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+            // assert
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        }
+    }
 }
@@ -1,14 +1,20 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use failure::ResultExt;
-use futures::{future, Future, IntoFuture};
-use hyper::{Body, Request, Response, StatusCode};
+use std::borrow::Cow;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use failure::{Fail, ResultExt};
+use futures::{future, Async, Future, IntoFuture, Poll, Stream};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
 use url::form_urlencoded;
 
 use edgelet_core::{LogOptions, LogTail, ModuleRuntime, RuntimeOperation};
+use edgelet_docker::{DockerLogDemultiplexer, LogLine};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -32,6 +38,8 @@ where
         req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         let runtime = self.runtime.clone();
 
         let response = params
@@ -43,25 +51,52 @@ where
                     .uri()
                     .query()
                     .map_or_else(|| Ok(LogOptions::default()), parse_options)?;
-                Ok((name, options))
+                let format = req
+                    .uri()
+                    .query()
+                    .map_or_else(|| Ok(LogFormat::default()), parse_format)?;
+                let timestamps = req.uri().query().map_or(Ok(None), parse_timestamps)?;
+                let options = options.with_timestamps(timestamps.is_some());
+                Ok((name, options, format, timestamps))
             })
-            .map(move |(name, options)| {
-                runtime.logs(&name, &options).then(|s| -> Result<_, Error> {
-                    let s = s.with_context(|_| {
-                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(name.clone()))
-                    })?;
-                    let response = Response::builder()
-                        .status(StatusCode::OK)
-                        .body(s.into())
-                        .context(ErrorKind::RuntimeOperation(
+            .map(move |(name, options, format, timestamps)| {
+                runtime
+                    .logs(&name, &options)
+                    .then(move |s| -> Result<_, Error> {
+                        let s = s.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(
+                                name.clone(),
+                            ))
+                        })?;
+                        let body: Body = s.into();
+                        let body = match timestamps {
+                            None => body,
+                            Some(TimestampFormat::Rfc3339) => Body::wrap_stream(
+                                DockerLogDemultiplexer::new(body)
+                                    .map(|line| Chunk::from(format_rfc3339(&line)))
+                                    .map_err(Fail::compat),
+                            ),
+                        };
+                        let mut response = Response::builder();
+                        response.status(StatusCode::OK);
+                        let body = match format {
+                            LogFormat::Raw => body,
+                            LogFormat::Sse => {
+                                response
+                                    .header(CONTENT_TYPE, "text/event-stream")
+                                    .header(CACHE_CONTROL, "no-cache");
+                                Body::wrap_stream(SseLogs::new(body))
+                            }
+                        };
+                        let response = response.body(body).context(ErrorKind::RuntimeOperation(
                             RuntimeOperation::GetModuleLogs(name),
                         ))?;
-                    Ok(response)
-                })
+                        Ok(response)
+                    })
             })
             .into_future()
             .flatten()
-            .or_else(|e| future::ok(e.into_response()));
+            .or_else(move |e| future::ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
@@ -79,10 +114,161 @@ fn parse_options(query: &str) -> Result<LogOptions, Error> {
         .find(|&(ref key, _)| key == "follow")
         .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
         .context(ErrorKind::MalformedRequestParameter("follow"))?;
-    let options = LogOptions::new().with_follow(follow).with_tail(tail);
+    let since = parse_time(&parse, "since")?;
+    let until = parse_time(&parse, "until")?;
+    let mut options = LogOptions::new().with_follow(follow).with_tail(tail);
+    if let (Some(since), Some(until)) = (since, until) {
+        if since >= until {
+            return Err(Error::from(ErrorKind::MalformedRequestParameter("until")));
+        }
+    }
+    if let Some(since) = since {
+        options = options.with_since(since);
+    }
+    if let Some(until) = until {
+        options = options.with_until(until);
+    }
     Ok(options)
 }
 
+fn parse_time(
+    parse: &[(Cow<'_, str>, Cow<'_, str>)],
+    name: &'static str,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    parse
+        .iter()
+        .find(|&(ref key, _)| key == name)
+        .map_or(Ok(None), |(_, val)| {
+            DateTime::parse_from_rfc3339(val).map(|time| Some(time.with_timezone(&Utc)))
+        })
+        .context(ErrorKind::MalformedRequestParameter(name))
+        .map_err(Error::from)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogFormat {
+    Raw,
+    Sse,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Raw
+    }
+}
+
+fn parse_format(query: &str) -> Result<LogFormat, Error> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|&(ref key, _)| key == "format")
+        .map_or(Ok(LogFormat::default()), |(_, val)| match &*val {
+            "raw" => Ok(LogFormat::Raw),
+            "sse" => Ok(LogFormat::Sse),
+            _ => Err(Error::from(ErrorKind::MalformedRequestParameter("format"))),
+        })
+}
+
+/// Output format of `timestamps`. Setting it asks the runtime for
+/// timestamped logs, which are then demultiplexed into lines of the form
+/// `<timestamp> <message>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimestampFormat {
+    Rfc3339,
+}
+
+fn parse_timestamps(query: &str) -> Result<Option<TimestampFormat>, Error> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|&(ref key, _)| key == "timestamps")
+        .map_or(Ok(None), |(_, val)| match &*val {
+            "rfc3339" => Ok(Some(TimestampFormat::Rfc3339)),
+            _ => Err(Error::from(ErrorKind::MalformedRequestParameter(
+                "timestamps",
+            ))),
+        })
+}
+
+fn format_rfc3339(line: &LogLine) -> String {
+    format!(
+        "{} {}\n",
+        line.timestamp()
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        line.message()
+    )
+}
+
+/// Frames a log stream as Server-Sent Events, emitting one `data:` event per
+/// log line.
+///
+/// Lines can be split across chunks of the underlying stream, so the trailing
+/// partial line of each chunk is held back until the rest of it arrives. Only
+/// that partial line is ever buffered, and the inner stream is only polled
+/// when the consumer asks for more.
+struct SseLogs<S> {
+    inner: S,
+    pending: Vec<u8>,
+    done: bool,
+}
+
+impl<S> SseLogs<S> {
+    fn new(inner: S) -> Self {
+        SseLogs {
+            inner,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+fn write_event(line: &[u8], out: &mut Vec<u8>) {
+    let line = if line.ends_with(b"\r") {
+        &line[..line.len() - 1]
+    } else {
+        line
+    };
+    out.extend_from_slice(b"data: ");
+    out.extend_from_slice(line);
+    out.extend_from_slice(b"\n\n");
+}
+
+impl<S> Stream for SseLogs<S>
+where
+    S: Stream<Item = Chunk>,
+{
+    type Item = Chunk;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        while !self.done {
+            match self.inner.poll()? {
+                Async::NotReady => return Ok(Async::NotReady),
+                Async::Ready(Some(chunk)) => {
+                    self.pending.extend_from_slice(&chunk);
+
+                    if let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') {
+                        let rest = self.pending.split_off(end + 1);
+                        let mut out = Vec::with_capacity(self.pending.len() + 8);
+                        for line in self.pending[..end].split(|&b| b == b'\n') {
+                            write_event(line, &mut out);
+                        }
+                        self.pending = rest;
+                        return Ok(Async::Ready(Some(out.into())));
+                    }
+                }
+                Async::Ready(None) => {
+                    self.done = true;
+                    if !self.pending.is_empty() {
+                        let mut out = Vec::with_capacity(self.pending.len() + 8);
+                        write_event(&self.pending, &mut out);
+                        self.pending.clear();
+                        return Ok(Async::Ready(Some(out.into())));
+                    }
+                }
+            }
+        }
+
+        Ok(Async::Ready(None))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
@@ -133,6 +319,161 @@ mod tests {
         );
     }
 
+    #[test]
+    fn logoption_time_range() {
+        let query = "since=2019-03-01T10:00:00Z&until=2019-03-01T12:00:00%2B01:00";
+        let options = parse_options(&query).unwrap();
+        assert_eq!(Some(Utc.ymd(2019, 3, 1).and_hms(10, 0, 0)), options.since());
+        assert_eq!(Some(Utc.ymd(2019, 3, 1).and_hms(11, 0, 0)), options.until());
+    }
+
+    #[test]
+    fn logoption_time_range_defaults_to_unbounded() {
+        let options = parse_options("follow=true").unwrap();
+        assert_eq!(None, options.since());
+        assert_eq!(None, options.until());
+    }
+
+    #[test]
+    fn logoption_since_error() {
+        let query = "since=yesterday";
+        let options = parse_options(&query);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `since` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn logoption_until_before_since_error() {
+        let query = "since=2019-03-01T10:00:00Z&until=2019-03-01T10:00:00Z";
+        let options = parse_options(&query);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `until` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn logformat_defaults_to_raw() {
+        let query = "follow=true&tail=6";
+        let format = parse_format(&query).unwrap();
+        assert_eq!(LogFormat::Raw, format);
+    }
+
+    #[test]
+    fn logformat_sse() {
+        let query = "follow=true&format=sse";
+        let format = parse_format(&query).unwrap();
+        assert_eq!(LogFormat::Sse, format);
+    }
+
+    #[test]
+    fn logformat_error() {
+        let query = "format=xml";
+        let format = parse_format(&query);
+        assert!(format.is_err());
+        assert_eq!(
+            "The request parameter `format` is malformed",
+            format.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn timestamps_default_to_none() {
+        let query = "follow=true&tail=6";
+        assert_eq!(None, parse_timestamps(&query).unwrap());
+    }
+
+    #[test]
+    fn timestamps_rfc3339() {
+        let query = "timestamps=rfc3339&format=sse";
+        assert_eq!(
+            Some(TimestampFormat::Rfc3339),
+            parse_timestamps(&query).unwrap()
+        );
+    }
+
+    #[test]
+    fn timestamps_error() {
+        let query = "timestamps=unix";
+        let timestamps = parse_timestamps(&query);
+        assert!(timestamps.is_err());
+        assert_eq!(
+            "The request parameter `timestamps` is malformed",
+            timestamps.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn rfc3339_lines_from_docker_frames() {
+        let payload = "2019-01-30T13:34:56.5+01:00 line 1\n";
+        #[allow(clippy::cast_possible_truncation)]
+        let mut frame = vec![1, 0, 0, 0, 0, 0, 0, payload.len() as u8];
+        frame.extend_from_slice(payload.as_bytes());
+        let chunks: Vec<Result<Chunk, hyper::Error>> = vec![Ok(Chunk::from(frame))];
+
+        let lines = DockerLogDemultiplexer::new(futures::stream::iter_result(chunks))
+            .map(|line| format_rfc3339(&line))
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(vec!["2019-01-30T12:34:56.500Z line 1\n"], lines);
+    }
+
+    #[test]
+    fn sse_frames_each_line() {
+        let chunks: Vec<Result<Chunk, hyper::Error>> = vec![
+            Ok(Chunk::from("line 1\nline")),
+            Ok(Chunk::from(" 2\r\n")),
+            Ok(Chunk::from("line 3")),
+        ];
+        let framed = SseLogs::new(futures::stream::iter_result(chunks))
+            .concat2()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            "data: line 1\n\ndata: line 2\n\ndata: line 3\n\n",
+            std::str::from_utf8(&framed).unwrap()
+        );
+    }
+
+    #[test]
+    fn sse_success() {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let handler = ModuleLogs::new(runtime);
+        let request = Request::get(
+            "http://localhost/modules/mod1/logs?api-version=2018-06-28&follow=true&format=sse",
+        )
+        .body(Body::default())
+        .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("text/event-stream", response.headers()[CONTENT_TYPE]);
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                assert_eq!(0, b.len());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn test_success() {
         let state = ModuleRuntimeState::default()
@@ -0,0 +1,323 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Client, Request, Response, StatusCode, Uri};
+use tokio::timer::Timeout as TimeoutFuture;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The label of a module's spec that holds the port its Prometheus metrics
+/// are served on.
+pub const METRICS_PORT_LABEL: &str = "metrics_port";
+
+/// How long a module gets to return its metrics.
+pub const MODULE_METRICS_TIMEOUT: Duration = Duration::from_secs(5);
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Scrapes `http://{module_ip}:{metrics_port}/metrics` of a module and
+/// returns what it serves, so Prometheus can scrape every module through the
+/// management API without the modules' ports being published on the host.
+///
+/// A module without a `metrics_port` label gets `404 Not Found`, and one
+/// that has no address because it isn't running `409 Conflict`. A module
+/// that doesn't answer in time gets `504 Gateway Timeout` and any other
+/// failure to scrape it `502 Bad Gateway`.
+pub struct GetModuleMetrics<M> {
+    runtime: M,
+    client: Client<HttpConnector>,
+    timeout: Duration,
+}
+
+impl<M> GetModuleMetrics<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleMetrics {
+            runtime,
+            client: Client::new(),
+            timeout: MODULE_METRICS_TIMEOUT,
+        }
+    }
+
+    #[cfg(test)]
+    fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleMetrics<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let client = self.client.clone();
+        let timeout = self.timeout;
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime
+                    .get_config(&name)
+                    .then(move |spec| -> Result<_, Error> {
+                        let spec = spec
+                            .with_context(|_| {
+                                ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(
+                                    name.clone(),
+                                ))
+                            })?
+                            .ok_or_else(|| ErrorKind::ModuleNotFound(name.clone()))?;
+                        let port: u16 = spec
+                            .labels()
+                            .get(METRICS_PORT_LABEL)
+                            .ok_or_else(|| ErrorKind::MetricsPortNotFound(name.clone()))?
+                            .parse::<u16>()
+                            .with_context(|_| ErrorKind::ModuleMetricsUnavailable(name.clone()))?;
+                        Ok((name, port))
+                    })
+                    .and_then(move |(name, port)| {
+                        runtime
+                            .inspect(&name)
+                            .then(move |details| -> Result<_, Error> {
+                                let details = details.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::InspectModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                let ip = details
+                                    .ip_address()
+                                    .ok_or_else(|| ErrorKind::ModuleNotRunning(name.clone()))?;
+                                let uri: Uri =
+                                    format!("http://{}/metrics", SocketAddr::new(ip, port))
+                                        .parse::<Uri>()
+                                        .with_context(|_| {
+                                            ErrorKind::ModuleMetricsUnavailable(name.clone())
+                                        })?;
+                                Ok((name, uri))
+                            })
+                    })
+                    .and_then(move |(name, uri)| scrape(&client, uri, name, timeout))
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn scrape(
+    client: &Client<HttpConnector>,
+    uri: Uri,
+    name: String,
+    timeout: Duration,
+) -> impl Future<Item = Response<Body>, Error = Error> {
+    TimeoutFuture::new(client.get(uri), timeout).then(move |response| {
+        let response = response.map_err(|err| {
+            if err.is_elapsed() {
+                Error::from(ErrorKind::ModuleMetricsTimeout(name.clone(), timeout))
+            } else {
+                match err.into_inner() {
+                    Some(err) => {
+                        Error::from(err.context(ErrorKind::ModuleMetricsUnavailable(name.clone())))
+                    }
+                    None => Error::from(ErrorKind::ModuleMetricsUnavailable(name.clone())),
+                }
+            }
+        })?;
+        if !response.status().is_success() {
+            return Err(Error::from(ErrorKind::ModuleMetricsUnavailable(name)));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .cloned()
+            .unwrap_or_else(|| HeaderValue::from_static(METRICS_CONTENT_TYPE));
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, content_type)
+            .body(response.into_body())
+            .context(ErrorKind::ModuleMetricsUnavailable(name))?;
+        Ok(response)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr, TcpListener};
+
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use hyper::server::Server;
+    use hyper::service::service_fn_ok;
+    use management::models::ErrorResponse;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn module(port: Option<u16>, ip: Option<IpAddr>) -> TestModule<Error> {
+        let mut labels = HashMap::new();
+        if let Some(port) = port {
+            labels.insert(METRICS_PORT_LABEL.to_string(), port.to_string());
+        }
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state)).with_labels(labels);
+        match ip {
+            Some(ip) => module.with_ip_address(ip),
+            None => module,
+        }
+    }
+
+    fn handle(
+        runtime: &mut Runtime,
+        handler: &GetModuleMetrics<TestRuntime<Error>>,
+    ) -> Response<Body> {
+        let request = Request::get("http://localhost/modules/mod1/metrics")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        runtime
+            .block_on(handler.handle(request, parameters))
+            .unwrap()
+    }
+
+    fn error_code(runtime: &mut Runtime, response: Response<Body>) -> Option<String> {
+        let b = runtime.block_on(response.into_body().concat2()).unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        error.code().map(ToString::to_string)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let mut runtime = Runtime::new().unwrap();
+        let server = Server::bind(&SocketAddr::new(LOCALHOST, 0)).serve(|| {
+            service_fn_ok(|req: Request<Body>| {
+                assert_eq!("/metrics", req.uri().path());
+                Response::new(Body::from("requests_total 7\n"))
+            })
+        });
+        let port = server.local_addr().port();
+        runtime.spawn(server.map_err(|err| panic!("{}", err)));
+        let handler =
+            GetModuleMetrics::new(TestRuntime::new(Ok(module(Some(port), Some(LOCALHOST)))));
+
+        // act
+        let response = handle(&mut runtime, &handler);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(METRICS_CONTENT_TYPE, response.headers()[CONTENT_TYPE]);
+        let b = runtime.block_on(response.into_body().concat2()).unwrap();
+        assert_eq!(b"requests_total 7\n", b.as_ref());
+    }
+
+    #[test]
+    fn no_metrics_port_is_not_found() {
+        // arrange
+        let mut runtime = Runtime::new().unwrap();
+        let handler = GetModuleMetrics::new(TestRuntime::new(Ok(module(None, Some(LOCALHOST)))));
+
+        // act
+        let response = handle(&mut runtime, &handler);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(
+            Some("MetricsPortNotFound".to_string()),
+            error_code(&mut runtime, response)
+        );
+    }
+
+    #[test]
+    fn module_without_address_is_not_running() {
+        // arrange
+        let mut runtime = Runtime::new().unwrap();
+        let handler = GetModuleMetrics::new(TestRuntime::new(Ok(module(Some(9600), None))));
+
+        // act
+        let response = handle(&mut runtime, &handler);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+    }
+
+    #[test]
+    fn unresponsive_module_times_out() {
+        // arrange
+        let mut runtime = Runtime::new().unwrap();
+        // Connections are accepted by the OS into the backlog but never
+        // answered.
+        let listener = TcpListener::bind(SocketAddr::new(LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handler =
+            GetModuleMetrics::new(TestRuntime::new(Ok(module(Some(port), Some(LOCALHOST)))))
+                .with_timeout(Duration::from_millis(100));
+
+        // act
+        let response = handle(&mut runtime, &handler);
+
+        // assert
+        assert_eq!(StatusCode::GATEWAY_TIMEOUT, response.status());
+        assert_eq!(
+            Some("ModuleMetricsTimeout".to_string()),
+            error_code(&mut runtime, response)
+        );
+        drop(listener);
+    }
+
+    #[test]
+    fn failed_scrape_is_bad_gateway() {
+        // arrange
+        let mut runtime = Runtime::new().unwrap();
+        let server = Server::bind(&SocketAddr::new(LOCALHOST, 0)).serve(|| {
+            service_fn_ok(|_: Request<Body>| {
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                response
+            })
+        });
+        let port = server.local_addr().port();
+        runtime.spawn(server.map_err(|err| panic!("{}", err)));
+        let handler =
+            GetModuleMetrics::new(TestRuntime::new(Ok(module(Some(port), Some(LOCALHOST)))));
+
+        // act
+        let response = handle(&mut runtime, &handler);
+
+        // assert
+        assert_eq!(StatusCode::BAD_GATEWAY, response.status());
+        assert_eq!(
+            Some("ModuleMetricsUnavailable".to_string()),
+            error_code(&mut runtime, response)
+        );
+    }
+}
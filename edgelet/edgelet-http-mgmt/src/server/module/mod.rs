@@ -2,37 +2,125 @@
 
 use std::collections::HashMap;
 
-use failure::Fail;
+use failure::{Fail, ResultExt};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{Module, ModuleRuntime, ModuleSpec as CoreModuleSpec, ModuleStatus};
+use edgelet_core::tenant::scoped_name;
+use edgelet_core::{
+    Module, ModuleRuntime, ModuleSpec as CoreModuleSpec, ModuleStatus,
+    ResourceLimits as CoreResourceLimits, RestartPolicy as CoreRestartPolicy,
+};
+use edgelet_http::route::Parameters;
 use management::models::*;
 
 use crate::error::{Error, ErrorKind};
 
+mod bandwidth;
+mod bulk;
+mod checkpoint;
+mod config;
+mod copy_file;
+mod copy_logs;
 mod create;
 mod delete;
+mod delete_all;
+mod diff;
+mod env;
+mod exec;
 mod get;
+mod graph;
+mod history;
+mod idempotency;
+mod inspect;
+mod labels;
 mod list;
 mod logs;
+mod metrics;
+mod names;
+mod patch;
+mod pause;
+mod ports;
+mod prepare_image;
 mod prepare_update;
+mod promote;
+mod registry;
+mod rename;
+mod resources;
 mod restart;
+mod rollback;
+mod secrets;
+mod signal;
 mod start;
+mod stats;
+mod status;
 mod stop;
+mod template;
+mod top;
+mod truststore;
 mod update;
-
+mod update_image;
+mod validation;
+mod volumes;
+mod watch;
+
+pub use self::bandwidth::SetModuleBandwidth;
+pub use self::bulk::{BulkStartModules, BulkStopModules};
+pub use self::checkpoint::{CheckpointModule, RestoreModule};
+pub use self::config::{GetModuleConfig, InvalidateConfig};
+pub use self::copy_file::{CopyFileToModule, COPY_FILE_BODY_OVERHEAD_BYTES, MAX_COPY_FILE_BYTES};
+pub use self::copy_logs::CopyModuleLogs;
 pub use self::create::CreateModule;
 pub use self::delete::DeleteModule;
+pub use self::delete_all::DeleteAllModules;
+pub(crate) use self::diff::changed_paths;
+pub use self::diff::GetModuleDiff;
+pub use self::env::{GetModuleEnv, PatchModuleEnv};
+pub use self::exec::ExecModule;
 pub use self::get::GetModule;
+pub use self::graph::GetModuleGraph;
+pub use self::history::{GetModuleAncestry, ModuleHistory};
+pub use self::idempotency::DEFAULT_IDEMPOTENCY_TTL;
+pub use self::inspect::InspectModule;
+pub use self::labels::{DeleteAllModuleLabels, DeleteModuleLabel, GetModuleLabel, SetModuleLabel};
 pub use self::list::ListModules;
 pub use self::logs::ModuleLogs;
+pub use self::metrics::GetModuleMetrics;
+pub use self::names::ModuleNameValidator;
+pub(crate) use self::patch::merge;
+pub use self::patch::PatchModule;
+pub use self::pause::{PauseModule, UnpauseModule};
+pub use self::ports::GetModulePorts;
+pub use self::prepare_image::PrepareModuleImage;
 pub use self::prepare_update::PrepareUpdateModule;
+pub use self::promote::PromoteModule;
+pub use self::registry::RegistryValidator;
+pub use self::rename::RenameModule;
+pub use self::resources::PatchModuleResources;
 pub use self::restart::RestartModule;
+pub use self::rollback::RollbackModule;
+pub use self::secrets::{SecretFilter, DEFAULT_SECRET_PATTERNS};
+pub use self::signal::SignalModule;
 pub use self::start::StartModule;
-pub use self::stop::StopModule;
+pub use self::stats::ModuleStats;
+pub use self::status::GetModuleStatus;
+pub use self::stop::{StopModule, MAX_STOP_WAIT_TIMEOUT};
+pub use self::top::GetModuleTop;
+pub use self::truststore::{AddModuleTrustStore, GetModuleTrustStore, RemoveModuleTrustStore};
 pub use self::update::UpdateModule;
+pub use self::update_image::UpdateModuleImage;
+pub use self::volumes::GetModuleVolumes;
+pub use self::watch::WatchModules;
+
+/// The name of module `name` in the runtime. Under `/tenants/{tenant}`,
+/// module names are scoped to the tenant named by the request.
+fn runtime_name(params: &Parameters, name: &str) -> String {
+    match params.name("tenant") {
+        Some(tenant) => scoped_name(tenant, name),
+        None => name.to_string(),
+    }
+}
 
 fn spec_to_core<M>(
     spec: &ModuleSpec,
@@ -60,6 +148,71 @@ where
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
+    let resource_limits = match spec.resource_limits() {
+        Some(limits) => CoreResourceLimits::new(
+            limits.cpu_shares(),
+            limits.memory_limit_bytes(),
+            limits.memory_swap_bytes(),
+        )
+        .with_context(|_| ErrorKind::InvalidResourceLimits(spec.name().to_string()))?,
+        None => CoreResourceLimits::default(),
+    };
+
+    let restart_policy = match spec.restart_policy() {
+        Some(policy) => match serde_json::from_value(serde_json::Value::String(policy.clone())) {
+            Ok(policy) => policy,
+            Err(err) => return Err(Error::from(err.context(context))),
+        },
+        None => CoreRestartPolicy::default(),
+    };
+
+    Ok(module_spec
+        .with_labels(spec.labels().cloned().unwrap_or_else(HashMap::new))
+        .with_resource_limits(resource_limits)
+        .with_restart_policy(restart_policy)
+        .with_depends_on(spec.depends_on().cloned().unwrap_or_else(Vec::new)))
+}
+
+/// The spec in the shape accepted by `POST /modules` and `PUT /modules/{name}`.
+fn core_to_spec<T>(spec: &CoreModuleSpec<T>) -> Result<ModuleSpec, serde_json::Error>
+where
+    T: Serialize,
+{
+    let mut env: Vec<EnvVar> = spec
+        .env()
+        .iter()
+        .map(|(key, value)| EnvVar::new(key.clone(), value.clone()))
+        .collect();
+    env.sort_by(|a, b| a.key().cmp(b.key()));
+    let config = Config::new(serde_json::to_value(spec.config())?).with_env(env);
+
+    let mut module_spec =
+        ModuleSpec::new(spec.name().to_string(), spec.type_().to_string(), config);
+    if !spec.labels().is_empty() {
+        module_spec.set_labels(spec.labels().clone());
+    }
+    let limits = spec.resource_limits();
+    if !limits.is_empty() {
+        let mut resource_limits = ResourceLimits::new();
+        if let Some(cpu_shares) = limits.cpu_shares() {
+            resource_limits.set_cpu_shares(cpu_shares);
+        }
+        if let Some(memory_limit_bytes) = limits.memory_limit_bytes() {
+            resource_limits.set_memory_limit_bytes(memory_limit_bytes);
+        }
+        if let Some(memory_swap_bytes) = limits.memory_swap_bytes() {
+            resource_limits.set_memory_swap_bytes(memory_swap_bytes);
+        }
+        module_spec.set_resource_limits(resource_limits);
+    }
+    if spec.restart_policy() != CoreRestartPolicy::Never {
+        if let serde_json::Value::String(policy) = serde_json::to_value(spec.restart_policy())? {
+            module_spec.set_restart_policy(policy);
+        }
+    }
+    if !spec.depends_on().is_empty() {
+        module_spec.set_depends_on(spec.depends_on().to_vec());
+    }
     Ok(module_spec)
 }
 
@@ -73,7 +226,12 @@ fn spec_to_details(spec: &ModuleSpec, module_status: ModuleStatus) -> ModuleDeta
             .map(|ev| EnvVar::new(ev.key().clone(), ev.value().clone()))
             .collect()
     });
-    let mut config = Config::new(spec.config().settings().clone());
+    // Registry credentials are write-only, so they aren't echoed back.
+    let mut settings = spec.config().settings().clone();
+    if let Some(settings) = settings.as_object_mut() {
+        settings.remove("registryAuth");
+    }
+    let mut config = Config::new(settings);
     if let Some(e) = env {
         config.set_env(e);
     }
@@ -85,16 +243,18 @@ fn spec_to_details(spec: &ModuleSpec, module_status: ModuleStatus) -> ModuleDeta
 
 #[cfg(test)]
 pub mod tests {
+    use std::time::Duration;
+
     use failure::Fail;
     use futures::{Future, Stream};
     use hyper::{Body, Response, StatusCode};
     use serde_json;
 
-    use edgelet_core::RuntimeOperation;
+    use edgelet_core::{ModuleRuntimeErrorReason, RuntimeOperation};
     use edgelet_docker::{Error as DockerError, ErrorKind as DockerErrorKind};
     use management::models::ErrorResponse;
 
-    use crate::error::{Error as MgmtError, ErrorKind};
+    use crate::error::{with_error_code, Error as MgmtError, ErrorKind};
     use crate::IntoResponse;
 
     #[derive(Clone, Copy, Debug, Fail)]
@@ -114,6 +274,12 @@ pub mod tests {
         }
     }
 
+    impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
+        fn from(_err: &'a Error) -> Self {
+            ModuleRuntimeErrorReason::Other
+        }
+    }
+
     #[test]
     fn not_found() {
         // arrange
@@ -144,6 +310,7 @@ pub mod tests {
                     "Could not start module m1\n\tcaused by: Could not start module m1\n\tcaused by: No such container: m1",
                     error.message()
                 );
+                assert_eq!(Some("ModuleNotFound"), error.code());
                 Ok(())
             }).wait()
             .unwrap();
@@ -175,6 +342,7 @@ pub mod tests {
                     "Could not start module m1\n\tcaused by: Could not start module m1\n\tcaused by: Conflict with current operation",
                     error.message()
                 );
+                assert_eq!(Some("ModuleAlreadyExists"), error.code());
                 Ok(())
             }).wait()
             .unwrap();
@@ -208,6 +376,106 @@ pub mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn stop_timeout() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(
+                DockerErrorKind::StopTimeout(Duration::from_secs(30)).context(
+                    DockerErrorKind::RuntimeOperation(RuntimeOperation::WaitUntilStopped(
+                        "m1".to_string(),
+                    )),
+                ),
+            )
+            .context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::WaitUntilStopped("m1".to_string()),
+            )),
+        );
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::REQUEST_TIMEOUT, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not wait for module m1 to stop\n\tcaused by: Could not wait for module m1 to stop\n\tcaused by: Module did not stop within 30s",
+            error.message()
+        );
+        assert_eq!(Some("ModuleStopTimeout"), error.code());
+    }
+
+    #[test]
+    fn checkpoint_not_supported() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(DockerErrorKind::CheckpointNotSupported.context(
+                DockerErrorKind::RuntimeOperation(RuntimeOperation::CheckpointModule(
+                    "m1".to_string(),
+                )),
+            ))
+            .context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CheckpointModule("m1".to_string()),
+            )),
+        );
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::NOT_IMPLEMENTED, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("CheckpointNotSupported"), error.code());
+    }
+
+    #[test]
+    fn bandwidth_not_supported() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(DockerErrorKind::BandwidthNotSupported.context(
+                DockerErrorKind::RuntimeOperation(RuntimeOperation::SetModuleBandwidth(
+                    "m1".to_string(),
+                )),
+            ))
+            .context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::SetModuleBandwidth("m1".to_string()),
+            )),
+        );
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::NOT_IMPLEMENTED, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("BandwidthNotSupported"), error.code());
+    }
+
+    #[test]
+    fn circuit_open() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(DockerErrorKind::CircuitOpen.context(
+                DockerErrorKind::RuntimeOperation(RuntimeOperation::StartModule("m1".to_string())),
+            ))
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(
+                "m1".to_string(),
+            ))),
+        );
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("RuntimeUnavailable"), error.code());
+    }
+
     #[test]
     fn internal_server() {
         // arrange
@@ -266,4 +534,137 @@ pub mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn correlation_id_is_rendered() {
+        // arrange
+        let error = MgmtError::from(ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(
+            "m1".to_string(),
+        )))
+        .with_correlation_id(Some("abc-123".to_string()));
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!("Could not start module m1", error.message());
+                assert_eq!(Some("abc-123"), error.correlation_id());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn invalid_module_name_code() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(
+                DockerErrorKind::InvalidModuleName("m 1".to_string()).context(
+                    DockerErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                        "m 1".to_string(),
+                    )),
+                ),
+            )
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                "m 1".to_string(),
+            ))),
+        );
+
+        // act
+        let code = error.code();
+
+        // assert
+        assert_eq!("InvalidModuleName", code);
+    }
+
+    #[test]
+    fn error_code_is_added_to_empty_error_response() {
+        // arrange
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+
+        // act
+        let response = with_error_code::<()>(response).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("NotFound"), error.code());
+        assert_eq!("Not Found", error.message());
+    }
+
+    #[test]
+    fn error_code_keeps_message_of_error_response() {
+        // arrange
+        let response = Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .body(r#"{"message":"Too many requests"}"#.into())
+            .unwrap();
+
+        // act
+        let response = with_error_code::<()>(response).wait().unwrap();
+
+        // assert
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("TooManyRequests"), error.code());
+        assert_eq!("Too many requests", error.message());
+    }
+
+    #[test]
+    fn error_code_is_not_replaced() {
+        // arrange
+        let response = MgmtError::from(ErrorKind::MalformedRequestBody).into_response();
+
+        // act
+        let response = with_error_code::<()>(response).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("MalformedRequestBody"), error.code());
+    }
+
+    #[test]
+    fn error_code_keeps_other_json_bodies() {
+        // arrange
+        let response = Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(r#"{"status":"unhealthy"}"#.into())
+            .unwrap();
+
+        // act
+        let response = with_error_code::<()>(response).wait().unwrap();
+
+        // assert
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_eq!(br#"{"status":"unhealthy"}"#, &b[..]);
+    }
+
+    #[test]
+    fn error_code_ignores_success() {
+        // arrange
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .body("ok".into())
+            .unwrap();
+
+        // act
+        let response = with_error_code::<()>(response).wait().unwrap();
+
+        // assert
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_eq!(b"ok", &b[..]);
+    }
 }
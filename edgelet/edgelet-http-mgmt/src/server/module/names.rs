@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use edgelet_core::{validate_module_name, ErrorKind as CoreErrorKind, ModuleNameRules};
+use edgelet_http::authorization::AuthorizedCaller;
+
+use crate::error::{Error, ErrorKind};
+use crate::server::AGENT_NAME;
+
+/// The names that `/modules/{name}` routes of the management API have for
+/// themselves, like `GET /modules/graph`, so a module of one of them couldn't
+/// be managed.
+const ROUTE_NAMES: &[&str] = &["all", "bulk", "graph", "watch"];
+
+/// Checks the names of modules against Docker's rules for container names
+/// and the configured `ModuleNameRules`, so that a bad name gets a `400`
+/// response instead of failing in the module runtime. The names of routes
+/// are refused to every caller.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleNameValidator {
+    rules: Arc<ModuleNameRules>,
+}
+
+impl ModuleNameValidator {
+    pub fn new(rules: ModuleNameRules) -> Self {
+        ModuleNameValidator {
+            rules: Arc::new(rules),
+        }
+    }
+
+    pub fn validate(&self, name: &str) -> Result<(), Error> {
+        validate_module_name(name, self.rules.pattern()).map_err(|err| match err.kind() {
+            CoreErrorKind::ModuleNameNotAllowed(name, pattern) => Error::from(
+                ErrorKind::ModuleNameNotAllowed(name.clone(), pattern.clone()),
+            ),
+            _ => Error::from(ErrorKind::InvalidModuleName(name.to_string())),
+        })?;
+        if ROUTE_NAMES.contains(&name) {
+            return Err(Error::from(ErrorKind::ReservedModuleName(name.to_string())));
+        }
+        Ok(())
+    }
+
+    pub fn is_reserved(&self, name: &str) -> bool {
+        self.rules.is_reserved(name)
+    }
+
+    /// Reserved names are only refused to callers other than edgeAgent, since
+    /// edgeAgent deploys edgeHub itself.
+    pub fn validate_new(&self, name: &str, caller: Option<&AuthorizedCaller>) -> Result<(), Error> {
+        self.validate(name)?;
+        let is_agent = caller.map_or(false, |caller| caller.name() == AGENT_NAME.as_str());
+        if !is_agent && self.rules.is_reserved(name) {
+            return Err(Error::from(ErrorKind::ReservedModuleName(name.to_string())));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn agent() -> AuthorizedCaller {
+        AuthorizedCaller::new("edgeAgent".to_string())
+    }
+
+    #[test]
+    fn names_follow_docker_rules_and_the_pattern() {
+        let rules = serde_json::from_value(json!({ "pattern": "^contoso-" })).unwrap();
+        let names = ModuleNameValidator::new(rules);
+
+        assert!(names.validate("contoso-sensor").is_ok());
+        match names.validate("contoso sensor").unwrap_err().kind() {
+            ErrorKind::InvalidModuleName(name) => assert_eq!("contoso sensor", name),
+            kind => panic!("Expected `InvalidModuleName` but got {:?}", kind),
+        }
+        match names.validate("sensor").unwrap_err().kind() {
+            ErrorKind::ModuleNameNotAllowed(name, pattern) => {
+                assert_eq!("sensor", name);
+                assert_eq!("^contoso-", pattern);
+            }
+            kind => panic!("Expected `ModuleNameNotAllowed` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn reserved_names_are_only_allowed_for_the_agent() {
+        let names = ModuleNameValidator::default();
+
+        assert!(names.validate_new("edgeHub", Some(&agent())).is_ok());
+        assert!(names.validate_new("mod1", None).is_ok());
+        for caller in &[None, Some(AuthorizedCaller::new("mod1".to_string()))] {
+            match names
+                .validate_new("edgeHub", caller.as_ref())
+                .unwrap_err()
+                .kind()
+            {
+                ErrorKind::ReservedModuleName(name) => assert_eq!("edgeHub", name),
+                kind => panic!("Expected `ReservedModuleName` but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn route_names_are_reserved_for_everyone() {
+        let names = ModuleNameValidator::default();
+
+        for &name in ROUTE_NAMES {
+            for caller in &[None, Some(agent())] {
+                match names
+                    .validate_new(name, caller.as_ref())
+                    .unwrap_err()
+                    .kind()
+                {
+                    ErrorKind::ReservedModuleName(reserved) => assert_eq!(name, reserved),
+                    kind => panic!("Expected `ReservedModuleName` but got {:?}", kind),
+                }
+            }
+        }
+        assert!(names.validate_new("graphs", None).is_ok());
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use failure::{err_msg, ResultExt};
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Request, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::{Config, EnvVar, ModuleSpec};
+
+use super::registry::RegistryValidator;
+use super::update::{parse_start, update};
+use super::{spec_to_core, SecretFilter};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Applies a JSON Merge Patch (RFC 7396) to the spec of an existing module.
+///
+/// The patch is merged onto the module's current spec as it would be sent to
+/// `PUT /modules/{name}`, so for example `{"config":{"settings":{"image":
+/// "img:2"}}}` only changes the image. A `null` removes the field, and arrays
+/// such as `config.env` are replaced as a whole. The merged spec then goes
+/// through the same update as a `PUT`, including the `start` parameter. A
+/// patch whose result is not a valid spec for the module is rejected with
+/// `422 Unprocessable Entity`, and one with an image from a registry that
+/// the registry validator doesn't allow with `403 Forbidden`.
+pub struct PatchModule<M> {
+    runtime: M,
+    secrets: SecretFilter,
+    registries: RegistryValidator,
+}
+
+impl<M> PatchModule<M> {
+    pub fn new(runtime: M) -> Self {
+        PatchModule {
+            runtime,
+            secrets: SecretFilter::default(),
+            registries: RegistryValidator::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_registry_validator(mut self, registries: RegistryValidator) -> Self {
+        self.registries = registries;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for PatchModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+        let registries = self.registries.clone();
+        let start = parse_start(req.uri().query());
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(|b| -> Result<Value, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        Ok(serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?)
+                    })
+                    .and_then(move |patch| {
+                        let get_name = name.clone();
+                        runtime
+                            .get(&name)
+                            .join(runtime.env(&name))
+                            .then(move |result| -> Result<_, Error> {
+                                let ((module, _), env) = result.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                                        get_name,
+                                    ))
+                                })?;
+                                let current = current_spec(&module, env).with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+
+                                let mut merged = current;
+                                merge(&mut merged, patch);
+                                let spec = patched_spec(&name, merged)?;
+                                registries.validate(&spec)?;
+                                let core_spec = spec_to_core::<M>(
+                                    &spec,
+                                    ErrorKind::InvalidModulePatch(name.clone()),
+                                )?;
+                                Ok((core_spec, spec, runtime))
+                            })
+                            .and_then(move |(core_spec, spec, runtime)| {
+                                update(runtime, core_spec, spec, start, secrets)
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// The spec of `module` in the shape accepted by `PUT /modules/{name}`.
+pub(super) fn current_spec<M>(
+    module: &M,
+    env: HashMap<String, String>,
+) -> Result<Value, serde_json::Error>
+where
+    M: Module,
+    M::Config: Serialize,
+{
+    let settings = serde_json::to_value(module.config())?;
+    let mut env: Vec<EnvVar> = env
+        .into_iter()
+        .map(|(key, value)| EnvVar::new(key, value))
+        .collect();
+    env.sort_by(|a, b| a.key().cmp(b.key()));
+
+    let spec = ModuleSpec::new(
+        module.name().to_string(),
+        module.type_().to_string(),
+        Config::new(settings).with_env(env),
+    )
+    .with_labels(module.labels());
+    serde_json::to_value(spec)
+}
+
+fn patched_spec(name: &str, merged: Value) -> Result<ModuleSpec, Error> {
+    let spec: ModuleSpec = serde_json::from_value(merged)
+        .with_context(|_| ErrorKind::InvalidModulePatch(name.to_string()))?;
+    if spec.name() != name {
+        return Err(Error::from(
+            err_msg("the name of the module cannot be changed")
+                .context(ErrorKind::InvalidModulePatch(name.to_string())),
+        ));
+    }
+    Ok(spec)
+}
+
+/// Merges `patch` into `target` as described by RFC 7396.
+pub(crate) fn merge(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            if let Value::Object(target) = target {
+                for (key, value) in patch {
+                    if value.is_null() {
+                        target.remove(&key);
+                    } else {
+                        merge(target.entry(key).or_insert(Value::Null), value);
+                    }
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::module::*;
+    use hyper::StatusCode;
+    use management::models::{ErrorResponse, ModuleDetails};
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image:1".to_string());
+        let mut env = HashMap::new();
+        env.insert("MODE".to_string(), "value".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("mod1".to_string(), config, Ok(state)).with_env(env);
+        TestRuntime::new(Ok(module))
+    }
+
+    fn parameters() -> Parameters {
+        Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())])
+    }
+
+    fn body_error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn merge_follows_rfc7396() {
+        let mut target = json!({"a": "b", "c": {"d": "e", "f": "g"}, "h": [1, 2]});
+        merge(
+            &mut target,
+            json!({"a": "z", "c": {"f": null}, "h": [3], "i": {"j": 1}}),
+        );
+        assert_eq!(
+            json!({"a": "z", "c": {"d": "e"}, "h": [3], "i": {"j": 1}}),
+            target
+        );
+
+        let mut target = json!({"a": "b"});
+        merge(&mut target, json!(["c"]));
+        assert_eq!(json!(["c"]), target);
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let handler = PatchModule::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1")
+            .body(r#"{"config":{"settings":{"image":"microsoft/test-image:2"}}}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+        assert_eq!("mod1", details.name());
+        assert_eq!(
+            "microsoft/test-image:2",
+            details.config().settings().get("image").unwrap()
+        );
+        let env = details.config().env().unwrap();
+        assert_eq!(1, env.len());
+        assert_eq!("MODE", env[0].key());
+        assert_eq!("value", env[0].value());
+    }
+
+    #[test]
+    fn null_removes_field() {
+        // arrange
+        let handler = PatchModule::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1")
+            .body(r#"{"config":{"env":null}}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+        assert!(details.config().env().is_none());
+    }
+
+    #[test]
+    fn invalid_result() {
+        // arrange
+        let handler = PatchModule::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1")
+            .body(r#"{"config":{"settings":{"image":null}}}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+        let error = body_error(response);
+        assert_eq!(Some("InvalidModulePatch"), error.code());
+        assert_eq!(
+            "Patch of module \"mod1\" results in an invalid module spec\n\tcaused by: missing field `image`",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn rename_is_rejected() {
+        // arrange
+        let handler = PatchModule::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1")
+            .body(r#"{"name":"mod2"}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+    }
+
+    #[test]
+    fn bad_body() {
+        // arrange
+        let handler = PatchModule::new(runtime());
+        let request = Request::patch("http://localhost/modules/mod1")
+            .body("invalid".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = PatchModule::new(TestRuntime::new(Err(Error::General)));
+        let request = Request::patch("http://localhost/modules/mod1")
+            .body("{}".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not get module mod1\n\tcaused by: General error",
+            body_error(response).message()
+        );
+    }
+}
@@ -0,0 +1,252 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::future::Either;
+use futures::{Future, IntoFuture};
+use hyper::{Body, Request, Response, StatusCode};
+
+use edgelet_core::{ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Freezes the processes of a running module without stopping it, for
+/// example to attach a debugger to them. The module stays paused until
+/// `POST /modules/{name}/unpause`.
+pub struct PauseModule<M> {
+    runtime: M,
+}
+
+impl<M> PauseModule<M> {
+    pub fn new(runtime: M) -> Self {
+        PauseModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for PauseModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| set_paused(self.runtime.clone(), name.to_string(), true))
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Thaws the processes of a module paused by `POST /modules/{name}/pause`.
+pub struct UnpauseModule<M> {
+    runtime: M,
+}
+
+impl<M> UnpauseModule<M> {
+    pub fn new(runtime: M) -> Self {
+        UnpauseModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for UnpauseModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| set_paused(self.runtime.clone(), name.to_string(), false))
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Pauses or unpauses the module after checking that it is running or
+/// paused, so that doing either twice is a `409` rather than whatever the
+/// runtime makes of it.
+fn set_paused<M>(
+    runtime: M,
+    name: String,
+    pause: bool,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    let operation = move |name: &str| {
+        let name = name.to_string();
+        if pause {
+            RuntimeOperation::PauseModule(name)
+        } else {
+            RuntimeOperation::UnpauseModule(name)
+        }
+    };
+
+    runtime
+        .get(&name)
+        .then(move |result| -> Result<_, Error> {
+            let (_, state) =
+                result.with_context(|_| ErrorKind::RuntimeOperation(operation(&name)))?;
+            match (*state.status(), pause) {
+                (ModuleStatus::Paused, true) => {
+                    Err(Error::from(ErrorKind::ModuleAlreadyPaused(name)))
+                }
+                (ModuleStatus::Running, true) | (ModuleStatus::Paused, false) => {
+                    Ok((name, runtime))
+                }
+                (_, true) => Err(Error::from(ErrorKind::ModuleNotRunning(name))),
+                (_, false) => Err(Error::from(ErrorKind::ModuleNotPaused(name))),
+            }
+        })
+        .and_then(move |(name, runtime)| {
+            let paused = if pause {
+                Either::A(runtime.pause(&name))
+            } else {
+                Either::B(runtime.unpause(&name))
+            };
+            paused.then(move |result| -> Result<_, Error> {
+                result.with_context(|_| ErrorKind::RuntimeOperation(operation(&name)))?;
+                let response = Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::default())
+                    .context(ErrorKind::RuntimeOperation(operation(&name)))?;
+                Ok(response)
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle<H: Handler<Parameters>>(handler: &H, action: &str) -> Response<Body> {
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::post(format!("http://localhost/modules/mod1/{}", action))
+            .body(Body::default())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn running_module_is_paused() {
+        // arrange
+        let handler = PauseModule::new(runtime(ModuleStatus::Running));
+
+        // act
+        let response = handle(&handler, "pause");
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn paused_module_is_unpaused() {
+        // arrange
+        let handler = UnpauseModule::new(runtime(ModuleStatus::Paused));
+
+        // act
+        let response = handle(&handler, "unpause");
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn pausing_twice_is_a_conflict() {
+        // arrange
+        let handler = PauseModule::new(runtime(ModuleStatus::Paused));
+
+        // act
+        let response = handle(&handler, "pause");
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let error = error(response);
+        assert_eq!("Module mod1 is already paused", error.message());
+        assert_eq!(Some("ModuleAlreadyPaused"), error.code());
+    }
+
+    #[test]
+    fn stopped_module_is_not_paused() {
+        // arrange
+        let handler = PauseModule::new(runtime(ModuleStatus::Stopped));
+
+        // act
+        let response = handle(&handler, "pause");
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        assert_eq!(Some("ModuleNotRunning"), error(response).code());
+    }
+
+    #[test]
+    fn unpausing_a_running_module_is_a_conflict() {
+        // arrange
+        let handler = UnpauseModule::new(runtime(ModuleStatus::Running));
+
+        // act
+        let response = handle(&handler, "unpause");
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let error = error(response);
+        assert_eq!("Module mod1 is not paused", error.message());
+        assert_eq!(Some("ModuleNotPaused"), error.code());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = UnpauseModule::new(TestRuntime::new(Err(Error::General)));
+
+        // act
+        let response = handle(&handler, "unpause");
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not unpause module mod1\n\tcaused by: General error",
+            error(response).message()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Lists the ports a module exposes and where they are published on the
+/// host. A module that isn't running has no ports, rather than being an
+/// error.
+pub struct GetModulePorts<M> {
+    runtime: M,
+}
+
+impl<M> GetModulePorts<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModulePorts { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModulePorts<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime
+                    .get_ports(&name)
+                    .then(|ports| -> Result<_, Error> {
+                        let ports = ports.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModulePorts(
+                                name.clone(),
+                            ))
+                        })?;
+                        let b = serde_json::to_string(&ports).with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModulePorts(
+                                name.clone(),
+                            ))
+                        })?;
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .context(ErrorKind::RuntimeOperation(
+                                RuntimeOperation::GetModulePorts(name),
+                            ))?;
+                        Ok(response)
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(runtime: TestRuntime<Error>) -> Response<Body> {
+        let handler = GetModulePorts::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/ports")
+            .body(Body::default())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!([{
+                "container_port": 8080,
+                "protocol": "tcp",
+                "host_ip": "0.0.0.0",
+                "host_port": 8080,
+            }]),
+            body(response)
+        );
+    }
+
+    #[test]
+    fn stopped_module_has_no_ports() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Stopped);
+
+        // act
+        let response = handle(runtime);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(json!([]), body(response));
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not get ports of module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Async, Future, IntoFuture, Poll, Stream};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, PullProgress, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+#[serde(bound = "A: DeserializeOwned")]
+struct PrepareImageRequest<A> {
+    image: String,
+    #[serde(rename = "registryAuth", default)]
+    registry_auth: Option<A>,
+}
+
+/// Pulls the image a module is going to be created from, so that deploying
+/// the module later doesn't have to wait for the pull.
+///
+/// The response body has one JSON object per line, each a progress update of
+/// the pull, and ends once the image is pulled. The runtime can only fail the
+/// pull after the response has started, in which case the last line is an
+/// object with an `error` message instead.
+pub struct PrepareModuleImage<M> {
+    runtime: M,
+}
+
+impl<M> PrepareModuleImage<M> {
+    pub fn new(runtime: M) -> Self {
+        PrepareModuleImage { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for PrepareModuleImage<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::RegistryAuth: DeserializeOwned,
+    M::PullImageStream: 'static,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(|b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let request =
+                            serde_json::from_slice::<PrepareImageRequest<M::RegistryAuth>>(&b)
+                                .context(ErrorKind::MalformedRequestBody)?;
+                        if request.image.trim().is_empty() {
+                            return Err(Error::from(ErrorKind::MalformedRequestBody));
+                        }
+                        Ok(request)
+                    })
+                    .and_then(move |request| {
+                        runtime
+                            .pull_image(&request.image, request.registry_auth)
+                            .then(move |progress| -> Result<_, Error> {
+                                let progress = progress.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::PrepareModuleImage(name.clone()),
+                                    )
+                                })?;
+                                let body = Body::wrap_stream(
+                                    ProgressLines::new(progress, name.clone())
+                                        .map_err(Fail::compat),
+                                );
+                                let response = Response::builder()
+                                    .status(StatusCode::OK)
+                                    .header(CONTENT_TYPE, "application/x-ndjson")
+                                    .body(body)
+                                    .context(ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::PrepareModuleImage(name),
+                                    ))?;
+                                Ok(response)
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Writes each progress update as a line of JSON. An error of the pull is
+/// written as a last line, since the status of the response was already
+/// sent.
+struct ProgressLines<S> {
+    inner: S,
+    name: String,
+    done: bool,
+}
+
+impl<S> ProgressLines<S> {
+    fn new(inner: S, name: String) -> Self {
+        ProgressLines {
+            inner,
+            name,
+            done: false,
+        }
+    }
+
+    fn context(&self) -> ErrorKind {
+        ErrorKind::RuntimeOperation(RuntimeOperation::PrepareModuleImage(self.name.clone()))
+    }
+}
+
+#[derive(Serialize)]
+struct ProgressError {
+    error: String,
+}
+
+fn line<T: Serialize>(value: &T) -> Result<Chunk, serde_json::Error> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    Ok(Chunk::from(line))
+}
+
+impl<S> Stream for ProgressLines<S>
+where
+    S: Stream<Item = PullProgress>,
+    S::Error: Fail,
+{
+    type Item = Chunk;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        match self.inner.poll() {
+            Ok(Async::Ready(Some(progress))) => Ok(Async::Ready(Some(
+                line(&progress).with_context(|_| self.context())?,
+            ))),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                self.done = true;
+                let err = Error::from(err.context(self.context()));
+                let mut message = err.to_string();
+                for cause in Fail::iter_causes(&err) {
+                    message.push_str(&format!("\n\tcaused by: {}", cause));
+                }
+                let error = ProgressError { error: message };
+                Ok(Async::Ready(Some(
+                    line(&error).with_context(|_| self.context())?,
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use futures::stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn handle(runtime: TestRuntime<Error>, body: &str) -> Response<Body> {
+        let handler = PrepareModuleImage::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::post("http://localhost/modules/mod1/prepareimage")
+            .body(body.to_string().into())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn runtime() -> TestRuntime<Error> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::new(Ok(module))
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, r#"{"image":"mcr.microsoft.com/foo:1.0"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/x-ndjson", response.headers()[CONTENT_TYPE]);
+        let b = response.into_body().concat2().wait().unwrap();
+        let lines: Vec<Value> = b
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                json!({ "id": "layer1", "status": "Pulling fs layer" }),
+                json!({ "status": "Downloaded newer image for mcr.microsoft.com/foo:1.0" }),
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn missing_image() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, r#"{"image":" "}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, r#"{"image":"mcr.microsoft.com/foo:1.0"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not prepare image for module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn failed_pull_ends_with_error_line() {
+        let progress = stream::iter_result(vec![
+            Ok(PullProgress::new("Pulling fs layer".to_string())),
+            Err(Error::General),
+            Ok(PullProgress::new("never sent".to_string())),
+        ]);
+
+        let lines = ProgressLines::new(progress, "mod1".to_string())
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(2, lines.len());
+        let error: Value = serde_json::from_slice(&lines[1]).unwrap();
+        assert_eq!(
+            json!({ "error": "Could not prepare image for module mod1\n\tcaused by: General error" }),
+            error
+        );
+    }
+}
@@ -0,0 +1,427 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::Utc;
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::Deserialize;
+use serde_json;
+
+use edgelet_core::{
+    Module, ModuleEvent, ModuleEventType, ModuleRuntime, ModuleSpec as CoreModuleSpec,
+    ModuleStatus, RuntimeOperation,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::registry::RegistryValidator;
+use super::{core_to_spec, spec_to_details, SecretFilter};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::server::events::LocalEvents;
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+struct PromoteRequest {
+    new_name: String,
+}
+
+fn new_name(b: Result<Chunk, hyper::Error>, name: &str) -> Result<String, Error> {
+    let b = b.context(ErrorKind::MalformedRequestBody)?;
+    let request =
+        serde_json::from_slice::<PromoteRequest>(&b).context(ErrorKind::MalformedRequestBody)?;
+    if request.new_name.is_empty() || request.new_name == name {
+        return Err(Error::from(ErrorKind::MalformedRequestBody));
+    }
+    Ok(request.new_name)
+}
+
+/// Promotes a module, typically a canary, to a module of another name with
+/// the same spec, and responds with the details of the new module.
+///
+/// The source module is stopped, the new module is created from the spec
+/// the source was last created from and started, and the source module is
+/// removed. If a step fails, the new module is removed again and the source
+/// module is started if it was running, and the `500` response tells in
+/// `rolled_back` whether that worked. A successful promotion is reported to
+/// `GET /events` as a `promoted` event of the new module. A module whose
+/// image is from a registry that the registry validator doesn't allow gets
+/// `403 Forbidden` and is left as it is.
+pub struct PromoteModule<M> {
+    runtime: M,
+    secrets: SecretFilter,
+    registries: RegistryValidator,
+    events: LocalEvents,
+}
+
+impl<M> PromoteModule<M> {
+    pub fn new(runtime: M) -> Self {
+        PromoteModule {
+            runtime,
+            secrets: SecretFilter::default(),
+            registries: RegistryValidator::default(),
+            events: LocalEvents::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_registry_validator(mut self, registries: RegistryValidator) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    pub(crate) fn with_local_events(mut self, events: LocalEvents) -> Self {
+        self.events = events;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for PromoteModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+        let registries = self.registries.clone();
+        let events = self.events.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then({
+                        let name = name.clone();
+                        move |b| new_name(b, &name)
+                    })
+                    .and_then(move |new_name| {
+                        let get_name = name.clone();
+                        runtime.get(&name).join(runtime.get_config(&name)).then(
+                            move |result| -> Result<_, Error> {
+                                let ((_, state), spec) = result.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                                        get_name,
+                                    ))
+                                })?;
+                                let spec = spec
+                                    .ok_or_else(|| ErrorKind::ModuleNotFound(name.clone()))?
+                                    .with_name(new_name);
+                                let new_spec = core_to_spec(&spec).with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleConfig(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                registries.validate(&new_spec)?;
+                                let details = spec_to_details(&new_spec, ModuleStatus::Running);
+                                let was_running = *state.status() == ModuleStatus::Running;
+                                Ok((spec, details, name, was_running, runtime))
+                            },
+                        )
+                    })
+                    .and_then(move |(spec, details, name, was_running, runtime)| {
+                        let new_name = spec.name().to_string();
+                        info!("Promoting module {} to {}", name, new_name);
+
+                        promote(runtime, spec, name, was_running).and_then(
+                            move |()| -> Result<_, Error> {
+                                events.publish(&ModuleEvent::new(
+                                    ModuleEventType::Promoted,
+                                    new_name.clone(),
+                                    Utc::now(),
+                                ));
+
+                                let b = serde_json::to_string(&secrets.redact(&details))
+                                    .with_context(|_| {
+                                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                                            new_name.clone(),
+                                        ))
+                                    })?;
+                                let response = Response::builder()
+                                    .status(StatusCode::OK)
+                                    .header(CONTENT_TYPE, "application/json")
+                                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                                    .body(b.into())
+                                    .context(ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::GetModule(new_name),
+                                    ))?;
+                                Ok(response)
+                            },
+                        )
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Replaces the module `name` with one created from `spec`, rolling back if
+/// any step fails. Errors are wrapped in `ErrorKind::PromoteModule`.
+fn promote<M>(
+    runtime: M,
+    spec: CoreModuleSpec<<M::Module as Module>::Config>,
+    name: String,
+    was_running: bool,
+) -> impl Future<Item = (), Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    let new_name = spec.name().to_string();
+
+    // Docker refuses to stop a module that isn't running.
+    let stop = if was_running {
+        Either::A(runtime.stop(&name, None).map_err({
+            let name = name.clone();
+            move |err| {
+                let operation = RuntimeOperation::StopModule(name);
+                (
+                    Error::from(err.context(ErrorKind::RuntimeOperation(operation))),
+                    false,
+                )
+            }
+        }))
+    } else {
+        Either::B(future::ok(()))
+    };
+
+    stop.and_then({
+        let runtime = runtime.clone();
+        let new_name = new_name.clone();
+        move |()| {
+            runtime.create(spec).map_err(move |err| {
+                let operation = RuntimeOperation::CreateModule(new_name);
+                (
+                    Error::from(err.context(ErrorKind::RuntimeOperation(operation))),
+                    false,
+                )
+            })
+        }
+    })
+    .and_then({
+        let runtime = runtime.clone();
+        let new_name = new_name.clone();
+        move |()| {
+            runtime.start(&new_name).map_err(move |err| {
+                let operation = RuntimeOperation::StartModule(new_name);
+                (
+                    Error::from(err.context(ErrorKind::RuntimeOperation(operation))),
+                    true,
+                )
+            })
+        }
+    })
+    .and_then({
+        let runtime = runtime.clone();
+        let name = name.clone();
+        move |()| {
+            runtime.remove(&name).map_err(move |err| {
+                let operation = RuntimeOperation::RemoveModule(name);
+                (
+                    Error::from(err.context(ErrorKind::RuntimeOperation(operation))),
+                    true,
+                )
+            })
+        }
+    })
+    .or_else(move |(err, created)| {
+        warn!(
+            "Promotion of module {} to {} failed, rolling back: {}",
+            name,
+            new_name,
+            Fail::find_root_cause(&err)
+        );
+        rollback(runtime, &name, &new_name, created, was_running).then(
+            move |rolled_back| -> Result<(), Error> {
+                let rolled_back = rolled_back.unwrap_or(false);
+                Err(Error::from(
+                    err.context(ErrorKind::PromoteModule(name, rolled_back)),
+                ))
+            },
+        )
+    })
+}
+
+/// Removes the new module if it was `created` and starts the source module
+/// again if it `was_running`, and tells whether both worked.
+fn rollback<M>(
+    runtime: M,
+    name: &str,
+    new_name: &str,
+    created: bool,
+    was_running: bool,
+) -> impl Future<Item = bool, Error = ()>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    let remove = if created {
+        let new_name = new_name.to_string();
+        Either::A(runtime.remove(&new_name).then(move |result| {
+            if let Err(err) = &result {
+                warn!("Could not remove promoted module {}: {}", new_name, err);
+            }
+            Ok(result.is_ok())
+        }))
+    } else {
+        Either::B(future::ok(true))
+    };
+
+    let name = name.to_string();
+    remove.and_then(move |removed| {
+        let start = if was_running {
+            Either::A(runtime.start(&name).then(move |result| {
+                if let Err(err) = &result {
+                    warn!("Could not start module {} again: {}", name, err);
+                }
+                Ok(result.is_ok())
+            }))
+        } else {
+            Either::B(future::ok(true))
+        };
+        start.map(move |started| removed && started)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::{ErrorResponse, ModuleDetails};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1-canary".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(handler: &PromoteModule<TestRuntime<Error>>, body: &str) -> Response<Body> {
+        let request = Request::post("http://localhost/modules/mod1-canary/promote")
+            .body(body.to_string().into())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1-canary".to_string())]);
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body_json(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let events = LocalEvents::default();
+        let receiver = events.subscribe();
+        let handler = PromoteModule::new(runtime()).with_local_events(events);
+
+        // act
+        let response = handle(&handler, r#"{"new_name":"mod1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+        assert_eq!("mod1", details.name());
+        assert_eq!("microsoft/test-image", details.config().settings()["image"]);
+
+        drop(handler);
+        let events: Vec<_> = receiver.collect().wait().unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(ModuleEventType::Promoted, events[0].event_type());
+        assert_eq!("mod1", events[0].module_name());
+    }
+
+    #[test]
+    fn failed_step_is_rolled_back() {
+        // arrange
+        let events = LocalEvents::default();
+        let receiver = events.subscribe();
+        let handler = PromoteModule::new(runtime().with_create_error(Error::General))
+            .with_local_events(events);
+
+        // act
+        let response = handle(&handler, r#"{"new_name":"mod1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let error = body_json(response);
+        assert_eq!(Value::Bool(true), error["rolled_back"]);
+        assert_eq!(
+            "Could not promote module \"mod1-canary\"\n\tcaused by: Could not create module mod1\n\tcaused by: General error",
+            error["message"]
+        );
+
+        drop(handler);
+        assert!(receiver.collect().wait().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_rollback_is_reported() {
+        // arrange
+        let handler = PromoteModule::new(runtime().with_start_error(Error::General));
+
+        // act
+        let response = handle(&handler, r#"{"new_name":"mod1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let error = body_json(response);
+        assert_eq!(Value::Bool(false), error["rolled_back"]);
+    }
+
+    #[test]
+    fn same_name_is_bad_request() {
+        // arrange
+        let handler = PromoteModule::new(runtime());
+
+        // act
+        let response = handle(&handler, r#"{"new_name":"mod1-canary"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!("Request body is malformed", error.message());
+    }
+
+    #[test]
+    fn unknown_module_is_not_found() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("other".to_string(), config, Ok(state));
+        let handler = PromoteModule::new(TestRuntime::new(Ok(module)));
+
+        // act
+        let response = handle(&handler, r#"{"new_name":"mod1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use edgelet_core::AllowedRegistries;
+use management::models::ModuleSpec;
+
+use crate::error::{Error, ErrorKind};
+
+/// The registry of images that don't name one.
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Checks that modules are only created or updated with images of the
+/// allowed registries. Clones share the allowlist, so that it can be
+/// replaced while the management API runs, like when iotedged gets `SIGHUP`.
+#[derive(Clone, Debug, Default)]
+pub struct RegistryValidator {
+    allowed: Arc<Mutex<AllowedRegistries>>,
+}
+
+impl RegistryValidator {
+    pub fn new(allowed: AllowedRegistries) -> Self {
+        RegistryValidator {
+            allowed: Arc::new(Mutex::new(allowed)),
+        }
+    }
+
+    pub fn set(&self, allowed: AllowedRegistries) {
+        *self.allowed.lock().unwrap() = allowed;
+    }
+
+    /// A spec without an image is left to the other validations.
+    pub fn validate(&self, spec: &ModuleSpec) -> Result<(), Error> {
+        let image = spec
+            .config()
+            .settings()
+            .get("image")
+            .and_then(Value::as_str);
+        match image {
+            Some(image) => {
+                let registry = image_registry(image);
+                // Images from Docker Hub may leave out its host, which the
+                // allowlist matches the images by.
+                let named = image.starts_with(registry) && image[registry.len()..].starts_with('/');
+                let reference = if named {
+                    image.to_string()
+                } else {
+                    format!("{}/{}", registry, image)
+                };
+                if self.allowed.lock().unwrap().allows(&reference) {
+                    Ok(())
+                } else {
+                    Err(Error::from(ErrorKind::RegistryNotAllowed(
+                        registry.to_string(),
+                    )))
+                }
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// The registry host of `image`. As with Docker, the first component of the
+/// image name is only a registry if it looks like a host, that is if it has
+/// a `.` or a `:` or is `localhost`.
+fn image_registry(image: &str) -> &str {
+    match image.find('/') {
+        Some(index) => {
+            let host = &image[..index];
+            if host.contains(|c| c == '.' || c == ':') || host == "localhost" {
+                host
+            } else {
+                DEFAULT_REGISTRY
+            }
+        }
+        None => DEFAULT_REGISTRY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use management::models::Config;
+    use serde_json::json;
+
+    use super::*;
+
+    fn spec(image: &str) -> ModuleSpec {
+        let config = Config::new(json!({ "image": image }));
+        ModuleSpec::new("mod1".to_string(), "docker".to_string(), config)
+    }
+
+    #[test]
+    fn image_registry_parses() {
+        assert_eq!("docker.io", image_registry("alpine"));
+        assert_eq!("docker.io", image_registry("library/alpine:3.9"));
+        assert_eq!(
+            "mcr.microsoft.com",
+            image_registry("mcr.microsoft.com/azureiotedge-agent:1.0")
+        );
+        assert_eq!(
+            "localhost:5000",
+            image_registry("localhost:5000/test-image")
+        );
+        assert_eq!("localhost", image_registry("localhost/test-image"));
+    }
+
+    #[test]
+    fn empty_allowlist_allows_every_registry() {
+        let validator = RegistryValidator::default();
+
+        assert!(validator
+            .validate(&spec("contoso.azurecr.io/test-image"))
+            .is_ok());
+    }
+
+    #[test]
+    fn registry_not_in_allowlist_is_rejected() {
+        let validator =
+            RegistryValidator::new(AllowedRegistries::new(
+                vec!["mcr.microsoft.com".to_string()],
+            ));
+
+        assert!(validator
+            .validate(&spec("mcr.microsoft.com/test-image"))
+            .is_ok());
+        let err = validator
+            .validate(&spec("contoso.azurecr.io/test-image"))
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::RegistryNotAllowed(registry) => assert_eq!("contoso.azurecr.io", registry),
+            kind => panic!("Expected `RegistryNotAllowed` error but got {:?}.", kind),
+        }
+    }
+
+    #[test]
+    fn look_alike_registry_is_rejected() {
+        let validator =
+            RegistryValidator::new(AllowedRegistries::new(
+                vec!["mcr.microsoft.com".to_string()],
+            ));
+
+        for host in &["mcr.microsoft.com.evil.io", "mcr.microsoft.comx.io"] {
+            let err = validator
+                .validate(&spec(&format!("{}/x", host)))
+                .unwrap_err();
+            match err.kind() {
+                ErrorKind::RegistryNotAllowed(registry) => assert_eq!(host, registry),
+                kind => panic!("Expected `RegistryNotAllowed` error but got {:?}.", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn images_without_registry_are_from_docker_hub() {
+        let validator =
+            RegistryValidator::new(AllowedRegistries::new(vec!["docker.io".to_string()]));
+
+        assert!(validator.validate(&spec("alpine")).is_ok());
+        assert!(validator.validate(&spec("library/alpine:3.9")).is_ok());
+        assert!(validator
+            .validate(&spec("docker.io.evil.io/alpine"))
+            .is_err());
+    }
+
+    #[test]
+    fn clones_share_the_allowlist() {
+        let validator = RegistryValidator::default();
+        let clone = validator.clone();
+
+        clone.set(AllowedRegistries::new(
+            vec!["mcr.microsoft.com".to_string()],
+        ));
+
+        assert!(validator.validate(&spec("alpine")).is_err());
+    }
+}
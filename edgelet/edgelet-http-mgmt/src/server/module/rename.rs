@@ -0,0 +1,413 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::Utc;
+use failure::{Fail, ResultExt};
+use futures::future::Either;
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use log::{info, warn};
+use serde_derive::Deserialize;
+use serde_json;
+
+use edgelet_core::{
+    Module, ModuleConfigStore, ModuleEvent, ModuleEventType, ModuleRuntime, RuntimeOperation,
+};
+use edgelet_http::authorization::AuthorizedCaller;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::{ModuleHistory, ModuleNameValidator};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::server::events::LocalEvents;
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+struct RenameRequest {
+    new_name: String,
+}
+
+fn new_name(b: Result<Chunk, hyper::Error>, name: &str) -> Result<String, Error> {
+    let b = b.context(ErrorKind::MalformedRequestBody)?;
+    let request =
+        serde_json::from_slice::<RenameRequest>(&b).context(ErrorKind::MalformedRequestBody)?;
+    if request.new_name.is_empty() || request.new_name == name {
+        return Err(Error::from(ErrorKind::MalformedRequestBody));
+    }
+    Ok(request.new_name)
+}
+
+/// Renames a module to the `new_name` of the body, so unlike deleting and
+/// creating it again the module keeps running and keeps its history. A
+/// module of the new name gets a `409` response, and the modules of the
+/// reserved names, like edgeAgent and edgeHub, can't be renamed at all.
+///
+/// The spec of the module in the config store and its update history move
+/// to the new name, and the rename is reported to `GET /events` as a
+/// `renamed` event of the new name with the old one in its `old_name`. If the
+/// spec can't be moved, the container gets its old name back and the request
+/// fails, so that the store never has the spec under a name no module has.
+pub struct RenameModule<M, S> {
+    runtime: M,
+    store: S,
+    history: ModuleHistory,
+    names: ModuleNameValidator,
+    events: LocalEvents,
+}
+
+impl<M, S> RenameModule<M, S> {
+    pub fn new(runtime: M, store: S, history: ModuleHistory) -> Self {
+        RenameModule {
+            runtime,
+            store,
+            history,
+            names: ModuleNameValidator::default(),
+            events: LocalEvents::default(),
+        }
+    }
+
+    pub fn with_name_validator(mut self, names: ModuleNameValidator) -> Self {
+        self.names = names;
+        self
+    }
+
+    pub(crate) fn with_local_events(mut self, events: LocalEvents) -> Self {
+        self.events = events;
+        self
+    }
+}
+
+impl<M, S> Handler<Parameters> for RenameModule<M, S>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    S: 'static + ModuleConfigStore + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let caller = req.extensions().get::<AuthorizedCaller>().cloned();
+        let runtime = self.runtime.clone();
+        let store = self.store.clone();
+        let history = self.history.clone();
+        let names = self.names.clone();
+        let events = self.events.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+                let operation = {
+                    let name = name.clone();
+                    move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::RenameModule(name.clone()))
+                    }
+                };
+
+                req.into_body()
+                    .concat2()
+                    .then({
+                        let name = name.clone();
+                        move |b| -> Result<_, Error> {
+                            if names.is_reserved(&name) {
+                                return Err(Error::from(ErrorKind::ReservedModuleName(name)));
+                            }
+                            let new_name = new_name(b, &name)?;
+                            names.validate_new(&new_name, caller.as_ref())?;
+                            Ok(new_name)
+                        }
+                    })
+                    .and_then({
+                        let operation = operation.clone();
+                        move |new_name| {
+                            runtime.list().then(move |modules| -> Result<_, Error> {
+                                let modules = modules.with_context(|_| operation())?;
+                                if modules.iter().any(|module| module.name() == new_name) {
+                                    return Err(Error::from(ErrorKind::ModuleAlreadyExists(
+                                        new_name,
+                                    )));
+                                }
+                                if !modules.iter().any(|module| module.name() == name) {
+                                    return Err(Error::from(ErrorKind::ModuleNotFound(name)));
+                                }
+                                Ok((name, new_name, runtime))
+                            })
+                        }
+                    })
+                    .and_then(move |(name, new_name, runtime)| {
+                        info!("Renaming module {} to {}", name, new_name);
+                        runtime.rename(&name, &new_name).then(move |result| {
+                            if let Err(err) = result {
+                                let err = Error::from(err.context(operation()));
+                                return Either::A(Err(err).into_future());
+                            }
+
+                            if let Err(err) = store.rename(&name, &new_name) {
+                                warn!(
+                                    "Could not move the config of module {} to {}: {}",
+                                    name, new_name, err
+                                );
+                                return Either::B(runtime.rename(&new_name, &name).then(
+                                    move |rollback| {
+                                        if let Err(rollback) = rollback {
+                                            warn!(
+                                                "Could not rename module {} back to {}: {}",
+                                                new_name, name, rollback
+                                            );
+                                        }
+                                        Err(Error::from(err.context(operation())))
+                                    },
+                                ));
+                            }
+                            history.rename(&name, &new_name);
+                            events.publish(
+                                &ModuleEvent::new(
+                                    ModuleEventType::Renamed,
+                                    new_name.clone(),
+                                    Utc::now(),
+                                )
+                                .with_old_name(name.clone()),
+                            );
+
+                            Either::A(
+                                Response::builder()
+                                    .status(StatusCode::NO_CONTENT)
+                                    .body(Body::default())
+                                    .with_context(|_| operation())
+                                    .map_err(Error::from)
+                                    .into_future(),
+                            )
+                        })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use edgelet_core::{
+        Error as CoreError, ErrorKind as CoreErrorKind, InMemoryConfigStore, ModuleRuntimeState,
+        ModuleSpec,
+    };
+    use edgelet_test_utils::module::*;
+    use management::models::{Config, ErrorResponse, ModuleSpec as ManagementSpec};
+    use serde_json::json;
+
+    use super::super::history::ModuleHistoryEntry;
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::new(Ok(module))
+    }
+
+    fn store() -> InMemoryConfigStore<TestConfig> {
+        let store = InMemoryConfigStore::new();
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let spec = ModuleSpec::new(
+            "mod1".to_string(),
+            "test".to_string(),
+            config,
+            HashMap::new(),
+        )
+        .unwrap();
+        store.set("mod1", spec).unwrap();
+        store
+    }
+
+    #[derive(Clone)]
+    struct FailingStore;
+
+    impl ModuleConfigStore for FailingStore {
+        type Config = TestConfig;
+
+        fn get(&self, _name: &str) -> Option<ModuleSpec<TestConfig>> {
+            None
+        }
+
+        fn set(&self, _name: &str, _spec: ModuleSpec<TestConfig>) -> Result<(), CoreError> {
+            Ok(())
+        }
+
+        fn delete(&self, _name: &str) -> Result<(), CoreError> {
+            Ok(())
+        }
+
+        fn rename(&self, _old_name: &str, _new_name: &str) -> Result<(), CoreError> {
+            Err(CoreError::from(CoreErrorKind::ModuleConfigStore(
+                "specs.json".to_string(),
+            )))
+        }
+
+        fn clear(&self) -> Result<(), CoreError> {
+            Ok(())
+        }
+    }
+
+    fn handle<H: Handler<Parameters>>(handler: &H, name: &str, body: &str) -> Response<Body> {
+        let request = Request::post(format!("http://localhost/modules/{}/rename", name))
+            .body(body.to_string().into())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), name.to_string())]);
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error_code(response: Response<Body>) -> Option<String> {
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        error.code().map(ToString::to_string)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime();
+        let store = store();
+        let history = ModuleHistory::new();
+        let spec = ManagementSpec::new(
+            "mod1".to_string(),
+            "docker".to_string(),
+            Config::new(json!({ "image": "microsoft/test-image" })),
+        );
+        history.record("mod1", ModuleHistoryEntry::new(Utc::now(), &spec));
+        let events = LocalEvents::default();
+        let receiver = events.subscribe();
+        let handler = RenameModule::new(runtime.clone(), store.clone(), history.clone())
+            .with_local_events(events);
+
+        // act
+        let response = handle(&handler, "mod1", r#"{"new_name":"mod2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(
+            vec![("mod1".to_string(), "mod2".to_string())],
+            runtime.renames()
+        );
+        assert!(store.get("mod1").is_none());
+        assert_eq!("mod2", store.get("mod2").unwrap().name());
+        assert!(history.ancestry("mod1").is_empty());
+        assert_eq!(1, history.ancestry("mod2").len());
+
+        drop(handler);
+        let events: Vec<_> = receiver.collect().wait().unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(ModuleEventType::Renamed, events[0].event_type());
+        assert_eq!("mod2", events[0].module_name());
+        assert_eq!(Some("mod1"), events[0].old_name());
+    }
+
+    #[test]
+    fn existing_name_is_a_conflict() {
+        // arrange
+        let runtime = runtime();
+        let store = store();
+        let handler = RenameModule::new(runtime.clone(), store.clone(), ModuleHistory::new());
+
+        // act
+        let response = handle(&handler, "mod2", r#"{"new_name":"mod1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        assert_eq!(
+            Some("ModuleAlreadyExists".to_string()),
+            error_code(response)
+        );
+        assert!(runtime.renames().is_empty());
+        assert!(store.get("mod1").is_some());
+    }
+
+    #[test]
+    fn missing_module_is_not_found() {
+        // arrange
+        let runtime = runtime();
+        let handler = RenameModule::new(runtime.clone(), store(), ModuleHistory::new());
+
+        // act
+        let response = handle(&handler, "mod2", r#"{"new_name":"mod3"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert!(runtime.renames().is_empty());
+    }
+
+    #[test]
+    fn invalid_new_name_is_bad_request() {
+        // arrange
+        let runtime = runtime();
+        let handler = RenameModule::new(runtime.clone(), store(), ModuleHistory::new());
+
+        // act
+        let same = handle(&handler, "mod1", r#"{"new_name":"mod1"}"#);
+        let invalid = handle(&handler, "mod1", r#"{"new_name":"mod 2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, same.status());
+        assert_eq!(StatusCode::BAD_REQUEST, invalid.status());
+        assert!(runtime.renames().is_empty());
+    }
+
+    #[test]
+    fn reserved_module_is_not_renamed() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "edgeHub".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::<Error>::new(Ok(module));
+        let handler = RenameModule::new(runtime.clone(), store(), ModuleHistory::new());
+
+        // act
+        let response = handle(&handler, "edgeHub", r#"{"new_name":"mod2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("ReservedModuleName".to_string()), error_code(response));
+        assert!(runtime.renames().is_empty());
+    }
+
+    #[test]
+    fn failed_config_move_renames_the_module_back() {
+        // arrange
+        let runtime = runtime();
+        let events = LocalEvents::default();
+        let receiver = events.subscribe();
+        let handler = RenameModule::new(runtime.clone(), FailingStore, ModuleHistory::new())
+            .with_local_events(events);
+
+        // act
+        let response = handle(&handler, "mod1", r#"{"new_name":"mod2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            vec![
+                ("mod1".to_string(), "mod2".to_string()),
+                ("mod2".to_string(), "mod1".to_string()),
+            ],
+            runtime.renames()
+        );
+        drop(handler);
+        let events: Vec<_> = receiver.collect().wait().unwrap();
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{
+    ModuleRuntime, ModuleStatus, ResourceLimits as CoreResourceLimits, RuntimeOperation,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ResourceLimits;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Changes the CPU and memory limits of a running module without restarting
+/// it. Limits left out of the request are unchanged.
+///
+/// The limits only last until the module is next created from its spec, and
+/// a memory limit above the memory the host has available is refused.
+pub struct PatchModuleResources<M> {
+    runtime: M,
+}
+
+impl<M> PatchModuleResources<M> {
+    pub fn new(runtime: M) -> Self {
+        PatchModuleResources { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for PatchModuleResources<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+                let operation = {
+                    let name = name.clone();
+                    move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::UpdateModuleResources(
+                            name.clone(),
+                        ))
+                    }
+                };
+
+                req.into_body()
+                    .concat2()
+                    .then({
+                        let name = name.clone();
+                        move |b| -> Result<_, Error> {
+                            let b = b.context(ErrorKind::MalformedRequestBody)?;
+                            let limits = serde_json::from_slice::<ResourceLimits>(&b)
+                                .context(ErrorKind::MalformedRequestBody)?;
+                            let limits = CoreResourceLimits::new(
+                                limits.cpu_shares(),
+                                limits.memory_limit_bytes(),
+                                limits.memory_swap_bytes(),
+                            )
+                            .with_context(|_| ErrorKind::InvalidResourceLimits(name))?;
+                            Ok(limits)
+                        }
+                    })
+                    .and_then({
+                        let operation = operation.clone();
+                        move |limits| {
+                            runtime.get(&name).then(move |result| -> Result<_, Error> {
+                                let (_, state) = result.with_context(|_| operation())?;
+                                if *state.status() != ModuleStatus::Running {
+                                    return Err(Error::from(ErrorKind::ModuleNotRunning(name)));
+                                }
+                                Ok((name, limits, runtime))
+                            })
+                        }
+                    })
+                    .and_then(|(name, limits, runtime)| {
+                        runtime
+                            .system_resources()
+                            .then(move |result| -> Result<_, Error> {
+                                let resources = result.context(ErrorKind::RuntimeOperation(
+                                    RuntimeOperation::SystemResources,
+                                ))?;
+                                check_host_resources(
+                                    &name,
+                                    &limits,
+                                    resources.available_memory_bytes(),
+                                )?;
+                                Ok((name, limits, runtime))
+                            })
+                    })
+                    .and_then(move |(name, limits, runtime)| {
+                        runtime.update_resources(&name, limits).then(
+                            move |result| -> Result<_, Error> {
+                                result.with_context(|_| operation())?;
+                                let response = Response::builder()
+                                    .status(StatusCode::NO_CONTENT)
+                                    .body(Body::default())
+                                    .with_context(|_| operation())?;
+                                Ok(response)
+                            },
+                        )
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// CPU shares are a weight relative to other modules rather than an amount,
+/// so only the memory limit can exceed what the host has.
+fn check_host_resources(
+    name: &str,
+    limits: &CoreResourceLimits,
+    available_memory_bytes: u64,
+) -> Result<(), Error> {
+    match limits.memory_limit_bytes() {
+        Some(memory) if memory > available_memory_bytes => Err(Error::from(
+            ErrorKind::ResourceLimitsExceedHost(name.to_string(), memory, available_memory_bytes),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(runtime: TestRuntime<Error>, body: &'static str) -> Response<Body> {
+        let handler = PatchModuleResources::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::patch("http://localhost/modules/mod1/resources")
+            .body(body.into())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body_error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"cpu_shares":512,"memory_limit_bytes":1024}"#);
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn stopped_module_conflicts() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Stopped);
+
+        // act
+        let response = handle(runtime, r#"{"cpu_shares":512}"#);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        assert_eq!(Some("ModuleNotRunning"), body_error(response).code());
+    }
+
+    #[test]
+    fn memory_beyond_host_is_unprocessable() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"memory_limit_bytes":2048}"#);
+
+        // assert
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+        let error = body_error(response);
+        assert_eq!(Some("ResourceLimitsExceedHost"), error.code());
+        assert_eq!(
+            "Memory limit of 2048 bytes for module \"mod1\" exceeds the 1024 bytes available on the host",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn invalid_limits_are_unprocessable() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(
+            runtime,
+            r#"{"memory_limit_bytes":512,"memory_swap_bytes":256}"#,
+        );
+
+        // assert
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+        assert_eq!(Some("InvalidResourceLimits"), body_error(response).code());
+    }
+
+    #[test]
+    fn bad_body() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"cpu_shares":-1}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, r#"{"cpu_shares":512}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not update resource limits of module mod1\n\tcaused by: General error",
+            body_error(response).message()
+        );
+    }
+}
@@ -1,13 +1,18 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::time::Duration;
+
 use failure::{Fail, ResultExt};
+use futures::future::Either;
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
+use url::form_urlencoded;
 
 use edgelet_core::{ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -23,20 +28,42 @@ impl<M> RestartModule<M> {
 
 impl<M> Handler<Parameters> for RestartModule<M>
 where
-    M: 'static + ModuleRuntime + Send,
+    M: 'static + ModuleRuntime + Clone + Send,
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
-            .map(|name| {
-                let name = name.to_string();
-
-                self.runtime.restart(&name).then(|result| match result {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = req
+            .uri()
+            .query()
+            .map_or_else(|| Ok(None), parse_grace_period)
+            .and_then(|grace_period| {
+                params
+                    .name("name")
+                    .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+                    .map(|name| (name.to_string(), grace_period))
+            })
+            .map(move |(name, grace_period)| {
+                // With a grace period the module is stopped and started again
+                // so that it gets that long to exit before it is killed.
+                let restart = match grace_period {
+                    Some(grace_period) => {
+                        let start_name = name.clone();
+                        Either::A(
+                            runtime
+                                .stop(&name, Some(grace_period))
+                                .and_then(move |_| runtime.start(&start_name)),
+                        )
+                    }
+                    None => Either::B(runtime.restart(&name)),
+                };
+
+                restart.then(|result| match result {
                     Ok(_) => Ok(name),
                     Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
                         RuntimeOperation::RestartModule(name),
@@ -53,18 +80,32 @@ where
                         RuntimeOperation::RestartModule(name),
                     ))?)
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
 }
 
+fn parse_grace_period(query: &str) -> Result<Option<Duration>, Error> {
+    match form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "grace_period_secs") {
+        Some((_, val)) => {
+            let secs = val
+                .parse::<u64>()
+                .context(ErrorKind::MalformedRequestParameter("grace_period_secs"))?;
+            Ok(Some(Duration::from_secs(secs)))
+        }
+        None => Ok(None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
     use edgelet_core::{ModuleRuntimeState, ModuleStatus};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
 
     use super::*;
     use crate::server::module::tests::Error;
@@ -97,6 +138,71 @@ mod tests {
         assert_eq!(StatusCode::NO_CONTENT, response.status());
     }
 
+    #[test]
+    fn success_with_grace_period() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let handler = RestartModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::post("http://localhost/modules/test/restart?grace_period_secs=10")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn grace_period_parses() {
+        assert_eq!(None, parse_grace_period("").unwrap());
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            parse_grace_period("grace_period_secs=30").unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_grace_period_is_bad_request() {
+        for value in &["-1", "1.5", "abc", ""] {
+            // arrange
+            let runtime = TestRuntime::new(Err(Error::General));
+            let handler = RestartModule::new(runtime);
+            let parameters =
+                Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+            let uri = format!(
+                "http://localhost/modules/test/restart?grace_period_secs={}",
+                value
+            );
+            let request = Request::post(uri.as_str()).body(Body::default()).unwrap();
+
+            // act
+            let response = handler.handle(request, parameters).wait().unwrap();
+
+            // assert
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+            response
+                .into_body()
+                .concat2()
+                .and_then(|b| {
+                    let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                    assert!(error
+                        .message()
+                        .starts_with("The request parameter `grace_period_secs` is malformed"));
+                    Ok(())
+                })
+                .wait()
+                .unwrap();
+        }
+    }
+
     #[test]
     fn restart_bad_params() {
         // arrange
@@ -0,0 +1,285 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::Serialize;
+use serde_json;
+
+use edgelet_core::{Module, ModuleEvent, ModuleEventType, ModuleRuntime};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleSpec;
+
+use super::history::{ModuleHistory, ModuleHistoryEntry};
+use super::registry::RegistryValidator;
+use super::update::{parse_start, update};
+use super::{spec_to_core, SecretFilter};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::server::events::LocalEvents;
+use crate::IntoResponse;
+
+#[derive(Serialize)]
+struct RollbackResponse<'a> {
+    rolled_back_to: RollbackTarget<'a>,
+}
+
+#[derive(Serialize)]
+struct RollbackTarget<'a> {
+    image: &'a str,
+    timestamp: &'a DateTime<Utc>,
+}
+
+/// Updates a module back to the spec of its update before the last one, as
+/// recorded in its history, and responds with the image and time of that
+/// update.
+///
+/// The rollback goes through the same update as `PUT /modules/{name}`,
+/// including the `start` parameter, and is recorded in the history like any
+/// other update, so rolling back again undoes the rollback. A module with
+/// fewer than two updates has nothing to roll back to and gets
+/// `409 Conflict`, and one whose earlier image is from a registry that the
+/// registry validator no longer allows gets `403 Forbidden`. A successful
+/// rollback is reported to `GET /events` as a `rolled_back` event.
+pub struct RollbackModule<M> {
+    runtime: M,
+    history: ModuleHistory,
+    secrets: SecretFilter,
+    registries: RegistryValidator,
+    events: LocalEvents,
+}
+
+impl<M> RollbackModule<M> {
+    pub fn new(runtime: M, history: ModuleHistory) -> Self {
+        RollbackModule {
+            runtime,
+            history,
+            secrets: SecretFilter::default(),
+            registries: RegistryValidator::default(),
+            events: LocalEvents::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_registry_validator(mut self, registries: RegistryValidator) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    pub(crate) fn with_local_events(mut self, events: LocalEvents) -> Self {
+        self.events = events;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for RollbackModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let history = self.history.clone();
+        let secrets = self.secrets.clone();
+        let registries = self.registries.clone();
+        let events = self.events.clone();
+        let start = parse_start(req.uri().query());
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| -> Result<_, Error> {
+                let name = name.to_string();
+                let target = history
+                    .previous(&name)
+                    .ok_or_else(|| ErrorKind::NoRollbackTarget(name.clone()))?;
+                let spec: ModuleSpec = serde_json::from_value(target.spec().clone())
+                    .with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                registries.validate(&spec)?;
+                let core_spec = spec_to_core::<M>(&spec, ErrorKind::UpdateModule(name.clone()))?
+                    .with_name(name.clone());
+                Ok((name, target, spec, core_spec))
+            })
+            .into_future()
+            .and_then(move |(name, target, spec, core_spec)| {
+                info!(
+                    "Rolling back module {} to its update of {}",
+                    name,
+                    target.timestamp()
+                );
+                let entry = ModuleHistoryEntry::new(Utc::now(), &spec);
+
+                update(runtime, core_spec, spec, start, secrets).and_then(
+                    move |_| -> Result<_, Error> {
+                        history.record(&name, entry);
+                        events.publish(&ModuleEvent::new(
+                            ModuleEventType::RolledBack,
+                            name.clone(),
+                            Utc::now(),
+                        ));
+
+                        let body = RollbackResponse {
+                            rolled_back_to: RollbackTarget {
+                                image: target.image(),
+                                timestamp: target.timestamp(),
+                            },
+                        };
+                        let b = serde_json::to_string(&body)
+                            .with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .context(ErrorKind::UpdateModule(name))?;
+                        Ok(response)
+                    },
+                )
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::{Config, ErrorResponse};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image:2".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn record(history: &ModuleHistory, minute: u32, image: &str) {
+        let spec = ModuleSpec::new(
+            "mod1".to_string(),
+            "docker".to_string(),
+            Config::new(json!({ "image": image })),
+        );
+        let timestamp = Utc.ymd(2019, 1, 30).and_hms(12, minute, 0);
+        history.record("mod1", ModuleHistoryEntry::new(timestamp, &spec));
+    }
+
+    fn handle(handler: &RollbackModule<TestRuntime<Error>>) -> Response<Body> {
+        let request = Request::post("http://localhost/modules/mod1/rollback")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let history = ModuleHistory::new();
+        record(&history, 0, "microsoft/test-image:1");
+        record(&history, 1, "microsoft/test-image:2");
+        let events = LocalEvents::default();
+        let receiver = events.subscribe();
+        let handler = RollbackModule::new(runtime(), history).with_local_events(events);
+
+        // act
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let body: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!({
+                "rolled_back_to": {
+                    "image": "microsoft/test-image:1",
+                    "timestamp": "2019-01-30T12:00:00Z",
+                },
+            }),
+            body
+        );
+
+        drop(handler);
+        let events: Vec<_> = receiver.collect().wait().unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(ModuleEventType::RolledBack, events[0].event_type());
+        assert_eq!("mod1", events[0].module_name());
+    }
+
+    #[test]
+    fn rolling_back_again_undoes_the_rollback() {
+        // arrange
+        let history = ModuleHistory::new();
+        record(&history, 0, "microsoft/test-image:1");
+        record(&history, 1, "microsoft/test-image:2");
+        let handler = RollbackModule::new(runtime(), history.clone());
+
+        // act
+        handle(&handler);
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let body: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!("microsoft/test-image:2", body["rolled_back_to"]["image"]);
+        assert_eq!(4, history.ancestry("mod1").len());
+    }
+
+    #[test]
+    fn no_earlier_update_is_conflict() {
+        // arrange
+        let history = ModuleHistory::new();
+        record(&history, 0, "microsoft/test-image:1");
+        let handler = RollbackModule::new(runtime(), history.clone());
+
+        // act
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("NoRollbackTarget"), error.code());
+        assert_eq!(1, history.ancestry("mod1").len());
+    }
+
+    #[test]
+    fn runtime_error_is_not_recorded() {
+        // arrange
+        let history = ModuleHistory::new();
+        record(&history, 0, "microsoft/test-image:1");
+        record(&history, 1, "microsoft/test-image:2");
+        let handler = RollbackModule::new(TestRuntime::new(Err(Error::General)), history.clone());
+
+        // act
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(2, history.ancestry("mod1").len());
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::ser::{Error as SerError, Serialize, Serializer};
+use serde_json::{self, Value};
+
+/// Environment variables whose keys match one of these patterns are
+/// redacted by default.
+pub const DEFAULT_SECRET_PATTERNS: &[&str] = &[
+    "*KEY",
+    "*SECRET",
+    "*PASSWORD",
+    "*TOKEN",
+    "*CONNECTIONSTRING",
+];
+
+const REDACTED: &str = "***";
+
+/// Redacts the values of environment variables that look like credentials
+/// from API responses.
+///
+/// Keys are matched against glob patterns, where `*` matches any number of
+/// characters and `?` a single one, ignoring case. The filter only applies
+/// where responses are serialized, so the stored module specs keep their
+/// values.
+#[derive(Clone, Debug)]
+pub struct SecretFilter {
+    patterns: Arc<Vec<String>>,
+}
+
+impl SecretFilter {
+    pub fn new(patterns: Vec<String>) -> Self {
+        SecretFilter {
+            patterns: Arc::new(patterns),
+        }
+    }
+
+    pub fn is_secret(&self, key: &str) -> bool {
+        let key = key.to_uppercase();
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(&pattern.to_uppercase(), &key))
+    }
+
+    /// Wraps `value` so that it serializes with the values of secret
+    /// environment variables redacted.
+    ///
+    /// Environment variables are recognized as the `env` list of `{key,
+    /// value}` objects in module specs, and as the `Env` list of `KEY=VALUE`
+    /// strings in docker create options and inspect output, wherever they are
+    /// nested in `value`.
+    pub fn redact<'a, T>(&'a self, value: &'a T) -> Redacted<'a, T> {
+        Redacted {
+            filter: self,
+            value,
+        }
+    }
+
+    /// The environment of a module, sorted by key, with secret values
+    /// redacted.
+    pub fn redact_env(&self, env: HashMap<String, String>) -> BTreeMap<String, String> {
+        env.into_iter()
+            .map(|(key, value)| {
+                if self.is_secret(&key) {
+                    (key, REDACTED.to_string())
+                } else {
+                    (key, value)
+                }
+            })
+            .collect()
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::Object(members) => {
+                for (name, member) in members {
+                    match (name.as_str(), member) {
+                        ("env", Value::Array(vars)) => {
+                            for var in vars {
+                                let secret = var
+                                    .get("key")
+                                    .and_then(Value::as_str)
+                                    .map_or(false, |key| self.is_secret(key));
+                                if let (true, Some(Value::String(value))) =
+                                    (secret, var.get_mut("value"))
+                                {
+                                    *value = REDACTED.to_string();
+                                }
+                            }
+                        }
+                        ("Env", Value::Array(vars)) => {
+                            for var in vars {
+                                if let Value::String(var) = var {
+                                    let key_len = var.find('=').unwrap_or_else(|| var.len());
+                                    if key_len < var.len() && self.is_secret(&var[..key_len]) {
+                                        var.truncate(key_len + 1);
+                                        var.push_str(REDACTED);
+                                    }
+                                }
+                            }
+                        }
+                        (_, member) => self.redact_value(member),
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    self.redact_value(value);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Default for SecretFilter {
+    fn default() -> Self {
+        SecretFilter::new(
+            DEFAULT_SECRET_PATTERNS
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        )
+    }
+}
+
+/// A value that serializes with its secrets redacted. See
+/// [`SecretFilter::redact`].
+pub struct Redacted<'a, T> {
+    filter: &'a SecretFilter,
+    value: &'a T,
+}
+
+impl<'a, T> Serialize for Redacted<'a, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut value = serde_json::to_value(self.value).map_err(S::Error::custom)?;
+        self.filter.redact_value(&mut value);
+        value.serialize(serializer)
+    }
+}
+
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    // The position after the last `*`, and the position in `value` it was
+    // matched up to, to backtrack to when the rest of the pattern fails.
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut v) = (0, 0);
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match star {
+                Some((star_p, star_v)) => {
+                    star = Some((star_p, star_v + 1));
+                    p = star_p;
+                    v = star_v + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn default_patterns() {
+        let filter = SecretFilter::default();
+
+        for key in &[
+            "API_KEY",
+            "SharedAccessKey",
+            "key",
+            "CLIENT_SECRET",
+            "DB_PASSWORD",
+            "GITHUB_TOKEN",
+            "IotHubConnectionString",
+        ] {
+            assert!(filter.is_secret(key), "{} should be a secret", key);
+        }
+
+        for key in &["KEYS", "TOKEN_URL", "PATH", "RUST_LOG", ""] {
+            assert!(!filter.is_secret(key), "{} should not be a secret", key);
+        }
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("A*B", "AB"));
+        assert!(glob_match("A*B", "AXYB"));
+        assert!(glob_match("A?C", "ABC"));
+        assert!(glob_match("*AB*", "XABY"));
+        assert!(glob_match("*ABC", "ABABC"));
+        assert!(!glob_match("A?C", "AC"));
+        assert!(!glob_match("A*B", "AXBY"));
+        assert!(!glob_match("ABC", "ABCD"));
+    }
+
+    #[test]
+    fn module_spec_env_is_redacted() {
+        let filter = SecretFilter::default();
+        let spec = json!({
+            "name": "m1",
+            "config": {
+                "settings": {
+                    "image": "ubuntu",
+                    "createOptions": {
+                        "Env": ["API_KEY=abc", "MODE=test", "TOKEN"],
+                    },
+                },
+                "env": [
+                    { "key": "DB_PASSWORD", "value": "hunter2" },
+                    { "key": "MODE", "value": "test" },
+                ],
+            },
+        });
+
+        let redacted = serde_json::to_value(filter.redact(&spec)).unwrap();
+
+        assert_eq!(
+            json!({
+                "name": "m1",
+                "config": {
+                    "settings": {
+                        "image": "ubuntu",
+                        "createOptions": {
+                            "Env": ["API_KEY=***", "MODE=test", "TOKEN"],
+                        },
+                    },
+                    "env": [
+                        { "key": "DB_PASSWORD", "value": "***" },
+                        { "key": "MODE", "value": "test" },
+                    ],
+                },
+            }),
+            redacted
+        );
+        assert_eq!("hunter2", spec["config"]["env"][0]["value"]);
+    }
+
+    #[test]
+    fn lists_are_redacted() {
+        let filter = SecretFilter::new(vec!["SECRET_*".to_string()]);
+        let modules = json!({ "modules": [
+            { "config": { "env": [{ "key": "secret_a", "value": "1" }] } },
+            { "config": { "env": [{ "key": "API_KEY", "value": "2" }] } },
+        ]});
+
+        let redacted = serde_json::to_value(filter.redact(&modules)).unwrap();
+
+        assert_eq!("***", redacted["modules"][0]["config"]["env"][0]["value"]);
+        assert_eq!("2", redacted["modules"][1]["config"]["env"][0]["value"]);
+    }
+
+    #[test]
+    fn env_map_is_redacted() {
+        let mut env = HashMap::new();
+        env.insert("SAS_TOKEN".to_string(), "abc".to_string());
+        env.insert("MODE".to_string(), "test".to_string());
+
+        let env = SecretFilter::default().redact_env(env);
+
+        assert_eq!("***", env["SAS_TOKEN"]);
+        assert_eq!("test", env["MODE"]);
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_derive::Deserialize;
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+const SIGKILL: u32 = 9;
+
+const SIGNALS: &[(&str, u32)] = &[
+    ("HUP", 1),
+    ("INT", 2),
+    ("QUIT", 3),
+    ("ILL", 4),
+    ("TRAP", 5),
+    ("ABRT", 6),
+    ("BUS", 7),
+    ("FPE", 8),
+    ("KILL", 9),
+    ("USR1", 10),
+    ("SEGV", 11),
+    ("USR2", 12),
+    ("PIPE", 13),
+    ("ALRM", 14),
+    ("TERM", 15),
+    ("STKFLT", 16),
+    ("CHLD", 17),
+    ("CONT", 18),
+    ("STOP", 19),
+    ("TSTP", 20),
+    ("TTIN", 21),
+    ("TTOU", 22),
+    ("URG", 23),
+    ("XCPU", 24),
+    ("XFSZ", 25),
+    ("VTALRM", 26),
+    ("PROF", 27),
+    ("WINCH", 28),
+    ("IO", 29),
+    ("PWR", 30),
+    ("SYS", 31),
+];
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Signal {
+    Number(u32),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+struct SignalRequest {
+    signal: Signal,
+}
+
+/// Sends a POSIX signal to the main process of a running module, given either
+/// by number or by name like `SIGUSR1` or `USR1`. `SIGKILL` is refused since
+/// the module should be stopped or deleted instead.
+pub struct SignalModule<M> {
+    runtime: M,
+}
+
+impl<M> SignalModule<M> {
+    pub fn new(runtime: M) -> Self {
+        SignalModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for SignalModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(|b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let request = serde_json::from_slice::<SignalRequest>(&b)
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        let signal = signal_number(&request.signal)?;
+                        if signal == SIGKILL {
+                            return Err(Error::from(ErrorKind::SignalNotAllowed(signal)));
+                        }
+                        Ok(signal)
+                    })
+                    .and_then(move |signal| {
+                        runtime
+                            .get(&name)
+                            .then(move |result| -> Result<_, Error> {
+                                let (_, state) = result.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::SignalModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                if *state.status() != ModuleStatus::Running {
+                                    return Err(Error::from(ErrorKind::ModuleNotRunning(name)));
+                                }
+                                Ok((name, signal, runtime))
+                            })
+                            .and_then(|(name, signal, runtime)| {
+                                runtime.signal(&name, signal).then(
+                                    move |result| -> Result<_, Error> {
+                                        result.with_context(|_| {
+                                            ErrorKind::RuntimeOperation(
+                                                RuntimeOperation::SignalModule(name.clone()),
+                                            )
+                                        })?;
+                                        let response = Response::builder()
+                                            .status(StatusCode::NO_CONTENT)
+                                            .body(Body::default())
+                                            .context(ErrorKind::RuntimeOperation(
+                                                RuntimeOperation::SignalModule(name),
+                                            ))?;
+                                        Ok(response)
+                                    },
+                                )
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn signal_number(signal: &Signal) -> Result<u32, Error> {
+    match signal {
+        Signal::Number(number) if SIGNALS.iter().any(|(_, n)| n == number) => Ok(*number),
+        Signal::Number(number) => Err(Error::from(ErrorKind::InvalidSignal(number.to_string()))),
+        Signal::Name(name) => {
+            let upper = name.to_uppercase();
+            let short = upper.trim_start_matches("SIG");
+            SIGNALS
+                .iter()
+                .find(|(n, _)| *n == short)
+                .map(|(_, number)| *number)
+                .ok_or_else(|| Error::from(ErrorKind::InvalidSignal(name.clone())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(runtime: TestRuntime<Error>, body: &str) -> Response<Body> {
+        let handler = SignalModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::post("http://localhost/modules/mod1/signal")
+            .body(body.to_string().into())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success_with_number() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"signal":10}"#);
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn success_with_name() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"signal":"SIGUSR1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn signal_names() {
+        assert_eq!(
+            10,
+            signal_number(&Signal::Name("SIGUSR1".to_string())).unwrap()
+        );
+        assert_eq!(1, signal_number(&Signal::Name("hup".to_string())).unwrap());
+        assert_eq!(
+            15,
+            signal_number(&Signal::Name("SigTerm".to_string())).unwrap()
+        );
+        assert!(signal_number(&Signal::Name("SIGFOO".to_string())).is_err());
+        assert!(signal_number(&Signal::Number(0)).is_err());
+        assert!(signal_number(&Signal::Number(64)).is_err());
+    }
+
+    #[test]
+    fn sigkill_is_rejected() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"signal":"SIGKILL"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let error = error(response);
+        assert!(error
+            .message()
+            .contains("use DELETE or stop endpoint instead"));
+        assert_eq!(Some("SignalNotAllowed"), error.code());
+    }
+
+    #[test]
+    fn invalid_signal() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, r#"{"signal":"SIGFOO"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(Some("InvalidSignal"), error(response).code());
+    }
+
+    #[test]
+    fn not_running() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Stopped);
+
+        // act
+        let response = handle(runtime, r#"{"signal":10}"#);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let error = error(response);
+        assert_eq!("Module mod1 is not running", error.message());
+        assert_eq!(Some("ModuleNotRunning"), error.code());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, r#"{"signal":10}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not signal module mod1\n\tcaused by: General error",
+            error(response).message()
+        );
+    }
+}
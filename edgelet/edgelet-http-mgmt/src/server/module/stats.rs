@@ -0,0 +1,178 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, ModuleStats as CoreModuleStats, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleStats as HttpModuleStats;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct ModuleStats<M> {
+    runtime: M,
+}
+
+impl<M> ModuleStats<M> {
+    pub fn new(runtime: M) -> Self {
+        ModuleStats { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ModuleStats<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime.stats(&name).then(|stats| -> Result<_, Error> {
+                    let stats = stats.with_context(|_| {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(name.clone()))
+                    })?;
+
+                    let body = core_to_stats(&stats);
+                    let b = serde_json::to_string(&body).with_context(|_| {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(name.clone()))
+                    })?;
+
+                    let response = Response::builder()
+                        .status(StatusCode::OK)
+                        .header(CONTENT_TYPE, "application/json")
+                        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                        .body(b.into())
+                        .context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::GetModuleStats(name),
+                        ))?;
+                    Ok(response)
+                })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[allow(clippy::cast_possible_wrap)]
+fn core_to_stats(stats: &CoreModuleStats) -> HttpModuleStats {
+    HttpModuleStats::new(
+        stats.cpu_percent(),
+        stats.memory_usage_bytes() as i64,
+        stats.memory_limit_bytes() as i64,
+        stats.network_rx_bytes() as i64,
+        stats.network_tx_bytes() as i64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    #[test]
+    fn success() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Stopped);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let handler = ModuleStats::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/stats")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let stats: HttpModuleStats = serde_json::from_slice(&b).unwrap();
+                assert!(stats.cpu_percent().abs() < std::f64::EPSILON);
+                assert_eq!(0, *stats.memory_usage_bytes());
+                assert_eq!(0, *stats.memory_limit_bytes());
+                assert_eq!(0, *stats.network_rx_bytes());
+                assert_eq!(0, *stats.network_tx_bytes());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+        let handler = ModuleStats::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/stats")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not get stats for module mod1\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn bad_params() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+        let handler = ModuleStats::new(runtime);
+        let request = Request::get("http://localhost/modules/mod1/stats")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_derive::Serialize;
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, ModuleRuntimeState, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Returns just the runtime status of a module, without its spec, so that
+/// monitoring agents can cheaply poll it to spot a crash-looping module.
+pub struct GetModuleStatus<M> {
+    runtime: M,
+}
+
+impl<M> GetModuleStatus<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleStatus { runtime }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ModuleStatusResponse<'a> {
+    name: &'a str,
+    status: &'a ModuleStatus,
+    restart_count: u32,
+    last_exit_code: Option<i64>,
+    started_at: Option<&'a DateTime<Utc>>,
+    finished_at: Option<&'a DateTime<Utc>>,
+}
+
+impl<'a> ModuleStatusResponse<'a> {
+    fn new(name: &'a str, state: &'a ModuleRuntimeState) -> Self {
+        ModuleStatusResponse {
+            name,
+            status: state.status(),
+            restart_count: state.restart_count(),
+            last_exit_code: state.exit_code(),
+            started_at: state.started_at(),
+            finished_at: state.finished_at(),
+        }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleStatus<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime.get(&name).then(|result| -> Result<_, Error> {
+                    let (_, state) = result.with_context(|_| {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone()))
+                    })?;
+
+                    let b = serde_json::to_string(&ModuleStatusResponse::new(&name, &state))
+                        .with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone()))
+                        })?;
+
+                    let response = Response::builder()
+                        .status(StatusCode::OK)
+                        .header(CONTENT_TYPE, "application/json")
+                        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                        .body(b.into())
+                        .context(ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                            name,
+                        )))?;
+                    Ok(response)
+                })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    #[test]
+    fn success() {
+        // arrange
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Failed)
+            .with_exit_code(Some(137))
+            .with_restart_count(4)
+            .with_started_at(Some(Utc.ymd(2019, 4, 13).and_hms(14, 20, 0)))
+            .with_finished_at(Some(Utc.ymd(2019, 4, 13).and_hms(15, 20, 0)));
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = GetModuleStatus::new(TestRuntime::new(Ok(module)));
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/status")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        let b = response.into_body().concat2().wait().unwrap();
+        let status: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!({
+                "name": "mod1",
+                "status": "failed",
+                "restart_count": 4,
+                "last_exit_code": 137,
+                "started_at": "2019-04-13T14:20:00Z",
+                "finished_at": "2019-04-13T15:20:00Z",
+            }),
+            status
+        );
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = GetModuleStatus::new(TestRuntime::new(Err(Error::General)));
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get("http://localhost/modules/mod1/status")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not get module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
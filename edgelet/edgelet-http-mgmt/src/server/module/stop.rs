@@ -1,16 +1,32 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::time::Duration;
+
 use failure::{Fail, ResultExt};
+use futures::future::Either;
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
+use url::form_urlencoded;
 
 use edgelet_core::{ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
+/// How long `?wait=true` waits for the module to stop by default.
+pub const DEFAULT_STOP_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The longest `timeout` that `?wait=true` accepts. The stop route gets this
+/// much more time than other runtime requests, so that the wait isn't cut
+/// off by the request timeout first.
+pub const MAX_STOP_WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Stops a module. With `?wait=true` the response is only sent once the
+/// module is no longer running, or with `408 Request Timeout` if it still is
+/// after `timeout` seconds.
 pub struct StopModule<M> {
     runtime: M,
 }
@@ -23,25 +39,49 @@ impl<M> StopModule<M> {
 
 impl<M> Handler<Parameters> for StopModule<M>
 where
-    M: 'static + ModuleRuntime + Send,
+    M: 'static + ModuleRuntime + Clone + Send,
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
-            .map(|name| {
-                let name = name.to_string();
-
-                self.runtime.stop(&name, None).then(|result| match result {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = req
+            .uri()
+            .query()
+            .map_or_else(|| Ok(None), parse_wait)
+            .and_then(|wait| {
+                params
+                    .name("name")
+                    .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+                    .map(|name| (name.to_string(), wait))
+            })
+            .map(move |(name, wait)| {
+                let stop = runtime.stop(&name, None).then(|result| match result {
                     Ok(_) => Ok(name),
                     Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
                         RuntimeOperation::StopModule(name),
                     )))),
-                })
+                });
+
+                match wait {
+                    Some(timeout) => Either::A(stop.and_then(move |name| {
+                        runtime
+                            .wait_until_stopped(&name, timeout)
+                            .then(|result| match result {
+                                Ok(_) => Ok(name),
+                                Err(err) => {
+                                    Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::WaitUntilStopped(name),
+                                    ))))
+                                }
+                            })
+                    })),
+                    None => Either::B(stop),
+                }
             })
             .into_future()
             .flatten()
@@ -53,12 +93,39 @@ where
                         name,
                     )))?)
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
 }
 
+/// The timeout to wait for, if `wait=true` is in the query. `timeout` is in
+/// seconds and only matters when waiting.
+fn parse_wait(query: &str) -> Result<Option<Duration>, Error> {
+    let mut wait = false;
+    let mut timeout = DEFAULT_STOP_WAIT_TIMEOUT;
+    for (key, val) in form_urlencoded::parse(query.as_bytes()) {
+        match &*key {
+            "wait" => {
+                wait = val
+                    .parse::<bool>()
+                    .context(ErrorKind::MalformedRequestParameter("wait"))?;
+            }
+            "timeout" => {
+                timeout = val
+                    .parse::<u64>()
+                    .map(Duration::from_secs)
+                    .context(ErrorKind::MalformedRequestParameter("timeout"))?;
+                if timeout > MAX_STOP_WAIT_TIMEOUT {
+                    return Err(Error::from(ErrorKind::MalformedRequestParameter("timeout")));
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(if wait { Some(timeout) } else { None })
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
@@ -97,6 +164,61 @@ mod tests {
         assert_eq!(StatusCode::NO_CONTENT, response.status());
     }
 
+    #[test]
+    fn success_with_wait() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let handler = StopModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::post("http://localhost/modules/test/stop?wait=true&timeout=10")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn wait_parses() {
+        assert_eq!(None, parse_wait("").unwrap());
+        assert_eq!(None, parse_wait("wait=false&timeout=10").unwrap());
+        assert_eq!(
+            Some(DEFAULT_STOP_WAIT_TIMEOUT),
+            parse_wait("wait=true").unwrap()
+        );
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            parse_wait("wait=true&timeout=10").unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_wait_is_bad_request() {
+        for query in &["wait=yes", "wait=true&timeout=-1", "wait=true&timeout=301"] {
+            // arrange
+            let runtime = TestRuntime::new(Err(Error::General));
+            let handler = StopModule::new(runtime);
+            let parameters =
+                Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+            let uri = format!("http://localhost/modules/test/stop?{}", query);
+            let request = Request::post(uri.as_str()).body(Body::default()).unwrap();
+
+            // act
+            let response = handler.handle(request, parameters).wait().unwrap();
+
+            // assert
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        }
+    }
+
     #[test]
     fn stop_bad_params() {
         // arrange
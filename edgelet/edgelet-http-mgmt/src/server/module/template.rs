@@ -0,0 +1,225 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::env;
+
+use failure::ResultExt;
+use hyper::{Body, Response, StatusCode};
+use log::warn;
+use serde_json::{self, Value};
+use url::form_urlencoded::parse as parse_query;
+
+use management::models::ModuleSpec;
+
+use super::idempotency::json_response;
+use super::SecretFilter;
+use crate::error::{Error, ErrorKind};
+
+/// Replaces the `${NAME}` placeholders in the string values of the module
+/// spec in `body` with the environment variables of the iotedged process.
+///
+/// Placeholders in the values of variables are replaced too, and variables
+/// that refer back to themselves are rejected. Placeholders of variables
+/// that aren't set are logged and left as they are.
+pub(super) fn resolve_templates(body: &[u8]) -> Result<Vec<u8>, Error> {
+    resolve_templates_with(body, |name| env::var(name).ok())
+}
+
+fn resolve_templates_with<F>(body: &[u8], lookup: F) -> Result<Vec<u8>, Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut spec: Value = serde_json::from_slice(body).context(ErrorKind::MalformedRequestBody)?;
+    resolve_value(&mut spec, &lookup)?;
+    let body = serde_json::to_vec(&spec).context(ErrorKind::MalformedRequestBody)?;
+    Ok(body)
+}
+
+fn resolve_value<F>(value: &mut Value, lookup: &F) -> Result<(), Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match value {
+        Value::String(s) => *s = expand(s, lookup, &mut Vec::new())?,
+        Value::Array(values) => {
+            for value in values {
+                resolve_value(value, lookup)?;
+            }
+        }
+        Value::Object(members) => {
+            for member in members.values_mut() {
+                resolve_value(member, lookup)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Expands the placeholders in `s`. `stack` holds the variables whose values
+/// are being expanded, so that a variable found on it again is a cycle.
+fn expand<F>(s: &str, lookup: &F, stack: &mut Vec<String>) -> Result<String, Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let name = placeholder[2..]
+            .find('}')
+            .map(|end| &placeholder[2..end + 2])
+            .filter(|name| is_variable_name(name));
+        let name = match name {
+            Some(name) => name,
+            None => {
+                expanded.push_str("${");
+                rest = &placeholder[2..];
+                continue;
+            }
+        };
+        rest = &placeholder[name.len() + 3..];
+
+        if let Some(i) = stack.iter().position(|var| var == name) {
+            let mut cycle = stack[i..].to_vec();
+            cycle.push(name.to_string());
+            return Err(Error::from(ErrorKind::TemplateCycle(cycle)));
+        }
+        match lookup(name) {
+            Some(value) => {
+                stack.push(name.to_string());
+                expanded.push_str(&expand(&value, lookup, stack)?);
+                stack.pop();
+            }
+            None => {
+                warn!(
+                    "Environment variable {} referenced by a module spec is not set, leaving it unresolved",
+                    name
+                );
+                expanded.push_str(&placeholder[..name.len() + 3]);
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The spec is only resolved and returned rather than applied when the
+/// `dry_run` query parameter is `true`.
+pub(super) fn parse_dry_run(query: Option<&str>) -> bool {
+    query.map_or(false, |query| {
+        parse_query(query.as_bytes()).any(|(key, value)| key == "dry_run" && value == "true")
+    })
+}
+
+/// Responds to a dry run with the resolved `spec`, with its secrets
+/// redacted.
+pub(super) fn dry_run_response(
+    spec: &ModuleSpec,
+    secrets: &SecretFilter,
+    context: ErrorKind,
+) -> Result<Response<Body>, Error> {
+    let b = serde_json::to_string(&secrets.redact(spec)).context(context)?;
+    Ok(json_response(StatusCode::OK, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn resolve(spec: Value) -> Result<Value, Error> {
+        let lookup = |name: &str| match name {
+            "HUB" => Some("hub.azure-devices.net".to_string()),
+            "URL" => Some("amqps://${HUB}:5671".to_string()),
+            "A" => Some("${B}".to_string()),
+            "B" => Some("x${A}".to_string()),
+            "SELF" => Some("${SELF}".to_string()),
+            _ => None,
+        };
+        let body = serde_json::to_vec(&spec).unwrap();
+        resolve_templates_with(&body, lookup).map(|b| serde_json::from_slice(&b).unwrap())
+    }
+
+    #[test]
+    fn placeholders_are_replaced_in_nested_strings() {
+        let spec = json!({
+            "name": "m1",
+            "config": {
+                "settings": { "image": "${HUB}/m1:1.0", "port": 5671 },
+                "env": [{ "key": "HOST", "value": "host=${HUB};" }],
+            },
+        });
+
+        assert_eq!(
+            json!({
+                "name": "m1",
+                "config": {
+                    "settings": { "image": "hub.azure-devices.net/m1:1.0", "port": 5671 },
+                    "env": [{ "key": "HOST", "value": "host=hub.azure-devices.net;" }],
+                },
+            }),
+            resolve(spec).unwrap()
+        );
+    }
+
+    #[test]
+    fn placeholders_in_variables_are_replaced() {
+        assert_eq!(
+            json!(["amqps://hub.azure-devices.net:5671"]),
+            resolve(json!(["${URL}"])).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_variables_and_invalid_placeholders_are_left_as_is() {
+        assert_eq!(
+            json!(["${UNKNOWN}", "${}", "${not a name}", "${HUB", "$HUB"]),
+            resolve(json!([
+                "${UNKNOWN}",
+                "${}",
+                "${not a name}",
+                "${HUB",
+                "$HUB"
+            ]))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        for (value, cycle) in vec![
+            ("${A}", vec!["A", "B", "A"]),
+            ("${B}", vec!["B", "A", "B"]),
+            ("${SELF}", vec!["SELF", "SELF"]),
+        ] {
+            let err = resolve(json!({ "name": value })).unwrap_err();
+            match err.kind() {
+                ErrorKind::TemplateCycle(vars) => assert_eq!(cycle, *vars),
+                kind => panic!("expected a template cycle but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_body() {
+        let err = resolve_templates_with(b"{", |_| None).unwrap_err();
+        match err.kind() {
+            ErrorKind::MalformedRequestBody => (),
+            kind => panic!("expected a malformed body but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn dry_run_query() {
+        assert!(parse_dry_run(Some("api-version=2019-01-30&dry_run=true")));
+        assert!(!parse_dry_run(Some("dry_run=false")));
+        assert!(!parse_dry_run(Some("dry_run")));
+        assert!(!parse_dry_run(None));
+    }
+}
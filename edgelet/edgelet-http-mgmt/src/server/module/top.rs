@@ -0,0 +1,193 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleTop;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Lists the processes running inside a module. The optional `ps_args` query
+/// parameter is passed on to `ps`, for example `?ps_args=auxf`.
+pub struct GetModuleTop<M> {
+    runtime: M,
+}
+
+impl<M> GetModuleTop<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleTop { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleTop<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let ps_args = req.uri().query().and_then(parse_ps_args);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(move |name| {
+                let name = name.to_string();
+
+                runtime
+                    .get(&name)
+                    .then(move |result| -> Result<_, Error> {
+                        let (_, state) = result.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(name.clone()))
+                        })?;
+                        if *state.status() != ModuleStatus::Running {
+                            return Err(Error::from(ErrorKind::ModuleNotRunning(name)));
+                        }
+                        Ok((name, runtime))
+                    })
+                    .and_then(move |(name, runtime)| {
+                        runtime
+                            .top(&name, ps_args.as_ref().map(String::as_str))
+                            .then(move |top| -> Result<_, Error> {
+                                let top = top.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                let body =
+                                    ModuleTop::new(top.titles().to_vec(), top.processes().to_vec());
+                                let b = serde_json::to_string(&body).with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                let response = Response::builder()
+                                    .status(StatusCode::OK)
+                                    .header(CONTENT_TYPE, "application/json")
+                                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                                    .body(b.into())
+                                    .context(ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::TopModule(name),
+                                    ))?;
+                                Ok(response)
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn parse_ps_args(query: &str) -> Option<String> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "ps_args")
+        .map(|(_, val)| val.into_owned())
+        .filter(|val| !val.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(runtime: TestRuntime<Error>, uri: &str) -> Response<Body> {
+        let handler = GetModuleTop::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::get(uri).body(Body::default()).unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Running);
+
+        // act
+        let response = handle(runtime, "http://localhost/modules/mod1/top?ps_args=auxf");
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({
+                "titles": ["PID", "USER", "CMD"],
+                "processes": [["1", "root", "/app"]],
+            }),
+            body(response)
+        );
+    }
+
+    #[test]
+    fn not_running() {
+        // arrange
+        let runtime = runtime(ModuleStatus::Stopped);
+
+        // act
+        let response = handle(runtime, "http://localhost/modules/mod1/top");
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("ModuleNotRunning"), error.code());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, "http://localhost/modules/mod1/top");
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not top module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn ps_args_parses() {
+        assert_eq!(Some("auxf".to_string()), parse_ps_args("ps_args=auxf"));
+        assert_eq!(None, parse_ps_args("ps_args="));
+        assert_eq!(None, parse_ps_args("other=1"));
+    }
+}
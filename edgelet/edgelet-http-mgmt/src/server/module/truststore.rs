@@ -0,0 +1,335 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use serde::Serialize;
+use serde_derive::Deserialize;
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{Error as CoreError, ErrorKind as CoreErrorKind, ModuleTrustStore};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+struct AddCertificateRequest {
+    certificate: String,
+}
+
+/// Lists the CA certificates that a module trusts besides those of its
+/// image, as `[{"subject", "thumbprint", "not_after"}]`.
+pub struct GetModuleTrustStore {
+    store: ModuleTrustStore,
+}
+
+impl GetModuleTrustStore {
+    pub fn new(store: ModuleTrustStore) -> Self {
+        GetModuleTrustStore { store }
+    }
+}
+
+impl Handler<Parameters> for GetModuleTrustStore {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                let cas = self
+                    .store
+                    .list(name)
+                    .map_err(|err| store_error(err, name))?;
+                json_response(&cas, name)
+            })
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+/// Adds the CA certificates of the PEM in the `certificate` of the body to
+/// the trust store of a module, and returns those that weren't already in
+/// it. The bundle mounted into the module's container is replaced right
+/// away, so a running module trusts them the next time it reads the bundle.
+pub struct AddModuleTrustStore {
+    store: ModuleTrustStore,
+}
+
+impl AddModuleTrustStore {
+    pub fn new(store: ModuleTrustStore) -> Self {
+        AddModuleTrustStore { store }
+    }
+}
+
+impl Handler<Parameters> for AddModuleTrustStore {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let store = self.store.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+                req.into_body()
+                    .concat2()
+                    .then(move |b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let request = serde_json::from_slice::<AddCertificateRequest>(&b)
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        let added = store
+                            .add(&name, request.certificate.as_bytes())
+                            .map_err(|err| store_error(err, &name))?;
+                        for ca in &added {
+                            info!(
+                                "Added CA certificate {} ({}) to the trust store of module {}",
+                                ca.thumbprint(),
+                                ca.subject(),
+                                name
+                            );
+                        }
+                        json_response(&added, &name)
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Removes the CA certificate with the `?thumbprint=` of the request from
+/// the trust store of a module, and from the bundle mounted into its
+/// container.
+pub struct RemoveModuleTrustStore {
+    store: ModuleTrustStore,
+}
+
+impl RemoveModuleTrustStore {
+    pub fn new(store: ModuleTrustStore) -> Self {
+        RemoveModuleTrustStore { store }
+    }
+}
+
+impl Handler<Parameters> for RemoveModuleTrustStore {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                let thumbprint = parse_thumbprint(req.uri().query().unwrap_or(""))?;
+                self.store
+                    .remove(name, &thumbprint)
+                    .map_err(|err| store_error(err, name))?;
+                info!(
+                    "Removed CA certificate {} from the trust store of module {}",
+                    thumbprint, name
+                );
+                let response = Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::default())
+                    .context(ErrorKind::ModuleTrustStore(name.to_string()))?;
+                Ok(response)
+            })
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+fn parse_thumbprint(query: &str) -> Result<String, Error> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "thumbprint")
+        .map(|(_, val)| val.into_owned())
+        .filter(|val| !val.is_empty())
+        .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("thumbprint")))
+}
+
+/// Errors of the request are returned as they are, and any other error of
+/// the store as an internal error of the trust store of module `name`.
+fn store_error(err: CoreError, name: &str) -> Error {
+    match err.kind() {
+        CoreErrorKind::InvalidModuleName(_) => {
+            Error::from(ErrorKind::InvalidModuleName(name.to_string()))
+        }
+        CoreErrorKind::InvalidTrustedCertificate(reason) => {
+            Error::from(ErrorKind::InvalidCertificate(reason.clone()))
+        }
+        CoreErrorKind::TrustedCertificateNotFound(thumbprint, module) => Error::from(
+            ErrorKind::TrustedCertificateNotFound(thumbprint.clone(), module.clone()),
+        ),
+        _ => Error::from(err.context(ErrorKind::ModuleTrustStore(name.to_string()))),
+    }
+}
+
+fn json_response<T: Serialize>(body: &T, name: &str) -> Result<Response<Body>, Error> {
+    let b = serde_json::to_string(body)
+        .with_context(|_| ErrorKind::ModuleTrustStore(name.to_string()))?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| ErrorKind::ModuleTrustStore(name.to_string()))?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use management::models::ErrorResponse;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509NameBuilder, X509};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn pem(cn: &str) -> String {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(30).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        String::from_utf8(cert.build().to_pem().unwrap()).unwrap()
+    }
+
+    fn parameters() -> Parameters {
+        Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())])
+    }
+
+    fn add(store: &ModuleTrustStore, body: &Value) -> Response<Body> {
+        let handler = AddModuleTrustStore::new(store.clone());
+        let request = Request::post("http://localhost/modules/mod1/truststore")
+            .body(serde_json::to_string(body).unwrap().into())
+            .unwrap();
+        handler.handle(request, parameters()).wait().unwrap()
+    }
+
+    fn body<T: serde::de::DeserializeOwned>(response: Response<Body>) -> T {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn added_certificate_is_listed() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModuleTrustStore::new(dir.path());
+        let handler = GetModuleTrustStore::new(store.clone());
+
+        // act
+        let added = add(&store, &json!({ "certificate": pem("contoso-ca") }));
+        let request = Request::get("http://localhost/modules/mod1/truststore")
+            .body(Body::default())
+            .unwrap();
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, added.status());
+        let added: Value = body(added);
+        assert_eq!("CN=contoso-ca", added[0]["subject"]);
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(added, body::<Value>(response));
+    }
+
+    #[test]
+    fn invalid_certificate_is_bad_request() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModuleTrustStore::new(dir.path());
+
+        // act
+        let response = add(&store, &json!({ "certificate": "not a certificate" }));
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            Some("InvalidCertificate"),
+            body::<ErrorResponse>(response).code()
+        );
+    }
+
+    #[test]
+    fn removed_certificate_is_not_found_again() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModuleTrustStore::new(dir.path());
+        let added: Value = body(add(&store, &json!({ "certificate": pem("contoso-ca") })));
+        let thumbprint = added[0]["thumbprint"].as_str().unwrap().to_string();
+        let handler = RemoveModuleTrustStore::new(store.clone());
+        let remove = || {
+            let request = Request::delete(format!(
+                "http://localhost/modules/mod1/truststore?thumbprint={}",
+                thumbprint
+            ))
+            .body(Body::default())
+            .unwrap();
+            handler.handle(request, parameters()).wait().unwrap()
+        };
+
+        // act
+        let removed = remove();
+        let again = remove();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, removed.status());
+        assert!(store.list("mod1").unwrap().is_empty());
+        assert_eq!(StatusCode::NOT_FOUND, again.status());
+        assert_eq!(
+            Some("CertificateNotFound"),
+            body::<ErrorResponse>(again).code()
+        );
+    }
+
+    #[test]
+    fn missing_thumbprint_is_bad_request() {
+        // arrange
+        let dir = tempfile::tempdir().unwrap();
+        let handler = RemoveModuleTrustStore::new(ModuleTrustStore::new(dir.path()));
+        let request = Request::delete("http://localhost/modules/mod1/truststore")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use chrono::Utc;
 use failure::ResultExt;
 use futures::{future, Future, Stream};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
@@ -11,21 +12,73 @@ use serde::Serialize;
 use serde_json;
 use url::form_urlencoded::parse as parse_query;
 
-use edgelet_core::{Module, ModuleRegistry, ModuleRuntime, ModuleStatus};
+use edgelet_core::{
+    Module, ModuleRegistry, ModuleRuntime, ModuleSpec as CoreModuleSpec, ModuleStatus,
+};
+use edgelet_http::authorization::AuthorizedCaller;
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
+use management::models::ModuleSpec;
 
-use super::{spec_to_core, spec_to_details};
+use super::history::{ModuleHistory, ModuleHistoryEntry};
+use super::names::ModuleNameValidator;
+use super::registry::RegistryValidator;
+use super::template::{dry_run_response, parse_dry_run, resolve_templates};
+use super::{runtime_name, spec_to_core, spec_to_details, SecretFilter};
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
+/// Set on responses to `PUT /modules/{name}` to tell whether the module was
+/// created rather than replaced, mirroring the `201`/`200` status.
+const CREATED_HEADER: &str = "x-created";
+
+/// Creates the module if it doesn't exist yet and replaces it otherwise.
+///
+/// `${NAME}` placeholders in the spec are replaced with environment
+/// variables of iotedged first. With `?dry_run=true` the resolved spec is
+/// returned instead of applying it. Every successful update is recorded in
+/// the module's history. Images from registries that the registry validator
+/// doesn't allow get a `403` response, and names that the name validator
+/// refuses get a `400` response. Like `POST /modules`, only edgeAgent may
+/// create a module of a reserved name, but others may replace one.
 pub struct UpdateModule<M> {
     runtime: M,
+    secrets: SecretFilter,
+    history: ModuleHistory,
+    registries: RegistryValidator,
+    names: ModuleNameValidator,
 }
 
 impl<M> UpdateModule<M> {
     pub fn new(runtime: M) -> Self {
-        UpdateModule { runtime }
+        UpdateModule {
+            runtime,
+            secrets: SecretFilter::default(),
+            history: ModuleHistory::default(),
+            registries: RegistryValidator::default(),
+            names: ModuleNameValidator::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_history(mut self, history: ModuleHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_registry_validator(mut self, registries: RegistryValidator) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    pub fn with_name_validator(mut self, names: ModuleNameValidator) -> Self {
+        self.names = names;
+        self
     }
 }
 
@@ -37,92 +90,164 @@ where
     fn handle(
         &self,
         req: Request<Body>,
-        _params: Parameters,
+        params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let runtime = self.runtime.clone();
+        let correlation_id = correlation_id(&req);
 
-        let start: bool = req
-            .uri()
-            .query()
-            .and_then(|query| {
-                parse_query(query.as_bytes())
-                    .find(|&(ref key, _)| key == "start")
-                    .and_then(|(_, v)| if v == "false" { None } else { Some(()) })
-                    .map(|_| true)
-            })
-            .unwrap_or_else(|| false);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+        let history = self.history.clone();
+        let registries = self.registries.clone();
+        let names = self.names.clone();
+        let caller = req.extensions().get::<AuthorizedCaller>().cloned();
+        let start = parse_start(req.uri().query());
+        let dry_run = parse_dry_run(req.uri().query());
 
         let response = req
             .into_body()
             .concat2()
-            .then(|b| -> Result<_, Error> {
+            .then(move |b| -> Result<_, Error> {
                 let b = b.context(ErrorKind::MalformedRequestBody)?;
-                let spec = serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
-                let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
-                Ok((core_spec, spec))
+                let b = resolve_templates(&b)?;
+                let spec: ModuleSpec =
+                    serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
+                names.validate(spec.name())?;
+                let reserved = names.validate_new(spec.name(), caller.as_ref());
+                registries.validate(&spec)?;
+                let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?
+                    .with_name(runtime_name(&params, spec.name()));
+                Ok((core_spec, spec, reserved))
             })
-            .and_then(move |(core_spec, spec)| {
-                let name = core_spec.name().to_string();
-
-                if start {
-                    info!("Updating and starting module {}", name);
-                } else {
-                    info!("Updating module {}", name);
+            .and_then({
+                let runtime = runtime.clone();
+                move |(core_spec, spec, reserved)| match reserved {
+                    Ok(()) => future::Either::A(future::ok((core_spec, spec))),
+                    // The name is reserved, so the upsert may only replace
+                    // the module and not create it.
+                    Err(err) => future::Either::B(runtime.list().then(move |modules| {
+                        let modules = modules.with_context(|_| {
+                            ErrorKind::UpdateModule(core_spec.name().to_string())
+                        })?;
+                        if modules
+                            .iter()
+                            .any(|module| module.name() == core_spec.name())
+                        {
+                            Ok((core_spec, spec))
+                        } else {
+                            Err(err)
+                        }
+                    })),
                 }
-
-                runtime.remove(&name).then(|result| {
-                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                    Ok((core_spec, spec, name, runtime))
-                })
-            })
-            .and_then(|(core_spec, spec, name, runtime)| {
-                debug!("Removed existing module {}", name);
-                runtime.registry().pull(core_spec.config()).then(|result| {
-                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                    Ok((core_spec, spec, name, runtime))
-                })
-            })
-            .and_then(|(core_spec, spec, name, runtime)| {
-                debug!("Successfully pulled new image for module {}", name);
-                runtime.create(core_spec).then(|result| {
-                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                    Ok((name, spec, runtime))
-                })
             })
-            .and_then(move |(name, spec, runtime)| {
-                debug!("Created module {}", name);
-                if start {
-                    info!("Starting module {}", name);
-                    future::Either::A(runtime.start(&name).then(|result| {
-                        result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                        Ok((ModuleStatus::Running, spec, name))
-                    }))
+            .and_then(move |(core_spec, spec)| {
+                if dry_run {
+                    let context = ErrorKind::UpdateModule(core_spec.name().to_string());
+                    future::Either::A(future::result(dry_run_response(&spec, &secrets, context)))
                 } else {
-                    future::Either::B(future::ok((ModuleStatus::Stopped, spec, name)))
+                    let name = core_spec.name().to_string();
+                    let entry = ModuleHistoryEntry::new(Utc::now(), &spec);
+                    future::Either::B(update(runtime, core_spec, spec, start, secrets).map(
+                        move |response| {
+                            history.record(&name, entry);
+                            response
+                        },
+                    ))
                 }
             })
-            .and_then(|(status, spec, name)| -> Result<_, Error> {
-                let details = spec_to_details(&spec, status);
-                let b = serde_json::to_string(&details)
-                    .with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                let response = Response::builder()
-                    .status(StatusCode::OK)
-                    .header(CONTENT_TYPE, "application/json")
-                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                    .body(b.into())
-                    .context(ErrorKind::UpdateModule(name))?;
-                Ok(response)
-            })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
 }
 
+/// The module is only started after the update when the `start` query
+/// parameter is present and not `false`.
+pub(super) fn parse_start(query: Option<&str>) -> bool {
+    query
+        .and_then(|query| {
+            parse_query(query.as_bytes())
+                .find(|&(ref key, _)| key == "start")
+                .and_then(|(_, v)| if v == "false" { None } else { Some(()) })
+                .map(|_| true)
+        })
+        .unwrap_or_else(|| false)
+}
+
+/// Pulls the image of `core_spec`, then creates or replaces the module with
+/// it, and responds with the details of `spec`, with its secrets redacted.
+pub(super) fn update<M>(
+    runtime: M,
+    core_spec: CoreModuleSpec<<M::Module as Module>::Config>,
+    spec: ModuleSpec,
+    start: bool,
+    secrets: SecretFilter,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    let name = core_spec.name().to_string();
+
+    if start {
+        info!("Updating and starting module {}", name);
+    } else {
+        info!("Updating module {}", name);
+    }
+
+    runtime
+        .registry()
+        .pull(core_spec.config())
+        .then(|result| {
+            result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+            Ok((core_spec, spec, name, runtime))
+        })
+        .and_then(|(core_spec, spec, name, runtime)| {
+            debug!("Successfully pulled new image for module {}", name);
+            runtime.upsert(core_spec).then(|result| {
+                let (_, created) =
+                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                Ok((name, spec, created, runtime))
+            })
+        })
+        .and_then(move |(name, spec, created, runtime)| {
+            if created {
+                debug!("Created module {}", name);
+            } else {
+                debug!("Replaced existing module {}", name);
+            }
+            if start {
+                info!("Starting module {}", name);
+                future::Either::A(runtime.start(&name).then(move |result| {
+                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                    Ok((ModuleStatus::Running, spec, created, name))
+                }))
+            } else {
+                future::Either::B(future::ok((ModuleStatus::Stopped, spec, created, name)))
+            }
+        })
+        .and_then(move |(status, spec, created, name)| -> Result<_, Error> {
+            let details = spec_to_details(&spec, status);
+            let b = serde_json::to_string(&secrets.redact(&details))
+                .with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+            let response = Response::builder()
+                .status(if created {
+                    StatusCode::CREATED
+                } else {
+                    StatusCode::OK
+                })
+                .header(CONTENT_TYPE, "application/json")
+                .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                .header(CREATED_HEADER, if created { "true" } else { "false" })
+                .body(b.into())
+                .context(ErrorKind::UpdateModule(name))?;
+            Ok(response)
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
-    use edgelet_core::{ModuleRuntimeState, ModuleStatus};
+    use edgelet_core::{AllowedRegistries, ModuleNameRules, ModuleRuntimeState, ModuleStatus};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::module::*;
     use lazy_static::lazy_static;
@@ -161,6 +286,7 @@ mod tests {
 
         // assert
         assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("false", *response.headers().get(CREATED_HEADER).unwrap());
         assert_eq!("160", *response.headers().get(CONTENT_LENGTH).unwrap());
         assert_eq!(
             "application/json",
@@ -186,6 +312,160 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn success_is_recorded_in_history() {
+        let history = ModuleHistory::new();
+        let handler = UpdateModule::new(RUNTIME.clone()).with_history(history.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = |uri| {
+            Request::put(uri)
+                .body(serde_json::to_string(&spec).unwrap().into())
+                .unwrap()
+        };
+
+        // act
+        handler
+            .handle(
+                request("http://localhost/modules/test-module"),
+                Parameters::new(),
+            )
+            .wait()
+            .unwrap();
+        handler
+            .handle(
+                request("http://localhost/modules/test-module?dry_run=true"),
+                Parameters::new(),
+            )
+            .wait()
+            .unwrap();
+
+        // assert
+        let ancestry = history.ancestry("test-module");
+        assert_eq!(1, ancestry.len());
+        assert_eq!("microsoft/test-image", ancestry[0].image());
+    }
+
+    #[test]
+    fn invalid_name_is_bad_request() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("-test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/-test-module")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("InvalidModuleName"), error.code());
+    }
+
+    #[test]
+    fn reserved_name_is_only_created_by_the_agent() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("edgeHub".to_string(), "docker".to_string(), config);
+        let body = serde_json::to_string(&spec).unwrap();
+
+        // act
+        let response = handler
+            .handle(
+                Request::put("http://localhost/modules/edgeHub")
+                    .body(body.clone().into())
+                    .unwrap(),
+                Parameters::new(),
+            )
+            .wait()
+            .unwrap();
+        let mut request = Request::put("http://localhost/modules/edgeHub")
+            .body(body.into())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(AuthorizedCaller::new("edgeAgent".to_string()));
+        let agent_response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("ReservedModuleName"), error.code());
+        assert_eq!(StatusCode::CREATED, agent_response.status());
+    }
+
+    #[test]
+    fn reserved_name_of_existing_module_is_replaced() {
+        let names =
+            ModuleNameValidator::new(ModuleNameRules::new(None, vec!["test-module".to_string()]));
+        let handler = UpdateModule::new(RUNTIME.clone()).with_name_validator(names);
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/test-module")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn registry_not_allowed_is_forbidden() {
+        let registries =
+            RegistryValidator::new(AllowedRegistries::new(
+                vec!["mcr.microsoft.com".to_string()],
+            ));
+        let handler = UpdateModule::new(RUNTIME.clone()).with_registry_validator(registries);
+        let config = Config::new(json!({"image":"contoso.azurecr.io/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/test-module")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!("RegistryNotAllowed", error["code"]);
+        assert_eq!("contoso.azurecr.io", error["registry"]);
+    }
+
+    #[test]
+    fn tenant_module_is_scoped_to_tenant() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/tenants/t1/modules/test-module")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+        let params = Parameters::with_captures(vec![
+            (Some("tenant".to_string()), "t1".to_string()),
+            (Some("name".to_string()), "test-module".to_string()),
+        ]);
+
+        // act
+        let response = handler.handle(request, params).wait().unwrap();
+
+        // assert
+        // The runtime only has the unscoped `test-module`, so `t1.test-module`
+        // is created rather than replacing it.
+        assert_eq!(StatusCode::CREATED, response.status());
+        assert_eq!("true", *response.headers().get(CREATED_HEADER).unwrap());
+        let b = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+        assert_eq!("test-module", details.name());
+    }
+
     #[test]
     fn success_start() {
         let handler = UpdateModule::new(RUNTIME.clone());
@@ -225,6 +505,34 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn success_created() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("new-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/new-module")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::CREATED, response.status());
+        assert_eq!("true", *response.headers().get(CREATED_HEADER).unwrap());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+                assert_eq!("new-module", details.name());
+                assert_eq!("stopped", details.status().runtime_status().status());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn bad_body() {
         let handler = UpdateModule::new(RUNTIME.clone());
@@ -252,6 +560,71 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn dry_run_returns_resolved_spec() {
+        std::env::set_var("IOTEDGE_TEST_UPDATE_TEMPLATE_IMAGE", "microsoft/test-image");
+        let runtime = TestRuntime::new(Err(Error::General));
+        let handler = UpdateModule::new(runtime);
+        let config = Config::new(json!({"image":"${IOTEDGE_TEST_UPDATE_TEMPLATE_IMAGE}"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/test-module?dry_run=true")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let spec: ModuleSpec = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "microsoft/test-image",
+                    spec.config().settings().get("image").unwrap()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn template_cycle_is_unprocessable() {
+        std::env::set_var(
+            "IOTEDGE_TEST_UPDATE_TEMPLATE_A",
+            "${IOTEDGE_TEST_UPDATE_TEMPLATE_B}",
+        );
+        std::env::set_var(
+            "IOTEDGE_TEST_UPDATE_TEMPLATE_B",
+            "${IOTEDGE_TEST_UPDATE_TEMPLATE_A}",
+        );
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"${IOTEDGE_TEST_UPDATE_TEMPLATE_A}"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/test-module")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(Some("TemplateCycle"), error.code());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn runtime_error() {
         let runtime = TestRuntime::new(Err(Error::General));
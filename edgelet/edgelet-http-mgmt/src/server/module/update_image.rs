@@ -0,0 +1,373 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::Utc;
+use failure::ResultExt;
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{self, Value};
+
+use edgelet_core::{Module, ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleSpec;
+
+use super::history::{ModuleHistory, ModuleHistoryEntry};
+use super::patch::current_spec;
+use super::registry::RegistryValidator;
+use super::update::update;
+use super::{spec_to_core, SecretFilter};
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+struct UpdateImageRequest {
+    image: String,
+}
+
+#[derive(Serialize)]
+struct UpdateImageResponse<'a> {
+    changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_image: Option<&'a str>,
+}
+
+/// Replaces the image of an existing module and leaves the rest of its spec
+/// as it is, so that rolling a tag doesn't take sending the whole spec.
+///
+/// The new spec goes through the same update as `PUT /modules/{name}`, and
+/// the module is started again if it was running. Both the spec with the old
+/// image, unless it is already the latest update, and the new one are
+/// recorded in the module's history, so a rollback goes back to the old
+/// image. A request for the image the module already has changes nothing
+/// and gets `{"changed": false}`, which makes retries safe. Images from
+/// registries that the registry validator doesn't allow get a `403`
+/// response.
+pub struct UpdateModuleImage<M> {
+    runtime: M,
+    secrets: SecretFilter,
+    history: ModuleHistory,
+    registries: RegistryValidator,
+}
+
+impl<M> UpdateModuleImage<M> {
+    pub fn new(runtime: M) -> Self {
+        UpdateModuleImage {
+            runtime,
+            secrets: SecretFilter::default(),
+            history: ModuleHistory::default(),
+            registries: RegistryValidator::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_history(mut self, history: ModuleHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_registry_validator(mut self, registries: RegistryValidator) -> Self {
+        self.registries = registries;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for UpdateModuleImage<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+        let history = self.history.clone();
+        let registries = self.registries.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                req.into_body()
+                    .concat2()
+                    .then(|b| -> Result<_, Error> {
+                        let b = b.context(ErrorKind::MalformedRequestBody)?;
+                        let request = serde_json::from_slice::<UpdateImageRequest>(&b)
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        if request.image.trim().is_empty() {
+                            return Err(Error::from(ErrorKind::MalformedRequestBody));
+                        }
+                        Ok(request.image)
+                    })
+                    .and_then(move |image| {
+                        let get_name = name.clone();
+                        runtime
+                            .get(&name)
+                            .join(runtime.env(&name))
+                            .then(move |result| -> Result<_, Error> {
+                                let ((module, state), env) = result.with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                                        get_name,
+                                    ))
+                                })?;
+                                let current = current_spec(&module, env).with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                let current: ModuleSpec = serde_json::from_value(current)
+                                    .with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                                let running = *state.status() == ModuleStatus::Running;
+                                Ok((name, image, current, running, runtime))
+                            })
+                            .and_then(move |(name, image, current, running, runtime)| {
+                                let previous_image = current
+                                    .config()
+                                    .settings()
+                                    .get("image")
+                                    .and_then(Value::as_str)
+                                    .unwrap_or_default()
+                                    .to_string();
+                                if previous_image == image {
+                                    return future::Either::A(future::result(json_response(
+                                        &name,
+                                        &UpdateImageResponse {
+                                            changed: false,
+                                            previous_image: None,
+                                        },
+                                    )));
+                                }
+
+                                info!(
+                                    "Updating image of module {} from {} to {}",
+                                    name, previous_image, image
+                                );
+                                let spec = with_image(&current, &image);
+                                let update = registries
+                                    .validate(&spec)
+                                    .and_then(|()| {
+                                        spec_to_core::<M>(
+                                            &spec,
+                                            ErrorKind::UpdateModule(name.clone()),
+                                        )
+                                    })
+                                    .into_future()
+                                    .and_then(move |core_spec| {
+                                        let old_entry =
+                                            ModuleHistoryEntry::new(Utc::now(), &current);
+                                        let new_entry = ModuleHistoryEntry::new(Utc::now(), &spec);
+                                        update(runtime, core_spec, spec, running, secrets).and_then(
+                                            move |_| {
+                                                record_update(
+                                                    &history, &name, old_entry, new_entry,
+                                                );
+                                                json_response(
+                                                    &name,
+                                                    &UpdateImageResponse {
+                                                        changed: true,
+                                                        previous_image: Some(&previous_image),
+                                                    },
+                                                )
+                                            },
+                                        )
+                                    });
+                                future::Either::B(update)
+                            })
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Records the update in the history of module `name`. The spec with the old
+/// image goes in first, unless it is already the latest entry, as when the
+/// module was last updated through `PUT /modules/{name}`.
+fn record_update(
+    history: &ModuleHistory,
+    name: &str,
+    old_entry: ModuleHistoryEntry,
+    new_entry: ModuleHistoryEntry,
+) {
+    let latest = history.ancestry(name).into_iter().next();
+    if latest.map_or(true, |entry| entry.spec_hash() != old_entry.spec_hash()) {
+        history.record(name, old_entry);
+    }
+    history.record(name, new_entry);
+}
+
+/// `spec` with its image replaced by `image`.
+fn with_image(spec: &ModuleSpec, image: &str) -> ModuleSpec {
+    let mut settings = spec.config().settings().clone();
+    if let Some(settings) = settings.as_object_mut() {
+        settings.insert("image".to_string(), Value::String(image.to_string()));
+    }
+    let config = spec.config().clone().with_settings(settings);
+    spec.clone().with_config(config)
+}
+
+fn json_response(name: &str, body: &UpdateImageResponse<'_>) -> Result<Response<Body>, Error> {
+    let b =
+        serde_json::to_string(body).with_context(|_| ErrorKind::UpdateModule(name.to_string()))?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::UpdateModule(name.to_string()))?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use edgelet_core::{AllowedRegistries, ModuleRuntimeState};
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image:1".to_string());
+        let mut env = HashMap::new();
+        env.insert("MODE".to_string(), "value".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("mod1".to_string(), config, Ok(state)).with_env(env);
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(handler: &UpdateModuleImage<TestRuntime<Error>>, body: &str) -> Response<Body> {
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        let request = Request::post("http://localhost/modules/mod1/update-image")
+            .body(body.to_string().into())
+            .unwrap();
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let history = ModuleHistory::new();
+        let handler =
+            UpdateModuleImage::new(runtime(ModuleStatus::Running)).with_history(history.clone());
+
+        // act
+        let response = handle(&handler, r#"{"image":"microsoft/test-image:2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({ "changed": true, "previous_image": "microsoft/test-image:1" }),
+            body(response)
+        );
+        let images: Vec<_> = history
+            .ancestry("mod1")
+            .iter()
+            .map(|entry| entry.image().to_string())
+            .collect();
+        assert_eq!(
+            vec!["microsoft/test-image:2", "microsoft/test-image:1"],
+            images
+        );
+        let latest = history.ancestry("mod1").remove(0);
+        assert_eq!(
+            &json!([{ "key": "MODE", "value": "value" }]),
+            &latest.spec()["config"]["env"]
+        );
+    }
+
+    #[test]
+    fn same_image_changes_nothing() {
+        // arrange
+        let history = ModuleHistory::new();
+        let handler =
+            UpdateModuleImage::new(runtime(ModuleStatus::Running)).with_history(history.clone());
+
+        // act
+        let response = handle(&handler, r#"{"image":"microsoft/test-image:1"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(json!({ "changed": false }), body(response));
+        assert!(history.ancestry("mod1").is_empty());
+    }
+
+    #[test]
+    fn registry_not_allowed_is_forbidden() {
+        // arrange
+        let history = ModuleHistory::new();
+        let registries =
+            RegistryValidator::new(AllowedRegistries::new(vec!["docker.io".to_string()]));
+        let handler = UpdateModuleImage::new(runtime(ModuleStatus::Running))
+            .with_history(history.clone())
+            .with_registry_validator(registries);
+
+        // act
+        let response = handle(&handler, r#"{"image":"contoso.azurecr.io/test-image:2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        let error = body(response);
+        assert_eq!("RegistryNotAllowed", error["code"]);
+        assert_eq!("contoso.azurecr.io", error["registry"]);
+        assert!(history.ancestry("mod1").is_empty());
+    }
+
+    #[test]
+    fn empty_image_is_bad_request() {
+        // arrange
+        let handler = UpdateModuleImage::new(runtime(ModuleStatus::Running));
+
+        // act
+        let response = handle(&handler, r#"{"image":" "}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = UpdateModuleImage::new(TestRuntime::new(Err(Error::General)));
+
+        // act
+        let response = handle(&handler, r#"{"image":"microsoft/test-image:2"}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not get module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,501 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashSet};
+
+use failure::ResultExt;
+use serde_json::{self, Value};
+
+use edgelet_core::{ResourceLimits as CoreResourceLimits, RestartPolicy};
+use edgelet_docker::is_cgroup_path;
+use management::models::ModuleSpec;
+
+use crate::error::{Error, ErrorKind, FieldError};
+
+/// Hosts that a port can be bound to on every address, so they overlap with
+/// any other host.
+const ANY_HOST: &[&str] = &["", "0.0.0.0", "::"];
+
+/// Deserializes the module spec in `body` and checks the fields that are
+/// valid JSON but would only fail once the runtime tries to create the
+/// module. Every invalid field is reported, with its path in the body.
+///
+/// A body that isn't a module spec at all is a malformed body rather than an
+/// invalid spec.
+pub fn validate_module_spec(body: &[u8]) -> Result<ModuleSpec, Error> {
+    let spec =
+        serde_json::from_slice::<ModuleSpec>(body).context(ErrorKind::MalformedRequestBody)?;
+
+    let mut fields = Vec::new();
+    validate_name(&spec, &mut fields);
+    validate_image(&spec, &mut fields);
+    validate_env(&spec, &mut fields);
+    validate_port_bindings(&spec, &mut fields);
+    validate_log_config(&spec, &mut fields);
+    validate_cgroup_parent(&spec, &mut fields);
+    validate_resource_limits(&spec, &mut fields);
+    validate_restart_policy(&spec, &mut fields);
+    validate_depends_on(&spec, &mut fields);
+
+    if fields.is_empty() {
+        Ok(spec)
+    } else {
+        Err(Error::from(ErrorKind::InvalidModuleSpec(fields)))
+    }
+}
+
+fn validate_name(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    if spec.name().trim().is_empty() {
+        fields.push(FieldError::new(
+            "name".to_string(),
+            "name must not be empty".to_string(),
+        ));
+    }
+}
+
+/// A missing image is left to the runtime's config, which fails to
+/// deserialize without one.
+fn validate_image(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    if let Some(image) = spec
+        .config()
+        .settings()
+        .get("image")
+        .and_then(Value::as_str)
+    {
+        if !is_image_reference(image) {
+            fields.push(FieldError::new(
+                "config.settings.image".to_string(),
+                format!("{:?} is not a valid image reference", image),
+            ));
+        }
+    }
+}
+
+fn validate_env(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    let mut seen = HashSet::new();
+    for (i, var) in spec.config().env().into_iter().flatten().enumerate() {
+        if !seen.insert(var.key().as_str()) {
+            fields.push(FieldError::new(
+                format!("config.env[{}].key", i),
+                format!("duplicate key {}", var.key()),
+            ));
+        }
+    }
+}
+
+/// Two bindings overlap when they bind the same host port with the same
+/// protocol on the same host, where binding to every address overlaps with
+/// any host.
+fn validate_port_bindings(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    let port_bindings = spec
+        .config()
+        .settings()
+        .pointer("/createOptions/HostConfig/PortBindings")
+        .and_then(Value::as_object);
+    // Sorted, so that the binding that is reported is always the same one.
+    let port_bindings: BTreeMap<_, _> = port_bindings.into_iter().flatten().collect();
+
+    let mut bound: Vec<(&str, &str, &str, &str)> = Vec::new();
+    for (container_port, bindings) in port_bindings {
+        let protocol = container_port.splitn(2, '/').nth(1).unwrap_or("tcp");
+        let bindings = bindings.as_array().map_or(&[][..], Vec::as_slice);
+        for (i, binding) in bindings.iter().enumerate() {
+            let host_port = match binding.get("HostPort").and_then(Value::as_str) {
+                Some(host_port) if !host_port.is_empty() => host_port,
+                _ => continue,
+            };
+            let host_ip = binding.get("HostIp").and_then(Value::as_str).unwrap_or("");
+
+            let overlap = bound
+                .iter()
+                .find(|(other_ip, other_port, other_protocol, _)| {
+                    *other_port == host_port
+                        && *other_protocol == protocol
+                        && (other_ip == &host_ip
+                            || ANY_HOST.contains(other_ip)
+                            || ANY_HOST.contains(&host_ip))
+                })
+                .map(|(_, _, _, other)| *other);
+            match overlap {
+                Some(other) => fields.push(FieldError::new(
+                    format!(
+                        "config.settings.createOptions.HostConfig.PortBindings[{:?}][{}].HostPort",
+                        container_port, i
+                    ),
+                    format!(
+                        "host port {}/{} is already bound to {}",
+                        host_port, protocol, other
+                    ),
+                )),
+                None => bound.push((host_ip, host_port, protocol, container_port)),
+            }
+        }
+    }
+}
+
+/// A log size that isn't a number of kilobytes, megabytes or gigabytes, like
+/// `10m`, would make docker refuse to create the container.
+fn validate_log_config(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    if let Some(max_size) = spec
+        .config()
+        .settings()
+        .pointer("/logConfig/maxSize")
+        .and_then(Value::as_str)
+    {
+        if !is_log_size(max_size) {
+            fields.push(FieldError::new(
+                "config.settings.logConfig.maxSize".to_string(),
+                format!(
+                    "{:?} is not a log size, expected a number followed by k, m or g",
+                    max_size
+                ),
+            ));
+        }
+    }
+}
+
+fn validate_cgroup_parent(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    if let Some(cgroup_parent) = spec
+        .config()
+        .settings()
+        .get("cgroupParent")
+        .and_then(Value::as_str)
+    {
+        if !is_cgroup_path(cgroup_parent) {
+            fields.push(FieldError::new(
+                "config.settings.cgroupParent".to_string(),
+                format!(
+                    "{:?} is not a cgroup path, expected an absolute path without \"..\" segments",
+                    cgroup_parent
+                ),
+            ));
+        }
+    }
+}
+
+fn validate_resource_limits(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    if let Some(limits) = spec.resource_limits() {
+        if let Err(err) = CoreResourceLimits::new(
+            limits.cpu_shares(),
+            limits.memory_limit_bytes(),
+            limits.memory_swap_bytes(),
+        ) {
+            fields.push(FieldError::new(
+                "resource_limits.memory_swap_bytes".to_string(),
+                err.to_string(),
+            ));
+        }
+    }
+}
+
+fn validate_restart_policy(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    if let Some(policy) = spec.restart_policy() {
+        if serde_json::from_value::<RestartPolicy>(Value::String(policy.clone())).is_err() {
+            fields.push(FieldError::new(
+                "restart_policy".to_string(),
+                format!(
+                    "{:?} is not a restart policy, expected never, on_failure or always",
+                    policy
+                ),
+            ));
+        }
+    }
+}
+
+fn validate_depends_on(spec: &ModuleSpec, fields: &mut Vec<FieldError>) {
+    for (i, dependency) in spec.depends_on().into_iter().flatten().enumerate() {
+        let message = if dependency.trim().is_empty() {
+            "module name must not be empty".to_string()
+        } else if dependency == spec.name() {
+            "a module can't depend on itself".to_string()
+        } else {
+            continue;
+        };
+        fields.push(FieldError::new(format!("depends_on[{}]", i), message));
+    }
+}
+
+/// Checks `image` against the grammar of docker image references, which is
+/// `[registry/]path[:tag][@digest]`.
+fn is_image_reference(image: &str) -> bool {
+    let (name, digest) = match image.find('@') {
+        Some(i) => (&image[..i], Some(&image[i + 1..])),
+        None => (image, None),
+    };
+    let (name, tag) = match name.rfind(':') {
+        Some(i) if !name[i..].contains('/') => (&name[..i], Some(&name[i + 1..])),
+        _ => (name, None),
+    };
+
+    let mut components: Vec<&str> = name.split('/').collect();
+    if components.len() > 1 && is_registry(components[0]) {
+        components.remove(0);
+    }
+
+    components
+        .iter()
+        .all(|component| is_path_component(component))
+        && tag.map_or(true, is_tag)
+        && digest.map_or(true, is_digest)
+}
+
+fn is_log_size(size: &str) -> bool {
+    size.ends_with(|c| c == 'k' || c == 'm' || c == 'g')
+        && size.len() > 1
+        && size[..size.len() - 1].chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_registry(component: &str) -> bool {
+    (component.contains('.') || component.contains(':') || component == "localhost")
+        && component
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == ':')
+}
+
+/// Lowercase letters and digits, separated by `.`, `_`, `__` or any number of
+/// `-`.
+fn is_path_component(component: &str) -> bool {
+    let alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    component.starts_with(alphanumeric)
+        && component.ends_with(alphanumeric)
+        && component
+            .split(alphanumeric)
+            .all(|separator| match separator {
+                "" | "." | "_" | "__" => true,
+                separator => separator.chars().all(|c| c == '-'),
+            })
+}
+
+fn is_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 128
+        && !tag.starts_with('.')
+        && !tag.starts_with('-')
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+fn is_digest(digest: &str) -> bool {
+    let mut parts = digest.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(algorithm), Some(hex)) => {
+            !algorithm.is_empty()
+                && algorithm.chars().all(|c| {
+                    c.is_ascii_alphanumeric() || c == '+' || c == '.' || c == '_' || c == '-'
+                })
+                && hex.len() >= 32
+                && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn fields(spec: &Value) -> Vec<FieldError> {
+        match validate_module_spec(spec.to_string().as_bytes()) {
+            Ok(_) => Vec::new(),
+            Err(err) => match err.kind() {
+                ErrorKind::InvalidModuleSpec(fields) => fields.clone(),
+                kind => panic!("Expected `InvalidModuleSpec` error but got {:?}.", kind),
+            },
+        }
+    }
+
+    fn field(field: &str, message: &str) -> FieldError {
+        FieldError::new(field.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn valid_spec() {
+        let spec = json!({
+            "name": "m1",
+            "type": "docker",
+            "config": {
+                "settings": {
+                    "image": "mcr.microsoft.com/azureiotedge-simulated-temperature-sensor:1.0",
+                    "createOptions": {
+                        "HostConfig": {
+                            "PortBindings": {
+                                "80/tcp": [{ "HostPort": "8080" }],
+                                "80/udp": [{ "HostPort": "8080" }],
+                                "443/tcp": [
+                                    { "HostIp": "127.0.0.1", "HostPort": "8443" },
+                                    { "HostIp": "10.0.0.1", "HostPort": "8443" },
+                                ],
+                            },
+                        },
+                    },
+                    "logConfig": { "maxSize": "10m", "maxFile": 5 },
+                },
+                "env": [{ "key": "FOO", "value": "1" }, { "key": "BAR", "value": "2" }],
+            },
+            "resource_limits": { "memory_limit_bytes": 100, "memory_swap_bytes": 200 },
+            "restart_policy": "on_failure",
+            "depends_on": ["m2"],
+        });
+
+        assert_eq!(Vec::<FieldError>::new(), fields(&spec));
+    }
+
+    #[test]
+    fn malformed_body_is_not_a_validation_error() {
+        let err = validate_module_spec(br#"{"name": "m1"}"#).unwrap_err();
+
+        assert!(match err.kind() {
+            ErrorKind::MalformedRequestBody => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let spec = json!({
+            "name": " ",
+            "type": "docker",
+            "config": {
+                "settings": {
+                    "image": "Microsoft/Image:latest",
+                    "createOptions": {
+                        "HostConfig": {
+                            "PortBindings": {
+                                "80/tcp": [{ "HostIp": "127.0.0.1", "HostPort": "8080" }],
+                                "81/tcp": [{ "HostPort": "8080" }],
+                            },
+                        },
+                    },
+                    "logConfig": { "maxSize": "10 MB" },
+                },
+                "env": [
+                    { "key": "FOO", "value": "1" },
+                    { "key": "BAR", "value": "2" },
+                    { "key": "FOO", "value": "3" },
+                ],
+            },
+            "resource_limits": { "memory_limit_bytes": 200, "memory_swap_bytes": 100 },
+            "restart_policy": "sometimes",
+        });
+
+        assert_eq!(
+            vec![
+                field("name", "name must not be empty"),
+                field(
+                    "config.settings.image",
+                    "\"Microsoft/Image:latest\" is not a valid image reference"
+                ),
+                field("config.env[2].key", "duplicate key FOO"),
+                field(
+                    "config.settings.createOptions.HostConfig.PortBindings[\"81/tcp\"][0].HostPort",
+                    "host port 8080/tcp is already bound to 80/tcp"
+                ),
+                field(
+                    "config.settings.logConfig.maxSize",
+                    "\"10 MB\" is not a log size, expected a number followed by k, m or g"
+                ),
+                field(
+                    "resource_limits.memory_swap_bytes",
+                    "Invalid resource limits: memory_swap_bytes 100 must be at least memory_limit_bytes 200 or -1"
+                ),
+                field(
+                    "restart_policy",
+                    "\"sometimes\" is not a restart policy, expected never, on_failure or always"
+                ),
+            ],
+            fields(&spec)
+        );
+    }
+
+    #[test]
+    fn dependencies_must_be_other_modules() {
+        let spec = json!({
+            "name": "m1",
+            "type": "docker",
+            "config": { "settings": {} },
+            "depends_on": ["m2", "", "m1"],
+        });
+
+        assert_eq!(
+            vec![
+                field("depends_on[1]", "module name must not be empty"),
+                field("depends_on[2]", "a module can't depend on itself"),
+            ],
+            fields(&spec)
+        );
+    }
+
+    #[test]
+    fn cgroup_parent_must_be_absolute() {
+        let spec = |cgroup_parent: &str| {
+            json!({
+                "name": "m1",
+                "type": "docker",
+                "config": { "settings": { "cgroupParent": cgroup_parent } },
+            })
+        };
+
+        assert_eq!(Vec::<FieldError>::new(), fields(&spec("/iotedge.slice")));
+        assert_eq!(
+            vec![field(
+                "config.settings.cgroupParent",
+                "\"/iotedge.slice/../system.slice\" is not a cgroup path, expected an absolute path without \"..\" segments"
+            )],
+            fields(&spec("/iotedge.slice/../system.slice"))
+        );
+        assert_eq!(1, fields(&spec("iotedge.slice")).len());
+    }
+
+    #[test]
+    fn missing_image_is_left_to_the_runtime() {
+        let spec = json!({ "name": "m1", "type": "docker", "config": { "settings": {} } });
+
+        assert_eq!(Vec::<FieldError>::new(), fields(&spec));
+    }
+
+    #[test]
+    fn log_sizes() {
+        for size in &["1k", "10m", "1024g"] {
+            assert!(is_log_size(size), "{} should be valid", size);
+        }
+
+        for size in &["", "m", "10", "10M", "10mb", "-1m", "1.5m", "10 m"] {
+            assert!(!is_log_size(size), "{} should be invalid", size);
+        }
+    }
+
+    #[test]
+    fn image_references() {
+        for image in &[
+            "ubuntu",
+            "ubuntu:18.04",
+            "library/ubuntu",
+            "localhost/ubuntu",
+            "localhost:5000/ubuntu:latest",
+            "mcr.microsoft.com/azureiotedge-agent:1.0",
+            "my_registry.azurecr.io/a__b/c-d.e---f:v1_2.3-rc",
+            "ubuntu@sha256:45b23dee08af5e43a7fea6c4cf9c25ccf269ee113168c19722f87876677c5cb2",
+        ] {
+            assert!(is_image_reference(image), "{} should be valid", image);
+        }
+
+        for image in &[
+            "",
+            "Ubuntu",
+            "ubuntu:",
+            "ubuntu:-latest",
+            "ubuntu latest",
+            "/ubuntu",
+            "ubuntu/",
+            "a..b",
+            "a___b",
+            "-ubuntu",
+            "ubuntu@sha256:abc",
+            "ubuntu@45b23dee08af5e43a7fea6c4cf9c25ccf269ee113168c19722f87876677c5cb2",
+        ] {
+            assert!(!is_image_reference(image), "{} should be invalid", image);
+        }
+    }
+}
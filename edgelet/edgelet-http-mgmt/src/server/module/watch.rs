@@ -0,0 +1,326 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use failure::{Fail, ResultExt};
+use futures::{stream, Future, Stream};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use log::debug;
+use serde::Serialize;
+use serde_json::{self, json, Value};
+use tokio::timer::Interval;
+
+use edgelet_core::{Module, ModuleRuntime, ModuleStatus, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ModuleDetails;
+
+use super::list::core_to_details;
+use super::SecretFilter;
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// How often a `{"type":"resync"}` message is sent while watching.
+const RESYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Streams the changes to the list of modules as newline-delimited JSON.
+///
+/// The first message is `{"type":"snapshot","modules":[...]}` with all of
+/// the modules. After every module event the list is compared with the
+/// modules last sent, and `{"op":"add","module":{...}}`, `{"op":"update",
+/// "module":{...}}` or `{"op":"delete","module":{"name":"..."}}` is sent for
+/// every module that was added, changed status or went away. Every minute a
+/// `{"type":"resync"}` message is sent, followed by any changes the events
+/// missed. The response stays open until the client goes away, and ends
+/// early if the runtime stops reporting events.
+pub struct WatchModules<M> {
+    runtime: M,
+    secrets: SecretFilter,
+}
+
+impl<M> WatchModules<M> {
+    pub fn new(runtime: M) -> Self {
+        WatchModules {
+            runtime,
+            secrets: SecretFilter::default(),
+        }
+    }
+
+    pub fn with_secret_filter(mut self, secrets: SecretFilter) -> Self {
+        self.secrets = secrets;
+        self
+    }
+}
+
+impl<M> Handler<Parameters> for WatchModules<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        debug!("Watch modules");
+
+        // The events are subscribed to before the snapshot is listed, so
+        // that no change between the two is missed.
+        let events = self
+            .runtime
+            .events()
+            .map(|_| WatchTrigger::Changed)
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::RuntimeOperation(RuntimeOperation::Events)))
+            })
+            .chain(stream::once(Ok(WatchTrigger::End)));
+        let runtime = self.runtime.clone();
+        let secrets = self.secrets.clone();
+
+        // The snapshot is listed before the response starts, so that an error
+        // listing the modules gets an error response.
+        let response = list_modules(&self.runtime)
+            .and_then(move |modules| -> Result<_, Error> {
+                let mut sent = HashMap::new();
+                let snapshot = snapshot(&mut sent, modules, &secrets)?;
+
+                let resyncs = Interval::new(Instant::now() + RESYNC_INTERVAL, RESYNC_INTERVAL)
+                    .map(|_| WatchTrigger::Resync)
+                    .map_err(|err| {
+                        Error::from(
+                            err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)),
+                        )
+                    });
+                let changes = events
+                    .select(resyncs)
+                    .take_while(|trigger| Ok(*trigger != WatchTrigger::End))
+                    .and_then(move |trigger| {
+                        list_modules(&runtime).map(move |modules| (trigger, modules))
+                    })
+                    .and_then(move |(trigger, modules)| -> Result<_, Error> {
+                        let mut messages = vec![];
+                        if trigger == WatchTrigger::Resync {
+                            messages.push(json!({ "type": "resync" }));
+                        }
+                        messages.extend(deltas(&mut sent, modules, &secrets)?);
+                        to_ndjson(&messages)
+                    })
+                    .filter(|b| !b.is_empty());
+                let body = stream::once(Ok(snapshot)).chain(changes).map(Chunk::from);
+
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/x-ndjson")
+                    .header(CACHE_CONTROL, "no-cache")
+                    .body(Body::wrap_stream(body.map_err(Fail::compat)))
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// What compares the list of modules with the one last sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WatchTrigger {
+    Changed,
+    Resync,
+    End,
+}
+
+/// The modules in the order the runtime reports them, with their status.
+fn list_modules<M>(
+    runtime: &M,
+) -> impl Future<Item = Vec<(ModuleStatus, ModuleDetails)>, Error = Error>
+where
+    M: 'static + ModuleRuntime,
+    <M::Module as Module>::Config: Serialize,
+{
+    runtime
+        .list_with_details()
+        .collect()
+        .then(|result| -> Result<_, Error> {
+            result
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
+                .into_iter()
+                .map(|(module, state)| {
+                    let details = core_to_details(module.name().to_string(), &module, &state)?;
+                    Ok((*state.status(), details))
+                })
+                .collect()
+        })
+}
+
+fn snapshot(
+    sent: &mut HashMap<String, ModuleStatus>,
+    modules: Vec<(ModuleStatus, ModuleDetails)>,
+    secrets: &SecretFilter,
+) -> Result<String, Error> {
+    let details: Vec<_> = modules
+        .into_iter()
+        .map(|(status, details)| {
+            sent.insert(details.name().to_string(), status);
+            details
+        })
+        .collect();
+    let modules = serde_json::to_value(secrets.redact(&details))
+        .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+    to_ndjson(&[json!({ "type": "snapshot", "modules": modules })])
+}
+
+/// The changes from the modules in `sent` to `modules`, which then replace
+/// them. Modules that went away are deleted last, by name.
+fn deltas(
+    sent: &mut HashMap<String, ModuleStatus>,
+    modules: Vec<(ModuleStatus, ModuleDetails)>,
+    secrets: &SecretFilter,
+) -> Result<Vec<Value>, Error> {
+    let mut current = HashMap::new();
+    let mut messages = vec![];
+    for (status, details) in modules {
+        let op = match sent.get(details.name()) {
+            None => Some("add"),
+            Some(last) if *last != status => Some("update"),
+            Some(_) => None,
+        };
+        if let Some(op) = op {
+            let module = serde_json::to_value(secrets.redact(&details))
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+            messages.push(json!({ "op": op, "module": module }));
+        }
+        current.insert(details.name().to_string(), status);
+    }
+
+    let mut deleted: Vec<_> = sent
+        .keys()
+        .filter(|name| !current.contains_key(*name))
+        .collect();
+    deleted.sort();
+    messages.extend(
+        deleted
+            .into_iter()
+            .map(|name| json!({ "op": "delete", "module": { "name": name } })),
+    );
+
+    *sent = current;
+    Ok(messages)
+}
+
+fn to_ndjson(messages: &[Value]) -> Result<String, Error> {
+    let mut b = String::new();
+    for message in messages {
+        let line = serde_json::to_string(message)
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+        b.push_str(&line);
+        b.push('\n');
+    }
+    Ok(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use edgelet_core::{ModuleEvent, ModuleEventType, ModuleRuntimeState};
+    use edgelet_test_utils::module::*;
+    use management::models::{Config, RuntimeStatus, Status};
+    use tokio::runtime::current_thread::Runtime;
+    use tokio::timer::Timeout;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn details(name: &str) -> ModuleDetails {
+        ModuleDetails::new(
+            "id".to_string(),
+            name.to_string(),
+            "docker".to_string(),
+            Config::new(json!({})),
+            Status::new(RuntimeStatus::new("running".to_string())),
+        )
+    }
+
+    #[test]
+    fn started_module_is_added() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default().with_status(ModuleStatus::Running)),
+        )
+        .with_events(vec![ModuleEvent::new(
+            ModuleEventType::Started,
+            "mod1".to_string(),
+            Utc::now(),
+        )]);
+        let runtime = TestRuntime::new(Ok(module)).with_module_listed_after_start();
+        let handler = WatchModules::new(runtime.clone());
+        let request = Request::get("http://localhost/modules/watch")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        runtime.start("mod1").wait().unwrap();
+        let body = Timeout::new(response.into_body().concat2(), Duration::from_secs(1));
+        let b = Runtime::new().unwrap().block_on(body).unwrap();
+
+        // assert
+        let messages: Vec<Value> = std::str::from_utf8(&b)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, messages.len());
+        assert_eq!(json!({ "type": "snapshot", "modules": [] }), messages[0]);
+        assert_eq!("add", messages[1]["op"]);
+        assert_eq!("mod1", messages[1]["module"]["name"]);
+        assert_eq!(
+            "running",
+            messages[1]["module"]["status"]["runtimeStatus"]["status"]
+        );
+    }
+
+    #[test]
+    fn deltas_add_update_and_delete() {
+        // arrange
+        let secrets = SecretFilter::default();
+        let mut sent = HashMap::new();
+        sent.insert("mod1".to_string(), ModuleStatus::Running);
+        sent.insert("mod2".to_string(), ModuleStatus::Running);
+        sent.insert("mod3".to_string(), ModuleStatus::Running);
+        let modules = vec![
+            (ModuleStatus::Running, details("mod1")),
+            (ModuleStatus::Stopped, details("mod2")),
+            (ModuleStatus::Running, details("mod4")),
+        ];
+
+        // act
+        let messages = deltas(&mut sent, modules, &secrets).unwrap();
+
+        // assert
+        let ops: Vec<_> = messages
+            .iter()
+            .map(|message| (message["op"].clone(), message["module"]["name"].clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (json!("update"), json!("mod2")),
+                (json!("add"), json!("mod4")),
+                (json!("delete"), json!("mod3")),
+            ],
+            ops
+        );
+        assert_eq!(3, sent.len());
+        assert_eq!(Some(&ModuleStatus::Stopped), sent.get("mod2"));
+        assert_eq!(3, deltas(&mut sent, vec![], &secrets).unwrap().len());
+        assert!(sent.is_empty());
+    }
+}
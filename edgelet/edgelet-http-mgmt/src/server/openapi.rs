@@ -0,0 +1,880 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::{self, json, Map, Value};
+use url::form_urlencoded;
+
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::{Error as HttpError, Version, VersionRange};
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// A route of the management service, as described in its OpenAPI document.
+///
+/// `request` and `response` name a schema under `components/schemas`. Only
+/// some of the types have hand-written schemas, so the other operations are
+/// described without a body schema.
+struct Route {
+    method: &'static str,
+    version: Option<VersionRange>,
+    path: &'static str,
+    operation_id: &'static str,
+    summary: &'static str,
+    status: u16,
+    request: Option<&'static str>,
+    response: Option<&'static str>,
+}
+
+macro_rules! routes {
+    ($($method:ident $ver:ident $(($min:ident, $max:ident))? $path:expr => $id:ident, $summary:expr, $status:expr, $request:expr, $response:expr;)*) => {
+        &[$(Route {
+            method: stringify!($method),
+            version: edgelet_http::route_version!($ver $(($min, $max))?),
+            path: $path,
+            operation_id: stringify!($id),
+            summary: $summary,
+            status: $status,
+            request: $request,
+            response: $response,
+        }),*]
+    };
+}
+
+/// Every route registered in `ManagementServiceBuilder::build`. This has to be kept
+/// in sync with the router by hand.
+const ROUTES: &[Route] = routes!(
+    get     Version2018_06_28 "/modules"                 => ListModules, "List modules.", 200, None, None;
+    post    Version2018_06_28 "/modules"                 => CreateModule, "Create module.", 201, Some("ModuleSpec"), None;
+    post    Version2019_01_30 "/modules/bulk/start"      => BulkStartModules, "Start several modules.", 200, None, None;
+    post    Version2019_01_30 "/modules/bulk/stop"       => BulkStopModules, "Stop several modules.", 200, None, None;
+    delete  Version2019_01_30 "/modules/all"             => DeleteAllModules, "Delete all user modules.", 204, None, None;
+    get     Version2019_01_30 "/modules/graph"           => GetModuleGraph, "Return the dependencies between modules.", 200, None, Some("ModuleGraph");
+    get     Version2019_01_30 "/modules/watch"           => WatchModules, "Stream changes to the list of modules.", 200, None, None;
+    get     Version2018_06_28 "/modules/{name}"          => GetModule, "Get a module's status.", 200, None, None;
+    put     Version2018_06_28 "/modules/{name}"          => UpdateModule, "Create or update a module.", 200, Some("ModuleSpec"), None;
+    patch   Version2019_01_30 "/modules/{name}"          => PatchModule, "Partially update a module.", 200, None, None;
+    post    Version2019_01_30 "/modules/{name}/prepareupdate" => PrepareUpdateModule, "Prepare to update a module.", 204, Some("ModuleSpec"), None;
+    post    Version2019_01_30 "/modules/{name}/rollback" => RollbackModule, "Roll a module back to the spec before its last update.", 200, None, Some("RollbackResult");
+    post    Version2019_01_30 "/modules/{name}/promote"  => PromoteModule, "Promote a module to another name.", 200, Some("PromoteRequest"), Some("ModuleDetails");
+    post    Version2019_01_30 "/modules/{name}/update-image" => UpdateModuleImage, "Update only the image of a module.", 200, Some("UpdateImageRequest"), Some("UpdateImageResult");
+    post    Version2019_01_30 "/modules/{name}/prepareimage" => PrepareModuleImage, "Pull the image of a module ahead of deployment.", 200, Some("PrepareImageRequest"), None;
+    delete  Version2018_06_28 "/modules/{name}"          => DeleteModule, "Delete a module.", 204, None, None;
+    post    Version2018_06_28 "/modules/{name}/start"    => StartModule, "Start a module.", 204, None, None;
+    post    Version2018_06_28 "/modules/{name}/stop"     => StopModule, "Stop a module.", 204, None, None;
+    post    Version2018_06_28 "/modules/{name}/restart"  => RestartModule, "Restart a module.", 204, None, None;
+    get     Version2018_06_28 "/modules/{name}/logs"     => ModuleLogs, "Get module logs.", 200, None, None;
+    post    Version2019_01_30 "/modules/{name}/copy-logs" => CopyModuleLogs, "Copy module logs to a file on the host.", 200, Some("CopyLogsRequest"), Some("CopyLogsResult");
+    get     Version2019_01_30 "/modules/{name}/metrics"  => GetModuleMetrics, "Scrape the Prometheus metrics of a module.", 200, None, None;
+    get     Version2019_01_30 "/modules/{name}/top"      => GetModuleTop, "List the processes running in a module.", 200, None, Some("ModuleTop");
+    get     Version2019_01_30 "/modules/{name}/stats"    => ModuleStats, "Get module resource usage.", 200, None, None;
+    get     Version2019_01_30 "/modules/{name}/config"   => GetModuleConfig, "Get the stored spec of a module.", 200, None, Some("ModuleSpec");
+    get     Version2019_01_30 "/modules/{name}/diff"     => GetModuleDiff, "Compare the stored spec of a module with the running module.", 200, None, None;
+    get     Version2019_01_30 "/modules/{name}/status"   => GetModuleStatus, "Get the runtime status of a module.", 200, None, None;
+    get     Version2019_01_30 "/modules/{name}/inspect"  => InspectModule, "Inspect a module.", 200, None, None;
+    get     Version2019_01_30 "/modules/{name}/ports"    => GetModulePorts, "List the exposed and published ports of a module.", 200, None, Some("PortBindingList");
+    get     Version2019_01_30 "/modules/{name}/volumes"  => GetModuleVolumes, "List the volumes mounted into a module.", 200, None, Some("VolumeMountList");
+    get     Version2019_01_30 "/modules/{name}/ancestry" => GetModuleAncestry, "List the recent updates of a module.", 200, None, Some("ModuleHistory");
+    get     Version2019_01_30 "/modules/{name}/env"      => GetModuleEnv, "Get the environment variables of a module.", 200, None, None;
+    patch   Version2019_01_30 "/modules/{name}/env"      => PatchModuleEnv, "Update the environment variables of a module.", 200, None, None;
+    get     Version2019_01_30 "/modules/{name}/labels/{key}" => GetModuleLabel, "Get the value of a label of a module.", 200, None, None;
+    put     Version2019_01_30 "/modules/{name}/labels/{key}" => SetModuleLabel, "Set a label of a module.", 200, None, Some("ModuleDetails");
+    delete  Version2019_01_30 "/modules/{name}/labels/{key}" => DeleteModuleLabel, "Remove a label from a module.", 200, None, Some("ModuleDetails");
+    delete  Version2019_01_30 "/modules/{name}/labels"   => DeleteAllModuleLabels, "Remove all labels from a module.", 204, None, None;
+    post    Version2019_01_30 "/modules/{name}/exec"     => ExecModule, "Run a command in a running module.", 200, Some("ExecRequest"), None;
+    post    Version2019_01_30 "/modules/{name}/signal"   => SignalModule, "Send a signal to a running module.", 204, Some("SignalRequest"), None;
+    post    Version2019_01_30 "/modules/{name}/pause"    => PauseModule, "Freeze the processes of a running module.", 204, None, None;
+    post    Version2019_01_30 "/modules/{name}/unpause"  => UnpauseModule, "Thaw the processes of a paused module.", 204, None, None;
+    post    Version2019_01_30 "/modules/{name}/rename" => RenameModule, "Rename a module.", 204, Some("RenameRequest"), None;
+    post    Version2019_01_30 "/modules/{name}/copy-file" => CopyFileToModule, "Write a file into a running module.", 204, None, None;
+    get     Version2019_01_30 "/modules/{name}/truststore" => GetModuleTrustStore, "List the CA certificates a module trusts.", 200, None, Some("TrustedCaList");
+    post    Version2019_01_30 "/modules/{name}/truststore" => AddModuleTrustStore, "Add CA certificates to the trust store of a module.", 200, Some("AddTrustedCaRequest"), Some("TrustedCaList");
+    delete  Version2019_01_30 "/modules/{name}/truststore" => RemoveModuleTrustStore, "Remove a CA certificate from the trust store of a module.", 204, None, None;
+    post    Version2019_01_30 "/modules/{name}/checkpoint" => CheckpointModule, "Checkpoint a running module.", 204, Some("CheckpointRequest"), None;
+    post    Version2019_01_30 "/modules/{name}/restore"  => RestoreModule, "Restore a module from a checkpoint.", 204, Some("CheckpointRequest"), None;
+    patch   Version2019_01_30 "/modules/{name}/resources" => PatchModuleResources, "Update the resource limits of a running module.", 204, Some("ResourceLimits"), None;
+    put     Version2019_01_30 "/modules/{name}/bandwidth" => SetModuleBandwidth, "Limit the network bandwidth of a running module.", 204, Some("BandwidthLimits"), None;
+
+    get     Version2019_01_30 "/tenants/{tenant}/modules"        => ListTenantModules, "List the modules of a tenant.", 200, None, None;
+    post    Version2019_01_30 "/tenants/{tenant}/modules"        => CreateTenantModule, "Create a module of a tenant.", 201, Some("ModuleSpec"), None;
+    put     Version2019_01_30 "/tenants/{tenant}/modules/{name}" => UpdateTenantModule, "Create or update a module of a tenant.", 200, Some("ModuleSpec"), None;
+    delete  Version2019_01_30 "/tenants/{tenant}/modules/{name}" => DeleteTenantModule, "Delete a module of a tenant.", 204, None, None;
+
+    get     Version2018_06_28 "/identities"              => ListIdentities, "List identities.", 200, None, Some("IdentityList");
+    post    Version2018_06_28 "/identities"              => CreateIdentity, "Create an identity.", 200, Some("IdentitySpec"), Some("Identity");
+    delete  Version2019_01_30 "/identities/all"          => DeleteAllIdentities, "Delete all module identities.", 204, None, None;
+    get     Version2019_01_30 "/identities/{name}"       => GetIdentity, "Get an identity.", 200, None, Some("Identity");
+    put     Version2018_06_28 "/identities/{name}"       => UpdateIdentity, "Update an identity.", 200, Some("UpdateIdentity"), Some("Identity");
+    patch   Version2019_01_30 "/identities/{name}"       => PatchIdentity, "Partially update an identity.", 200, None, Some("Identity");
+    delete  Version2018_06_28 "/identities/{name}"       => DeleteIdentity, "Delete an identity.", 204, None, None;
+
+    get     Version2018_06_28 "/systeminfo"              => GetSystemInfo, "Return host system information.", 200, None, Some("SystemInfo");
+    get     Version2019_01_30 "/systeminfo/resources"    => GetSystemResources, "Return host resource availability.", 200, None, None;
+    post    Version2019_01_30 "/system/pruneimages"      => PruneImages, "Remove images that no module uses.", 200, Some("PruneImagesRequest"), Some("PruneImagesResult");
+    get     Version2019_01_30 "/system/edgeagent"        => GetEdgeAgentState, "Return the edge agent's twin properties.", 200, None, Some("TwinProperties");
+    get     Version2019_01_30 "/system/twin/desired"     => GetTwinDesired, "Return the desired properties of the device twin.", 200, None, Some("TwinDesired");
+    get     Version2019_01_30 "/system/capabilities"     => GetCapabilities, "Return the optional features the module runtime supports.", 200, None, Some("Capabilities");
+    get     Version2019_01_30 "/system/connectivity"     => GetConnectivityStatus, "Return whether the device can reach IoT Hub.", 200, None, Some("ConnectivityStatus");
+    get     Version2019_01_30 "/system/gcpolicy"         => GetGcPolicy, "Return the image garbage collection policy.", 200, None, Some("GcPolicy");
+    put     Version2019_01_30 "/system/gcpolicy"         => PutGcPolicy, "Replace the image garbage collection policy.", 200, Some("GcPolicy"), Some("GcPolicy");
+    get     Version2019_01_30 "/system/certificates"     => GetCertificateInventory, "List the certificates the daemon manages.", 200, None, Some("CertInfoList");
+    post    Version2019_01_30 "/system/certificates/renew" => RenewCertificate, "Renew a certificate and tell the modules that serve it to reload it.", 200, Some("RenewCertificateRequest"), Some("CertInfo");
+    get     Version2019_01_30 "/system/proxy"            => GetProxyConfig, "Return the upstream proxy configuration.", 200, None, Some("ProxyConfig");
+    put     Version2019_01_30 "/system/proxy"            => PutProxyConfig, "Replace the upstream proxy configuration.", 200, Some("ProxyConfig"), Some("ProxyConfig");
+    get     Version2019_01_30 "/system/config"           => GetDaemonConfig, "Return the daemon settings and where each came from.", 200, None, Some("DaemonConfigSources");
+    put     Version2019_01_30 "/system/config"           => UpdateDaemonConfig, "Change the daemon settings that take effect without a restart.", 200, Some("DaemonConfig"), Some("DaemonConfig");
+    get     Version2019_01_30 "/system/snapshot"         => GetSystemSnapshot, "Return the state of the host and of every module for troubleshooting.", 200, None, Some("SystemSnapshot");
+    delete  Version2019_01_30 "/system/state"            => ResetDaemonState, "Clear the daemon's cached state and provision the device again.", 202, None, None;
+
+    get     Version2019_01_30 "/auditlog"                => GetAuditLog, "Return the most recent management API calls.", 200, None, None;
+    get     Version2019_01_30 "/metrics"                 => GetMetrics, "Return runtime metrics.", 200, None, None;
+
+    get     Version2019_01_30 "/healthz"                 => GetHealth, "Return whether the management service is ready.", 200, None, None;
+    get     Version2019_01_30 "/healthz/live"            => GetLiveness, "Return whether the management service is running.", 200, None, None;
+    get     Version2019_01_30 "/healthz/ready"           => GetReadiness, "Return whether the management service is ready.", 200, None, None;
+
+    post    Version2019_01_30 "/auth/token"              => IssueAuthToken, "Issue an HMAC token for a management API request.", 200, Some("AuthTokenRequest"), Some("AuthToken");
+
+    get     Version2019_01_30 "/events"                  => WatchEvents, "Stream module lifecycle events.", 200, None, None;
+
+    get     Unversioned       "/versions"                => GetVersions, "List the supported API versions.", 200, None, Some("Versions");
+    get     Version2018_06_28 "/swagger.json"            => GetOpenApi, "Describe the management API.", 200, None, None;
+);
+
+/// Serves the OpenAPI 3.0 document of the management API on
+/// `GET /swagger.json`.
+///
+/// The document describes the API at the `api-version` of the request, so
+/// it lists only the routes that are available at that version.
+pub struct GetOpenApi;
+
+impl Handler<Parameters> for GetOpenApi {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = api_version(&req)
+            .and_then(|version| {
+                let b =
+                    serde_json::to_string(&document(version)).context(ErrorKind::DescribeApi)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::DescribeApi)?;
+                Ok(response)
+            })
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+fn api_version(req: &Request<Body>) -> Result<Version, Error> {
+    let query = req.uri().query().unwrap_or("");
+    let version = form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "api-version")
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_default();
+
+    version
+        .parse()
+        .map_err(|()| Error::from(ErrorKind::InvalidApiVersion(version)))
+}
+
+fn document(version: Version) -> Value {
+    let mut paths = Map::new();
+    for route in ROUTES
+        .iter()
+        .filter(|route| route.version.map_or(true, |v| v.contains(version)))
+    {
+        let path = paths
+            .entry(route.path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        path[route.method] = operation(route);
+    }
+
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "IoT Edge Management API",
+            "version": version.to_string(),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+        },
+    })
+}
+
+fn operation(route: &Route) -> Value {
+    let mut parameters = vec![];
+    if route.version.is_some() {
+        parameters.push(json!({
+            "name": "api-version",
+            "in": "query",
+            "required": true,
+            "schema": { "type": "string" },
+        }));
+    }
+    for name in &["tenant", "name", "key"] {
+        if route.path.contains(&format!("{{{}}}", name)) {
+            parameters.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            }));
+        }
+    }
+
+    let mut success = json!({ "description": "Ok" });
+    if let Some(schema) = route.response {
+        success["content"] = json_content(schema);
+    }
+    let mut responses = Map::new();
+    responses.insert(route.status.to_string(), success);
+    responses.insert(
+        "default".to_string(),
+        json!({
+            "description": "Error",
+            "content": json_content("ErrorResponse"),
+        }),
+    );
+
+    let mut operation = json!({
+        "operationId": route.operation_id,
+        "summary": route.summary,
+        "parameters": parameters,
+        "responses": responses,
+    });
+    if let Some(schema) = route.request {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": json_content(schema),
+        });
+    }
+    operation
+}
+
+fn json_content(schema: &str) -> Value {
+    json!({
+        "application/json": {
+            "schema": { "$ref": format!("#/components/schemas/{}", schema) },
+        },
+    })
+}
+
+fn schemas() -> Value {
+    json!({
+        "ModuleSpec": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "type": { "type": "string" },
+                "config": { "$ref": "#/components/schemas/Config" },
+                "labels": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                },
+                "resource_limits": { "$ref": "#/components/schemas/ResourceLimits" },
+                "depends_on": { "type": "array", "items": { "type": "string" } },
+            },
+            "required": ["name", "type", "config"],
+        },
+        "ModuleDetails": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "name": { "type": "string" },
+                "type": { "type": "string" },
+                "config": { "$ref": "#/components/schemas/Config" },
+                "status": { "type": "object" },
+            },
+            "required": ["id", "name", "type", "config", "status"],
+        },
+        "ModuleGraph": {
+            "type": "object",
+            "properties": {
+                "nodes": { "type": "array", "items": { "type": "string" } },
+                "edges": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "from": { "type": "string" },
+                            "to": { "type": "string" },
+                            "type": { "type": "string", "enum": ["depends_on"] },
+                        },
+                        "required": ["from", "to", "type"],
+                    },
+                },
+                "start_order": { "type": "array", "items": { "type": "string" } },
+            },
+            "required": ["nodes", "edges", "start_order"],
+        },
+        "Config": {
+            "type": "object",
+            "properties": {
+                "settings": { "type": "object" },
+                "env": {
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/EnvVar" },
+                },
+            },
+            "required": ["settings"],
+        },
+        "EnvVar": {
+            "type": "object",
+            "properties": {
+                "key": { "type": "string" },
+                "value": { "type": "string" },
+            },
+            "required": ["key", "value"],
+        },
+        "BandwidthLimits": {
+            "type": "object",
+            "properties": {
+                "ingress_kbps": { "type": "integer", "format": "int32", "minimum": 0 },
+                "egress_kbps": { "type": "integer", "format": "int32", "minimum": 0 },
+            },
+            "required": ["ingress_kbps", "egress_kbps"],
+        },
+        "ResourceLimits": {
+            "type": "object",
+            "properties": {
+                "cpu_shares": { "type": "integer", "format": "int32", "minimum": 0 },
+                "memory_limit_bytes": { "type": "integer", "format": "int64", "minimum": 0 },
+                "memory_swap_bytes": { "type": "integer", "format": "int64" },
+            },
+        },
+        "SystemInfo": {
+            "type": "object",
+            "properties": {
+                "osType": { "type": "string" },
+                "architecture": { "type": "string" },
+                "version": { "type": "string" },
+                "cgroupVersion": { "type": "string", "enum": ["v1", "v2"] },
+            },
+            "required": ["osType", "architecture"],
+        },
+        "IdentitySpec": {
+            "type": "object",
+            "properties": {
+                "moduleId": { "type": "string" },
+                "managedBy": { "type": "string" },
+                "x509Thumbprint": { "type": "string" },
+            },
+            "required": ["moduleId"],
+        },
+        "Versions": {
+            "type": "object",
+            "properties": {
+                "supported_versions": { "type": "array", "items": { "type": "string" } },
+                "latest": { "type": "string" },
+            },
+            "required": ["supported_versions", "latest"],
+        },
+        "ExecRequest": {
+            "type": "object",
+            "properties": {
+                "cmd": { "type": "array", "items": { "type": "string" } },
+                "tty": { "type": "boolean" },
+            },
+            "required": ["cmd"],
+        },
+        "PortBindingList": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "container_port": { "type": "integer" },
+                    "protocol": { "type": "string" },
+                    "host_ip": { "type": "string" },
+                    "host_port": { "type": "integer" },
+                },
+                "required": ["container_port", "protocol"],
+            },
+        },
+        "ModuleTop": {
+            "type": "object",
+            "properties": {
+                "titles": { "type": "array", "items": { "type": "string" } },
+                "processes": {
+                    "type": "array",
+                    "items": { "type": "array", "items": { "type": "string" } },
+                },
+            },
+            "required": ["titles", "processes"],
+        },
+        "VolumeMountList": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "container_path": { "type": "string" },
+                    "host_path": { "type": "string" },
+                    "mode": { "type": "string", "enum": ["rw", "ro"] },
+                },
+                "required": ["container_path", "host_path", "mode"],
+            },
+        },
+        "RollbackResult": {
+            "type": "object",
+            "properties": {
+                "rolled_back_to": {
+                    "type": "object",
+                    "properties": {
+                        "image": { "type": "string" },
+                        "timestamp": { "type": "string", "format": "date-time" },
+                    },
+                    "required": ["image", "timestamp"],
+                },
+            },
+            "required": ["rolled_back_to"],
+        },
+        "ModuleHistory": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "timestamp": { "type": "string", "format": "date-time" },
+                    "image": { "type": "string" },
+                    "spec_hash": { "type": "string" },
+                },
+                "required": ["timestamp", "image", "spec_hash"],
+            },
+        },
+        "SignalRequest": {
+            "type": "object",
+            "properties": {
+                "signal": {
+                    "oneOf": [
+                        { "type": "integer" },
+                        { "type": "string" },
+                    ],
+                },
+            },
+            "required": ["signal"],
+        },
+        "CheckpointRequest": {
+            "type": "object",
+            "properties": {
+                "checkpoint_id": { "type": "string" },
+            },
+            "required": ["checkpoint_id"],
+        },
+        "CopyLogsRequest": {
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "tail": { "type": "integer", "format": "int64", "minimum": 0 },
+            },
+            "required": ["path"],
+        },
+        "CopyLogsResult": {
+            "type": "object",
+            "properties": {
+                "bytes_written": { "type": "integer", "format": "int64" },
+                "path": { "type": "string" },
+            },
+            "required": ["bytes_written", "path"],
+        },
+        "PromoteRequest": {
+            "type": "object",
+            "properties": {
+                "new_name": { "type": "string" },
+            },
+            "required": ["new_name"],
+        },
+        "RenameRequest": {
+            "type": "object",
+            "properties": {
+                "new_name": { "type": "string" },
+            },
+            "required": ["new_name"],
+        },
+        "UpdateImageRequest": {
+            "type": "object",
+            "properties": {
+                "image": { "type": "string" },
+            },
+            "required": ["image"],
+        },
+        "UpdateImageResult": {
+            "type": "object",
+            "properties": {
+                "changed": { "type": "boolean" },
+                "previous_image": { "type": "string" },
+            },
+            "required": ["changed"],
+        },
+        "PrepareImageRequest": {
+            "type": "object",
+            "properties": {
+                "image": { "type": "string" },
+                "registryAuth": {
+                    "type": "object",
+                    "properties": {
+                        "server": { "type": "string" },
+                        "username": { "type": "string" },
+                        "password": { "type": "string" },
+                    },
+                },
+            },
+            "required": ["image"],
+        },
+        "PruneImagesRequest": {
+            "type": "object",
+            "properties": {
+                "dangling_only": { "type": "boolean", "default": true },
+            },
+        },
+        "PruneImagesResult": {
+            "type": "object",
+            "properties": {
+                "reclaimed_bytes": { "type": "integer", "format": "int64" },
+                "removed_images": { "type": "array", "items": { "type": "string" } },
+            },
+            "required": ["reclaimed_bytes", "removed_images"],
+        },
+        "AuthTokenRequest": {
+            "type": "object",
+            "properties": {
+                "module_id": { "type": "string" },
+                "generation_id": { "type": "string" },
+                "sas_token": { "type": "string" },
+                "method": { "type": "string" },
+                "path": { "type": "string" },
+            },
+            "required": ["module_id", "generation_id", "sas_token", "method", "path"],
+        },
+        "AuthToken": {
+            "type": "object",
+            "properties": {
+                "token": { "type": "string" },
+                "expires_at": { "type": "string", "format": "date-time" },
+            },
+            "required": ["token", "expires_at"],
+        },
+        "Capabilities": {
+            "type": "object",
+            "properties": {
+                "exec": { "type": "boolean" },
+                "checkpoint": { "type": "boolean" },
+                "signal": { "type": "boolean" },
+                "top": { "type": "boolean" },
+                "stats": { "type": "boolean" },
+                "update_resources": { "type": "boolean" },
+                "pull_image": { "type": "boolean" },
+                "prune_images": { "type": "boolean" },
+                "events": { "type": "boolean" },
+                "bandwidth": { "type": "boolean" },
+            },
+            "required": ["exec", "checkpoint", "signal", "top", "stats", "update_resources", "pull_image", "prune_images", "events", "bandwidth"],
+        },
+        "ConnectivityStatus": {
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "enum": ["connected", "disconnected", "unknown"] },
+                "since": { "type": "string", "format": "date-time" },
+                "last_error": { "type": "string" },
+            },
+            "required": ["status"],
+        },
+        "GcPolicy": {
+            "type": "object",
+            "properties": {
+                "min_free_bytes": { "type": "integer", "format": "int64", "minimum": 0 },
+                "max_image_age_secs": { "type": "integer", "format": "int64", "minimum": 0 },
+            },
+        },
+        "DaemonConfig": {
+            "type": "object",
+            "description": "The settings of the daemon, as in its config file, with secrets redacted.",
+            "additionalProperties": true,
+        },
+        "DaemonConfigSources": {
+            "type": "object",
+            "description": "Every top-level section of the daemon settings, with secrets redacted, and where it came from.",
+            "additionalProperties": {
+                "type": "object",
+                "properties": {
+                    "value": {},
+                    "source": { "type": "string", "enum": ["file", "env", "default"] },
+                },
+            },
+        },
+        "ProxyConfig": {
+            "type": "object",
+            "properties": {
+                "https_proxy": { "type": "string" },
+                "http_proxy": { "type": "string" },
+                "no_proxy": { "type": "string" },
+            },
+        },
+        "CertInfo": {
+            "type": "object",
+            "properties": {
+                "alias": { "type": "string" },
+                "not_before": { "type": "string", "format": "date-time" },
+                "not_after": { "type": "string", "format": "date-time" },
+                "thumbprint": { "type": "string" },
+                "subject_cn": { "type": "string" },
+            },
+            "required": ["alias", "not_before", "not_after", "thumbprint", "subject_cn"],
+        },
+        "CertInfoList": {
+            "type": "array",
+            "items": { "$ref": "#/components/schemas/CertInfo" },
+        },
+        "TrustedCa": {
+            "type": "object",
+            "properties": {
+                "subject": { "type": "string" },
+                "thumbprint": { "type": "string" },
+                "not_after": { "type": "string", "format": "date-time" },
+            },
+            "required": ["subject", "thumbprint", "not_after"],
+        },
+        "TrustedCaList": {
+            "type": "array",
+            "items": { "$ref": "#/components/schemas/TrustedCa" },
+        },
+        "AddTrustedCaRequest": {
+            "type": "object",
+            "properties": {
+                "certificate": { "type": "string" },
+            },
+            "required": ["certificate"],
+        },
+        "RenewCertificateRequest": {
+            "type": "object",
+            "properties": {
+                "alias": { "type": "string" },
+            },
+            "required": ["alias"],
+        },
+        "SystemSnapshot": {
+            "type": "object",
+            "properties": {
+                "timestamp": { "type": "string", "format": "date-time" },
+                "system_info": { "type": "object" },
+                "system_resources": { "type": "object" },
+                "disk_usage": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "mount_point": { "type": "string" },
+                            "total_bytes": { "type": "integer", "format": "int64" },
+                            "free_bytes": { "type": "integer", "format": "int64" },
+                        },
+                    },
+                },
+                "network_interfaces": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "addresses": { "type": "array", "items": { "type": "string" } },
+                        },
+                    },
+                },
+                "modules": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "state": { "type": "object" },
+                        },
+                    },
+                },
+                "events": { "type": "array", "items": { "type": "object" } },
+            },
+        },
+        "TwinProperties": {
+            "type": "object",
+            "properties": {
+                "desired": { "type": "object" },
+                "reported": { "type": "object" },
+            },
+        },
+        "TwinDesired": {
+            "type": "object",
+            "properties": {
+                "desired": { "type": "object" },
+                "last_updated": { "type": "string", "format": "date-time" },
+            },
+            "required": ["desired", "last_updated"],
+        },
+        "UpdateIdentity": {
+            "type": "object",
+            "properties": {
+                "generationId": { "type": "string" },
+                "managedBy": { "type": "string" },
+                "x509Thumbprint": { "type": "string" },
+            },
+            "required": ["generationId"],
+        },
+        "Identity": {
+            "type": "object",
+            "properties": {
+                "moduleId": { "type": "string" },
+                "managedBy": { "type": "string" },
+                "generationId": { "type": "string" },
+                "authType": { "type": "string", "enum": ["None", "Sas", "X509"] },
+            },
+            "required": ["moduleId", "managedBy", "generationId", "authType"],
+        },
+        "IdentityList": {
+            "type": "object",
+            "properties": {
+                "identities": {
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/Identity" },
+                },
+            },
+            "required": ["identities"],
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "properties": {
+                "code": { "type": "string" },
+                "message": { "type": "string" },
+                "correlationId": { "type": "string" },
+            },
+            "required": ["message"],
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    fn get(api_version: &str) -> Response<Body> {
+        let request = Request::get(format!(
+            "http://localhost/swagger.json?api-version={}",
+            api_version
+        ))
+        .body(Body::default())
+        .unwrap();
+        GetOpenApi
+            .handle(request, Parameters::new())
+            .wait()
+            .unwrap()
+    }
+
+    fn document(api_version: &str) -> Value {
+        let response = get(api_version);
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn describes_routes_of_requested_version() {
+        let doc = document("2019-01-30");
+        assert_eq!("3.0.0", doc["openapi"]);
+        assert_eq!("2019-01-30", doc["info"]["version"]);
+        assert_eq!(
+            "GetModuleStatus",
+            doc["paths"]["/modules/{name}/status"]["get"]["operationId"]
+        );
+
+        let doc = document("2018-06-28");
+        assert_eq!("2018-06-28", doc["info"]["version"]);
+        assert!(doc["paths"].get("/modules/{name}/status").is_none());
+        assert!(doc["paths"]["/modules/{name}"].get("patch").is_none());
+        assert!(doc["paths"]["/modules/{name}"].get("put").is_some());
+        assert_eq!(
+            0,
+            doc["paths"]["/versions"]["get"]["parameters"]
+                .as_array()
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn schema_references_resolve() {
+        let doc = document("2019-01-30");
+        let schemas = &doc["components"]["schemas"];
+
+        let mut refs = Vec::new();
+        collect_refs(&doc, &mut refs);
+        assert!(refs.contains(&"#/components/schemas/ModuleSpec".to_string()));
+        for r in refs {
+            let name = r.trim_start_matches("#/components/schemas/");
+            assert!(schemas.get(name).is_some(), "unresolved {}", r);
+        }
+    }
+
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match value {
+                        Value::String(s) if key == "$ref" => refs.push(s.clone()),
+                        _ => collect_refs(value, refs),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|value| collect_refs(value, refs)),
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn path_parameters_are_declared() {
+        let doc = document("2019-01-30");
+        let parameters = doc["paths"]["/identities/{name}"]["delete"]["parameters"]
+            .as_array()
+            .unwrap();
+        assert!(parameters
+            .iter()
+            .any(|p| p["name"] == "name" && p["in"] == "path"));
+
+        let parameters = doc["paths"]["/tenants/{tenant}/modules/{name}"]["put"]["parameters"]
+            .as_array()
+            .unwrap();
+        for name in &["tenant", "name"] {
+            assert!(parameters
+                .iter()
+                .any(|p| p["name"] == *name && p["in"] == "path"));
+        }
+
+        let parameters = doc["paths"]["/modules/{name}/labels/{key}"]["put"]["parameters"]
+            .as_array()
+            .unwrap();
+        for name in &["name", "key"] {
+            assert!(parameters
+                .iter()
+                .any(|p| p["name"] == *name && p["in"] == "path"));
+        }
+    }
+
+    #[test]
+    fn invalid_api_version() {
+        let response = get("2000-01-01");
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!("InvalidApiVersion", error.code().unwrap());
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Returns which of the optional features, like `exec` and checkpoints, the
+/// module runtime supports, so that clients can check before using the
+/// endpoints that need them instead of handling their errors.
+pub struct GetCapabilities<M> {
+    runtime: M,
+}
+
+impl<M> GetCapabilities<M> {
+    pub fn new(runtime: M) -> Self {
+        GetCapabilities { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetCapabilities<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = serde_json::to_string(&self.runtime.capabilities())
+            .context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::GetCapabilities,
+            ))
+            .map_err(Error::from)
+            .and_then(|b| {
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetCapabilities,
+                    ))
+                    .map_err(Error::from)
+            })
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{Capabilities, ModuleRuntimeState};
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::new(Ok(module))
+            .with_capabilities(Capabilities::default().with_exec(true).with_signal(true));
+        let handler = GetCapabilities::new(runtime);
+        let request = Request::get("http://localhost/system/capabilities")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let capabilities: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!({
+                "exec": true,
+                "checkpoint": false,
+                "signal": true,
+                "top": false,
+                "stats": false,
+                "update_resources": false,
+                "pull_image": false,
+                "prune_images": false,
+                "events": false,
+                "bandwidth": false,
+            }),
+            capabilities
+        );
+    }
+}
@@ -0,0 +1,544 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::{Duration, Utc};
+use failure::{Fail, ResultExt};
+use futures::future;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::{info, warn};
+use serde_derive::Deserialize;
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{
+    CertInfo, CertificateManager, CertificateManagerErrorReason, Module, ModuleRuntime,
+    ModuleStatus,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The label of a module's spec that names the certificate the module
+/// serves, so that the module is told to reload it when it is renewed.
+pub const CERTIFICATE_ALIAS_LABEL: &str = "edgelet.certificate-alias";
+
+const SIGHUP: u32 = 1;
+
+/// Lists the certificates the daemon manages, so monitoring agents can alert
+/// on certificates that are about to expire.
+///
+/// With `?expiring_within_days=N` only the certificates that expire within
+/// the next `N` days, or already have, are listed.
+pub struct GetCertificateInventory<C> {
+    certificates: C,
+}
+
+impl<C> GetCertificateInventory<C> {
+    pub fn new(certificates: C) -> Self {
+        GetCertificateInventory { certificates }
+    }
+}
+
+impl<C> Handler<Parameters> for GetCertificateInventory<C>
+where
+    C: 'static + CertificateManager + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = parse_expiring_within_days(req.uri().query().unwrap_or(""))
+            .map(|expiring_within_days| {
+                self.certificates
+                    .list()
+                    .then(move |certificates| -> Result<_, Error> {
+                        let mut certificates = certificates.context(ErrorKind::ListCertificates)?;
+                        if let Some(days) = expiring_within_days {
+                            let deadline = Utc::now() + Duration::days(days);
+                            certificates.retain(|cert| *cert.not_after() <= deadline);
+                        }
+
+                        let b = serde_json::to_string(&certificates)
+                            .context(ErrorKind::ListCertificates)?;
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .context(ErrorKind::ListCertificates)?;
+                        Ok(response)
+                    })
+            })
+            .into_future()
+            .flatten()
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn parse_expiring_within_days(query: &str) -> Result<Option<i64>, Error> {
+    match form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "expiring_within_days") {
+        Some((_, val)) => match val.parse::<u32>() {
+            Ok(days) => Ok(Some(i64::from(days))),
+            Err(_) => Err(Error::from(ErrorKind::MalformedRequestParameter(
+                "expiring_within_days",
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+#[derive(Deserialize)]
+struct RenewRequest {
+    alias: String,
+}
+
+/// Renews a certificate on demand and responds with the new certificate.
+///
+/// Every running module whose `edgelet.certificate-alias` label is the
+/// alias is sent `SIGHUP` afterwards so it reloads the certificate. Modules
+/// that can't be signaled are only logged, since the certificate has been
+/// renewed by then.
+pub struct RenewCertificate<C, M> {
+    certificates: C,
+    runtime: M,
+}
+
+impl<C, M> RenewCertificate<C, M> {
+    pub fn new(certificates: C, runtime: M) -> Self {
+        RenewCertificate {
+            certificates,
+            runtime,
+        }
+    }
+}
+
+impl<C, M> Handler<Parameters> for RenewCertificate<C, M>
+where
+    C: 'static + CertificateManager + Clone + Send,
+    for<'r> &'r C::Error: Into<CertificateManagerErrorReason>,
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let certificates = self.certificates.clone();
+        let runtime = self.runtime.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(|b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let request = serde_json::from_slice::<RenewRequest>(&b)
+                    .context(ErrorKind::MalformedRequestBody)?;
+                Ok(request.alias)
+            })
+            .and_then(move |alias| {
+                certificates.renew(&alias).then(move |result| match result {
+                    Ok(cert) => Ok((alias, cert)),
+                    Err(err) => {
+                        let reason: CertificateManagerErrorReason = (&err).into();
+                        match reason {
+                            CertificateManagerErrorReason::NotFound => {
+                                Err(Error::from(ErrorKind::CertificateNotFound(alias)))
+                            }
+                            CertificateManagerErrorReason::RenewalInProgress => {
+                                Err(Error::from(ErrorKind::CertificateRenewalInProgress(alias)))
+                            }
+                            CertificateManagerErrorReason::Other => {
+                                Err(Error::from(err.context(ErrorKind::RenewCertificate(alias))))
+                            }
+                        }
+                    }
+                })
+            })
+            .and_then(move |(alias, cert)| {
+                reload_modules(&runtime, alias.clone())
+                    .then(move |_| -> Result<_, Error> { renewed_response(&alias, &cert) })
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Sends `SIGHUP` to the running modules that serve the certificate `alias`.
+fn reload_modules<M>(runtime: &M, alias: String) -> impl Future<Item = (), Error = ()>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    let runtime = runtime.clone();
+
+    runtime
+        .list_with_details()
+        .collect()
+        .then(move |modules| match modules {
+            Ok(modules) => {
+                let signals = modules
+                    .into_iter()
+                    .filter(|(module, state)| {
+                        *state.status() == ModuleStatus::Running
+                            && module.labels().get(CERTIFICATE_ALIAS_LABEL) == Some(&alias)
+                    })
+                    .map(|(module, _)| {
+                        let name = module.name().to_string();
+                        info!(
+                            "Sending SIGHUP to module {} to reload certificate {}",
+                            name, alias
+                        );
+                        runtime.signal(&name, SIGHUP).then(move |result| {
+                            if let Err(err) = result {
+                                warn!("Could not signal module {}: {}", name, err);
+                            }
+                            Ok(())
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                future::Either::A(future::join_all(signals).map(|_| ()))
+            }
+            Err(err) => {
+                warn!(
+                    "Could not list the modules to reload certificate {}: {}",
+                    alias, err
+                );
+                future::Either::B(future::ok(()))
+            }
+        })
+}
+
+fn renewed_response(alias: &str, cert: &CertInfo) -> Result<Response<Body>, Error> {
+    let b = serde_json::to_string(cert)
+        .with_context(|_| ErrorKind::RenewCertificate(alias.to_string()))?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| ErrorKind::RenewCertificate(alias.to_string()))?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use futures::future::FutureResult;
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::server::module::tests::Error as RuntimeError;
+
+    #[derive(Clone, Copy, Debug, Fail)]
+    enum TestError {
+        #[fail(display = "General error")]
+        General,
+        #[fail(display = "Certificate not found")]
+        NotFound,
+        #[fail(display = "Renewal in progress")]
+        RenewalInProgress,
+    }
+
+    impl<'a> From<&'a TestError> for CertificateManagerErrorReason {
+        fn from(err: &'a TestError) -> Self {
+            match err {
+                TestError::General => CertificateManagerErrorReason::Other,
+                TestError::NotFound => CertificateManagerErrorReason::NotFound,
+                TestError::RenewalInProgress => CertificateManagerErrorReason::RenewalInProgress,
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestCertificates {
+        certificates: Vec<CertInfo>,
+        error: Option<TestError>,
+    }
+
+    impl TestCertificates {
+        fn new(certificates: Vec<CertInfo>) -> Self {
+            TestCertificates {
+                certificates,
+                error: None,
+            }
+        }
+
+        fn with_error(mut self, error: TestError) -> Self {
+            self.error = Some(error);
+            self
+        }
+    }
+
+    impl CertificateManager for TestCertificates {
+        type Error = TestError;
+        type ListFuture = FutureResult<Vec<CertInfo>, Self::Error>;
+        type RenewFuture = FutureResult<CertInfo, Self::Error>;
+
+        fn list(&self) -> Self::ListFuture {
+            match self.error {
+                Some(err) => future::err(err),
+                None => future::ok(self.certificates.clone()),
+            }
+        }
+
+        fn renew(&self, alias: &str) -> Self::RenewFuture {
+            match self.error {
+                Some(err) => future::err(err),
+                None => self
+                    .certificates
+                    .iter()
+                    .find(|cert| cert.alias() == alias)
+                    .map(|cert| {
+                        CertInfo::new(
+                            alias.to_string(),
+                            Utc::now(),
+                            Utc::now() + Duration::days(90),
+                            "CD".repeat(20),
+                            cert.subject_cn().to_string(),
+                        )
+                    })
+                    .ok_or(TestError::NotFound)
+                    .into_future(),
+            }
+        }
+    }
+
+    fn cert(alias: &str, days_left: i64) -> CertInfo {
+        let now = Utc::now();
+        CertInfo::new(
+            alias.to_string(),
+            now - Duration::days(30),
+            now + Duration::days(days_left),
+            "AB".repeat(20),
+            alias.to_string(),
+        )
+    }
+
+    fn handle(certificates: TestCertificates, uri: &str) -> Response<Body> {
+        let handler = GetCertificateInventory::new(certificates);
+        let request = Request::get(uri).body(Body::default()).unwrap();
+        handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap()
+    }
+
+    fn aliases(response: Response<Body>) -> Vec<String> {
+        let b = response.into_body().concat2().wait().unwrap();
+        let certificates: Vec<Value> = serde_json::from_slice(&b).unwrap();
+        certificates
+            .iter()
+            .map(|cert| cert["alias"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn module_runtime(alias: &str, status: ModuleStatus) -> TestRuntime<RuntimeError> {
+        let mut labels = HashMap::new();
+        labels.insert(CERTIFICATE_ALIAS_LABEL.to_string(), alias.to_string());
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new("mod1".to_string(), config, Ok(state)).with_labels(labels);
+        TestRuntime::new(Ok(module))
+    }
+
+    fn renew(
+        certificates: TestCertificates,
+        runtime: &TestRuntime<RuntimeError>,
+        body: &str,
+    ) -> Response<Body> {
+        let handler = RenewCertificate::new(certificates, runtime.clone());
+        let request = Request::post("http://localhost/system/certificates/renew")
+            .body(body.to_string().into())
+            .unwrap();
+        handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap()
+    }
+
+    fn error_code(response: Response<Body>) -> Option<String> {
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        error.code().map(ToString::to_string)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let certificates = TestCertificates::new(vec![cert("ca", 90), cert("mod1server", 3)]);
+
+        // act
+        let response = handle(certificates, "http://localhost/system/certificates");
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let certificates: Vec<Value> = serde_json::from_slice(&b).unwrap();
+        assert_eq!(2, certificates.len());
+        assert_eq!("ca", certificates[0]["alias"]);
+        assert_eq!("ca", certificates[0]["subject_cn"]);
+        assert_eq!("AB".repeat(20), certificates[0]["thumbprint"]);
+        assert!(certificates[0]["not_before"].is_string());
+        assert!(certificates[0]["not_after"].is_string());
+    }
+
+    #[test]
+    fn expiring_within_days_filters_certificates() {
+        // arrange
+        let certificates = TestCertificates::new(vec![
+            cert("ca", 90),
+            cert("mod1server", 3),
+            cert("expired", -1),
+        ]);
+
+        // act
+        let response = handle(
+            certificates,
+            "http://localhost/system/certificates?expiring_within_days=7",
+        );
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(vec!["mod1server", "expired"], aliases(response));
+    }
+
+    #[test]
+    fn malformed_expiring_within_days_is_bad_request() {
+        // arrange
+        let certificates = TestCertificates::new(vec![]);
+
+        // act
+        let response = handle(
+            certificates,
+            "http://localhost/system/certificates?expiring_within_days=soon",
+        );
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            Some("MalformedRequestParameter".to_string()),
+            error_code(response)
+        );
+    }
+
+    #[test]
+    fn list_error() {
+        // arrange
+        let certificates = TestCertificates::new(vec![]).with_error(TestError::General);
+
+        // act
+        let response = handle(certificates, "http://localhost/system/certificates");
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not list certificates\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn renew_signals_modules_serving_certificate() {
+        // arrange
+        let certificates = TestCertificates::new(vec![cert("mod1server", 3)]);
+        let runtime = module_runtime("mod1server", ModuleStatus::Running);
+
+        // act
+        let response = renew(certificates, &runtime, r#"{"alias":"mod1server"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let cert: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!("mod1server", cert["alias"]);
+        assert_eq!("CD".repeat(20), cert["thumbprint"]);
+        assert_eq!(vec![("mod1".to_string(), SIGHUP)], runtime.signals());
+    }
+
+    #[test]
+    fn renew_does_not_signal_other_modules() {
+        // arrange
+        let certificates = TestCertificates::new(vec![cert("mod1server", 3), cert("ca", 90)]);
+        let runtime = module_runtime("mod1server", ModuleStatus::Running);
+        let stopped = module_runtime("mod1server", ModuleStatus::Stopped);
+
+        // act
+        let response = renew(certificates.clone(), &runtime, r#"{"alias":"ca"}"#);
+        let stopped_response = renew(certificates, &stopped, r#"{"alias":"mod1server"}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(StatusCode::OK, stopped_response.status());
+        assert!(runtime.signals().is_empty());
+        assert!(stopped.signals().is_empty());
+    }
+
+    #[test]
+    fn renew_unknown_alias_is_not_found() {
+        // arrange
+        let certificates = TestCertificates::new(vec![cert("mod1server", 3)]);
+        let runtime = module_runtime("mod1server", ModuleStatus::Running);
+
+        // act
+        let response = renew(certificates, &runtime, r#"{"alias":"device-ca"}"#);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(
+            Some("CertificateNotFound".to_string()),
+            error_code(response)
+        );
+        assert!(runtime.signals().is_empty());
+    }
+
+    #[test]
+    fn renew_in_progress_is_conflict() {
+        // arrange
+        let certificates = TestCertificates::new(vec![cert("mod1server", 3)])
+            .with_error(TestError::RenewalInProgress);
+        let runtime = module_runtime("mod1server", ModuleStatus::Running);
+
+        // act
+        let response = renew(certificates, &runtime, r#"{"alias":"mod1server"}"#);
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        assert_eq!(
+            Some("CertificateRenewalInProgress".to_string()),
+            error_code(response)
+        );
+    }
+
+    #[test]
+    fn renew_without_alias_is_bad_request() {
+        // arrange
+        let certificates = TestCertificates::new(vec![cert("mod1server", 3)]);
+        let runtime = module_runtime("mod1server", ModuleStatus::Running);
+
+        // act
+        let response = renew(certificates, &runtime, "{}");
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            Some("MalformedRequestBody".to_string()),
+            error_code(response)
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use serde_json::{self, json, Map, Value};
+
+use edgelet_core::{
+    redact_daemon_config, DaemonConfigSource, DaemonConfigStore, ErrorKind as CoreErrorKind,
+    RELOADABLE_SETTINGS,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::server::module::{changed_paths, merge};
+use crate::IntoResponse;
+
+/// Returns the settings of the daemon in effect, with their secrets
+/// redacted. Every top-level section is returned as its `value` and the
+/// `source` it came from, `file`, `env` or `default`, so that a setting
+/// overridden by an environment variable is easy to spot.
+pub struct GetDaemonConfig<D> {
+    store: D,
+}
+
+impl<D> GetDaemonConfig<D> {
+    pub fn new(store: D) -> Self {
+        GetDaemonConfig { store }
+    }
+}
+
+impl<D> Handler<Parameters> for GetDaemonConfig<D>
+where
+    D: 'static + DaemonConfigStore + Send + Sync,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = config_response(&self.store)
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+fn config_response<D: DaemonConfigStore>(store: &D) -> Result<Response<Body>, Error> {
+    let config = store.get().context(ErrorKind::GetDaemonConfig)?;
+    let sources = store.sources().context(ErrorKind::GetDaemonConfig)?;
+    let sections: Map<String, Value> = match redact_daemon_config(config) {
+        Value::Object(config) => config
+            .into_iter()
+            .map(|(key, value)| {
+                let source = sources
+                    .get(&key)
+                    .cloned()
+                    .unwrap_or(DaemonConfigSource::Default);
+                (key, json!({ "value": value, "source": source }))
+            })
+            .collect(),
+        _ => Map::new(),
+    };
+
+    let b = serde_json::to_string(&sections).context(ErrorKind::GetDaemonConfig)?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::GetDaemonConfig)?;
+    Ok(response)
+}
+
+/// Changes the settings of the daemon with a JSON Merge Patch (RFC 7396) of
+/// its config, and returns all of the settings in effect afterwards, with
+/// their secrets redacted.
+///
+/// Only the settings in `RELOADABLE_SETTINGS`, like the image garbage
+/// collection policy and the upstream proxies, take effect without a
+/// restart. A patch that changes any other setting, like the URIs the APIs
+/// listen on or the certificates, gets a `409` response that lists those
+/// settings, and nothing is changed. Every setting that changes is logged.
+pub struct UpdateDaemonConfig<D> {
+    store: D,
+}
+
+impl<D> UpdateDaemonConfig<D> {
+    pub fn new(store: D) -> Self {
+        UpdateDaemonConfig { store }
+    }
+}
+
+impl<D> Handler<Parameters> for UpdateDaemonConfig<D>
+where
+    D: 'static + DaemonConfigStore + Clone + Send + Sync,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let store = self.store.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(move |b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let patch =
+                    serde_json::from_slice::<Value>(&b).context(ErrorKind::MalformedRequestBody)?;
+                if !patch.is_object() {
+                    return Err(Error::from(ErrorKind::MalformedRequestBody));
+                }
+
+                let current = store.get().context(ErrorKind::UpdateDaemonConfig)?;
+                let mut config = current.clone();
+                merge(&mut config, patch);
+
+                let changed = changed_paths(&current, &config);
+                let restart_required = restart_required(&changed);
+                if !restart_required.is_empty() {
+                    return Err(Error::from(ErrorKind::RestartRequired(restart_required)));
+                }
+                if !changed.is_empty() {
+                    for path in &changed {
+                        info!("Daemon setting {} changed", path);
+                    }
+                    store.set(config).map_err(|err| {
+                        let kind = match err.kind() {
+                            CoreErrorKind::InvalidDaemonConfig(_) => {
+                                ErrorKind::MalformedRequestBody
+                            }
+                            _ => ErrorKind::UpdateDaemonConfig,
+                        };
+                        Error::from(err.context(kind))
+                    })?;
+                }
+
+                let config = store.get().context(ErrorKind::UpdateDaemonConfig)?;
+                let b = serde_json::to_string(&redact_daemon_config(config))
+                    .context(ErrorKind::UpdateDaemonConfig)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::UpdateDaemonConfig)?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// The top-level settings of the `changed` paths that need a restart of the
+/// daemon, in order and without duplicates.
+fn restart_required(changed: &[String]) -> Vec<String> {
+    let mut keys: Vec<String> = changed
+        .iter()
+        .filter_map(|path| path.split('/').nth(1))
+        .filter(|key| !RELOADABLE_SETTINGS.contains(key))
+        .map(ToString::to_string)
+        .collect();
+    keys.dedup();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::InMemoryDaemonConfigStore;
+    use serde_json::json;
+
+    use super::*;
+
+    fn store() -> InMemoryDaemonConfigStore {
+        InMemoryDaemonConfigStore::new(json!({
+            "provisioning": { "source": "manual", "device_connection_string": "secret" },
+            "listen": { "management_uri": "unix:///var/run/iotedge/mgmt.sock" },
+            "image_gc": { "min_free_bytes": 0, "max_image_age_secs": 0 },
+        }))
+    }
+
+    #[test]
+    fn settings_are_returned_with_their_sources() {
+        // arrange
+        let store = store().with_source("listen", DaemonConfigSource::Env);
+        let handler = GetDaemonConfig::new(store);
+        let request = Request::get("http://localhost/system/config")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({
+                "provisioning": {
+                    "value": { "source": "manual", "device_connection_string": "***" },
+                    "source": "default",
+                },
+                "listen": {
+                    "value": { "management_uri": "unix:///var/run/iotedge/mgmt.sock" },
+                    "source": "env",
+                },
+                "image_gc": {
+                    "value": { "min_free_bytes": 0, "max_image_age_secs": 0 },
+                    "source": "default",
+                },
+            }),
+            body(response)
+        );
+    }
+
+    fn handle(store: &InMemoryDaemonConfigStore, body: &str) -> Response<Body> {
+        let handler = UpdateDaemonConfig::new(store.clone());
+        let request = Request::put("http://localhost/system/config")
+            .body(body.to_string().into())
+            .unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    fn body(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn reloadable_settings_are_applied() {
+        // arrange
+        let store = store();
+
+        // act
+        let response = handle(
+            &store,
+            r#"{"image_gc":{"min_free_bytes":1024},"proxy":{"https_proxy":"http://proxy:3128/"}}"#,
+        );
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({
+                "provisioning": { "source": "manual", "device_connection_string": "***" },
+                "listen": { "management_uri": "unix:///var/run/iotedge/mgmt.sock" },
+                "image_gc": { "min_free_bytes": 1024, "max_image_age_secs": 0 },
+                "proxy": { "https_proxy": "http://proxy:3128/" },
+            }),
+            body(response)
+        );
+        assert_eq!(
+            json!({ "min_free_bytes": 1024, "max_image_age_secs": 0 }),
+            store.get().unwrap()["image_gc"]
+        );
+    }
+
+    #[test]
+    fn other_settings_need_a_restart() {
+        // arrange
+        let store = store();
+        let before = store.get().unwrap();
+
+        // act
+        let response = handle(
+            &store,
+            r#"{"listen":{"management_uri":"http://0.0.0.0:15580"},"hostname":"device2","image_gc":null}"#,
+        );
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let error = body(response);
+        assert_eq!("RestartRequired", error["code"]);
+        assert_eq!(json!(["hostname", "listen"]), error["keys"]);
+        assert_eq!(before, store.get().unwrap());
+    }
+
+    #[test]
+    fn non_object_is_bad_request() {
+        // arrange
+        let store = store();
+
+        // act
+        let response = handle(&store, "[]");
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
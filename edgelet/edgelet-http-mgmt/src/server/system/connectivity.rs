@@ -0,0 +1,187 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ConnectivityStatus, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// How long a connectivity status is served from the cache.
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Returns whether the device can reach IoT Hub.
+///
+/// Monitoring agents tend to poll this every second, so the status is cached
+/// for five seconds rather than asked of the runtime on every request.
+pub struct GetConnectivityStatus<M> {
+    runtime: M,
+    cache: StatusCache,
+}
+
+impl<M> GetConnectivityStatus<M> {
+    pub fn new(runtime: M) -> Self {
+        GetConnectivityStatus {
+            runtime,
+            cache: StatusCache::new(CACHE_TTL),
+        }
+    }
+}
+
+impl<M> Handler<Parameters> for GetConnectivityStatus<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let status = match self.cache.get(Instant::now()) {
+            Some(status) => future::Either::A(future::ok(status)),
+            None => {
+                let cache = self.cache.clone();
+                future::Either::B(self.runtime.connectivity_status().then(
+                    move |status| -> Result<_, Error> {
+                        let status = status.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::GetConnectivityStatus,
+                        ))?;
+                        cache.insert(status.clone(), Instant::now());
+                        Ok(status)
+                    },
+                ))
+            }
+        };
+
+        let response = status
+            .and_then(|status| -> Result<_, Error> {
+                let b = serde_json::to_string(&status).context(ErrorKind::RuntimeOperation(
+                    RuntimeOperation::GetConnectivityStatus,
+                ))?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetConnectivityStatus,
+                    ))?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// The last connectivity status, until it is `ttl` old.
+#[derive(Clone)]
+struct StatusCache {
+    ttl: Duration,
+    entry: Arc<Mutex<Option<(ConnectivityStatus, Instant)>>>,
+}
+
+impl StatusCache {
+    fn new(ttl: Duration) -> Self {
+        StatusCache {
+            ttl,
+            entry: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn get(&self, now: Instant) -> Option<ConnectivityStatus> {
+        match *self.entry.lock().unwrap() {
+            Some((ref status, cached_at)) if now.duration_since(cached_at) < self.ttl => {
+                Some(status.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn insert(&self, status: ConnectivityStatus, now: Instant) {
+        *self.entry.lock().unwrap() = Some((status, now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ConnectivityState, ModuleRuntimeState};
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn handle(runtime: TestRuntime<Error>) -> Response<Body> {
+        let handler = GetConnectivityStatus::new(runtime);
+        let request = Request::get("http://localhost/system/connectivity")
+            .body(Body::default())
+            .unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::new(Ok(module));
+
+        // act
+        let response = handle(runtime);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let status: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(json!({ "status": "connected" }), status);
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not get IoT Hub connectivity status\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn cached_status_expires() {
+        let cache = StatusCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        let status = ConnectivityStatus::new(ConnectivityState::Connected);
+
+        assert_eq!(None, cache.get(now));
+        cache.insert(status.clone(), now);
+        assert_eq!(Some(status), cache.get(now + Duration::from_secs(4)));
+        assert_eq!(None, cache.get(now + Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Mutex;
+
+use failure::{Fail, ResultExt};
+use futures::Future;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::{self, Value};
+
+use edgelet_core::{IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+const EDGE_AGENT_MODULE_ID: &str = "$edgeAgent";
+
+/// Returns the twin properties of the edge agent module as last synced with
+/// IoT Hub, so the agent can compare them with the deployment it is running.
+pub struct GetEdgeAgentState<I> {
+    id_manager: Mutex<I>,
+}
+
+impl<I> GetEdgeAgentState<I> {
+    pub fn new(id_manager: I) -> Self {
+        GetEdgeAgentState {
+            id_manager: Mutex::new(id_manager),
+        }
+    }
+}
+
+impl<I> Handler<Parameters> for GetEdgeAgentState<I>
+where
+    I: 'static + IdentityManager + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = self
+            .id_manager
+            .lock()
+            .unwrap()
+            .get_twin(IdentitySpec::new(EDGE_AGENT_MODULE_ID.to_string()))
+            .then(|result| match result {
+                Ok(Some(properties)) => write_response(&properties),
+                Ok(None) => Err(Error::from(ErrorKind::IdentityNotFound(
+                    EDGE_AGENT_MODULE_ID.to_string(),
+                ))),
+                Err(err) => {
+                    let kind = if is_disconnected(&err) {
+                        ErrorKind::IotHubUnavailable
+                    } else {
+                        ErrorKind::IdentityOperation(IdentityOperation::GetTwin(
+                            EDGE_AGENT_MODULE_ID.to_string(),
+                        ))
+                    };
+                    Err(Error::from(err.context(kind)))
+                }
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn is_disconnected<E: Fail>(err: &E) -> bool {
+    Fail::find_root_cause(err)
+        .downcast_ref::<hyper::Error>()
+        .map_or(false, hyper::Error::is_connect)
+}
+
+fn write_response(properties: &Value) -> Result<Response<Body>, Error> {
+    let operation = || {
+        ErrorKind::IdentityOperation(IdentityOperation::GetTwin(EDGE_AGENT_MODULE_ID.to_string()))
+    };
+    let b = serde_json::to_string(properties).with_context(|_| operation())?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| operation())?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_test_utils::identity::TestIdentityManager;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::json;
+
+    use super::*;
+
+    fn handle(manager: TestIdentityManager) -> Response<Body> {
+        let handler = GetEdgeAgentState::new(manager);
+        let request = Request::get("http://localhost/system/edgeagent")
+            .body(Body::default())
+            .unwrap();
+        handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap()
+    }
+
+    fn error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let properties = json!({
+            "desired": { "schemaVersion": "1.0", "$version": 4 },
+            "reported": { "lastDesiredVersion": 3 },
+        });
+        let manager = TestIdentityManager::new(vec![])
+            .with_fail_get(false)
+            .with_twin("$edgeAgent", properties.clone());
+
+        // act
+        let response = handle(manager);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let body: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(properties, body);
+    }
+
+    #[test]
+    fn no_twin_is_not_found() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![]).with_fail_get(false);
+
+        // act
+        let response = handle(manager);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!("Identity $edgeAgent not found", error(response).message());
+    }
+
+    #[test]
+    fn get_twin_fails() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![]);
+
+        // act
+        let response = handle(manager);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            "Could not get twin of module $edgeAgent\n\tcaused by: General error",
+            error(response).message()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{future, Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{GcPolicy, SharedGcPolicy};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Returns the policy the image garbage collector runs with.
+pub struct GetGcPolicy {
+    policy: SharedGcPolicy,
+}
+
+impl GetGcPolicy {
+    pub fn new(policy: SharedGcPolicy) -> Self {
+        GetGcPolicy { policy }
+    }
+}
+
+impl Handler<Parameters> for GetGcPolicy {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let response = policy_response(&self.policy.get())
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+/// Replaces the policy the image garbage collector runs with, from its next
+/// check of the free disk space on. The policy lasts until iotedged
+/// restarts, which goes back to the configured one.
+pub struct PutGcPolicy {
+    policy: SharedGcPolicy,
+}
+
+impl PutGcPolicy {
+    pub fn new(policy: SharedGcPolicy) -> Self {
+        PutGcPolicy { policy }
+    }
+}
+
+impl Handler<Parameters> for PutGcPolicy {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let shared = self.policy.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(move |b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let policy = serde_json::from_slice::<GcPolicy>(&b)
+                    .context(ErrorKind::MalformedRequestBody)?;
+                shared.set(policy.clone());
+                policy_response(&policy)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn policy_response(policy: &GcPolicy) -> Result<Response<Body>, Error> {
+    let b = serde_json::to_string(policy).context(ErrorKind::GcPolicy)?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::GcPolicy)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn body(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn get_returns_the_policy() {
+        // arrange
+        let handler = GetGcPolicy::new(SharedGcPolicy::new(GcPolicy::new(1024, 3600)));
+        let request = Request::get("http://localhost/system/gcpolicy")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({ "min_free_bytes": 1024, "max_image_age_secs": 3600 }),
+            body(response)
+        );
+    }
+
+    #[test]
+    fn put_replaces_the_policy() {
+        // arrange
+        let policy = SharedGcPolicy::default();
+        let handler = PutGcPolicy::new(policy.clone());
+        let request = Request::put("http://localhost/system/gcpolicy")
+            .body(r#"{"min_free_bytes":2048,"max_image_age_secs":60}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(GcPolicy::new(2048, 60), policy.get());
+    }
+
+    #[test]
+    fn put_bad_body_keeps_the_policy() {
+        // arrange
+        let policy = SharedGcPolicy::new(GcPolicy::new(1024, 3600));
+        let handler = PutGcPolicy::new(policy.clone());
+        let request = Request::put("http://localhost/system/gcpolicy")
+            .body(r#"{"min_free_bytes":-1}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(GcPolicy::new(1024, 3600), policy.get());
+    }
+}
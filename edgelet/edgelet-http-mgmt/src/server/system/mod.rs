@@ -0,0 +1,24 @@
+// Copyright (c) Microsoft. All rights reserved.
+mod capabilities;
+mod certificates;
+mod config;
+mod connectivity;
+mod edge_agent;
+mod gc_policy;
+mod proxy;
+mod prune_images;
+mod snapshot;
+mod state;
+mod twin;
+
+pub use self::capabilities::GetCapabilities;
+pub use self::certificates::{GetCertificateInventory, RenewCertificate};
+pub use self::config::{GetDaemonConfig, UpdateDaemonConfig};
+pub use self::connectivity::GetConnectivityStatus;
+pub use self::edge_agent::GetEdgeAgentState;
+pub use self::gc_policy::{GetGcPolicy, PutGcPolicy};
+pub use self::proxy::{GetProxyConfig, PutProxyConfig};
+pub use self::prune_images::PruneImages;
+pub use self::snapshot::GetSystemSnapshot;
+pub use self::state::ResetDaemonState;
+pub use self::twin::GetTwinDesired;
@@ -0,0 +1,201 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_derive::Deserialize;
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Deserialize)]
+struct PruneImagesRequest {
+    dangling_only: Option<bool>,
+    /// `dangle_only` is accepted too.
+    dangle_only: Option<bool>,
+}
+
+impl PruneImagesRequest {
+    fn dangling_only(&self) -> bool {
+        self.dangling_only.or(self.dangle_only).unwrap_or(true)
+    }
+}
+
+/// Removes the images no module uses, to free up disk space on the device.
+/// Only untagged images are removed unless the body sets `dangling_only` to
+/// `false`.
+pub struct PruneImages<M> {
+    runtime: M,
+}
+
+impl<M> PruneImages<M> {
+    pub fn new(runtime: M) -> Self {
+        PruneImages { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for PruneImages<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(|b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let request = serde_json::from_slice::<PruneImagesRequest>(&b)
+                    .context(ErrorKind::MalformedRequestBody)?;
+                Ok(request)
+            })
+            .and_then(move |request| {
+                runtime
+                    .prune_images(request.dangling_only())
+                    .then(|result| -> Result<_, Error> {
+                        let result = result
+                            .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages))?;
+                        let b = serde_json::to_string(&result)
+                            .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages))?;
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages))?;
+                        Ok(response)
+                    })
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn handle(runtime: TestRuntime<Error>, body: &str) -> Response<Body> {
+        let handler = PruneImages::new(runtime);
+        let request = Request::post("http://localhost/system/pruneimages")
+            .body(body.to_string().into())
+            .unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    fn runtime() -> TestRuntime<Error> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::new(Ok(module))
+    }
+
+    fn body(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn dangling_only() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, r#"{"dangling_only":true}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({
+                "reclaimed_bytes": 1024,
+                "removed_images": ["sha256:dangling"],
+            }),
+            body(response)
+        );
+    }
+
+    #[test]
+    fn all_unused() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, r#"{"dangle_only":false}"#);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            json!({
+                "reclaimed_bytes": 2048,
+                "removed_images": ["sha256:dangling", "sha256:unused"],
+            }),
+            body(response)
+        );
+    }
+
+    #[test]
+    fn defaults_to_dangling_only() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, "{}");
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(json!(["sha256:dangling"]), body(response)["removed_images"]);
+    }
+
+    #[test]
+    fn malformed_body() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, r#"{"dangling_only":"yes"}"#);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, r#"{"dangling_only":true}"#);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not prune images\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
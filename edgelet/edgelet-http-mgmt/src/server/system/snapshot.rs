@@ -0,0 +1,212 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::{self, Write};
+
+use failure::ResultExt;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::Future;
+use hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Returns the state of the host and of every module at once, for attaching
+/// to support requests.
+///
+/// Nothing is changed by taking a snapshot. Snapshots run to hundreds of
+/// kilobytes, so they are compressed with gzip for clients that accept it.
+pub struct GetSystemSnapshot<M> {
+    runtime: M,
+}
+
+impl<M> GetSystemSnapshot<M> {
+    pub fn new(runtime: M) -> Self {
+        GetSystemSnapshot { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetSystemSnapshot<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let gzip = accepts_gzip(&req);
+
+        let response = self
+            .runtime
+            .snapshot()
+            .then(move |snapshot| -> Result<_, Error> {
+                let context = || ErrorKind::RuntimeOperation(RuntimeOperation::Snapshot);
+                let snapshot = snapshot.with_context(|_| context())?;
+                let b = serde_json::to_vec(&snapshot).with_context(|_| context())?;
+
+                let mut response = Response::builder();
+                response
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(VARY, "accept-encoding");
+                let b = if gzip {
+                    response.header(CONTENT_ENCODING, "gzip");
+                    compress(&b).with_context(|_| context())?
+                } else {
+                    b
+                };
+                let response = response
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .with_context(|_| context())?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Whether `gzip` is among the codings of the request's `Accept-Encoding`,
+/// other than with a quality of zero.
+fn accepts_gzip(req: &Request<Body>) -> bool {
+    req.headers()
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let is_gzip = parts
+                .next()
+                .map_or(false, |name| name.eq_ignore_ascii_case("gzip"));
+            let rejected = parts.any(|param| {
+                param.starts_with("q=") && param[2..].parse::<f32>().map_or(false, |q| q <= 0.0)
+            });
+            is_gzip && !rejected
+        })
+}
+
+fn compress(b: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_test_utils::module::*;
+    use flate2::read::GzDecoder;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::new(Ok(module))
+    }
+
+    fn handle(runtime: TestRuntime<Error>, accept_encoding: Option<&str>) -> Response<Body> {
+        let handler = GetSystemSnapshot::new(runtime);
+        let mut request = Request::get("http://localhost/system/snapshot");
+        if let Some(accept_encoding) = accept_encoding {
+            request.header(ACCEPT_ENCODING, accept_encoding);
+        }
+        let request = request.body(Body::default()).unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    fn assert_snapshot(b: &[u8]) {
+        let snapshot: Value = serde_json::from_slice(b).unwrap();
+        assert_eq!("os_type_sample", snapshot["system_info"]["os_type"]);
+        assert_eq!(4, snapshot["system_resources"]["total_cpu_count"]);
+        assert_eq!("/", snapshot["disk_usage"][0]["mount_point"]);
+        assert_eq!("eth0", snapshot["network_interfaces"][0]["name"]);
+        assert_eq!("mod1", snapshot["modules"][0]["name"]);
+        assert_eq!(Value::Array(vec![]), snapshot["events"]);
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, None);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_snapshot(&b);
+    }
+
+    #[test]
+    fn success_gzip() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, Some("deflate, gzip;q=0.8"));
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("gzip", response.headers().get(CONTENT_ENCODING).unwrap());
+        assert_eq!("accept-encoding", response.headers().get(VARY).unwrap());
+        let b = response.into_body().concat2().wait().unwrap();
+        let mut decoded = Vec::new();
+        GzDecoder::new(&b[..]).read_to_end(&mut decoded).unwrap();
+        assert_snapshot(&decoded);
+    }
+
+    #[test]
+    fn gzip_with_zero_quality_is_not_used() {
+        // arrange
+        let runtime = runtime();
+
+        // act
+        let response = handle(runtime, Some("gzip;q=0, identity"));
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+
+        // act
+        let response = handle(runtime, Some("gzip"));
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not take a snapshot of the system\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
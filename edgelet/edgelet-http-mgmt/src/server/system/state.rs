@@ -0,0 +1,214 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Mutex;
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::Future;
+use hyper::{Body, Request, Response, StatusCode};
+use log::{info, warn};
+use url::form_urlencoded::parse as parse_query;
+
+use edgelet_core::{IdentityManager, StateReset};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::server::identity::delete_all;
+use crate::IntoResponse;
+
+/// Makes the daemon throw away its cached state and start again as if it
+/// was just installed, for devices stuck on a corrupt state file.
+///
+/// The response is `202 Accepted` and the reset happens after it is sent:
+/// the daemon stops its services, clears its state directory and, with it,
+/// the cached provisioning and identity credentials, and then provisions
+/// the device and initializes the module runtime again. With
+/// `include_identities=true` every module identity is deleted first, as by
+/// `DELETE /identities/all`. The request is kept in the audit log like
+/// every other. A second reset before the daemon restarts gets
+/// `409 Conflict`.
+pub struct ResetDaemonState<I> {
+    id_manager: Mutex<I>,
+    reset: StateReset,
+}
+
+impl<I> ResetDaemonState<I> {
+    pub fn new(id_manager: I, reset: StateReset) -> Self {
+        ResetDaemonState {
+            id_manager: Mutex::new(id_manager),
+            reset,
+        }
+    }
+}
+
+impl<I> Handler<Parameters> for ResetDaemonState<I>
+where
+    I: 'static + IdentityManager + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        if self.reset.is_requested() {
+            let err = Error::from(ErrorKind::StateResetInProgress);
+            return Box::new(future::ok(
+                err.with_correlation_id(correlation_id).into_response(),
+            ));
+        }
+
+        let remove_identities = if include_identities(req.uri().query()) {
+            let id_manager = self.id_manager.lock().unwrap().clone();
+            Either::A(delete_all(id_manager).then(|outcomes| {
+                let failures: Vec<_> = match outcomes {
+                    Ok(outcomes) => outcomes
+                        .into_iter()
+                        .filter_map(|(_, result)| result.err())
+                        .collect(),
+                    Err(err) => vec![err],
+                };
+                for err in failures {
+                    warn!(
+                        "Resetting the daemon state without removing all module identities: {}",
+                        Fail::find_root_cause(&err)
+                    );
+                }
+                Ok::<_, ()>(())
+            }))
+        } else {
+            Either::B(future::ok(()))
+        };
+
+        let reset = self.reset.clone();
+        tokio::spawn(remove_identities.map(move |()| {
+            if reset.request() {
+                info!("Resetting the daemon state...");
+            }
+        }));
+
+        let response = Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .body(Body::default())
+            .context(ErrorKind::ResetState)
+            .map_err(Error::from)
+            .or_else(|e| -> Result<_, HttpError> {
+                Ok(e.with_correlation_id(correlation_id).into_response())
+            });
+
+        Box::new(future::result(response))
+    }
+}
+
+fn include_identities(query: Option<&str>) -> bool {
+    query.map_or(false, |query| {
+        parse_query(query.as_bytes())
+            .any(|(key, value)| key == "include_identities" && value == "true")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::AuthType;
+    use edgelet_test_utils::identity::{TestIdentity, TestIdentityManager};
+    use futures::sync::oneshot::Receiver;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+
+    fn manager() -> TestIdentityManager {
+        TestIdentityManager::new(vec![TestIdentity::new("m1", "iotedge", "1", AuthType::Sas)])
+    }
+
+    /// Runs the reset the handler spawns to completion along with it.
+    fn handle(handler: &ResetDaemonState<TestIdentityManager>, uri: &str) -> Response<Body> {
+        let request = Request::delete(uri).body(Body::default()).unwrap();
+        let mut runtime = Runtime::new().unwrap();
+        let response = runtime
+            .block_on(future::lazy(|| handler.handle(request, Parameters::new())))
+            .unwrap();
+        runtime.run().unwrap();
+        response
+    }
+
+    fn requested(receiver: Receiver<()>) -> bool {
+        receiver.wait().is_ok()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let (reset, receiver) = StateReset::channel();
+        let handler = ResetDaemonState::new(manager(), reset);
+
+        // act
+        let response = handle(&handler, "http://localhost/system/state");
+
+        // assert
+        assert_eq!(StatusCode::ACCEPTED, response.status());
+        assert!(requested(receiver));
+    }
+
+    #[test]
+    fn success_with_identities() {
+        // arrange
+        let (reset, receiver) = StateReset::channel();
+        let handler = ResetDaemonState::new(manager(), reset);
+
+        // act
+        let response = handle(
+            &handler,
+            "http://localhost/system/state?include_identities=true",
+        );
+
+        // assert
+        assert_eq!(StatusCode::ACCEPTED, response.status());
+        assert!(requested(receiver));
+    }
+
+    #[test]
+    fn identity_errors_do_not_prevent_reset() {
+        // arrange
+        let (reset, receiver) = StateReset::channel();
+        let handler = ResetDaemonState::new(manager().with_fail_list(true), reset);
+
+        // act
+        let response = handle(
+            &handler,
+            "http://localhost/system/state?include_identities=true",
+        );
+
+        // assert
+        assert_eq!(StatusCode::ACCEPTED, response.status());
+        assert!(requested(receiver));
+    }
+
+    #[test]
+    fn second_reset_is_conflict() {
+        // arrange
+        let (reset, _receiver) = StateReset::channel();
+        reset.request();
+        let handler = ResetDaemonState::new(manager(), reset);
+
+        // act
+        let response = handle(&handler, "http://localhost/system/state");
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("StateResetInProgress"), error.code());
+    }
+
+    #[test]
+    fn include_identities_query() {
+        assert!(include_identities(Some("include_identities=true")));
+        assert!(!include_identities(Some("include_identities=false")));
+        assert!(!include_identities(None));
+    }
+}
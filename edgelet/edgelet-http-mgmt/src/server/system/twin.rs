@@ -0,0 +1,235 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::Future;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_derive::Serialize;
+use serde_json::{self, Value};
+
+use edgelet_core::{ConnectivityState, IdentityManager, IdentityOperation, ModuleRuntime};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The desired properties of the device twin and when they were read from
+/// IoT Hub.
+#[derive(Clone, Serialize)]
+struct TwinDesired {
+    desired: Value,
+    last_updated: DateTime<Utc>,
+}
+
+/// Returns the desired properties of the device twin, so monitoring agents
+/// don't have to ask IoT Hub for them themselves.
+///
+/// The properties are read from IoT Hub on every request and kept. While the
+/// device is offline the last properties read are returned instead, and
+/// `last_updated` tells how old they are; with none read yet the response
+/// is `503 Service Unavailable`.
+pub struct GetTwinDesired<I, M> {
+    id_manager: Mutex<I>,
+    runtime: M,
+    cache: Arc<Mutex<Option<TwinDesired>>>,
+}
+
+impl<I, M> GetTwinDesired<I, M> {
+    pub fn new(id_manager: I, runtime: M) -> Self {
+        GetTwinDesired {
+            id_manager: Mutex::new(id_manager),
+            runtime,
+            cache: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl<I, M> Handler<Parameters> for GetTwinDesired<I, M>
+where
+    I: 'static + IdentityManager + Send,
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let runtime = self.runtime.clone();
+        let cache = self.cache.clone();
+
+        let response = self
+            .id_manager
+            .lock()
+            .unwrap()
+            .get_twin_desired()
+            .then(move |result| match result {
+                Ok(desired) => {
+                    let twin = TwinDesired {
+                        desired,
+                        last_updated: Utc::now(),
+                    };
+                    *cache.lock().unwrap() = Some(twin.clone());
+                    Either::A(future::ok(twin))
+                }
+                Err(err) => Either::B(runtime.connectivity_status().then(move |status| {
+                    let offline = status
+                        .map(|status| status.status() == ConnectivityState::Disconnected)
+                        .unwrap_or(false);
+                    let cached = cache.lock().unwrap().clone();
+                    match cached {
+                        Some(twin) if offline => Ok(twin),
+                        _ => {
+                            let kind = if offline {
+                                ErrorKind::IotHubUnavailable
+                            } else {
+                                ErrorKind::IdentityOperation(IdentityOperation::GetTwinDesired)
+                            };
+                            Err(Error::from(err.context(kind)))
+                        }
+                    }
+                })),
+            })
+            .and_then(|twin| write_response(&twin))
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn write_response(twin: &TwinDesired) -> Result<Response<Body>, Error> {
+    let operation = || ErrorKind::IdentityOperation(IdentityOperation::GetTwinDesired);
+    let b = serde_json::to_string(twin).with_context(|_| operation())?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| operation())?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ConnectivityStatus, ModuleRuntimeState};
+    use edgelet_test_utils::identity::TestIdentityManager;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::module::tests::Error as TestError;
+
+    fn runtime(status: ConnectivityState) -> TestRuntime<TestError> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::new(Ok(module)).with_connectivity(ConnectivityStatus::new(status))
+    }
+
+    fn handle(
+        handler: &GetTwinDesired<TestIdentityManager, TestRuntime<TestError>>,
+    ) -> Response<Body> {
+        let request = Request::get("http://localhost/system/twin/desired")
+            .body(Body::default())
+            .unwrap();
+        handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap()
+    }
+
+    fn body(response: Response<Body>) -> Value {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let desired = json!({ "telemetryInterval": 30, "$version": 7 });
+        let manager = TestIdentityManager::new(vec![])
+            .with_fail_get(false)
+            .with_device_twin(desired.clone());
+        let handler = GetTwinDesired::new(manager, runtime(ConnectivityState::Connected));
+
+        // act
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = body(response);
+        assert_eq!(desired, body["desired"]);
+        assert!(body["last_updated"].is_string());
+    }
+
+    #[test]
+    fn offline_returns_last_known_twin() {
+        // arrange
+        let desired = json!({ "telemetryInterval": 30 });
+        let manager = TestIdentityManager::new(vec![])
+            .with_fail_get(false)
+            .with_device_twin(desired.clone());
+        let handler = GetTwinDesired::new(manager, runtime(ConnectivityState::Disconnected));
+        let fresh = body(handle(&handler));
+        *handler.id_manager.lock().unwrap() = TestIdentityManager::new(vec![]);
+
+        // act
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(fresh, body(response));
+    }
+
+    #[test]
+    fn offline_without_twin_is_unavailable() {
+        // arrange
+        let handler = GetTwinDesired::new(
+            TestIdentityManager::new(vec![]),
+            runtime(ConnectivityState::Disconnected),
+        );
+
+        // act
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("IotHubUnavailable"), error.code());
+    }
+
+    #[test]
+    fn online_error_is_not_hidden_by_cache() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![])
+            .with_fail_get(false)
+            .with_device_twin(json!({ "telemetryInterval": 30 }));
+        let handler = GetTwinDesired::new(manager, runtime(ConnectivityState::Connected));
+        handle(&handler);
+        *handler.id_manager.lock().unwrap() = TestIdentityManager::new(vec![]);
+
+        // act
+        let response = handle(&handler);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            "Could not get the desired properties of the device twin\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
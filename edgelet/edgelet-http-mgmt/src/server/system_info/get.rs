@@ -13,6 +13,7 @@ use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
 
+use crate::correlation::correlation_id;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -33,9 +34,11 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
         debug!("Get System Information");
 
         let response = self
@@ -45,11 +48,14 @@ where
                 let system_info = system_info
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo))?;
 
-                let body = SystemInfo::new(
+                let mut body = SystemInfo::new(
                     system_info.os_type().to_string(),
                     system_info.architecture().to_string(),
                     system_info.version().to_string(),
                 );
+                if let Some(cgroup_version) = system_info.cgroup_version() {
+                    body.set_cgroup_version(cgroup_version.to_string());
+                }
 
                 let b = serde_json::to_string(&body)
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo))?;
@@ -62,7 +68,7 @@ where
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo))?;
                 Ok(response)
             })
-            .or_else(|e| Ok(e.into_response()));
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
 
         Box::new(response)
     }
@@ -107,6 +113,7 @@ mod tests {
                 assert_eq!("os_type_sample", os_type);
                 assert_eq!("architecture_sample", architecture);
                 assert_eq!(edgelet_core::version(), system_info.version());
+                assert_eq!(Some("v2"), system_info.cgroup_version());
 
                 Ok(())
             })
@@ -0,0 +1,142 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::Future;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetSystemResources<M> {
+    runtime: M,
+}
+
+impl<M> GetSystemResources<M> {
+    pub fn new(runtime: M) -> Self {
+        GetSystemResources { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetSystemResources<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        debug!("Get System Resources");
+
+        let response = self
+            .runtime
+            .system_resources()
+            .then(|system_resources| -> Result<_, Error> {
+                let system_resources = system_resources.context(ErrorKind::RuntimeOperation(
+                    RuntimeOperation::SystemResources,
+                ))?;
+
+                let b = serde_json::to_string(&system_resources).context(
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources),
+                )?;
+
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::SystemResources,
+                    ))?;
+                Ok(response)
+            })
+            .or_else(move |e| Ok(e.with_correlation_id(correlation_id).into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleRuntimeState;
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    #[test]
+    fn system_resources_success() {
+        // arrange
+        let state = ModuleRuntimeState::default();
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::new(Ok(module));
+        let handler = GetSystemResources::new(runtime);
+        let request = Request::get("http://localhost/systeminfo/resources")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let system_resources: serde_json::Value = serde_json::from_slice(&b).unwrap();
+
+                assert_eq!(4, system_resources["total_cpu_count"]);
+                assert_eq!(1024, system_resources["available_memory_bytes"]);
+                assert_eq!(4096, system_resources["total_disk_bytes"]);
+                assert_eq!(2048, system_resources["free_disk_bytes"]);
+
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn system_resources_failed() {
+        // arrange
+        let runtime = TestRuntime::new(Err(Error::General));
+        let handler = GetSystemResources::new(runtime);
+        let request = Request::get("http://localhost/systeminfo/resources")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not query system resources\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+}
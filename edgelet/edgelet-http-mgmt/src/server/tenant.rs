@@ -0,0 +1,103 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use futures::future;
+use futures::Future;
+use hyper::{Body, Request, Response};
+
+use edgelet_core::TenantConfig;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Rejects requests under `/tenants/{tenant}` for tenants that aren't
+/// configured with `404 Not Found`, before the inner handler or its
+/// authorization sees them.
+pub struct TenantScope<H> {
+    inner: H,
+    tenants: TenantConfig,
+}
+
+impl<H> TenantScope<H> {
+    pub fn new(inner: H, tenants: TenantConfig) -> Self {
+        TenantScope { inner, tenants }
+    }
+}
+
+impl<H> Handler<Parameters> for TenantScope<H>
+where
+    H: Handler<Parameters>,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let error = match params.name("tenant") {
+            Some(tenant) if self.tenants.contains(tenant) => None,
+            Some(tenant) => Some(ErrorKind::TenantNotFound(tenant.to_string())),
+            None => Some(ErrorKind::MissingRequiredParameter("tenant")),
+        };
+
+        match error {
+            Some(kind) => Box::new(future::ok(
+                Error::from(kind)
+                    .with_correlation_id(correlation_id)
+                    .into_response(),
+            )),
+            None => self.inner.handle(req, params),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use hyper::StatusCode;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    struct Done;
+
+    impl Handler<Parameters> for Done {
+        fn handle(
+            &self,
+            _req: Request<Body>,
+            _params: Parameters,
+        ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+            Box::new(future::ok(Response::new(Body::from("done"))))
+        }
+    }
+
+    fn handle(tenant: &str) -> Response<Body> {
+        let handler = TenantScope::new(Done, TenantConfig::new(vec!["t1".to_string()]));
+        let req = Request::get(format!("http://localhost/tenants/{}/modules", tenant))
+            .body(Body::default())
+            .unwrap();
+        let params =
+            Parameters::with_captures(vec![(Some("tenant".to_string()), tenant.to_string())]);
+        handler.handle(req, params).wait().unwrap()
+    }
+
+    #[test]
+    fn configured_tenant_passes_through() {
+        let response = handle("t1");
+
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn unknown_tenant_is_not_found() {
+        let response = handle("t2");
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+        assert_eq!(Some("TenantNotFound"), error.code());
+    }
+}
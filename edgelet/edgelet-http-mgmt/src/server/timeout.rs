@@ -0,0 +1,211 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::time::Duration;
+
+use failure::Fail;
+use futures::Future;
+use hyper::{Body, Request, Response};
+use log::{error, warn};
+use tokio::timer::Timeout as TimeoutFuture;
+
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// How long a request may wait for the module runtime by default.
+pub const DEFAULT_RUNTIME_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a request may wait for the identity manager by default.
+pub const DEFAULT_IDENTITY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a single request to the management service may take before it
+/// is answered with `504 Gateway Timeout`. Routes that call the identity
+/// manager use `identity`, all others use `runtime`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeouts {
+    runtime: Duration,
+    identity: Duration,
+}
+
+impl Timeouts {
+    pub fn new(runtime: Duration, identity: Duration) -> Self {
+        Timeouts { runtime, identity }
+    }
+
+    pub fn runtime(&self) -> Duration {
+        self.runtime
+    }
+
+    pub fn identity(&self) -> Duration {
+        self.identity
+    }
+
+    /// These timeouts, but with `extra` more time for the module runtime.
+    /// This is for routes that wait on the module on purpose.
+    pub fn with_extra_runtime(self, extra: Duration) -> Self {
+        Timeouts::new(self.runtime + extra, self.identity)
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts::new(DEFAULT_RUNTIME_TIMEOUT, DEFAULT_IDENTITY_TIMEOUT)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Upstream {
+    Runtime,
+    Identity,
+}
+
+/// Fails a request that the inner handler doesn't answer in time, so that an
+/// unresponsive container runtime or identity service can't keep requests
+/// and their threads waiting forever.
+///
+/// Only the time until the response head is ready counts, so streamed
+/// bodies like followed logs aren't cut off.
+pub struct Timeout<H> {
+    inner: H,
+    duration: Duration,
+    upstream: Upstream,
+}
+
+impl<H> Timeout<H> {
+    pub fn runtime(inner: H, timeouts: Timeouts) -> Self {
+        Timeout {
+            inner,
+            duration: timeouts.runtime(),
+            upstream: Upstream::Runtime,
+        }
+    }
+
+    pub fn identity(inner: H, timeouts: Timeouts) -> Self {
+        Timeout {
+            inner,
+            duration: timeouts.identity(),
+            upstream: Upstream::Identity,
+        }
+    }
+}
+
+impl<H> Handler<Parameters> for Timeout<H>
+where
+    H: Handler<Parameters>,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+        let (duration, upstream) = (self.duration, self.upstream);
+
+        let response =
+            TimeoutFuture::new(self.inner.handle(req, params), duration).or_else(move |err| {
+                let kind = match upstream {
+                    Upstream::Runtime => ErrorKind::RuntimeTimeout(duration),
+                    Upstream::Identity => ErrorKind::IdentityTimeout(duration),
+                };
+                let id = correlation_id.clone().unwrap_or_else(|| "-".to_string());
+
+                let err = if err.is_elapsed() {
+                    warn!("Request {} timed out: {}", id, kind);
+                    Error::from(kind)
+                } else if err.is_inner() {
+                    return Err(err.into_inner().expect("error is inner"));
+                } else {
+                    let err = err.into_timer().expect("error is a timer error");
+                    error!("Could not time request {}: {}", id, err);
+                    Error::from(err.context(kind))
+                };
+                Ok(err.with_correlation_id(correlation_id).into_response())
+            });
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Stream};
+    use hyper::StatusCode;
+    use management::models::ErrorResponse;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+    use crate::correlation::{CorrelationId, REQUEST_ID_HEADER};
+
+    struct Slow(Option<Duration>);
+
+    impl Handler<Parameters> for Slow {
+        fn handle(
+            &self,
+            _req: Request<Body>,
+            _params: Parameters,
+        ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+            match self.0 {
+                Some(delay) => Box::new(
+                    tokio::timer::Delay::new(std::time::Instant::now() + delay)
+                        .then(|_| Ok::<_, HttpError>(Response::new(Body::from("done")))),
+                ),
+                None => Box::new(future::empty()),
+            }
+        }
+    }
+
+    fn handle<H: Handler<Parameters>>(handler: &H) -> Response<Body> {
+        let mut req = Request::get("http://localhost/modules")
+            .header(REQUEST_ID_HEADER, "req-1")
+            .body(Body::default())
+            .unwrap();
+        let correlation_id = CorrelationId::from_request(&req);
+        req.extensions_mut().insert(correlation_id);
+
+        Runtime::new()
+            .unwrap()
+            .block_on(future::lazy(|| handler.handle(req, Parameters::new())))
+            .unwrap()
+    }
+
+    fn error(response: Response<Body>) -> ErrorResponse {
+        let b = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[test]
+    fn hung_runtime_times_out() {
+        let timeouts = Timeouts::new(Duration::from_millis(10), Duration::from_secs(60));
+        let response = handle(&Timeout::runtime(Slow(None), timeouts));
+
+        assert_eq!(StatusCode::GATEWAY_TIMEOUT, response.status());
+        let error = error(response);
+        assert_eq!(Some("RuntimeTimeout"), error.code());
+        assert_eq!(Some("req-1"), error.correlation_id());
+    }
+
+    #[test]
+    fn hung_identity_manager_times_out() {
+        let timeouts = Timeouts::new(Duration::from_secs(60), Duration::from_millis(10));
+        let response = handle(&Timeout::identity(Slow(None), timeouts));
+
+        assert_eq!(StatusCode::GATEWAY_TIMEOUT, response.status());
+        assert_eq!(Some("IdentityTimeout"), error(response).code());
+    }
+
+    #[test]
+    fn response_in_time_passes_through() {
+        let timeouts = Timeouts::new(Duration::from_secs(60), Duration::from_secs(60));
+        let response = handle(&Timeout::runtime(
+            Slow(Some(Duration::from_millis(10))),
+            timeouts,
+        ));
+
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        assert_eq!(b"done", &*b);
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_derive::Serialize;
+use serde_json;
+
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::{Error as HttpError, Version, API_VERSION};
+
+use crate::correlation::correlation_id;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Serialize)]
+struct Versions {
+    supported_versions: Vec<String>,
+    latest: String,
+}
+
+/// Lists the API versions the daemon supports on `GET /versions`, which
+/// doesn't need an `api-version` so that clients can call it before they
+/// know which version to use.
+pub struct GetVersions;
+
+impl Handler<Parameters> for GetVersions {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let correlation_id = correlation_id(&req);
+
+        let versions = Versions {
+            supported_versions: Version::ALL.iter().map(ToString::to_string).collect(),
+            latest: API_VERSION.to_string(),
+        };
+        let response = serde_json::to_string(&versions)
+            .context(ErrorKind::DescribeApi)
+            .map_err(Error::from)
+            .and_then(|b| {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::DescribeApi)?;
+                Ok(response)
+            })
+            .unwrap_or_else(|e| e.with_correlation_id(correlation_id).into_response());
+
+        Box::new(future::ok(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[test]
+    fn lists_every_version() {
+        let request = Request::get("http://localhost/versions")
+            .body(Body::default())
+            .unwrap();
+
+        let response = GetVersions
+            .handle(request, Parameters::new())
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let b = response.into_body().concat2().wait().unwrap();
+        let versions: Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(
+            json!({
+                "supported_versions": ["2018-06-28", "2019-01-30"],
+                "latest": "2019-01-30",
+            }),
+            versions
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::env;
+
+use chrono::{DateTime, Utc};
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Body, Request, Response};
+use log::info;
+
+use crate::authorization::AuthorizedCaller;
+use crate::peer::PeerAddr;
+
+/// Turns on the access log when set to `1`.
+pub const ACCESS_LOG_ENV_KEY: &str = "IOTEDGE_ACCESS_LOG";
+
+/// What query strings that hold a SAS token are replaced with.
+const MASKED_QUERY: &str = "***";
+
+/// Logs one line per answered request in the Common Log Format, for
+/// operators that need access logs of the service:
+///
+/// ```text
+/// 127.0.0.1 - edgeAgent [30/Jan/2019:12:00:00 +0000] "GET /modules HTTP/1.1" 200 512
+/// ```
+///
+/// Requests that arrived over a Unix socket have `-` for the client address,
+/// and requests that no module was authorized for have `-` for the module.
+/// Query strings with a `sas` parameter are masked so tokens don't end up
+/// in the logs. The log is disabled by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AccessLogMiddleware {
+    enabled: bool,
+}
+
+impl AccessLogMiddleware {
+    pub fn new(enabled: bool) -> Self {
+        AccessLogMiddleware { enabled }
+    }
+
+    /// Enabled when `IOTEDGE_ACCESS_LOG` is `1`.
+    pub fn from_env() -> Self {
+        AccessLogMiddleware::new(env::var(ACCESS_LOG_ENV_KEY).map_or(false, |value| value == "1"))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// What to log about `req` once it is answered.
+    pub fn request(&self, req: &Request<Body>) -> AccessLogRequest {
+        if !self.enabled {
+            return AccessLogRequest(None);
+        }
+
+        let client = req
+            .extensions()
+            .get::<PeerAddr>()
+            .map_or_else(|| "-".to_string(), |PeerAddr(addr)| addr.ip().to_string());
+        let path = match req.uri().query() {
+            Some(query) if has_sas(query) => format!("{}?{}", req.uri().path(), MASKED_QUERY),
+            Some(query) => format!("{}?{}", req.uri().path(), query),
+            None => req.uri().path().to_string(),
+        };
+        let request = format!("{} {} {:?}", req.method(), path, req.version());
+
+        AccessLogRequest(Some(RequestLine { client, request }))
+    }
+}
+
+fn has_sas(query: &str) -> bool {
+    query
+        .split('&')
+        .any(|pair| pair.split('=').next() == Some("sas"))
+}
+
+#[derive(Debug)]
+struct RequestLine {
+    client: String,
+    request: String,
+}
+
+#[derive(Debug)]
+pub struct AccessLogRequest(Option<RequestLine>);
+
+impl AccessLogRequest {
+    pub fn log(&self, response: &Response<Body>) {
+        if let Some(line) = self.line(response, Utc::now()) {
+            info!("{}", line);
+        }
+    }
+
+    fn line(&self, response: &Response<Body>, timestamp: DateTime<Utc>) -> Option<String> {
+        let request = self.0.as_ref()?;
+        let module = response
+            .extensions()
+            .get::<AuthorizedCaller>()
+            .map_or("-", AuthorizedCaller::name);
+        let bytes = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .unwrap_or("-");
+
+        Some(format!(
+            "{} - {} [{}] \"{}\" {} {}",
+            request.client,
+            module,
+            timestamp.format("%d/%b/%Y:%H:%M:%S %z"),
+            request.request,
+            response.status().as_u16(),
+            bytes,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use chrono::TimeZone;
+    use hyper::StatusCode;
+
+    use super::*;
+
+    fn request(uri: &str, tcp: bool) -> Request<Body> {
+        let mut req = Request::get(uri).body(Body::empty()).unwrap();
+        if tcp {
+            let addr: SocketAddr = "10.0.0.7:51234".parse().unwrap();
+            req.extensions_mut().insert(PeerAddr(addr));
+        }
+        req
+    }
+
+    fn response(caller: Option<&str>) -> Response<Body> {
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_LENGTH, "512")
+            .body(Body::empty())
+            .unwrap();
+        if let Some(caller) = caller {
+            response
+                .extensions_mut()
+                .insert(AuthorizedCaller(caller.to_string()));
+        }
+        response
+    }
+
+    fn line(req: &Request<Body>, response: &Response<Body>) -> Option<String> {
+        let timestamp = Utc.ymd(2019, 1, 30).and_hms(12, 0, 0);
+        AccessLogMiddleware::new(true)
+            .request(req)
+            .line(response, timestamp)
+    }
+
+    #[test]
+    fn line_is_in_common_log_format() {
+        let req = request("http://localhost/modules?api-version=2019-01-30", true);
+
+        assert_eq!(
+            Some(
+                "10.0.0.7 - edgeAgent [30/Jan/2019:12:00:00 +0000] \
+                 \"GET /modules?api-version=2019-01-30 HTTP/1.1\" 200 512"
+                    .to_string()
+            ),
+            line(&req, &response(Some("edgeAgent")))
+        );
+    }
+
+    #[test]
+    fn unix_socket_and_anonymous_caller_are_dashes() {
+        let req = request("http://localhost/modules", false);
+
+        assert_eq!(
+            Some(
+                "- - - [30/Jan/2019:12:00:00 +0000] \"GET /modules HTTP/1.1\" 200 512".to_string()
+            ),
+            line(&req, &response(None))
+        );
+    }
+
+    #[test]
+    fn sas_query_is_masked() {
+        let req = request("http://localhost/modules?api-version=1&sas=secret", true);
+
+        let masked = line(&req, &response(None)).unwrap();
+        assert!(masked.contains("\"GET /modules?*** HTTP/1.1\""));
+        assert!(!masked.contains("secret"));
+
+        let req = request("http://localhost/modules?sasquatch=1", true);
+        let unmasked = line(&req, &response(None)).unwrap();
+        assert!(unmasked.contains("\"GET /modules?sasquatch=1 HTTP/1.1\""));
+    }
+
+    #[test]
+    fn disabled_logs_nothing() {
+        let req = request("http://localhost/modules", true);
+
+        let line = AccessLogMiddleware::default()
+            .request(&req)
+            .line(&response(None), Utc::now());
+
+        assert_eq!(None, line);
+    }
+}
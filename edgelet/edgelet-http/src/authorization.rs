@@ -7,12 +7,30 @@ use futures::{future, Future};
 use hyper::{Body, Request, Response};
 
 use edgelet_core::pid::Pid;
+use edgelet_core::tenant::unscoped_name;
 use edgelet_core::{Authorization as CoreAuth, ModuleRuntime, ModuleRuntimeErrorReason, Policy};
 
 use crate::error::{Error, ErrorKind};
+use crate::hmac_auth::TokenCaller;
 use crate::route::{Handler, Parameters};
 use crate::IntoResponse;
 
+/// Attached to the extensions of authorized requests whose policy identifies
+/// the calling module, for the inner handler, and to those of their
+/// responses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthorizedCaller(pub(crate) String);
+
+impl AuthorizedCaller {
+    pub fn new(name: String) -> Self {
+        AuthorizedCaller(name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
 pub struct Authorization<H, M> {
     auth: CoreAuth<M>,
     inner: Arc<H>,
@@ -43,53 +61,99 @@ where
         req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send> {
+        let name_param = match self.auth.policy() {
+            Policy::Tenant => "tenant",
+            _ => "name",
+        };
         let (name, pid) = (
-            params.name("name").map(|n| n.to_string()),
+            params.name(name_param).map(|n| n.to_string()),
             req.extensions()
                 .get::<Pid>()
                 .cloned()
                 .unwrap_or_else(|| Pid::None),
         );
+        // Over TCP every caller has the same pid, so the module the HMAC token
+        // was issued to has to be one the policy allows as well.
+        let token_allowed = req.extensions().get::<TokenCaller>().map_or(true, |token| {
+            token_allowed(self.auth.policy(), name.as_ref(), token.name())
+        });
         let inner = self.inner.clone();
+        let caller = self.auth.caller(name.as_ref().map(String::as_str));
+        let expected_caller = match self.auth.policy() {
+            Policy::ModuleId(expected_name) => {
+                Some(ErrorKind::CallerNotAllowed(expected_name.clone()))
+            }
+            Policy::Tenant => name.clone().map(ErrorKind::CallerNotInTenant),
+            _ => None,
+        };
 
-        let response =
-            self.auth
-                .authorize(name.clone(), pid)
-                .then(|authorized| {
-                    authorized
-                        .context(ErrorKind::Authorization)
-                        .map_err(Error::from)
-                })
-                .and_then(move |authorized| {
-                    if authorized {
-                        future::Either::A(inner.handle(req, params).then(|resp| {
-                            resp.context(ErrorKind::Authorization).map_err(Error::from)
-                        }))
-                    } else {
-                        future::Either::B(future::err(Error::from(ErrorKind::ModuleNotFound(
-                            name.unwrap_or_else(String::new),
-                        ))))
+        let response = self
+            .auth
+            .authorize(name.clone(), pid)
+            .then(|authorized| {
+                authorized
+                    .context(ErrorKind::Authorization)
+                    .map_err(Error::from)
+            })
+            .and_then(move |authorized| {
+                if authorized && token_allowed {
+                    let mut req = req;
+                    if let Some(ref caller) = caller {
+                        req.extensions_mut()
+                            .insert(AuthorizedCaller(caller.clone()));
                     }
-                })
-                .or_else(|e| future::ok(e.into_response()));
+                    future::Either::A(inner.handle(req, params).then(|resp| {
+                        let mut resp = resp
+                            .context(ErrorKind::Authorization)
+                            .map_err(Error::from)?;
+                        if let Some(caller) = caller {
+                            resp.extensions_mut().insert(AuthorizedCaller(caller));
+                        }
+                        Ok(resp)
+                    }))
+                } else if let Some(kind) = expected_caller {
+                    future::Either::B(future::err(Error::from(kind)))
+                } else {
+                    future::Either::B(future::err(Error::from(ErrorKind::ModuleNotFound(
+                        name.unwrap_or_else(String::new),
+                    ))))
+                }
+            })
+            .or_else(|e| future::ok(e.into_response()));
 
         Box::new(response)
     }
 }
 
+fn token_allowed(policy: &Policy, name: Option<&String>, token_module: &str) -> bool {
+    match policy {
+        Policy::Anonymous => true,
+        Policy::Caller => name.map_or(false, |name| name.trim_start_matches('$') == token_module),
+        Policy::Module(expected_name) => *expected_name == token_module,
+        Policy::ModuleId(expected_name) => expected_name == token_module,
+        Policy::Tenant => name.map_or(false, |tenant| {
+            unscoped_name(tenant, token_module).is_some()
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::error::Error;
     use std::time::Duration;
 
+    use bytes::Bytes;
     use futures::future::FutureResult;
     use futures::stream::Empty;
     use futures::{future, stream, IntoFuture, Stream};
     use hyper::{Body, Request, Response, StatusCode};
 
     use edgelet_core::{
-        LogOptions, Module, ModuleRegistry, ModuleRuntimeErrorReason, ModuleRuntimeState,
-        ModuleSpec, ModuleTop, SystemInfo,
+        Capabilities, CircuitState, ConnectivityStatus, Error as CoreError, LogOptions, Module,
+        ModuleDetails, ModuleEvent, ModuleRegistry, ModuleRuntimeErrorReason, ModuleRuntimeState,
+        ModuleSpec, ModuleStats, ModuleTop, PortBinding, PruneImagesResult, PullProgress,
+        ResourceLimits, StartResult, SystemInfo, SystemResources, SystemSnapshot, VolumeMount,
     };
 
     use super::*;
@@ -114,6 +178,45 @@ mod tests {
         assert_eq!("from TestHandler", body);
     }
 
+    #[test]
+    fn handler_attaches_authorized_caller() {
+        let runtime = TestModuleList::new(vec![TestModule::new("abc", 123)]);
+        let mut request = Request::default();
+        request.extensions_mut().insert(Pid::Value(123));
+        let params = Parameters::with_captures(vec![(Some("name".to_string()), "abc".to_string())]);
+
+        let auth = Authorization::new(TestHandler::new(), Policy::Caller, runtime.clone());
+        let response = auth.handle(request, params).wait().unwrap();
+        assert_eq!(
+            Some("abc"),
+            response
+                .extensions()
+                .get::<AuthorizedCaller>()
+                .map(AuthorizedCaller::name)
+        );
+
+        let auth = Authorization::new(TestHandler::new(), Policy::Anonymous, runtime);
+        let response = auth
+            .handle(Request::default(), Parameters::default())
+            .wait()
+            .unwrap();
+        assert!(response.extensions().get::<AuthorizedCaller>().is_none());
+    }
+
+    #[test]
+    fn handler_passes_authorized_caller_to_inner_handler() {
+        let runtime = TestModuleList::new(vec![TestModule::new("abc", 123)]);
+        let mut request = Request::default();
+        request.extensions_mut().insert(Pid::Value(123));
+        let params = Parameters::with_captures(vec![(Some("name".to_string()), "abc".to_string())]);
+
+        let auth = Authorization::new(CallerHandler, Policy::Caller, runtime);
+        let response = auth.handle(request, params).wait().unwrap();
+        let body = response.into_body().concat2().wait().unwrap();
+
+        assert_eq!(b"abc", &body[..]);
+    }
+
     #[test]
     fn handler_responds_with_not_found_when_not_authorized() {
         let runtime = TestModuleList::new(vec![TestModule::new("abc", 123)]);
@@ -150,6 +253,97 @@ mod tests {
         assert_eq!(404, response.status());
     }
 
+    #[test]
+    fn module_id_policy_allows_only_that_module() {
+        let runtime = TestModuleList::new(vec![
+            TestModule::new("abc", 123),
+            TestModule::new("xyz", 456),
+        ]);
+        let policy = || Policy::ModuleId("abc".to_string());
+        let params =
+            || Parameters::with_captures(vec![(Some("name".to_string()), "xyz".to_string())]);
+
+        let mut request = Request::default();
+        request.extensions_mut().insert(Pid::Value(123));
+        let auth = Authorization::new(TestHandler::new(), policy(), runtime.clone());
+        let response = auth.handle(request, params()).wait().unwrap();
+        assert_eq!(200, response.status());
+        assert_eq!(
+            Some("abc"),
+            response
+                .extensions()
+                .get::<AuthorizedCaller>()
+                .map(AuthorizedCaller::name)
+        );
+
+        let mut request = Request::default();
+        request.extensions_mut().insert(Pid::Value(456));
+        let auth = Authorization::new(TestHandler::new(), policy(), runtime);
+        let response = auth.handle(request, params()).wait().unwrap();
+        assert_eq!(403, response.status());
+    }
+
+    #[test]
+    fn tenant_policy_allows_only_modules_of_that_tenant() {
+        let runtime = TestModuleList::new(vec![
+            TestModule::new("t1.abc", 123),
+            TestModule::new("t2.abc", 456),
+        ]);
+        let params =
+            || Parameters::with_captures(vec![(Some("tenant".to_string()), "t1".to_string())]);
+
+        let mut request = Request::default();
+        request.extensions_mut().insert(Pid::Value(123));
+        let auth = Authorization::new(TestHandler::new(), Policy::Tenant, runtime.clone());
+        let response = auth.handle(request, params()).wait().unwrap();
+        assert_eq!(200, response.status());
+        assert!(response.extensions().get::<AuthorizedCaller>().is_none());
+
+        let mut request = Request::default();
+        request.extensions_mut().insert(Pid::Value(456));
+        let auth = Authorization::new(TestHandler::new(), Policy::Tenant, runtime);
+        let response = auth.handle(request, params()).wait().unwrap();
+        assert_eq!(403, response.status());
+    }
+
+    #[test]
+    fn token_caller_has_to_be_allowed_by_the_policy() {
+        let runtime = TestModuleList::new(vec![
+            TestModule::new("edgeAgent", 123),
+            TestModule::new("abc", 456),
+        ]);
+        let request = |token_module: &str| {
+            let mut request = Request::default();
+            request.extensions_mut().insert(Pid::Any);
+            request
+                .extensions_mut()
+                .insert(TokenCaller(token_module.to_string()));
+            request
+        };
+        let params =
+            || Parameters::with_captures(vec![(Some("name".to_string()), "abc".to_string())]);
+
+        let auth = Authorization::new(
+            TestHandler::new(),
+            Policy::ModuleId("edgeAgent".to_string()),
+            runtime.clone(),
+        );
+        let response = auth.handle(request("edgeAgent"), params()).wait().unwrap();
+        assert_eq!(200, response.status());
+        let response = auth.handle(request("abc"), params()).wait().unwrap();
+        assert_eq!(403, response.status());
+
+        let auth = Authorization::new(TestHandler::new(), Policy::Caller, runtime.clone());
+        let response = auth.handle(request("abc"), params()).wait().unwrap();
+        assert_eq!(200, response.status());
+        let response = auth.handle(request("edgeAgent"), params()).wait().unwrap();
+        assert_eq!(404, response.status());
+
+        let auth = Authorization::new(TestHandler::new(), Policy::Anonymous, runtime);
+        let response = auth.handle(request("abc"), params()).wait().unwrap();
+        assert_eq!(200, response.status());
+    }
+
     #[test]
     fn handler_responds_with_not_found_when_authorizer_fails() {
         let runtime = TestModuleList::new_with_behavior(
@@ -236,6 +430,23 @@ mod tests {
         }
     }
 
+    struct CallerHandler;
+
+    impl Handler<Parameters> for CallerHandler {
+        fn handle(
+            &self,
+            req: Request<Body>,
+            _params: Parameters,
+        ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+            let caller = req
+                .extensions()
+                .get::<AuthorizedCaller>()
+                .map_or("-", AuthorizedCaller::name)
+                .to_string();
+            Box::new(future::ok(Response::new(caller.into())))
+        }
+    }
+
     struct TestConfig {}
 
     #[derive(Clone)]
@@ -265,6 +476,9 @@ mod tests {
         fn config(&self) -> &Self::Config {
             &TestConfig {}
         }
+        fn labels(&self) -> HashMap<String, String> {
+            HashMap::new()
+        }
         fn runtime_state(&self) -> Self::RuntimeStateFuture {
             notimpl_error!()
         }
@@ -319,6 +533,7 @@ mod tests {
         type ModuleRegistry = Self;
         type Chunk = String;
         type Logs = Empty<Self::Chunk, Self::Error>;
+        type RegistryAuth = ();
         type CreateFuture = FutureResult<(), Self::Error>;
         type GetFuture = FutureResult<(Self::Module, ModuleRuntimeState), Self::Error>;
         type InitFuture = FutureResult<(), Self::Error>;
@@ -331,8 +546,37 @@ mod tests {
         type StartFuture = FutureResult<(), Self::Error>;
         type StopFuture = FutureResult<(), Self::Error>;
         type SystemInfoFuture = FutureResult<SystemInfo, Self::Error>;
+        type SystemResourcesFuture = FutureResult<SystemResources, Self::Error>;
         type RemoveAllFuture = FutureResult<(), Self::Error>;
         type TopFuture = FutureResult<ModuleTop, Self::Error>;
+        type StatsFuture = FutureResult<ModuleStats, Self::Error>;
+        type InspectFuture = FutureResult<ModuleDetails, Self::Error>;
+        type EnvFuture = FutureResult<HashMap<String, String>, Self::Error>;
+        type UpdateEnvFuture = FutureResult<(), Self::Error>;
+        type UpdateResourcesFuture = FutureResult<(), Self::Error>;
+        type UpsertFuture = FutureResult<(Self::Module, bool), Self::Error>;
+        type PingFuture = FutureResult<(), Self::Error>;
+        type EventStream = Box<dyn Stream<Item = ModuleEvent, Error = Self::Error> + Send>;
+        type GetConfigFuture = FutureResult<Option<ModuleSpec<Self::Config>>, Self::Error>;
+        type ExecFuture = FutureResult<Self::Logs, Self::Error>;
+        type PullImageStream = Empty<PullProgress, Self::Error>;
+        type PullImageFuture = FutureResult<Self::PullImageStream, Self::Error>;
+        type PruneImagesFuture = FutureResult<PruneImagesResult, Self::Error>;
+        type SignalFuture = FutureResult<(), Self::Error>;
+        type PortsFuture = FutureResult<Vec<PortBinding>, Self::Error>;
+        type WaitUntilStoppedFuture = FutureResult<(), Self::Error>;
+        type ConnectivityStatusFuture = FutureResult<ConnectivityStatus, Self::Error>;
+        type CheckpointFuture = FutureResult<(), Self::Error>;
+        type RestoreFuture = FutureResult<(), Self::Error>;
+        type ListCheckpointsFuture = FutureResult<Vec<String>, Self::Error>;
+        type VolumesFuture = FutureResult<Vec<VolumeMount>, Self::Error>;
+        type SnapshotFuture = FutureResult<SystemSnapshot, Self::Error>;
+        type StartAllFuture = FutureResult<Vec<StartResult>, CoreError>;
+        type SetBandwidthFuture = FutureResult<(), Self::Error>;
+        type PauseFuture = FutureResult<(), Self::Error>;
+        type UnpauseFuture = FutureResult<(), Self::Error>;
+        type CopyToContainerFuture = FutureResult<(), Self::Error>;
+        type RenameFuture = FutureResult<(), Self::Error>;
 
         fn init(&self) -> Self::InitFuture {
             notimpl_error!()
@@ -358,6 +602,10 @@ mod tests {
             notimpl_error!()
         }
 
+        fn system_resources(&self) -> Self::SystemResourcesFuture {
+            notimpl_error!()
+        }
+
         fn restart(&self, _id: &str) -> Self::RestartFuture {
             notimpl_error!()
         }
@@ -371,7 +619,20 @@ mod tests {
         }
 
         fn list_with_details(&self) -> Self::ListWithDetailsStream {
-            Box::new(notimpl_error_stream!())
+            match self.behavior {
+                TestModuleListBehavior::FailCall => Box::new(notimpl_error_stream!()),
+                _ => Box::new(stream::iter_ok(
+                    self.modules
+                        .iter()
+                        .map(|m| {
+                            (
+                                m.clone(),
+                                ModuleRuntimeState::default().with_pid(Pid::Value(m.pid)),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+            }
         }
 
         fn logs(&self, _id: &str, _options: &LogOptions) -> Self::LogsFuture {
@@ -386,7 +647,7 @@ mod tests {
             notimpl_error!()
         }
 
-        fn top(&self, id: &str) -> Self::TopFuture {
+        fn top(&self, id: &str, _ps_args: Option<&str>) -> Self::TopFuture {
             let module = self
                 .modules
                 .iter()
@@ -399,5 +660,143 @@ mod tests {
                 TestModuleListBehavior::FailCall => notimpl_error!(),
             }
         }
+
+        fn stats(&self, _id: &str) -> Self::StatsFuture {
+            notimpl_error!()
+        }
+
+        fn inspect(&self, _id: &str) -> Self::InspectFuture {
+            notimpl_error!()
+        }
+
+        fn env(&self, _id: &str) -> Self::EnvFuture {
+            notimpl_error!()
+        }
+
+        fn update_env(&self, _id: &str, _env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+            notimpl_error!()
+        }
+
+        fn update_resources(
+            &self,
+            _id: &str,
+            _limits: ResourceLimits,
+        ) -> Self::UpdateResourcesFuture {
+            notimpl_error!()
+        }
+
+        fn upsert(&self, _module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
+            notimpl_error!()
+        }
+
+        fn ping(&self) -> Self::PingFuture {
+            notimpl_error!()
+        }
+
+        fn events(&self) -> Self::EventStream {
+            Box::new(notimpl_error_stream!())
+        }
+
+        fn get_config(&self, _id: &str) -> Self::GetConfigFuture {
+            notimpl_error!()
+        }
+
+        fn exec(&self, _id: &str, _cmd: &[String], _tty: bool) -> Self::ExecFuture {
+            notimpl_error!()
+        }
+
+        fn pull_image(
+            &self,
+            _image: &str,
+            _auth: Option<Self::RegistryAuth>,
+        ) -> Self::PullImageFuture {
+            notimpl_error!()
+        }
+
+        fn prune_images(&self, _dangling_only: bool) -> Self::PruneImagesFuture {
+            notimpl_error!()
+        }
+
+        fn signal(&self, _id: &str, _signal: u32) -> Self::SignalFuture {
+            notimpl_error!()
+        }
+
+        fn get_ports(&self, _id: &str) -> Self::PortsFuture {
+            notimpl_error!()
+        }
+
+        fn wait_until_stopped(
+            &self,
+            _id: &str,
+            _timeout: Duration,
+        ) -> Self::WaitUntilStoppedFuture {
+            notimpl_error!()
+        }
+
+        fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+            notimpl_error!()
+        }
+
+        fn checkpoint(&self, _id: &str, _checkpoint_id: &str) -> Self::CheckpointFuture {
+            notimpl_error!()
+        }
+
+        fn restore(&self, _id: &str, _checkpoint_id: &str) -> Self::RestoreFuture {
+            notimpl_error!()
+        }
+
+        fn list_checkpoints(&self, _id: &str) -> Self::ListCheckpointsFuture {
+            notimpl_error!()
+        }
+
+        fn get_volumes(&self, _id: &str) -> Self::VolumesFuture {
+            notimpl_error!()
+        }
+
+        fn snapshot(&self) -> Self::SnapshotFuture {
+            notimpl_error!()
+        }
+
+        fn start_all(&self, _specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+            unimplemented!()
+        }
+
+        fn set_bandwidth(
+            &self,
+            _id: &str,
+            _ingress_kbps: u32,
+            _egress_kbps: u32,
+        ) -> Self::SetBandwidthFuture {
+            unimplemented!()
+        }
+
+        fn pause(&self, _id: &str) -> Self::PauseFuture {
+            unimplemented!()
+        }
+
+        fn unpause(&self, _id: &str) -> Self::UnpauseFuture {
+            unimplemented!()
+        }
+
+        fn copy_to_container(
+            &self,
+            _id: &str,
+            _dest_path: &str,
+            _content: Bytes,
+        ) -> Self::CopyToContainerFuture {
+            unimplemented!()
+        }
+
+        fn rename(&self, _old_name: &str, _new_name: &str) -> Self::RenameFuture {
+            unimplemented!()
+        }
+
+        fn circuit_state(&self) -> CircuitState {
+            CircuitState::Closed
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
     }
 }
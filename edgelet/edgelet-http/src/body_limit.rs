@@ -0,0 +1,210 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use failure::{Compat, Fail};
+use futures::{Async, Poll, Stream};
+use hyper::body::Payload;
+use hyper::{Body, Chunk, Request, Response};
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The default for `BodySizeLimit`, 1 MiB.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Limits the size of the body of every request.
+///
+/// A request that declares a `Content-Length` over the limit is refused
+/// immediately. Any other request with a body gets it wrapped in a
+/// `LimitedBody`, which fails once more than `max_body_bytes` have been
+/// read, and the response is then replaced with `413 Payload Too Large` by
+/// the returned `BodySizeGuard`. Requests without a body, such as most `GET`
+/// and `DELETE` requests, are passed through untouched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BodySizeLimit {
+    max_body_bytes: usize,
+}
+
+impl BodySizeLimit {
+    pub fn new(max_body_bytes: usize) -> Self {
+        BodySizeLimit { max_body_bytes }
+    }
+
+    pub fn max_body_bytes(self) -> usize {
+        self.max_body_bytes
+    }
+
+    /// On failure returns the response to send instead of calling the inner
+    /// service.
+    pub fn limit(
+        self,
+        req: Request<Body>,
+    ) -> Result<(Request<Body>, BodySizeGuard), Response<Body>> {
+        if req.body().is_end_stream() {
+            return Ok((req, BodySizeGuard::new(self, None)));
+        }
+
+        // This is the Content-Length the client declared, if any.
+        let too_large = req
+            .body()
+            .content_length()
+            .map_or(false, |len| len > self.max_body_bytes as u64);
+        if too_large {
+            return Err(payload_too_large(self.max_body_bytes));
+        }
+
+        let (parts, body) = req.into_parts();
+        let body = LimitedBody::new(body, self.max_body_bytes);
+        let guard = BodySizeGuard::new(self, Some(body.exceeded.clone()));
+        Ok((Request::from_parts(parts, Body::wrap_stream(body)), guard))
+    }
+}
+
+impl Default for BodySizeLimit {
+    fn default() -> Self {
+        BodySizeLimit::new(DEFAULT_MAX_BODY_BYTES)
+    }
+}
+
+/// Remembers whether the body of a request went over the limit.
+#[derive(Debug)]
+pub struct BodySizeGuard {
+    limit: BodySizeLimit,
+    exceeded: Option<Arc<AtomicBool>>,
+}
+
+impl BodySizeGuard {
+    fn new(limit: BodySizeLimit, exceeded: Option<Arc<AtomicBool>>) -> Self {
+        BodySizeGuard { limit, exceeded }
+    }
+
+    /// Replaces `response` with `413 Payload Too Large` if the body of the
+    /// request went over the limit while it was being handled.
+    pub fn check(&self, response: Response<Body>) -> Response<Body> {
+        match self.exceeded {
+            Some(ref exceeded) if exceeded.load(Ordering::SeqCst) => {
+                payload_too_large(self.limit.max_body_bytes)
+            }
+            _ => response,
+        }
+    }
+}
+
+/// A request body that fails once more than a given number of bytes have
+/// been read from it.
+pub struct LimitedBody {
+    inner: Body,
+    remaining: usize,
+    max_body_bytes: usize,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl LimitedBody {
+    pub fn new(inner: Body, max_body_bytes: usize) -> Self {
+        LimitedBody {
+            inner,
+            remaining: max_body_bytes,
+            max_body_bytes,
+            exceeded: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Stream for LimitedBody {
+    type Item = Chunk;
+    type Error = Compat<Error>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.inner.poll() {
+            Ok(Async::Ready(Some(chunk))) => {
+                if chunk.len() > self.remaining {
+                    self.exceeded.store(true, Ordering::SeqCst);
+                    return Err(
+                        Error::from(ErrorKind::PayloadTooLarge(self.max_body_bytes)).compat()
+                    );
+                }
+                self.remaining -= chunk.len();
+                Ok(Async::Ready(Some(chunk)))
+            }
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => Err(Error::from(err.context(ErrorKind::ServiceError)).compat()),
+        }
+    }
+}
+
+fn payload_too_large(max_body_bytes: usize) -> Response<Body> {
+    Error::from(ErrorKind::PayloadTooLarge(max_body_bytes)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use hyper::StatusCode;
+
+    use super::*;
+
+    fn chunked(chunks: Vec<&'static str>) -> Body {
+        let chunks = chunks
+            .into_iter()
+            .map(|c| Ok::<_, hyper::Error>(Chunk::from(c)));
+        Body::wrap_stream(futures::stream::iter_result(chunks))
+    }
+
+    #[test]
+    fn request_without_body_is_untouched() {
+        let req = Request::get("http://localhost/modules")
+            .body(Body::empty())
+            .unwrap();
+
+        let (req, guard) = BodySizeLimit::new(1).limit(req).unwrap();
+
+        assert!(guard.exceeded.is_none());
+        assert!(req.body().is_end_stream());
+    }
+
+    #[test]
+    fn declared_length_over_limit_is_refused() {
+        let req = Request::post("http://localhost/modules")
+            .body(Body::from("0123456789"))
+            .unwrap();
+
+        let response = BodySizeLimit::new(4).limit(req).unwrap_err();
+
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, response.status());
+    }
+
+    #[test]
+    fn body_within_limit_is_read() {
+        let req = Request::post("http://localhost/modules")
+            .body(chunked(vec!["0123", "4567"]))
+            .unwrap();
+
+        let (req, guard) = BodySizeLimit::new(8).limit(req).unwrap();
+        let body = req.into_body().concat2().wait().unwrap();
+
+        assert_eq!(b"01234567", body.as_ref());
+        let response = guard.check(Response::new(Body::empty()));
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn body_over_limit_fails_and_replaces_response() {
+        let req = Request::post("http://localhost/modules")
+            .body(chunked(vec!["0123", "4567", "8"]))
+            .unwrap();
+
+        let (req, guard) = BodySizeLimit::new(8).limit(req).unwrap();
+        assert!(req.into_body().concat2().wait().is_err());
+
+        let response = guard.check(Response::new(Body::empty()));
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        assert_eq!(
+            r#"{"message":"Request body is larger than 8 bytes"}"#,
+            std::str::from_utf8(&body).unwrap()
+        );
+    }
+}
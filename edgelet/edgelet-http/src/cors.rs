@@ -0,0 +1,210 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use hyper::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use crate::error::{Error, ErrorKind};
+use crate::peer::PeerAddr;
+use crate::IntoResponse;
+
+const ANY_ORIGIN: &str = "*";
+const ALLOWED_METHODS: &str = "GET, POST, PUT, PATCH, DELETE, OPTIONS";
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type, x-request-id";
+
+/// Lets browsers call the service from the allowed origins.
+///
+/// Preflight requests from an allowed origin are answered directly with
+/// `204 No Content`, and those from any other origin with `403 Forbidden`.
+/// Every other response to a request from an allowed origin gets the
+/// `Access-Control-Allow-*` headers. An allowed origin of `"*"` allows every
+/// origin, and with no allowed origins, the default, nothing is allowed.
+///
+/// Only requests that arrived over TCP are considered, so that a service
+/// listening on a Unix socket is never opened up to browsers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CorsMiddleware {
+    allowed_origins: Vec<String>,
+}
+
+impl CorsMiddleware {
+    pub fn new(allowed_origins: Vec<String>) -> Self {
+        CorsMiddleware { allowed_origins }
+    }
+
+    pub fn allowed_origins(&self) -> &[String] {
+        &self.allowed_origins
+    }
+
+    /// The response to send instead of calling the inner service, if `req`
+    /// is a preflight request.
+    pub fn preflight(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        if *req.method() != Method::OPTIONS
+            || !req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return None;
+        }
+        let origin = cors_origin(req)?;
+
+        let response = match self.allow_origin(origin) {
+            Some(allow_origin) => {
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::NO_CONTENT;
+                CorsHeaders(Some(allow_origin)).apply(response)
+            }
+            None => Error::from(ErrorKind::OriginNotAllowed(
+                String::from_utf8_lossy(origin.as_bytes()).into_owned(),
+            ))
+            .into_response(),
+        };
+        Some(response)
+    }
+
+    /// The headers to add to the response to `req`.
+    pub fn headers(&self, req: &Request<Body>) -> CorsHeaders {
+        CorsHeaders(cors_origin(req).and_then(|origin| self.allow_origin(origin)))
+    }
+
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        if self
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == ANY_ORIGIN)
+        {
+            Some(HeaderValue::from_static(ANY_ORIGIN))
+        } else if self
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+        {
+            Some(origin.clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// The `Origin` of a request that arrived over TCP.
+fn cors_origin(req: &Request<Body>) -> Option<&HeaderValue> {
+    req.extensions().get::<PeerAddr>()?;
+    req.headers().get(ORIGIN)
+}
+
+#[derive(Debug)]
+pub struct CorsHeaders(Option<HeaderValue>);
+
+impl CorsHeaders {
+    pub fn apply(&self, mut response: Response<Body>) -> Response<Body> {
+        if let Some(ref allow_origin) = self.0 {
+            let headers = response.headers_mut();
+            if allow_origin != ANY_ORIGIN {
+                headers.append(VARY, HeaderValue::from_static("Origin"));
+            }
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin.clone());
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static(ALLOWED_METHODS),
+            );
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(ALLOWED_HEADERS),
+            );
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    fn request(method: Method, origin: &str, tcp: bool) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(method)
+            .uri("http://localhost/modules")
+            .header(ORIGIN, origin)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        if tcp {
+            let addr: SocketAddr = "127.0.0.1:15580".parse().unwrap();
+            req.extensions_mut().insert(PeerAddr(addr));
+        }
+        req
+    }
+
+    fn cors(origins: &[&str]) -> CorsMiddleware {
+        CorsMiddleware::new(origins.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn preflight_from_allowed_origin() {
+        let response = cors(&["http://dashboard"])
+            .preflight(&request(Method::OPTIONS, "http://dashboard", true))
+            .unwrap();
+
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(
+            "http://dashboard",
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+        assert_eq!("Origin", response.headers()[VARY]);
+        assert_eq!(
+            ALLOWED_METHODS,
+            response.headers()[ACCESS_CONTROL_ALLOW_METHODS]
+        );
+        assert_eq!(
+            ALLOWED_HEADERS,
+            response.headers()[ACCESS_CONTROL_ALLOW_HEADERS]
+        );
+    }
+
+    #[test]
+    fn preflight_from_other_origin_is_forbidden() {
+        let response = CorsMiddleware::default()
+            .preflight(&request(Method::OPTIONS, "http://dashboard", true))
+            .unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn any_origin() {
+        let req = request(Method::GET, "http://dashboard", true);
+        let cors = cors(&["*"]);
+
+        assert!(cors.preflight(&req).is_none());
+        let response = cors.headers(&req).apply(Response::new(Body::empty()));
+        assert_eq!("*", response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]);
+        assert!(!response.headers().contains_key(VARY));
+    }
+
+    #[test]
+    fn other_origin_gets_no_headers() {
+        let req = request(Method::GET, "http://elsewhere", true);
+
+        let response = cors(&["http://dashboard"])
+            .headers(&req)
+            .apply(Response::new(Body::empty()));
+
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn unix_socket_is_ignored() {
+        let cors = cors(&["*"]);
+
+        assert!(cors
+            .preflight(&request(Method::OPTIONS, "http://dashboard", false))
+            .is_none());
+        let response = cors
+            .headers(&request(Method::GET, "http://dashboard", false))
+            .apply(Response::new(Body::empty()));
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
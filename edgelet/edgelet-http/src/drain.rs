@@ -0,0 +1,182 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::Either;
+use futures::prelude::*;
+use hyper::service::Service;
+use hyper::Request;
+use log::info;
+use tokio::timer::{Delay, Interval};
+
+/// How long a server waits for open connections to finish their requests
+/// after it stops accepting new connections.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Counts things that are in progress. Each `Guard` counts as one until it is
+/// dropped.
+#[derive(Clone, Default)]
+pub struct Counter(Arc<AtomicUsize>);
+
+impl Counter {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn guard(&self) -> Guard {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Guard(self.0.clone())
+    }
+}
+
+pub struct Guard(Arc<AtomicUsize>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Counts the requests that `inner` is answering.
+pub struct InFlightService<T> {
+    requests: Counter,
+    inner: T,
+}
+
+impl<T> InFlightService<T> {
+    pub fn new(requests: Counter, inner: T) -> Self {
+        InFlightService { requests, inner }
+    }
+}
+
+impl<T> Service for InFlightService<T>
+where
+    T: Service,
+{
+    type ReqBody = T::ReqBody;
+    type ResBody = T::ResBody;
+    type Error = T::Error;
+    type Future = InFlight<T::Future>;
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        InFlight {
+            inner: self.inner.call(req),
+            _guard: self.requests.guard(),
+        }
+    }
+}
+
+pub struct InFlight<F> {
+    inner: F,
+    _guard: Guard,
+}
+
+impl<F: Future> Future for InFlight<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+/// Resolves once every connection is closed, or after `timeout`, whichever
+/// comes first.
+pub fn drain(
+    connections: Counter,
+    requests: Counter,
+    timeout: Duration,
+) -> impl Future<Item = (), Error = ()> {
+    let in_flight = requests.get();
+    if connections.get() > 0 {
+        info!(
+            "Waiting up to {:?} for {} in-flight requests to complete",
+            timeout, in_flight
+        );
+    }
+
+    let closed = Interval::new(Instant::now(), DRAIN_POLL_INTERVAL)
+        .take_while(move |_| Ok(connections.get() > 0))
+        .for_each(|_| Ok(()));
+    let timed_out = Delay::new(Instant::now() + timeout);
+
+    closed.select2(timed_out).then(move |result| {
+        match result {
+            Ok(Either::A(_)) | Err(Either::A(_)) => {
+                info!("Drained {} in-flight requests", in_flight);
+            }
+            Ok(Either::B(_)) | Err(Either::B(_)) => {
+                let remaining = requests.get();
+                info!(
+                    "Drained {} in-flight requests, {} did not complete in time",
+                    in_flight.saturating_sub(remaining),
+                    remaining
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_are_counted_until_dropped() {
+        let counter = Counter::default();
+        let first = counter.guard();
+        let second = counter.clone().guard();
+        assert_eq!(2, counter.get());
+
+        drop(first);
+        assert_eq!(1, counter.get());
+        drop(second);
+        assert_eq!(0, counter.get());
+    }
+
+    #[test]
+    fn drain_waits_for_connections() {
+        let connections = Counter::default();
+        let connection = connections.guard();
+        let close = Delay::new(Instant::now() + Duration::from_millis(100)).then(move |_| {
+            drop(connection);
+            Ok::<_, ()>(())
+        });
+
+        let started = Instant::now();
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.spawn(close);
+        runtime
+            .block_on(drain(
+                connections.clone(),
+                Counter::default(),
+                Duration::from_secs(10),
+            ))
+            .unwrap();
+
+        assert_eq!(0, connections.get());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn drain_gives_up_after_timeout() {
+        let connections = Counter::default();
+        let _connection = connections.guard();
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime
+            .block_on(drain(
+                connections.clone(),
+                Counter::default(),
+                Duration::from_millis(100),
+            ))
+            .unwrap();
+
+        assert_eq!(1, connections.get());
+    }
+}
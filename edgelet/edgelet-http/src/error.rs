@@ -26,6 +26,12 @@ pub enum ErrorKind {
     #[fail(display = "An error occurred while binding a listener to {}", _0)]
     BindListener(BindListenerType),
 
+    #[fail(display = "Only module {:?} may call this endpoint", _0)]
+    CallerNotAllowed(String),
+
+    #[fail(display = "Only modules of tenant {:?} may call this endpoint", _0)]
+    CallerNotInTenant(String),
+
     #[fail(display = "Could not perform HTTP request")]
     Http,
 
@@ -38,6 +44,12 @@ pub enum ErrorKind {
     #[fail(display = "Invalid API version {:?}", _0)]
     InvalidApiVersion(String),
 
+    #[fail(display = "Invalid HMAC token: {}", _0)]
+    InvalidHmacToken(&'static str),
+
+    #[fail(display = "Invalid TLS configuration: {}", _0)]
+    InvalidTlsConfig(&'static str),
+
     #[fail(display = "Invalid URL {:?}", _0)]
     InvalidUrl(String),
 
@@ -57,9 +69,15 @@ pub enum ErrorKind {
     #[fail(display = "Module not found")]
     ModuleNotFound(String),
 
+    #[fail(display = "Origin {:?} is not allowed", _0)]
+    OriginNotAllowed(String),
+
     #[fail(display = "An error occurred for path {}", _0)]
     Path(String),
 
+    #[fail(display = "Request body is larger than {} bytes", _0)]
+    PayloadTooLarge(usize),
+
     #[fail(display = "An error occurred with the proxy {}", _0)]
     Proxy(Uri),
 
@@ -69,6 +87,9 @@ pub enum ErrorKind {
     #[fail(display = "Token source error")]
     TokenSource,
 
+    #[fail(display = "Too many requests")]
+    TooManyRequests,
+
     #[fail(
         display = "Could not form well-formed URL by joining {:?} with {:?}",
         _0, _1
@@ -136,6 +157,12 @@ impl IntoResponse for Error {
         let status_code = match *self.kind() {
             ErrorKind::Authorization | ErrorKind::ModuleNotFound(_) => StatusCode::NOT_FOUND,
             ErrorKind::InvalidApiVersion(_) => StatusCode::BAD_REQUEST,
+            ErrorKind::InvalidHmacToken(_) => StatusCode::UNAUTHORIZED,
+            ErrorKind::CallerNotAllowed(_)
+            | ErrorKind::CallerNotInTenant(_)
+            | ErrorKind::OriginNotAllowed(_) => StatusCode::FORBIDDEN,
+            ErrorKind::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorKind::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -180,8 +207,11 @@ pub enum InvalidUrlReason {
     FileNotFound,
     InvalidScheme,
     InvalidCredentials,
+    Ipv6ZoneId,
     NoAddress,
     NoHost,
+    SocketPathHasNul,
+    SocketPathTooLong,
     UnrecognizedSocket,
 }
 
@@ -196,8 +226,17 @@ impl Display for InvalidUrlReason {
             InvalidUrlReason::InvalidCredentials => {
                 write!(f, "Username or password could not be parsed from URL")
             }
+            InvalidUrlReason::Ipv6ZoneId => {
+                write!(f, "IPv6 addresses with a zone ID are not supported")
+            }
             InvalidUrlReason::NoAddress => write!(f, "URL has no address"),
             InvalidUrlReason::NoHost => write!(f, "URL has no host"),
+            InvalidUrlReason::SocketPathHasNul => write!(f, "Socket path contains a null byte"),
+            InvalidUrlReason::SocketPathTooLong => write!(
+                f,
+                "Socket path is longer than {} bytes",
+                crate::unix::MAX_SOCKET_PATH_LEN
+            ),
             InvalidUrlReason::UnrecognizedSocket => {
                 write!(f, "URL does not correspond to a valid socket")
             }
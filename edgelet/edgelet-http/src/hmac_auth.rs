@@ -0,0 +1,293 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use base64;
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Method, Request, Response};
+use openssl::memcmp;
+
+use edgelet_core::crypto::{Sign, Signature, SignatureAlgorithm};
+use edgelet_core::Error as CoreError;
+
+use crate::error::{Error, ErrorKind};
+use crate::peer::PeerAddr;
+use crate::IntoResponse;
+
+/// How long a token is accepted for, in seconds, on either side of the time
+/// it was issued at. The allowance for tokens from the future is for clocks
+/// that are slightly off.
+pub const HMAC_TOKEN_LIFETIME_SECS: i64 = 5 * 60;
+
+/// The scheme of the `Authorization` header that carries a token.
+const SCHEME: &str = "HMAC";
+
+/// Attached to the extensions of requests that `HmacAuthMiddleware`
+/// authenticated, with the module their token was issued to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenCaller(pub(crate) String);
+
+impl TokenCaller {
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Authenticates requests by the HMAC token in their
+/// `Authorization: HMAC <token>` header, for services that listen on TCP
+/// where callers can't be told apart by their pid.
+///
+/// A token is `<module_id>:<timestamp>:<signature>`, where `timestamp` is in
+/// seconds since the Unix epoch and `signature` is the base64 encoded
+/// `HMAC-SHA256(key, "<method>\n<path>\n<timestamp>\n<module_id>")`, so it
+/// is only good for one method and path, without the query, and for five
+/// minutes around `timestamp`. Requests without a valid token are refused
+/// with `401 Unauthorized`, and those with one get a `TokenCaller` of the
+/// module the token was issued to, which `Authorization` checks against its
+/// policy.
+///
+/// Only requests that arrived over TCP are considered, like by
+/// `CorsMiddleware`, and without a key, the default, every request is let
+/// through. The check has to come before any other handling of the request.
+#[derive(Clone, Default)]
+pub struct HmacAuthMiddleware {
+    key: Option<Arc<dyn TokenKey>>,
+}
+
+impl HmacAuthMiddleware {
+    pub fn new<K>(key: K) -> Self
+    where
+        K: 'static + Sign + Send + Sync,
+    {
+        HmacAuthMiddleware {
+            key: Some(Arc::new(key)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// The token of module `module_id` for a `method` request of `path`
+    /// issued at `timestamp`, or `None` without a key.
+    pub fn token(
+        &self,
+        module_id: &str,
+        method: &Method,
+        path: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Result<Option<String>, Error> {
+        match self.key {
+            Some(ref key) => {
+                let timestamp = timestamp.timestamp();
+                let signature = key
+                    .sign(string_to_sign(module_id, method, path, timestamp).as_bytes())
+                    .context(ErrorKind::ServiceError)?;
+                Ok(Some(format!(
+                    "{}:{}:{}",
+                    module_id,
+                    timestamp,
+                    base64::encode(&signature)
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// On failure returns the response to send instead of calling the inner
+    /// service.
+    pub fn authenticate(
+        &self,
+        req: &mut Request<Body>,
+        now: DateTime<Utc>,
+    ) -> Result<(), Response<Body>> {
+        match self.key {
+            Some(ref key) if req.extensions().get::<PeerAddr>().is_some() => {
+                let caller = verify(&**key, req, now).map_err(IntoResponse::into_response)?;
+                req.extensions_mut().insert(caller);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn verify(
+    key: &dyn TokenKey,
+    req: &Request<Body>,
+    now: DateTime<Utc>,
+) -> Result<TokenCaller, Error> {
+    let header = req
+        .headers()
+        .get(AUTHORIZATION)
+        .ok_or_else(|| ErrorKind::InvalidHmacToken("the Authorization header is missing"))?;
+    let token = header
+        .to_str()
+        .ok()
+        .and_then(|header| {
+            let mut parts = header.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some(SCHEME), Some(token)) => Some(token.trim()),
+                _ => None,
+            }
+        })
+        .ok_or_else(|| ErrorKind::InvalidHmacToken("the Authorization header is not HMAC"))?;
+
+    // Neither the timestamp nor the signature can contain a `:`, so the
+    // module id is whatever comes before them.
+    let mut parts = token.rsplitn(3, ':');
+    let (signature, timestamp, module_id) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(signature), Some(timestamp), Some(module_id)) if !module_id.is_empty() => {
+            (signature, timestamp, module_id)
+        }
+        _ => return Err(Error::from(ErrorKind::InvalidHmacToken("malformed token"))),
+    };
+    let timestamp = timestamp
+        .parse::<i64>()
+        .map_err(|_| ErrorKind::InvalidHmacToken("malformed timestamp"))?;
+    let signature = base64::decode(signature)
+        .map_err(|_| ErrorKind::InvalidHmacToken("malformed signature"))?;
+
+    if (now.timestamp() - timestamp).abs() > HMAC_TOKEN_LIFETIME_SECS {
+        return Err(Error::from(ErrorKind::InvalidHmacToken(
+            "the token expired",
+        )));
+    }
+
+    let expected = key
+        .sign(string_to_sign(module_id, req.method(), req.uri().path(), timestamp).as_bytes())
+        .context(ErrorKind::ServiceError)?;
+    if expected.len() != signature.len() || !memcmp::eq(&expected, &signature) {
+        return Err(Error::from(ErrorKind::InvalidHmacToken(
+            "the signature doesn't match",
+        )));
+    }
+
+    Ok(TokenCaller(module_id.to_string()))
+}
+
+fn string_to_sign(module_id: &str, method: &Method, path: &str, timestamp: i64) -> String {
+    format!("{}\n{}\n{}\n{}", method, path, timestamp, module_id)
+}
+
+/// A `Sign` that can be kept behind a trait object.
+trait TokenKey: Send + Sync {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CoreError>;
+}
+
+impl<K> TokenKey for K
+where
+    K: Sign + Send + Sync,
+{
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CoreError> {
+        Sign::sign(self, SignatureAlgorithm::HMACSHA256, data)
+            .map(|signature| signature.as_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use chrono::{Duration, TimeZone};
+    use edgelet_core::crypto::MemoryKey;
+    use hyper::StatusCode;
+
+    use super::*;
+
+    fn middleware() -> HmacAuthMiddleware {
+        HmacAuthMiddleware::new(MemoryKey::new("key"))
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 1, 30).and_hms(12, 0, 0)
+    }
+
+    fn request(authorization: Option<&str>) -> Request<Body> {
+        let mut builder = Request::get("http://localhost/modules?api-version=2019-01-30");
+        if let Some(authorization) = authorization {
+            builder.header(AUTHORIZATION, authorization);
+        }
+        let mut req = builder.body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(PeerAddr("127.0.0.1:8080".parse::<SocketAddr>().unwrap()));
+        req
+    }
+
+    #[test]
+    fn valid_token_is_accepted() {
+        let middleware = middleware();
+        let token = middleware
+            .token(
+                "mod1",
+                &Method::GET,
+                "/modules",
+                now() - Duration::minutes(4),
+            )
+            .unwrap()
+            .unwrap();
+
+        let mut req = request(Some(&format!("HMAC {}", token)));
+
+        assert!(middleware.authenticate(&mut req, now()).is_ok());
+        assert_eq!(
+            Some("mod1"),
+            req.extensions().get::<TokenCaller>().map(TokenCaller::name)
+        );
+    }
+
+    #[test]
+    fn invalid_tokens_are_refused() {
+        let middleware = middleware();
+        let expired = middleware
+            .token(
+                "mod1",
+                &Method::GET,
+                "/modules",
+                now() - Duration::minutes(6),
+            )
+            .unwrap()
+            .unwrap();
+        let other_path = middleware
+            .token("mod1", &Method::GET, "/identities", now())
+            .unwrap()
+            .unwrap();
+        let other_module = middleware
+            .token("mod1", &Method::GET, "/modules", now())
+            .unwrap()
+            .unwrap()
+            .replacen("mod1", "mod2", 1);
+        let other_key = HmacAuthMiddleware::new(MemoryKey::new("other"))
+            .token("mod1", &Method::GET, "/modules", now())
+            .unwrap()
+            .unwrap();
+
+        for authorization in &[
+            None,
+            Some(format!("Bearer {}", other_path)),
+            Some("HMAC not-a-token".to_string()),
+            Some(format!("HMAC {}", expired)),
+            Some(format!("HMAC {}", other_path)),
+            Some(format!("HMAC {}", other_module)),
+            Some(format!("HMAC {}", other_key)),
+        ] {
+            let mut req = request(authorization.as_ref().map(String::as_str));
+            let response = middleware.authenticate(&mut req, now()).unwrap_err();
+            assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+            assert!(req.extensions().get::<TokenCaller>().is_none());
+        }
+    }
+
+    #[test]
+    fn requests_over_unix_sockets_are_let_through() {
+        let mut req = request(None);
+        req.extensions_mut().remove::<PeerAddr>();
+
+        assert!(middleware().authenticate(&mut req, now()).is_ok());
+        assert!(HmacAuthMiddleware::default()
+            .authenticate(&mut request(None), now())
+            .is_ok());
+    }
+}
@@ -9,46 +9,74 @@
     clippy::use_self
 )]
 
+use std::env::{self, VarError};
 #[cfg(unix)]
 use std::net;
-use std::net::ToSocketAddrs;
+use std::net::{SocketAddr, SocketAddrV6, ToSocketAddrs};
 #[cfg(unix)]
 use std::os::unix::io::FromRawFd;
 use std::sync::Arc;
+use std::time::Duration;
 
 use failure::{Fail, ResultExt};
-use futures::{future, Future, Poll, Stream};
+use futures::future::{self, Either};
+use futures::sync::oneshot;
+use futures::{Future, Poll, Stream};
 use hyper::server::conn::Http;
 use hyper::service::{NewService, Service};
 use hyper::{Body, Response};
-use log::{debug, error, Level};
+use log::{debug, error, info, Level};
 #[cfg(unix)]
 use systemd::Socket;
 use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
 #[cfg(unix)]
 use tokio_uds::UnixListener;
-use url::Url;
+use url::{Host, Url};
 
 use edgelet_core::{UrlExt, UNIX_SCHEME};
 use edgelet_utils::log_failure;
 
+mod access_log;
 pub mod authorization;
+mod body_limit;
 pub mod client;
+mod cors;
+mod drain;
 pub mod error;
+mod hmac_auth;
 pub mod logging;
+mod peer;
 mod pid;
 pub mod route;
+mod tls;
 mod unix;
 mod util;
 mod version;
 
+pub use self::access_log::{AccessLogMiddleware, AccessLogRequest, ACCESS_LOG_ENV_KEY};
+pub use self::body_limit::{BodySizeGuard, BodySizeLimit, LimitedBody, DEFAULT_MAX_BODY_BYTES};
+pub use self::cors::{CorsHeaders, CorsMiddleware};
+pub use self::drain::DEFAULT_DRAIN_TIMEOUT;
 pub use self::error::{BindListenerType, Error, ErrorKind, InvalidUrlReason};
+pub use self::hmac_auth::{HmacAuthMiddleware, TokenCaller, HMAC_TOKEN_LIFETIME_SECS};
+pub use self::peer::PeerAddr;
+pub use self::tls::{ClientCn, TlsConfig, DEFAULT_TLS_HANDSHAKE_TIMEOUT};
 pub use self::util::proxy::MaybeProxyClient;
 pub use self::util::UrlConnector;
-pub use self::version::{Version, API_VERSION};
+pub use self::version::{Version, VersionRange, API_VERSION};
 
+use self::drain::{drain, Counter, InFlightService};
+use self::peer::PeerAddrService;
 use self::pid::PidService;
+use self::tls::ClientCnService;
 use self::util::incoming::Incoming;
+use self::util::IncomingSocketAddr;
+
+/// Overrides the URL that the management API listens on.
+pub const MANAGEMENT_URI_ENV_KEY: &str = "IOTEDGE_MANAGEMENT_URI";
+/// Overrides the URL that the workload API listens on.
+pub const WORKLOAD_URI_ENV_KEY: &str = "IOTEDGE_WORKLOAD_URI";
 
 const HTTP_SCHEME: &str = "http";
 #[cfg(windows)]
@@ -82,6 +110,9 @@ pub struct Server<S> {
     protocol: Http,
     new_service: S,
     incoming: Incoming,
+    drain_timeout: Duration,
+    tls: Option<TlsAcceptor>,
+    tls_handshake_timeout: Duration,
 }
 
 impl<S> Server<S>
@@ -93,10 +124,47 @@ where
     <S as NewService>::InitError: Fail,
     <<S as NewService>::Service as Service>::Future: Send + 'static,
 {
+    /// How long `run_until` waits for open connections to finish their
+    /// requests once it is signalled to shut down. Defaults to
+    /// `DEFAULT_DRAIN_TIMEOUT`.
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Requires mutual TLS on TCP connections, so that only clients with a
+    /// certificate signed by the configured CA can connect. The common name
+    /// of a client's certificate is added to its requests as a `ClientCn`
+    /// extension. Unix sockets are served without TLS.
+    pub fn with_tls(mut self, config: &TlsConfig) -> Result<Self, Error> {
+        self.tls = Some(config.acceptor()?);
+        Ok(self)
+    }
+
+    /// How long clients have to complete the TLS handshake once they have
+    /// connected. Defaults to `DEFAULT_TLS_HANDSHAKE_TIMEOUT`.
+    pub fn with_tls_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.tls_handshake_timeout = timeout;
+        self
+    }
+
+    /// The address a TCP server listens on, which tells the port that was
+    /// picked when binding to port 0. Unix sockets have none.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.incoming {
+            Incoming::Tcp(listener) => listener.local_addr().ok(),
+            Incoming::Unix(_) => None,
+        }
+    }
+
     pub fn run(self) -> Run {
         self.run_until(future::empty())
     }
 
+    /// Serves connections until `shutdown_signal` resolves. The server then
+    /// stops accepting connections, lets open connections finish the requests
+    /// they are answering, and resolves once they are closed or the drain
+    /// timeout has passed.
     pub fn run_until<F>(self, shutdown_signal: F) -> Run
     where
         F: Future<Item = (), Error = ()> + Send + 'static,
@@ -105,41 +173,86 @@ where
             protocol,
             new_service,
             incoming,
+            drain_timeout,
+            tls: acceptor,
+            tls_handshake_timeout,
         } = self;
 
         let protocol = Arc::new(protocol);
+        let connections = Counter::default();
+        let requests = Counter::default();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let stop = stop_rx.shared();
 
-        let srv = incoming.for_each(move |(socket, addr)| {
-            let protocol = protocol.clone();
-
-            debug!("accepted new connection ({})", addr);
-            let pid = socket.pid()?;
-            let fut = new_service
-                .new_service()
-                .then(move |srv| match srv {
-                    Ok(srv) => Ok((srv, addr)),
-                    Err(err) => {
-                        error!("server connection error: ({})", addr);
-                        log_failure(Level::Error, &err);
-                        Err(())
-                    }
-                })
-                .and_then(move |(srv, addr)| {
-                    let service = PidService::new(pid, srv);
-                    protocol
-                        .serve_connection(socket, service)
-                        .then(move |result| match result {
-                            Ok(_) => Ok(()),
-                            Err(err) => {
-                                error!("server connection error: ({})", addr);
-                                log_failure(Level::Error, &err);
-                                Err(())
-                            }
-                        })
-                });
-            tokio::spawn(fut);
-            Ok(())
-        });
+        let srv = {
+            let connections = connections.clone();
+            let requests = requests.clone();
+            incoming.for_each(move |(socket, addr)| {
+                let protocol = protocol.clone();
+                let connection = connections.guard();
+                let requests = requests.clone();
+                let stop = stop.clone();
+
+                debug!("accepted new connection ({})", addr);
+                let pid = socket.pid()?;
+                let peer = match addr {
+                    IncomingSocketAddr::Tcp(ref addr) => Some(*addr),
+                    IncomingSocketAddr::Unix(_) => None,
+                };
+                let handshake_addr = addr.to_string();
+                let handshake = tls::accept(acceptor.as_ref(), socket, tls_handshake_timeout)
+                    .map_err(move |err| {
+                        debug!("TLS handshake failed ({}): {}", handshake_addr, err);
+                    });
+                let fut = new_service
+                    .new_service()
+                    .then(move |srv| match srv {
+                        Ok(srv) => Ok((srv, addr)),
+                        Err(err) => {
+                            error!("server connection error: ({})", addr);
+                            log_failure(Level::Error, &err);
+                            Err(())
+                        }
+                    })
+                    .join(handshake)
+                    .and_then(move |((srv, addr), (socket, client_cn))| {
+                        let service = InFlightService::new(
+                            requests,
+                            PeerAddrService::new(
+                                peer,
+                                ClientCnService::new(client_cn, PidService::new(pid, srv)),
+                            ),
+                        );
+                        protocol
+                            .serve_connection(socket, service)
+                            .select2(stop)
+                            .then(|result| match result {
+                                Ok(Either::A((done, _))) => Either::A(future::ok(done)),
+                                Err(Either::A((err, _))) => Either::A(future::err(err)),
+                                // Shutting down, so let the connection finish what
+                                // it's doing and close it instead of waiting for
+                                // more requests.
+                                Ok(Either::B((_, mut conn))) | Err(Either::B((_, mut conn))) => {
+                                    conn.graceful_shutdown();
+                                    Either::B(conn)
+                                }
+                            })
+                            .then(move |result| {
+                                drop(connection);
+                                match result {
+                                    Ok(_) => Ok(()),
+                                    Err(err) => {
+                                        error!("server connection error: ({})", addr);
+                                        log_failure(Level::Error, &err);
+                                        Err(())
+                                    }
+                                }
+                            })
+                    });
+                tokio::spawn(fut);
+                Ok(())
+            })
+        };
 
         // We don't care if the shut_down signal errors.
         // Swallow the error.
@@ -150,14 +263,74 @@ where
         let main_execution = shutdown_signal
             .select(srv)
             .then(move |result| match result {
-                Ok(((), _other)) => Ok(()),
-                Err((e, _other)) => Err(Error::from(e.context(ErrorKind::ServiceError))),
+                Ok(((), _other)) => {
+                    stop_tx.send(()).unwrap_or(());
+                    Either::A(
+                        drain(connections, requests, drain_timeout)
+                            .map_err(|()| unreachable!("drain never fails")),
+                    )
+                }
+                Err((e, _other)) => {
+                    Either::B(future::err(Error::from(e.context(ErrorKind::ServiceError))))
+                }
             });
 
         Run(Box::new(main_execution))
     }
 }
 
+/// The URL to listen on, which is the value of the environment variable
+/// `key` if it is set and `default` otherwise.
+pub fn listen_url(key: &str, default: &Url) -> Result<Url, Error> {
+    match env::var(key) {
+        Ok(value) => {
+            if has_zone_id(&value) {
+                return Err(
+                    ErrorKind::InvalidUrlWithReason(value, InvalidUrlReason::Ipv6ZoneId).into(),
+                );
+            }
+            let url = Url::parse(&value).with_context(|_| ErrorKind::InvalidUrl(value.clone()))?;
+            info!("Using {} from {} instead of {}", url, key, default);
+            Ok(url)
+        }
+        Err(VarError::NotPresent) => Ok(default.clone()),
+        Err(VarError::NotUnicode(value)) => {
+            Err(ErrorKind::InvalidUrl(value.to_string_lossy().into_owned()).into())
+        }
+    }
+}
+
+/// Whether the host of `url` is an IPv6 literal with a zone ID, like
+/// `[fe80::1%eth0]`. Zone IDs name a network interface, which makes them
+/// differ between platforms and devices, so they aren't supported.
+fn has_zone_id(url: &str) -> bool {
+    url.find('[')
+        .and_then(|start| {
+            let host = &url[start..];
+            host.find(']').map(|end| host[..end].contains('%'))
+        })
+        .unwrap_or(false)
+}
+
+/// The address to listen on for an `http` or `tcp` URL. IPv6 literals like
+/// `[::1]` are used as they are instead of being resolved.
+fn socket_addr(url: &Url) -> Result<SocketAddr, Error> {
+    let no_address =
+        || ErrorKind::InvalidUrlWithReason(url.to_string(), InvalidUrlReason::NoAddress);
+
+    if let Some(Host::Ipv6(ip)) = url.host() {
+        let port = url.port_or_known_default().ok_or_else(no_address)?;
+        return Ok(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, 0)));
+    }
+
+    let addr = url
+        .to_socket_addrs()
+        .context(ErrorKind::InvalidUrl(url.to_string()))?
+        .next()
+        .ok_or_else(no_address)?;
+    Ok(addr)
+}
+
 pub trait HyperExt {
     fn bind_url<S>(&self, url: Url, new_service: S) -> Result<Server<S>, Error>
     where
@@ -171,17 +344,7 @@ impl HyperExt for Http {
     {
         let incoming = match url.scheme() {
             HTTP_SCHEME | TCP_SCHEME => {
-                let addr = url
-                    .to_socket_addrs()
-                    .context(ErrorKind::InvalidUrl(url.to_string()))?
-                    .next()
-                    .ok_or_else(|| {
-                        ErrorKind::InvalidUrlWithReason(
-                            url.to_string(),
-                            InvalidUrlReason::NoAddress,
-                        )
-                    })?;
-
+                let addr = socket_addr(&url)?;
                 let listener = TcpListener::bind(&addr)
                     .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(addr)))?;
                 Incoming::Tcp(listener)
@@ -190,6 +353,9 @@ impl HyperExt for Http {
                 let path = url
                     .to_uds_file_path()
                     .map_err(|_| ErrorKind::InvalidUrl(url.to_string()))?;
+                unix::validate_path(&path)
+                    .map_err(|reason| ErrorKind::InvalidUrlWithReason(url.to_string(), reason))?;
+                info!("Binding socket {}", path.display());
                 unix::listener(path)?
             }
             #[cfg(unix)]
@@ -244,6 +410,89 @@ impl HyperExt for Http {
             protocol: self.clone(),
             new_service,
             incoming,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            tls: None,
+            tls_handshake_timeout: DEFAULT_TLS_HANDSHAKE_TIMEOUT,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::service::service_fn_ok;
+    use hyper::Request;
+
+    use super::*;
+
+    #[test]
+    fn listen_url_defaults_without_env_var() {
+        let default = Url::parse("unix:///var/run/iotedge/mgmt.sock").unwrap();
+        let url = listen_url("IOTEDGE_TEST_LISTEN_URL_UNSET", &default).unwrap();
+        assert_eq!(default, url);
+    }
+
+    #[test]
+    fn listen_url_from_env_var() {
+        let default = Url::parse("unix:///var/run/iotedge/mgmt.sock").unwrap();
+
+        env::set_var("IOTEDGE_TEST_LISTEN_URL", "unix:///tmp/iotedge/mgmt.sock");
+        let url = listen_url("IOTEDGE_TEST_LISTEN_URL", &default).unwrap();
+        assert_eq!("/tmp/iotedge/mgmt.sock", url.path());
+
+        env::set_var("IOTEDGE_TEST_LISTEN_URL_BAD", "not a url");
+        assert!(listen_url("IOTEDGE_TEST_LISTEN_URL_BAD", &default).is_err());
+    }
+
+    #[test]
+    fn ipv6_literal_is_used_as_address() {
+        let url = Url::parse("http://[::1]:8080").unwrap();
+        assert_eq!(
+            "[::1]:8080".parse::<SocketAddr>().unwrap(),
+            socket_addr(&url).unwrap()
+        );
+
+        let url = Url::parse("http://[::]").unwrap();
+        assert_eq!(
+            "[::]:80".parse::<SocketAddr>().unwrap(),
+            socket_addr(&url).unwrap()
+        );
+
+        let url = Url::parse("tcp://[::1]").unwrap();
+        assert!(socket_addr(&url).is_err());
+    }
+
+    #[test]
+    fn ipv6_zone_id_is_rejected() {
+        let default = Url::parse("unix:///var/run/iotedge/mgmt.sock").unwrap();
+
+        env::set_var("IOTEDGE_TEST_LISTEN_URL_ZONE", "http://[fe80::1%eth0]:8080");
+        let err = listen_url("IOTEDGE_TEST_LISTEN_URL_ZONE", &default).unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidUrlWithReason(_, reason) => {
+                assert_eq!(InvalidUrlReason::Ipv6ZoneId, *reason)
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        assert!(!has_zone_id("http://[::1]:8080/path%20with%20escapes"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn long_socket_path_is_rejected() {
+        let url = Url::parse(&format!("unix:///tmp/{}.sock", "a".repeat(200))).unwrap();
+        let new_service = || {
+            Ok::<_, std::io::Error>(service_fn_ok(|_: Request<Body>| {
+                Response::new(Body::empty())
+            }))
+        };
+
+        let err = Http::new().bind_url(url, new_service).err().unwrap();
+        match err.kind() {
+            ErrorKind::InvalidUrlWithReason(_, reason) => {
+                assert_eq!(InvalidUrlReason::SocketPathTooLong, *reason)
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::net::SocketAddr;
+
+use futures::prelude::*;
+use hyper::service::Service;
+use hyper::{Body, Error as HyperError, Request};
+
+/// The address of the TCP peer a request arrived from.
+///
+/// Requests received over a Unix domain socket or a named pipe don't carry
+/// this extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeerAddr(pub SocketAddr);
+
+#[derive(Clone)]
+pub struct PeerAddrService<T> {
+    addr: Option<SocketAddr>,
+    inner: T,
+}
+
+impl<T> PeerAddrService<T> {
+    pub fn new(addr: Option<SocketAddr>, inner: T) -> Self {
+        PeerAddrService { addr, inner }
+    }
+}
+
+impl<T> Service for PeerAddrService<T>
+where
+    T: Service<ReqBody = Body>,
+    <T as Service>::ResBody: Stream<Error = HyperError> + 'static,
+    <<T as Service>::ResBody as Stream>::Item: AsRef<[u8]>,
+{
+    type ReqBody = T::ReqBody;
+    type ResBody = T::ResBody;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        let mut req = req;
+        if let Some(addr) = self.addr {
+            req.extensions_mut().insert(PeerAddr(addr));
+        }
+        self.inner.call(req)
+    }
+}
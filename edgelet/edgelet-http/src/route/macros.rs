@@ -22,14 +22,38 @@
 ///
 /// The method names must be lowercase and must be one of:
 ///
-/// `get`, `post`, `put` and `delete`
+/// `get`, `post`, `put`, `patch` and `delete`
+///
+/// A route declared with a `Version` variant serves that version and every
+/// later one. `VersionRange(Version2018_06_28, Version2019_01_30)` instead
+/// serves only the versions from the first to the second, inclusive. A
+/// version of `Unversioned` registers a route that doesn't need an
+/// `api-version`.
 #[macro_export]
 macro_rules! router {
-    ($($method:ident $ver:ident, $glob:expr => $handler:expr),+ $(,)*) => ({
+    ($($method:ident $ver:ident $(($min:ident, $max:ident))?, $glob:expr => $handler:expr),+ $(,)*) => ({
         Router::from(
             $crate::route::RegexRoutesBuilder::default()
-            $(.$method(Version::$ver, $glob, $handler))*
+            $(.$method($crate::route_version!($ver $(($min, $max))?), $glob, $handler))*
             .finish()
         )
     });
 }
+
+/// The version of a route declared with `router!`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! route_version {
+    (Unversioned) => {
+        None::<$crate::VersionRange>
+    };
+    (VersionRange($min:ident, $max:ident)) => {
+        Some($crate::VersionRange::new(
+            $crate::Version::$min,
+            $crate::Version::$max,
+        ))
+    };
+    ($ver:ident) => {
+        Some($crate::VersionRange::starting_at(Version::$ver))
+    };
+}
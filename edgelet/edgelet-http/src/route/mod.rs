@@ -13,10 +13,11 @@ use hyper::{Body, Method, Request, Response, StatusCode};
 use url::form_urlencoded::parse as parse_query;
 
 use crate::error::{Error, ErrorKind};
-use crate::version::Version;
+use crate::version::{Version, VersionRange};
 use crate::IntoResponse;
 
 pub mod macros;
+mod rate_limit;
 mod regex;
 
 pub type BoxFuture<T, E> = Box<dyn Future<Item = T, Error = E>>;
@@ -49,10 +50,12 @@ pub type HandlerParamsPair<'a, P> = (&'a dyn Handler<P>, P);
 pub trait Recognizer {
     type Parameters: 'static;
 
+    /// `version` is `None` when the request doesn't have an `api-version`,
+    /// in which case only unversioned routes match.
     fn recognize(
         &self,
         method: &Method,
-        version: Version,
+        version: Option<Version>,
         path: &str,
     ) -> Result<HandlerParamsPair<'_, Self::Parameters>, StatusCode>;
 }
@@ -60,39 +63,57 @@ pub trait Recognizer {
 pub trait Builder: Sized {
     type Recognizer: Recognizer;
 
-    fn route<S, H>(self, method: Method, version: Version, pattern: S, handler: H) -> Self
+    /// A route with a `version` of `None` is unversioned. It matches requests
+    /// with any `api-version`, and requests without one. A single `Version`
+    /// matches requests with that `api-version` or a later one, and a
+    /// `VersionRange` the requests with an `api-version` in the range.
+    fn route<V, S, H>(self, method: Method, version: V, pattern: S, handler: H) -> Self
     where
+        V: Into<Option<VersionRange>>,
         S: AsRef<str>,
         H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync;
 
     fn finish(self) -> Self::Recognizer;
 
-    fn get<S, H>(self, version: Version, pattern: S, handler: H) -> Self
+    fn get<V, S, H>(self, version: V, pattern: S, handler: H) -> Self
     where
+        V: Into<Option<VersionRange>>,
         S: AsRef<str>,
         H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
     {
         self.route(Method::GET, version, pattern, handler)
     }
 
-    fn post<S, H>(self, version: Version, pattern: S, handler: H) -> Self
+    fn post<V, S, H>(self, version: V, pattern: S, handler: H) -> Self
     where
+        V: Into<Option<VersionRange>>,
         S: AsRef<str>,
         H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
     {
         self.route(Method::POST, version, pattern, handler)
     }
 
-    fn put<S, H>(self, version: Version, pattern: S, handler: H) -> Self
+    fn put<V, S, H>(self, version: V, pattern: S, handler: H) -> Self
     where
+        V: Into<Option<VersionRange>>,
         S: AsRef<str>,
         H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
     {
         self.route(Method::PUT, version, pattern, handler)
     }
 
-    fn delete<S, H>(self, version: Version, pattern: S, handler: H) -> Self
+    fn patch<V, S, H>(self, version: V, pattern: S, handler: H) -> Self
     where
+        V: Into<Option<VersionRange>>,
+        S: AsRef<str>,
+        H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
+    {
+        self.route(Method::PATCH, version, pattern, handler)
+    }
+
+    fn delete<V, S, H>(self, version: V, pattern: S, handler: H) -> Self
+    where
+        V: Into<Option<VersionRange>>,
         S: AsRef<str>,
         H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
     {
@@ -155,37 +176,42 @@ where
     type Future = Box<dyn Future<Item = Response<Self::ResBody>, Error = Self::Error> + Send>;
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
-        let api_version = {
-            let query = req.uri().query();
-            query.and_then(|query| {
-                let mut query = parse_query(query.as_bytes());
-                let (_, api_version) = query.find(|&(ref key, _)| key == "api-version")?;
-
-                api_version.parse().ok()
-            })
+        let api_version = req.uri().query().and_then(|query| {
+            let mut query = parse_query(query.as_bytes());
+            let (_, api_version) = query.find(|&(ref key, _)| key == "api-version")?;
+
+            Some(api_version.parse())
+        });
+        let api_version = match api_version {
+            Some(Ok(api_version)) => Some(api_version),
+            Some(Err(())) => return invalid_api_version(),
+            None => None,
         };
 
-        match api_version {
-            Some(api_version) => {
-                let method = req.method().clone();
-                let path = req.uri().path().to_owned();
-                match self.inner.recognize(&method, api_version, &path) {
-                    Ok((handler, params)) => {
-                        Box::new(handler.handle(req, params).map_err(|err| err.compat()))
-                    }
-                    Err(code) => Box::new(future::ok(
-                        Response::builder()
-                            .status(code)
-                            .body(Body::empty())
-                            .expect("hyper::Response with empty body should not fail to build"),
-                    )),
-                }
+        let method = req.method().clone();
+        let path = req.uri().path().to_owned();
+        match self.inner.recognize(&method, api_version, &path) {
+            Ok((handler, params)) => {
+                Box::new(handler.handle(req, params).map_err(|err| err.compat()))
             }
-            None => Box::new(future::ok(
-                Error::from(ErrorKind::InvalidApiVersion(String::new())).into_response(),
+            // Without an api-version only unversioned routes can match, so
+            // the api-version is what's wrong with the request.
+            Err(_) if api_version.is_none() => invalid_api_version(),
+            Err(code) => Box::new(future::ok(
+                Response::builder()
+                    .status(code)
+                    .body(Body::empty())
+                    .expect("hyper::Response with empty body should not fail to build"),
             )),
         }
     }
 }
 
+fn invalid_api_version() -> Box<dyn Future<Item = Response<Body>, Error = Compat<Error>> + Send> {
+    Box::new(future::ok(
+        Error::from(ErrorKind::InvalidApiVersion(String::new())).into_response(),
+    ))
+}
+
+pub use crate::route::rate_limit::{RateLimiter, RateLimiterConfig};
 pub use crate::route::regex::{Parameters, RegexRecognizer, RegexRoutesBuilder};
@@ -0,0 +1,236 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future;
+use hyper::header::RETRY_AFTER;
+use hyper::service::Service;
+use hyper::{Body, Request, Response};
+
+use crate::error::{Error, ErrorKind};
+use crate::peer::PeerAddr;
+use crate::route::{Recognizer, RouterService};
+use crate::IntoResponse;
+
+/// Buckets that have refilled completely are forgotten once this many
+/// sources are being tracked, so that the map doesn't grow without bound.
+const MAX_TRACKED_SOURCES: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimiterConfig {
+    requests_per_second: u32,
+    burst: u32,
+}
+
+impl RateLimiterConfig {
+    /// A value of zero for either limit is treated as one.
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        RateLimiterConfig {
+            requests_per_second: requests_per_second.max(1),
+            burst: burst.max(1),
+        }
+    }
+
+    pub fn requests_per_second(&self) -> u32 {
+        self.requests_per_second
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+}
+
+/// Limits the rate at which each client can call the wrapped router.
+///
+/// Every source IP gets its own token bucket that holds up to `burst` tokens
+/// and is refilled at `requests_per_second`. Requests that did not arrive
+/// over TCP have no source IP and share a single bucket. When a bucket is
+/// empty the request is answered with `429 Too Many Requests` and a
+/// `Retry-After` header, and is never passed to the router.
+pub struct RateLimiter<R: Recognizer> {
+    inner: RouterService<R>,
+    config: RateLimiterConfig,
+    buckets: Arc<Mutex<HashMap<Option<IpAddr>, TokenBucket>>>,
+}
+
+impl<R: Recognizer> RateLimiter<R> {
+    pub fn new(inner: RouterService<R>, config: RateLimiterConfig) -> Self {
+        RateLimiter {
+            inner,
+            config,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn acquire(&self, source: Option<IpAddr>, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_SOURCES && !buckets.contains_key(&source) {
+            let config = &self.config;
+            buckets.retain(|_, bucket| !bucket.is_full(config, now));
+        }
+
+        buckets
+            .entry(source)
+            .or_insert_with(|| TokenBucket::new(&self.config, now))
+            .acquire(&self.config, now)
+    }
+}
+
+impl<R: Recognizer> Clone for RateLimiter<R> {
+    fn clone(&self) -> Self {
+        RateLimiter {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            buckets: self.buckets.clone(),
+        }
+    }
+}
+
+impl<R> Service for RateLimiter<R>
+where
+    R: Recognizer,
+{
+    type ReqBody = <RouterService<R> as Service>::ReqBody;
+    type ResBody = <RouterService<R> as Service>::ResBody;
+    type Error = <RouterService<R> as Service>::Error;
+    type Future = <RouterService<R> as Service>::Future;
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let source = req
+            .extensions()
+            .get::<PeerAddr>()
+            .map(|&PeerAddr(addr)| addr.ip());
+
+        match self.acquire(source, Instant::now()) {
+            Ok(()) => self.inner.call(req),
+            Err(wait) => Box::new(future::ok(too_many_requests(wait))),
+        }
+    }
+}
+
+fn too_many_requests(wait: Duration) -> Response<Body> {
+    // Retry-After only has a resolution of seconds, so round up to avoid
+    // sending the client back before a token is available.
+    let seconds = if wait.subsec_nanos() == 0 {
+        wait.as_secs()
+    } else {
+        wait.as_secs() + 1
+    };
+
+    let mut response = Error::from(ErrorKind::TooManyRequests).into_response();
+    response.headers_mut().insert(
+        RETRY_AFTER,
+        seconds
+            .max(1)
+            .to_string()
+            .parse()
+            .expect("integer is a valid header value"),
+    );
+    response
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: &RateLimiterConfig, now: Instant) -> Self {
+        TokenBucket {
+            tokens: f64::from(config.burst),
+            updated: now,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn refill(&mut self, config: &RateLimiterConfig, now: Instant) {
+        if now > self.updated {
+            let elapsed = now - self.updated;
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            self.tokens = (self.tokens + elapsed * f64::from(config.requests_per_second))
+                .min(f64::from(config.burst));
+            self.updated = now;
+        }
+    }
+
+    fn is_full(&mut self, config: &RateLimiterConfig, now: Instant) -> bool {
+        self.refill(config, now);
+        self.tokens >= f64::from(config.burst)
+    }
+
+    /// On failure returns how long the caller has to wait for the next
+    /// token.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn acquire(&mut self, config: &RateLimiterConfig, now: Instant) -> Result<(), Duration> {
+        self.refill(config, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let seconds = (1.0 - self.tokens) / f64::from(config.requests_per_second);
+            Err(Duration::from_nanos((seconds * 1e9).ceil() as u64))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_treats_zero_as_one() {
+        let config = RateLimiterConfig::new(0, 0);
+        assert_eq!(1, config.requests_per_second());
+        assert_eq!(1, config.burst());
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_refuses() {
+        let config = RateLimiterConfig::new(2, 3);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(&config, now);
+
+        assert!(bucket.acquire(&config, now).is_ok());
+        assert!(bucket.acquire(&config, now).is_ok());
+        assert!(bucket.acquire(&config, now).is_ok());
+        assert_eq!(
+            Err(Duration::from_millis(500)),
+            bucket.acquire(&config, now)
+        );
+    }
+
+    #[test]
+    fn bucket_refills_over_time_up_to_burst() {
+        let config = RateLimiterConfig::new(2, 3);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(&config, now);
+        for _ in 0..3 {
+            bucket.acquire(&config, now).unwrap();
+        }
+
+        let later = now + Duration::from_millis(500);
+        assert!(bucket.acquire(&config, later).is_ok());
+        assert!(bucket.acquire(&config, later).is_err());
+
+        let much_later = later + Duration::from_secs(60);
+        assert!(bucket.is_full(&config, much_later));
+        for _ in 0..3 {
+            assert!(bucket.acquire(&config, much_later).is_ok());
+        }
+        assert!(bucket.acquire(&config, much_later).is_err());
+    }
+
+    #[test]
+    fn retry_after_is_rounded_up_to_whole_seconds() {
+        let response = too_many_requests(Duration::from_millis(1500));
+        assert_eq!(hyper::StatusCode::TOO_MANY_REQUESTS, response.status());
+        assert_eq!("2", response.headers()[RETRY_AFTER]);
+
+        let response = too_many_requests(Duration::from_millis(1));
+        assert_eq!("1", response.headers()[RETRY_AFTER]);
+    }
+}
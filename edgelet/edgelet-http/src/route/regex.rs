@@ -9,7 +9,7 @@ use percent_encoding::percent_decode;
 use regex::Regex;
 
 use super::{Builder, Handler, HandlerParamsPair, Recognizer};
-use crate::version::Version;
+use crate::version::{Version, VersionRange};
 
 pub trait IntoCaptures {
     fn into_captures(self) -> Vec<(Option<String>, String)>;
@@ -67,7 +67,7 @@ impl Default for Parameters {
 struct RegexRoute {
     pattern: Regex,
     handler: Box<dyn Handler<Parameters> + Sync>,
-    version: Version,
+    version: Option<VersionRange>,
 }
 
 #[derive(Default)]
@@ -78,8 +78,9 @@ pub struct RegexRoutesBuilder {
 impl Builder for RegexRoutesBuilder {
     type Recognizer = RegexRecognizer;
 
-    fn route<S, H>(mut self, method: Method, version: Version, pattern: S, handler: H) -> Self
+    fn route<V, S, H>(mut self, method: Method, version: V, pattern: S, handler: H) -> Self
     where
+        V: Into<Option<VersionRange>>,
         S: AsRef<str>,
         H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
     {
@@ -92,7 +93,7 @@ impl Builder for RegexRoutesBuilder {
             .push(RegexRoute {
                 pattern,
                 handler,
-                version,
+                version: version.into(),
             });
         self
     }
@@ -114,12 +115,17 @@ impl Recognizer for RegexRecognizer {
     fn recognize(
         &self,
         method: &Method,
-        api_version: Version,
+        api_version: Option<Version>,
         path: &str,
     ) -> Result<HandlerParamsPair<'_, Self::Parameters>, StatusCode> {
         let routes = self.routes.get(method).ok_or(StatusCode::NOT_FOUND)?;
         for route in routes {
-            if api_version >= route.version {
+            let available = match (api_version, route.version) {
+                (_, None) => true,
+                (Some(api_version), Some(versions)) => versions.contains(api_version),
+                (None, Some(_)) => false,
+            };
+            if available {
                 if let Some(params) = match_route(&route.pattern, path) {
                     return Ok((&*route.handler, params));
                 }
@@ -0,0 +1,318 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use failure::ResultExt;
+use futures::future::{self, Either};
+use futures::prelude::*;
+use hyper::service::Service;
+use hyper::{Body, Error as HyperError, Request};
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use tokio::timer::Timeout;
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::{
+    AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerConfig, Session,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::error::{Error, ErrorKind};
+use crate::util::StreamSelector;
+
+/// How long a client has to complete the TLS handshake after it connects,
+/// so that clients that never finish it don't hold on to their connection.
+pub const DEFAULT_TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The certificates a server authenticates itself and its clients with over
+/// TCP. Clients have to present a certificate signed by `ca_cert_pem`, or
+/// the TLS handshake fails.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    server_cert_pem: String,
+    server_key_pem: String,
+    ca_cert_pem: String,
+}
+
+impl TlsConfig {
+    pub fn new(server_cert_pem: String, server_key_pem: String, ca_cert_pem: String) -> Self {
+        TlsConfig {
+            server_cert_pem,
+            server_key_pem,
+            ca_cert_pem,
+        }
+    }
+
+    pub fn server_cert_pem(&self) -> &str {
+        &self.server_cert_pem
+    }
+
+    pub fn server_key_pem(&self) -> &str {
+        &self.server_key_pem
+    }
+
+    pub fn ca_cert_pem(&self) -> &str {
+        &self.ca_cert_pem
+    }
+
+    pub(crate) fn acceptor(&self) -> Result<TlsAcceptor, Error> {
+        let certs = pemfile::certs(&mut self.server_cert_pem.as_bytes())
+            .ok()
+            .filter(|certs| !certs.is_empty())
+            .ok_or_else(|| ErrorKind::InvalidTlsConfig("no server certificate"))?;
+        let key = private_key(&self.server_key_pem)
+            .ok_or_else(|| ErrorKind::InvalidTlsConfig("no server private key"))?;
+
+        let mut roots = RootCertStore::empty();
+        match roots.add_pem_file(&mut self.ca_cert_pem.as_bytes()) {
+            Ok((valid, _)) if valid > 0 => (),
+            _ => return Err(ErrorKind::InvalidTlsConfig("no CA certificate").into()),
+        }
+
+        let mut config = ServerConfig::new(AllowAnyAuthenticatedClient::new(roots));
+        config
+            .set_single_cert(certs, key)
+            .context(ErrorKind::InvalidTlsConfig(
+                "unsupported server private key",
+            ))?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// The first PKCS #8 or, failing that, RSA private key in `pem`.
+fn private_key(pem: &str) -> Option<PrivateKey> {
+    pemfile::pkcs8_private_keys(&mut pem.as_bytes())
+        .ok()
+        .and_then(|keys| keys.into_iter().next())
+        .or_else(|| {
+            pemfile::rsa_private_keys(&mut pem.as_bytes())
+                .ok()
+                .and_then(|keys| keys.into_iter().next())
+        })
+}
+
+/// The common name of the client certificate a request was authenticated
+/// with.
+///
+/// Only requests received over a TCP connection of a server with a
+/// `TlsConfig` carry this extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientCn(pub String);
+
+/// Completes the TLS handshake of `socket` if `acceptor` is set and it is a
+/// TCP connection, failing if that takes longer than `timeout`. Other
+/// connections are returned as they are, so Unix sockets stay authenticated
+/// by the caller's process ID only.
+pub(crate) fn accept(
+    acceptor: Option<&TlsAcceptor>,
+    socket: StreamSelector,
+    timeout: Duration,
+) -> impl Future<Item = (StreamSelector, Option<ClientCn>), Error = io::Error> + Send {
+    match (acceptor, socket) {
+        (Some(acceptor), StreamSelector::Tcp(stream)) => Either::A(
+            Timeout::new(acceptor.accept(stream), timeout)
+                .map_err(|err| {
+                    err.into_inner().unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out")
+                    })
+                })
+                .map(|stream| {
+                    let client_cn = stream
+                        .get_ref()
+                        .1
+                        .get_peer_certificates()
+                        .and_then(|certs| certs.first().and_then(common_name));
+                    (StreamSelector::Tls(Box::new(stream)), client_cn)
+                }),
+        ),
+        (_, socket) => Either::B(future::ok((socket, None))),
+    }
+}
+
+fn common_name(cert: &Certificate) -> Option<ClientCn> {
+    let cert = X509::from_der(&cert.0).ok()?;
+    let entry = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next()?;
+    let cn = entry.data().as_utf8().ok()?;
+    Some(ClientCn(cn.to_string()))
+}
+
+#[derive(Clone)]
+pub struct ClientCnService<T> {
+    client_cn: Option<ClientCn>,
+    inner: T,
+}
+
+impl<T> ClientCnService<T> {
+    pub fn new(client_cn: Option<ClientCn>, inner: T) -> Self {
+        ClientCnService { client_cn, inner }
+    }
+}
+
+impl<T> Service for ClientCnService<T>
+where
+    T: Service<ReqBody = Body>,
+    <T as Service>::ResBody: Stream<Error = HyperError> + 'static,
+    <<T as Service>::ResBody as Stream>::Item: AsRef<[u8]>,
+{
+    type ReqBody = T::ReqBody;
+    type ResBody = T::ResBody;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        let mut req = req;
+        if let Some(ref client_cn) = self.client_cn {
+            req.extensions_mut().insert(client_cn.clone());
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::service::service_fn_ok;
+    use hyper::{Response, StatusCode};
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::{BigNum, MsbOption};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::{BasicConstraints, KeyUsage, SubjectAlternativeName};
+    use openssl::x509::X509NameBuilder;
+
+    use super::*;
+
+    /// A certificate for `cn` valid for a day, a CA certificate if `issuer`
+    /// is `None` and otherwise a leaf certificate signed by `issuer`. Unlike
+    /// the CAs, the leaves can also authenticate a server as `localhost`.
+    fn certificate(cn: &str, issuer: Option<(&X509, &PKey<Private>)>) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        let name = name.build();
+
+        let mut serial = BigNum::new().unwrap();
+        serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        match issuer {
+            None => {
+                cert.set_issuer_name(&name).unwrap();
+                cert.append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                    .unwrap();
+                cert.append_extension(
+                    KeyUsage::new()
+                        .critical()
+                        .key_cert_sign()
+                        .crl_sign()
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+                cert.sign(&key, MessageDigest::sha256()).unwrap();
+            }
+            Some((issuer_cert, issuer_key)) => {
+                cert.set_issuer_name(issuer_cert.subject_name()).unwrap();
+                let san = SubjectAlternativeName::new()
+                    .dns("localhost")
+                    .build(&cert.x509v3_context(Some(issuer_cert), None))
+                    .unwrap();
+                cert.append_extension(san).unwrap();
+                cert.sign(issuer_key, MessageDigest::sha256()).unwrap();
+            }
+        }
+
+        (cert.build(), key)
+    }
+
+    fn pem(cert: &X509) -> String {
+        String::from_utf8(cert.to_pem().unwrap()).unwrap()
+    }
+
+    fn key_pem(key: &PKey<Private>) -> String {
+        String::from_utf8(key.private_key_to_pem_pkcs8().unwrap()).unwrap()
+    }
+
+    /// The server certificate and key and the CA certificate of a valid
+    /// `TlsConfig`.
+    fn pems() -> (String, String, String) {
+        let (ca, ca_key) = certificate("ca", None);
+        let (cert, key) = certificate("server", Some((&ca, &ca_key)));
+        (pem(&cert), key_pem(&key), pem(&ca))
+    }
+
+    #[test]
+    fn acceptor_from_valid_config() {
+        let (cert, key, ca) = pems();
+
+        assert!(TlsConfig::new(cert, key, ca).acceptor().is_ok());
+    }
+
+    #[test]
+    fn acceptor_rejects_missing_pems() {
+        let (cert, key, ca) = pems();
+
+        for (config, reason) in vec![
+            (
+                TlsConfig::new(String::new(), key.clone(), ca.clone()),
+                "no server certificate",
+            ),
+            (
+                TlsConfig::new(cert.clone(), String::new(), ca.clone()),
+                "no server private key",
+            ),
+            (
+                TlsConfig::new(cert.clone(), key.clone(), String::new()),
+                "no CA certificate",
+            ),
+        ] {
+            let err = config.acceptor().err().unwrap();
+            assert_eq!(&ErrorKind::InvalidTlsConfig(reason), err.kind());
+        }
+    }
+
+    #[test]
+    fn common_name_of_certificate() {
+        let (ca, ca_key) = certificate("ca", None);
+        let (cert, _) = certificate("module1", Some((&ca, &ca_key)));
+        let der = cert.to_der().unwrap();
+
+        assert_eq!(
+            Some(ClientCn("module1".to_string())),
+            common_name(&Certificate(der))
+        );
+        assert_eq!(None, common_name(&Certificate(vec![1, 2, 3])));
+    }
+
+    #[test]
+    fn client_cn_service_inserts_extension() {
+        let inner = service_fn_ok(|req: Request<Body>| {
+            let status = match req.extensions().get::<ClientCn>() {
+                Some(ClientCn(cn)) if cn == "module1" => StatusCode::OK,
+                _ => StatusCode::FORBIDDEN,
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap()
+        });
+        let mut service = ClientCnService::new(Some(ClientCn("module1".to_string())), inner);
+
+        let response = service
+            .call(Request::get("/").body(Body::empty()).unwrap())
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+    }
+}
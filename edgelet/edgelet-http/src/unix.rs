@@ -16,9 +16,24 @@ use tokio_uds::UnixListener;
 #[cfg(windows)]
 use tokio_uds_windows::UnixListener;
 
-use crate::error::{Error, ErrorKind};
+use crate::error::{Error, ErrorKind, InvalidUrlReason};
 use crate::util::{incoming::Incoming, socket_file_exists};
 
+/// The longest socket path that fits in a `sockaddr_un` on every platform.
+/// Linux allows 108 bytes but macOS and the BSDs only 104.
+pub const MAX_SOCKET_PATH_LEN: usize = 104;
+
+/// Checks that `path` can be bound as a Unix socket.
+pub fn validate_path(path: &Path) -> Result<(), InvalidUrlReason> {
+    if path.to_string_lossy().contains('\0') {
+        Err(InvalidUrlReason::SocketPathHasNul)
+    } else if path.as_os_str().len() > MAX_SOCKET_PATH_LEN {
+        Err(InvalidUrlReason::SocketPathTooLong)
+    } else {
+        Ok(())
+    }
+}
+
 pub fn listener<P: AsRef<Path>>(path: P) -> Result<Incoming, Error> {
     let listener = if socket_file_exists(path.as_ref()) {
         // get the previous file's metadata
@@ -81,6 +96,25 @@ mod tests {
     use nix::sys::stat::stat;
     use tempfile::tempdir;
 
+    #[test]
+    fn socket_path_is_validated() {
+        assert_eq!(
+            Ok(()),
+            validate_path(Path::new("/var/run/iotedge/mgmt.sock"))
+        );
+        assert_eq!(
+            Err(InvalidUrlReason::SocketPathHasNul),
+            validate_path(Path::new("/var/run/iotedge/mgmt\0.sock"))
+        );
+
+        let longest = format!("/{}", "a".repeat(MAX_SOCKET_PATH_LEN - 1));
+        assert_eq!(Ok(()), validate_path(Path::new(&longest)));
+        assert_eq!(
+            Err(InvalidUrlReason::SocketPathTooLong),
+            validate_path(Path::new(&format!("{}a", longest)))
+        );
+    }
+
     #[test]
     fn test_unlink() {
         let dir = tempdir().unwrap();
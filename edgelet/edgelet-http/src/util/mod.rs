@@ -16,6 +16,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 #[cfg(windows)]
 use tokio_named_pipe::PipeStream;
+use tokio_rustls::server::TlsStream;
 #[cfg(unix)]
 use tokio_uds::UnixStream;
 #[cfg(windows)]
@@ -33,6 +34,7 @@ pub use self::incoming::Incoming;
 
 pub enum StreamSelector {
     Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
     #[cfg(windows)]
     Pipe(PipeStream),
     Unix(UnixStream),
@@ -42,7 +44,7 @@ impl StreamSelector {
     #[allow(clippy::match_same_arms)]
     pub fn pid(&self) -> io::Result<Pid> {
         match *self {
-            StreamSelector::Tcp(_) => Ok(Pid::Any),
+            StreamSelector::Tcp(_) | StreamSelector::Tls(_) => Ok(Pid::Any),
             #[cfg(windows)]
             StreamSelector::Pipe(_) => Ok(Pid::Any),
             StreamSelector::Unix(ref stream) => stream.pid(),
@@ -54,6 +56,7 @@ impl Read for StreamSelector {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match *self {
             StreamSelector::Tcp(ref mut stream) => stream.read(buf),
+            StreamSelector::Tls(ref mut stream) => stream.read(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(ref mut stream) => stream.read(buf),
             StreamSelector::Unix(ref mut stream) => stream.read(buf),
@@ -65,6 +68,7 @@ impl Write for StreamSelector {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match *self {
             StreamSelector::Tcp(ref mut stream) => stream.write(buf),
+            StreamSelector::Tls(ref mut stream) => stream.write(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(ref mut stream) => stream.write(buf),
             StreamSelector::Unix(ref mut stream) => stream.write(buf),
@@ -74,6 +78,7 @@ impl Write for StreamSelector {
     fn flush(&mut self) -> io::Result<()> {
         match *self {
             StreamSelector::Tcp(ref mut stream) => stream.flush(),
+            StreamSelector::Tls(ref mut stream) => stream.flush(),
             #[cfg(windows)]
             StreamSelector::Pipe(ref mut stream) => stream.flush(),
             StreamSelector::Unix(ref mut stream) => stream.flush(),
@@ -86,6 +91,7 @@ impl AsyncRead for StreamSelector {
     unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
         match *self {
             StreamSelector::Tcp(ref stream) => stream.prepare_uninitialized_buffer(buf),
+            StreamSelector::Tls(ref stream) => stream.prepare_uninitialized_buffer(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(ref stream) => stream.prepare_uninitialized_buffer(buf),
             StreamSelector::Unix(ref stream) => stream.prepare_uninitialized_buffer(buf),
@@ -96,6 +102,7 @@ impl AsyncRead for StreamSelector {
     fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
         match *self {
             StreamSelector::Tcp(ref mut stream) => stream.read_buf(buf),
+            StreamSelector::Tls(ref mut stream) => stream.read_buf(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(ref mut stream) => stream.read_buf(buf),
             StreamSelector::Unix(ref mut stream) => stream.read_buf(buf),
@@ -107,6 +114,7 @@ impl AsyncWrite for StreamSelector {
     fn shutdown(&mut self) -> Poll<(), io::Error> {
         match *self {
             StreamSelector::Tcp(ref mut stream) => <&TcpStream>::shutdown(&mut &*stream),
+            StreamSelector::Tls(ref mut stream) => stream.shutdown(),
             #[cfg(windows)]
             StreamSelector::Pipe(ref mut stream) => PipeStream::shutdown(stream),
             StreamSelector::Unix(ref mut stream) => <&UnixStream>::shutdown(&mut &*stream),
@@ -117,6 +125,7 @@ impl AsyncWrite for StreamSelector {
     fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
         match *self {
             StreamSelector::Tcp(ref mut stream) => stream.write_buf(buf),
+            StreamSelector::Tls(ref mut stream) => stream.write_buf(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(ref mut stream) => stream.write_buf(buf),
             StreamSelector::Unix(ref mut stream) => stream.write_buf(buf),
@@ -5,29 +5,143 @@ use std::str::FromStr;
 
 pub const API_VERSION: Version = Version::Version2019_01_30;
 
-#[derive(Clone, Copy, Debug, PartialOrd, PartialEq)]
-pub enum Version {
-    Version2018_06_28,
-    Version2019_01_30,
+/// Declares the `Version` enum along with its string forms, so that a new API
+/// version only has to be added in one place to be parsed, displayed and
+/// listed in `Version::ALL`.
+macro_rules! versions {
+    ($($variant:ident => $value:literal,)*) => {
+        #[derive(Clone, Copy, Debug, PartialOrd, PartialEq)]
+        pub enum Version {
+            $($variant,)*
+        }
+
+        impl Version {
+            /// Every API version, oldest first.
+            pub const ALL: &'static [Version] = &[$(Version::$variant,)*];
+        }
+
+        impl FromStr for Version {
+            type Err = ();
+
+            fn from_str(s: &str) -> Result<Version, ()> {
+                match s {
+                    $($value => Ok(Version::$variant),)*
+                    _ => Err(()),
+                }
+            }
+        }
+
+        impl fmt::Display for Version {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match *self {
+                    $(Version::$variant => f.write_str($value),)*
+                }
+            }
+        }
+    };
 }
 
-impl FromStr for Version {
-    type Err = ();
+versions! {
+    Version2018_06_28 => "2018-06-28",
+    Version2019_01_30 => "2019-01-30",
+}
 
-    fn from_str(s: &str) -> Result<Version, ()> {
-        match s {
-            "2018-06-28" => Ok(Version::Version2018_06_28),
-            "2019-01-30" => Ok(Version::Version2019_01_30),
-            _ => Err(()),
+/// The API versions a route serves, from `min` to `max` inclusive.
+///
+/// A route registered with a single version serves that version and every
+/// later one, which is the range `VersionRange::starting_at(version)`. A
+/// range with an explicit `max` lets a route be retired at some version
+/// without duplicating the routes that stay the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VersionRange {
+    min: Version,
+    max: Version,
+}
+
+impl VersionRange {
+    /// A range with `min` after `max` contains no versions.
+    pub const fn new(min: Version, max: Version) -> Self {
+        VersionRange { min, max }
+    }
+
+    pub const fn starting_at(min: Version) -> Self {
+        VersionRange {
+            min,
+            max: API_VERSION,
         }
     }
+
+    pub fn min(&self) -> Version {
+        self.min
+    }
+
+    pub fn max(&self) -> Version {
+        self.max
+    }
+
+    pub fn contains(&self, version: Version) -> bool {
+        self.min <= version && version <= self.max
+    }
 }
 
-impl fmt::Display for Version {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Version::Version2018_06_28 => write!(f, "2018-06-28"),
-            Version::Version2019_01_30 => write!(f, "2019-01-30"),
+impl From<Version> for VersionRange {
+    fn from(version: Version) -> Self {
+        VersionRange::starting_at(version)
+    }
+}
+
+impl From<Version> for Option<VersionRange> {
+    fn from(version: Version) -> Self {
+        Some(VersionRange::starting_at(version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_versions_are_ordered_and_round_trip() {
+        for pair in Version::ALL.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        for version in Version::ALL {
+            assert_eq!(Ok(*version), version.to_string().parse());
         }
+        assert_eq!(Some(&API_VERSION), Version::ALL.last());
+    }
+
+    #[test]
+    fn range_contains_its_boundaries() {
+        let range = VersionRange::new(Version::Version2018_06_28, Version::Version2019_01_30);
+        assert!(range.contains(Version::Version2018_06_28));
+        assert!(range.contains(Version::Version2019_01_30));
+
+        let range = VersionRange::new(Version::Version2018_06_28, Version::Version2018_06_28);
+        assert!(range.contains(Version::Version2018_06_28));
+        assert!(!range.contains(Version::Version2019_01_30));
+    }
+
+    #[test]
+    fn range_excludes_versions_outside_it() {
+        let range = VersionRange::new(Version::Version2019_01_30, Version::Version2019_01_30);
+        assert!(!range.contains(Version::Version2018_06_28));
+
+        let range = VersionRange::new(Version::Version2019_01_30, Version::Version2018_06_28);
+        for version in Version::ALL {
+            assert!(!range.contains(*version));
+        }
+    }
+
+    #[test]
+    fn single_version_starts_a_range() {
+        let range = VersionRange::from(Version::Version2018_06_28);
+        for version in Version::ALL {
+            assert!(range.contains(*version));
+        }
+
+        let range = VersionRange::from(Version::Version2019_01_30);
+        assert!(!range.contains(Version::Version2018_06_28));
+        assert_eq!(API_VERSION, range.max());
     }
 }
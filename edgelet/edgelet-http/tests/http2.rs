@@ -0,0 +1,74 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use edgelet_http::HyperExt;
+use edgelet_test_utils::get_unused_tcp_port;
+use futures::future;
+use futures::prelude::*;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Client, Request, Response, StatusCode};
+use url::Url;
+
+const REQUESTS: usize = 50;
+
+#[allow(clippy::needless_pass_by_value)]
+fn echo_path(req: Request<Body>) -> impl Future<Item = Response<Body>, Error = io::Error> {
+    future::ok(Response::new(req.uri().path().to_string().into()))
+}
+
+#[test]
+fn concurrent_requests_share_one_connection() {
+    let port = get_unused_tcp_port();
+    let url = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+    let connections = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let connections = connections.clone();
+        let mut protocol = Http::new();
+        protocol.http2_only(true);
+        protocol
+            .bind_url(url, move || {
+                connections.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, io::Error>(service_fn(echo_path))
+            })
+            .unwrap()
+            .run()
+            .map_err(|err| eprintln!("{}", err))
+    };
+
+    let client = Client::builder().http2_only(true).build_http::<Body>();
+    let get = move |path: String| {
+        let uri = format!("http://127.0.0.1:{}{}", port, path)
+            .parse()
+            .unwrap();
+        client
+            .get(uri)
+            .and_then(|res| {
+                assert_eq!(StatusCode::OK, res.status());
+                res.into_body().concat2()
+            })
+            .map(move |body| {
+                assert_eq!(path, String::from_utf8_lossy(body.as_ref()));
+            })
+    };
+
+    // The first request establishes the connection, which all the concurrent
+    // requests after it are then multiplexed over.
+    let task = get("/first".to_string()).and_then(move |()| {
+        future::join_all((0..REQUESTS).map(move |i| get(format!("/requests/{}", i))))
+    });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let responses = runtime.block_on(task).unwrap();
+
+    assert_eq!(REQUESTS, responses.len());
+    assert_eq!(1, connections.load(Ordering::SeqCst));
+}
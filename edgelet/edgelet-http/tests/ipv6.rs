@@ -0,0 +1,39 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+
+use std::io;
+
+use edgelet_http::HyperExt;
+use futures::future;
+use futures::prelude::*;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Client, Request, Response, StatusCode};
+use url::Url;
+
+#[allow(clippy::needless_pass_by_value)]
+fn ok(_req: Request<Body>) -> impl Future<Item = Response<Body>, Error = io::Error> {
+    future::ok(Response::new(Body::empty()))
+}
+
+#[test]
+fn serves_ipv6_loopback() {
+    let url = Url::parse("http://[::1]:0").unwrap();
+    let server = Http::new()
+        .bind_url(url, || Ok::<_, io::Error>(service_fn(ok)))
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    assert!(addr.is_ipv6());
+
+    let uri = format!("http://[::1]:{}/", addr.port()).parse().unwrap();
+    let client = Client::new();
+    let task = client.get(uri).map(|res| res.status());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server.run().map_err(|err| eprintln!("{}", err)));
+    let status = runtime.block_on(task).unwrap();
+
+    assert_eq!(StatusCode::OK, status);
+}
@@ -4,12 +4,15 @@
 #![deny(clippy::all, clippy::pedantic)]
 
 use futures::{future, Future, Stream};
+use hyper::header::RETRY_AFTER;
 use hyper::service::{NewService, Service};
 use hyper::{Body, Chunk, Request, Response, StatusCode};
 
-use edgelet_http::route::{Builder, Parameters, RegexRoutesBuilder, Router};
-use edgelet_http::Error as HttpError;
-use edgelet_http::Version;
+use edgelet_http::route::{
+    Builder, Parameters, RateLimiter, RateLimiterConfig, RegexRoutesBuilder, Router,
+};
+use edgelet_http::{router, Version, VersionRange};
+use edgelet_http::{Error as HttpError, PeerAddr};
 
 #[allow(clippy::needless_pass_by_value)]
 fn route1(
@@ -141,6 +144,39 @@ fn same_path_with_different_version() {
     assert_eq!("route1 thename2", body2);
 }
 
+#[test]
+fn unversioned_route() {
+    let recognizer = RegexRoutesBuilder::default()
+        .get(None::<VersionRange>, "/route1/(?P<name>[^/]+)", route1)
+        .get(
+            Version::Version2018_06_28,
+            "/route2/(?P<name>[^/]+)",
+            route2,
+        )
+        .finish();
+    let router = Router::from(recognizer);
+    let mut service = router.new_service().wait().unwrap();
+
+    let request = Request::get("http://example.com/route1/thename")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    let request = Request::get("http://example.com/route1/thename?api-version=2019-01-30")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    // A versioned route still needs an api-version.
+    let request = Request::get("http://example.com/route2/thename")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+}
+
 #[test]
 fn not_found() {
     let recognizer = RegexRoutesBuilder::default()
@@ -238,3 +274,98 @@ fn not_found_for_api_version() {
 
     assert_eq!(StatusCode::NOT_FOUND, response.status());
 }
+
+#[test]
+fn version_range_route() {
+    let recognizer = RegexRoutesBuilder::default()
+        .get(
+            VersionRange::new(Version::Version2018_06_28, Version::Version2018_06_28),
+            "/route1/(?P<name>[^/]+)",
+            route1,
+        )
+        .get(
+            Version::Version2019_01_30,
+            "/route1/(?P<name>[^/]+)",
+            route2,
+        )
+        .finish();
+    let router = Router::from(recognizer);
+    let mut service = router.new_service().wait().unwrap();
+
+    let request = Request::get("http://example.com/route1/thename?api-version=2018-06-28")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    let body = response.into_body().concat2().wait().unwrap();
+    assert_eq!(b"route1 thename", &*body);
+
+    // The range ends before 2019-01-30, so the later route answers.
+    let request = Request::get("http://example.com/route1/thename?api-version=2019-01-30")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    let body = response.into_body().concat2().wait().unwrap();
+    assert_eq!(b"route2 thename", &*body);
+}
+
+#[test]
+fn version_range_in_router_macro() {
+    let router = router!(
+        get VersionRange(Version2019_01_30, Version2019_01_30), "/route1/(?P<name>[^/]+)" => route1,
+        get Version2018_06_28, "/route2/(?P<name>[^/]+)" => route2,
+    );
+    let mut service = router.new_service().wait().unwrap();
+
+    let request = Request::get("http://example.com/route1/thename?api-version=2019-01-30")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    let request = Request::get("http://example.com/route1/thename?api-version=2018-06-28")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let request = Request::get("http://example.com/route2/thename?api-version=2019-01-30")
+        .body(Body::default())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(StatusCode::CREATED, response.status());
+}
+
+#[test]
+fn rate_limited_per_peer() {
+    let recognizer = RegexRoutesBuilder::default()
+        .get(
+            Version::Version2018_06_28,
+            "/route1/(?P<name>[^/]+)",
+            route1,
+        )
+        .finish();
+    let router = Router::from(recognizer);
+    let service = router.new_service().wait().unwrap();
+    let mut service = RateLimiter::new(service, RateLimiterConfig::new(1, 2));
+
+    let request = |peer: &str| {
+        let mut request = Request::get("http://example.com/route1/thename?api-version=2018-06-28")
+            .body(Body::default())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(PeerAddr(peer.parse().unwrap()));
+        request
+    };
+
+    let response1 = service.call(request("10.0.0.1:1000")).wait().unwrap();
+    let response2 = service.call(request("10.0.0.1:1001")).wait().unwrap();
+    let response3 = service.call(request("10.0.0.1:1002")).wait().unwrap();
+    let response4 = service.call(request("10.0.0.2:1000")).wait().unwrap();
+
+    assert_eq!(StatusCode::OK, response1.status());
+    assert_eq!(StatusCode::OK, response2.status());
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, response3.status());
+    assert_eq!("1", response3.headers()[RETRY_AFTER]);
+    assert_eq!(StatusCode::OK, response4.status());
+}
@@ -0,0 +1,217 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use edgelet_http::{ClientCn, HyperExt, TlsConfig};
+use edgelet_test_utils::get_unused_tcp_port;
+use futures::future;
+use futures::prelude::*;
+use hyper::client::conn;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, StatusCode};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::extension::{BasicConstraints, KeyUsage, SubjectAlternativeName};
+use openssl::x509::{X509NameBuilder, X509};
+use tokio::net::TcpStream;
+use tokio::runtime::current_thread::Runtime;
+use tokio_rustls::rustls::{Certificate, ClientConfig, PrivateKey};
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// A certificate for `cn` valid for a day, a CA certificate if `issuer` is
+/// `None` and otherwise a leaf certificate for `localhost` signed by `issuer`.
+fn certificate(cn: &str, issuer: Option<(&X509, &PKey<Private>)>) -> (X509, PKey<Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+    let name = name.build();
+
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    match issuer {
+        None => {
+            cert.set_issuer_name(&name).unwrap();
+            cert.append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+            cert.append_extension(
+                KeyUsage::new()
+                    .critical()
+                    .key_cert_sign()
+                    .crl_sign()
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+            cert.sign(&key, MessageDigest::sha256()).unwrap();
+        }
+        Some((issuer_cert, issuer_key)) => {
+            cert.set_issuer_name(issuer_cert.subject_name()).unwrap();
+            let san = SubjectAlternativeName::new()
+                .dns("localhost")
+                .build(&cert.x509v3_context(Some(issuer_cert), None))
+                .unwrap();
+            cert.append_extension(san).unwrap();
+            cert.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        }
+    }
+
+    (cert.build(), key)
+}
+
+fn pem(cert: &X509) -> String {
+    String::from_utf8(cert.to_pem().unwrap()).unwrap()
+}
+
+/// Responds with the common name of the client certificate the request was
+/// authenticated with.
+#[allow(clippy::needless_pass_by_value)]
+fn echo_client_cn(req: Request<Body>) -> impl Future<Item = Response<Body>, Error = io::Error> {
+    let response = match req.extensions().get::<ClientCn>() {
+        Some(ClientCn(cn)) => Response::new(cn.clone().into()),
+        None => Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::empty())
+            .unwrap(),
+    };
+    future::ok(response)
+}
+
+struct Fixture {
+    port: u16,
+    ca: (X509, PKey<Private>),
+    runtime: Runtime,
+}
+
+impl Fixture {
+    fn new(handshake_timeout: Duration) -> Self {
+        let port = get_unused_tcp_port();
+        let url = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+        let ca = certificate("ca", None);
+        let (server_cert, server_key) = certificate("server", Some((&ca.0, &ca.1)));
+        let config = TlsConfig::new(
+            pem(&server_cert),
+            String::from_utf8(server_key.private_key_to_pem_pkcs8().unwrap()).unwrap(),
+            pem(&ca.0),
+        );
+
+        let server = Http::new()
+            .bind_url(url, || Ok::<_, io::Error>(service_fn(echo_client_cn)))
+            .unwrap()
+            .with_tls(&config)
+            .unwrap()
+            .with_tls_handshake_timeout(handshake_timeout)
+            .run()
+            .map_err(|err| eprintln!("{}", err));
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        Fixture { port, ca, runtime }
+    }
+
+    /// Sends a request over TLS, authenticating the client with `client`
+    /// if it is set, and returns the status and body of the response.
+    fn get(
+        &mut self,
+        client: Option<(&X509, &PKey<Private>)>,
+    ) -> Result<(StatusCode, String), String> {
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add(&Certificate(self.ca.0.to_der().unwrap()))
+            .unwrap();
+        if let Some((cert, key)) = client {
+            config.set_single_client_cert(
+                vec![Certificate(cert.to_der().unwrap())],
+                PrivateKey(key.private_key_to_der().unwrap()),
+            );
+        }
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let addr = format!("127.0.0.1:{}", self.port).parse().unwrap();
+        let request = TcpStream::connect(&addr)
+            .and_then(move |stream| {
+                let domain = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+                connector.connect(domain, stream)
+            })
+            .map_err(|err| err.to_string())
+            .and_then(|stream| conn::handshake(stream).map_err(|err| err.to_string()))
+            .and_then(|(mut client, connection)| {
+                tokio::spawn(connection.map_err(|_| ()));
+                client
+                    .send_request(Request::get("/").body(Body::empty()).unwrap())
+                    .map_err(|err| err.to_string())
+            })
+            .and_then(|res| {
+                let status = res.status();
+                res.into_body()
+                    .concat2()
+                    .map(move |body| (status, String::from_utf8_lossy(&body).to_string()))
+                    .map_err(|err| err.to_string())
+            });
+        self.runtime.block_on(request)
+    }
+}
+
+#[test]
+fn trusted_client_certificate_is_passed_on_as_client_cn() {
+    let mut fixture = Fixture::new(Duration::from_secs(10));
+    let client = certificate("module1", Some((&fixture.ca.0, &fixture.ca.1)));
+
+    assert_eq!(
+        Ok((StatusCode::OK, "module1".to_string())),
+        fixture.get(Some((&client.0, &client.1)))
+    );
+}
+
+#[test]
+fn untrusted_client_certificate_is_refused() {
+    let mut fixture = Fixture::new(Duration::from_secs(10));
+    let other_ca = certificate("other ca", None);
+    let client = certificate("module1", Some((&other_ca.0, &other_ca.1)));
+
+    assert!(fixture.get(Some((&client.0, &client.1))).is_err());
+}
+
+#[test]
+fn missing_client_certificate_is_refused() {
+    let mut fixture = Fixture::new(Duration::from_secs(10));
+
+    assert!(fixture.get(None).is_err());
+}
+
+#[test]
+fn connections_that_never_start_the_handshake_are_closed() {
+    let mut fixture = Fixture::new(Duration::from_millis(100));
+
+    // The server closes the connection once the handshake times out, so
+    // reading from it ends rather than waiting for a response forever.
+    let addr = format!("127.0.0.1:{}", fixture.port).parse().unwrap();
+    let read = TcpStream::connect(&addr).and_then(|stream| tokio::io::read_to_end(stream, vec![]));
+    let (_, received) = fixture.runtime.block_on(read).unwrap();
+
+    assert!(received.is_empty());
+}
@@ -5,7 +5,9 @@ use std::fmt::Display;
 
 use failure::{Backtrace, Context, Fail};
 
-use edgelet_core::IdentityOperation;
+use edgelet_core::{IdentityManagerErrorReason, IdentityOperation};
+use edgelet_http::Error as HttpError;
+use edgelet_http::ErrorKind as HttpErrorKind;
 
 #[derive(Debug)]
 pub struct Error {
@@ -70,6 +72,20 @@ impl From<Context<ErrorKind>> for Error {
     }
 }
 
+impl<'a> From<&'a Error> for IdentityManagerErrorReason {
+    fn from(err: &'a Error) -> Self {
+        match Fail::find_root_cause(err)
+            .downcast_ref::<HttpError>()
+            .map(HttpError::kind)
+        {
+            Some(HttpErrorKind::HttpWithErrorResponse(status, _)) => {
+                IdentityManagerErrorReason::ServiceError(status.as_u16())
+            }
+            _ => IdentityManagerErrorReason::Other,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum IdentityOperationReason {
     InvalidHubResponse,
@@ -2,7 +2,11 @@
 
 #![deny(rust_2018_idioms, warnings)]
 #![deny(clippy::all, clippy::pedantic)]
-#![allow(clippy::module_name_repetitions, clippy::use_self)]
+#![allow(
+    clippy::doc_markdown, // clippy want the "IoT" of "IoT Hub" in a code fence
+    clippy::module_name_repetitions,
+    clippy::use_self,
+)]
 
 mod error;
 
@@ -16,14 +20,18 @@ use futures::future::{self, Either};
 use futures::Future;
 use percent_encoding::{define_encode_set, percent_encode, PATH_SEGMENT_ENCODE_SET};
 use serde_derive::Serialize;
+use serde_json::{self, Value};
 use url::form_urlencoded::Serializer as UrlSerializer;
 
 use edgelet_core::crypto::{KeyIdentity, KeyStore, Sign, Signature, SignatureAlgorithm};
-use edgelet_core::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_core::{
+    AuthType, Connectivity, Identity, IdentityManager, IdentityOperation, IdentitySpec,
+    X509Thumbprint,
+};
 use edgelet_http::client::{ClientImpl, TokenSource};
 use iothubservice::{
     AuthMechanism, AuthType as HubAuthType, DeviceClient, ErrorKind as HubErrorKind, Module,
-    ModuleOperationReason as HubReason, SymmetricKey,
+    ModuleOperationReason as HubReason, SymmetricKey, X509Thumbprint as HubX509Thumbprint,
 };
 
 pub use crate::error::{Error, ErrorKind, IdentityOperationReason};
@@ -71,6 +79,15 @@ impl Identity for HubIdentity {
     }
 }
 
+/// Authentication with the certificate that has the given thumbprint.
+fn x509_auth(thumbprint: &X509Thumbprint) -> AuthMechanism {
+    AuthMechanism::default()
+        .with_type(HubAuthType::X509)
+        .with_x509_thumbprint(
+            HubX509Thumbprint::default().with_primary_thumbprint(thumbprint.as_str().to_string()),
+        )
+}
+
 fn convert_auth_type(hub_auth_type: HubAuthType) -> AuthType {
     match hub_auth_type {
         HubAuthType::None => AuthType::None,
@@ -161,6 +178,7 @@ where
     D: 'static + Sign + Clone,
 {
     state: Arc<State<K, C, D>>,
+    connectivity: Connectivity,
     phantom: PhantomData<D>,
 }
 
@@ -174,10 +192,35 @@ where
     pub fn new(key_store: K, client: DeviceClient<C, SasTokenSource<D>>) -> Self {
         HubIdentityManager {
             state: Arc::new(State { key_store, client }),
+            connectivity: Connectivity::new(),
             phantom: PhantomData,
         }
     }
 
+    /// Records in `connectivity` whether IoT Hub could be reached, after every
+    /// request to it.
+    pub fn with_connectivity(mut self, connectivity: Connectivity) -> Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    fn track<F>(&self, request: F) -> impl Future<Item = F::Item, Error = F::Error>
+    where
+        F: Future,
+        F::Error: Fail,
+    {
+        let connectivity = self.connectivity.clone();
+        request.then(move |result| {
+            match result {
+                Err(ref err) if is_disconnected(err) => {
+                    connectivity.disconnected(Fail::find_root_cause(err).to_string())
+                }
+                _ => connectivity.connected(),
+            }
+            result
+        })
+    }
+
     fn get_key_pair(&self, id: &str, generation_id: &str) -> Result<(K::Key, K::Key), Error> {
         self.state
             .key_store
@@ -199,6 +242,14 @@ where
     }
 }
 
+/// Whether a request failed because IoT Hub couldn't be reached, rather than
+/// because of the answer it gave.
+fn is_disconnected<E: Fail>(err: &E) -> bool {
+    Fail::find_root_cause(err)
+        .downcast_ref::<hyper::Error>()
+        .map_or(false, hyper::Error::is_connect)
+}
+
 fn build_key_name(key_name: &str, generation_id: &str) -> String {
     format!("{}{}", key_name, generation_id)
 }
@@ -213,6 +264,7 @@ where
     fn clone(&self) -> Self {
         HubIdentityManager {
             state: self.state.clone(),
+            connectivity: self.connectivity.clone(),
             phantom: PhantomData,
         }
     }
@@ -232,8 +284,27 @@ where
     type ListFuture = Box<dyn Future<Item = Vec<Self::Identity>, Error = Self::Error> + Send>;
     type GetFuture = Box<dyn Future<Item = Option<Self::Identity>, Error = Self::Error> + Send>;
     type DeleteFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type GetTwinFuture = Box<dyn Future<Item = Option<Value>, Error = Self::Error> + Send>;
+    type GetTwinDesiredFuture = Box<dyn Future<Item = Value, Error = Self::Error> + Send>;
 
     fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture {
+        if let Some(thumbprint) = id.x509_thumbprint() {
+            let module_id = id.module_id().to_string();
+            return Box::new(
+                self.track(self.state.client.create_module(
+                    module_id.clone(),
+                    Some(x509_auth(thumbprint)),
+                    id.managed_by(),
+                ))
+                .map_err(|err| {
+                    Error::from(err.context(ErrorKind::IdentityOperation(
+                        IdentityOperation::CreateIdentity(module_id),
+                    )))
+                })
+                .map(HubIdentity::new),
+            );
+        }
+
         // This code first creates a module in the hub with the auth type
         // set as "None" in order to have a generation identifier generated for
         // the module by the hub. Once we have a generation ID we use it to
@@ -242,62 +313,78 @@ where
         let idman = self.clone();
         let module_id = id.module_id().to_string();
         Box::new(
-            self.state
-                .client
-                .create_module(
-                    module_id.clone(),
-                    Some(AuthMechanism::default().with_type(HubAuthType::None)),
-                    id.managed_by(),
-                )
-                .then(|module| {
-                    let module = module.with_context(|_| {
-                        ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
-                            module_id.clone(),
-                        ))
-                    })?;
-
-                    if let (Some(module_id2), Some(generation_id)) =
-                        (module.module_id(), module.generation_id())
-                    {
-                        idman.get_key_pair(module_id2, generation_id).map(
-                            |(primary_key, secondary_key)| {
-                                (primary_key, secondary_key, idman, module_id)
-                            },
-                        )
-                    } else {
-                        Err(Error::from(ErrorKind::CreateIdentityWithReason(
+            self.track(self.state.client.create_module(
+                module_id.clone(),
+                Some(AuthMechanism::default().with_type(HubAuthType::None)),
+                id.managed_by(),
+            ))
+            .then(|module| {
+                let module = module.with_context(|_| {
+                    ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
+                        module_id.clone(),
+                    ))
+                })?;
+
+                if let (Some(module_id2), Some(generation_id)) =
+                    (module.module_id(), module.generation_id())
+                {
+                    idman.get_key_pair(module_id2, generation_id).map(
+                        |(primary_key, secondary_key)| {
+                            (primary_key, secondary_key, idman, module_id)
+                        },
+                    )
+                } else {
+                    Err(Error::from(ErrorKind::CreateIdentityWithReason(
+                        module_id,
+                        IdentityOperationReason::InvalidHubResponse,
+                    )))
+                }
+            })
+            .and_then(move |(primary_key, secondary_key, idman, module_id)| {
+                let auth = AuthMechanism::default()
+                    .with_type(HubAuthType::Sas)
+                    .with_symmetric_key(
+                        SymmetricKey::default()
+                            .with_primary_key(base64::encode(primary_key.as_ref()))
+                            .with_secondary_key(base64::encode(secondary_key.as_ref())),
+                    );
+
+                idman
+                    .track(idman.state.client.update_module(
+                        id.module_id().to_string(),
+                        Some(auth),
+                        id.managed_by(),
+                    ))
+                    .map_err(|err| {
+                        Error::from(err.context(ErrorKind::CreateIdentityWithReason(
                             module_id,
                             IdentityOperationReason::InvalidHubResponse,
                         )))
-                    }
-                })
-                .and_then(move |(primary_key, secondary_key, idman, module_id)| {
-                    let auth = AuthMechanism::default()
-                        .with_type(HubAuthType::Sas)
-                        .with_symmetric_key(
-                            SymmetricKey::default()
-                                .with_primary_key(base64::encode(primary_key.as_ref()))
-                                .with_secondary_key(base64::encode(secondary_key.as_ref())),
-                        );
-
-                    idman
-                        .state
-                        .client
-                        .update_module(id.module_id().to_string(), Some(auth), id.managed_by())
-                        .map_err(|err| {
-                            Error::from(err.context(ErrorKind::CreateIdentityWithReason(
-                                module_id,
-                                IdentityOperationReason::InvalidHubResponse,
-                            )))
-                        })
-                        .map(HubIdentity::new)
-                }),
+                    })
+                    .map(HubIdentity::new)
+            }),
         )
     }
 
     fn update(&mut self, id: IdentitySpec) -> Self::UpdateFuture {
         let module_id = id.module_id().to_string();
 
+        if let Some(thumbprint) = id.x509_thumbprint() {
+            return Box::new(
+                self.track(self.state.client.update_module(
+                    module_id.clone(),
+                    Some(x509_auth(thumbprint)),
+                    id.managed_by(),
+                ))
+                .map_err(|err| {
+                    Error::from(err.context(ErrorKind::IdentityOperation(
+                        IdentityOperation::UpdateIdentity(module_id),
+                    )))
+                })
+                .map(HubIdentity::new),
+            );
+        }
+
         let result = if let Some(generation_id) = id.generation_id() {
             match self.get_key_pair(&module_id, generation_id) {
                 Ok((primary_key, secondary_key)) => {
@@ -310,15 +397,17 @@ where
                         );
 
                     Either::A(
-                        self.state
-                            .client
-                            .update_module(module_id.clone(), Some(auth), id.managed_by())
-                            .map_err(|err| {
-                                Error::from(err.context(ErrorKind::IdentityOperation(
-                                    IdentityOperation::UpdateIdentity(module_id),
-                                )))
-                            })
-                            .map(HubIdentity::new),
+                        self.track(self.state.client.update_module(
+                            module_id.clone(),
+                            Some(auth),
+                            id.managed_by(),
+                        ))
+                        .map_err(|err| {
+                            Error::from(err.context(ErrorKind::IdentityOperation(
+                                IdentityOperation::UpdateIdentity(module_id),
+                            )))
+                        })
+                        .map(HubIdentity::new),
                     )
                 }
 
@@ -338,9 +427,7 @@ where
 
     fn list(&self) -> Self::ListFuture {
         Box::new(
-            self.state
-                .client
-                .list_modules()
+            self.track(self.state.client.list_modules())
                 .map_err(|err| {
                     Error::from(err.context(ErrorKind::IdentityOperation(
                         IdentityOperation::ListIdentities,
@@ -353,32 +440,75 @@ where
     fn get(&self, id: IdentitySpec) -> Self::GetFuture {
         let module_id = id.module_id().to_string();
 
-        Box::new(self.state.client.get_module_by_id(module_id.clone()).then(
-            |module| match module {
-                Ok(module) => Ok(Some(HubIdentity::new(module))),
-                Err(err) => {
-                    if let HubErrorKind::GetModuleWithReason(_, HubReason::ModuleNotFound) =
-                        err.kind()
-                    {
-                        Ok(None)
-                    } else {
-                        Err(Error::from(err.context(ErrorKind::IdentityOperation(
-                            IdentityOperation::GetIdentity(module_id),
-                        ))))
+        Box::new(
+            self.track(self.state.client.get_module_by_id(module_id.clone()))
+                .then(|module| match module {
+                    Ok(module) => Ok(Some(HubIdentity::new(module))),
+                    Err(err) => {
+                        if let HubErrorKind::GetModuleWithReason(_, HubReason::ModuleNotFound) =
+                            err.kind()
+                        {
+                            Ok(None)
+                        } else {
+                            Err(Error::from(err.context(ErrorKind::IdentityOperation(
+                                IdentityOperation::GetIdentity(module_id),
+                            ))))
+                        }
                     }
-                }
-            },
-        ))
+                }),
+        )
     }
 
     fn delete(&mut self, id: IdentitySpec) -> Self::DeleteFuture {
         let module_id = id.module_id().to_string();
 
-        Box::new(self.state.client.delete_module(&module_id).map_err(|err| {
-            Error::from(err.context(ErrorKind::IdentityOperation(
-                IdentityOperation::DeleteIdentity(module_id),
-            )))
-        }))
+        Box::new(
+            self.track(self.state.client.delete_module(&module_id))
+                .map_err(|err| {
+                    Error::from(err.context(ErrorKind::IdentityOperation(
+                        IdentityOperation::DeleteIdentity(module_id),
+                    )))
+                }),
+        )
+    }
+
+    fn get_twin(&self, id: IdentitySpec) -> Self::GetTwinFuture {
+        let module_id = id.module_id().to_string();
+
+        Box::new(
+            self.track(self.state.client.get_module_twin(module_id.clone()))
+                .then(|twin| match twin {
+                    Ok(twin) => serde_json::to_value(twin.properties())
+                        .map(Some)
+                        .with_context(|_| {
+                            ErrorKind::IdentityOperation(IdentityOperation::GetTwin(module_id))
+                        })
+                        .map_err(Error::from),
+                    Err(err) => {
+                        if let HubErrorKind::GetModuleTwinWithReason(_, HubReason::ModuleNotFound) =
+                            err.kind()
+                        {
+                            Ok(None)
+                        } else {
+                            Err(Error::from(err.context(ErrorKind::IdentityOperation(
+                                IdentityOperation::GetTwin(module_id),
+                            ))))
+                        }
+                    }
+                }),
+        )
+    }
+
+    fn get_twin_desired(&self) -> Self::GetTwinDesiredFuture {
+        Box::new(
+            self.track(self.state.client.get_twin())
+                .map(|twin| twin.properties().desired().clone())
+                .map_err(|err| {
+                    Error::from(err.context(ErrorKind::IdentityOperation(
+                        IdentityOperation::GetTwinDesired,
+                    )))
+                }),
+        )
     }
 }
 
@@ -597,6 +727,73 @@ mod tests {
         assert_eq!(hub_identity.hub_module(), &expected_module_result);
     }
 
+    #[test]
+    fn create_with_x509_thumbprint_succeeds() {
+        let thumbprint = "0123456789abcdef0123456789abcdef01234567";
+
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+        let expected_module = Module::default()
+            .with_device_id("d1".to_string())
+            .with_module_id("m1".to_string())
+            .with_authentication(
+                AuthMechanism::default()
+                    .with_type(HubAuthType::X509)
+                    .with_x509_thumbprint(
+                        HubX509Thumbprint::default()
+                            .with_primary_thumbprint(thumbprint.to_string()),
+                    ),
+            );
+        let expected_module_result = expected_module.clone().with_generation_id("g1".to_string());
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(req.method(), &Method::PUT);
+            assert_eq!(req.uri().path(), "/devices/d1/modules/m1");
+            // the module is created with its final authentication, there is
+            // no follow-up update with derived keys
+            assert!(req.headers().get(hyper::header::IF_MATCH).is_none());
+
+            let expected_module = expected_module.clone();
+            req.into_body()
+                .concat2()
+                .and_then(|req_body| Ok(serde_json::from_slice::<Module>(&req_body).unwrap()))
+                .and_then(move |module| {
+                    assert_eq!(module, expected_module);
+
+                    let mut response = Response::new(
+                        serde_json::to_string(&module.with_generation_id("g1".to_string()))
+                            .unwrap()
+                            .into(),
+                    );
+                    response
+                        .headers_mut()
+                        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                    Ok(response)
+                })
+        };
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(handler, Some(token_source), api_version, host_name).unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+
+        let mut identity_manager = HubIdentityManager::new(MemoryKeyStore::new(), device_client);
+        let task = identity_manager.create(
+            IdentitySpec::new("m1".to_string())
+                .with_x509_thumbprint(X509Thumbprint::new(thumbprint.to_string()).unwrap()),
+        );
+
+        let hub_identity = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+
+        assert_eq!(hub_identity.hub_module(), &expected_module_result);
+        assert_eq!(AuthType::X509, hub_identity.auth_type());
+    }
+
     #[test]
     fn list_succeeds() {
         let m1pkey = "m1pkey";
@@ -861,6 +1058,76 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn answered_requests_are_recorded_as_connected() {
+        let key_store = MemoryKeyStore::new();
+
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_req: Request<Body>| {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            Ok(response)
+        };
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(handler, Some(token_source), api_version, host_name).unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let connectivity = Connectivity::new();
+
+        let mut identity_manager = HubIdentityManager::new(key_store, device_client)
+            .with_connectivity(connectivity.clone());
+        let task = identity_manager.delete(IdentitySpec::new("m1".to_string()));
+
+        let result = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task);
+
+        assert!(result.is_err());
+        assert_eq!(
+            edgelet_core::ConnectivityState::Connected,
+            connectivity.status().status()
+        );
+    }
+
+    #[test]
+    fn throttled_requests_have_their_status_code() {
+        let key_store = MemoryKeyStore::new();
+
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_req: Request<Body>| {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+            Ok(response)
+        };
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(handler, Some(token_source), api_version, host_name).unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+
+        let identity_manager = HubIdentityManager::new(key_store, device_client);
+        let task = identity_manager.list();
+
+        let err = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap_err();
+
+        assert_eq!(
+            edgelet_core::IdentityManagerErrorReason::ServiceError(429),
+            (&err).into()
+        );
+    }
+
     #[test]
     fn token_source_success() {
         // arrange
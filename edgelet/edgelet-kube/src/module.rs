@@ -1,5 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::HashMap;
+
 use edgelet_core::{Module, ModuleRuntimeState, ModuleStatus};
 use edgelet_docker::DockerConfig;
 use edgelet_utils::ensure_not_empty_with_context;
@@ -40,6 +42,14 @@ impl Module for KubeModule {
         &self.config
     }
 
+    fn labels(&self) -> HashMap<String, String> {
+        self.config
+            .create_options()
+            .labels()
+            .cloned()
+            .unwrap_or_else(HashMap::new)
+    }
+
     fn runtime_state(&self) -> Self::RuntimeStateFuture {
         // Working on assumption that if Kube module exists (present in cluster), status is successful
         // TODO: get Pod "last known good state" when we implement a more robust recovery in iotedged
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -8,9 +9,13 @@ use crate::constants::*;
 use crate::convert::{auth_to_image_pull_secret, pod_to_module, spec_to_deployment};
 use crate::error::{Error, ErrorKind, Result};
 use crate::module::KubeModule;
+use bytes::Bytes;
+use chrono::Utc;
 use edgelet_core::{
-    LogOptions, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec, ModuleTop,
-    RuntimeOperation, SystemInfo,
+    Capabilities, CircuitState, ConnectivityStatus, Error as CoreError, LogOptions, ModuleDetails,
+    ModuleEvent, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec, ModuleStats,
+    ModuleStatus, ModuleTop, PortBinding, PruneImagesResult, PullProgress, ResourceLimits,
+    RuntimeOperation, StartResult, SystemInfo, SystemResources, SystemSnapshot, VolumeMount,
 };
 use edgelet_docker::DockerConfig;
 use edgelet_utils::{ensure_not_empty_with_context, sanitize_dns_label};
@@ -285,6 +290,7 @@ where
     type ModuleRegistry = Self;
     type Chunk = Chunk;
     type Logs = Logs;
+    type RegistryAuth = ();
 
     type CreateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type GetFuture =
@@ -299,17 +305,59 @@ where
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type SystemInfoFuture = Box<dyn Future<Item = SystemInfo, Error = Self::Error> + Send>;
+    type SystemResourcesFuture =
+        Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type TopFuture = Box<dyn Future<Item = ModuleTop, Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
+    type InspectFuture = Box<dyn Future<Item = ModuleDetails, Error = Self::Error> + Send>;
+    type EnvFuture = Box<dyn Future<Item = HashMap<String, String>, Error = Self::Error> + Send>;
+    type UpdateEnvFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UpdateResourcesFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UpsertFuture = Box<dyn Future<Item = (Self::Module, bool), Error = Self::Error> + Send>;
+    type PingFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type EventStream = Box<dyn Stream<Item = ModuleEvent, Error = Self::Error> + Send>;
+    type GetConfigFuture =
+        Box<dyn Future<Item = Option<ModuleSpec<Self::Config>>, Error = Self::Error> + Send>;
+    type ExecFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type PullImageStream = Box<dyn Stream<Item = PullProgress, Error = Self::Error> + Send>;
+    type PullImageFuture =
+        Box<dyn Future<Item = Self::PullImageStream, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PruneImagesResult, Error = Self::Error> + Send>;
+    type SignalFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type PortsFuture = Box<dyn Future<Item = Vec<PortBinding>, Error = Self::Error> + Send>;
+    type WaitUntilStoppedFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ConnectivityStatusFuture =
+        Box<dyn Future<Item = ConnectivityStatus, Error = Self::Error> + Send>;
+    type CheckpointFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RestoreFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ListCheckpointsFuture = Box<dyn Future<Item = Vec<String>, Error = Self::Error> + Send>;
+    type VolumesFuture = Box<dyn Future<Item = Vec<VolumeMount>, Error = Self::Error> + Send>;
+    type SnapshotFuture = Box<dyn Future<Item = SystemSnapshot, Error = Self::Error> + Send>;
+    type StartAllFuture = Box<dyn Future<Item = Vec<StartResult>, Error = CoreError> + Send>;
+    type SetBandwidthFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type PauseFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type UnpauseFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type CopyToContainerFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn init(&self) -> Self::InitFuture {
         Box::new(future::ok(()))
     }
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        Box::new(self.upsert(module).map(|_| ()))
+    }
+
+    fn upsert(&self, module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
         let f = spec_to_deployment(self, &module)
+            .and_then(|(name, new_deployment)| {
+                let kube_module =
+                    KubeModule::new(module.name().to_string(), module.config().clone())?;
+                Ok((name, new_deployment, kube_module))
+            })
             .map_err(Error::from)
-            .map(|(name, new_deployment)| {
+            .map(|(name, new_deployment, kube_module)| {
                 let client_copy = self.client.clone();
                 let namespace_copy = self.namespace().to_owned();
                 self.client
@@ -332,7 +380,7 @@ where
                         {
                             // found deployment, if the deployment found doesn't match, replace it.
                             if current_deployment == new_deployment {
-                                Either::A(Either::A(future::ok(())))
+                                Either::A(Either::A(future::ok(false)))
                             } else {
                                 let fut = client_copy
                                     .lock()
@@ -344,7 +392,7 @@ where
                                         &new_deployment,
                                     )
                                     .map_err(Error::from)
-                                    .map(|_| ());
+                                    .map(|_| false);
                                 Either::A(Either::B(fut))
                             }
                         } else {
@@ -355,10 +403,11 @@ where
                                 .borrow_mut()
                                 .create_deployment(namespace_copy.as_str(), &new_deployment)
                                 .map_err(Error::from)
-                                .map(|_| ());
+                                .map(|_| true);
                             Either::B(fut)
                         }
                     })
+                    .map(move |created| (kube_module, created))
             })
             .into_future()
             .flatten();
@@ -393,6 +442,11 @@ where
         )))
     }
 
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(SystemResources::default()))
+    }
+
     fn list(&self) -> Self::ListFuture {
         let result = self
             .client
@@ -433,9 +487,172 @@ where
         Box::new(future::ok(()))
     }
 
-    fn top(&self, id: &str) -> Self::TopFuture {
+    fn top(&self, id: &str, _ps_args: Option<&str>) -> Self::TopFuture {
         Box::new(future::ok(ModuleTop::new(id.to_string(), Vec::new())))
     }
+
+    fn stats(&self, _id: &str) -> Self::StatsFuture {
+        Box::new(future::ok(ModuleStats::default()))
+    }
+
+    fn inspect(&self, id: &str) -> Self::InspectFuture {
+        // As with KubeModule::runtime_state, a module that exists in the cluster is assumed to be running
+        Box::new(future::ok(ModuleDetails::new(
+            id.to_string(),
+            ModuleStatus::Running,
+        )))
+    }
+
+    fn env(&self, _id: &str) -> Self::EnvFuture {
+        Box::new(future::ok(HashMap::new()))
+    }
+
+    fn update_env(&self, _id: &str, _env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+        Box::new(future::ok(()))
+    }
+
+    fn update_resources(&self, _id: &str, _limits: ResourceLimits) -> Self::UpdateResourcesFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn ping(&self) -> Self::PingFuture {
+        // Listing the deployments we manage is the cheapest request that
+        // proves the API server is reachable with our credentials.
+        Box::new(
+            self.client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_deployments(self.namespace(), None, Some(&self.device_hub_selector))
+                .map_err(Error::from)
+                .map(|_| ()),
+        )
+    }
+
+    fn events(&self) -> Self::EventStream {
+        // TODO: Implement this.
+        Box::new(stream::empty())
+    }
+
+    fn get_config(&self, _id: &str) -> Self::GetConfigFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(None))
+    }
+
+    fn exec(&self, _id: &str, _cmd: &[String], _tty: bool) -> Self::ExecFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(Logs("".to_string(), Body::empty())))
+    }
+
+    fn pull_image(&self, _image: &str, _auth: Option<Self::RegistryAuth>) -> Self::PullImageFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(
+            Box::new(stream::empty()) as Self::PullImageStream
+        ))
+    }
+
+    fn prune_images(&self, _dangling_only: bool) -> Self::PruneImagesFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(PruneImagesResult::default()))
+    }
+
+    fn signal(&self, _id: &str, _signal: u32) -> Self::SignalFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn get_ports(&self, _id: &str) -> Self::PortsFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(Vec::new()))
+    }
+
+    fn wait_until_stopped(&self, _id: &str, _timeout: Duration) -> Self::WaitUntilStoppedFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(ConnectivityStatus::default()))
+    }
+
+    fn checkpoint(&self, _id: &str, _checkpoint_id: &str) -> Self::CheckpointFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn restore(&self, _id: &str, _checkpoint_id: &str) -> Self::RestoreFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn list_checkpoints(&self, _id: &str) -> Self::ListCheckpointsFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(Vec::new()))
+    }
+
+    fn get_volumes(&self, _id: &str) -> Self::VolumesFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(Vec::new()))
+    }
+
+    fn snapshot(&self) -> Self::SnapshotFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(SystemSnapshot::new(
+            Utc::now(),
+            SystemInfo::new("linux".to_string(), "x86_64".to_string()),
+            SystemResources::default(),
+        )))
+    }
+
+    fn start_all(&self, _specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+        unimplemented!()
+    }
+
+    fn set_bandwidth(
+        &self,
+        _id: &str,
+        _ingress_kbps: u32,
+        _egress_kbps: u32,
+    ) -> Self::SetBandwidthFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn pause(&self, _id: &str) -> Self::PauseFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn unpause(&self, _id: &str) -> Self::UnpauseFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn copy_to_container(
+        &self,
+        _id: &str,
+        _dest_path: &str,
+        _content: Bytes,
+    ) -> Self::CopyToContainerFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn rename(&self, _old_name: &str, _new_name: &str) -> Self::RenameFuture {
+        // TODO: Implement this.
+        Box::new(future::ok(()))
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        CircuitState::Closed
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // TODO: Report the features once they are implemented.
+        Capabilities::default()
+    }
 }
 
 #[derive(Debug)]
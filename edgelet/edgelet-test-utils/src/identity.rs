@@ -1,8 +1,11 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::HashMap;
+
 use failure::Fail;
 use futures::future::{self, FutureResult, IntoFuture};
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 
 use edgelet_core::{AuthType, Identity, IdentityManager, IdentitySpec};
 
@@ -71,6 +74,8 @@ pub struct TestIdentityManager {
     fail_list: bool,
     fail_get: bool,
     fail_create: bool,
+    twins: HashMap<String, Value>,
+    device_twin: Value,
 }
 
 impl TestIdentityManager {
@@ -81,6 +86,8 @@ impl TestIdentityManager {
             fail_list: false,
             fail_get: true,
             fail_create: false,
+            twins: HashMap::new(),
+            device_twin: Value::Null,
         }
     }
 
@@ -98,6 +105,17 @@ impl TestIdentityManager {
         self.fail_create = fail_create;
         self
     }
+
+    pub fn with_twin(mut self, module_id: &str, properties: Value) -> Self {
+        self.twins.insert(module_id.to_string(), properties);
+        self
+    }
+
+    /// Sets the desired properties of the device twin.
+    pub fn with_device_twin(mut self, desired: Value) -> Self {
+        self.device_twin = desired;
+        self
+    }
 }
 
 impl IdentityManager for TestIdentityManager {
@@ -108,6 +126,8 @@ impl IdentityManager for TestIdentityManager {
     type ListFuture = FutureResult<Vec<Self::Identity>, Self::Error>;
     type GetFuture = FutureResult<Option<Self::Identity>, Self::Error>;
     type DeleteFuture = FutureResult<(), Self::Error>;
+    type GetTwinFuture = FutureResult<Option<Value>, Self::Error>;
+    type GetTwinDesiredFuture = FutureResult<Value, Self::Error>;
 
     fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture {
         if self.fail_create {
@@ -118,7 +138,7 @@ impl IdentityManager for TestIdentityManager {
                 id.module_id(),
                 id.managed_by().unwrap_or(&"".to_string()),
                 &format!("{}", self.gen_id_sentinel),
-                AuthType::Sas,
+                auth_type(&id),
             );
             self.identities.push(id.clone());
 
@@ -140,8 +160,8 @@ impl IdentityManager for TestIdentityManager {
             // verify if genid matches
             assert_eq!(&module.generation_id, generation_id);
 
-            // set the sas type
-            module.auth_type = AuthType::Sas;
+            // set the sas or x509 type
+            module.auth_type = auth_type(&id);
 
             // Update managed by
             module.managed_by = id.managed_by().unwrap_or(&"".to_string()).to_string();
@@ -174,7 +194,7 @@ impl IdentityManager for TestIdentityManager {
                 .find(|m| m.module_id() == id.module_id())
             {
                 Some(module) => future::ok(Some(module.clone())),
-                None => future::err(Error::ModuleNotFound),
+                None => future::ok(None),
             }
         }
     }
@@ -189,4 +209,28 @@ impl IdentityManager for TestIdentityManager {
             .ok_or(Error::ModuleNotFound)
             .into_future()
     }
+
+    fn get_twin(&self, id: IdentitySpec) -> Self::GetTwinFuture {
+        if self.fail_get {
+            future::err(Error::General)
+        } else {
+            future::ok(self.twins.get(id.module_id()).cloned())
+        }
+    }
+
+    fn get_twin_desired(&self) -> Self::GetTwinDesiredFuture {
+        if self.fail_get {
+            future::err(Error::General)
+        } else {
+            future::ok(self.device_twin.clone())
+        }
+    }
+}
+
+fn auth_type(id: &IdentitySpec) -> AuthType {
+    if id.x509_thumbprint().is_some() {
+        AuthType::X509
+    } else {
+        AuthType::Sas
+    }
 }
@@ -1,8 +1,14 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
 use edgelet_core::*;
 use failure::Fail;
 use futures::future::{self, FutureResult};
@@ -66,6 +72,10 @@ impl TestConfig {
 pub struct TestModule<E> {
     name: String,
     config: TestConfig,
+    labels: HashMap<String, String>,
+    env: HashMap<String, String>,
+    events: Vec<ModuleEvent>,
+    ip_address: Option<IpAddr>,
     state: Result<ModuleRuntimeState, E>,
 }
 
@@ -74,9 +84,35 @@ impl<E: Fail> TestModule<E> {
         TestModule {
             name,
             config,
+            labels: HashMap::new(),
+            env: HashMap::new(),
+            events: vec![],
+            ip_address: None,
             state,
         }
     }
+
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Events returned by `TestRuntime::events`.
+    pub fn with_events(mut self, events: Vec<ModuleEvent>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Address returned by `TestRuntime::inspect`.
+    pub fn with_ip_address(mut self, ip_address: IpAddr) -> Self {
+        self.ip_address = Some(ip_address);
+        self
+    }
 }
 
 impl<E: Clone + Fail> Module for TestModule<E> {
@@ -96,6 +132,10 @@ impl<E: Clone + Fail> Module for TestModule<E> {
         &self.config
     }
 
+    fn labels(&self) -> HashMap<String, String> {
+        self.labels.clone()
+    }
+
     fn runtime_state(&self) -> Self::RuntimeStateFuture {
         self.state.clone().into_future()
     }
@@ -105,6 +145,15 @@ impl<E: Clone + Fail> Module for TestModule<E> {
 pub struct TestRuntime<E> {
     module: Result<TestModule<E>, E>,
     registry: TestRegistry<E>,
+    create_error: Option<E>,
+    start_error: Option<E>,
+    connectivity: ConnectivityStatus,
+    capabilities: Capabilities,
+    signals: Arc<Mutex<Vec<(String, u32)>>>,
+    bandwidths: Arc<Mutex<Vec<(String, u32, u32)>>>,
+    copies: Arc<Mutex<Vec<(String, String, Bytes)>>>,
+    renames: Arc<Mutex<Vec<(String, String)>>>,
+    started: Arc<AtomicBool>,
 }
 
 impl<E> TestRuntime<E>
@@ -115,8 +164,71 @@ where
         TestRuntime {
             registry: TestRegistry::new(module.as_ref().err().cloned()),
             module,
+            create_error: None,
+            start_error: None,
+            connectivity: ConnectivityStatus::new(ConnectivityState::Connected),
+            capabilities: Capabilities::default(),
+            signals: Arc::new(Mutex::new(vec![])),
+            bandwidths: Arc::new(Mutex::new(vec![])),
+            copies: Arc::new(Mutex::new(vec![])),
+            renames: Arc::new(Mutex::new(vec![])),
+            started: Arc::new(AtomicBool::new(true)),
         }
     }
+
+    /// Leaves the module out of `list` and `list_with_details` until `start`
+    /// is called, like a module that is started while the list is watched.
+    /// Clones share whether it was started.
+    pub fn with_module_listed_after_start(mut self) -> Self {
+        self.started = Arc::new(AtomicBool::new(false));
+        self
+    }
+
+    /// Makes `create` fail with `err` while everything else succeeds.
+    pub fn with_create_error(mut self, err: E) -> Self {
+        self.create_error = Some(err);
+        self
+    }
+
+    /// Makes `start` fail with `err` while everything else succeeds.
+    pub fn with_start_error(mut self, err: E) -> Self {
+        self.start_error = Some(err);
+        self
+    }
+
+    pub fn with_connectivity(mut self, connectivity: ConnectivityStatus) -> Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The modules and signals `signal` was called with, in order. Clones
+    /// share them.
+    pub fn signals(&self) -> Vec<(String, u32)> {
+        self.signals.lock().unwrap().clone()
+    }
+
+    /// The modules and rates `set_bandwidth` was called with, in order.
+    /// Clones share them.
+    pub fn bandwidths(&self) -> Vec<(String, u32, u32)> {
+        self.bandwidths.lock().unwrap().clone()
+    }
+
+    /// The modules, paths and contents `copy_to_container` was called with,
+    /// in order. Clones share them.
+    pub fn copies(&self) -> Vec<(String, String, Bytes)> {
+        self.copies.lock().unwrap().clone()
+    }
+
+    /// The old and new names of every module renamed, in order. Clones share
+    /// the renames.
+    pub fn renames(&self) -> Vec<(String, String)> {
+        self.renames.lock().unwrap().clone()
+    }
 }
 
 pub struct EmptyBody<E> {
@@ -159,6 +271,7 @@ impl<E: Clone + Fail> ModuleRuntime for TestRuntime<E> {
     type ModuleRegistry = TestRegistry<E>;
     type Chunk = String;
     type Logs = EmptyBody<Self::Error>;
+    type RegistryAuth = ();
 
     type CreateFuture = FutureResult<(), Self::Error>;
     type GetFuture = FutureResult<(Self::Module, ModuleRuntimeState), Self::Error>;
@@ -172,44 +285,86 @@ impl<E: Clone + Fail> ModuleRuntime for TestRuntime<E> {
     type StartFuture = FutureResult<(), Self::Error>;
     type StopFuture = FutureResult<(), Self::Error>;
     type SystemInfoFuture = FutureResult<SystemInfo, Self::Error>;
+    type SystemResourcesFuture = FutureResult<SystemResources, Self::Error>;
     type RemoveAllFuture = FutureResult<(), Self::Error>;
     type TopFuture = FutureResult<ModuleTop, Self::Error>;
+    type StatsFuture = FutureResult<ModuleStats, Self::Error>;
+    type InspectFuture = FutureResult<ModuleDetails, Self::Error>;
+    type EnvFuture = FutureResult<HashMap<String, String>, Self::Error>;
+    type UpdateEnvFuture = FutureResult<(), Self::Error>;
+    type UpdateResourcesFuture = FutureResult<(), Self::Error>;
+    type UpsertFuture = FutureResult<(Self::Module, bool), Self::Error>;
+    type PingFuture = FutureResult<(), Self::Error>;
+    type EventStream = Box<dyn Stream<Item = ModuleEvent, Error = Self::Error> + Send>;
+    type GetConfigFuture = FutureResult<Option<ModuleSpec<Self::Config>>, Self::Error>;
+    type ExecFuture = FutureResult<Self::Logs, Self::Error>;
+    type PullImageStream = Box<dyn Stream<Item = PullProgress, Error = Self::Error> + Send>;
+    type PullImageFuture = FutureResult<Self::PullImageStream, Self::Error>;
+    type PruneImagesFuture = FutureResult<PruneImagesResult, Self::Error>;
+    type SignalFuture = FutureResult<(), Self::Error>;
+    type PortsFuture = FutureResult<Vec<PortBinding>, Self::Error>;
+    type WaitUntilStoppedFuture = FutureResult<(), Self::Error>;
+    type ConnectivityStatusFuture = FutureResult<ConnectivityStatus, Self::Error>;
+    type CheckpointFuture = FutureResult<(), Self::Error>;
+    type RestoreFuture = FutureResult<(), Self::Error>;
+    type ListCheckpointsFuture = FutureResult<Vec<String>, Self::Error>;
+    type VolumesFuture = FutureResult<Vec<VolumeMount>, Self::Error>;
+    type SnapshotFuture = FutureResult<SystemSnapshot, Self::Error>;
+    type StartAllFuture = Box<dyn Future<Item = Vec<StartResult>, Error = Error> + Send>;
+    type SetBandwidthFuture = FutureResult<(), Self::Error>;
+    type PauseFuture = FutureResult<(), Self::Error>;
+    type UnpauseFuture = FutureResult<(), Self::Error>;
+    type CopyToContainerFuture = FutureResult<(), Self::Error>;
+    type RenameFuture = FutureResult<(), Self::Error>;
 
     fn system_info(&self) -> Self::SystemInfoFuture {
         match self.module {
-            Ok(_) => future::ok(SystemInfo::new(
-                "os_type_sample".to_string(),
-                "architecture_sample".to_string(),
-            )),
+            Ok(_) => future::ok(
+                SystemInfo::new(
+                    "os_type_sample".to_string(),
+                    "architecture_sample".to_string(),
+                )
+                .with_cgroup_version(CgroupVersion::V2),
+            ),
             Err(ref e) => future::err(e.clone()),
         }
     }
 
-    fn init(&self) -> Self::InitFuture {
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
         match self.module {
-            Ok(_) => future::ok(()),
+            Ok(_) => future::ok(SystemResources::new(4, 1024, 4096, 2048)),
             Err(ref e) => future::err(e.clone()),
         }
     }
 
-    fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+    fn init(&self) -> Self::InitFuture {
         match self.module {
             Ok(_) => future::ok(()),
             Err(ref e) => future::err(e.clone()),
         }
     }
 
+    fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        match (&self.module, &self.create_error) {
+            (Ok(_), Some(e)) | (Err(e), _) => future::err(e.clone()),
+            (Ok(_), None) => future::ok(()),
+        }
+    }
+
     fn get(&self, _id: &str) -> Self::GetFuture {
         match self.module {
-            Ok(ref m) => future::ok((m.clone(), ModuleRuntimeState::default())),
+            Ok(ref m) => future::ok((m.clone(), m.state.clone().unwrap_or_default())),
             Err(ref e) => future::err(e.clone()),
         }
     }
 
     fn start(&self, _id: &str) -> Self::StartFuture {
-        match self.module {
-            Ok(_) => future::ok(()),
-            Err(ref e) => future::err(e.clone()),
+        match (&self.module, &self.start_error) {
+            (Ok(_), Some(e)) | (Err(e), _) => future::err(e.clone()),
+            (Ok(_), None) => {
+                self.started.store(true, Ordering::SeqCst);
+                future::ok(())
+            }
         }
     }
 
@@ -236,6 +391,7 @@ impl<E: Clone + Fail> ModuleRuntime for TestRuntime<E> {
 
     fn list(&self) -> Self::ListFuture {
         match self.module {
+            Ok(_) if !self.started.load(Ordering::SeqCst) => future::ok(vec![]),
             Ok(ref m) => future::ok(vec![m.clone()]),
             Err(ref e) => future::err(e.clone()),
         }
@@ -243,6 +399,7 @@ impl<E: Clone + Fail> ModuleRuntime for TestRuntime<E> {
 
     fn list_with_details(&self) -> Self::ListWithDetailsStream {
         match self.module {
+            Ok(_) if !self.started.load(Ordering::SeqCst) => Box::new(stream::empty()),
             Ok(ref m) => {
                 let m = m.clone();
                 Box::new(m.runtime_state().map(|rs| (m, rs)).into_stream())
@@ -266,16 +423,335 @@ impl<E: Clone + Fail> ModuleRuntime for TestRuntime<E> {
         future::ok(())
     }
 
-    fn top(&self, id: &str) -> Self::TopFuture {
+    fn top(&self, id: &str, _ps_args: Option<&str>) -> Self::TopFuture {
+        match self.module {
+            Ok(ref m) => {
+                assert_eq!(id, m.name());
+                match m.state {
+                    Ok(ref s) => future::ok(
+                        ModuleTop::new(m.name.clone(), vec![s.pid()]).with_processes(
+                            vec!["PID".to_string(), "USER".to_string(), "CMD".to_string()],
+                            vec![vec![
+                                "1".to_string(),
+                                "root".to_string(),
+                                "/app".to_string(),
+                            ]],
+                        ),
+                    ),
+                    Err(ref e) => future::err(e.clone()),
+                }
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        match self.module {
+            Ok(ref m) => {
+                assert_eq!(id, m.name());
+                match m.state {
+                    Ok(_) => future::ok(ModuleStats::default()),
+                    Err(ref e) => future::err(e.clone()),
+                }
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn inspect(&self, id: &str) -> Self::InspectFuture {
         match self.module {
             Ok(ref m) => {
                 assert_eq!(id, m.name());
                 match m.state {
-                    Ok(ref s) => future::ok(ModuleTop::new(m.name.clone(), vec![s.pid()])),
+                    Ok(ref state) => future::ok(
+                        ModuleDetails::new(m.name().to_string(), *state.status())
+                            .with_exit_code(state.exit_code().map(|code| code as i32))
+                            .with_started_at(state.started_at().cloned())
+                            .with_finished_at(state.finished_at().cloned())
+                            .with_ip_address(m.ip_address),
+                    ),
                     Err(ref e) => future::err(e.clone()),
                 }
             }
             Err(ref e) => future::err(e.clone()),
         }
     }
+
+    fn env(&self, id: &str) -> Self::EnvFuture {
+        match self.module {
+            Ok(ref m) => {
+                assert_eq!(id, m.name());
+                future::ok(m.env.clone())
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn update_env(&self, id: &str, _env: HashMap<String, String>) -> Self::UpdateEnvFuture {
+        match self.module {
+            Ok(ref m) => {
+                assert_eq!(id, m.name());
+                future::ok(())
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn update_resources(&self, id: &str, _limits: ResourceLimits) -> Self::UpdateResourcesFuture {
+        match self.module {
+            Ok(ref m) => {
+                assert_eq!(id, m.name());
+                future::ok(())
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn upsert(&self, module: ModuleSpec<Self::Config>) -> Self::UpsertFuture {
+        match self.module {
+            Ok(ref m) if m.name() == module.name() => future::ok((m.clone(), false)),
+            Ok(_) => future::ok((
+                TestModule::new(
+                    module.name().to_string(),
+                    module.config().clone(),
+                    Ok(ModuleRuntimeState::default()),
+                ),
+                true,
+            )),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn ping(&self) -> Self::PingFuture {
+        match self.module {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn events(&self) -> Self::EventStream {
+        match self.module {
+            Ok(ref m) => Box::new(stream::iter_ok(m.events.clone())),
+            Err(ref e) => Box::new(stream::once(Err(e.clone()))),
+        }
+    }
+
+    fn get_config(&self, id: &str) -> Self::GetConfigFuture {
+        match self.module {
+            Ok(ref m) if m.name == id => future::ok(Some(
+                ModuleSpec::new(
+                    m.name.clone(),
+                    "test".to_string(),
+                    m.config.clone(),
+                    m.env.clone(),
+                )
+                .expect("invalid test module spec")
+                .with_labels(m.labels.clone()),
+            )),
+            Ok(_) => future::ok(None),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn exec(&self, _id: &str, _cmd: &[String], _tty: bool) -> Self::ExecFuture {
+        match self.module {
+            Ok(_) => future::ok(EmptyBody::new()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn pull_image(&self, image: &str, _auth: Option<Self::RegistryAuth>) -> Self::PullImageFuture {
+        match self.module {
+            Ok(_) => future::ok(Box::new(stream::iter_ok(vec![
+                PullProgress::new("Pulling fs layer".to_string())
+                    .with_id(Some("layer1".to_string())),
+                PullProgress::new(format!("Downloaded newer image for {}", image)),
+            ]))),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn prune_images(&self, dangling_only: bool) -> Self::PruneImagesFuture {
+        let mut removed = vec!["sha256:dangling".to_string()];
+        if !dangling_only {
+            removed.push("sha256:unused".to_string());
+        }
+        match self.module {
+            Ok(_) => future::ok(PruneImagesResult::new(1024 * removed.len() as u64, removed)),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn signal(&self, id: &str, signal: u32) -> Self::SignalFuture {
+        match self.module {
+            Ok(_) => {
+                self.signals.lock().unwrap().push((id.to_string(), signal));
+                future::ok(())
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn get_ports(&self, _id: &str) -> Self::PortsFuture {
+        match self.module {
+            Ok(ref m) => match m.state {
+                Ok(ref state) if *state.status() == ModuleStatus::Running => {
+                    future::ok(vec![PortBinding::new(8080, "tcp".to_string())
+                        .with_host_ip(Some("0.0.0.0".to_string()))
+                        .with_host_port(Some(8080))])
+                }
+                Ok(_) => future::ok(Vec::new()),
+                Err(ref e) => future::err(e.clone()),
+            },
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn wait_until_stopped(&self, _id: &str, _timeout: Duration) -> Self::WaitUntilStoppedFuture {
+        match self.module {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn connectivity_status(&self) -> Self::ConnectivityStatusFuture {
+        match self.module {
+            Ok(_) => future::ok(self.connectivity.clone()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn checkpoint(&self, _id: &str, _checkpoint_id: &str) -> Self::CheckpointFuture {
+        match self.module {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn restore(&self, _id: &str, _checkpoint_id: &str) -> Self::RestoreFuture {
+        match self.module {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn list_checkpoints(&self, _id: &str) -> Self::ListCheckpointsFuture {
+        match self.module {
+            Ok(_) => future::ok(vec!["checkpoint1".to_string()]),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn get_volumes(&self, _id: &str) -> Self::VolumesFuture {
+        match self.module {
+            Ok(_) => future::ok(vec![VolumeMount::new(
+                "/data".to_string(),
+                "/var/lib/edge/data".to_string(),
+                "rw".to_string(),
+            )]),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn snapshot(&self) -> Self::SnapshotFuture {
+        match self.module {
+            Ok(ref m) => match m.state {
+                Ok(ref state) => future::ok(
+                    SystemSnapshot::new(
+                        Utc.ymd(2019, 1, 30).and_hms(0, 0, 0),
+                        SystemInfo::new(
+                            "os_type_sample".to_string(),
+                            "architecture_sample".to_string(),
+                        ),
+                        SystemResources::new(4, 1024, 4096, 2048),
+                    )
+                    .with_disk_usage(vec![DiskUsage::new("/".into(), 4096, 2048)])
+                    .with_network_interfaces(vec![NetworkInterface::new(
+                        "eth0".to_string(),
+                        vec!["10.0.0.2".parse().unwrap()],
+                    )])
+                    .with_modules(vec![ModuleSnapshot::new(m.name.clone(), state.clone())])
+                    .with_events(m.events.clone()),
+                ),
+                Err(ref e) => future::err(e.clone()),
+            },
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn start_all(&self, specs: Vec<ModuleSpec<Self::Config>>) -> Self::StartAllFuture {
+        Box::new(start_in_dependency_order(self.clone(), specs))
+    }
+
+    fn set_bandwidth(
+        &self,
+        id: &str,
+        ingress_kbps: u32,
+        egress_kbps: u32,
+    ) -> Self::SetBandwidthFuture {
+        match self.module {
+            Ok(_) => {
+                self.bandwidths
+                    .lock()
+                    .unwrap()
+                    .push((id.to_string(), ingress_kbps, egress_kbps));
+                future::ok(())
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn pause(&self, _id: &str) -> Self::PauseFuture {
+        match self.module {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn unpause(&self, _id: &str) -> Self::UnpauseFuture {
+        match self.module {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn copy_to_container(
+        &self,
+        id: &str,
+        dest_path: &str,
+        content: Bytes,
+    ) -> Self::CopyToContainerFuture {
+        match self.module {
+            Ok(_) => {
+                self.copies
+                    .lock()
+                    .unwrap()
+                    .push((id.to_string(), dest_path.to_string(), content));
+                future::ok(())
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> Self::RenameFuture {
+        match self.module {
+            Ok(_) => {
+                self.renames
+                    .lock()
+                    .unwrap()
+                    .push((old_name.to_string(), new_name.to_string()));
+                future::ok(())
+            }
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        CircuitState::Closed
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
 }
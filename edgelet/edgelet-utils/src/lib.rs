@@ -12,7 +12,7 @@ mod ser_de;
 use std::collections::HashMap;
 
 pub use crate::error::{Error, ErrorKind};
-pub use crate::logging::log_failure;
+pub use crate::logging::{correlation_id, log_failure, with_correlation_id};
 pub use crate::macros::ensure_not_empty_with_context;
 pub use crate::ser_de::{serde_clone, serialize_ordered, string_or_struct};
 
@@ -1,11 +1,52 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::cell::RefCell;
+
 use failure::Fail;
 use log::{log, Level};
 
+thread_local! {
+    static CORRELATION_ID: RefCell<Option<String>> = RefCell::new(None);
+}
+
 pub fn log_failure(level: Level, fail: &dyn Fail) {
     log!(level, "{}", fail);
     for cause in fail.iter_causes() {
         log!(level, "\tcaused by: {}", cause);
     }
 }
+
+/// Runs `f` with `id` as the correlation ID of the records logged on this
+/// thread, restoring the previous ID afterwards.
+///
+/// A request is handled by a future that may be polled on any thread, so the
+/// ID should be set around each poll of the future rather than once.
+pub fn with_correlation_id<T>(id: &str, f: impl FnOnce() -> T) -> T {
+    let previous = CORRELATION_ID.with(|current| current.replace(Some(id.to_string())));
+    let result = f();
+    CORRELATION_ID.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// The correlation ID of the request being handled on this thread, if any.
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.with(|current| current.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn correlation_id_is_scoped() {
+        assert_eq!(None, correlation_id());
+        with_correlation_id("outer", || {
+            assert_eq!(Some("outer".to_string()), correlation_id());
+            with_correlation_id("inner", || {
+                assert_eq!(Some("inner".to_string()), correlation_id());
+            });
+            assert_eq!(Some("outer".to_string()), correlation_id());
+        });
+        assert_eq!(None, correlation_id());
+    }
+}
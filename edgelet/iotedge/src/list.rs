@@ -95,6 +95,7 @@ fn humanize_state(state: &ModuleRuntimeState) -> String {
                 })
             })
             .unwrap_or_else(|| "Failed".to_string()),
+        ModuleStatus::Paused => "Paused".to_string(),
         ModuleStatus::Running => state.started_at().map_or_else(
             || "Up".to_string(),
             |time| {
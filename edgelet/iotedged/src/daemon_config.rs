@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use failure::Fail;
+use log::info;
+use serde_json::{self, Value};
+
+use edgelet_config::{save_settings, settings_sources, Settings};
+use edgelet_core::{
+    DaemonConfigSource, DaemonConfigStore, Error as CoreError, ErrorKind as CoreErrorKind,
+    ProxySettings, SharedGcPolicy, RELOADABLE_SETTINGS,
+};
+use edgelet_docker::DockerConfig;
+use edgelet_http_mgmt::RegistryValidator;
+
+use crate::reload_settings;
+
+/// Keeps the settings of iotedged in its config file, from which they are
+/// loaded again after every change, like on `SIGHUP`. The image garbage
+/// collection policy and the proxies are the ones in effect, since they can
+/// also change through their own endpoints. Without a config file the
+/// settings only last until iotedged restarts.
+#[derive(Clone)]
+pub struct ConfigFileDaemonConfigStore {
+    config_file: Option<PathBuf>,
+    config: Arc<Mutex<Value>>,
+    registries: RegistryValidator,
+    gc_policy: SharedGcPolicy,
+}
+
+impl ConfigFileDaemonConfigStore {
+    pub fn new(
+        settings: &Settings<DockerConfig>,
+        registries: RegistryValidator,
+        gc_policy: SharedGcPolicy,
+    ) -> Result<Self, serde_json::Error> {
+        Ok(ConfigFileDaemonConfigStore {
+            config_file: settings.config_file().map(Path::to_path_buf),
+            config: Arc::new(Mutex::new(serde_json::to_value(settings)?)),
+            registries,
+            gc_policy,
+        })
+    }
+}
+
+impl DaemonConfigStore for ConfigFileDaemonConfigStore {
+    fn get(&self) -> Result<Value, CoreError> {
+        let mut config = match self.config_file {
+            Some(ref config_file) => Settings::<DockerConfig>::new(Some(config_file))
+                .and_then(|settings| Ok(serde_json::to_value(&settings)?))
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::DaemonConfig)))?,
+            None => self.config.lock().unwrap().clone(),
+        };
+        if let Value::Object(ref mut config) = config {
+            let gc_policy = serde_json::to_value(self.gc_policy.get())
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::DaemonConfig)))?;
+            let proxy = serde_json::to_value(ProxySettings::from_env())
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::DaemonConfig)))?;
+            config.insert("image_gc".to_string(), gc_policy);
+            config.insert("proxy".to_string(), proxy);
+        }
+        Ok(config)
+    }
+
+    fn sources(&self) -> Result<BTreeMap<String, DaemonConfigSource>, CoreError> {
+        let mut sources = settings_sources(self.config_file.as_ref().map(PathBuf::as_path))
+            .map_err(|err| CoreError::from(err.context(CoreErrorKind::DaemonConfig)))?;
+        // The proxies are those of `HTTPS_PROXY` and the like when the config
+        // file has none.
+        if !sources.contains_key("proxy") && !ProxySettings::from_env().is_default() {
+            sources.insert("proxy".to_string(), DaemonConfigSource::Env);
+        }
+        Ok(sources)
+    }
+
+    fn set(&self, config: Value) -> Result<(), CoreError> {
+        let settings = serde_json::from_value::<Settings<DockerConfig>>(config.clone())
+            .map_err(|err| CoreError::from(CoreErrorKind::InvalidDaemonConfig(err.to_string())))?;
+
+        // Only the sections that changed are saved, so that the policy and
+        // proxies set through their own endpoints aren't saved with them.
+        let current = self.get()?;
+        let sections: Vec<_> = RELOADABLE_SETTINGS
+            .iter()
+            .filter(|name| config.get(**name) != current.get(**name))
+            .map(|name| (*name, config.get(*name).cloned()))
+            .collect();
+
+        match self.config_file {
+            Some(ref config_file) => {
+                save_settings(config_file, &sections)
+                    .and_then(|()| reload_settings(Some(config_file), &self.registries))
+                    .map_err(|err| CoreError::from(err.context(CoreErrorKind::DaemonConfig)))?;
+            }
+            None => {
+                info!("No config file to save the settings to");
+                self.registries.set(settings.allowed_registries().clone());
+                *self.config.lock().unwrap() = config;
+            }
+        }
+
+        // As with `ConfigFileProxyStore`, the settings loaded again leave the
+        // environment alone when they have no proxies.
+        settings.proxy().apply_to_env();
+        self.gc_policy.set(settings.image_gc().clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    use edgelet_core::{AllowedRegistries, GcPolicy};
+
+    use super::*;
+
+    #[cfg(unix)]
+    static SETTINGS: &str = "../edgelet-config/test/linux/sample_settings.yaml";
+
+    #[cfg(unix)]
+    #[test]
+    fn saved_settings_keep_the_mode_of_the_config_file() {
+        let tmp_dir = TempDir::new("daemon_config").unwrap();
+        let path = tmp_dir.path().join("config.yaml");
+        fs::copy(SETTINGS, &path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o400)).unwrap();
+        let settings = Settings::<DockerConfig>::new(Some(path.as_path())).unwrap();
+        let store = ConfigFileDaemonConfigStore::new(
+            &settings,
+            RegistryValidator::new(AllowedRegistries::default()),
+            SharedGcPolicy::default(),
+        )
+        .unwrap();
+
+        let mut config = store.get().unwrap();
+        config["image_gc"] = json!({ "min_free_bytes": 1024, "max_image_age_secs": 3600 });
+        store.set(config).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(0o400, metadata.permissions().mode() & 0o777);
+        let settings = Settings::<DockerConfig>::new(Some(path.as_path())).unwrap();
+        assert_eq!(&GcPolicy::new(1024, 3600), settings.image_gc());
+    }
+}
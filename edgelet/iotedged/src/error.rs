@@ -19,12 +19,24 @@ pub enum ErrorKind {
     #[fail(display = "The symmetric key string could not be activated")]
     ActivateSymmetricKey,
 
+    #[fail(display = "The image garbage collector encountered an error")]
+    ImageGarbageCollector,
+
     #[fail(display = "The daemon could not start up successfully: {}", _0)]
     Initialize(InitializeErrorReason),
 
     #[fail(display = "The management service encountered an error")]
     ManagementService,
 
+    #[fail(display = "The module watchdog encountered an error")]
+    ModuleWatchdog,
+
+    #[fail(display = "The settings could not be reloaded")]
+    ReloadSettings,
+
+    #[fail(display = "The daemon state could not be cleared")]
+    ResetState,
+
     #[fail(display = "The symmetric key string is malformed")]
     SymmetricKeyMalformed,
 
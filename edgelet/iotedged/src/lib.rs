@@ -10,8 +10,10 @@
 )]
 
 pub mod app;
+mod daemon_config;
 mod error;
 pub mod logging;
+mod proxy;
 pub mod signal;
 pub mod workload;
 
@@ -25,40 +27,51 @@ use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::fs::{DirBuilder, File};
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 
 use failure::{Fail, ResultExt};
 use futures::future::Either;
 use futures::sync::oneshot::{self, Receiver};
-use futures::{future, Future};
+use futures::{future, Future, Stream};
 use hyper::server::conn::Http;
 use hyper::Uri;
-use log::{debug, info};
+use log::{debug, info, Level};
 use sha2::{Digest, Sha256};
 use url::Url;
 
 use docker::models::HostConfig;
 use dps::DPS_API_VERSION;
-use edgelet_config::{Dps, Manual, Provisioning, Settings, DEFAULT_CONNECTION_STRING};
+use edgelet_config::{
+    Dps, ListenTls, LoadSettingsError, Manual, Provisioning, Settings, DEFAULT_CONNECTION_STRING,
+};
 use edgelet_core::crypto::{
     Activate, CreateCertificate, Decrypt, DerivedKeyStore, Encrypt, GetTrustBundle, KeyIdentity,
     KeyStore, MasterEncryptionKey, MemoryKey, MemoryKeyStore, Sign, IOTEDGED_CA_ALIAS,
 };
-use edgelet_core::watchdog::Watchdog;
+use edgelet_core::watchdog::{ModuleWatchdog, Watchdog};
 use edgelet_core::{
-    CertificateIssuer, CertificateProperties, CertificateType, ModuleRuntime, ModuleSpec, UrlExt,
-    WorkloadConfig, UNIX_SCHEME,
+    CertificateInventory, CertificateIssuer, CertificateManager, CertificateManagerErrorReason,
+    CertificateProperties, CertificateType, FileBackedConfigStore, ModuleRuntime, ModuleSpec,
+    ModuleTrustStore, RetryingIdentityManager, SharedGcPolicy, StateReset, UrlExt, WorkloadConfig,
+    UNIX_SCHEME,
 };
-use edgelet_docker::{DockerConfig, DockerModuleRuntime};
+use edgelet_docker::{CircuitBreaker, DockerConfig, DockerModuleRuntime, ImageGarbageCollector};
 use edgelet_hsm::tpm::{TpmKey, TpmKeyStore};
 use edgelet_hsm::Crypto;
 use edgelet_http::client::{Client as HttpClient, ClientImpl};
 use edgelet_http::logging::LoggingService;
-use edgelet_http::{HyperExt, MaybeProxyClient, API_VERSION};
-use edgelet_http_mgmt::ManagementService;
+use edgelet_http::{
+    listen_url, HmacAuthMiddleware, HyperExt, MaybeProxyClient, TlsConfig, API_VERSION,
+    MANAGEMENT_URI_ENV_KEY, WORKLOAD_URI_ENV_KEY,
+};
+use edgelet_http_mgmt::{
+    ManagementService, ManagementStores, ModuleHistory, ModuleNameValidator, RegistryValidator,
+};
 use edgelet_http_workload::WorkloadService;
 use edgelet_iothub::{HubIdentityManager, SasTokenSource};
+use edgelet_utils::log_failure;
 use hsm::tpm::Tpm;
 use hsm::ManageTpmKeys;
 use iothubservice::DeviceClient;
@@ -67,6 +80,8 @@ use provisioning::provisioning::{
     Provision, ProvisioningResult,
 };
 
+use crate::daemon_config::ConfigFileDaemonConfigStore;
+use crate::proxy::ConfigFileProxyStore;
 use crate::workload::WorkloadData;
 
 pub use self::error::{Error, ErrorKind, InitializeErrorReason};
@@ -140,6 +155,21 @@ const EDGE_SETTINGS_STATE_FILENAME: &str = "settings_state";
 /// This is the name of the cache subdirectory for settings state
 const EDGE_SETTINGS_SUBDIR: &str = "cache";
 
+/// This is the name of the file the history of module updates is kept in
+const EDGE_MODULE_HISTORY_FILENAME: &str = "module_history.json";
+
+/// This is the name of the file module configs are kept in. It is in the cache
+/// subdirectory so that it is wiped together with all modules on reconfigure.
+const EDGE_MODULE_CONFIGS_FILENAME: &str = "module_configs.json";
+
+/// This is the name of the subdirectory the trust stores of modules are kept
+/// in. Unlike the cache subdirectory, it is kept on reconfigure.
+const EDGE_TRUST_STORE_SUBDIR: &str = "truststore";
+
+/// The name of the device key, derived from the root key in the HSM, that
+/// signs the HMAC tokens of the management API.
+const MANAGEMENT_HMAC_KEY_NAME: &str = "management-api-hmac";
+
 /// These are the properties of the workload CA certificate
 const IOTEDGED_VALIDITY: u64 = 7_776_000; // 90 days
 const IOTEDGED_COMMONNAME: &str = "iotedged workload ca";
@@ -162,9 +192,6 @@ impl Main {
     {
         let Main { settings } = self;
 
-        let mut tokio_runtime = tokio::runtime::Runtime::new()
-            .context(ErrorKind::Initialize(InitializeErrorReason::Tokio))?;
-
         if let Provisioning::Manual(ref manual) = settings.provisioning() {
             if manual.device_connection_string() == DEFAULT_CONNECTION_STRING {
                 return Err(Error::from(ErrorKind::Initialize(
@@ -173,138 +200,184 @@ impl Main {
             }
         }
 
-        let hyper_client = MaybeProxyClient::new(get_proxy_uri(None)?)
-            .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
-
-        info!(
-            "Using runtime network id {}",
-            settings.moby_runtime().network()
-        );
-        let runtime = DockerModuleRuntime::new(settings.moby_runtime().uri())
-            .context(ErrorKind::Initialize(InitializeErrorReason::ModuleRuntime))?
-            .with_network_id(settings.moby_runtime().network().to_string());
-
-        init_docker_runtime(&runtime, &mut tokio_runtime)?;
-
-        info!(
-            "Configuring {} as the home directory.",
-            settings.homedir().display()
-        );
-        env::set_var(HOMEDIR_KEY, &settings.homedir());
-
-        info!("Configuring certificates...");
-        let certificates = &settings.certificates();
-        match certificates.as_ref() {
-            None => info!(
-                "Transparent gateway certificates not found, operating in quick start mode..."
-            ),
-            Some(&c) => {
-                let path = c.device_ca_cert().as_os_str();
-                info!("Configuring the Device CA certificate using {:?}.", path);
-                env::set_var(DEVICE_CA_CERT_KEY, path);
-
-                let path = c.device_ca_pk().as_os_str();
-                info!("Configuring the Device private key using {:?}.", path);
-                env::set_var(DEVICE_CA_PK_KEY, path);
-
-                let path = c.trusted_ca_certs().as_os_str();
-                info!("Configuring the trusted CA certificates using {:?}.", path);
-                env::set_var(TRUSTED_CA_CERTS_KEY, path);
-            }
-        };
-        info!("Finished configuring certificates.");
-
-        info!("Initializing hsm...");
-        let crypto = Crypto::new().context(ErrorKind::Initialize(InitializeErrorReason::Hsm))?;
-        info!("Finished initializing hsm.");
-
-        // Detect if the settings were changed and if the device needs to be reconfigured
-        let cache_subdir_path = Path::new(&settings.homedir()).join(EDGE_SETTINGS_SUBDIR);
-        check_settings_state(
-            cache_subdir_path.clone(),
-            EDGE_SETTINGS_STATE_FILENAME,
-            &settings,
-            &runtime,
-            &crypto,
-            &mut tokio_runtime,
-        )?;
+        // The proxies of the config file win over the ones iotedged was
+        // started with.
+        if !settings.proxy().is_default() {
+            settings.proxy().apply_to_env();
+        }
 
-        info!("Provisioning edge device...");
-        match settings.provisioning() {
-            Provisioning::Manual(manual) => {
-                let (key_store, provisioning_result, root_key) =
-                    manual_provision(&manual, &mut tokio_runtime)?;
-                info!("Finished provisioning edge device.");
-                let cfg = WorkloadData::new(
-                    provisioning_result.hub_name().to_string(),
-                    provisioning_result.device_id().to_string(),
-                    IOTEDGE_ID_CERT_MAX_DURATION_SECS,
-                    IOTEDGE_SERVER_CERT_MAX_DURATION_SECS,
-                );
-                start_api(
-                    &settings,
-                    hyper_client,
-                    &runtime,
-                    &key_store,
-                    cfg,
-                    root_key,
-                    shutdown_signal,
-                    &crypto,
-                    tokio_runtime,
-                )?;
-            }
-            Provisioning::Dps(dps) => {
-                let dps_path = cache_subdir_path.join(EDGE_PROVISIONING_BACKUP_FILENAME);
-
-                macro_rules! start_edgelet {
-                    ($key_store:ident, $provisioning_result:ident, $root_key:ident, $runtime:ident) => {{
-                        info!("Finished provisioning edge device.");
-
-                        let cfg = WorkloadData::new(
-                            $provisioning_result.hub_name().to_string(),
-                            $provisioning_result.device_id().to_string(),
-                            IOTEDGE_ID_CERT_MAX_DURATION_SECS,
-                            IOTEDGE_SERVER_CERT_MAX_DURATION_SECS,
-                        );
-                        start_api(
-                            &settings,
-                            hyper_client,
-                            &$runtime,
-                            &$key_store,
-                            cfg,
-                            $root_key,
-                            shutdown_signal,
-                            &crypto,
-                            tokio_runtime,
-                        )?;
-                    }};
+        // Every start of the daemon after a reset of its state waits for the
+        // same shutdown signal.
+        let shutdown_signal = shutdown_signal.shared();
+
+        loop {
+            let shutdown_signal = shutdown_signal.clone().then(|_| Ok::<_, ()>(()));
+            let mut tokio_runtime = tokio::runtime::Runtime::new()
+                .context(ErrorKind::Initialize(InitializeErrorReason::Tokio))?;
+
+            let hyper_client = MaybeProxyClient::new(get_proxy_uri(None)?)
+                .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
+
+            info!(
+                "Using runtime network id {}",
+                settings.moby_runtime().network()
+            );
+            let runtime = DockerModuleRuntime::new(settings.moby_runtime().uri())
+                .context(ErrorKind::Initialize(InitializeErrorReason::ModuleRuntime))?
+                .with_network_id(settings.moby_runtime().network().to_string())
+                .with_trust_store(ModuleTrustStore::new(
+                    Path::new(settings.homedir()).join(EDGE_TRUST_STORE_SUBDIR),
+                ));
+
+            init_docker_runtime(&runtime, &mut tokio_runtime)?;
+
+            info!(
+                "Configuring {} as the home directory.",
+                settings.homedir().display()
+            );
+            env::set_var(HOMEDIR_KEY, &settings.homedir());
+
+            info!("Configuring certificates...");
+            let certificates = &settings.certificates();
+            match certificates.as_ref() {
+                None => info!(
+                    "Transparent gateway certificates not found, operating in quick start mode..."
+                ),
+                Some(&c) => {
+                    let path = c.device_ca_cert().as_os_str();
+                    info!("Configuring the Device CA certificate using {:?}.", path);
+                    env::set_var(DEVICE_CA_CERT_KEY, path);
+
+                    let path = c.device_ca_pk().as_os_str();
+                    info!("Configuring the Device private key using {:?}.", path);
+                    env::set_var(DEVICE_CA_PK_KEY, path);
+
+                    let path = c.trusted_ca_certs().as_os_str();
+                    info!("Configuring the trusted CA certificates using {:?}.", path);
+                    env::set_var(TRUSTED_CA_CERTS_KEY, path);
                 }
+            };
+            info!("Finished configuring certificates.");
+
+            info!("Initializing hsm...");
+            // The HSM can't list its certificates, so they are recorded as
+            // they are created for `GET /system/certificates`.
+            let crypto = CertificateInventory::new(
+                Crypto::new().context(ErrorKind::Initialize(InitializeErrorReason::Hsm))?,
+            );
+            info!("Finished initializing hsm.");
+
+            // Detect if the settings were changed and if the device needs to be reconfigured
+            let cache_subdir_path = Path::new(&settings.homedir()).join(EDGE_SETTINGS_SUBDIR);
+            check_settings_state(
+                cache_subdir_path.clone(),
+                EDGE_SETTINGS_STATE_FILENAME,
+                &settings,
+                &runtime,
+                &crypto,
+                &mut tokio_runtime,
+            )?;
 
-                if let Some(key) = dps.symmetric_key() {
-                    info!("Staring provisioning edge device via symmetric key...");
-                    let (key_store, provisioning_result, root_key, runtime) =
-                        dps_symmetric_key_provision(
-                            &dps,
-                            hyper_client.clone(),
-                            dps_path,
-                            runtime,
-                            &mut tokio_runtime,
-                            key,
-                        )?;
-                    start_edgelet!(key_store, provisioning_result, root_key, runtime);
-                } else {
-                    info!("Staring provisioning edge device via TPM...");
-                    let (key_store, provisioning_result, root_key, runtime) = dps_tpm_provision(
-                        &dps,
-                        hyper_client.clone(),
-                        dps_path,
-                        runtime,
-                        &mut tokio_runtime,
-                    )?;
-                    start_edgelet!(key_store, provisioning_result, root_key, runtime);
+            info!("Provisioning edge device...");
+            let status = match settings.provisioning() {
+                Provisioning::Manual(manual) => {
+                    let (key_store, provisioning_result, root_key) =
+                        manual_provision(&manual, &mut tokio_runtime)?;
+                    info!("Finished provisioning edge device.");
+                    let cfg = WorkloadData::new(
+                        provisioning_result.hub_name().to_string(),
+                        provisioning_result.device_id().to_string(),
+                        IOTEDGE_ID_CERT_MAX_DURATION_SECS,
+                        IOTEDGE_SERVER_CERT_MAX_DURATION_SECS,
+                    );
+                    start_api(
+                        &settings,
+                        hyper_client,
+                        &runtime,
+                        &key_store,
+                        cfg,
+                        root_key,
+                        shutdown_signal,
+                        &crypto,
+                        tokio_runtime,
+                    )?
+                }
+                Provisioning::Dps(dps) => {
+                    let dps_path = cache_subdir_path.join(EDGE_PROVISIONING_BACKUP_FILENAME);
+
+                    macro_rules! start_edgelet {
+                        ($key_store:ident, $provisioning_result:ident, $root_key:ident, $runtime:ident) => {{
+                            info!("Finished provisioning edge device.");
+
+                            let cfg = WorkloadData::new(
+                                $provisioning_result.hub_name().to_string(),
+                                $provisioning_result.device_id().to_string(),
+                                IOTEDGE_ID_CERT_MAX_DURATION_SECS,
+                                IOTEDGE_SERVER_CERT_MAX_DURATION_SECS,
+                            );
+                            start_api(
+                                &settings,
+                                hyper_client,
+                                &$runtime,
+                                &$key_store,
+                                cfg,
+                                $root_key,
+                                shutdown_signal,
+                                &crypto,
+                                tokio_runtime,
+                            )?
+                        }};
+                    }
+
+                    if let Some(key) = dps.symmetric_key() {
+                        info!("Staring provisioning edge device via symmetric key...");
+                        let (key_store, provisioning_result, root_key, runtime) =
+                            dps_symmetric_key_provision(
+                                &dps,
+                                hyper_client.clone(),
+                                dps_path,
+                                runtime,
+                                &mut tokio_runtime,
+                                key,
+                            )?;
+                        start_edgelet!(key_store, provisioning_result, root_key, runtime)
+                    } else {
+                        info!("Staring provisioning edge device via TPM...");
+                        let (key_store, provisioning_result, root_key, runtime) =
+                            dps_tpm_provision(
+                                &dps,
+                                hyper_client.clone(),
+                                dps_path,
+                                runtime,
+                                &mut tokio_runtime,
+                            )?;
+                        start_edgelet!(key_store, provisioning_result, root_key, runtime)
+                    }
+                }
+            };
+
+            match status {
+                StartApiReturnStatus::Shutdown => break,
+                StartApiReturnStatus::Restart => {
+                    // Without the cached settings state the next start
+                    // reconfigures the device from scratch, which removes
+                    // all modules and replaces the master encryption key and
+                    // the workload CA, and without the provisioning backup
+                    // it provisions the device again.
+                    info!(
+                        "Clearing the daemon state in {}...",
+                        cache_subdir_path.display()
+                    );
+                    if let Err(err) = fs::remove_dir_all(&cache_subdir_path) {
+                        if err.kind() != io::ErrorKind::NotFound {
+                            let err = Error::from(err.context(ErrorKind::ResetState));
+                            log_failure(Level::Warn, &err);
+                        }
+                    }
+                    info!("Restarting the daemon...");
                 }
             }
-        };
+        }
 
         info!("Shutdown complete.");
         Ok(())
@@ -481,12 +554,13 @@ fn start_api<HC, K, F, C, W>(
     shutdown_signal: F,
     crypto: &C,
     mut tokio_runtime: tokio::runtime::Runtime,
-) -> Result<(), Error>
+) -> Result<StartApiReturnStatus, Error>
 where
     F: Future<Item = (), Error = ()> + Send + 'static,
     HC: ClientImpl + 'static,
     K: Sign + Clone + Send + Sync + 'static,
     C: CreateCertificate
+        + CertificateManager
         + Decrypt
         + Encrypt
         + GetTrustBundle
@@ -495,6 +569,7 @@ where
         + Send
         + Sync
         + 'static,
+    for<'r> &'r C::Error: Into<CertificateManagerErrorReason>,
     W: WorkloadConfig + Clone + Send + Sync + 'static,
 {
     let hub_name = workload_config.iot_hub_name().to_string();
@@ -510,12 +585,38 @@ where
     .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
     let device_client = DeviceClient::new(http_client, device_id.clone())
         .context(ErrorKind::Initialize(InitializeErrorReason::DeviceClient))?;
-    let id_man = HubIdentityManager::new(key_store.clone(), device_client);
+    let id_man = HubIdentityManager::new(key_store.clone(), device_client)
+        .with_connectivity(runtime.connectivity().clone());
 
     let (mgmt_tx, mgmt_rx) = oneshot::channel();
     let (work_tx, work_rx) = oneshot::channel();
 
-    let mgmt = start_management(&settings, &runtime, &id_man, mgmt_rx);
+    // Shared by the image garbage collector and the management API, so that
+    // `PUT /system/gcpolicy` takes effect without a restart.
+    let gc_policy = SharedGcPolicy::new(settings.image_gc().clone());
+
+    // Shared by the management API and the reload of the settings on
+    // `SIGHUP`, so that changes to `allowed_registries` take effect without
+    // a restart.
+    let registries = RegistryValidator::new(settings.allowed_registries().clone());
+
+    // `DELETE /system/state` stops the services like a shutdown does, after
+    // which `Main::run_until` clears the state and starts them again.
+    let (state_reset, reset_requested) = StateReset::channel();
+
+    // Management API calls fail fast while the Docker daemon is unreachable,
+    // rather than each holding its request until the socket times out.
+    let mgmt = start_management(
+        &settings,
+        &CircuitBreaker::new(runtime.clone()),
+        &id_man,
+        key_store,
+        &gc_policy,
+        &state_reset,
+        crypto,
+        &registries,
+        mgmt_rx,
+    );
 
     let workload = start_workload(
         &settings,
@@ -529,6 +630,15 @@ where
     let (runt_tx, runt_rx) = oneshot::channel();
     let edge_rt = start_runtime(&runtime, &id_man, &hub_name, &device_id, &settings, runt_rx)?;
 
+    let (watchdog_tx, watchdog_rx) = oneshot::channel();
+    tokio_runtime.spawn(start_module_watchdog(&runtime, &settings, watchdog_rx));
+
+    let (image_gc_tx, image_gc_rx) = oneshot::channel();
+    tokio_runtime.spawn(start_image_gc(&runtime, gc_policy, image_gc_rx));
+
+    let (reload_tx, reload_rx) = oneshot::channel();
+    tokio_runtime.spawn(start_settings_reload(&settings, registries, reload_rx));
+
     // Wait for the watchdog to finish, and then send signal to the workload and management services.
     // This way the edgeAgent can finish shutting down all modules.
     let edge_rt_with_cleanup = edge_rt.map_err(Into::into).and_then(|_| {
@@ -537,11 +647,30 @@ where
         future::ok(())
     });
 
-    let shutdown = shutdown_signal.map(move |_| {
-        debug!("shutdown signaled");
-        // Signal the watchdog to shutdown
-        runt_tx.send(()).unwrap_or(());
+    // The management service drops its end of the reset channel when it
+    // stops, which mustn't be taken for a request.
+    let reset_requested = reset_requested.then(|result| match result {
+        Ok(()) => Either::A(future::ok::<_, ()>(StartApiReturnStatus::Restart)),
+        Err(_) => Either::B(future::empty()),
     });
+    let (status_tx, status_rx) = oneshot::channel();
+    let shutdown = shutdown_signal
+        .map(|()| StartApiReturnStatus::Shutdown)
+        .select(reset_requested)
+        .then(move |result| {
+            let status = match result {
+                Ok((status, _)) => status,
+                Err(_) => StartApiReturnStatus::Shutdown,
+            };
+            debug!("shutdown signaled");
+            // Signal the watchdogs to shutdown
+            runt_tx.send(()).unwrap_or(());
+            watchdog_tx.send(()).unwrap_or(());
+            image_gc_tx.send(()).unwrap_or(());
+            reload_tx.send(()).unwrap_or(());
+            status_tx.send(status).unwrap_or(());
+            Ok::<_, ()>(())
+        });
     tokio_runtime.spawn(shutdown);
 
     let services = mgmt
@@ -552,7 +681,18 @@ where
         });
     tokio_runtime.block_on(services)?;
 
-    Ok(())
+    let status = tokio_runtime
+        .block_on(status_rx)
+        .unwrap_or(StartApiReturnStatus::Shutdown);
+    Ok(status)
+}
+
+/// Why `start_api` returned.
+enum StartApiReturnStatus {
+    /// The daemon state was reset and the daemon has to start again.
+    Restart,
+    /// The daemon was asked to shut down.
+    Shutdown,
 }
 
 fn init_docker_runtime(
@@ -784,6 +924,79 @@ where
     Ok(runtime_future)
 }
 
+// Restarts modules that exit according to their restart policy. An error
+// only stops the restarts, the rest of the daemon keeps running.
+fn start_module_watchdog(
+    runtime: &DockerModuleRuntime,
+    settings: &Settings<DockerConfig>,
+    shutdown: Receiver<()>,
+) -> impl Future<Item = (), Error = ()> {
+    ModuleWatchdog::new(runtime.clone(), settings.watchdog().clone())
+        .run_until(shutdown.map_err(|_| ()))
+        .map_err(|err| {
+            let err = Error::from(err.context(ErrorKind::ModuleWatchdog));
+            log_failure(Level::Warn, &err);
+        })
+}
+
+// Removes unused images while the disk runs low on space. Like the module
+// watchdog, an error only stops the garbage collection.
+fn start_image_gc(
+    runtime: &DockerModuleRuntime,
+    policy: SharedGcPolicy,
+    shutdown: Receiver<()>,
+) -> impl Future<Item = (), Error = ()> {
+    ImageGarbageCollector::new(runtime.clone(), policy)
+        .run_until(shutdown.map_err(|_| ()))
+        .map_err(|err| {
+            let err = Error::from(err.context(ErrorKind::ImageGarbageCollector));
+            log_failure(Level::Warn, &err);
+        })
+}
+
+/// Loads the settings again on every `SIGHUP` and applies the ones that can
+/// change while iotedged runs. Settings that fail to load are kept as they
+/// were.
+fn start_settings_reload(
+    settings: &Settings<DockerConfig>,
+    registries: RegistryValidator,
+    shutdown: Receiver<()>,
+) -> impl Future<Item = (), Error = ()> {
+    let config_file = settings.config_file().map(Path::to_path_buf);
+    signal::reload()
+        .for_each(move |()| {
+            if let Err(err) =
+                reload_settings(config_file.as_ref().map(PathBuf::as_path), &registries)
+            {
+                let err = Error::from(err.context(ErrorKind::ReloadSettings));
+                log_failure(Level::Warn, &err);
+            }
+            Ok(())
+        })
+        .select(shutdown.map_err(|_| ()))
+        .map(|_| ())
+        .map_err(|_| ())
+}
+
+/// Loads the settings from `config_file` and applies the ones that can
+/// change while iotedged runs, which are the allowed registries and the
+/// proxies, if the file sets any.
+fn reload_settings(
+    config_file: Option<&Path>,
+    registries: &RegistryValidator,
+) -> Result<(), LoadSettingsError> {
+    let settings = Settings::<DockerConfig>::new(config_file)?;
+    info!(
+        "Allowed registries are now {:?}",
+        settings.allowed_registries().registries()
+    );
+    registries.set(settings.allowed_registries().clone());
+    if !settings.proxy().is_default() {
+        settings.proxy().apply_to_env();
+    }
+    Ok(())
+}
+
 fn vol_mount_uri(config: &mut DockerConfig, uris: &[&Url]) -> Result<(), Error> {
     let create_options = config
         .clone_create_options()
@@ -866,40 +1079,133 @@ fn build_env(
     env
 }
 
-fn start_management<K, HC>(
+/// Reads the PEM files of the TLS settings of a listener.
+fn tls_config(tls: &ListenTls) -> Result<TlsConfig, Error> {
+    let read = |path: &Path| {
+        fs::read_to_string(path).context(ErrorKind::Initialize(
+            InitializeErrorReason::ManagementService,
+        ))
+    };
+    Ok(TlsConfig::new(
+        read(tls.server_cert())?,
+        read(tls.server_key())?,
+        read(tls.client_ca_cert())?,
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_management<K, HC, C>(
     settings: &Settings<DockerConfig>,
-    mgmt: &DockerModuleRuntime,
+    mgmt: &CircuitBreaker<DockerModuleRuntime>,
     id_man: &HubIdentityManager<DerivedKeyStore<K>, HC, K>,
+    key_store: &DerivedKeyStore<K>,
+    gc_policy: &SharedGcPolicy,
+    state_reset: &StateReset,
+    certificates: &C,
+    registries: &RegistryValidator,
     shutdown: Receiver<()>,
 ) -> impl Future<Item = (), Error = Error>
 where
     K: 'static + Sign + Clone + Send + Sync,
     HC: 'static + ClientImpl + Send + Sync,
+    C: 'static + CertificateManager + Clone + Send + Sync,
+    for<'r> &'r C::Error: Into<CertificateManagerErrorReason>,
 {
     info!("Starting management API...");
 
     let label = "mgmt".to_string();
-    let url = settings.listen().management_uri().clone();
+    let url = listen_url(MANAGEMENT_URI_ENV_KEY, settings.listen().management_uri());
+    let mut protocol = Http::new();
+    protocol.http2_only(settings.listen().use_http2());
+    let history =
+        ModuleHistory::load(Path::new(settings.homedir()).join(EDGE_MODULE_HISTORY_FILENAME));
+    let config_store = FileBackedConfigStore::load(
+        Path::new(settings.homedir())
+            .join(EDGE_SETTINGS_SUBDIR)
+            .join(EDGE_MODULE_CONFIGS_FILENAME),
+    );
+    let hmac_auth = if settings.listen().management_hmac_auth() {
+        match key_store
+            .get(&KeyIdentity::Device, MANAGEMENT_HMAC_KEY_NAME)
+            .context(ErrorKind::Initialize(
+                InitializeErrorReason::ManagementService,
+            )) {
+            Ok(key) => HmacAuthMiddleware::new(key),
+            Err(err) => return Either::A(future::err(Error::from(err))),
+        }
+    } else {
+        HmacAuthMiddleware::default()
+    };
+    let tls = match settings.listen().management_tls().map(tls_config) {
+        Some(Ok(tls)) => Some(tls),
+        Some(Err(err)) => return Either::A(future::err(err)),
+        None => None,
+    };
+    let daemon_config =
+        match ConfigFileDaemonConfigStore::new(settings, registries.clone(), gc_policy.clone()) {
+            Ok(daemon_config) => daemon_config,
+            Err(err) => {
+                return Either::A(future::err(Error::from(err.context(
+                    ErrorKind::Initialize(InitializeErrorReason::ManagementService),
+                ))))
+            }
+        };
 
-    ManagementService::new(mgmt, id_man)
+    let stores = ManagementStores {
+        config_store,
+        certificates: certificates.clone(),
+        proxy: ConfigFileProxyStore::new(settings.config_file(), registries.clone()),
+        daemon_config,
+        key_store: key_store.clone(),
+        trust_store: ModuleTrustStore::new(
+            Path::new(settings.homedir()).join(EDGE_TRUST_STORE_SUBDIR),
+        ),
+    };
+    let identity = RetryingIdentityManager::new(id_man.clone());
+    let service = ManagementService::builder(mgmt, &identity, stores)
+        .with_tenants(settings.tenants().clone())
+        .with_gc_policy(gc_policy.clone())
+        .with_state_reset(state_reset.clone())
+        .with_history(history)
+        .with_registry_validator(registries.clone())
+        .with_name_validator(ModuleNameValidator::new(settings.module_names().clone()))
+        .with_hmac_auth(hmac_auth)
+        .build()
         .then(move |service| -> Result<_, Error> {
             let service = service.context(ErrorKind::Initialize(
                 InitializeErrorReason::ManagementService,
             ))?;
+            let url = url.context(ErrorKind::Initialize(
+                InitializeErrorReason::ManagementService,
+            ))?;
+            let shutting_down = service.shutting_down();
             let service = LoggingService::new(label, service);
             info!("Listening on {} with 1 thread for management API.", url);
-            let run = Http::new()
-                .bind_url(url.clone(), service)
-                .map_err(|err| {
+            let server = protocol.bind_url(url.clone(), service).map_err(|err| {
+                err.context(ErrorKind::Initialize(
+                    InitializeErrorReason::ManagementService,
+                ))
+            })?;
+            let server = match tls {
+                Some(tls) => server.with_tls(&tls).map_err(|err| {
                     err.context(ErrorKind::Initialize(
                         InitializeErrorReason::ManagementService,
                     ))
-                })?
-                .run_until(shutdown.map_err(|_| ()))
+                })?,
+                None => server,
+            };
+            let run = server
+                .run_until(shutdown.then(move |_| {
+                    info!("Shutting down management API...");
+                    shutting_down.store(true, Ordering::SeqCst);
+                    Ok(())
+                }))
                 .map_err(|err| Error::from(err.context(ErrorKind::ManagementService)));
             Ok(run)
         })
-        .flatten()
+        .flatten();
+
+    Either::B(service)
 }
 
 fn start_workload<K, C, W>(
@@ -926,15 +1232,20 @@ where
     info!("Starting workload API...");
 
     let label = "work".to_string();
-    let url = settings.listen().workload_uri().clone();
+    let url = listen_url(WORKLOAD_URI_ENV_KEY, settings.listen().workload_uri());
+    let mut protocol = Http::new();
+    protocol.http2_only(settings.listen().use_http2());
 
     WorkloadService::new(key_store, crypto.clone(), runtime, config)
         .then(move |service| -> Result<_, Error> {
             let service = service.context(ErrorKind::Initialize(
                 InitializeErrorReason::WorkloadService,
             ))?;
+            let url = url.context(ErrorKind::Initialize(
+                InitializeErrorReason::WorkloadService,
+            ))?;
             let service = LoggingService::new(label, service);
-            let run = Http::new()
+            let run = protocol
                 .bind_url(url.clone(), service)
                 .map_err(|err| {
                     err.context(ErrorKind::Initialize(
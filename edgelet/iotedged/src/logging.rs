@@ -6,9 +6,10 @@ use std::io::Write;
 #[cfg(target_os = "windows")]
 use clap::crate_name;
 
-use edgelet_utils::log_failure;
+use edgelet_utils::{correlation_id, log_failure};
 use env_logger;
-use log::{Level, LevelFilter};
+use log::{Level, LevelFilter, Record};
+use serde_json::{json, Value};
 #[cfg(target_os = "windows")]
 use win_logger::EventLogger;
 
@@ -17,10 +18,21 @@ use crate::error::Error;
 #[cfg(target_os = "windows")]
 const IOTEDGED_SERVICE_NAME: &str = crate_name!();
 const ENV_LOG: &str = "IOTEDGE_LOG";
+const ENV_LOG_FORMAT: &str = "IOTEDGE_LOG_FORMAT";
 
 pub fn init() {
-    env_logger::Builder::new()
-        .format(|fmt, record| {
+    let mut builder = env_logger::Builder::new();
+    if env::var(ENV_LOG_FORMAT)
+        .map(|format| format == "json")
+        .unwrap_or(false)
+    {
+        // One JSON object per line, for log aggregators to parse
+        builder.format(|fmt, record| {
+            let timestamp = fmt.timestamp().to_string();
+            writeln!(fmt, "{}", json_record(&timestamp, record))
+        });
+    } else {
+        builder.format(|fmt, record| {
             let level = match record.level() {
                 Level::Trace => "TRCE",
                 Level::Debug => "DBUG",
@@ -50,12 +62,24 @@ pub fn init() {
                     record.args()
                 )
             }
-        })
+        });
+    }
+    builder
         .filter_level(LevelFilter::Info)
         .parse(&env::var(ENV_LOG).unwrap_or_default())
         .init();
 }
 
+fn json_record(timestamp: &str, record: &Record<'_>) -> Value {
+    json!({
+        "timestamp": timestamp,
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "correlation_id": correlation_id(),
+        "message": record.args().to_string(),
+    })
+}
+
 #[cfg(target_os = "windows")]
 pub fn init_win_log() {
     let mut min_log_level = "info".to_string();
@@ -81,3 +105,52 @@ fn syslog_level(level: Level) -> i8 {
 pub fn log_error(error: &Error) {
     log_failure(Level::Error, error);
 }
+
+#[cfg(test)]
+mod tests {
+    use edgelet_utils::with_correlation_id;
+
+    use super::*;
+
+    #[test]
+    fn json_record_has_correlation_id_of_request() {
+        let line = with_correlation_id("abc-123", || {
+            json_record(
+                "2019-01-30T10:00:00Z",
+                &Record::builder()
+                    .args(format_args!("Listing modules"))
+                    .level(Level::Info)
+                    .target("edgelet_docker::runtime")
+                    .build(),
+            )
+            .to_string()
+        });
+
+        let record: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            json!({
+                "timestamp": "2019-01-30T10:00:00Z",
+                "level": "INFO",
+                "target": "edgelet_docker::runtime",
+                "correlation_id": "abc-123",
+                "message": "Listing modules",
+            }),
+            record
+        );
+    }
+
+    #[test]
+    fn json_record_without_request_has_null_correlation_id() {
+        let record = json_record(
+            "2019-01-30T10:00:00Z",
+            &Record::builder()
+                .args(format_args!("Starting"))
+                .level(Level::Warn)
+                .target("iotedged")
+                .build(),
+        );
+
+        assert_eq!(Value::Null, record["correlation_id"]);
+        assert_eq!("WARN", record["level"]);
+    }
+}
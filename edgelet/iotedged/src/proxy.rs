@@ -0,0 +1,57 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::path::{Path, PathBuf};
+
+use failure::Fail;
+use log::info;
+
+use edgelet_config::save_proxy_settings;
+use edgelet_core::{
+    Error as CoreError, ErrorKind as CoreErrorKind, ProxySettings, ProxySettingsStore,
+};
+use edgelet_http_mgmt::RegistryValidator;
+
+use crate::reload_settings;
+
+/// Keeps the proxy settings in the `proxy` section of the config file, from
+/// which the settings are loaded again after every change, and in the
+/// environment of iotedged, which is where its HTTP clients read the proxies
+/// from. Without a config file the settings only last until iotedged
+/// restarts.
+#[derive(Clone)]
+pub struct ConfigFileProxyStore {
+    config_file: Option<PathBuf>,
+    registries: RegistryValidator,
+}
+
+impl ConfigFileProxyStore {
+    pub fn new(config_file: Option<&Path>, registries: RegistryValidator) -> Self {
+        ConfigFileProxyStore {
+            config_file: config_file.map(Path::to_path_buf),
+            registries,
+        }
+    }
+}
+
+impl ProxySettingsStore for ConfigFileProxyStore {
+    fn get(&self) -> ProxySettings {
+        ProxySettings::from_env()
+    }
+
+    fn set(&self, proxy: ProxySettings) -> Result<(), CoreError> {
+        match self.config_file {
+            Some(ref config_file) => {
+                save_proxy_settings(config_file, &proxy)
+                    .and_then(|()| reload_settings(Some(config_file), &self.registries))
+                    .map_err(|err| CoreError::from(err.context(CoreErrorKind::ProxySettings)))?;
+            }
+            None => info!("No config file to save the proxy settings to"),
+        }
+
+        // The settings loaded again leave the environment alone when they
+        // have no proxies, so unsetting them takes doing it here.
+        proxy.apply_to_env();
+        info!("Proxy settings are now {:?}", proxy.redacted());
+        Ok(())
+    }
+}
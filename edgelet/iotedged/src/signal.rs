@@ -3,23 +3,31 @@
 // Adapted from the conduit proxy signal handling:
 // https://github.com/runconduit/conduit/blob/master/proxy/src/signal.rs
 
-use futures::Future;
+use futures::{Future, Stream};
 
 type ShutdownSignal = Box<dyn Future<Item = (), Error = ()> + Send>;
 
+type ReloadSignal = Box<dyn Stream<Item = (), Error = ()> + Send>;
+
 pub fn shutdown() -> ShutdownSignal {
     imp::shutdown()
 }
 
+/// Yields every time iotedged is asked to reload its settings, which is on
+/// `SIGHUP`. Never yields where there is no such signal.
+pub fn reload() -> ReloadSignal {
+    imp::reload()
+}
+
 #[cfg(unix)]
 mod imp {
     use std::fmt;
 
     use futures::{future, Future, Stream};
     use log::info;
-    use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+    use tokio_signal::unix::{Signal, SIGHUP, SIGINT, SIGTERM};
 
-    use super::ShutdownSignal;
+    use super::{ReloadSignal, ShutdownSignal};
 
     pub(super) fn shutdown() -> ShutdownSignal {
         let signals = [SIGINT, SIGTERM].iter().map(|&sig| {
@@ -40,12 +48,27 @@ mod imp {
         Box::new(on_any_signal)
     }
 
+    pub(super) fn reload() -> ReloadSignal {
+        let on_sighup = Signal::new(SIGHUP)
+            .flatten_stream()
+            .map(|_| {
+                info!(
+                    target: "iotedged::signal",
+                    "Received {}, reloading settings",
+                    DisplaySignal(SIGHUP),
+                );
+            })
+            .map_err(|_| unreachable!("Signal never returns an error"));
+        Box::new(on_sighup)
+    }
+
     #[derive(Clone, Copy)]
     struct DisplaySignal(i32);
 
     impl fmt::Display for DisplaySignal {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             let s = match self.0 {
+                SIGHUP => "SIGHUP",
                 SIGINT => "SIGINT",
                 SIGTERM => "SIGTERM",
                 other => return write!(f, "signal {}", other),
@@ -57,11 +80,11 @@ mod imp {
 
 #[cfg(not(unix))]
 mod imp {
-    use futures::{Future, Stream};
+    use futures::{stream, Future, Stream};
     use log::info;
     use tokio_signal;
 
-    use super::ShutdownSignal;
+    use super::{ReloadSignal, ShutdownSignal};
 
     pub(super) fn shutdown() -> ShutdownSignal {
         let on_ctrl_c = tokio_signal::ctrl_c()
@@ -76,4 +99,8 @@ mod imp {
             .map_err(|_| unreachable!("ctrl_c never returns errors"));
         Box::new(on_ctrl_c)
     }
+
+    pub(super) fn reload() -> ReloadSignal {
+        Box::new(stream::empty())
+    }
 }
@@ -11,7 +11,7 @@ use edgelet_http::error::ErrorKind as HttpErrorKind;
 use edgelet_utils::ensure_not_empty_with_context;
 
 use crate::error::{Error, ErrorKind, ModuleOperationReason};
-use crate::model::{AuthMechanism, Module};
+use crate::model::{AuthMechanism, Module, Twin};
 
 define_encode_set! {
     pub IOTHUB_ENCODE_SET = [PATH_SEGMENT_ENCODE_SET] | { '=' }
@@ -158,6 +158,67 @@ where
         }
     }
 
+    pub fn get_twin(&self) -> impl Future<Item = Twin, Error = Error> {
+        self.client
+            .request::<(), Twin>(
+                Method::GET,
+                &format!("/twins/{}", url_encode(&self.device_id)),
+                None,
+                None,
+                false,
+            )
+            .then(|twin| match twin {
+                Ok(Some(twin)) => Ok(twin),
+                Ok(None) => Err(Error::from(ErrorKind::GetDeviceTwin)),
+                Err(err) => Err(Error::from(err.context(ErrorKind::GetDeviceTwin))),
+            })
+    }
+
+    pub fn get_module_twin(&self, module_id: String) -> impl Future<Item = Twin, Error = Error> {
+        if module_id.trim().is_empty() {
+            Either::B(future::err(Error::from(
+                ErrorKind::GetModuleTwinWithReason(module_id, ModuleOperationReason::EmptyModuleId),
+            )))
+        } else {
+            let res = self
+                .client
+                .request::<(), Twin>(
+                    Method::GET,
+                    &format!(
+                        "/twins/{}/modules/{}",
+                        url_encode(&self.device_id),
+                        url_encode(&module_id)
+                    ),
+                    None,
+                    None,
+                    false,
+                )
+                .then(|twin| match twin {
+                    Ok(Some(twin)) => Ok(twin),
+
+                    Ok(None) => Err(Error::from(ErrorKind::GetModuleTwinWithReason(
+                        module_id,
+                        ModuleOperationReason::EmptyResponse,
+                    ))),
+
+                    Err(err) => Err({
+                        if let HttpErrorKind::HttpWithErrorResponse(StatusCode::NOT_FOUND, _) =
+                            err.kind()
+                        {
+                            Error::from(ErrorKind::GetModuleTwinWithReason(
+                                module_id,
+                                ModuleOperationReason::ModuleNotFound,
+                            ))
+                        } else {
+                            Error::from(err.context(ErrorKind::GetModuleTwin(module_id)))
+                        }
+                    }),
+                });
+
+            Either::A(res)
+        }
+    }
+
     pub fn list_modules(&self) -> impl Future<Item = Vec<Module>, Error = Error> {
         self.client
             .request::<(), Vec<Module>>(
@@ -229,13 +290,13 @@ mod tests {
     use chrono::{DateTime, Utc};
     use futures::Stream;
     use hyper::{self, Body, Client as HyperClient, Method, Request, Response};
-    use serde_json;
+    use serde_json::{self, json};
     use tokio;
     use typed_headers::{mime, ContentType, HeaderMapExt};
     use url::Url;
 
     use crate::error::{ErrorKind, ModuleOperationReason};
-    use crate::model::{AuthType, SymmetricKey};
+    use crate::model::{AuthType, Properties, SymmetricKey};
 
     struct NullTokenSource;
 
@@ -742,4 +803,116 @@ mod tests {
             .block_on(task)
             .unwrap();
     }
+
+    #[test]
+    fn module_twin_get_request() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+        let twin = Twin::new(
+            "d1",
+            3,
+            AuthType::Sas,
+            Properties::new(json!({ "schemaVersion": "1.0" }))
+                .with_reported(json!({ "lastDesiredStatus": { "code": 200 } })),
+        )
+        .with_module_id("$edgeAgent".to_string());
+        let expected_twin = serde_json::to_value(&twin).unwrap();
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(req.method(), &Method::GET);
+            assert_eq!(req.uri().path(), "/twins/d1/modules/$edgeAgent");
+
+            let mut response = Response::new(serde_json::to_string(&twin).unwrap().into());
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            Ok(response)
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client
+            .get_module_twin("$edgeAgent".to_string())
+            .then(|twin| {
+                let twin = twin.unwrap();
+                assert_eq!(expected_twin, serde_json::to_value(&twin).unwrap());
+                Ok::<_, Error>(())
+            });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn device_twin_get_request() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+        let twin = Twin::new(
+            "d1",
+            7,
+            AuthType::Sas,
+            Properties::new(json!({ "telemetryInterval": 30, "$version": 7 })),
+        );
+        let expected_twin = serde_json::to_value(&twin).unwrap();
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(req.method(), &Method::GET);
+            assert_eq!(req.uri().path(), "/twins/d1");
+
+            let mut response = Response::new(serde_json::to_string(&twin).unwrap().into());
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            Ok(response)
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client.get_twin().then(|twin| {
+            let twin = twin.unwrap();
+            assert_eq!(expected_twin, serde_json::to_value(&twin).unwrap());
+            Ok::<_, Error>(())
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn module_twin_get_not_found() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_: Request<Body>| {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .expect("could not build hyper::Response");
+            Ok(response)
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client
+            .get_module_twin("m1".to_string())
+            .then(|twin| {
+                assert_eq!(
+                    ErrorKind::GetModuleTwinWithReason(
+                        "m1".to_string(),
+                        ModuleOperationReason::ModuleNotFound
+                    ),
+                    *twin.unwrap_err().kind()
+                );
+                Ok::<_, Error>(())
+            });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
 }
@@ -19,12 +19,21 @@ pub enum ErrorKind {
     #[fail(display = "Could not delete module {}: {}", _0, _1)]
     DeleteModuleWithReason(String, ModuleOperationReason),
 
+    #[fail(display = "Could not get the device twin")]
+    GetDeviceTwin,
+
     #[fail(display = "Could not get module {}", _0)]
     GetModule(String),
 
     #[fail(display = "Could not get module {}: {}", _0, _1)]
     GetModuleWithReason(String, ModuleOperationReason),
 
+    #[fail(display = "Could not get twin of module {}", _0)]
+    GetModuleTwin(String),
+
+    #[fail(display = "Could not get twin of module {}: {}", _0, _1)]
+    GetModuleTwinWithReason(String, ModuleOperationReason),
+
     #[fail(display = "IoT Hub service error: [{}] {}", _0, _1)]
     HubService(StatusCode, String),
 
@@ -89,16 +89,30 @@ impl Twin {
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Properties {
     desired: Value,
+    #[serde(default)]
+    reported: Value,
 }
 
 impl Properties {
     pub fn new(desired: Value) -> Properties {
-        Properties { desired }
+        Properties {
+            desired,
+            reported: Value::Null,
+        }
+    }
+
+    pub fn with_reported(mut self, reported: Value) -> Self {
+        self.reported = reported;
+        self
     }
 
     pub fn desired(&self) -> &Value {
         &self.desired
     }
+
+    pub fn reported(&self) -> &Value {
+        &self.reported
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
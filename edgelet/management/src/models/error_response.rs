@@ -14,13 +14,38 @@ use serde_json::Value;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
+    #[serde(rename = "code", skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
     #[serde(rename = "message")]
     message: String,
+    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
 }
 
 impl ErrorResponse {
     pub fn new(message: String) -> Self {
-        ErrorResponse { message }
+        ErrorResponse {
+            code: None,
+            message,
+            correlation_id: None,
+        }
+    }
+
+    pub fn set_code(&mut self, code: String) {
+        self.code = Some(code);
+    }
+
+    pub fn with_code(mut self, code: String) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_code(&mut self) {
+        self.code = None;
     }
 
     pub fn set_message(&mut self, message: String) {
@@ -35,4 +60,21 @@ impl ErrorResponse {
     pub fn message(&self) -> &String {
         &self.message
     }
+
+    pub fn set_correlation_id(&mut self, correlation_id: String) {
+        self.correlation_id = Some(correlation_id);
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: String) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_correlation_id(&mut self) {
+        self.correlation_id = None;
+    }
 }
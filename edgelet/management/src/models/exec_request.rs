@@ -0,0 +1,57 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2018-06-28
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecRequest {
+    #[serde(rename = "cmd")]
+    cmd: Vec<String>,
+    #[serde(rename = "tty", skip_serializing_if = "Option::is_none")]
+    tty: Option<bool>,
+}
+
+impl ExecRequest {
+    pub fn new(cmd: Vec<String>) -> Self {
+        ExecRequest { cmd, tty: None }
+    }
+
+    pub fn set_cmd(&mut self, cmd: Vec<String>) {
+        self.cmd = cmd;
+    }
+
+    pub fn with_cmd(mut self, cmd: Vec<String>) -> Self {
+        self.cmd = cmd;
+        self
+    }
+
+    pub fn cmd(&self) -> &[String] {
+        &self.cmd
+    }
+
+    pub fn set_tty(&mut self, tty: bool) {
+        self.tty = Some(tty);
+    }
+
+    pub fn with_tty(mut self, tty: bool) -> Self {
+        self.tty = Some(tty);
+        self
+    }
+
+    pub fn tty(&self) -> Option<&bool> {
+        self.tty.as_ref()
+    }
+
+    pub fn reset_tty(&mut self) {
+        self.tty = None;
+    }
+}
@@ -1,11 +1,17 @@
+mod bulk_module_request;
+pub use self::bulk_module_request::BulkModuleRequest;
 mod config;
 pub use self::config::Config;
 mod env_var;
 pub use self::env_var::EnvVar;
 mod error_response;
 pub use self::error_response::ErrorResponse;
+mod exec_request;
+pub use self::exec_request::ExecRequest;
 mod exit_status;
 pub use self::exit_status::ExitStatus;
+mod health_status;
+pub use self::health_status::HealthStatus;
 mod identity;
 pub use self::identity::Identity;
 mod identity_list;
@@ -14,12 +20,20 @@ mod identity_spec;
 pub use self::identity_spec::IdentitySpec;
 mod update_identity;
 pub use self::update_identity::UpdateIdentity;
+mod module_env_patch;
+pub use self::module_env_patch::ModuleEnvPatch;
 mod module_details;
 pub use self::module_details::ModuleDetails;
 mod module_list;
 pub use self::module_list::ModuleList;
 mod module_spec;
 pub use self::module_spec::ModuleSpec;
+mod module_stats;
+pub use self::module_stats::ModuleStats;
+mod module_top;
+pub use self::module_top::ModuleTop;
+mod resource_limits;
+pub use self::resource_limits::ResourceLimits;
 mod runtime_status;
 pub use self::runtime_status::RuntimeStatus;
 mod status;
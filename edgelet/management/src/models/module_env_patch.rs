@@ -0,0 +1,66 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use std::collections::HashMap;
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ModuleEnvPatch {
+    #[serde(rename = "set", skip_serializing_if = "Option::is_none")]
+    set: Option<HashMap<String, String>>,
+    #[serde(rename = "unset", skip_serializing_if = "Option::is_none")]
+    unset: Option<Vec<String>>,
+}
+
+impl ModuleEnvPatch {
+    pub fn new() -> Self {
+        ModuleEnvPatch {
+            set: None,
+            unset: None,
+        }
+    }
+
+    pub fn set_set(&mut self, set: HashMap<String, String>) {
+        self.set = Some(set);
+    }
+
+    pub fn with_set(mut self, set: HashMap<String, String>) -> Self {
+        self.set = Some(set);
+        self
+    }
+
+    pub fn set(&self) -> Option<&HashMap<String, String>> {
+        self.set.as_ref()
+    }
+
+    pub fn reset_set(&mut self) {
+        self.set = None;
+    }
+
+    pub fn set_unset(&mut self, unset: Vec<String>) {
+        self.unset = Some(unset);
+    }
+
+    pub fn with_unset(mut self, unset: Vec<String>) -> Self {
+        self.unset = Some(unset);
+        self
+    }
+
+    pub fn unset(&self) -> Option<&[String]> {
+        self.unset.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_unset(&mut self) {
+        self.unset = None;
+    }
+}
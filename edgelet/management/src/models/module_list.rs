@@ -16,11 +16,22 @@ use serde_json::Value;
 pub struct ModuleList {
     #[serde(rename = "modules")]
     modules: Vec<crate::models::ModuleDetails>,
+    #[serde(rename = "total_count", skip_serializing_if = "Option::is_none")]
+    total_count: Option<i64>,
+    #[serde(rename = "limit", skip_serializing_if = "Option::is_none")]
+    limit: Option<i64>,
+    #[serde(rename = "offset", skip_serializing_if = "Option::is_none")]
+    offset: Option<i64>,
 }
 
 impl ModuleList {
     pub fn new(modules: Vec<crate::models::ModuleDetails>) -> Self {
-        ModuleList { modules }
+        ModuleList {
+            modules,
+            total_count: None,
+            limit: None,
+            offset: None,
+        }
     }
 
     pub fn set_modules(&mut self, modules: Vec<crate::models::ModuleDetails>) {
@@ -35,4 +46,55 @@ impl ModuleList {
     pub fn modules(&self) -> &[crate::models::ModuleDetails] {
         &self.modules
     }
+
+    pub fn set_total_count(&mut self, total_count: i64) {
+        self.total_count = Some(total_count);
+    }
+
+    pub fn with_total_count(mut self, total_count: i64) -> Self {
+        self.total_count = Some(total_count);
+        self
+    }
+
+    pub fn total_count(&self) -> Option<&i64> {
+        self.total_count.as_ref()
+    }
+
+    pub fn reset_total_count(&mut self) {
+        self.total_count = None;
+    }
+
+    pub fn set_limit(&mut self, limit: i64) {
+        self.limit = Some(limit);
+    }
+
+    pub fn with_limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn limit(&self) -> Option<&i64> {
+        self.limit.as_ref()
+    }
+
+    pub fn reset_limit(&mut self) {
+        self.limit = None;
+    }
+
+    pub fn set_offset(&mut self, offset: i64) {
+        self.offset = Some(offset);
+    }
+
+    pub fn with_offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn offset(&self) -> Option<&i64> {
+        self.offset.as_ref()
+    }
+
+    pub fn reset_offset(&mut self) {
+        self.offset = None;
+    }
 }
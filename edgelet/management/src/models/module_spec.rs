@@ -8,11 +8,13 @@
  * Generated by: https://github.com/swagger-api/swagger-codegen.git
  */
 
+use std::collections::HashMap;
+
 use serde_derive::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use serde_json::Value;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ModuleSpec {
     /// The name of a the module.
     #[serde(rename = "name")]
@@ -21,6 +23,17 @@ pub struct ModuleSpec {
     type_: String,
     #[serde(rename = "config")]
     config: crate::models::Config,
+    /// Labels attached to the module, which can be used to filter the module list.
+    #[serde(rename = "labels", skip_serializing_if = "Option::is_none")]
+    labels: Option<HashMap<String, String>>,
+    #[serde(rename = "resource_limits", skip_serializing_if = "Option::is_none")]
+    resource_limits: Option<crate::models::ResourceLimits>,
+    /// Whether iotedged restarts the module after it exits: never, `on_failure` or always.
+    #[serde(rename = "restart_policy", skip_serializing_if = "Option::is_none")]
+    restart_policy: Option<String>,
+    /// The names of the modules that have to be started before this one.
+    #[serde(rename = "depends_on", skip_serializing_if = "Option::is_none")]
+    depends_on: Option<Vec<String>>,
 }
 
 impl ModuleSpec {
@@ -29,6 +42,10 @@ impl ModuleSpec {
             name,
             type_,
             config,
+            labels: None,
+            resource_limits: None,
+            restart_policy: None,
+            depends_on: None,
         }
     }
 
@@ -70,4 +87,72 @@ impl ModuleSpec {
     pub fn config(&self) -> &crate::models::Config {
         &self.config
     }
+
+    pub fn set_labels(&mut self, labels: HashMap<String, String>) {
+        self.labels = Some(labels);
+    }
+
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    pub fn labels(&self) -> Option<&HashMap<String, String>> {
+        self.labels.as_ref()
+    }
+
+    pub fn reset_labels(&mut self) {
+        self.labels = None;
+    }
+
+    pub fn set_resource_limits(&mut self, resource_limits: crate::models::ResourceLimits) {
+        self.resource_limits = Some(resource_limits);
+    }
+
+    pub fn with_resource_limits(mut self, resource_limits: crate::models::ResourceLimits) -> Self {
+        self.resource_limits = Some(resource_limits);
+        self
+    }
+
+    pub fn resource_limits(&self) -> Option<&crate::models::ResourceLimits> {
+        self.resource_limits.as_ref()
+    }
+
+    pub fn reset_resource_limits(&mut self) {
+        self.resource_limits = None;
+    }
+
+    pub fn set_restart_policy(&mut self, restart_policy: String) {
+        self.restart_policy = Some(restart_policy);
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: String) -> Self {
+        self.restart_policy = Some(restart_policy);
+        self
+    }
+
+    pub fn restart_policy(&self) -> Option<&String> {
+        self.restart_policy.as_ref()
+    }
+
+    pub fn reset_restart_policy(&mut self) {
+        self.restart_policy = None;
+    }
+
+    pub fn set_depends_on(&mut self, depends_on: Vec<String>) {
+        self.depends_on = Some(depends_on);
+    }
+
+    pub fn with_depends_on(mut self, depends_on: Vec<String>) -> Self {
+        self.depends_on = Some(depends_on);
+        self
+    }
+
+    pub fn depends_on(&self) -> Option<&Vec<String>> {
+        self.depends_on.as_ref()
+    }
+
+    pub fn reset_depends_on(&mut self) {
+        self.depends_on = None;
+    }
 }
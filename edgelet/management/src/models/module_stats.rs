@@ -0,0 +1,110 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2018-06-28
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModuleStats {
+    #[serde(rename = "cpu_percent")]
+    cpu_percent: f64,
+    #[serde(rename = "memory_usage_bytes")]
+    memory_usage_bytes: i64,
+    #[serde(rename = "memory_limit_bytes")]
+    memory_limit_bytes: i64,
+    #[serde(rename = "network_rx_bytes")]
+    network_rx_bytes: i64,
+    #[serde(rename = "network_tx_bytes")]
+    network_tx_bytes: i64,
+}
+
+impl ModuleStats {
+    pub fn new(
+        cpu_percent: f64,
+        memory_usage_bytes: i64,
+        memory_limit_bytes: i64,
+        network_rx_bytes: i64,
+        network_tx_bytes: i64,
+    ) -> Self {
+        ModuleStats {
+            cpu_percent,
+            memory_usage_bytes,
+            memory_limit_bytes,
+            network_rx_bytes,
+            network_tx_bytes,
+        }
+    }
+
+    pub fn set_cpu_percent(&mut self, cpu_percent: f64) {
+        self.cpu_percent = cpu_percent;
+    }
+
+    pub fn with_cpu_percent(mut self, cpu_percent: f64) -> Self {
+        self.cpu_percent = cpu_percent;
+        self
+    }
+
+    pub fn cpu_percent(&self) -> &f64 {
+        &self.cpu_percent
+    }
+
+    pub fn set_memory_usage_bytes(&mut self, memory_usage_bytes: i64) {
+        self.memory_usage_bytes = memory_usage_bytes;
+    }
+
+    pub fn with_memory_usage_bytes(mut self, memory_usage_bytes: i64) -> Self {
+        self.memory_usage_bytes = memory_usage_bytes;
+        self
+    }
+
+    pub fn memory_usage_bytes(&self) -> &i64 {
+        &self.memory_usage_bytes
+    }
+
+    pub fn set_memory_limit_bytes(&mut self, memory_limit_bytes: i64) {
+        self.memory_limit_bytes = memory_limit_bytes;
+    }
+
+    pub fn with_memory_limit_bytes(mut self, memory_limit_bytes: i64) -> Self {
+        self.memory_limit_bytes = memory_limit_bytes;
+        self
+    }
+
+    pub fn memory_limit_bytes(&self) -> &i64 {
+        &self.memory_limit_bytes
+    }
+
+    pub fn set_network_rx_bytes(&mut self, network_rx_bytes: i64) {
+        self.network_rx_bytes = network_rx_bytes;
+    }
+
+    pub fn with_network_rx_bytes(mut self, network_rx_bytes: i64) -> Self {
+        self.network_rx_bytes = network_rx_bytes;
+        self
+    }
+
+    pub fn network_rx_bytes(&self) -> &i64 {
+        &self.network_rx_bytes
+    }
+
+    pub fn set_network_tx_bytes(&mut self, network_tx_bytes: i64) {
+        self.network_tx_bytes = network_tx_bytes;
+    }
+
+    pub fn with_network_tx_bytes(mut self, network_tx_bytes: i64) -> Self {
+        self.network_tx_bytes = network_tx_bytes;
+        self
+    }
+
+    pub fn network_tx_bytes(&self) -> &i64 {
+        &self.network_tx_bytes
+    }
+}
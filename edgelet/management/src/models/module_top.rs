@@ -0,0 +1,53 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2018-06-28
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModuleTop {
+    #[serde(rename = "titles")]
+    titles: Vec<String>,
+    #[serde(rename = "processes")]
+    processes: Vec<Vec<String>>,
+}
+
+impl ModuleTop {
+    pub fn new(titles: Vec<String>, processes: Vec<Vec<String>>) -> Self {
+        ModuleTop { titles, processes }
+    }
+
+    pub fn set_titles(&mut self, titles: Vec<String>) {
+        self.titles = titles;
+    }
+
+    pub fn with_titles(mut self, titles: Vec<String>) -> Self {
+        self.titles = titles;
+        self
+    }
+
+    pub fn titles(&self) -> &[String] {
+        &self.titles
+    }
+
+    pub fn set_processes(&mut self, processes: Vec<Vec<String>>) {
+        self.processes = processes;
+    }
+
+    pub fn with_processes(mut self, processes: Vec<Vec<String>>) -> Self {
+        self.processes = processes;
+        self
+    }
+
+    pub fn processes(&self) -> &[Vec<String>] {
+        &self.processes
+    }
+}
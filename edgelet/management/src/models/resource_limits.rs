@@ -0,0 +1,87 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Relative CPU weight of the module.
+    #[serde(rename = "cpu_shares", skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<u32>,
+    /// Memory limit in bytes.
+    #[serde(rename = "memory_limit_bytes", skip_serializing_if = "Option::is_none")]
+    memory_limit_bytes: Option<u64>,
+    /// Limit of memory plus swap in bytes, or -1 for unlimited swap.
+    #[serde(rename = "memory_swap_bytes", skip_serializing_if = "Option::is_none")]
+    memory_swap_bytes: Option<i64>,
+}
+
+impl ResourceLimits {
+    pub fn new() -> Self {
+        ResourceLimits {
+            cpu_shares: None,
+            memory_limit_bytes: None,
+            memory_swap_bytes: None,
+        }
+    }
+
+    pub fn set_cpu_shares(&mut self, cpu_shares: u32) {
+        self.cpu_shares = Some(cpu_shares);
+    }
+
+    pub fn with_cpu_shares(mut self, cpu_shares: u32) -> Self {
+        self.cpu_shares = Some(cpu_shares);
+        self
+    }
+
+    pub fn cpu_shares(&self) -> Option<u32> {
+        self.cpu_shares
+    }
+
+    pub fn reset_cpu_shares(&mut self) {
+        self.cpu_shares = None;
+    }
+
+    pub fn set_memory_limit_bytes(&mut self, memory_limit_bytes: u64) {
+        self.memory_limit_bytes = Some(memory_limit_bytes);
+    }
+
+    pub fn with_memory_limit_bytes(mut self, memory_limit_bytes: u64) -> Self {
+        self.memory_limit_bytes = Some(memory_limit_bytes);
+        self
+    }
+
+    pub fn memory_limit_bytes(&self) -> Option<u64> {
+        self.memory_limit_bytes
+    }
+
+    pub fn reset_memory_limit_bytes(&mut self) {
+        self.memory_limit_bytes = None;
+    }
+
+    pub fn set_memory_swap_bytes(&mut self, memory_swap_bytes: i64) {
+        self.memory_swap_bytes = Some(memory_swap_bytes);
+    }
+
+    pub fn with_memory_swap_bytes(mut self, memory_swap_bytes: i64) -> Self {
+        self.memory_swap_bytes = Some(memory_swap_bytes);
+        self
+    }
+
+    pub fn memory_swap_bytes(&self) -> Option<i64> {
+        self.memory_swap_bytes
+    }
+
+    pub fn reset_memory_swap_bytes(&mut self) {
+        self.memory_swap_bytes = None;
+    }
+}
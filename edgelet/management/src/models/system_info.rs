@@ -20,6 +20,8 @@ pub struct SystemInfo {
     architecture: String,
     #[serde(rename = "version")]
     version: String,
+    #[serde(rename = "cgroupVersion", skip_serializing_if = "Option::is_none")]
+    cgroup_version: Option<String>,
 }
 
 impl SystemInfo {
@@ -28,6 +30,7 @@ impl SystemInfo {
             os_type,
             architecture,
             version,
+            cgroup_version: None,
         }
     }
 
@@ -69,4 +72,21 @@ impl SystemInfo {
     pub fn version(&self) -> &String {
         &self.version
     }
+
+    pub fn set_cgroup_version(&mut self, cgroup_version: String) {
+        self.cgroup_version = Some(cgroup_version);
+    }
+
+    pub fn with_cgroup_version(mut self, cgroup_version: String) -> Self {
+        self.cgroup_version = Some(cgroup_version);
+        self
+    }
+
+    pub fn cgroup_version(&self) -> Option<&str> {
+        self.cgroup_version.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_cgroup_version(&mut self) {
+        self.cgroup_version = None;
+    }
 }
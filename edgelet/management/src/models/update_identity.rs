@@ -18,6 +18,8 @@ pub struct UpdateIdentity {
     generation_id: String,
     #[serde(rename = "managedBy", skip_serializing_if = "Option::is_none")]
     managed_by: Option<String>,
+    #[serde(rename = "x509Thumbprint", skip_serializing_if = "Option::is_none")]
+    x509_thumbprint: Option<String>,
 }
 
 impl UpdateIdentity {
@@ -25,6 +27,7 @@ impl UpdateIdentity {
         UpdateIdentity {
             generation_id,
             managed_by: None,
+            x509_thumbprint: None,
         }
     }
 
@@ -57,4 +60,21 @@ impl UpdateIdentity {
     pub fn reset_managed_by(&mut self) {
         self.managed_by = None;
     }
+
+    pub fn set_x509_thumbprint(&mut self, x509_thumbprint: String) {
+        self.x509_thumbprint = Some(x509_thumbprint);
+    }
+
+    pub fn with_x509_thumbprint(mut self, x509_thumbprint: String) -> Self {
+        self.x509_thumbprint = Some(x509_thumbprint);
+        self
+    }
+
+    pub fn x509_thumbprint(&self) -> Option<&str> {
+        self.x509_thumbprint.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_x509_thumbprint(&mut self) {
+        self.x509_thumbprint = None;
+    }
 }